@@ -0,0 +1,121 @@
+const containerEl = document.querySelector("#container");
+const statusMessageEl = document.querySelector("#status-message");
+const payButtonEl = document.querySelector("#pay-button");
+const cancelButtonEl = document.querySelector("#cancel-button");
+
+const sessionId = new URL(window.location.href).searchParams.get('session');
+var accessToken = localStorage.getItem('access_token');
+var session = null;
+
+// Come back to this page after logging in.
+function redirectToLogin() {
+    const next = `${location.pathname}${location.search}`;
+    window.location.href = `login.html?next=${encodeURIComponent(next)}`;
+}
+
+if (!accessToken) {
+    redirectToLogin();
+}
+
+function formatAmount(amount, currency) {
+    return Intl.NumberFormat("en-MY", { style: 'currency', currency }).format(amount);
+}
+
+// Merchant-provided text is set with `textContent`, never `innerHTML`.
+function renderSession() {
+    document.querySelector('#merchant').textContent = session.merchant.username;
+    document.querySelector('#amount').textContent = formatAmount(Number(session.amount), session.currency);
+    document.querySelector('#memo').textContent = session.memo ?? '';
+    document.querySelector('#reference').textContent = session.reference;
+
+    if (session.status === 'open') {
+        statusMessageEl.textContent = '';
+        containerEl.ariaLabel = '';
+    } else {
+        statusMessageEl.textContent = `this checkout is ${session.status}`;
+        containerEl.ariaLabel = 'disabled';
+    }
+}
+
+function loadSession() {
+    if (!sessionId) {
+        statusMessageEl.textContent = 'missing checkout session';
+        return;
+    }
+
+    fetch(`/checkout-sessions/${encodeURIComponent(sessionId)}`, {
+        headers: {
+            'Authorization': `Bearer ${accessToken}`,
+            'Accept': 'application/json'
+        }
+    })
+        .then((res) => {
+            if (res.status === 401) {
+                redirectToLogin();
+                return null;
+            }
+            if (!res.ok) {
+                statusMessageEl.textContent = 'checkout session not found';
+                return null;
+            }
+            return res.json();
+        })
+        .then((data) => {
+            if (!data) return;
+
+            session = data;
+            renderSession();
+        })
+        .catch((err) => {
+            console.log(err);
+            statusMessageEl.textContent = 'connection error';
+        });
+}
+
+function pay() {
+    if (!session || session.status !== 'open') {
+        return;
+    }
+
+    containerEl.ariaLabel = 'disabled';
+
+    fetch(`/checkout-sessions/${encodeURIComponent(session.id)}/pay`, {
+        method: 'POST',
+        headers: {
+            'Authorization': `Bearer ${accessToken}`,
+            'Accept': 'application/json'
+        }
+    })
+        .then(async (res) => {
+            if (res.status === 401) {
+                redirectToLogin();
+                return;
+            }
+
+            const data = await res.json();
+            if (!res.ok) {
+                statusMessageEl.textContent = data.detail ?? data.title ?? 'error';
+                containerEl.ariaLabel = '';
+                return;
+            }
+
+            window.location.href = data.success_url;
+        })
+        .catch((err) => {
+            console.log(err);
+            statusMessageEl.textContent = 'connection error';
+            containerEl.ariaLabel = '';
+        });
+}
+
+function cancel() {
+    if (!session) {
+        return;
+    }
+
+    window.location.href = session.cancel_url;
+}
+
+payButtonEl.addEventListener('click', pay);
+cancelButtonEl.addEventListener('click', cancel);
+loadSession();
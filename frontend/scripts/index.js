@@ -17,6 +17,13 @@ var currencyFormatter = Intl.NumberFormat("en-MY", {
     currency: 'MYR'
 })
 
+function formatAmount(amount, currency) {
+    if (!currency || currency === 'MYR') {
+        return currencyFormatter.format(amount);
+    }
+    return Intl.NumberFormat("en-MY", { style: 'currency', currency }).format(amount);
+}
+
 const historyContainerEl = document.querySelector("#history #container");
 const containerEls = {
     'send': document.querySelector("#send-container"),
@@ -64,7 +71,7 @@ function renderBalance() {
         .then((data) => {
             if (!data) return;
 
-            let amount = Number(data.balance);
+            let amount = Number(data.available_balance ?? data.balance);
             document.querySelector('#balance').innerHTML = currencyFormatter.format(isNaN(amount) ? 0 : amount);
         })
         .catch((err) => {
@@ -111,8 +118,10 @@ function renderTransactions() {
                 const parts = locale.includes(',') ? locale.split(', ') : [dt.toLocaleDateString(), dt.toLocaleTimeString()];
 
                 transactionEl.querySelector('#icon').src = `./assets/${type}.png`;
-                transactionEl.querySelector('#amount').innerHTML = currencyFormatter.format(isNaN(amt) ? 0 : amt);
-                transactionEl.querySelector('#type').innerHTML = isOutgoing ? `to ${transaction.recipient}` : `from ${transaction.sender}`;
+                transactionEl.querySelector('#amount').innerHTML = formatAmount(isNaN(amt) ? 0 : amt, transaction.currency);
+                const direction = isOutgoing ? `to ${transaction.recipient}` : `from ${transaction.sender}`;
+                const isLinked = transaction.kind && transaction.kind !== 'transfer';
+                transactionEl.querySelector('#type').innerHTML = isLinked ? `${transaction.kind} ${direction}` : direction;
                 transactionEl.querySelector('#date').innerHTML = parts[0] || '';
                 transactionEl.querySelector('#time').innerHTML = parts[1] || '';
 
@@ -163,8 +172,9 @@ function updateContainerStates() {
 // pub struct PostTranscactionPayload {
 //     #[serde(with = "bigdecimal::serde::json_num")]
 //     amount: BigDecimal,
-//     recipient: Uuid,
-//     sender: Uuid,
+//     recipient: Recipient, // UUID or username
+//     memo: Option<String>,
+//     reference: Option<String>,
 // }
 
 // #region send
@@ -191,8 +201,7 @@ function sendMoney() {
         },
         body: JSON.stringify({
             amount: amount,
-            recipient: recipient,
-            sender: uuid
+            recipient: recipient
         })
     })
         .then((res) => {
@@ -243,9 +252,96 @@ validateSendInputs();
     } catch (_) { }
 })();
 
-// setInterval(() => {
-//     // horrible, yes
-//     // no ws or sse though so this is the next simplest alternative
-//     renderBalance();
-//     renderTransactions();
-// }, 2000);
+// #region live updates
+// EventSource can't send the access token, so the event stream is read with
+// fetch instead, reconnecting with the ID of the last event seen so that
+// missed events are sent again.
+const EVENTS_RETRY_MS = 3000;
+var lastEventId = null;
+
+function handleEvent(name, data) {
+    switch (name) {
+        case 'transaction':
+            renderTransactions();
+            break;
+        case 'balance': {
+            const account = (data.accounts || []).find((account) => account.currency === 'MYR');
+            const amount = Number(account ? account.available_balance : 0);
+            document.querySelector('#balance').innerHTML = currencyFormatter.format(isNaN(amount) ? 0 : amount);
+            break;
+        }
+        case 'resync':
+            renderBalance();
+            renderTransactions();
+            break;
+    }
+}
+
+function dispatchEventBlock(block) {
+    let name = 'message';
+    let id = null;
+    const dataLines = [];
+    for (const line of block.split('\n')) {
+        if (!line || line.startsWith(':')) continue;
+
+        const colon = line.indexOf(':');
+        const field = colon < 0 ? line : line.slice(0, colon);
+        let value = colon < 0 ? '' : line.slice(colon + 1);
+        if (value.startsWith(' ')) value = value.slice(1);
+
+        if (field === 'event') name = value;
+        else if (field === 'id') id = value;
+        else if (field === 'data') dataLines.push(value);
+    }
+    if (id !== null) lastEventId = id;
+    if (!dataLines.length) return;
+
+    try {
+        handleEvent(name, JSON.parse(dataLines.join('\n')));
+    } catch (err) {
+        console.error(err);
+    }
+}
+
+async function streamEvents() {
+    const headers = {
+        'Authorization': `Bearer ${accessToken}`,
+        'Accept': 'text/event-stream'
+    };
+    if (lastEventId) headers['Last-Event-ID'] = lastEventId;
+
+    const r = await fetch('/events', { headers });
+    if (r.status === 401 || r.status === 403) {
+        window.location.href = 'login.html';
+        return false;
+    }
+    if (!r.ok || !r.body) {
+        throw new Error(`unexpected status ${r.status}`);
+    }
+
+    const reader = r.body.pipeThrough(new TextDecoderStream()).getReader();
+    let buffer = '';
+    for (;;) {
+        const { value, done } = await reader.read();
+        if (done) return true;
+
+        buffer += value.replace(/\r\n?/g, '\n');
+        let end;
+        while ((end = buffer.indexOf('\n\n')) >= 0) {
+            dispatchEventBlock(buffer.slice(0, end));
+            buffer = buffer.slice(end + 2);
+        }
+    }
+}
+
+(async function listenForEvents() {
+    for (;;) {
+        try {
+            if (await streamEvents() === false) return;
+        } catch (err) {
+            console.error(err);
+        }
+        await new Promise((resolve) => setTimeout(resolve, EVENTS_RETRY_MS));
+    }
+})();
+// #endregion
@@ -86,7 +86,11 @@ async function login(username, password) {
             localStorage.setItem('username', username);
             localStorage.setItem('access_token', data.access_token);
 
-            window.location.href = '/index.html';
+            // Go back to the page which asked to log in, e.g. a checkout, but
+            // only on this site.
+            const next = new URL(window.location.href).searchParams.get('next');
+            const nextUrl = next ? new URL(next, location.origin) : null;
+            window.location.href = nextUrl?.origin === location.origin ? nextUrl.href : '/index.html';
         }).catch((err) => {
             statusMessageEl.innerHTML = 'Network error';
         })
@@ -0,0 +1,2 @@
+ALTER TABLE transactions DROP COLUMN reference;
+ALTER TABLE transactions DROP COLUMN memo;
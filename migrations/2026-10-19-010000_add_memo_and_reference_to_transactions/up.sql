@@ -0,0 +1,2 @@
+ALTER TABLE transactions ADD COLUMN memo TEXT;
+ALTER TABLE transactions ADD COLUMN reference TEXT;
@@ -0,0 +1,16 @@
+CREATE TABLE payment_requests (
+  id BLOB NOT NULL PRIMARY KEY,
+  requester BLOB NOT NULL,
+  payer BLOB,
+  amount TEXT NOT NULL,
+  memo TEXT,
+  status TEXT NOT NULL,
+  created_at TEXT NOT NULL,
+  expires_at TEXT NOT NULL,
+  transaction_id BLOB,
+  FOREIGN KEY (requester) REFERENCES users (id),
+  FOREIGN KEY (payer) REFERENCES users (id),
+  FOREIGN KEY (transaction_id) REFERENCES transactions (id)
+) STRICT;
+CREATE INDEX payment_requests_requester_idx ON payment_requests (requester);
+CREATE INDEX payment_requests_payer_idx ON payment_requests (payer);
@@ -0,0 +1 @@
+-- Padded timestamps are read the same as before, so there is nothing to undo.
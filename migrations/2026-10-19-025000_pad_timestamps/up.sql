@@ -0,0 +1,7 @@
+-- Timestamps are written with exactly nine fractional digits, so that they sort
+-- as text. Those written before had as few as needed.
+UPDATE payment_requests SET
+  created_at = substr(created_at, 1, 19) || '.' || substr(iif(substr(created_at, 20, 1) = '.', substr(created_at, 21, length(created_at) - 21), '') || '000000000', 1, 9) || 'Z',
+  expires_at = substr(expires_at, 1, 19) || '.' || substr(iif(substr(expires_at, 20, 1) = '.', substr(expires_at, 21, length(expires_at) - 21), '') || '000000000', 1, 9) || 'Z';
+UPDATE transactions SET
+  timestamp = substr(timestamp, 1, 19) || '.' || substr(iif(substr(timestamp, 20, 1) = '.', substr(timestamp, 21, length(timestamp) - 21), '') || '000000000', 1, 9) || 'Z';
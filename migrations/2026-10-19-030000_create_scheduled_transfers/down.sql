@@ -0,0 +1,2 @@
+DROP TABLE scheduled_transfer_attempts;
+DROP TABLE scheduled_transfers;
@@ -0,0 +1,32 @@
+CREATE TABLE scheduled_transfers (
+  id BLOB NOT NULL PRIMARY KEY,
+  sender BLOB NOT NULL,
+  recipient BLOB NOT NULL,
+  amount TEXT NOT NULL,
+  memo TEXT,
+  reference TEXT,
+  recurrence TEXT,
+  starts_at TEXT NOT NULL,
+  occurrence INTEGER NOT NULL,
+  next_run_at TEXT NOT NULL,
+  retries INTEGER NOT NULL,
+  status TEXT NOT NULL,
+  created_at TEXT NOT NULL,
+  FOREIGN KEY (sender) REFERENCES users (id),
+  FOREIGN KEY (recipient) REFERENCES users (id)
+) STRICT;
+CREATE INDEX scheduled_transfers_sender_idx ON scheduled_transfers (sender);
+CREATE INDEX scheduled_transfers_status_next_run_at_idx ON scheduled_transfers (status, next_run_at);
+
+CREATE TABLE scheduled_transfer_attempts (
+  id BLOB NOT NULL PRIMARY KEY,
+  scheduled_transfer_id BLOB NOT NULL,
+  scheduled_for TEXT NOT NULL,
+  attempted_at TEXT NOT NULL,
+  outcome TEXT NOT NULL,
+  error TEXT,
+  transaction_id BLOB,
+  FOREIGN KEY (scheduled_transfer_id) REFERENCES scheduled_transfers (id),
+  FOREIGN KEY (transaction_id) REFERENCES transactions (id)
+) STRICT;
+CREATE INDEX scheduled_transfer_attempts_scheduled_transfer_id_idx ON scheduled_transfer_attempts (scheduled_transfer_id);
@@ -0,0 +1,17 @@
+CREATE TABLE holds (
+  id BLOB NOT NULL PRIMARY KEY,
+  sender BLOB NOT NULL,
+  recipient BLOB NOT NULL,
+  amount TEXT NOT NULL,
+  memo TEXT,
+  reference TEXT,
+  status TEXT NOT NULL,
+  created_at TEXT NOT NULL,
+  expires_at TEXT NOT NULL,
+  transaction_id BLOB,
+  FOREIGN KEY (sender) REFERENCES users (id),
+  FOREIGN KEY (recipient) REFERENCES users (id),
+  FOREIGN KEY (transaction_id) REFERENCES transactions (id)
+) STRICT;
+CREATE INDEX holds_sender_status_idx ON holds (sender, status);
+CREATE INDEX holds_recipient_idx ON holds (recipient);
@@ -0,0 +1,4 @@
+DROP INDEX transactions_original_transaction_id_idx;
+ALTER TABLE transactions DROP COLUMN flagged;
+ALTER TABLE transactions DROP COLUMN original_transaction_id;
+ALTER TABLE transactions DROP COLUMN kind;
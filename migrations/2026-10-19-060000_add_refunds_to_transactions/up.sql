@@ -0,0 +1,4 @@
+ALTER TABLE transactions ADD COLUMN kind TEXT NOT NULL DEFAULT 'transfer';
+ALTER TABLE transactions ADD COLUMN original_transaction_id BLOB REFERENCES transactions (id);
+ALTER TABLE transactions ADD COLUMN flagged INTEGER NOT NULL DEFAULT 0;
+CREATE INDEX transactions_original_transaction_id_idx ON transactions (original_transaction_id);
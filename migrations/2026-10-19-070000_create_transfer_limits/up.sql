@@ -0,0 +1,13 @@
+CREATE TABLE transfer_limits (
+  user_id BLOB NOT NULL PRIMARY KEY,
+  per_transaction TEXT,
+  daily TEXT,
+  monthly TEXT,
+  max_transfers INTEGER,
+  updated_at TEXT NOT NULL,
+  per_transaction_removed INTEGER NOT NULL DEFAULT 0,
+  daily_removed INTEGER NOT NULL DEFAULT 0,
+  monthly_removed INTEGER NOT NULL DEFAULT 0,
+  max_transfers_removed INTEGER NOT NULL DEFAULT 0,
+  FOREIGN KEY (user_id) REFERENCES users (id)
+) STRICT;
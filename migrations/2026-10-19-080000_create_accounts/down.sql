@@ -0,0 +1,9 @@
+ALTER TABLE scheduled_transfers DROP COLUMN currency;
+ALTER TABLE payment_requests DROP COLUMN currency;
+ALTER TABLE holds DROP COLUMN currency;
+ALTER TABLE transactions DROP COLUMN currency;
+ALTER TABLE users ADD COLUMN balance TEXT NOT NULL DEFAULT '0';
+UPDATE users SET balance = accounts.balance
+  FROM accounts
+  WHERE accounts.user_id = users.id AND accounts.currency = 'MYR';
+DROP TABLE accounts;
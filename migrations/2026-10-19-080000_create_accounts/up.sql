@@ -0,0 +1,15 @@
+CREATE TABLE accounts (
+  user_id BLOB NOT NULL,
+  currency TEXT NOT NULL,
+  balance TEXT NOT NULL,
+  created_at TEXT NOT NULL,
+  PRIMARY KEY (user_id, currency),
+  FOREIGN KEY (user_id) REFERENCES users (id)
+) STRICT;
+INSERT INTO accounts (user_id, currency, balance, created_at)
+  SELECT id, 'MYR', balance, strftime('%Y-%m-%dT%H:%M:%S', 'now') || '.000000000Z' FROM users;
+ALTER TABLE users DROP COLUMN balance;
+ALTER TABLE transactions ADD COLUMN currency TEXT NOT NULL DEFAULT 'MYR';
+ALTER TABLE holds ADD COLUMN currency TEXT NOT NULL DEFAULT 'MYR';
+ALTER TABLE payment_requests ADD COLUMN currency TEXT NOT NULL DEFAULT 'MYR';
+ALTER TABLE scheduled_transfers ADD COLUMN currency TEXT NOT NULL DEFAULT 'MYR';
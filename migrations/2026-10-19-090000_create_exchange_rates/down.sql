@@ -0,0 +1,6 @@
+DROP TABLE exchange_quotes;
+ALTER TABLE transactions DROP COLUMN spread;
+ALTER TABLE transactions DROP COLUMN exchange_rate;
+ALTER TABLE transactions DROP COLUMN converted_currency;
+ALTER TABLE transactions DROP COLUMN converted_amount;
+DROP TABLE exchange_rates;
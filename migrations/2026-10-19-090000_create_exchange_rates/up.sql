@@ -0,0 +1,27 @@
+CREATE TABLE exchange_rates (
+  source_currency TEXT NOT NULL,
+  target_currency TEXT NOT NULL,
+  rate TEXT NOT NULL,
+  spread TEXT NOT NULL,
+  updated_at TEXT NOT NULL,
+  PRIMARY KEY (source_currency, target_currency)
+) STRICT;
+ALTER TABLE transactions ADD COLUMN converted_amount TEXT;
+ALTER TABLE transactions ADD COLUMN converted_currency TEXT;
+ALTER TABLE transactions ADD COLUMN exchange_rate TEXT;
+ALTER TABLE transactions ADD COLUMN spread TEXT;
+CREATE TABLE exchange_quotes (
+  id BLOB NOT NULL PRIMARY KEY,
+  user_id BLOB NOT NULL,
+  source_currency TEXT NOT NULL,
+  source_amount TEXT NOT NULL,
+  target_currency TEXT NOT NULL,
+  target_amount TEXT NOT NULL,
+  rate TEXT NOT NULL,
+  spread TEXT NOT NULL,
+  created_at TEXT NOT NULL,
+  expires_at TEXT NOT NULL,
+  transaction_id BLOB,
+  FOREIGN KEY (user_id) REFERENCES users (id),
+  FOREIGN KEY (transaction_id) REFERENCES transactions (id)
+) STRICT;
@@ -0,0 +1,6 @@
+DELETE FROM accounts WHERE user_id = X'00000000000070008000000000000001';
+DELETE FROM transactions WHERE sender = X'00000000000070008000000000000001' OR recipient = X'00000000000070008000000000000001';
+DELETE FROM users WHERE id = X'00000000000070008000000000000001';
+DROP TABLE fee_rules;
+ALTER TABLE transactions DROP COLUMN fee;
+ALTER TABLE users DROP COLUMN tier;
@@ -0,0 +1,16 @@
+ALTER TABLE users ADD COLUMN tier TEXT NOT NULL DEFAULT 'standard';
+ALTER TABLE transactions ADD COLUMN fee TEXT;
+CREATE TABLE fee_rules (
+  id BLOB NOT NULL PRIMARY KEY,
+  currency TEXT,
+  tier TEXT,
+  min_amount TEXT,
+  max_amount TEXT,
+  flat TEXT NOT NULL,
+  percentage TEXT NOT NULL,
+  min_fee TEXT,
+  max_fee TEXT,
+  priority INTEGER NOT NULL,
+  created_at TEXT NOT NULL
+) STRICT;
+INSERT INTO users (id, username, password_hash, role) VALUES (X'00000000000070008000000000000001', 'platform_revenue', '', 'system');
@@ -0,0 +1,4 @@
+DELETE FROM accounts WHERE user_id = X'00000000000070008000000000000002';
+DELETE FROM transactions WHERE sender = X'00000000000070008000000000000002' OR recipient = X'00000000000070008000000000000002';
+DELETE FROM users WHERE id = X'00000000000070008000000000000002';
+ALTER TABLE transactions DROP COLUMN initiator;
@@ -0,0 +1,13 @@
+ALTER TABLE transactions ADD COLUMN initiator BLOB;
+INSERT INTO users (id, username, password_hash, role) VALUES (X'00000000000070008000000000000002', 'treasury', '', 'system');
+-- Balances from before the treasury are recorded as minted and granted to their
+-- holders, or burnt from them if negative, so that the supply accounts for them.
+INSERT INTO transactions (id, amount, recipient, sender, timestamp, memo, kind, currency)
+  SELECT unhex(printf('%012X', CAST(unixepoch('subsec') * 1000 AS INTEGER)) || '7' || substr(hex(randomblob(2)), 1, 3) || '8' || substr(hex(randomblob(8)), 1, 15)), balance, X'00000000000070008000000000000002', X'00000000000070008000000000000002', strftime('%Y-%m-%dT%H:%M:%S', 'now') || '.000000000Z', 'opening balance', 'mint', currency
+  FROM accounts WHERE user_id != X'00000000000070008000000000000002' AND CAST(balance AS REAL) > 0;
+INSERT INTO transactions (id, amount, recipient, sender, timestamp, memo, kind, currency)
+  SELECT unhex(printf('%012X', CAST(unixepoch('subsec') * 1000 AS INTEGER)) || '7' || substr(hex(randomblob(2)), 1, 3) || '8' || substr(hex(randomblob(8)), 1, 15)), balance, user_id, X'00000000000070008000000000000002', strftime('%Y-%m-%dT%H:%M:%S', 'now') || '.000000000Z', 'opening balance', 'grant', currency
+  FROM accounts WHERE user_id != X'00000000000070008000000000000002' AND CAST(balance AS REAL) > 0;
+INSERT INTO transactions (id, amount, recipient, sender, timestamp, memo, kind, currency)
+  SELECT unhex(printf('%012X', CAST(unixepoch('subsec') * 1000 AS INTEGER)) || '7' || substr(hex(randomblob(2)), 1, 3) || '8' || substr(hex(randomblob(8)), 1, 15)), substr(balance, 2), X'00000000000070008000000000000002', user_id, strftime('%Y-%m-%dT%H:%M:%S', 'now') || '.000000000Z', 'opening balance', 'burn', currency
+  FROM accounts WHERE user_id != X'00000000000070008000000000000002' AND CAST(balance AS REAL) < 0;
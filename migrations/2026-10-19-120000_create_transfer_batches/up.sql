@@ -0,0 +1,15 @@
+CREATE TABLE transfer_batches (
+  id BLOB NOT NULL PRIMARY KEY,
+  sender BLOB NOT NULL,
+  currency TEXT NOT NULL,
+  created_at TEXT NOT NULL,
+  FOREIGN KEY (sender) REFERENCES users (id)
+) STRICT;
+CREATE TABLE transfer_batch_items (
+  batch_id BLOB NOT NULL,
+  position INTEGER NOT NULL,
+  transaction_id BLOB NOT NULL,
+  PRIMARY KEY (batch_id, position),
+  FOREIGN KEY (batch_id) REFERENCES transfer_batches (id),
+  FOREIGN KEY (transaction_id) REFERENCES transactions (id)
+) STRICT;
@@ -0,0 +1,5 @@
+DROP TABLE group_settlements;
+DROP TABLE group_expense_shares;
+DROP TABLE group_expenses;
+DROP TABLE group_members;
+DROP TABLE groups;
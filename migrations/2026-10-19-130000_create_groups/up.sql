@@ -0,0 +1,41 @@
+CREATE TABLE groups (
+  id BLOB NOT NULL PRIMARY KEY,
+  name TEXT NOT NULL,
+  currency TEXT NOT NULL,
+  created_by BLOB NOT NULL,
+  created_at TEXT NOT NULL,
+  FOREIGN KEY (created_by) REFERENCES users (id)
+) STRICT;
+CREATE TABLE group_members (
+  group_id BLOB NOT NULL,
+  user_id BLOB NOT NULL,
+  joined_at TEXT NOT NULL,
+  PRIMARY KEY (group_id, user_id),
+  FOREIGN KEY (group_id) REFERENCES groups (id),
+  FOREIGN KEY (user_id) REFERENCES users (id)
+) STRICT;
+CREATE TABLE group_expenses (
+  id BLOB NOT NULL PRIMARY KEY,
+  group_id BLOB NOT NULL,
+  payer BLOB NOT NULL,
+  amount TEXT NOT NULL,
+  description TEXT NOT NULL,
+  created_at TEXT NOT NULL,
+  FOREIGN KEY (group_id) REFERENCES groups (id),
+  FOREIGN KEY (payer) REFERENCES users (id)
+) STRICT;
+CREATE TABLE group_expense_shares (
+  expense_id BLOB NOT NULL,
+  user_id BLOB NOT NULL,
+  amount TEXT NOT NULL,
+  PRIMARY KEY (expense_id, user_id),
+  FOREIGN KEY (expense_id) REFERENCES group_expenses (id),
+  FOREIGN KEY (user_id) REFERENCES users (id)
+) STRICT;
+CREATE TABLE group_settlements (
+  group_id BLOB NOT NULL,
+  transaction_id BLOB NOT NULL,
+  PRIMARY KEY (group_id, transaction_id),
+  FOREIGN KEY (group_id) REFERENCES groups (id),
+  FOREIGN KEY (transaction_id) REFERENCES transactions (id)
+) STRICT;
@@ -0,0 +1,3 @@
+DELETE FROM transactions WHERE kind IN ('pocket_deposit', 'pocket_withdrawal');
+ALTER TABLE transactions DROP COLUMN pocket_id;
+DROP TABLE pockets;
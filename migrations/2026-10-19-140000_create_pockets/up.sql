@@ -0,0 +1,14 @@
+CREATE TABLE pockets (
+  id BLOB NOT NULL PRIMARY KEY,
+  user_id BLOB NOT NULL,
+  name TEXT NOT NULL,
+  currency TEXT NOT NULL,
+  balance TEXT NOT NULL,
+  target_amount TEXT,
+  target_date TEXT,
+  created_at TEXT NOT NULL,
+  archived_at TEXT,
+  FOREIGN KEY (user_id, currency) REFERENCES accounts (user_id, currency)
+) STRICT;
+CREATE INDEX pockets_user_id_idx ON pockets (user_id);
+ALTER TABLE transactions ADD COLUMN pocket_id BLOB REFERENCES pockets (id);
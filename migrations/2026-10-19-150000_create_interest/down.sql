@@ -0,0 +1,4 @@
+DROP TABLE interest_carryovers;
+DROP TABLE interest_accruals;
+DROP TABLE interest_rates;
+DELETE FROM transactions WHERE kind = 'interest';
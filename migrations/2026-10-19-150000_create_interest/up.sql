@@ -0,0 +1,31 @@
+CREATE TABLE interest_rates (
+  product TEXT NOT NULL,
+  currency TEXT NOT NULL,
+  annual_rate TEXT NOT NULL,
+  updated_at TEXT NOT NULL,
+  PRIMARY KEY (product, currency)
+) STRICT;
+CREATE TABLE interest_accruals (
+  product TEXT NOT NULL,
+  holder_id BLOB NOT NULL,
+  currency TEXT NOT NULL,
+  accrued_on TEXT NOT NULL,
+  user_id BLOB NOT NULL,
+  balance TEXT NOT NULL,
+  annual_rate TEXT NOT NULL,
+  amount TEXT NOT NULL,
+  created_at TEXT NOT NULL,
+  transaction_id BLOB,
+  PRIMARY KEY (product, holder_id, currency, accrued_on),
+  FOREIGN KEY (user_id) REFERENCES users (id),
+  FOREIGN KEY (transaction_id) REFERENCES transactions (id)
+) STRICT;
+CREATE INDEX interest_accruals_transaction_id_idx ON interest_accruals (transaction_id);
+CREATE TABLE interest_carryovers (
+  product TEXT NOT NULL,
+  holder_id BLOB NOT NULL,
+  currency TEXT NOT NULL,
+  amount TEXT NOT NULL,
+  updated_at TEXT NOT NULL,
+  PRIMARY KEY (product, holder_id, currency)
+) STRICT;
@@ -0,0 +1,2 @@
+DROP TABLE checkout_sessions;
+ALTER TABLE users DROP COLUMN kind;
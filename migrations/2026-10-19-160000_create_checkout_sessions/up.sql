@@ -0,0 +1,21 @@
+ALTER TABLE users ADD COLUMN kind TEXT NOT NULL DEFAULT 'personal';
+CREATE TABLE checkout_sessions (
+  id BLOB NOT NULL PRIMARY KEY,
+  merchant_id BLOB NOT NULL,
+  amount TEXT NOT NULL,
+  currency TEXT NOT NULL,
+  reference TEXT NOT NULL,
+  memo TEXT,
+  success_url TEXT NOT NULL,
+  cancel_url TEXT NOT NULL,
+  status TEXT NOT NULL,
+  created_at TEXT NOT NULL,
+  expires_at TEXT NOT NULL,
+  completed_at TEXT,
+  payer BLOB,
+  transaction_id BLOB,
+  FOREIGN KEY (merchant_id) REFERENCES users (id),
+  FOREIGN KEY (payer) REFERENCES users (id),
+  FOREIGN KEY (transaction_id) REFERENCES transactions (id)
+) STRICT;
+CREATE INDEX checkout_sessions_merchant_id_idx ON checkout_sessions (merchant_id);
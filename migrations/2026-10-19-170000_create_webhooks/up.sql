@@ -0,0 +1,34 @@
+CREATE TABLE webhook_endpoints (
+  id BLOB NOT NULL PRIMARY KEY,
+  user_id BLOB NOT NULL,
+  url TEXT NOT NULL,
+  secret TEXT NOT NULL,
+  all_users INTEGER NOT NULL,
+  created_at TEXT NOT NULL,
+  FOREIGN KEY (user_id) REFERENCES users (id)
+) STRICT;
+CREATE INDEX webhook_endpoints_user_id_idx ON webhook_endpoints (user_id);
+CREATE TABLE webhook_subscriptions (
+  endpoint_id BLOB NOT NULL,
+  event_type TEXT NOT NULL,
+  PRIMARY KEY (endpoint_id, event_type),
+  FOREIGN KEY (endpoint_id) REFERENCES webhook_endpoints (id)
+) STRICT;
+CREATE TABLE webhook_deliveries (
+  id BLOB NOT NULL PRIMARY KEY,
+  endpoint_id BLOB NOT NULL,
+  event_id BLOB NOT NULL,
+  event_type TEXT NOT NULL,
+  payload TEXT NOT NULL,
+  status TEXT NOT NULL,
+  attempts INTEGER NOT NULL,
+  next_attempt_at TEXT NOT NULL,
+  last_attempt_at TEXT,
+  last_response_status INTEGER,
+  last_error TEXT,
+  created_at TEXT NOT NULL,
+  delivered_at TEXT,
+  FOREIGN KEY (endpoint_id) REFERENCES webhook_endpoints (id)
+) STRICT;
+CREATE INDEX webhook_deliveries_endpoint_id_idx ON webhook_deliveries (endpoint_id);
+CREATE INDEX webhook_deliveries_status_next_attempt_at_idx ON webhook_deliveries (status, next_attempt_at);
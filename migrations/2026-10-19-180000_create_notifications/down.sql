@@ -0,0 +1,3 @@
+DROP TABLE known_devices;
+DROP TABLE notification_preferences;
+DROP TABLE notifications;
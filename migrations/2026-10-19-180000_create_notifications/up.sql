@@ -0,0 +1,25 @@
+CREATE TABLE notifications (
+  id BLOB NOT NULL PRIMARY KEY,
+  user_id BLOB NOT NULL,
+  kind TEXT NOT NULL,
+  data TEXT NOT NULL,
+  created_at TEXT NOT NULL,
+  read_at TEXT,
+  FOREIGN KEY (user_id) REFERENCES users (id)
+) STRICT;
+CREATE INDEX notifications_user_id_idx ON notifications (user_id);
+CREATE TABLE notification_preferences (
+  user_id BLOB NOT NULL,
+  kind TEXT NOT NULL,
+  enabled INTEGER NOT NULL,
+  PRIMARY KEY (user_id, kind),
+  FOREIGN KEY (user_id) REFERENCES users (id)
+) STRICT;
+CREATE TABLE known_devices (
+  user_id BLOB NOT NULL,
+  fingerprint BLOB NOT NULL,
+  first_seen_at TEXT NOT NULL,
+  last_seen_at TEXT NOT NULL,
+  PRIMARY KEY (user_id, fingerprint),
+  FOREIGN KEY (user_id) REFERENCES users (id)
+) STRICT;
@@ -0,0 +1,5 @@
+DROP TABLE gift_code_redemptions;
+DROP TABLE gift_codes;
+DELETE FROM accounts WHERE user_id = X'00000000000070008000000000000003';
+DELETE FROM transactions WHERE sender = X'00000000000070008000000000000003' OR recipient = X'00000000000070008000000000000003';
+DELETE FROM users WHERE id = X'00000000000070008000000000000003';
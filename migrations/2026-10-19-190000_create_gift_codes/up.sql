@@ -0,0 +1,33 @@
+INSERT INTO users (id, username, password_hash, role) VALUES (X'00000000000070008000000000000003', 'gift_code_escrow', '', 'system');
+CREATE TABLE gift_codes (
+  id BLOB NOT NULL PRIMARY KEY,
+  created_by BLOB NOT NULL,
+  funded_by BLOB NOT NULL,
+  code_hash BLOB NOT NULL UNIQUE,
+  amount TEXT NOT NULL,
+  currency TEXT NOT NULL,
+  max_redemptions INTEGER NOT NULL,
+  redemption_count INTEGER NOT NULL,
+  memo TEXT,
+  status TEXT NOT NULL,
+  created_at TEXT NOT NULL,
+  expires_at TEXT NOT NULL,
+  funding_transaction_id BLOB NOT NULL,
+  refund_transaction_id BLOB,
+  FOREIGN KEY (created_by) REFERENCES users (id),
+  FOREIGN KEY (funded_by) REFERENCES users (id),
+  FOREIGN KEY (funding_transaction_id) REFERENCES transactions (id),
+  FOREIGN KEY (refund_transaction_id) REFERENCES transactions (id)
+) STRICT;
+CREATE INDEX gift_codes_created_by_idx ON gift_codes (created_by);
+CREATE INDEX gift_codes_status_expires_at_idx ON gift_codes (status, expires_at);
+CREATE TABLE gift_code_redemptions (
+  gift_code_id BLOB NOT NULL,
+  user_id BLOB NOT NULL,
+  transaction_id BLOB NOT NULL,
+  redeemed_at TEXT NOT NULL,
+  PRIMARY KEY (gift_code_id, user_id),
+  FOREIGN KEY (gift_code_id) REFERENCES gift_codes (id),
+  FOREIGN KEY (user_id) REFERENCES users (id),
+  FOREIGN KEY (transaction_id) REFERENCES transactions (id)
+) STRICT;
@@ -0,0 +1,145 @@
+//! ISO 4217 currencies, and how precise amounts in them may be.
+
+use std::fmt;
+use std::str::FromStr;
+
+use bigdecimal::BigDecimal;
+use diesel::deserialize::{self, FromSql};
+use diesel::serialize::{self, Output, ToSql};
+use diesel::sql_types::Text;
+use diesel::sqlite::{Sqlite, SqliteValue};
+use diesel::{AsExpression, FromSqlRow};
+use serde::{Deserialize, Deserializer, Serialize, de};
+
+/// The currency of balances from before accounts had currencies, and of
+/// transfers which don't specify one.
+///
+/// Must match the default in the migration which added currencies.
+pub const DEFAULT_CURRENCY: Currency = Currency {
+    code: "MYR",
+    minor_units: 2,
+};
+
+/// The supported currencies, with the number of digits after the decimal
+/// separator of their minor unit.
+const CURRENCIES: &[Currency] = &[
+    Currency::new("AED", 2),
+    Currency::new("AUD", 2),
+    Currency::new("BHD", 3),
+    Currency::new("BRL", 2),
+    Currency::new("CAD", 2),
+    Currency::new("CHF", 2),
+    Currency::new("CNY", 2),
+    Currency::new("CZK", 2),
+    Currency::new("DKK", 2),
+    Currency::new("EUR", 2),
+    Currency::new("GBP", 2),
+    Currency::new("HKD", 2),
+    Currency::new("HUF", 2),
+    Currency::new("IDR", 2),
+    Currency::new("ILS", 2),
+    Currency::new("INR", 2),
+    Currency::new("JPY", 0),
+    Currency::new("KRW", 0),
+    Currency::new("KWD", 3),
+    Currency::new("MXN", 2),
+    DEFAULT_CURRENCY,
+    Currency::new("NOK", 2),
+    Currency::new("NZD", 2),
+    Currency::new("PHP", 2),
+    Currency::new("PLN", 2),
+    Currency::new("SAR", 2),
+    Currency::new("SEK", 2),
+    Currency::new("SGD", 2),
+    Currency::new("THB", 2),
+    Currency::new("TRY", 2),
+    Currency::new("TWD", 2),
+    Currency::new("USD", 2),
+    Currency::new("VND", 0),
+    Currency::new("ZAR", 2),
+];
+
+/// An ISO 4217 currency.
+#[derive(
+    Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug, AsExpression, FromSqlRow, Serialize,
+)]
+#[diesel(sql_type = Text)]
+#[serde(into = "String")]
+pub struct Currency {
+    code: &'static str,
+    minor_units: u8,
+}
+
+impl Currency {
+    const fn new(code: &'static str, minor_units: u8) -> Self {
+        Self { code, minor_units }
+    }
+
+    /// The alphabetic code, e.g. `MYR`.
+    pub fn code(self) -> &'static str {
+        self.code
+    }
+
+    /// The number of digits after the decimal separator of the minor unit,
+    /// e.g. 2 for sen, or 0 for currencies without a minor unit.
+    pub fn minor_units(self) -> u8 {
+        self.minor_units
+    }
+
+    /// Whether `amount` can be expressed in this currency, i.e. it is not more
+    /// precise than the minor unit.
+    pub fn is_valid_amount(self, amount: &BigDecimal) -> bool {
+        amount.normalized().fractional_digit_count() <= i64::from(self.minor_units)
+    }
+}
+
+impl fmt::Display for Currency {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.code)
+    }
+}
+
+impl FromStr for Currency {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        CURRENCIES
+            .iter()
+            .find(|currency| currency.code == s)
+            .copied()
+            .ok_or_else(|| format!("unsupported currency: {s}"))
+    }
+}
+
+// Not derived with `#[serde(try_from = "String")]`, as serde would then
+// require the deserializer to outlive the `&'static str` code.
+impl<'de> Deserialize<'de> for Currency {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let s = String::deserialize(deserializer)?;
+
+        s.parse().map_err(de::Error::custom)
+    }
+}
+
+impl From<Currency> for String {
+    fn from(value: Currency) -> Self {
+        value.code.to_owned()
+    }
+}
+
+impl FromSql<Text, Sqlite> for Currency {
+    fn from_sql(bytes: SqliteValue<'_, '_, '_>) -> deserialize::Result<Self> {
+        let s = <String as FromSql<Text, Sqlite>>::from_sql(bytes)?;
+
+        Ok(s.parse()?)
+    }
+}
+
+impl ToSql<Text, Sqlite> for Currency {
+    fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, Sqlite>) -> serialize::Result {
+        <str as ToSql<Text, Sqlite>>::to_sql(self.code, out)
+    }
+}
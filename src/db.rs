@@ -0,0 +1,55 @@
+use anyhow::Result;
+use diesel::{ConnectionError, ConnectionResult, SqliteConnection};
+use diesel_async::pooled_connection::deadpool::Pool;
+use diesel_async::pooled_connection::{AsyncDieselConnectionManager, ManagerConfig};
+use diesel_async::sync_connection_wrapper::SyncConnectionWrapper;
+use diesel_async::{AsyncConnection as _, SimpleAsyncConnection as _};
+use futures_lite::FutureExt as _;
+
+use crate::state::{DbConnection, DbConnectionPool};
+
+const DB_CONNECT_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(5);
+
+pub fn build_connection_pool(db_url: String) -> Result<DbConnectionPool> {
+    let mut config = ManagerConfig::default();
+    config.custom_setup = Box::new(|db_url| establish_connection(db_url).boxed());
+    let manager: AsyncDieselConnectionManager<DbConnection> =
+        AsyncDieselConnectionManager::<_>::new_with_config(db_url, config);
+    let pool = Pool::builder(manager)
+        .create_timeout(Some(DB_CONNECT_TIMEOUT))
+        .runtime(deadpool::Runtime::Tokio1)
+        .build()?;
+
+    Ok(pool)
+}
+
+pub async fn establish_connection(db_url: &str) -> ConnectionResult<DbConnection> {
+    let mut conn = SyncConnectionWrapper::<SqliteConnection>::establish(db_url).await?;
+
+    // See <https://fractaledmind.github.io/2023/09/07/enhancing-rails-sqlite-fine-tuning/>
+
+    // Sleep if the database is busy, this corresponds to up to 2 seconds sleeping
+    // time.
+    conn.batch_execute("PRAGMA busy_timeout = 2000;")
+        .await
+        .map_err(ConnectionError::CouldntSetupConfiguration)?;
+    // Better write-concurrency.
+    conn.batch_execute("PRAGMA journal_mode = WAL;")
+        .await
+        .map_err(ConnectionError::CouldntSetupConfiguration)?;
+    // Fsync only in critical moments.
+    conn.batch_execute("PRAGMA synchronous = NORMAL;")
+        .await
+        .map_err(ConnectionError::CouldntSetupConfiguration)?;
+    // Write WAL changes back every 1000 pages, for a 1MB WAL file on average.
+    // May affect readers if number is increased.
+    conn.batch_execute("PRAGMA wal_autocheckpoint = 1000;")
+        .await
+        .map_err(ConnectionError::CouldntSetupConfiguration)?;
+    // Free some space by truncating possibly massive WAL files from the last run.
+    conn.batch_execute("PRAGMA wal_checkpoint(TRUNCATE);")
+        .await
+        .map_err(ConnectionError::CouldntSetupConfiguration)?;
+
+    Ok(conn)
+}
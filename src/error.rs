@@ -67,3 +67,14 @@ impl IntoResponse for JsonRejection {
             .into_response()
     }
 }
+
+/// The response to a user who may not do what they asked to.
+pub fn permission_denied() -> Response {
+    (
+        StatusCode::FORBIDDEN,
+        Json(json!({
+            "title": "PermissionDenied",
+        })),
+    )
+        .into_response()
+}
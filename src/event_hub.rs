@@ -0,0 +1,256 @@
+//! An in-process hub which broadcasts events to the users they concern, e.g.
+//! over Server-Sent Events.
+//!
+//! Events are numbered in the order they are published, and the latest
+//! [`REPLAY_CAPACITY`] are kept, so that a subscriber which reconnects can
+//! resume after the last event it saw. The hub is local to this process, so
+//! events are neither kept across restarts nor shared between instances of the
+//! service. Subscribers which may have missed events are told to resync, i.e.
+//! to fetch the current state again.
+//!
+//! Whatever commits transactions, be it a handler or a background job,
+//! publishes them with [`publish_transactions`] once they are committed.
+
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+
+use anyhow::Context as _;
+use serde::Serialize;
+use tokio::sync::broadcast;
+use tracing::error;
+use uuid::Uuid;
+
+use crate::handlers::account::{AccountResponse, account_responses};
+use crate::handlers::transaction::PostTransactionResponse;
+use crate::models::Transaction;
+use crate::state::DbConnection;
+
+/// How many of the latest events are kept for subscribers which resume.
+pub const REPLAY_CAPACITY: usize = 1024;
+
+/// How many events may be queued for a subscriber which is slow to receive
+/// them, before it is told to resync.
+const CHANNEL_CAPACITY: usize = 256;
+
+#[derive(Clone)]
+pub struct EventHub {
+    sender: broadcast::Sender<Arc<Event>>,
+    recent: Arc<Mutex<Recent>>,
+    /// Tells the event IDs of this process apart from those of earlier ones.
+    epoch: i64,
+}
+
+/// The latest events, and the sequence number of the next one.
+struct Recent {
+    events: VecDeque<Arc<Event>>,
+    next_seq: u64,
+}
+
+#[derive(Debug)]
+pub struct Event {
+    seq: u64,
+    user_id: Uuid,
+    /// The event type, e.g. `transaction`.
+    pub name: &'static str,
+    /// The JSON data of the event.
+    pub data: String,
+}
+
+/// What a subscriber receives next.
+#[derive(Debug)]
+pub enum Delivery {
+    Event(Arc<Event>),
+    /// The subscriber may have missed events.
+    Resync,
+}
+
+/// The events of one user, starting with those which were missed.
+pub struct Subscription {
+    user_id: Uuid,
+    resync: bool,
+    backlog: VecDeque<Arc<Event>>,
+    receiver: broadcast::Receiver<Arc<Event>>,
+}
+
+impl EventHub {
+    pub fn new() -> Self {
+        Self {
+            sender: broadcast::channel(CHANNEL_CAPACITY).0,
+            recent: Arc::new(Mutex::new(Recent {
+                events: VecDeque::with_capacity(REPLAY_CAPACITY),
+                next_seq: 1,
+            })),
+            epoch: jiff::Timestamp::now().as_millisecond(),
+        }
+    }
+
+    /// Sends an event to the subscribers of `user_id`.
+    pub fn publish<T: Serialize>(
+        &self,
+        user_id: Uuid,
+        name: &'static str,
+        data: &T,
+    ) -> anyhow::Result<()> {
+        let data = serde_json::to_string(data).context("failed to serialize event")?;
+        let mut recent = self.recent.lock().expect("event hub mutex poisoned");
+
+        let event = Arc::new(Event {
+            seq: recent.next_seq,
+            user_id,
+            name,
+            data,
+        });
+        recent.next_seq = recent.next_seq.saturating_add(1);
+        if recent.events.len() >= REPLAY_CAPACITY {
+            recent.events.pop_front();
+        }
+        recent.events.push_back(Arc::clone(&event));
+
+        // The event is sent while the lock is held, so that subscribers see
+        // events either in their backlog or live, but never in both.
+        //
+        // Sending only fails if there are no subscribers.
+        let _ = self.sender.send(event);
+
+        Ok(())
+    }
+
+    /// Subscribes to the events of `user_id`, resuming after `last_event_id`,
+    /// as sent in the `Last-Event-ID` header, if any.
+    pub fn subscribe(&self, user_id: Uuid, last_event_id: Option<&str>) -> Subscription {
+        let recent = self.recent.lock().expect("event hub mutex poisoned");
+        let receiver = self.sender.subscribe();
+
+        let (resync, backlog) = match last_event_id {
+            None => (false, VecDeque::new()),
+            Some(last_event_id) => match self.parse_event_id(last_event_id) {
+                // Resume if no events were dropped since the last one seen.
+                Some(last_seq)
+                    if last_seq < recent.next_seq
+                        && recent
+                            .events
+                            .front()
+                            .is_none_or(|oldest| oldest.seq <= last_seq.saturating_add(1)) =>
+                {
+                    let backlog = recent
+                        .events
+                        .iter()
+                        .filter(|event| event.seq > last_seq && event.user_id == user_id)
+                        .cloned()
+                        .collect();
+                    (false, backlog)
+                },
+                _ => (true, VecDeque::new()),
+            },
+        };
+
+        Subscription {
+            user_id,
+            resync,
+            backlog,
+            receiver,
+        }
+    }
+
+    /// Returns the ID of `event`, to be sent as the SSE `id` field.
+    pub fn event_id(&self, event: &Event) -> String {
+        format!("{}-{}", self.epoch, event.seq)
+    }
+
+    /// Returns the sequence number of an event ID of this process.
+    fn parse_event_id(&self, event_id: &str) -> Option<u64> {
+        let (epoch, seq) = event_id.split_once('-')?;
+        if epoch.parse::<i64>().ok()? != self.epoch {
+            return None;
+        }
+
+        seq.parse().ok()
+    }
+}
+
+impl Default for EventHub {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Subscription {
+    /// Waits for what the subscriber receives next.
+    ///
+    /// Returns `None` once the hub is gone.
+    pub async fn next(&mut self) -> Option<Delivery> {
+        if self.resync {
+            self.resync = false;
+            return Some(Delivery::Resync);
+        }
+        if let Some(event) = self.backlog.pop_front() {
+            return Some(Delivery::Event(event));
+        }
+
+        loop {
+            match self.receiver.recv().await {
+                Ok(event) if event.user_id == self.user_id => return Some(Delivery::Event(event)),
+                Ok(_) => {},
+                Err(broadcast::error::RecvError::Lagged(_)) => return Some(Delivery::Resync),
+                Err(broadcast::error::RecvError::Closed) => return None,
+            }
+        }
+    }
+}
+
+/// The data of `balance` events.
+#[derive(Serialize)]
+struct BalanceEventData {
+    accounts: Vec<AccountResponse>,
+}
+
+/// Tells the senders and recipients of `transactions`, which were just
+/// committed, about them and their new balances.
+///
+/// Failures are only logged, as the transactions were made regardless.
+pub async fn publish_transactions(
+    event_hub: &EventHub,
+    conn: &mut DbConnection,
+    transactions: &[Transaction],
+) {
+    if let Err(err) = try_publish_transactions(event_hub, conn, transactions).await {
+        error!(?err, "failed to publish transaction events");
+    }
+}
+
+async fn try_publish_transactions(
+    event_hub: &EventHub,
+    conn: &mut DbConnection,
+    transactions: &[Transaction],
+) -> anyhow::Result<()> {
+    let mut user_ids = Vec::new();
+    for transaction in transactions {
+        let data = PostTransactionResponse::from(transaction.clone());
+        event_hub.publish(transaction.sender, "transaction", &data)?;
+        if transaction.recipient != transaction.sender {
+            event_hub.publish(transaction.recipient, "transaction", &data)?;
+        }
+
+        user_ids.extend([transaction.sender, transaction.recipient]);
+    }
+
+    user_ids.sort_unstable();
+    user_ids.dedup();
+    let now = jiff::Timestamp::now();
+    for user_id in user_ids {
+        publish_balance(event_hub, conn, user_id, now).await?;
+    }
+
+    Ok(())
+}
+
+async fn publish_balance(
+    event_hub: &EventHub,
+    conn: &mut DbConnection,
+    user_id: Uuid,
+    now: jiff::Timestamp,
+) -> anyhow::Result<()> {
+    let accounts = account_responses(conn, user_id, now).await?;
+
+    event_hub.publish(user_id, "balance", &BalanceEventData { accounts })
+}
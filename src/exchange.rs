@@ -0,0 +1,282 @@
+//! Conversion between currencies at the exchange rates set by admins.
+//!
+//! There is no external rate provider: rates are only ever what an admin set
+//! or imported.
+
+use std::str::FromStr as _;
+
+use anyhow::Context as _;
+use axum::Json;
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use bigdecimal::rounding::RoundingMode;
+use bigdecimal::{BigDecimal, One as _, Zero as _};
+use diesel::prelude::*;
+#[allow(
+    clippy::unused_trait_names,
+    reason = "error[E0034]: multiple applicable items in scope"
+)]
+use diesel_async::RunQueryDsl;
+use serde_json::json;
+use uuid::Uuid;
+
+use crate::currency::Currency;
+use crate::models::exchange_quote::NewExchangeQuote;
+use crate::models::{ExchangeQuote, ExchangeRate};
+use crate::state::DbConnection;
+
+/// How long a quote locks its rate for.
+pub const QUOTE_VALIDITY: jiff::SignedDuration = jiff::SignedDuration::from_secs(60);
+
+/// A conversion of an amount from one currency into another.
+#[derive(Clone, Debug)]
+pub struct NewQuote {
+    pub user_id: Uuid,
+    pub amount: BigDecimal,
+    pub source_currency: Currency,
+    pub target_currency: Currency,
+}
+
+/// Reasons a conversion is rejected.
+#[derive(Clone, Debug)]
+pub enum ConversionError {
+    InvalidAmount,
+    /// The amount is more precise than the minor unit of the source currency.
+    AmountTooPrecise(Currency),
+    SameCurrency,
+    RateUnavailable {
+        source_currency: Currency,
+        target_currency: Currency,
+    },
+    /// The amount converts to less than the minor unit of the target currency.
+    AmountTooSmall,
+}
+
+impl ConversionError {
+    /// The title of the error response.
+    pub fn title(&self) -> &'static str {
+        match self {
+            Self::InvalidAmount | Self::AmountTooPrecise(_) | Self::AmountTooSmall => {
+                "InvalidAmount"
+            },
+            Self::SameCurrency => "SameCurrency",
+            Self::RateUnavailable { .. } => "RateUnavailable",
+        }
+    }
+}
+
+impl IntoResponse for ConversionError {
+    fn into_response(self) -> Response {
+        let status = match self {
+            Self::InvalidAmount
+            | Self::AmountTooPrecise(_)
+            | Self::SameCurrency
+            | Self::AmountTooSmall => StatusCode::BAD_REQUEST,
+            Self::RateUnavailable { .. } => StatusCode::UNPROCESSABLE_ENTITY,
+        };
+
+        let body = match &self {
+            Self::AmountTooPrecise(currency) => json!({
+                "title": self.title(),
+                "detail": format!(
+                    "{currency} amounts may have at most {} decimal places",
+                    currency.minor_units()
+                ),
+            }),
+            Self::RateUnavailable {
+                source_currency,
+                target_currency,
+            } => json!({
+                "title": self.title(),
+                "detail": format!("no exchange rate from {source_currency} to {target_currency}"),
+            }),
+            Self::AmountTooSmall => json!({
+                "title": self.title(),
+                "detail": "amount is too small to convert",
+            }),
+            _ => json!({
+                "title": self.title(),
+            }),
+        };
+
+        (status, Json(body)).into_response()
+    }
+}
+
+/// Checks that a rate and spread can be used for conversions.
+pub fn validate_rate(rate: &BigDecimal, spread: &BigDecimal) -> Result<(), String> {
+    if *rate <= BigDecimal::zero() {
+        return Err("rate must be positive".to_owned());
+    }
+    if *spread < BigDecimal::zero() || *spread >= BigDecimal::one() {
+        return Err("spread must be at least 0 and less than 1".to_owned());
+    }
+
+    Ok(())
+}
+
+/// Converts `amount` at `exchange_rate`, less the spread, rounding down to the
+/// minor unit of the target currency.
+pub fn convert(amount: &BigDecimal, exchange_rate: &ExchangeRate) -> BigDecimal {
+    let converted = amount * &exchange_rate.rate * (BigDecimal::one() - &exchange_rate.spread);
+
+    converted
+        .with_scale_round(
+            i64::from(exchange_rate.target_currency.minor_units()),
+            RoundingMode::Down,
+        )
+        .normalized()
+}
+
+/// Returns the rate from `source_currency` to `target_currency`, if an admin
+/// has set one.
+pub async fn find_rate(
+    conn: &mut DbConnection,
+    source_currency: Currency,
+    target_currency: Currency,
+) -> anyhow::Result<Option<ExchangeRate>> {
+    use crate::schema::exchange_rates;
+
+    exchange_rates::table
+        .find((source_currency, target_currency))
+        .select(ExchangeRate::as_select())
+        .first(conn)
+        .await
+        .optional()
+        .context("failed to query exchange rates")
+}
+
+/// Returns the rate from `source_currency` to `target_currency` without the
+/// spread, which is what an amount is worth rather than what it converts to.
+///
+/// If only the rate the other way is set, its inverse is returned.
+pub async fn find_mid_rate(
+    conn: &mut DbConnection,
+    source_currency: Currency,
+    target_currency: Currency,
+) -> anyhow::Result<Option<BigDecimal>> {
+    if let Some(exchange_rate) = find_rate(conn, source_currency, target_currency).await? {
+        return Ok(Some(exchange_rate.rate));
+    }
+
+    Ok(find_rate(conn, target_currency, source_currency)
+        .await?
+        .map(|exchange_rate| BigDecimal::one() / exchange_rate.rate))
+}
+
+/// Converts an amount at the current rate, and locks the result for
+/// [`QUOTE_VALIDITY`] so that it can be used for a transfer.
+pub async fn create_quote(
+    conn: &mut DbConnection,
+    quote: &NewQuote,
+    now: jiff::Timestamp,
+) -> anyhow::Result<Result<ExchangeQuote, ConversionError>> {
+    use crate::schema::exchange_quotes;
+
+    if quote.amount <= BigDecimal::zero() {
+        return Ok(Err(ConversionError::InvalidAmount));
+    }
+    if !quote.source_currency.is_valid_amount(&quote.amount) {
+        return Ok(Err(ConversionError::AmountTooPrecise(
+            quote.source_currency,
+        )));
+    }
+    if quote.source_currency == quote.target_currency {
+        return Ok(Err(ConversionError::SameCurrency));
+    }
+
+    let Some(exchange_rate) = find_rate(conn, quote.source_currency, quote.target_currency).await?
+    else {
+        return Ok(Err(ConversionError::RateUnavailable {
+            source_currency: quote.source_currency,
+            target_currency: quote.target_currency,
+        }));
+    };
+
+    let target_amount = convert(&quote.amount, &exchange_rate);
+    if target_amount.is_zero() {
+        return Ok(Err(ConversionError::AmountTooSmall));
+    }
+
+    let new_quote = NewExchangeQuote {
+        id: Uuid::now_v7(),
+        user_id: quote.user_id,
+        source_currency: quote.source_currency,
+        source_amount: quote.amount.clone(),
+        target_currency: quote.target_currency,
+        target_amount,
+        rate: exchange_rate.rate,
+        spread: exchange_rate.spread,
+        created_at: now,
+        expires_at: now
+            .checked_add(QUOTE_VALIDITY)
+            .context("quote expiry out of range")?,
+    };
+
+    let created_quote = diesel::insert_into(exchange_quotes::table)
+        .values(new_quote)
+        .returning(ExchangeQuote::as_returning())
+        .get_result(conn)
+        .await
+        .context("failed to insert exchange quote")?;
+
+    Ok(Ok(created_quote))
+}
+
+/// Parses exchange rates from CSV with the columns `source_currency`,
+/// `target_currency`, `rate` and optionally `spread`, which defaults to 0.
+///
+/// A header row is skipped if present. Errors mention the line they are on.
+pub fn parse_rates_csv(csv: &str, now: jiff::Timestamp) -> Result<Vec<ExchangeRate>, String> {
+    let mut exchange_rates = Vec::new();
+    for (index, line) in csv.lines().enumerate() {
+        let line_number = index.saturating_add(1);
+        let line = line.trim();
+        if line.is_empty() || (index == 0 && line.starts_with("source_currency")) {
+            continue;
+        }
+
+        let fields = line.split(',').map(str::trim).collect::<Vec<_>>();
+        let (source_currency, target_currency, rate, spread) = match fields[..] {
+            [source_currency, target_currency, rate] => {
+                (source_currency, target_currency, rate, "0")
+            },
+            [source_currency, target_currency, rate, spread] => {
+                (source_currency, target_currency, rate, spread)
+            },
+            _ => {
+                return Err(format!(
+                    "line {line_number}: expected 3 or 4 fields, found {}",
+                    fields.len()
+                ));
+            },
+        };
+
+        let source_currency = source_currency
+            .parse::<Currency>()
+            .map_err(|err| format!("line {line_number}: {err}"))?;
+        let target_currency = target_currency
+            .parse::<Currency>()
+            .map_err(|err| format!("line {line_number}: {err}"))?;
+        if source_currency == target_currency {
+            return Err(format!(
+                "line {line_number}: source and target currency must differ"
+            ));
+        }
+        let rate = BigDecimal::from_str(rate)
+            .map_err(|err| format!("line {line_number}: invalid rate: {err}"))?;
+        let spread = BigDecimal::from_str(spread)
+            .map_err(|err| format!("line {line_number}: invalid spread: {err}"))?;
+        validate_rate(&rate, &spread).map_err(|err| format!("line {line_number}: {err}"))?;
+
+        exchange_rates.push(ExchangeRate {
+            source_currency,
+            target_currency,
+            rate,
+            spread,
+            updated_at: now,
+        });
+    }
+
+    Ok(exchange_rates)
+}
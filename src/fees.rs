@@ -0,0 +1,53 @@
+//! Fees charged to the senders of transfers, according to rules set by admins.
+
+use anyhow::Context as _;
+use bigdecimal::{BigDecimal, Zero as _};
+use diesel::prelude::*;
+#[allow(
+    clippy::unused_trait_names,
+    reason = "error[E0034]: multiple applicable items in scope"
+)]
+use diesel_async::RunQueryDsl;
+use uuid::Uuid;
+
+use crate::currency::Currency;
+use crate::models::FeeRule;
+use crate::models::user::UserTier;
+use crate::state::DbConnection;
+
+/// The system user whose accounts receive fees.
+///
+/// Must match the user inserted by the migration which added fees.
+pub const REVENUE_USER_ID: Uuid = Uuid::from_u128(0x0000_0000_0000_7000_8000_0000_0000_0001);
+
+/// Returns the fee for a transfer of `amount` in `currency` by `user_id`,
+/// which is zero if no rule applies.
+pub async fn fee_for(
+    conn: &mut DbConnection,
+    user_id: Uuid,
+    amount: &BigDecimal,
+    currency: Currency,
+) -> anyhow::Result<BigDecimal> {
+    use crate::models::types;
+    use crate::schema::{fee_rules, users};
+
+    let tier: UserTier = users::table
+        .find(types::Uuid::from(user_id))
+        .select(users::tier)
+        .first(conn)
+        .await
+        .context("could not find user")?;
+
+    // Amounts are stored as text, so rules are matched here rather than in SQL.
+    let fee_rules: Vec<FeeRule> = fee_rules::table
+        .select(FeeRule::as_select())
+        .order((fee_rules::priority.desc(), fee_rules::id.asc()))
+        .load(conn)
+        .await
+        .context("failed to query fee rules")?;
+
+    Ok(fee_rules
+        .iter()
+        .find(|fee_rule| fee_rule.applies_to(amount, currency, tier))
+        .map_or_else(BigDecimal::zero, |fee_rule| fee_rule.fee(amount, currency)))
+}
@@ -0,0 +1,426 @@
+//! Gift codes, which users fund for others to redeem for balance.
+//!
+//! Creating a gift code moves the funds for all of its redemptions into an
+//! escrow account, so that they can't be spent otherwise in the meantime. Each
+//! redemption pays its share out of escrow, and whatever is left once the gift
+//! code expires or is cancelled is refunded to whoever funded it. All of these
+//! are ledger transactions.
+//!
+//! Only a hash of each code is stored. Codes are random and long enough not to
+//! be guessed, so the hash needn't be slow, and redemption is rate limited on
+//! top of that.
+
+use std::slice;
+use std::time::Duration;
+
+use anyhow::Context as _;
+use axum::Json;
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use bigdecimal::{BigDecimal, Zero as _};
+use diesel::prelude::*;
+#[allow(
+    clippy::unused_trait_names,
+    reason = "error[E0034]: multiple applicable items in scope"
+)]
+use diesel_async::RunQueryDsl;
+use ring::digest;
+use secrecy::SecretString;
+use serde_json::json;
+use tracing::{error, info};
+use uuid::Uuid;
+
+use crate::currency::Currency;
+use crate::event_hub::{EventHub, publish_transactions};
+use crate::ledger::{self, NewGiftCodeMove, TransferError};
+use crate::limits::TransferLimits;
+use crate::models::gift_code::{GiftCodeRedemption, GiftCodeStatus};
+use crate::models::transaction::TransactionKind;
+use crate::models::{GiftCode, Transaction};
+use crate::state::{DbConnection, DbConnectionPool};
+
+/// The system user whose accounts hold the funds of gift codes until they are
+/// redeemed or refunded.
+///
+/// Must match the user inserted by the migration which added gift codes.
+pub const ESCROW_USER_ID: Uuid = Uuid::from_u128(0x0000_0000_0000_7000_8000_0000_0000_0003);
+
+/// How often to look for gift codes which expired.
+pub const POLL_INTERVAL: Duration = Duration::from_secs(60);
+
+/// The characters codes are made of, i.e. Crockford's Base32, which leaves out
+/// those which are easily confused.
+const CODE_ALPHABET: &[u8; 32] = b"0123456789ABCDEFGHJKMNPQRSTVWXYZ";
+
+/// The number of characters in a code, for 80 bits of randomness.
+const CODE_LEN: usize = 16;
+
+/// The number of characters between the dashes of a code, for readability.
+const CODE_GROUP_LEN: usize = 4;
+
+/// A gift code to be created.
+#[derive(Clone, Debug)]
+pub struct NewGiftCode {
+    pub created_by: Uuid,
+    /// The user the funds are taken from, either the creator or the treasury.
+    pub funded_by: Uuid,
+    /// The amount paid per redemption.
+    pub amount: BigDecimal,
+    pub currency: Currency,
+    pub max_redemptions: i32,
+    pub memo: Option<String>,
+    pub expires_at: jiff::Timestamp,
+}
+
+/// Reasons a redemption is rejected.
+#[derive(Clone, Debug)]
+pub enum RedemptionError {
+    /// No gift code has the code.
+    GiftCodeNotFound,
+    /// The gift code expired, was cancelled or was fully redeemed.
+    NotRedeemable,
+    /// The user already redeemed the gift code.
+    AlreadyRedeemed,
+    /// The user has no account in the currency of the gift code.
+    CurrencyMismatch(Currency),
+}
+
+impl RedemptionError {
+    /// The title of the error response.
+    pub fn title(&self) -> &'static str {
+        match self {
+            Self::GiftCodeNotFound => "GiftCodeNotFound",
+            Self::NotRedeemable => "GiftCodeNotRedeemable",
+            Self::AlreadyRedeemed => "AlreadyRedeemed",
+            Self::CurrencyMismatch(_) => "CurrencyMismatch",
+        }
+    }
+}
+
+impl IntoResponse for RedemptionError {
+    fn into_response(self) -> Response {
+        let status = match self {
+            Self::GiftCodeNotFound => StatusCode::NOT_FOUND,
+            Self::NotRedeemable => StatusCode::GONE,
+            Self::AlreadyRedeemed => StatusCode::CONFLICT,
+            Self::CurrencyMismatch(_) => StatusCode::BAD_REQUEST,
+        };
+
+        let body = match &self {
+            Self::CurrencyMismatch(currency) => json!({
+                "title": self.title(),
+                "detail": format!("a {currency} account is needed to redeem this gift code"),
+            }),
+            _ => json!({
+                "title": self.title(),
+            }),
+        };
+
+        (status, Json(body)).into_response()
+    }
+}
+
+/// Generates a new code, e.g. `7K2M-QX9D-4RTB-H0ZP`.
+pub fn generate_code() -> SecretString {
+    let bytes: [u8; CODE_LEN] = rand::random();
+
+    let mut code = String::with_capacity(CODE_LEN.saturating_mul(2));
+    for (i, b) in bytes.iter().enumerate() {
+        if i > 0 && i % CODE_GROUP_LEN == 0 {
+            code.push('-');
+        }
+        // 256 is a multiple of 32, so every character is equally likely.
+        code.push(char::from(CODE_ALPHABET[usize::from(b % 32)]));
+    }
+
+    code.into()
+}
+
+/// Returns the hash which a gift code with `code` is stored with.
+///
+/// Codes are compared regardless of case, dashes and whitespace, and with the
+/// characters which Crockford's Base32 leaves out read as those they are
+/// easily confused with.
+pub fn hash_code(code: &str) -> Vec<u8> {
+    let normalized: String = code
+        .chars()
+        .filter(|c| *c != '-' && !c.is_whitespace())
+        .map(|c| match c.to_ascii_uppercase() {
+            'O' => '0',
+            'I' | 'L' => '1',
+            c => c,
+        })
+        .collect();
+
+    digest::digest(&digest::SHA256, normalized.as_bytes())
+        .as_ref()
+        .to_vec()
+}
+
+/// Creates a gift code stored with `code_hash`, moving the funds for all of its
+/// redemptions into escrow, in its own database transaction.
+///
+/// See [`ledger::apply_gift_code_move`] for the limits which apply.
+pub async fn create(
+    conn: &mut DbConnection,
+    new_gift_code: &NewGiftCode,
+    code_hash: &[u8],
+    default_limits: &TransferLimits,
+) -> anyhow::Result<Result<(GiftCode, Transaction), TransferError>> {
+    use crate::schema::gift_codes;
+
+    ledger::run_immediate_transaction(conn, |conn| {
+        Box::pin(async move {
+            let funding = NewGiftCodeMove {
+                user_id: new_gift_code.funded_by,
+                amount: &new_gift_code.amount * BigDecimal::from(new_gift_code.max_redemptions),
+                currency: new_gift_code.currency,
+                kind: TransactionKind::GiftCodeFunding,
+                memo: new_gift_code.memo.clone(),
+                initiator: (new_gift_code.funded_by != new_gift_code.created_by)
+                    .then_some(new_gift_code.created_by),
+            };
+            let created_transaction =
+                match ledger::apply_gift_code_move(conn, &funding, default_limits).await? {
+                    Ok(created_transaction) => created_transaction,
+                    Err(err) => return Ok(Err(err)),
+                };
+
+            let gift_code = GiftCode {
+                id: Uuid::now_v7(),
+                created_by: new_gift_code.created_by,
+                funded_by: new_gift_code.funded_by,
+                code_hash: code_hash.to_vec(),
+                amount: new_gift_code.amount.clone(),
+                currency: new_gift_code.currency,
+                max_redemptions: new_gift_code.max_redemptions,
+                redemption_count: 0,
+                memo: new_gift_code.memo.clone(),
+                status: GiftCodeStatus::Active,
+                created_at: created_transaction.timestamp,
+                expires_at: new_gift_code.expires_at,
+                funding_transaction_id: created_transaction.id,
+                refund_transaction_id: None,
+            };
+            let created_gift_code: GiftCode = diesel::insert_into(gift_codes::table)
+                .values(gift_code)
+                .returning(GiftCode::as_returning())
+                .get_result(conn)
+                .await
+                .context("failed to insert gift code")?;
+
+            Ok(Ok((created_gift_code, created_transaction)))
+        })
+    })
+    .await
+}
+
+/// Redeems the gift code stored with `code_hash` for `user_id` at `now`, paying
+/// its amount out of escrow to them, in its own database transaction.
+pub async fn redeem(
+    conn: &mut DbConnection,
+    user_id: Uuid,
+    code_hash: &[u8],
+    now: jiff::Timestamp,
+) -> anyhow::Result<Result<(GiftCode, Transaction), RedemptionError>> {
+    use crate::models::types;
+    use crate::schema::{gift_code_redemptions, gift_codes};
+
+    ledger::run_immediate_transaction(conn, |conn| {
+        Box::pin(async move {
+            let gift_code: Option<GiftCode> = gift_codes::table
+                .filter(gift_codes::code_hash.eq(code_hash))
+                .select(GiftCode::as_select())
+                .first(conn)
+                .await
+                .optional()
+                .context("failed to query gift codes")?;
+            let Some(mut gift_code) = gift_code else {
+                return Ok(Err(RedemptionError::GiftCodeNotFound));
+            };
+            if gift_code.status_at(now) != GiftCodeStatus::Active {
+                return Ok(Err(RedemptionError::NotRedeemable));
+            }
+
+            let already_redeemed = diesel::select(diesel::dsl::exists(
+                gift_code_redemptions::table
+                    .find((types::Uuid::from(gift_code.id), types::Uuid::from(user_id))),
+            ))
+            .get_result::<bool>(conn)
+            .await
+            .context("failed to query gift code redemptions")?;
+            if already_redeemed {
+                return Ok(Err(RedemptionError::AlreadyRedeemed));
+            }
+
+            let payout = NewGiftCodeMove {
+                user_id,
+                amount: gift_code.amount.clone(),
+                currency: gift_code.currency,
+                kind: TransactionKind::GiftCodeRedemption,
+                memo: gift_code.memo.clone(),
+                initiator: None,
+            };
+            // Limits only apply to funding gift codes.
+            let created_transaction =
+                match ledger::apply_gift_code_move(conn, &payout, &TransferLimits::default())
+                    .await?
+                {
+                    Ok(created_transaction) => created_transaction,
+                    Err(TransferError::CurrencyMismatch(currency)) => {
+                        return Ok(Err(RedemptionError::CurrencyMismatch(currency)));
+                    },
+                    Err(err) => anyhow::bail!("failed to pay out gift code: {err:?}"),
+                };
+
+            diesel::insert_into(gift_code_redemptions::table)
+                .values(GiftCodeRedemption {
+                    gift_code_id: gift_code.id,
+                    user_id,
+                    transaction_id: created_transaction.id,
+                    redeemed_at: created_transaction.timestamp,
+                })
+                .execute(conn)
+                .await
+                .context("failed to insert gift code redemption")?;
+
+            gift_code.redemption_count = gift_code.redemption_count.saturating_add(1);
+            if gift_code.redemption_count >= gift_code.max_redemptions {
+                gift_code.status = GiftCodeStatus::Redeemed;
+            }
+            let gift_code: GiftCode =
+                diesel::update(gift_codes::table.find(types::Uuid::from(gift_code.id)))
+                    .set(gift_code)
+                    .returning(GiftCode::as_returning())
+                    .get_result(conn)
+                    .await
+                    .context("failed to update gift code")?;
+
+            Ok(Ok((gift_code, created_transaction)))
+        })
+    })
+    .await
+}
+
+/// Closes an active gift code as `status`, i.e. expired or cancelled, and
+/// refunds what is left of its funds, in its own database transaction.
+///
+/// Returns `None` if the gift code is no longer active, e.g. as it was fully
+/// redeemed in the meantime.
+pub async fn close(
+    conn: &mut DbConnection,
+    gift_code_id: Uuid,
+    status: GiftCodeStatus,
+) -> anyhow::Result<Option<(GiftCode, Option<Transaction>)>> {
+    use crate::models::types;
+    use crate::schema::gift_codes;
+
+    ledger::run_immediate_transaction(conn, |conn| {
+        Box::pin(async move {
+            let mut gift_code: GiftCode = gift_codes::table
+                .find(types::Uuid::from(gift_code_id))
+                .select(GiftCode::as_select())
+                .first(conn)
+                .await
+                .context("failed to query gift codes")?;
+            if gift_code.status != GiftCodeStatus::Active {
+                return Ok(None);
+            }
+
+            let remaining_redemptions = gift_code
+                .max_redemptions
+                .saturating_sub(gift_code.redemption_count);
+            let remaining = &gift_code.amount * BigDecimal::from(remaining_redemptions);
+            let created_transaction = if remaining > BigDecimal::zero() {
+                let refund = NewGiftCodeMove {
+                    user_id: gift_code.funded_by,
+                    amount: remaining,
+                    currency: gift_code.currency,
+                    kind: TransactionKind::GiftCodeRefund,
+                    memo: gift_code.memo.clone(),
+                    initiator: None,
+                };
+                // Limits only apply to funding gift codes.
+                let created_transaction =
+                    ledger::apply_gift_code_move(conn, &refund, &TransferLimits::default())
+                        .await?
+                        .map_err(|err| anyhow::anyhow!("failed to refund gift code: {err:?}"))?;
+                Some(created_transaction)
+            } else {
+                None
+            };
+
+            gift_code.status = status;
+            gift_code.refund_transaction_id = created_transaction
+                .as_ref()
+                .map(|created_transaction| created_transaction.id);
+            let gift_code: GiftCode =
+                diesel::update(gift_codes::table.find(types::Uuid::from(gift_code.id)))
+                    .set(gift_code)
+                    .returning(GiftCode::as_returning())
+                    .get_result(conn)
+                    .await
+                    .context("failed to update gift code")?;
+
+            Ok(Some((gift_code, created_transaction)))
+        })
+    })
+    .await
+}
+
+/// Expires gift codes and refunds what is left of them, forever.
+pub async fn run_gift_code_expiry(pool: DbConnectionPool, event_hub: EventHub) {
+    let mut interval = tokio::time::interval(POLL_INTERVAL);
+    interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+
+    loop {
+        interval.tick().await;
+
+        if let Err(err) = expire_due_gift_codes(&pool, &event_hub, jiff::Timestamp::now()).await {
+            error!(?err, "failed to expire gift codes");
+        }
+    }
+}
+
+/// Expires every active gift code which expired at `now`, refunding what is
+/// left of its funds, and publishes the refunds to `event_hub`.
+pub async fn expire_due_gift_codes(
+    pool: &DbConnectionPool,
+    event_hub: &EventHub,
+    now: jiff::Timestamp,
+) -> anyhow::Result<()> {
+    use crate::models::types;
+    use crate::schema::gift_codes;
+
+    let mut conn = pool
+        .get()
+        .await
+        .context("failed to get database connection")?;
+
+    let gift_codes: Vec<GiftCode> = gift_codes::table
+        .filter(gift_codes::status.eq(GiftCodeStatus::Active))
+        .filter(gift_codes::expires_at.le(types::Timestamp::from(now)))
+        .select(GiftCode::as_select())
+        .load(&mut conn)
+        .await
+        .context("failed to query gift codes")?;
+
+    for gift_code in gift_codes {
+        match close(&mut conn, gift_code.id, GiftCodeStatus::Expired).await {
+            Ok(Some((gift_code, refund))) => {
+                info!(
+                    %gift_code.id,
+                    refund_transaction_id = ?refund.as_ref().map(|refund| refund.id),
+                    "expired gift code"
+                );
+                if let Some(refund) = refund {
+                    publish_transactions(event_hub, &mut conn, slice::from_ref(&refund)).await;
+                }
+            },
+            Ok(None) => {},
+            Err(err) => error!(?err, %gift_code.id, "failed to expire gift code"),
+        }
+    }
+
+    Ok(())
+}
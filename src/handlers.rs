@@ -1,3 +1,20 @@
+pub mod account;
 pub mod auth;
+pub mod checkout_session;
+pub mod event;
+pub mod exchange;
+pub mod fee_rule;
+pub mod gift_code;
+pub mod group;
+pub mod hold;
+pub mod interest;
+pub mod notification;
+pub mod payment_link;
+pub mod payment_request;
+pub mod pocket;
+pub mod scheduled_transfer;
 pub mod transaction;
+pub mod transfer_limit;
+pub mod treasury;
 pub mod user;
+pub mod webhook_endpoint;
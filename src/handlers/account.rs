@@ -0,0 +1,178 @@
+use anyhow::Context as _;
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use axum::response::Result;
+use axum::{Extension, Json};
+use axum_extra::extract::WithRejection;
+use bigdecimal::{BigDecimal, Zero as _};
+use diesel::prelude::*;
+#[allow(
+    clippy::unused_trait_names,
+    reason = "error[E0034]: multiple applicable items in scope"
+)]
+use diesel_async::RunQueryDsl;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use uuid::Uuid;
+
+use crate::currency::Currency;
+use crate::error::{AppError, JsonRejection};
+use crate::ledger;
+use crate::middleware::auth::AuthenticatedUser;
+use crate::models::account::{Account, NewAccount};
+use crate::state::{DbConnection, DbConnectionPool};
+
+#[derive(Deserialize)]
+pub struct AccountsPathParams {
+    user_id: Uuid,
+}
+
+#[derive(Deserialize)]
+pub struct PostAccountPayload {
+    currency: Currency,
+}
+
+#[derive(Serialize)]
+pub struct GetAccountsResponse {
+    accounts: Vec<AccountResponse>,
+}
+
+#[derive(Serialize)]
+pub struct AccountResponse {
+    pub currency: Currency,
+    /// The same as `ledger_balance`, kept for consistency with users.
+    #[serde(with = "bigdecimal::serde::json_num")]
+    pub balance: BigDecimal,
+    /// The balance which may be spent, i.e. the ledger balance minus what is
+    /// reserved by active holds.
+    #[serde(with = "bigdecimal::serde::json_num")]
+    pub available_balance: BigDecimal,
+    /// The balance after all settled transactions.
+    #[serde(with = "bigdecimal::serde::json_num")]
+    pub ledger_balance: BigDecimal,
+    pub created_at: jiff::Timestamp,
+}
+
+/// Returns the accounts of the authenticated user, one per currency.
+pub async fn get_accounts(
+    State(pool): State<DbConnectionPool>,
+    Extension(authenticated_user): Extension<AuthenticatedUser>,
+    Path(AccountsPathParams { user_id }): Path<AccountsPathParams>,
+) -> Result<Json<GetAccountsResponse>> {
+    let mut conn = pool
+        .get()
+        .await
+        .context("failed to get database connection")
+        .map_err(AppError::from)?;
+
+    if authenticated_user.subject != user_id {
+        return Err((
+            StatusCode::FORBIDDEN,
+            Json(json!({
+                "title": "PermissionDenied",
+            })),
+        ))?;
+    }
+
+    let accounts = account_responses(&mut conn, user_id, jiff::Timestamp::now())
+        .await
+        .map_err(AppError::from)?;
+
+    Ok(Json(GetAccountsResponse { accounts }))
+}
+
+/// Opens an account in another currency for the authenticated user, with a
+/// balance of zero.
+pub async fn post_account(
+    State(pool): State<DbConnectionPool>,
+    Extension(authenticated_user): Extension<AuthenticatedUser>,
+    Path(AccountsPathParams { user_id }): Path<AccountsPathParams>,
+    WithRejection(Json(payload), _): WithRejection<Json<PostAccountPayload>, JsonRejection>,
+) -> Result<(StatusCode, Json<AccountResponse>)> {
+    use crate::schema::accounts;
+
+    let mut conn = pool
+        .get()
+        .await
+        .context("failed to get database connection")
+        .map_err(AppError::from)?;
+
+    if authenticated_user.subject != user_id {
+        return Err((
+            StatusCode::FORBIDDEN,
+            Json(json!({
+                "title": "PermissionDenied",
+            })),
+        ))?;
+    }
+
+    let new_account = NewAccount {
+        user_id,
+        currency: payload.currency,
+        balance: BigDecimal::zero(),
+        created_at: jiff::Timestamp::now(),
+    };
+    let account: Option<Account> = diesel::insert_into(accounts::table)
+        .values(new_account)
+        .on_conflict_do_nothing()
+        .returning(Account::as_returning())
+        .get_result(&mut conn)
+        .await
+        .optional()
+        .context("failed to insert account")
+        .map_err(AppError::from)?;
+    let Some(account) = account else {
+        return Err((
+            StatusCode::CONFLICT,
+            Json(json!({
+                "title": "AccountExists",
+                "detail": format!("you already have a {} account", payload.currency),
+            })),
+        ))?;
+    };
+
+    Ok((
+        StatusCode::CREATED,
+        Json(AccountResponse {
+            currency: account.currency,
+            available_balance: account.balance.clone(),
+            ledger_balance: account.balance.clone(),
+            balance: account.balance,
+            created_at: account.created_at,
+        }),
+    ))
+}
+
+/// Returns the accounts of `user_id` ordered by currency, with what is
+/// available to spend at `now`.
+pub async fn account_responses(
+    conn: &mut DbConnection,
+    user_id: Uuid,
+    now: jiff::Timestamp,
+) -> anyhow::Result<Vec<AccountResponse>> {
+    use crate::models::types;
+    use crate::schema::accounts;
+
+    let accounts: Vec<Account> = accounts::table
+        .filter(accounts::user_id.eq(types::Uuid::from(user_id)))
+        .order(accounts::currency.asc())
+        .select(Account::as_select())
+        .load(conn)
+        .await
+        .context("failed to query accounts")?;
+
+    let mut account_responses = Vec::with_capacity(accounts.len());
+    for account in accounts {
+        let held_amount = ledger::held_amount(conn, user_id, account.currency, now).await?;
+
+        account_responses.push(AccountResponse {
+            currency: account.currency,
+            available_balance: &account.balance - held_amount,
+            ledger_balance: account.balance.clone(),
+            balance: account.balance,
+            created_at: account.created_at,
+        });
+    }
+
+    Ok(account_responses)
+}
@@ -1,28 +1,30 @@
+use std::slice;
+
 use anyhow::Context as _;
 use axum::Json;
 use axum::extract::State;
-use axum::http::StatusCode;
+use axum::http::{HeaderMap, StatusCode, header};
 use axum::response::Result;
 use axum_extra::extract::WithRejection;
-use bigdecimal::BigDecimal;
-use biscuit::SingleOrMultiple::Single;
-use biscuit::jwa::SignatureAlgorithm;
-use biscuit::{ClaimsSet, JWT, RegisteredClaims, jws};
-use chrono::TimeDelta;
-use jiff::SpanRelativeTo;
+use bigdecimal::{BigDecimal, Zero as _};
 use secrecy::{ExposeSecret as _, SecretString};
 use serde::{Deserialize, Serialize};
 use serde_json::json;
-use tracing::debug;
+use tracing::{debug, warn};
 use uuid::Uuid;
 
+use crate::currency::DEFAULT_CURRENCY;
 use crate::error::{AppError, JsonRejection};
-use crate::middleware::auth::JwtAccessTokenClaims;
+use crate::event_hub::{EventHub, publish_transactions};
+use crate::jwt;
+use crate::ledger;
 use crate::models::User;
-use crate::models::user::NewUser;
+use crate::models::account::NewAccount;
+use crate::models::user::{NewUser, UserKind, UserRole, UserTier};
+use crate::notification;
 use crate::state::{
     AccessTokenAudience, AccessTokenClientId, AccessTokenExpiration, AccessTokenIssuer,
-    DbConnectionPool, JwsSigningSecret,
+    DbConnectionPool, JwsSigningSecret, SignupGrant,
 };
 
 #[derive(Debug, Deserialize)]
@@ -48,6 +50,10 @@ pub struct PostSignUpResponse {
     id: Uuid,
 }
 
+#[allow(
+    clippy::too_many_arguments,
+    reason = "each piece of state is extracted as its own argument"
+)]
 pub async fn post_login(
     State(pool): State<DbConnectionPool>,
     State(access_token_issuer): State<AccessTokenIssuer>,
@@ -55,6 +61,7 @@ pub async fn post_login(
     State(access_token_audience): State<AccessTokenAudience>,
     State(access_token_client_id): State<AccessTokenClientId>,
     State(jws_signing_secret): State<JwsSigningSecret>,
+    headers: HeaderMap,
     WithRejection(Json(payload), _): WithRejection<Json<PostLoginPayload>, JsonRejection>,
 ) -> Result<Json<PostLoginResponse>> {
     let mut conn = pool
@@ -122,48 +129,29 @@ pub async fn post_login(
         )
     })?;
 
-    let now = chrono::Utc::now();
-
-    let access_token_max_age = access_token_expiration
-        .0
-        .to_duration(SpanRelativeTo::days_are_24_hours())
-        .map_err(anyhow::Error::from)
-        .and_then(|duration| std::time::Duration::try_from(duration).map_err(anyhow::Error::from))
-        .and_then(|duration| TimeDelta::from_std(duration).map_err(anyhow::Error::from))
-        .expect("converting `access_token_expiration` should not fail");
-
-    let access_token = JWT::<JwtAccessTokenClaims, biscuit::Empty>::new_decoded(
-        jws::RegisteredHeader {
-            algorithm: SignatureAlgorithm::HS256,
-            media_type: Some("at+jwt".to_owned()),
-            ..Default::default()
-        }
-        .into(),
-        ClaimsSet {
-            registered: RegisteredClaims {
-                issuer: Some(access_token_issuer.0.as_str().to_owned()),
-                expiry: Some(now.checked_add_signed(access_token_max_age).unwrap().into()),
-                audience: Some(Single(access_token_audience.0.as_str().to_owned())),
-                subject: Some(user.id.to_string()),
-                issued_at: Some(now.into()),
-                id: Some(Uuid::new_v4().to_string()),
-                ..Default::default()
-            },
-            private: JwtAccessTokenClaims {
-                client_id: access_token_client_id.0.to_string(),
-            },
-        },
-    );
-    let access_token = access_token
-        .encode(&jws::Secret::Bytes(
-            jws_signing_secret.0.expose_secret().to_vec(),
-        ))
-        .context("failed to encode and sign access token")
-        .map_err(AppError::from)?;
-    let access_token = access_token
-        .encoded()
-        .expect("`access_token` should be already encoded")
-        .to_string();
+    // Tell the user if they logged in from a new device. This is only a
+    // courtesy, so failing to do so doesn't fail the login.
+    let user_agent = headers
+        .get(header::USER_AGENT)
+        .and_then(|user_agent| user_agent.to_str().ok());
+    let user_id = user.id;
+    if let Err(err) = ledger::run_immediate_transaction(&mut conn, |conn| {
+        Box::pin(async move { notification::record_login(conn, user_id, user_agent).await })
+    })
+    .await
+    {
+        warn!(%user_id, ?err, "could not record login");
+    }
+
+    let access_token = jwt::encode_access_token(
+        user.id,
+        &access_token_issuer,
+        access_token_expiration,
+        &access_token_audience,
+        access_token_client_id,
+        &jws_signing_secret,
+    )
+    .map_err(AppError::from)?;
 
     Ok(Json(PostLoginResponse {
         id: user.id,
@@ -171,8 +159,12 @@ pub async fn post_login(
     }))
 }
 
+/// Creates a user with an account in the default currency, which is granted
+/// [`SignupGrant`] from the treasury if the treasury holds enough.
 pub async fn post_signup(
     State(pool): State<DbConnectionPool>,
+    State(signup_grant): State<SignupGrant>,
+    State(event_hub): State<EventHub>,
     WithRejection(Json(payload), _): WithRejection<Json<PostSignupPayload>, JsonRejection>,
 ) -> Result<Json<PostSignUpResponse>> {
     use diesel::prelude::*;
@@ -182,7 +174,7 @@ pub async fn post_signup(
     )]
     use diesel_async::RunQueryDsl;
 
-    use crate::schema::users;
+    use crate::schema::{accounts, users};
 
     let mut conn = pool
         .get()
@@ -190,6 +182,20 @@ pub async fn post_signup(
         .context("failed to get database connection")
         .map_err(AppError::from)?;
 
+    // Recipients may be given by user ID or by username, and anything which reads as
+    // a user ID is taken as one, so such a username could never be paid by name.
+    if payload.username.parse::<Uuid>().is_ok() {
+        debug!(payload.username, "username is shaped like a user ID");
+
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(json!({
+                "title": "InvalidUsername",
+                "detail": "username must not be shaped like a user ID",
+            })),
+        ))?;
+    }
+
     // Check if the user exists.
     let existing_user: Option<User> = users::table
         .filter(users::username.eq(&payload.username))
@@ -218,22 +224,60 @@ pub async fn post_signup(
     }
 
     let password_hash = password_auth::generate_hash(payload.password.expose_secret()).into();
-    let balance = BigDecimal::from(rand::random_range(..=u16::MAX));
 
     let new_user = NewUser {
         id: Uuid::now_v7(),
         username: payload.username,
         password_hash,
-        balance,
+        role: UserRole::User,
+        tier: UserTier::Standard,
+        kind: UserKind::Personal,
+    };
+    let new_account = NewAccount {
+        user_id: new_user.id,
+        currency: DEFAULT_CURRENCY,
+        balance: BigDecimal::zero(),
+        created_at: jiff::Timestamp::now(),
     };
 
-    let created_user: User = diesel::insert_into(users::table)
-        .values(new_user)
-        .returning(User::as_returning())
-        .get_result(&mut conn)
-        .await
-        .context("failed to insert user")
-        .map_err(AppError::from)?;
+    let (created_user, grant) = ledger::run_immediate_transaction(&mut conn, |conn| {
+        let new_user = new_user.clone();
+        let new_account = new_account.clone();
+        let signup_grant = signup_grant.clone();
+        Box::pin(async move {
+            let created_user: User = diesel::insert_into(users::table)
+                .values(new_user)
+                .returning(User::as_returning())
+                .get_result(conn)
+                .await
+                .context("failed to insert user")?;
+
+            diesel::insert_into(accounts::table)
+                .values(new_account)
+                .execute(conn)
+                .await
+                .context("failed to insert account")?;
+
+            let mut grant = None;
+            if !signup_grant.0.is_zero() {
+                // Signing up doesn't depend on the grant, so that users can
+                // still sign up once the treasury runs out.
+                match ledger::apply_grant(conn, created_user.id, &signup_grant.0, DEFAULT_CURRENCY)
+                    .await?
+                {
+                    Ok(transaction) => grant = Some(transaction),
+                    Err(err) => warn!(%created_user.id, ?err, "could not grant signup bonus"),
+                }
+            }
+
+            Ok((created_user, grant))
+        })
+    })
+    .await
+    .map_err(AppError::from)?;
+    if let Some(grant) = grant {
+        publish_transactions(&event_hub, &mut conn, slice::from_ref(&grant)).await;
+    }
 
     Ok(Json(PostSignUpResponse {
         id: created_user.id,
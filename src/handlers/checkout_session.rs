@@ -0,0 +1,600 @@
+use std::slice;
+
+use anyhow::Context as _;
+use axum::extract::{Path, Query, State};
+use axum::http::StatusCode;
+use axum::response::{IntoResponse as _, Response, Result};
+use axum::{Extension, Json};
+use axum_extra::extract::WithRejection;
+use bigdecimal::{BigDecimal, Zero as _};
+use diesel::prelude::*;
+#[allow(
+    clippy::unused_trait_names,
+    reason = "error[E0034]: multiple applicable items in scope"
+)]
+use diesel_async::RunQueryDsl;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tracing::debug;
+use url::Url;
+use uuid::Uuid;
+
+use crate::currency::{Currency, DEFAULT_CURRENCY};
+use crate::error::{AppError, JsonRejection, permission_denied};
+use crate::event_hub::{EventHub, publish_transactions};
+use crate::handlers::payment_request::validate_expiry;
+use crate::handlers::transaction::{validate_memo, validate_reference};
+use crate::http_url;
+use crate::ledger::{self, NewTransfer, TransferError};
+use crate::limits::TransferLimits;
+use crate::middleware::auth::AuthenticatedUser;
+use crate::models::checkout_session::{CheckoutSessionStatus, NewCheckoutSession};
+use crate::models::user::UserKind;
+use crate::models::{CheckoutSession, User};
+use crate::state::{DbConnection, DbConnectionPool, PaymentPage, PublicUrl};
+use crate::webhook;
+
+/// How long a checkout session stays payable, unless specified otherwise.
+const DEFAULT_EXPIRY: jiff::SignedDuration = jiff::SignedDuration::from_hours(24);
+
+/// The longest a checkout session may stay payable, in days.
+const MAX_EXPIRY_DAYS: i64 = 30;
+
+/// The maximum length of a redirect URL, in bytes.
+const REDIRECT_URL_MAX_LEN: usize = 2048;
+
+#[derive(Deserialize)]
+pub struct CheckoutSessionPathParams {
+    checkout_session_id: Uuid,
+}
+
+#[derive(Deserialize)]
+pub struct GetCheckoutSessionsQueryParams {
+    status: Option<CheckoutSessionStatus>,
+}
+
+#[derive(Deserialize)]
+pub struct PostCheckoutSessionPayload {
+    #[serde(with = "bigdecimal::serde::json_num")]
+    amount: BigDecimal,
+    /// Defaults to [`DEFAULT_CURRENCY`].
+    currency: Option<Currency>,
+    reference: String,
+    memo: Option<String>,
+    success_url: String,
+    cancel_url: String,
+    expires_at: Option<jiff::Timestamp>,
+}
+
+#[derive(Serialize)]
+pub struct GetCheckoutSessionsResponse {
+    checkout_sessions: Vec<CheckoutSessionResponse>,
+}
+
+#[derive(Serialize)]
+pub struct CheckoutSessionResponse {
+    id: Uuid,
+    merchant: CheckoutSessionMerchantResponse,
+    #[serde(with = "bigdecimal::serde::json_num")]
+    amount: BigDecimal,
+    currency: Currency,
+    reference: String,
+    memo: Option<String>,
+    success_url: String,
+    cancel_url: String,
+    status: CheckoutSessionStatus,
+    /// The hosted page on which the session is paid.
+    url: Url,
+    created_at: jiff::Timestamp,
+    expires_at: jiff::Timestamp,
+    completed_at: Option<jiff::Timestamp>,
+    /// Only shown to the merchant and the payer.
+    payer: Option<Uuid>,
+    /// Only shown to the merchant and the payer.
+    transaction_id: Option<Uuid>,
+}
+
+#[derive(Serialize)]
+pub struct CheckoutSessionMerchantResponse {
+    id: Uuid,
+    username: String,
+}
+
+impl CheckoutSessionResponse {
+    fn new(
+        checkout_session: CheckoutSession,
+        merchant: &User,
+        public_url: &PublicUrl,
+        user_id: Uuid,
+        now: jiff::Timestamp,
+    ) -> Self {
+        // # Security
+        //
+        // Anyone holding the ID may see a checkout session, so as to pay it, but
+        // who paid is only shown to those involved.
+        let is_involved =
+            checkout_session.merchant_id == user_id || checkout_session.payer == Some(user_id);
+
+        Self {
+            id: checkout_session.id,
+            merchant: CheckoutSessionMerchantResponse {
+                id: merchant.id,
+                username: merchant.username.clone(),
+            },
+            status: checkout_session.status_at(now),
+            url: public_url.payment_uri(PaymentPage::CheckoutSession(checkout_session.id)),
+            amount: checkout_session.amount,
+            currency: checkout_session.currency,
+            reference: checkout_session.reference,
+            memo: checkout_session.memo,
+            success_url: checkout_session.success_url,
+            cancel_url: checkout_session.cancel_url,
+            created_at: checkout_session.created_at,
+            expires_at: checkout_session.expires_at,
+            completed_at: checkout_session.completed_at,
+            payer: checkout_session.payer.filter(|_| is_involved),
+            transaction_id: checkout_session.transaction_id.filter(|_| is_involved),
+        }
+    }
+}
+
+/// Creates a checkout session, which anyone holding its hosted page may pay to
+/// the authenticated user.
+///
+/// Only merchants may create checkout sessions.
+pub async fn post_checkout_session(
+    State(pool): State<DbConnectionPool>,
+    State(public_url): State<PublicUrl>,
+    Extension(authenticated_user): Extension<AuthenticatedUser>,
+    WithRejection(Json(payload), _): WithRejection<Json<PostCheckoutSessionPayload>, JsonRejection>,
+) -> Result<(StatusCode, Json<CheckoutSessionResponse>)> {
+    use crate::models::types;
+    use crate::schema::{checkout_sessions, users};
+
+    let mut conn = pool
+        .get()
+        .await
+        .context("failed to get database connection")
+        .map_err(AppError::from)?;
+
+    let merchant: User = users::table
+        .find(types::Uuid::from(authenticated_user.subject))
+        .select(User::as_select())
+        .first(&mut conn)
+        .await
+        .context("could not find user")
+        .map_err(AppError::from)?;
+    if merchant.kind != UserKind::Merchant {
+        return Err((
+            StatusCode::FORBIDDEN,
+            Json(json!({
+                "title": "PermissionDenied",
+                "detail": "only merchants may create checkout sessions",
+            })),
+        ))?;
+    }
+
+    let currency = payload.currency.unwrap_or(DEFAULT_CURRENCY);
+    if payload.amount <= BigDecimal::zero() {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(json!({
+                "title": "InvalidAmount",
+            })),
+        ))?;
+    }
+    if !currency.is_valid_amount(&payload.amount) {
+        return Err(TransferError::AmountTooPrecise(currency))?;
+    }
+    if ledger::find_account(&mut conn, merchant.id, currency)
+        .await
+        .map_err(AppError::from)?
+        .is_none()
+    {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(json!({
+                "title": "CurrencyMismatch",
+                "detail": format!("merchant must have a {currency} account"),
+            })),
+        ))?;
+    }
+
+    if let Err(detail) = validate_reference(&payload.reference) {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(json!({
+                "title": "InvalidReference",
+                "detail": detail,
+            })),
+        ))?;
+    }
+
+    let memo = validate_memo(payload.memo.as_deref())?;
+
+    // Payers' browsers are redirected to these URLs, rather than the service
+    // sending requests to them, so their hosts may be anywhere.
+    for (field, redirect_url) in [
+        ("success_url", &payload.success_url),
+        ("cancel_url", &payload.cancel_url),
+    ] {
+        if let Err(detail) = http_url::validate(redirect_url, REDIRECT_URL_MAX_LEN) {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                Json(json!({
+                    "title": "InvalidRedirectUrl",
+                    "detail": format!("{field}: {detail}"),
+                })),
+            ))?;
+        }
+    }
+
+    let now = jiff::Timestamp::now();
+    let expires_at = validate_expiry(now, payload.expires_at, DEFAULT_EXPIRY, MAX_EXPIRY_DAYS)?;
+
+    let new_checkout_session = NewCheckoutSession {
+        // Use a random UUID rather than a time-ordered one, as holding the ID is
+        // enough to pay a checkout session.
+        id: Uuid::new_v4(),
+        merchant_id: merchant.id,
+        amount: payload.amount,
+        currency,
+        reference: payload.reference,
+        memo,
+        success_url: payload.success_url,
+        cancel_url: payload.cancel_url,
+        status: CheckoutSessionStatus::Open,
+        created_at: now,
+        expires_at,
+    };
+
+    let created_checkout_session: CheckoutSession = diesel::insert_into(checkout_sessions::table)
+        .values(new_checkout_session)
+        .returning(CheckoutSession::as_returning())
+        .get_result(&mut conn)
+        .await
+        .context("failed to insert checkout session")
+        .map_err(AppError::from)?;
+
+    Ok((
+        StatusCode::CREATED,
+        Json(CheckoutSessionResponse::new(
+            created_checkout_session,
+            &merchant,
+            &public_url,
+            authenticated_user.subject,
+            now,
+        )),
+    ))
+}
+
+/// Lists checkout sessions created by the authenticated user.
+pub async fn get_checkout_sessions(
+    State(pool): State<DbConnectionPool>,
+    State(public_url): State<PublicUrl>,
+    Extension(authenticated_user): Extension<AuthenticatedUser>,
+    Query(GetCheckoutSessionsQueryParams { status }): Query<GetCheckoutSessionsQueryParams>,
+) -> Result<Json<GetCheckoutSessionsResponse>> {
+    use crate::models::types;
+    use crate::schema::{checkout_sessions, users};
+
+    let mut conn = pool
+        .get()
+        .await
+        .context("failed to get database connection")
+        .map_err(AppError::from)?;
+
+    let merchant: User = users::table
+        .find(types::Uuid::from(authenticated_user.subject))
+        .select(User::as_select())
+        .first(&mut conn)
+        .await
+        .context("could not find user")
+        .map_err(AppError::from)?;
+
+    let checkout_sessions: Vec<CheckoutSession> = checkout_sessions::table
+        .filter(checkout_sessions::merchant_id.eq(types::Uuid::from(merchant.id)))
+        .select(CheckoutSession::as_select())
+        .order(checkout_sessions::created_at.desc())
+        .load(&mut conn)
+        .await
+        .context("failed to query checkout sessions")
+        .map_err(AppError::from)?;
+
+    let now = jiff::Timestamp::now();
+    Ok(Json(GetCheckoutSessionsResponse {
+        checkout_sessions: checkout_sessions
+            .into_iter()
+            .map(|checkout_session| {
+                CheckoutSessionResponse::new(
+                    checkout_session,
+                    &merchant,
+                    &public_url,
+                    merchant.id,
+                    now,
+                )
+            })
+            .filter(|checkout_session| {
+                status.is_none_or(|status| checkout_session.status == status)
+            })
+            .collect(),
+    }))
+}
+
+/// Returns a checkout session, e.g. for the hosted page to show what is being
+/// paid.
+pub async fn get_checkout_session(
+    State(pool): State<DbConnectionPool>,
+    State(public_url): State<PublicUrl>,
+    Extension(authenticated_user): Extension<AuthenticatedUser>,
+    Path(CheckoutSessionPathParams {
+        checkout_session_id,
+    }): Path<CheckoutSessionPathParams>,
+) -> Result<Json<CheckoutSessionResponse>> {
+    let mut conn = pool
+        .get()
+        .await
+        .context("failed to get database connection")
+        .map_err(AppError::from)?;
+
+    let Some(checkout_session) = find_checkout_session(&mut conn, checkout_session_id)
+        .await
+        .map_err(AppError::from)?
+    else {
+        debug!(%checkout_session_id, "could not find checkout session");
+
+        return Err(checkout_session_not_found())?;
+    };
+
+    let checkout_session = checkout_session_response(
+        &mut conn,
+        checkout_session,
+        &public_url,
+        authenticated_user.subject,
+    )
+    .await
+    .map_err(AppError::from)?;
+
+    Ok(Json(checkout_session))
+}
+
+/// Pays a checkout session, by transferring its amount from the authenticated
+/// user to the merchant.
+///
+/// The response includes the transaction, so that the hosted page can send the
+/// payer on to the success URL.
+pub async fn post_checkout_session_pay(
+    State(pool): State<DbConnectionPool>,
+    State(public_url): State<PublicUrl>,
+    State(default_limits): State<TransferLimits>,
+    State(event_hub): State<EventHub>,
+    Extension(authenticated_user): Extension<AuthenticatedUser>,
+    Path(CheckoutSessionPathParams {
+        checkout_session_id,
+    }): Path<CheckoutSessionPathParams>,
+) -> Result<Json<CheckoutSessionResponse>> {
+    use crate::models::types;
+    use crate::schema::checkout_sessions;
+
+    let mut conn = pool
+        .get()
+        .await
+        .context("failed to get database connection")
+        .map_err(AppError::from)?;
+
+    let user_id = authenticated_user.subject;
+    let default_limits = &default_limits;
+    let (checkout_session, created_transaction) =
+        ledger::run_immediate_transaction(&mut conn, |conn| {
+            Box::pin(async move {
+                let mut checkout_session =
+                    match find_open_checkout_session(conn, checkout_session_id).await? {
+                        Ok(checkout_session) => checkout_session,
+                        Err(response) => return Ok(Err(response)),
+                    };
+
+                if checkout_session.merchant_id == user_id {
+                    return Ok(Err(permission_denied()));
+                }
+
+                let new_transfer = NewTransfer {
+                    amount: checkout_session.amount.clone(),
+                    currency: checkout_session.currency,
+                    recipient: checkout_session.merchant_id,
+                    sender: user_id,
+                    memo: checkout_session.memo.clone(),
+                    reference: Some(checkout_session.reference.clone()),
+                    quote_id: None,
+                };
+                let created_transaction =
+                    match ledger::apply_transfer(conn, &new_transfer, default_limits).await? {
+                        Ok(created_transaction) => created_transaction,
+                        Err(err) => return Ok(Err(err.into_response())),
+                    };
+
+                checkout_session.status = CheckoutSessionStatus::Completed;
+                checkout_session.completed_at = Some(created_transaction.timestamp);
+                checkout_session.payer = Some(user_id);
+                checkout_session.transaction_id = Some(created_transaction.id);
+
+                let checkout_session: CheckoutSession = diesel::update(
+                    checkout_sessions::table.find(types::Uuid::from(checkout_session.id)),
+                )
+                .set(checkout_session)
+                .returning(CheckoutSession::as_returning())
+                .get_result(conn)
+                .await
+                .context("failed to update checkout session")?;
+                webhook::enqueue_checkout_session_completed(conn, &checkout_session).await?;
+
+                Ok(Ok((checkout_session, created_transaction)))
+            })
+        })
+        .await
+        .map_err(AppError::from)??;
+    publish_transactions(&event_hub, &mut conn, slice::from_ref(&created_transaction)).await;
+
+    let checkout_session =
+        checkout_session_response(&mut conn, checkout_session, &public_url, user_id)
+            .await
+            .map_err(AppError::from)?;
+
+    Ok(Json(checkout_session))
+}
+
+/// Cancels an open checkout session, so that it can no longer be paid.
+///
+/// Only the merchant who created the checkout session may cancel it.
+pub async fn post_checkout_session_cancel(
+    State(pool): State<DbConnectionPool>,
+    State(public_url): State<PublicUrl>,
+    Extension(authenticated_user): Extension<AuthenticatedUser>,
+    Path(CheckoutSessionPathParams {
+        checkout_session_id,
+    }): Path<CheckoutSessionPathParams>,
+) -> Result<Json<CheckoutSessionResponse>> {
+    use crate::models::types;
+    use crate::schema::checkout_sessions;
+
+    let mut conn = pool
+        .get()
+        .await
+        .context("failed to get database connection")
+        .map_err(AppError::from)?;
+
+    let user_id = authenticated_user.subject;
+    let checkout_session = ledger::run_immediate_transaction(&mut conn, |conn| {
+        Box::pin(async move {
+            let mut checkout_session =
+                match find_open_checkout_session(conn, checkout_session_id).await? {
+                    Ok(checkout_session) => checkout_session,
+                    Err(response) => return Ok(Err(response)),
+                };
+
+            if checkout_session.merchant_id != user_id {
+                return Ok(Err(permission_denied()));
+            }
+
+            checkout_session.status = CheckoutSessionStatus::Cancelled;
+
+            let checkout_session: CheckoutSession = diesel::update(
+                checkout_sessions::table.find(types::Uuid::from(checkout_session.id)),
+            )
+            .set(checkout_session)
+            .returning(CheckoutSession::as_returning())
+            .get_result(conn)
+            .await
+            .context("failed to update checkout session")?;
+
+            Ok(Ok(checkout_session))
+        })
+    })
+    .await
+    .map_err(AppError::from)??;
+
+    let checkout_session =
+        checkout_session_response(&mut conn, checkout_session, &public_url, user_id)
+            .await
+            .map_err(AppError::from)?;
+
+    Ok(Json(checkout_session))
+}
+
+async fn find_checkout_session(
+    conn: &mut DbConnection,
+    checkout_session_id: Uuid,
+) -> anyhow::Result<Option<CheckoutSession>> {
+    use crate::models::types;
+    use crate::schema::checkout_sessions;
+
+    checkout_sessions::table
+        .find(types::Uuid::from(checkout_session_id))
+        .select(CheckoutSession::as_select())
+        .first(conn)
+        .await
+        .optional()
+        .context("failed to query checkout sessions")
+}
+
+/// Finds a checkout session which is still open.
+///
+/// Marks the checkout session as expired if it has expired.
+async fn find_open_checkout_session(
+    conn: &mut DbConnection,
+    checkout_session_id: Uuid,
+) -> anyhow::Result<Result<CheckoutSession, Response>> {
+    use crate::models::types;
+    use crate::schema::checkout_sessions;
+
+    let Some(checkout_session) = find_checkout_session(conn, checkout_session_id).await? else {
+        debug!(%checkout_session_id, "could not find checkout session");
+
+        return Ok(Err(checkout_session_not_found()));
+    };
+
+    match checkout_session.status_at(jiff::Timestamp::now()) {
+        CheckoutSessionStatus::Open => Ok(Ok(checkout_session)),
+        CheckoutSessionStatus::Expired => {
+            if checkout_session.status != CheckoutSessionStatus::Expired {
+                diesel::update(
+                    checkout_sessions::table.find(types::Uuid::from(checkout_session.id)),
+                )
+                .set(checkout_sessions::status.eq(CheckoutSessionStatus::Expired))
+                .execute(conn)
+                .await
+                .context("failed to update checkout session")?;
+            }
+
+            Ok(Err(checkout_session_not_open(
+                CheckoutSessionStatus::Expired,
+            )))
+        },
+        status => Ok(Err(checkout_session_not_open(status))),
+    }
+}
+
+async fn checkout_session_response(
+    conn: &mut DbConnection,
+    checkout_session: CheckoutSession,
+    public_url: &PublicUrl,
+    user_id: Uuid,
+) -> anyhow::Result<CheckoutSessionResponse> {
+    use crate::models::types;
+    use crate::schema::users;
+
+    let merchant: User = users::table
+        .find(types::Uuid::from(checkout_session.merchant_id))
+        .select(User::as_select())
+        .first(conn)
+        .await
+        .context("failed to query users")?;
+
+    Ok(CheckoutSessionResponse::new(
+        checkout_session,
+        &merchant,
+        public_url,
+        user_id,
+        jiff::Timestamp::now(),
+    ))
+}
+
+fn checkout_session_not_found() -> Response {
+    (
+        StatusCode::NOT_FOUND,
+        Json(json!({
+            "title": "CheckoutSessionNotFound",
+        })),
+    )
+        .into_response()
+}
+
+fn checkout_session_not_open(status: CheckoutSessionStatus) -> Response {
+    (
+        StatusCode::CONFLICT,
+        Json(json!({
+            "title": "CheckoutSessionNotOpen",
+            "detail": format!("checkout session is {status}"),
+        })),
+    )
+        .into_response()
+}
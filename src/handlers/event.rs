@@ -0,0 +1,61 @@
+use std::convert::Infallible;
+use std::time::Duration;
+
+use axum::Extension;
+use axum::extract::State;
+use axum::http::HeaderMap;
+use axum::response::sse::{self, KeepAlive, Sse};
+use futures_lite::{Stream, StreamExt as _};
+
+use crate::event_hub::{Delivery, EventHub};
+use crate::middleware::auth::AuthenticatedUser;
+
+/// How often to send a comment when there are no events, so that proxies don't
+/// close the connection and clients notice when it is gone.
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(15);
+
+/// The header an `EventSource` sends when it reconnects.
+const LAST_EVENT_ID_HEADER: &str = "last-event-id";
+
+/// How long clients should wait before reconnecting.
+const RETRY_INTERVAL: Duration = Duration::from_secs(3);
+
+/// Streams events of the authenticated user as Server-Sent Events:
+///
+/// * `transaction`, for each new transaction they sent or received, with the
+///   same data as when a transaction is made.
+/// * `balance`, with their accounts, whenever their balance changes.
+/// * `resync`, when events may have been missed, after which the client should
+///   fetch its balance and transactions again.
+///
+/// Events have IDs, so that a client which reconnects with the `Last-Event-ID`
+/// header is sent the events it missed.
+pub async fn get_events(
+    State(event_hub): State<EventHub>,
+    Extension(authenticated_user): Extension<AuthenticatedUser>,
+    headers: HeaderMap,
+) -> Sse<impl Stream<Item = Result<sse::Event, Infallible>>> {
+    let last_event_id = headers
+        .get(LAST_EVENT_ID_HEADER)
+        .and_then(|value| value.to_str().ok());
+    let subscription = event_hub.subscribe(authenticated_user.subject, last_event_id);
+
+    let retry = sse::Event::default().retry(RETRY_INTERVAL);
+    let events = futures_lite::stream::unfold(subscription, move |mut subscription| {
+        let event_hub = event_hub.clone();
+        async move {
+            let event = match subscription.next().await? {
+                Delivery::Event(event) => sse::Event::default()
+                    .id(event_hub.event_id(&event))
+                    .event(event.name)
+                    .data(&event.data),
+                Delivery::Resync => sse::Event::default().event("resync").data("{}"),
+            };
+
+            Some((Ok(event), subscription))
+        }
+    });
+
+    Sse::new(futures_lite::stream::once(Ok(retry)).chain(events))
+        .keep_alive(KeepAlive::new().interval(HEARTBEAT_INTERVAL))
+}
@@ -0,0 +1,398 @@
+use anyhow::Context as _;
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use axum::response::Result;
+use axum::{Extension, Json};
+use axum_extra::extract::WithRejection;
+use bigdecimal::{BigDecimal, Zero as _};
+use diesel::prelude::*;
+#[allow(
+    clippy::unused_trait_names,
+    reason = "error[E0034]: multiple applicable items in scope"
+)]
+use diesel_async::RunQueryDsl;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tracing::debug;
+use uuid::Uuid;
+
+use crate::currency::{Currency, DEFAULT_CURRENCY};
+use crate::error::{AppError, JsonRejection};
+use crate::exchange::{self, NewQuote};
+use crate::handlers::user::is_admin;
+use crate::ledger;
+use crate::middleware::auth::AuthenticatedUser;
+use crate::models::{ExchangeQuote, ExchangeRate};
+use crate::state::{DbConnection, DbConnectionPool};
+
+#[derive(Deserialize)]
+pub struct ExchangeRatePathParams {
+    source_currency: Currency,
+    target_currency: Currency,
+}
+
+#[derive(Deserialize)]
+pub struct ExchangeQuotePathParams {
+    quote_id: Uuid,
+}
+
+#[derive(Deserialize)]
+pub struct PutExchangeRatePayload {
+    #[serde(with = "bigdecimal::serde::json_num")]
+    rate: BigDecimal,
+    /// Defaults to 0.
+    #[serde(default, with = "bigdecimal::serde::json_num_option")]
+    spread: Option<BigDecimal>,
+}
+
+#[derive(Serialize)]
+pub struct GetExchangeRatesResponse {
+    exchange_rates: Vec<ExchangeRateResponse>,
+}
+
+#[derive(Serialize)]
+pub struct ExchangeRateResponse {
+    source_currency: Currency,
+    target_currency: Currency,
+    #[serde(with = "bigdecimal::serde::json_num")]
+    rate: BigDecimal,
+    #[serde(with = "bigdecimal::serde::json_num")]
+    spread: BigDecimal,
+    updated_at: jiff::Timestamp,
+}
+
+#[derive(Deserialize)]
+pub struct PostExchangeQuotePayload {
+    #[serde(with = "bigdecimal::serde::json_num")]
+    amount: BigDecimal,
+    /// Defaults to [`DEFAULT_CURRENCY`].
+    source_currency: Option<Currency>,
+    target_currency: Currency,
+}
+
+#[derive(Serialize)]
+pub struct ExchangeQuoteResponse {
+    id: Uuid,
+    source_currency: Currency,
+    #[serde(with = "bigdecimal::serde::json_num")]
+    source_amount: BigDecimal,
+    target_currency: Currency,
+    /// What the recipient is credited, after the spread.
+    #[serde(with = "bigdecimal::serde::json_num")]
+    target_amount: BigDecimal,
+    #[serde(with = "bigdecimal::serde::json_num")]
+    rate: BigDecimal,
+    #[serde(with = "bigdecimal::serde::json_num")]
+    spread: BigDecimal,
+    created_at: jiff::Timestamp,
+    expires_at: jiff::Timestamp,
+    /// The transfer which used the quote, if any.
+    transaction_id: Option<Uuid>,
+}
+
+impl From<ExchangeRate> for ExchangeRateResponse {
+    fn from(exchange_rate: ExchangeRate) -> Self {
+        Self {
+            source_currency: exchange_rate.source_currency,
+            target_currency: exchange_rate.target_currency,
+            rate: exchange_rate.rate,
+            spread: exchange_rate.spread,
+            updated_at: exchange_rate.updated_at,
+        }
+    }
+}
+
+impl From<ExchangeQuote> for ExchangeQuoteResponse {
+    fn from(quote: ExchangeQuote) -> Self {
+        Self {
+            id: quote.id,
+            source_currency: quote.source_currency,
+            source_amount: quote.source_amount,
+            target_currency: quote.target_currency,
+            target_amount: quote.target_amount,
+            rate: quote.rate,
+            spread: quote.spread,
+            created_at: quote.created_at,
+            expires_at: quote.expires_at,
+            transaction_id: quote.transaction_id,
+        }
+    }
+}
+
+/// Returns all exchange rates.
+pub async fn get_exchange_rates(
+    State(pool): State<DbConnectionPool>,
+) -> Result<Json<GetExchangeRatesResponse>> {
+    use crate::schema::exchange_rates;
+
+    let mut conn = pool
+        .get()
+        .await
+        .context("failed to get database connection")
+        .map_err(AppError::from)?;
+
+    let exchange_rates: Vec<ExchangeRate> = exchange_rates::table
+        .select(ExchangeRate::as_select())
+        .order((
+            exchange_rates::source_currency.asc(),
+            exchange_rates::target_currency.asc(),
+        ))
+        .load(&mut conn)
+        .await
+        .context("failed to query exchange rates")
+        .map_err(AppError::from)?;
+
+    Ok(Json(GetExchangeRatesResponse {
+        exchange_rates: exchange_rates.into_iter().map(Into::into).collect(),
+    }))
+}
+
+/// Sets the rate from one currency to another.
+///
+/// Only admins may set exchange rates.
+pub async fn put_exchange_rate(
+    State(pool): State<DbConnectionPool>,
+    Extension(authenticated_user): Extension<AuthenticatedUser>,
+    Path(ExchangeRatePathParams {
+        source_currency,
+        target_currency,
+    }): Path<ExchangeRatePathParams>,
+    WithRejection(Json(payload), _): WithRejection<Json<PutExchangeRatePayload>, JsonRejection>,
+) -> Result<Json<ExchangeRateResponse>> {
+    let mut conn = pool
+        .get()
+        .await
+        .context("failed to get database connection")
+        .map_err(AppError::from)?;
+
+    if !is_admin(&mut conn, authenticated_user.subject)
+        .await
+        .map_err(AppError::from)?
+    {
+        return Err((
+            StatusCode::FORBIDDEN,
+            Json(json!({
+                "title": "PermissionDenied",
+            })),
+        ))?;
+    }
+
+    if source_currency == target_currency {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(json!({
+                "title": "InvalidRate",
+                "detail": "source and target currency must differ",
+            })),
+        ))?;
+    }
+    let spread = payload.spread.unwrap_or_else(BigDecimal::zero);
+    if let Err(detail) = exchange::validate_rate(&payload.rate, &spread) {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(json!({
+                "title": "InvalidRate",
+                "detail": detail,
+            })),
+        ))?;
+    }
+
+    let exchange_rate = upsert_exchange_rate(
+        &mut conn,
+        ExchangeRate {
+            source_currency,
+            target_currency,
+            rate: payload.rate,
+            spread,
+            updated_at: jiff::Timestamp::now(),
+        },
+    )
+    .await
+    .map_err(AppError::from)?;
+
+    Ok(Json(exchange_rate.into()))
+}
+
+/// Removes the rate from one currency to another, so that it can no longer be
+/// converted.
+///
+/// Only admins may remove exchange rates.
+pub async fn delete_exchange_rate(
+    State(pool): State<DbConnectionPool>,
+    Extension(authenticated_user): Extension<AuthenticatedUser>,
+    Path(ExchangeRatePathParams {
+        source_currency,
+        target_currency,
+    }): Path<ExchangeRatePathParams>,
+) -> Result<StatusCode> {
+    use crate::schema::exchange_rates;
+
+    let mut conn = pool
+        .get()
+        .await
+        .context("failed to get database connection")
+        .map_err(AppError::from)?;
+
+    if !is_admin(&mut conn, authenticated_user.subject)
+        .await
+        .map_err(AppError::from)?
+    {
+        return Err((
+            StatusCode::FORBIDDEN,
+            Json(json!({
+                "title": "PermissionDenied",
+            })),
+        ))?;
+    }
+
+    diesel::delete(exchange_rates::table.find((source_currency, target_currency)))
+        .execute(&mut conn)
+        .await
+        .context("failed to delete exchange rate")
+        .map_err(AppError::from)?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Sets exchange rates from CSV, see [`exchange::parse_rates_csv`] for the
+/// format. Either all rates are set, or none if any is invalid.
+///
+/// Only admins may import exchange rates.
+pub async fn post_exchange_rates_import(
+    State(pool): State<DbConnectionPool>,
+    Extension(authenticated_user): Extension<AuthenticatedUser>,
+    csv: String,
+) -> Result<Json<GetExchangeRatesResponse>> {
+    let mut conn = pool
+        .get()
+        .await
+        .context("failed to get database connection")
+        .map_err(AppError::from)?;
+
+    if !is_admin(&mut conn, authenticated_user.subject)
+        .await
+        .map_err(AppError::from)?
+    {
+        return Err((
+            StatusCode::FORBIDDEN,
+            Json(json!({
+                "title": "PermissionDenied",
+            })),
+        ))?;
+    }
+
+    let exchange_rates = match exchange::parse_rates_csv(&csv, jiff::Timestamp::now()) {
+        Ok(exchange_rates) => exchange_rates,
+        Err(detail) => {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                Json(json!({
+                    "title": "InvalidCsv",
+                    "detail": detail,
+                })),
+            ))?;
+        },
+    };
+
+    let exchange_rates = ledger::run_immediate_transaction(&mut conn, |conn| {
+        let exchange_rates = exchange_rates.clone();
+        Box::pin(async move {
+            let mut imported = Vec::with_capacity(exchange_rates.len());
+            for exchange_rate in exchange_rates {
+                imported.push(upsert_exchange_rate(conn, exchange_rate).await?);
+            }
+            Ok(imported)
+        })
+    })
+    .await
+    .map_err(AppError::from)?;
+
+    Ok(Json(GetExchangeRatesResponse {
+        exchange_rates: exchange_rates.into_iter().map(Into::into).collect(),
+    }))
+}
+
+/// Converts an amount at the current rate, and locks the rate for
+/// [`exchange::QUOTE_VALIDITY`]. The quote can then be used for a transfer to
+/// a recipient with an account in the target currency.
+pub async fn post_exchange_quote(
+    State(pool): State<DbConnectionPool>,
+    Extension(authenticated_user): Extension<AuthenticatedUser>,
+    WithRejection(Json(payload), _): WithRejection<Json<PostExchangeQuotePayload>, JsonRejection>,
+) -> Result<(StatusCode, Json<ExchangeQuoteResponse>)> {
+    let mut conn = pool
+        .get()
+        .await
+        .context("failed to get database connection")
+        .map_err(AppError::from)?;
+
+    let new_quote = NewQuote {
+        user_id: authenticated_user.subject,
+        amount: payload.amount,
+        source_currency: payload.source_currency.unwrap_or(DEFAULT_CURRENCY),
+        target_currency: payload.target_currency,
+    };
+    let quote = exchange::create_quote(&mut conn, &new_quote, jiff::Timestamp::now())
+        .await
+        .map_err(AppError::from)??;
+
+    Ok((StatusCode::CREATED, Json(quote.into())))
+}
+
+/// Returns a quote of the authenticated user.
+pub async fn get_exchange_quote(
+    State(pool): State<DbConnectionPool>,
+    Extension(authenticated_user): Extension<AuthenticatedUser>,
+    Path(ExchangeQuotePathParams { quote_id }): Path<ExchangeQuotePathParams>,
+) -> Result<Json<ExchangeQuoteResponse>> {
+    use crate::models::types;
+    use crate::schema::exchange_quotes;
+
+    let mut conn = pool
+        .get()
+        .await
+        .context("failed to get database connection")
+        .map_err(AppError::from)?;
+
+    let quote: Option<ExchangeQuote> = exchange_quotes::table
+        .find(types::Uuid::from(quote_id))
+        .filter(exchange_quotes::user_id.eq(types::Uuid::from(authenticated_user.subject)))
+        .select(ExchangeQuote::as_select())
+        .first(&mut conn)
+        .await
+        .optional()
+        .context("failed to query exchange quotes")
+        .map_err(AppError::from)?;
+    let Some(quote) = quote else {
+        debug!(%quote_id, "could not find exchange quote");
+
+        return Err((
+            StatusCode::NOT_FOUND,
+            Json(json!({
+                "title": "QuoteNotFound",
+            })),
+        ))?;
+    };
+
+    Ok(Json(quote.into()))
+}
+
+async fn upsert_exchange_rate(
+    conn: &mut DbConnection,
+    exchange_rate: ExchangeRate,
+) -> anyhow::Result<ExchangeRate> {
+    use crate::schema::exchange_rates;
+
+    diesel::insert_into(exchange_rates::table)
+        .values(exchange_rate.clone())
+        .on_conflict((
+            exchange_rates::source_currency,
+            exchange_rates::target_currency,
+        ))
+        .do_update()
+        .set(exchange_rate)
+        .returning(ExchangeRate::as_returning())
+        .get_result(conn)
+        .await
+        .context("failed to upsert exchange rate")
+}
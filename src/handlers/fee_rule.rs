@@ -0,0 +1,326 @@
+use anyhow::Context as _;
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use axum::response::Result;
+use axum::{Extension, Json};
+use axum_extra::extract::WithRejection;
+use bigdecimal::{BigDecimal, Zero as _};
+use diesel::prelude::*;
+#[allow(
+    clippy::unused_trait_names,
+    reason = "error[E0034]: multiple applicable items in scope"
+)]
+use diesel_async::RunQueryDsl;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tracing::debug;
+use uuid::Uuid;
+
+use crate::currency::Currency;
+use crate::error::{AppError, JsonRejection};
+use crate::handlers::user::is_admin;
+use crate::middleware::auth::AuthenticatedUser;
+use crate::models::FeeRule;
+use crate::models::user::UserTier;
+use crate::state::{DbConnection, DbConnectionPool};
+
+#[derive(Deserialize)]
+pub struct FeeRulePathParams {
+    fee_rule_id: Uuid,
+}
+
+/// A fee rule. Conditions which are omitted always match, and fees which are
+/// omitted are zero.
+#[derive(Deserialize)]
+pub struct FeeRulePayload {
+    currency: Option<Currency>,
+    tier: Option<UserTier>,
+    #[serde(default, with = "bigdecimal::serde::json_num_option")]
+    min_amount: Option<BigDecimal>,
+    #[serde(default, with = "bigdecimal::serde::json_num_option")]
+    max_amount: Option<BigDecimal>,
+    #[serde(default, with = "bigdecimal::serde::json_num_option")]
+    flat: Option<BigDecimal>,
+    #[serde(default, with = "bigdecimal::serde::json_num_option")]
+    percentage: Option<BigDecimal>,
+    #[serde(default, with = "bigdecimal::serde::json_num_option")]
+    min_fee: Option<BigDecimal>,
+    #[serde(default, with = "bigdecimal::serde::json_num_option")]
+    max_fee: Option<BigDecimal>,
+    /// Of the rules which apply to a transfer, the one with the highest
+    /// priority is used. Defaults to 0.
+    #[serde(default)]
+    priority: i32,
+}
+
+#[derive(Serialize)]
+pub struct GetFeeRulesResponse {
+    fee_rules: Vec<FeeRuleResponse>,
+}
+
+#[derive(Serialize)]
+pub struct FeeRuleResponse {
+    id: Uuid,
+    currency: Option<Currency>,
+    tier: Option<UserTier>,
+    #[serde(with = "bigdecimal::serde::json_num_option")]
+    min_amount: Option<BigDecimal>,
+    #[serde(with = "bigdecimal::serde::json_num_option")]
+    max_amount: Option<BigDecimal>,
+    #[serde(with = "bigdecimal::serde::json_num")]
+    flat: BigDecimal,
+    #[serde(with = "bigdecimal::serde::json_num")]
+    percentage: BigDecimal,
+    #[serde(with = "bigdecimal::serde::json_num_option")]
+    min_fee: Option<BigDecimal>,
+    #[serde(with = "bigdecimal::serde::json_num_option")]
+    max_fee: Option<BigDecimal>,
+    priority: i32,
+    created_at: jiff::Timestamp,
+}
+
+impl From<FeeRule> for FeeRuleResponse {
+    fn from(fee_rule: FeeRule) -> Self {
+        Self {
+            id: fee_rule.id,
+            currency: fee_rule.currency,
+            tier: fee_rule.tier,
+            min_amount: fee_rule.min_amount,
+            max_amount: fee_rule.max_amount,
+            flat: fee_rule.flat,
+            percentage: fee_rule.percentage,
+            min_fee: fee_rule.min_fee,
+            max_fee: fee_rule.max_fee,
+            priority: fee_rule.priority,
+            created_at: fee_rule.created_at,
+        }
+    }
+}
+
+impl FeeRulePayload {
+    fn into_fee_rule(self, id: Uuid, created_at: jiff::Timestamp) -> FeeRule {
+        FeeRule {
+            id,
+            currency: self.currency,
+            tier: self.tier,
+            min_amount: self.min_amount,
+            max_amount: self.max_amount,
+            flat: self.flat.unwrap_or_else(BigDecimal::zero),
+            percentage: self.percentage.unwrap_or_else(BigDecimal::zero),
+            min_fee: self.min_fee,
+            max_fee: self.max_fee,
+            priority: self.priority,
+            created_at,
+        }
+    }
+}
+
+/// Returns all fee rules, the ones which take precedence first.
+///
+/// Only admins may see fee rules.
+pub async fn get_fee_rules(
+    State(pool): State<DbConnectionPool>,
+    Extension(authenticated_user): Extension<AuthenticatedUser>,
+) -> Result<Json<GetFeeRulesResponse>> {
+    use crate::schema::fee_rules;
+
+    let mut conn = pool
+        .get()
+        .await
+        .context("failed to get database connection")
+        .map_err(AppError::from)?;
+
+    if !is_admin(&mut conn, authenticated_user.subject)
+        .await
+        .map_err(AppError::from)?
+    {
+        return Err((
+            StatusCode::FORBIDDEN,
+            Json(json!({
+                "title": "PermissionDenied",
+            })),
+        ))?;
+    }
+
+    let fee_rules: Vec<FeeRule> = fee_rules::table
+        .select(FeeRule::as_select())
+        .order((fee_rules::priority.desc(), fee_rules::id.asc()))
+        .load(&mut conn)
+        .await
+        .context("failed to query fee rules")
+        .map_err(AppError::from)?;
+
+    Ok(Json(GetFeeRulesResponse {
+        fee_rules: fee_rules.into_iter().map(Into::into).collect(),
+    }))
+}
+
+/// Adds a fee rule.
+///
+/// Only admins may add fee rules.
+pub async fn post_fee_rule(
+    State(pool): State<DbConnectionPool>,
+    Extension(authenticated_user): Extension<AuthenticatedUser>,
+    WithRejection(Json(payload), _): WithRejection<Json<FeeRulePayload>, JsonRejection>,
+) -> Result<(StatusCode, Json<FeeRuleResponse>)> {
+    use crate::schema::fee_rules;
+
+    let mut conn = pool
+        .get()
+        .await
+        .context("failed to get database connection")
+        .map_err(AppError::from)?;
+
+    if !is_admin(&mut conn, authenticated_user.subject)
+        .await
+        .map_err(AppError::from)?
+    {
+        return Err((
+            StatusCode::FORBIDDEN,
+            Json(json!({
+                "title": "PermissionDenied",
+            })),
+        ))?;
+    }
+
+    let fee_rule = payload.into_fee_rule(Uuid::now_v7(), jiff::Timestamp::now());
+    if let Err(detail) = fee_rule.validate() {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(json!({
+                "title": "InvalidFeeRule",
+                "detail": detail,
+            })),
+        ))?;
+    }
+
+    let fee_rule: FeeRule = diesel::insert_into(fee_rules::table)
+        .values(fee_rule)
+        .returning(FeeRule::as_returning())
+        .get_result(&mut conn)
+        .await
+        .context("failed to insert fee rule")
+        .map_err(AppError::from)?;
+
+    Ok((StatusCode::CREATED, Json(fee_rule.into())))
+}
+
+/// Replaces a fee rule.
+///
+/// Only admins may change fee rules.
+pub async fn put_fee_rule(
+    State(pool): State<DbConnectionPool>,
+    Extension(authenticated_user): Extension<AuthenticatedUser>,
+    Path(FeeRulePathParams { fee_rule_id }): Path<FeeRulePathParams>,
+    WithRejection(Json(payload), _): WithRejection<Json<FeeRulePayload>, JsonRejection>,
+) -> Result<Json<FeeRuleResponse>> {
+    use crate::models::types;
+    use crate::schema::fee_rules;
+
+    let mut conn = pool
+        .get()
+        .await
+        .context("failed to get database connection")
+        .map_err(AppError::from)?;
+
+    if !is_admin(&mut conn, authenticated_user.subject)
+        .await
+        .map_err(AppError::from)?
+    {
+        return Err((
+            StatusCode::FORBIDDEN,
+            Json(json!({
+                "title": "PermissionDenied",
+            })),
+        ))?;
+    }
+
+    let Some(existing) = find_fee_rule(&mut conn, fee_rule_id)
+        .await
+        .map_err(AppError::from)?
+    else {
+        debug!(%fee_rule_id, "could not find fee rule");
+
+        return Err((
+            StatusCode::NOT_FOUND,
+            Json(json!({
+                "title": "FeeRuleNotFound",
+            })),
+        ))?;
+    };
+
+    let fee_rule = payload.into_fee_rule(existing.id, existing.created_at);
+    if let Err(detail) = fee_rule.validate() {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(json!({
+                "title": "InvalidFeeRule",
+                "detail": detail,
+            })),
+        ))?;
+    }
+
+    let fee_rule: FeeRule = diesel::update(fee_rules::table.find(types::Uuid::from(fee_rule_id)))
+        .set(fee_rule)
+        .returning(FeeRule::as_returning())
+        .get_result(&mut conn)
+        .await
+        .context("failed to update fee rule")
+        .map_err(AppError::from)?;
+
+    Ok(Json(fee_rule.into()))
+}
+
+/// Removes a fee rule.
+///
+/// Only admins may remove fee rules.
+pub async fn delete_fee_rule(
+    State(pool): State<DbConnectionPool>,
+    Extension(authenticated_user): Extension<AuthenticatedUser>,
+    Path(FeeRulePathParams { fee_rule_id }): Path<FeeRulePathParams>,
+) -> Result<StatusCode> {
+    use crate::models::types;
+    use crate::schema::fee_rules;
+
+    let mut conn = pool
+        .get()
+        .await
+        .context("failed to get database connection")
+        .map_err(AppError::from)?;
+
+    if !is_admin(&mut conn, authenticated_user.subject)
+        .await
+        .map_err(AppError::from)?
+    {
+        return Err((
+            StatusCode::FORBIDDEN,
+            Json(json!({
+                "title": "PermissionDenied",
+            })),
+        ))?;
+    }
+
+    diesel::delete(fee_rules::table.find(types::Uuid::from(fee_rule_id)))
+        .execute(&mut conn)
+        .await
+        .context("failed to delete fee rule")
+        .map_err(AppError::from)?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn find_fee_rule(
+    conn: &mut DbConnection,
+    fee_rule_id: Uuid,
+) -> anyhow::Result<Option<FeeRule>> {
+    use crate::models::types;
+    use crate::schema::fee_rules;
+
+    fee_rules::table
+        .find(types::Uuid::from(fee_rule_id))
+        .select(FeeRule::as_select())
+        .first(conn)
+        .await
+        .optional()
+        .context("failed to query fee rules")
+}
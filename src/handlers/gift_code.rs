@@ -0,0 +1,410 @@
+use std::slice;
+
+use anyhow::Context as _;
+use axum::extract::{Path, State};
+use axum::http::{StatusCode, header};
+use axum::response::{IntoResponse as _, Response, Result};
+use axum::{Extension, Json};
+use axum_extra::extract::WithRejection;
+use bigdecimal::{BigDecimal, Zero as _};
+use diesel::prelude::*;
+#[allow(
+    clippy::unused_trait_names,
+    reason = "error[E0034]: multiple applicable items in scope"
+)]
+use diesel_async::RunQueryDsl;
+use secrecy::{ExposeSecret as _, SecretString};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tracing::debug;
+use uuid::Uuid;
+
+use crate::currency::{Currency, DEFAULT_CURRENCY};
+use crate::error::{AppError, JsonRejection};
+use crate::event_hub::{EventHub, publish_transactions};
+use crate::gift_code::{self, NewGiftCode};
+use crate::handlers::payment_request::validate_expiry;
+use crate::handlers::transaction::validate_memo;
+use crate::handlers::user::is_admin;
+use crate::ledger::{TREASURY_USER_ID, TransferError};
+use crate::limits::TransferLimits;
+use crate::middleware::auth::AuthenticatedUser;
+use crate::models::GiftCode;
+use crate::models::gift_code::GiftCodeStatus;
+use crate::state::{DbConnectionPool, GiftCodeRedemptionRateLimiter};
+
+/// How long a gift code may be redeemed, unless specified otherwise.
+const DEFAULT_EXPIRY: jiff::SignedDuration = jiff::SignedDuration::from_hours(30 * 24);
+
+/// The longest a gift code may be redeemed, in days.
+const MAX_EXPIRY_DAYS: i64 = 365;
+
+/// The most times a gift code may be redeemed.
+const MAX_REDEMPTIONS: i32 = 1000;
+
+#[derive(Deserialize)]
+pub struct GiftCodePathParams {
+    gift_code_id: Uuid,
+}
+
+#[derive(Deserialize)]
+pub struct PostGiftCodePayload {
+    /// The amount paid per redemption.
+    #[serde(with = "bigdecimal::serde::json_num")]
+    amount: BigDecimal,
+    /// Defaults to [`DEFAULT_CURRENCY`].
+    currency: Option<Currency>,
+    /// How many users may redeem the gift code. Defaults to one.
+    max_redemptions: Option<i32>,
+    memo: Option<String>,
+    expires_at: Option<jiff::Timestamp>,
+    /// Whether to fund the gift code from the treasury rather than from the
+    /// authenticated user. Only admins may set this.
+    #[serde(default)]
+    from_treasury: bool,
+}
+
+#[derive(Deserialize)]
+pub struct PostGiftCodeRedemptionPayload {
+    code: SecretString,
+}
+
+#[derive(Serialize)]
+pub struct GetGiftCodesResponse {
+    gift_codes: Vec<GiftCodeResponse>,
+}
+
+#[derive(Serialize)]
+pub struct GiftCodeResponse {
+    id: Uuid,
+    funded_by: Uuid,
+    #[serde(with = "bigdecimal::serde::json_num")]
+    amount: BigDecimal,
+    currency: Currency,
+    max_redemptions: i32,
+    redemption_count: i32,
+    memo: Option<String>,
+    status: GiftCodeStatus,
+    created_at: jiff::Timestamp,
+    expires_at: jiff::Timestamp,
+    funding_transaction_id: Uuid,
+    refund_transaction_id: Option<Uuid>,
+    /// The code to redeem the gift code with, only shown when the gift code is
+    /// created.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    code: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct GiftCodeRedemptionResponse {
+    gift_code_id: Uuid,
+    #[serde(with = "bigdecimal::serde::json_num")]
+    amount: BigDecimal,
+    currency: Currency,
+    memo: Option<String>,
+    transaction_id: Uuid,
+}
+
+impl GiftCodeResponse {
+    fn new(gift_code: GiftCode, now: jiff::Timestamp) -> Self {
+        Self {
+            id: gift_code.id,
+            funded_by: gift_code.funded_by,
+            status: gift_code.status_at(now),
+            amount: gift_code.amount,
+            currency: gift_code.currency,
+            max_redemptions: gift_code.max_redemptions,
+            redemption_count: gift_code.redemption_count,
+            memo: gift_code.memo,
+            created_at: gift_code.created_at,
+            expires_at: gift_code.expires_at,
+            funding_transaction_id: gift_code.funding_transaction_id,
+            refund_transaction_id: gift_code.refund_transaction_id,
+            code: None,
+        }
+    }
+}
+
+/// Creates a gift code, moving the funds for all of its redemptions from the
+/// authenticated user, or from the treasury, into escrow.
+///
+/// The code to redeem the gift code with is only returned here.
+pub async fn post_gift_code(
+    State(pool): State<DbConnectionPool>,
+    State(default_limits): State<TransferLimits>,
+    State(event_hub): State<EventHub>,
+    Extension(authenticated_user): Extension<AuthenticatedUser>,
+    WithRejection(Json(payload), _): WithRejection<Json<PostGiftCodePayload>, JsonRejection>,
+) -> Result<(StatusCode, Json<GiftCodeResponse>)> {
+    let mut conn = pool
+        .get()
+        .await
+        .context("failed to get database connection")
+        .map_err(AppError::from)?;
+
+    let user_id = authenticated_user.subject;
+    if payload.from_treasury && !is_admin(&mut conn, user_id).await.map_err(AppError::from)? {
+        return Err((
+            StatusCode::FORBIDDEN,
+            Json(json!({
+                "title": "PermissionDenied",
+                "detail": "only admins may fund gift codes from the treasury",
+            })),
+        ))?;
+    }
+
+    let currency = payload.currency.unwrap_or(DEFAULT_CURRENCY);
+    if payload.amount <= BigDecimal::zero() {
+        return Err(TransferError::InvalidAmount)?;
+    }
+    if !currency.is_valid_amount(&payload.amount) {
+        return Err(TransferError::AmountTooPrecise(currency))?;
+    }
+
+    let max_redemptions = payload.max_redemptions.unwrap_or(1);
+    if !(1..=MAX_REDEMPTIONS).contains(&max_redemptions) {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(json!({
+                "title": "InvalidMaxRedemptions",
+                "detail": format!("max redemptions must be between 1 and {MAX_REDEMPTIONS}"),
+            })),
+        ))?;
+    }
+
+    let memo = validate_memo(payload.memo.as_deref())?;
+
+    let now = jiff::Timestamp::now();
+    let expires_at = validate_expiry(now, payload.expires_at, DEFAULT_EXPIRY, MAX_EXPIRY_DAYS)?;
+
+    let new_gift_code = NewGiftCode {
+        created_by: user_id,
+        funded_by: if payload.from_treasury {
+            TREASURY_USER_ID
+        } else {
+            user_id
+        },
+        amount: payload.amount,
+        currency,
+        max_redemptions,
+        memo,
+        expires_at,
+    };
+    let code = gift_code::generate_code();
+    let code_hash = gift_code::hash_code(code.expose_secret());
+
+    let (created_gift_code, created_transaction) =
+        gift_code::create(&mut conn, &new_gift_code, &code_hash, &default_limits)
+            .await
+            .map_err(AppError::from)??;
+
+    publish_transactions(&event_hub, &mut conn, slice::from_ref(&created_transaction)).await;
+
+    let mut response = GiftCodeResponse::new(created_gift_code, now);
+    response.code = Some(code.expose_secret().to_owned());
+
+    Ok((StatusCode::CREATED, Json(response)))
+}
+
+/// Lists the gift codes which the authenticated user created, newest first.
+pub async fn get_gift_codes(
+    State(pool): State<DbConnectionPool>,
+    Extension(authenticated_user): Extension<AuthenticatedUser>,
+) -> Result<Json<GetGiftCodesResponse>> {
+    use crate::models::types;
+    use crate::schema::gift_codes;
+
+    let mut conn = pool
+        .get()
+        .await
+        .context("failed to get database connection")
+        .map_err(AppError::from)?;
+
+    let gift_codes: Vec<GiftCode> = gift_codes::table
+        .filter(gift_codes::created_by.eq(types::Uuid::from(authenticated_user.subject)))
+        .select(GiftCode::as_select())
+        .order(gift_codes::id.desc())
+        .load(&mut conn)
+        .await
+        .context("failed to query gift codes")
+        .map_err(AppError::from)?;
+
+    let now = jiff::Timestamp::now();
+    Ok(Json(GetGiftCodesResponse {
+        gift_codes: gift_codes
+            .into_iter()
+            .map(|gift_code| GiftCodeResponse::new(gift_code, now))
+            .collect(),
+    }))
+}
+
+/// Only the creator of a gift code may see it.
+pub async fn get_gift_code(
+    State(pool): State<DbConnectionPool>,
+    Extension(authenticated_user): Extension<AuthenticatedUser>,
+    Path(GiftCodePathParams { gift_code_id }): Path<GiftCodePathParams>,
+) -> Result<Json<GiftCodeResponse>> {
+    use crate::models::types;
+    use crate::schema::gift_codes;
+
+    let mut conn = pool
+        .get()
+        .await
+        .context("failed to get database connection")
+        .map_err(AppError::from)?;
+
+    let Some(gift_code): Option<GiftCode> = gift_codes::table
+        .find(types::Uuid::from(gift_code_id))
+        .filter(gift_codes::created_by.eq(types::Uuid::from(authenticated_user.subject)))
+        .select(GiftCode::as_select())
+        .first(&mut conn)
+        .await
+        .optional()
+        .context("failed to query gift codes")
+        .map_err(AppError::from)?
+    else {
+        debug!(%gift_code_id, "could not find gift code");
+
+        return Err(gift_code_not_found())?;
+    };
+
+    Ok(Json(GiftCodeResponse::new(
+        gift_code,
+        jiff::Timestamp::now(),
+    )))
+}
+
+/// Cancels an active gift code, refunding what is left of its funds to whoever
+/// funded it.
+///
+/// Only the creator of a gift code may cancel it.
+pub async fn post_gift_code_cancel(
+    State(pool): State<DbConnectionPool>,
+    State(event_hub): State<EventHub>,
+    Extension(authenticated_user): Extension<AuthenticatedUser>,
+    Path(GiftCodePathParams { gift_code_id }): Path<GiftCodePathParams>,
+) -> Result<Json<GiftCodeResponse>> {
+    use crate::models::types;
+    use crate::schema::gift_codes;
+
+    let mut conn = pool
+        .get()
+        .await
+        .context("failed to get database connection")
+        .map_err(AppError::from)?;
+
+    let Some(gift_code): Option<GiftCode> = gift_codes::table
+        .find(types::Uuid::from(gift_code_id))
+        .filter(gift_codes::created_by.eq(types::Uuid::from(authenticated_user.subject)))
+        .select(GiftCode::as_select())
+        .first(&mut conn)
+        .await
+        .optional()
+        .context("failed to query gift codes")
+        .map_err(AppError::from)?
+    else {
+        debug!(%gift_code_id, "could not find gift code");
+
+        return Err(gift_code_not_found())?;
+    };
+
+    // Expired gift codes are refunded in the background instead.
+    let now = jiff::Timestamp::now();
+    let status = gift_code.status_at(now);
+    if status != GiftCodeStatus::Active {
+        return Err(gift_code_not_active(status))?;
+    }
+
+    let Some((cancelled_gift_code, refund)) =
+        gift_code::close(&mut conn, gift_code_id, GiftCodeStatus::Cancelled)
+            .await
+            .map_err(AppError::from)?
+    else {
+        // It was fully redeemed in the meantime.
+        return Err(gift_code_not_active(GiftCodeStatus::Redeemed))?;
+    };
+
+    if let Some(refund) = refund {
+        publish_transactions(&event_hub, &mut conn, slice::from_ref(&refund)).await;
+    }
+
+    Ok(Json(GiftCodeResponse::new(cancelled_gift_code, now)))
+}
+
+/// Redeems a gift code for the authenticated user, paying its amount to them.
+///
+/// Each user may redeem a gift code once.
+pub async fn post_gift_code_redemption(
+    State(pool): State<DbConnectionPool>,
+    State(rate_limiter): State<GiftCodeRedemptionRateLimiter>,
+    State(event_hub): State<EventHub>,
+    Extension(authenticated_user): Extension<AuthenticatedUser>,
+    WithRejection(Json(payload), _): WithRejection<
+        Json<PostGiftCodeRedemptionPayload>,
+        JsonRejection,
+    >,
+) -> Result<Json<GiftCodeRedemptionResponse>> {
+    let user_id = authenticated_user.subject;
+
+    // # Security
+    //
+    // Rate limit redemptions, whether or not the code exists, so that codes
+    // can't be guessed by trying many of them.
+    if let Err(retry_after) = rate_limiter.0.check(user_id) {
+        debug!(%user_id, "gift code redemption rate limited");
+
+        return Err((
+            StatusCode::TOO_MANY_REQUESTS,
+            [(
+                header::RETRY_AFTER,
+                retry_after.as_secs().saturating_add(1).to_string(),
+            )],
+            Json(json!({
+                "title": "TooManyRequests",
+            })),
+        ))?;
+    }
+
+    let mut conn = pool
+        .get()
+        .await
+        .context("failed to get database connection")
+        .map_err(AppError::from)?;
+
+    let code_hash = gift_code::hash_code(payload.code.expose_secret());
+    let (redeemed_gift_code, created_transaction) =
+        gift_code::redeem(&mut conn, user_id, &code_hash, jiff::Timestamp::now())
+            .await
+            .map_err(AppError::from)??;
+
+    publish_transactions(&event_hub, &mut conn, slice::from_ref(&created_transaction)).await;
+
+    Ok(Json(GiftCodeRedemptionResponse {
+        gift_code_id: redeemed_gift_code.id,
+        amount: created_transaction.amount,
+        currency: created_transaction.currency,
+        memo: created_transaction.memo,
+        transaction_id: created_transaction.id,
+    }))
+}
+
+fn gift_code_not_found() -> Response {
+    (
+        StatusCode::NOT_FOUND,
+        Json(json!({
+            "title": "GiftCodeNotFound",
+        })),
+    )
+        .into_response()
+}
+
+fn gift_code_not_active(status: GiftCodeStatus) -> Response {
+    (
+        StatusCode::CONFLICT,
+        Json(json!({
+            "title": "GiftCodeNotActive",
+            "detail": format!("gift code is {status}"),
+        })),
+    )
+        .into_response()
+}
@@ -0,0 +1,771 @@
+use anyhow::Context as _;
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use axum::response::{IntoResponse as _, Response, Result};
+use axum::{Extension, Json};
+use axum_extra::extract::WithRejection;
+use bigdecimal::BigDecimal;
+use diesel::prelude::*;
+#[allow(
+    clippy::unused_trait_names,
+    reason = "error[E0034]: multiple applicable items in scope"
+)]
+use diesel_async::RunQueryDsl;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tracing::debug;
+use uuid::Uuid;
+
+use crate::currency::{Currency, DEFAULT_CURRENCY};
+use crate::error::{AppError, JsonRejection};
+use crate::event_hub::{EventHub, publish_transactions};
+use crate::handlers::transaction::{PostTransactionResponse, sanitize_memo};
+use crate::handlers::user::check_username_lookup;
+use crate::ledger;
+use crate::limits::TransferLimits;
+use crate::middleware::auth::AuthenticatedUser;
+use crate::models::group::{GroupExpense, GroupExpenseShare, GroupMember};
+use crate::models::user::{UserRef, UserRole};
+use crate::models::{Group, User};
+use crate::split::{self, Split};
+use crate::state::{DbConnection, DbConnectionPool, UsernameLookupRateLimiter};
+
+#[derive(Deserialize)]
+pub struct GroupPathParams {
+    group_id: Uuid,
+}
+
+#[derive(Deserialize)]
+pub struct PostGroupPayload {
+    name: String,
+    /// The currency of all expenses. Defaults to [`DEFAULT_CURRENCY`].
+    currency: Option<Currency>,
+    /// Members besides the authenticated user, who is always a member.
+    #[serde(default)]
+    members: Vec<UserRef>,
+}
+
+#[derive(Serialize)]
+pub struct GroupResponse {
+    id: Uuid,
+    name: String,
+    currency: Currency,
+    created_by: Uuid,
+    created_at: jiff::Timestamp,
+    /// The members, in the order they joined in.
+    members: Vec<GroupMemberResponse>,
+}
+
+#[derive(Serialize)]
+pub struct GroupMemberResponse {
+    id: Uuid,
+    username: String,
+}
+
+#[derive(Serialize)]
+pub struct GetGroupsResponse {
+    groups: Vec<GroupResponse>,
+}
+
+#[derive(Deserialize)]
+pub struct PostGroupMemberPayload {
+    user: UserRef,
+}
+
+#[derive(Deserialize)]
+pub struct PostGroupExpensePayload {
+    #[serde(with = "bigdecimal::serde::json_num")]
+    amount: BigDecimal,
+    description: String,
+    /// Who paid for the expense, which may only be the authenticated user, so
+    /// that members cannot record expenses on behalf of others.
+    payer: Option<Uuid>,
+    split: SplitPayload,
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum SplitPayload {
+    Equal {
+        /// Defaults to all members.
+        members: Option<Vec<Uuid>>,
+    },
+    Shares {
+        shares: Vec<SharesPayload>,
+    },
+    Exact {
+        amounts: Vec<ExactAmountPayload>,
+    },
+}
+
+#[derive(Deserialize)]
+pub struct SharesPayload {
+    user_id: Uuid,
+    shares: u32,
+}
+
+#[derive(Deserialize)]
+pub struct ExactAmountPayload {
+    user_id: Uuid,
+    #[serde(with = "bigdecimal::serde::json_num")]
+    amount: BigDecimal,
+}
+
+#[derive(Serialize)]
+pub struct GroupExpenseResponse {
+    id: Uuid,
+    group_id: Uuid,
+    payer: Uuid,
+    #[serde(with = "bigdecimal::serde::json_num")]
+    amount: BigDecimal,
+    currency: Currency,
+    description: String,
+    created_at: jiff::Timestamp,
+    shares: Vec<GroupExpenseShareResponse>,
+}
+
+#[derive(Serialize)]
+pub struct GroupExpenseShareResponse {
+    user_id: Uuid,
+    #[serde(with = "bigdecimal::serde::json_num")]
+    amount: BigDecimal,
+}
+
+#[derive(Serialize)]
+pub struct GetGroupExpensesResponse {
+    /// The expenses, newest first.
+    expenses: Vec<GroupExpenseResponse>,
+}
+
+#[derive(Serialize)]
+pub struct GetGroupBalancesResponse {
+    currency: Currency,
+    /// What each member is owed, or owes if negative.
+    balances: Vec<GroupBalanceResponse>,
+    /// Who should pay whom to settle all balances.
+    debts: Vec<DebtResponse>,
+}
+
+#[derive(Serialize)]
+pub struct GroupBalanceResponse {
+    user_id: Uuid,
+    #[serde(with = "bigdecimal::serde::json_num")]
+    balance: BigDecimal,
+}
+
+#[derive(Serialize)]
+pub struct DebtResponse {
+    from: Uuid,
+    to: Uuid,
+    #[serde(with = "bigdecimal::serde::json_num")]
+    amount: BigDecimal,
+}
+
+#[derive(Serialize)]
+pub struct SettleUpResponse {
+    transactions: Vec<PostTransactionResponse>,
+}
+
+/// Maximum length of a group name, in characters, after sanitization.
+const GROUP_NAME_MAX_CHARS: usize = 64;
+
+/// Creates a group of the authenticated user and `members`.
+pub async fn post_group(
+    State(pool): State<DbConnectionPool>,
+    State(rate_limiter): State<UsernameLookupRateLimiter>,
+    Extension(authenticated_user): Extension<AuthenticatedUser>,
+    WithRejection(Json(payload), _): WithRejection<Json<PostGroupPayload>, JsonRejection>,
+) -> Result<(StatusCode, Json<GroupResponse>)> {
+    use crate::schema::{group_members, groups};
+
+    let mut conn = pool
+        .get()
+        .await
+        .context("failed to get database connection")
+        .map_err(AppError::from)?;
+
+    let name = match sanitize_memo(&payload.name) {
+        Ok(Some(name)) if name.chars().count() <= GROUP_NAME_MAX_CHARS => name,
+        _ => {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                Json(json!({
+                    "title": "InvalidName",
+                    "detail": format!(
+                        "name must be between 1 and {GROUP_NAME_MAX_CHARS} characters"
+                    ),
+                })),
+            ))?;
+        },
+    };
+
+    let mut member_ids = vec![authenticated_user.subject];
+    for member in &payload.members {
+        let user = match find_user(&mut conn, &rate_limiter, authenticated_user.subject, member)
+            .await
+            .map_err(AppError::from)?
+        {
+            Ok(user) => user,
+            Err(response) => return Err(response)?,
+        };
+        if !member_ids.contains(&user.id) {
+            member_ids.push(user.id);
+        }
+    }
+
+    let currency = payload.currency.unwrap_or(DEFAULT_CURRENCY);
+
+    let group = ledger::run_immediate_transaction(&mut conn, |conn| {
+        let (name, member_ids) = (name.clone(), member_ids.clone());
+        Box::pin(async move {
+            let group: Group = diesel::insert_into(groups::table)
+                .values(Group {
+                    id: Uuid::now_v7(),
+                    name,
+                    currency,
+                    created_by: authenticated_user.subject,
+                    created_at: jiff::Timestamp::now(),
+                })
+                .returning(Group::as_returning())
+                .get_result(conn)
+                .await
+                .context("failed to insert group")?;
+            for user_id in member_ids {
+                diesel::insert_into(group_members::table)
+                    .values(GroupMember {
+                        group_id: group.id,
+                        user_id,
+                        joined_at: group.created_at,
+                    })
+                    .execute(conn)
+                    .await
+                    .context("failed to insert group member")?;
+            }
+
+            Ok(group)
+        })
+    })
+    .await
+    .map_err(AppError::from)?;
+
+    let response = group_response(&mut conn, group)
+        .await
+        .map_err(AppError::from)?;
+
+    Ok((StatusCode::CREATED, Json(response)))
+}
+
+/// Returns the groups the authenticated user is a member of, newest first.
+pub async fn get_groups(
+    State(pool): State<DbConnectionPool>,
+    Extension(authenticated_user): Extension<AuthenticatedUser>,
+) -> Result<Json<GetGroupsResponse>> {
+    use crate::models::types;
+    use crate::schema::{group_members, groups};
+
+    let mut conn = pool
+        .get()
+        .await
+        .context("failed to get database connection")
+        .map_err(AppError::from)?;
+
+    let user_groups: Vec<Group> = groups::table
+        .inner_join(group_members::table)
+        .filter(group_members::user_id.eq(types::Uuid::from(authenticated_user.subject)))
+        .order((groups::created_at.desc(), groups::id.desc()))
+        .select(Group::as_select())
+        .load(&mut conn)
+        .await
+        .context("failed to query groups")
+        .map_err(AppError::from)?;
+
+    let mut groups = Vec::with_capacity(user_groups.len());
+    for group in user_groups {
+        groups.push(
+            group_response(&mut conn, group)
+                .await
+                .map_err(AppError::from)?,
+        );
+    }
+
+    Ok(Json(GetGroupsResponse { groups }))
+}
+
+/// Returns a group of the authenticated user, with its members.
+pub async fn get_group(
+    State(pool): State<DbConnectionPool>,
+    Extension(authenticated_user): Extension<AuthenticatedUser>,
+    Path(GroupPathParams { group_id }): Path<GroupPathParams>,
+) -> Result<Json<GroupResponse>> {
+    let mut conn = pool
+        .get()
+        .await
+        .context("failed to get database connection")
+        .map_err(AppError::from)?;
+
+    let group = match find_group(&mut conn, group_id, authenticated_user.subject)
+        .await
+        .map_err(AppError::from)?
+    {
+        Ok(group) => group,
+        Err(response) => return Err(response)?,
+    };
+
+    let response = group_response(&mut conn, group)
+        .await
+        .map_err(AppError::from)?;
+
+    Ok(Json(response))
+}
+
+/// Adds a user to a group. Any member may add others.
+pub async fn post_group_member(
+    State(pool): State<DbConnectionPool>,
+    State(rate_limiter): State<UsernameLookupRateLimiter>,
+    Extension(authenticated_user): Extension<AuthenticatedUser>,
+    Path(GroupPathParams { group_id }): Path<GroupPathParams>,
+    WithRejection(Json(payload), _): WithRejection<Json<PostGroupMemberPayload>, JsonRejection>,
+) -> Result<(StatusCode, Json<GroupResponse>)> {
+    use crate::schema::group_members;
+
+    let mut conn = pool
+        .get()
+        .await
+        .context("failed to get database connection")
+        .map_err(AppError::from)?;
+
+    let group = match find_group(&mut conn, group_id, authenticated_user.subject)
+        .await
+        .map_err(AppError::from)?
+    {
+        Ok(group) => group,
+        Err(response) => return Err(response)?,
+    };
+
+    let user = match find_user(
+        &mut conn,
+        &rate_limiter,
+        authenticated_user.subject,
+        &payload.user,
+    )
+    .await
+    .map_err(AppError::from)?
+    {
+        Ok(user) => user,
+        Err(response) => return Err(response)?,
+    };
+
+    let inserted = diesel::insert_into(group_members::table)
+        .values(GroupMember {
+            group_id: group.id,
+            user_id: user.id,
+            joined_at: jiff::Timestamp::now(),
+        })
+        .on_conflict_do_nothing()
+        .execute(&mut conn)
+        .await
+        .context("failed to insert group member")
+        .map_err(AppError::from)?;
+    if inserted == 0 {
+        return Err((
+            StatusCode::CONFLICT,
+            Json(json!({
+                "title": "AlreadyMember",
+            })),
+        ))?;
+    }
+
+    let response = group_response(&mut conn, group)
+        .await
+        .map_err(AppError::from)?;
+
+    Ok((StatusCode::CREATED, Json(response)))
+}
+
+/// Records an expense which the authenticated user paid for, split between
+/// members.
+pub async fn post_group_expense(
+    State(pool): State<DbConnectionPool>,
+    Extension(authenticated_user): Extension<AuthenticatedUser>,
+    Path(GroupPathParams { group_id }): Path<GroupPathParams>,
+    WithRejection(Json(payload), _): WithRejection<Json<PostGroupExpensePayload>, JsonRejection>,
+) -> Result<(StatusCode, Json<GroupExpenseResponse>)> {
+    use crate::schema::{group_expense_shares, group_expenses};
+
+    let mut conn = pool
+        .get()
+        .await
+        .context("failed to get database connection")
+        .map_err(AppError::from)?;
+
+    let description = match sanitize_memo(&payload.description) {
+        Ok(Some(description)) => description,
+        Ok(None) => {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                Json(json!({
+                    "title": "InvalidDescription",
+                    "detail": "description must not be empty",
+                })),
+            ))?;
+        },
+        Err(detail) => {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                Json(json!({
+                    "title": "InvalidDescription",
+                    "detail": detail.replacen("memo", "description", 1),
+                })),
+            ))?;
+        },
+    };
+
+    let split = match payload.split {
+        SplitPayload::Equal { members } => Split::Equal(members.unwrap_or_default()),
+        SplitPayload::Shares { shares } => Split::Shares(
+            shares
+                .into_iter()
+                .map(|share| (share.user_id, share.shares))
+                .collect(),
+        ),
+        SplitPayload::Exact { amounts } => Split::Exact(
+            amounts
+                .into_iter()
+                .map(|amount| (amount.user_id, amount.amount))
+                .collect(),
+        ),
+    };
+    let payer = authenticated_user.subject;
+    if payload.payer.is_some_and(|user_id| user_id != payer) {
+        return Err((
+            StatusCode::FORBIDDEN,
+            Json(json!({
+                "title": "PermissionDenied",
+                "detail": "expenses may only be recorded by whoever paid for them",
+            })),
+        ))?;
+    }
+
+    let expense = ledger::run_immediate_transaction(&mut conn, |conn| {
+        let (amount, description, split) =
+            (payload.amount.clone(), description.clone(), split.clone());
+        Box::pin(async move {
+            let group = match find_group(conn, group_id, authenticated_user.subject).await? {
+                Ok(group) => group,
+                Err(response) => return Ok(Err(response)),
+            };
+
+            let members = split::members(conn, group.id).await?;
+            // An equal split without members is between all members.
+            let split = match split {
+                Split::Equal(user_ids) if user_ids.is_empty() => Split::Equal(members.clone()),
+                split => split,
+            };
+            let shares = match split::split(&amount, group.currency, &split, &members) {
+                Ok(shares) => shares,
+                Err(err) => return Ok(Err(err.into_response())),
+            };
+
+            let expense: GroupExpense = diesel::insert_into(group_expenses::table)
+                .values(GroupExpense {
+                    id: Uuid::now_v7(),
+                    group_id: group.id,
+                    payer,
+                    amount: amount.normalized(),
+                    description,
+                    created_at: jiff::Timestamp::now(),
+                })
+                .returning(GroupExpense::as_returning())
+                .get_result(conn)
+                .await
+                .context("failed to insert group expense")?;
+
+            let mut expense_shares = Vec::with_capacity(shares.len());
+            for (user_id, amount) in shares {
+                let share: GroupExpenseShare = diesel::insert_into(group_expense_shares::table)
+                    .values(GroupExpenseShare {
+                        expense_id: expense.id,
+                        user_id,
+                        amount,
+                    })
+                    .returning(GroupExpenseShare::as_returning())
+                    .get_result(conn)
+                    .await
+                    .context("failed to insert group expense share")?;
+                expense_shares.push(share);
+            }
+
+            Ok(Ok(GroupExpenseResponse::new(
+                expense,
+                group.currency,
+                expense_shares,
+            )))
+        })
+    })
+    .await
+    .map_err(AppError::from)??;
+
+    Ok((StatusCode::CREATED, Json(expense)))
+}
+
+/// Returns the expenses of a group of the authenticated user, newest first.
+pub async fn get_group_expenses(
+    State(pool): State<DbConnectionPool>,
+    Extension(authenticated_user): Extension<AuthenticatedUser>,
+    Path(GroupPathParams { group_id }): Path<GroupPathParams>,
+) -> Result<Json<GetGroupExpensesResponse>> {
+    use crate::models::types;
+    use crate::schema::{group_expense_shares, group_expenses};
+
+    let mut conn = pool
+        .get()
+        .await
+        .context("failed to get database connection")
+        .map_err(AppError::from)?;
+
+    let group = match find_group(&mut conn, group_id, authenticated_user.subject)
+        .await
+        .map_err(AppError::from)?
+    {
+        Ok(group) => group,
+        Err(response) => return Err(response)?,
+    };
+
+    let expenses: Vec<GroupExpense> = group_expenses::table
+        .filter(group_expenses::group_id.eq(types::Uuid::from(group.id)))
+        .order((group_expenses::created_at.desc(), group_expenses::id.desc()))
+        .select(GroupExpense::as_select())
+        .load(&mut conn)
+        .await
+        .context("failed to query group expenses")
+        .map_err(AppError::from)?;
+    let mut shares: Vec<GroupExpenseShare> = group_expense_shares::table
+        .inner_join(group_expenses::table)
+        .filter(group_expenses::group_id.eq(types::Uuid::from(group.id)))
+        .select(GroupExpenseShare::as_select())
+        .load(&mut conn)
+        .await
+        .context("failed to query group expense shares")
+        .map_err(AppError::from)?;
+
+    let expenses = expenses
+        .into_iter()
+        .map(|expense| {
+            let (expense_shares, rest) = shares
+                .drain(..)
+                .partition(|share| share.expense_id == expense.id);
+            shares = rest;
+            GroupExpenseResponse::new(expense, group.currency, expense_shares)
+        })
+        .collect();
+
+    Ok(Json(GetGroupExpensesResponse { expenses }))
+}
+
+/// Returns who owes whom in a group of the authenticated user.
+pub async fn get_group_balances(
+    State(pool): State<DbConnectionPool>,
+    Extension(authenticated_user): Extension<AuthenticatedUser>,
+    Path(GroupPathParams { group_id }): Path<GroupPathParams>,
+) -> Result<Json<GetGroupBalancesResponse>> {
+    let mut conn = pool
+        .get()
+        .await
+        .context("failed to get database connection")
+        .map_err(AppError::from)?;
+
+    let group = match find_group(&mut conn, group_id, authenticated_user.subject)
+        .await
+        .map_err(AppError::from)?
+    {
+        Ok(group) => group,
+        Err(response) => return Err(response)?,
+    };
+
+    let balances = split::group_balances(&mut conn, group.id)
+        .await
+        .map_err(AppError::from)?;
+    let debts = split::simplify(&balances)
+        .into_iter()
+        .map(|debt| DebtResponse {
+            from: debt.from,
+            to: debt.to,
+            amount: debt.amount,
+        })
+        .collect();
+
+    Ok(Json(GetGroupBalancesResponse {
+        currency: group.currency,
+        balances: balances
+            .into_iter()
+            .map(|(user_id, balance)| GroupBalanceResponse { user_id, balance })
+            .collect(),
+        debts,
+    }))
+}
+
+/// Pays what the authenticated user owes other members of a group, with one
+/// transfer per member, either all of them or none.
+pub async fn post_group_settle_up(
+    State(pool): State<DbConnectionPool>,
+    State(default_limits): State<TransferLimits>,
+    State(event_hub): State<EventHub>,
+    Extension(authenticated_user): Extension<AuthenticatedUser>,
+    Path(GroupPathParams { group_id }): Path<GroupPathParams>,
+) -> Result<(StatusCode, Json<SettleUpResponse>)> {
+    let mut conn = pool
+        .get()
+        .await
+        .context("failed to get database connection")
+        .map_err(AppError::from)?;
+
+    let group = match find_group(&mut conn, group_id, authenticated_user.subject)
+        .await
+        .map_err(AppError::from)?
+    {
+        Ok(group) => group,
+        Err(response) => return Err(response)?,
+    };
+
+    let created_transactions = split::settle_up(
+        &mut conn,
+        &group,
+        authenticated_user.subject,
+        &default_limits,
+    )
+    .await
+    .map_err(AppError::from)??;
+    publish_transactions(&event_hub, &mut conn, &created_transactions).await;
+
+    Ok((
+        StatusCode::CREATED,
+        Json(SettleUpResponse {
+            transactions: created_transactions.into_iter().map(Into::into).collect(),
+        }),
+    ))
+}
+
+impl GroupExpenseResponse {
+    fn new(expense: GroupExpense, currency: Currency, shares: Vec<GroupExpenseShare>) -> Self {
+        Self {
+            id: expense.id,
+            group_id: expense.group_id,
+            payer: expense.payer,
+            amount: expense.amount,
+            currency,
+            description: expense.description,
+            created_at: expense.created_at,
+            shares: shares
+                .into_iter()
+                .map(|share| GroupExpenseShareResponse {
+                    user_id: share.user_id,
+                    amount: share.amount,
+                })
+                .collect(),
+        }
+    }
+}
+
+/// Returns `group_id` if `user_id` is a member of it.
+///
+/// Groups of others are reported as not found, so that their IDs can't be
+/// probed.
+async fn find_group(
+    conn: &mut DbConnection,
+    group_id: Uuid,
+    user_id: Uuid,
+) -> anyhow::Result<Result<Group, Response>> {
+    use crate::models::types;
+    use crate::schema::{group_members, groups};
+
+    let group: Option<Group> = groups::table
+        .inner_join(group_members::table)
+        .filter(groups::id.eq(types::Uuid::from(group_id)))
+        .filter(group_members::user_id.eq(types::Uuid::from(user_id)))
+        .select(Group::as_select())
+        .first(conn)
+        .await
+        .optional()
+        .context("failed to query groups")?;
+    let Some(group) = group else {
+        debug!(%group_id, "could not find group");
+
+        return Ok(Err((
+            StatusCode::NOT_FOUND,
+            Json(json!({
+                "title": "GroupNotFound",
+            })),
+        )
+            .into_response()));
+    };
+
+    Ok(Ok(group))
+}
+
+/// Returns the user `user_ref` refers to, who may become a member of a group.
+///
+/// Usernames resolved on behalf of `user_id` count towards their rate limit of
+/// username lookups.
+async fn find_user(
+    conn: &mut DbConnection,
+    rate_limiter: &UsernameLookupRateLimiter,
+    user_id: Uuid,
+    user_ref: &UserRef,
+) -> anyhow::Result<Result<User, Response>> {
+    if let UserRef::Username(_) = user_ref
+        && let Err(response) = check_username_lookup(rate_limiter, user_id)
+    {
+        return Ok(Err(response));
+    }
+
+    let user: Option<User> = user_ref
+        .query()
+        .select(User::as_select())
+        .first(conn)
+        .await
+        .optional()
+        .context("failed to query users")?;
+    match user {
+        Some(user) if user.role != UserRole::System => Ok(Ok(user)),
+        _ => {
+            debug!(?user_ref, "could not find user");
+
+            Ok(Err((
+                StatusCode::NOT_FOUND,
+                Json(json!({
+                    "title": "UserNotFound",
+                })),
+            )
+                .into_response()))
+        },
+    }
+}
+
+async fn group_response(conn: &mut DbConnection, group: Group) -> anyhow::Result<GroupResponse> {
+    use crate::models::types;
+    use crate::schema::{group_members, users};
+
+    let members: Vec<(types::Uuid, String)> = group_members::table
+        .inner_join(users::table)
+        .filter(group_members::group_id.eq(types::Uuid::from(group.id)))
+        .order((group_members::joined_at.asc(), group_members::user_id.asc()))
+        .select((users::id, users::username))
+        .load(conn)
+        .await
+        .context("failed to query group members")?;
+
+    Ok(GroupResponse {
+        id: group.id,
+        name: group.name,
+        currency: group.currency,
+        created_by: group.created_by,
+        created_at: group.created_at,
+        members: members
+            .into_iter()
+            .map(|(id, username)| GroupMemberResponse {
+                id: id.into(),
+                username,
+            })
+            .collect(),
+    })
+}
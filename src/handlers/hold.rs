@@ -0,0 +1,497 @@
+use std::slice;
+
+use anyhow::Context as _;
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use axum::response::{IntoResponse as _, Response, Result};
+use axum::{Extension, Json};
+use axum_extra::extract::WithRejection;
+use bigdecimal::{BigDecimal, Zero as _};
+use diesel::prelude::*;
+#[allow(
+    clippy::unused_trait_names,
+    reason = "error[E0034]: multiple applicable items in scope"
+)]
+use diesel_async::RunQueryDsl;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tracing::debug;
+use uuid::Uuid;
+
+use crate::currency::{Currency, DEFAULT_CURRENCY};
+use crate::error::{AppError, JsonRejection, permission_denied};
+use crate::event_hub::{EventHub, publish_transactions};
+use crate::handlers::payment_request::validate_expiry;
+use crate::handlers::transaction::{validate_memo, validate_reference};
+use crate::ledger::{self, NewTransfer, TransferError};
+use crate::limits::TransferLimits;
+use crate::middleware::auth::AuthenticatedUser;
+use crate::models::hold::{HoldStatus, NewHold};
+use crate::models::user::UserRef;
+use crate::models::{Hold, User};
+use crate::state::{DbConnection, DbConnectionPool};
+
+/// How long a hold reserves funds, unless specified otherwise.
+const DEFAULT_EXPIRY: jiff::SignedDuration = jiff::SignedDuration::from_hours(7 * 24);
+
+/// The longest a hold may reserve funds, in days.
+const MAX_EXPIRY_DAYS: i64 = 30;
+
+#[derive(Deserialize)]
+pub struct HoldPathParams {
+    hold_id: Uuid,
+}
+
+#[derive(Deserialize)]
+pub struct PostHoldPayload {
+    #[serde(with = "bigdecimal::serde::json_num")]
+    amount: BigDecimal,
+    /// Defaults to [`DEFAULT_CURRENCY`].
+    currency: Option<Currency>,
+    /// The user the funds are reserved for, who may capture or void them.
+    recipient: UserRef,
+    memo: Option<String>,
+    reference: Option<String>,
+    expires_at: Option<jiff::Timestamp>,
+}
+
+#[derive(Deserialize)]
+pub struct PostHoldCapturePayload {
+    /// How much of the hold to capture. If omitted, the full amount is
+    /// captured. The rest is released to the sender.
+    #[serde(default, with = "bigdecimal::serde::json_num_option")]
+    amount: Option<BigDecimal>,
+}
+
+#[derive(Serialize)]
+pub struct GetHoldsResponse {
+    holds: Vec<HoldResponse>,
+}
+
+#[derive(Serialize)]
+pub struct HoldResponse {
+    id: Uuid,
+    sender: Uuid,
+    recipient: Uuid,
+    #[serde(with = "bigdecimal::serde::json_num")]
+    amount: BigDecimal,
+    currency: Currency,
+    memo: Option<String>,
+    reference: Option<String>,
+    status: HoldStatus,
+    created_at: jiff::Timestamp,
+    expires_at: jiff::Timestamp,
+    transaction_id: Option<Uuid>,
+}
+
+impl HoldResponse {
+    fn new(hold: Hold, now: jiff::Timestamp) -> Self {
+        Self {
+            id: hold.id,
+            sender: hold.sender,
+            recipient: hold.recipient,
+            status: hold.status_at(now),
+            amount: hold.amount,
+            currency: hold.currency,
+            memo: hold.memo,
+            reference: hold.reference,
+            created_at: hold.created_at,
+            expires_at: hold.expires_at,
+            transaction_id: hold.transaction_id,
+        }
+    }
+}
+
+/// Reserves funds of the authenticated user for the recipient, without moving
+/// them yet.
+///
+/// The reserved funds no longer count towards the authenticated user's
+/// available balance, until the hold is captured, voided or expires.
+pub async fn post_hold(
+    State(pool): State<DbConnectionPool>,
+    Extension(authenticated_user): Extension<AuthenticatedUser>,
+    WithRejection(Json(payload), _): WithRejection<Json<PostHoldPayload>, JsonRejection>,
+) -> Result<(StatusCode, Json<HoldResponse>)> {
+    use crate::schema::holds;
+
+    let mut conn = pool
+        .get()
+        .await
+        .context("failed to get database connection")
+        .map_err(AppError::from)?;
+
+    let currency = payload.currency.unwrap_or(DEFAULT_CURRENCY);
+    if payload.amount <= BigDecimal::zero() {
+        return Err(TransferError::InvalidAmount)?;
+    }
+    if !currency.is_valid_amount(&payload.amount) {
+        return Err(TransferError::AmountTooPrecise(currency))?;
+    }
+
+    let memo = validate_memo(payload.memo.as_deref())?;
+
+    if let Some(Err(detail)) = payload.reference.as_deref().map(validate_reference) {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(json!({
+                "title": "InvalidReference",
+                "detail": detail,
+            })),
+        ))?;
+    }
+
+    let now = jiff::Timestamp::now();
+    let expires_at = validate_expiry(now, payload.expires_at, DEFAULT_EXPIRY, MAX_EXPIRY_DAYS)?;
+
+    let recipient: User = match payload
+        .recipient
+        .query()
+        .select(User::as_select())
+        .first(&mut conn)
+        .await
+    {
+        Ok(recipient) => recipient,
+        Err(diesel::NotFound) => {
+            debug!(?payload.recipient, "could not find recipient");
+
+            return Err(TransferError::InvalidRecipient)?;
+        },
+        Err(err) => {
+            return Err(err)
+                .context("failed to query users")
+                .map_err(AppError::from)?;
+        },
+    };
+    if recipient.id == authenticated_user.subject {
+        return Err(TransferError::SelfTransfer)?;
+    }
+
+    let new_hold = NewHold {
+        id: Uuid::now_v7(),
+        sender: authenticated_user.subject,
+        recipient: recipient.id,
+        amount: payload.amount,
+        currency,
+        memo,
+        reference: payload.reference,
+        status: HoldStatus::Active,
+        created_at: now,
+        expires_at,
+    };
+    let new_hold = &new_hold;
+
+    // Hold the write lock while comparing the available balance, like a
+    // transfer does, so that the same funds cannot be reserved twice.
+    let created_hold = ledger::run_immediate_transaction(&mut conn, |conn| {
+        Box::pin(async move {
+            // The recipient needs an account in the currency too, to be able
+            // to capture the hold.
+            let (Some(sender), Some(_recipient)) = (
+                ledger::find_account(conn, new_hold.sender, new_hold.currency).await?,
+                ledger::find_account(conn, new_hold.recipient, new_hold.currency).await?,
+            ) else {
+                return Ok(Err(TransferError::CurrencyMismatch(new_hold.currency)));
+            };
+            let available_balance = &sender.balance
+                - ledger::held_amount(conn, new_hold.sender, new_hold.currency, now).await?;
+            if available_balance < new_hold.amount {
+                return Ok(Err(TransferError::InsufficientBalance));
+            }
+
+            let created_hold: Hold = diesel::insert_into(holds::table)
+                .values(new_hold.clone())
+                .returning(Hold::as_returning())
+                .get_result(conn)
+                .await
+                .context("failed to insert hold")?;
+
+            Ok(Ok(created_hold))
+        })
+    })
+    .await
+    .map_err(AppError::from)??;
+
+    Ok((
+        StatusCode::CREATED,
+        Json(HoldResponse::new(created_hold, now)),
+    ))
+}
+
+/// Lists holds which the authenticated user placed or may capture.
+pub async fn get_holds(
+    State(pool): State<DbConnectionPool>,
+    Extension(authenticated_user): Extension<AuthenticatedUser>,
+) -> Result<Json<GetHoldsResponse>> {
+    use crate::models::types;
+    use crate::schema::holds;
+
+    let mut conn = pool
+        .get()
+        .await
+        .context("failed to get database connection")
+        .map_err(AppError::from)?;
+
+    let holds: Vec<Hold> = holds::table
+        .filter(
+            holds::sender
+                .eq(types::Uuid::from(authenticated_user.subject))
+                .or(holds::recipient.eq(types::Uuid::from(authenticated_user.subject))),
+        )
+        .select(Hold::as_select())
+        .order(holds::id.desc())
+        .load(&mut conn)
+        .await
+        .context("failed to query holds")
+        .map_err(AppError::from)?;
+
+    let now = jiff::Timestamp::now();
+    Ok(Json(GetHoldsResponse {
+        holds: holds
+            .into_iter()
+            .map(|hold| HoldResponse::new(hold, now))
+            .collect(),
+    }))
+}
+
+pub async fn get_hold(
+    State(pool): State<DbConnectionPool>,
+    Extension(authenticated_user): Extension<AuthenticatedUser>,
+    Path(HoldPathParams { hold_id }): Path<HoldPathParams>,
+) -> Result<Json<HoldResponse>> {
+    let mut conn = pool
+        .get()
+        .await
+        .context("failed to get database connection")
+        .map_err(AppError::from)?;
+
+    let Some(hold) = find_visible_hold(&mut conn, hold_id, authenticated_user.subject)
+        .await
+        .map_err(AppError::from)?
+    else {
+        debug!(%hold_id, "could not find hold");
+
+        return Err(hold_not_found())?;
+    };
+
+    Ok(Json(HoldResponse::new(hold, jiff::Timestamp::now())))
+}
+
+/// Captures a hold, by transferring the full or a partial amount from the sender
+/// to the recipient. Any amount not captured is released.
+///
+/// Only the recipient may capture a hold.
+pub async fn post_hold_capture(
+    State(pool): State<DbConnectionPool>,
+    State(default_limits): State<TransferLimits>,
+    State(event_hub): State<EventHub>,
+    Extension(authenticated_user): Extension<AuthenticatedUser>,
+    Path(HoldPathParams { hold_id }): Path<HoldPathParams>,
+    WithRejection(Json(payload), _): WithRejection<Json<PostHoldCapturePayload>, JsonRejection>,
+) -> Result<Json<HoldResponse>> {
+    use crate::models::types;
+    use crate::schema::holds;
+
+    let mut conn = pool
+        .get()
+        .await
+        .context("failed to get database connection")
+        .map_err(AppError::from)?;
+
+    let user_id = authenticated_user.subject;
+    let capture_amount = payload.amount.as_ref();
+    let default_limits = &default_limits;
+    let (hold, created_transaction) = ledger::run_immediate_transaction(&mut conn, |conn| {
+        Box::pin(async move {
+            let mut hold = match find_active_hold(conn, hold_id, user_id).await? {
+                Ok(hold) => hold,
+                Err(response) => return Ok(Err(response)),
+            };
+
+            if hold.recipient != user_id {
+                return Ok(Err(permission_denied()));
+            }
+
+            let amount = capture_amount.unwrap_or(&hold.amount).clone();
+            if amount <= BigDecimal::zero() || amount > hold.amount {
+                return Ok(Err((
+                    StatusCode::BAD_REQUEST,
+                    Json(json!({
+                        "title": "InvalidAmount",
+                        "detail": "capture amount must be positive and at most the held amount",
+                    })),
+                )
+                    .into_response()));
+            }
+
+            // Release the hold before transferring, so that the funds it
+            // reserved count towards the sender's available balance again.
+            hold.status = HoldStatus::Captured;
+            diesel::update(holds::table.find(types::Uuid::from(hold.id)))
+                .set(holds::status.eq(HoldStatus::Captured))
+                .execute(conn)
+                .await
+                .context("failed to update hold")?;
+
+            let new_transfer = NewTransfer {
+                amount,
+                currency: hold.currency,
+                recipient: hold.recipient,
+                sender: hold.sender,
+                memo: hold.memo.clone(),
+                reference: hold.reference.clone(),
+                quote_id: None,
+            };
+            let created_transaction =
+                match ledger::apply_transfer(conn, &new_transfer, default_limits).await? {
+                    Ok(created_transaction) => created_transaction,
+                    Err(err) => {
+                        debug!(%hold.id, ?err, "could not capture hold");
+
+                        diesel::update(holds::table.find(types::Uuid::from(hold.id)))
+                            .set(holds::status.eq(HoldStatus::Active))
+                            .execute(conn)
+                            .await
+                            .context("failed to update hold")?;
+
+                        return Ok(Err(err.into_response()));
+                    },
+                };
+
+            hold.transaction_id = Some(created_transaction.id);
+
+            let hold: Hold = diesel::update(holds::table.find(types::Uuid::from(hold.id)))
+                .set(hold)
+                .returning(Hold::as_returning())
+                .get_result(conn)
+                .await
+                .context("failed to update hold")?;
+
+            Ok(Ok((hold, created_transaction)))
+        })
+    })
+    .await
+    .map_err(AppError::from)??;
+    publish_transactions(&event_hub, &mut conn, slice::from_ref(&created_transaction)).await;
+
+    Ok(Json(HoldResponse::new(hold, jiff::Timestamp::now())))
+}
+
+/// Voids a hold, releasing the funds it reserved to the sender.
+///
+/// Only the recipient may void a hold. The sender has to wait for it to expire.
+pub async fn post_hold_void(
+    State(pool): State<DbConnectionPool>,
+    Extension(authenticated_user): Extension<AuthenticatedUser>,
+    Path(HoldPathParams { hold_id }): Path<HoldPathParams>,
+) -> Result<Json<HoldResponse>> {
+    use crate::models::types;
+    use crate::schema::holds;
+
+    let mut conn = pool
+        .get()
+        .await
+        .context("failed to get database connection")
+        .map_err(AppError::from)?;
+
+    let user_id = authenticated_user.subject;
+    let hold = ledger::run_immediate_transaction(&mut conn, |conn| {
+        Box::pin(async move {
+            let hold = match find_active_hold(conn, hold_id, user_id).await? {
+                Ok(hold) => hold,
+                Err(response) => return Ok(Err(response)),
+            };
+
+            if hold.recipient != user_id {
+                return Ok(Err(permission_denied()));
+            }
+
+            let hold: Hold = diesel::update(holds::table.find(types::Uuid::from(hold.id)))
+                .set(holds::status.eq(HoldStatus::Voided))
+                .returning(Hold::as_returning())
+                .get_result(conn)
+                .await
+                .context("failed to update hold")?;
+
+            Ok(Ok(hold))
+        })
+    })
+    .await
+    .map_err(AppError::from)??;
+
+    Ok(Json(HoldResponse::new(hold, jiff::Timestamp::now())))
+}
+
+/// Finds a hold which `user_id` placed or may capture.
+async fn find_visible_hold(
+    conn: &mut DbConnection,
+    hold_id: Uuid,
+    user_id: Uuid,
+) -> anyhow::Result<Option<Hold>> {
+    use crate::models::types;
+    use crate::schema::holds;
+
+    let hold: Option<Hold> = holds::table
+        .find(types::Uuid::from(hold_id))
+        .select(Hold::as_select())
+        .first(conn)
+        .await
+        .optional()
+        .context("failed to query holds")?;
+
+    Ok(hold.filter(|hold| hold.sender == user_id || hold.recipient == user_id))
+}
+
+/// Finds a hold which `user_id` may see and which is still active.
+///
+/// Marks the hold as expired if it has expired.
+async fn find_active_hold(
+    conn: &mut DbConnection,
+    hold_id: Uuid,
+    user_id: Uuid,
+) -> anyhow::Result<Result<Hold, Response>> {
+    use crate::models::types;
+    use crate::schema::holds;
+
+    let Some(hold) = find_visible_hold(conn, hold_id, user_id).await? else {
+        debug!(%hold_id, "could not find hold");
+
+        return Ok(Err(hold_not_found()));
+    };
+
+    match hold.status_at(jiff::Timestamp::now()) {
+        HoldStatus::Active => Ok(Ok(hold)),
+        HoldStatus::Expired => {
+            if hold.status != HoldStatus::Expired {
+                diesel::update(holds::table.find(types::Uuid::from(hold.id)))
+                    .set(holds::status.eq(HoldStatus::Expired))
+                    .execute(conn)
+                    .await
+                    .context("failed to update hold")?;
+            }
+
+            Ok(Err(hold_not_active(HoldStatus::Expired)))
+        },
+        status => Ok(Err(hold_not_active(status))),
+    }
+}
+
+fn hold_not_found() -> Response {
+    (
+        StatusCode::NOT_FOUND,
+        Json(json!({
+            "title": "HoldNotFound",
+        })),
+    )
+        .into_response()
+}
+
+fn hold_not_active(status: HoldStatus) -> Response {
+    (
+        StatusCode::CONFLICT,
+        Json(json!({
+            "title": "HoldNotActive",
+            "detail": format!("hold is {status}"),
+        })),
+    )
+        .into_response()
+}
@@ -0,0 +1,301 @@
+use std::collections::BTreeMap;
+
+use anyhow::Context as _;
+use axum::extract::{Path, Query, State};
+use axum::http::StatusCode;
+use axum::response::Result;
+use axum::{Extension, Json};
+use axum_extra::extract::WithRejection;
+use bigdecimal::BigDecimal;
+use diesel::prelude::*;
+#[allow(
+    clippy::unused_trait_names,
+    reason = "error[E0034]: multiple applicable items in scope"
+)]
+use diesel_async::RunQueryDsl;
+use jiff::tz::TimeZone;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use uuid::Uuid;
+
+use crate::currency::Currency;
+use crate::error::{AppError, JsonRejection};
+use crate::handlers::user::is_admin;
+use crate::interest;
+use crate::middleware::auth::AuthenticatedUser;
+use crate::models::interest_rate::InterestProduct;
+use crate::models::{InterestAccrual, InterestRate};
+use crate::state::{DbConnection, DbConnectionPool};
+
+#[derive(Deserialize)]
+pub struct InterestRatePathParams {
+    product: InterestProduct,
+    currency: Currency,
+}
+
+#[derive(Deserialize)]
+pub struct PutInterestRatePayload {
+    /// In percent per year.
+    #[serde(with = "bigdecimal::serde::json_num")]
+    annual_rate: BigDecimal,
+}
+
+#[derive(Deserialize)]
+pub struct GetInterestAccrualPreviewQueryParams {
+    /// Defaults to today, in UTC.
+    date: Option<jiff::civil::Date>,
+}
+
+#[derive(Serialize)]
+pub struct GetInterestRatesResponse {
+    interest_rates: Vec<InterestRateResponse>,
+}
+
+#[derive(Serialize)]
+pub struct InterestRateResponse {
+    product: InterestProduct,
+    currency: Currency,
+    #[serde(with = "bigdecimal::serde::json_num")]
+    annual_rate: BigDecimal,
+    updated_at: jiff::Timestamp,
+}
+
+#[derive(Serialize)]
+pub struct GetInterestAccrualPreviewResponse {
+    date: jiff::civil::Date,
+    accruals: Vec<InterestAccrualResponse>,
+    /// The sum of the accruals per currency.
+    totals: Vec<InterestTotalResponse>,
+}
+
+#[derive(Serialize)]
+pub struct InterestAccrualResponse {
+    product: InterestProduct,
+    /// The user for accounts, or the pocket for pockets.
+    holder_id: Uuid,
+    user_id: Uuid,
+    currency: Currency,
+    #[serde(with = "bigdecimal::serde::json_num")]
+    balance: BigDecimal,
+    #[serde(with = "bigdecimal::serde::json_num")]
+    annual_rate: BigDecimal,
+    #[serde(with = "bigdecimal::serde::json_num")]
+    amount: BigDecimal,
+}
+
+#[derive(Serialize)]
+pub struct InterestTotalResponse {
+    currency: Currency,
+    #[serde(with = "bigdecimal::serde::json_num")]
+    amount: BigDecimal,
+}
+
+impl From<InterestRate> for InterestRateResponse {
+    fn from(interest_rate: InterestRate) -> Self {
+        Self {
+            product: interest_rate.product,
+            currency: interest_rate.currency,
+            annual_rate: interest_rate.annual_rate,
+            updated_at: interest_rate.updated_at,
+        }
+    }
+}
+
+impl From<InterestAccrual> for InterestAccrualResponse {
+    fn from(accrual: InterestAccrual) -> Self {
+        Self {
+            product: accrual.product,
+            holder_id: accrual.holder_id,
+            user_id: accrual.user_id,
+            currency: accrual.currency,
+            balance: accrual.balance,
+            annual_rate: accrual.annual_rate,
+            amount: accrual.amount,
+        }
+    }
+}
+
+/// Returns all interest rates.
+pub async fn get_interest_rates(
+    State(pool): State<DbConnectionPool>,
+) -> Result<Json<GetInterestRatesResponse>> {
+    use crate::schema::interest_rates;
+
+    let mut conn = pool
+        .get()
+        .await
+        .context("failed to get database connection")
+        .map_err(AppError::from)?;
+
+    let interest_rates: Vec<InterestRate> = interest_rates::table
+        .select(InterestRate::as_select())
+        .order((
+            interest_rates::product.asc(),
+            interest_rates::currency.asc(),
+        ))
+        .load(&mut conn)
+        .await
+        .context("failed to query interest rates")
+        .map_err(AppError::from)?;
+
+    Ok(Json(GetInterestRatesResponse {
+        interest_rates: interest_rates.into_iter().map(Into::into).collect(),
+    }))
+}
+
+/// Sets the interest paid on balances of a product in a currency, from the
+/// next accrual on.
+///
+/// Only admins may set interest rates.
+pub async fn put_interest_rate(
+    State(pool): State<DbConnectionPool>,
+    Extension(authenticated_user): Extension<AuthenticatedUser>,
+    Path(InterestRatePathParams { product, currency }): Path<InterestRatePathParams>,
+    WithRejection(Json(payload), _): WithRejection<Json<PutInterestRatePayload>, JsonRejection>,
+) -> Result<Json<InterestRateResponse>> {
+    let mut conn = pool
+        .get()
+        .await
+        .context("failed to get database connection")
+        .map_err(AppError::from)?;
+
+    if !is_admin(&mut conn, authenticated_user.subject)
+        .await
+        .map_err(AppError::from)?
+    {
+        return Err((
+            StatusCode::FORBIDDEN,
+            Json(json!({
+                "title": "PermissionDenied",
+            })),
+        ))?;
+    }
+
+    if let Err(detail) = interest::validate_rate(&payload.annual_rate) {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(json!({
+                "title": "InvalidRate",
+                "detail": detail,
+            })),
+        ))?;
+    }
+
+    let interest_rate = upsert_interest_rate(
+        &mut conn,
+        InterestRate {
+            product,
+            currency,
+            annual_rate: payload.annual_rate,
+            updated_at: jiff::Timestamp::now(),
+        },
+    )
+    .await
+    .map_err(AppError::from)?;
+
+    Ok(Json(interest_rate.into()))
+}
+
+/// Removes the interest rate of a product in a currency, so that its balances
+/// no longer accrue interest. What accrued already is still paid out.
+///
+/// Only admins may remove interest rates.
+pub async fn delete_interest_rate(
+    State(pool): State<DbConnectionPool>,
+    Extension(authenticated_user): Extension<AuthenticatedUser>,
+    Path(InterestRatePathParams { product, currency }): Path<InterestRatePathParams>,
+) -> Result<StatusCode> {
+    use crate::schema::interest_rates;
+
+    let mut conn = pool
+        .get()
+        .await
+        .context("failed to get database connection")
+        .map_err(AppError::from)?;
+
+    if !is_admin(&mut conn, authenticated_user.subject)
+        .await
+        .map_err(AppError::from)?
+    {
+        return Err((
+            StatusCode::FORBIDDEN,
+            Json(json!({
+                "title": "PermissionDenied",
+            })),
+        ))?;
+    }
+
+    diesel::delete(interest_rates::table.find((product, currency)))
+        .execute(&mut conn)
+        .await
+        .context("failed to delete interest rate")
+        .map_err(AppError::from)?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Returns the interest each balance would accrue on a day at its current
+/// balance, without recording it.
+///
+/// Only admins may preview interest accruals.
+pub async fn get_interest_accrual_preview(
+    State(pool): State<DbConnectionPool>,
+    Extension(authenticated_user): Extension<AuthenticatedUser>,
+    Query(GetInterestAccrualPreviewQueryParams { date }): Query<
+        GetInterestAccrualPreviewQueryParams,
+    >,
+) -> Result<Json<GetInterestAccrualPreviewResponse>> {
+    let mut conn = pool
+        .get()
+        .await
+        .context("failed to get database connection")
+        .map_err(AppError::from)?;
+
+    if !is_admin(&mut conn, authenticated_user.subject)
+        .await
+        .map_err(AppError::from)?
+    {
+        return Err((
+            StatusCode::FORBIDDEN,
+            Json(json!({
+                "title": "PermissionDenied",
+            })),
+        ))?;
+    }
+
+    let date = date.unwrap_or_else(|| jiff::Timestamp::now().to_zoned(TimeZone::UTC).date());
+    let accruals = interest::accruals(&mut conn, date)
+        .await
+        .map_err(AppError::from)?;
+
+    let mut totals: BTreeMap<Currency, BigDecimal> = BTreeMap::new();
+    for accrual in &accruals {
+        *totals.entry(accrual.currency).or_default() += &accrual.amount;
+    }
+
+    Ok(Json(GetInterestAccrualPreviewResponse {
+        date,
+        accruals: accruals.into_iter().map(Into::into).collect(),
+        totals: totals
+            .into_iter()
+            .map(|(currency, amount)| InterestTotalResponse { currency, amount })
+            .collect(),
+    }))
+}
+
+async fn upsert_interest_rate(
+    conn: &mut DbConnection,
+    interest_rate: InterestRate,
+) -> anyhow::Result<InterestRate> {
+    use crate::schema::interest_rates;
+
+    diesel::insert_into(interest_rates::table)
+        .values(interest_rate.clone())
+        .on_conflict((interest_rates::product, interest_rates::currency))
+        .do_update()
+        .set(interest_rate)
+        .returning(InterestRate::as_returning())
+        .get_result(conn)
+        .await
+        .context("failed to upsert interest rate")
+}
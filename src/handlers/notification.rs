@@ -0,0 +1,391 @@
+use std::collections::BTreeMap;
+
+use anyhow::Context as _;
+use axum::extract::{Path, Query, State};
+use axum::http::StatusCode;
+use axum::response::Result;
+use axum::{Extension, Json};
+use axum_extra::extract::WithRejection;
+use diesel::prelude::*;
+#[allow(
+    clippy::unused_trait_names,
+    reason = "error[E0034]: multiple applicable items in scope"
+)]
+use diesel_async::RunQueryDsl;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tracing::debug;
+use uuid::Uuid;
+
+use crate::error::{AppError, JsonRejection};
+use crate::middleware::auth::AuthenticatedUser;
+use crate::models::Notification;
+use crate::models::notification::{NotificationKind, NotificationPreference};
+use crate::state::{DbConnection, DbConnectionPool};
+
+/// The maximum number of notifications listed, newest first.
+const NOTIFICATIONS_LIMIT: i64 = 100;
+
+#[derive(Deserialize)]
+pub struct NotificationsPathParams {
+    user_id: Uuid,
+}
+
+#[derive(Deserialize)]
+pub struct NotificationPathParams {
+    user_id: Uuid,
+    notification_id: Uuid,
+}
+
+#[derive(Deserialize)]
+pub struct GetNotificationsQueryParams {
+    /// Whether to list only the notifications which haven't been read yet.
+    #[serde(default)]
+    unread: bool,
+}
+
+#[derive(Deserialize)]
+pub struct PutNotificationPreferencesPayload {
+    /// Whether to send notifications of each kind. Kinds which are omitted
+    /// are left as they are.
+    preferences: BTreeMap<NotificationKind, bool>,
+}
+
+#[derive(Serialize)]
+pub struct GetNotificationsResponse {
+    notifications: Vec<NotificationResponse>,
+}
+
+#[derive(Serialize)]
+pub struct NotificationResponse {
+    id: Uuid,
+    kind: NotificationKind,
+    data: serde_json::Value,
+    created_at: jiff::Timestamp,
+    read_at: Option<jiff::Timestamp>,
+}
+
+#[derive(Serialize)]
+pub struct GetUnreadCountResponse {
+    unread_count: i64,
+}
+
+#[derive(Serialize)]
+pub struct NotificationPreferencesResponse {
+    /// Whether notifications of each kind are sent.
+    preferences: BTreeMap<NotificationKind, bool>,
+}
+
+impl TryFrom<Notification> for NotificationResponse {
+    type Error = anyhow::Error;
+
+    fn try_from(notification: Notification) -> anyhow::Result<Self> {
+        Ok(Self {
+            id: notification.id,
+            kind: notification.kind,
+            data: serde_json::from_str(&notification.data)
+                .context("failed to parse notification data")?,
+            created_at: notification.created_at,
+            read_at: notification.read_at,
+        })
+    }
+}
+
+/// Returns the latest notifications of a user, newest first.
+///
+/// Users may only see their own notifications.
+pub async fn get_notifications(
+    State(pool): State<DbConnectionPool>,
+    Extension(authenticated_user): Extension<AuthenticatedUser>,
+    Path(NotificationsPathParams { user_id }): Path<NotificationsPathParams>,
+    Query(GetNotificationsQueryParams { unread }): Query<GetNotificationsQueryParams>,
+) -> Result<Json<GetNotificationsResponse>> {
+    use crate::models::types;
+    use crate::schema::notifications;
+
+    if authenticated_user.subject != user_id {
+        return Err((
+            StatusCode::FORBIDDEN,
+            Json(json!({
+                "title": "PermissionDenied",
+            })),
+        ))?;
+    }
+
+    let mut conn = pool
+        .get()
+        .await
+        .context("failed to get database connection")
+        .map_err(AppError::from)?;
+
+    let mut query = notifications::table
+        .filter(notifications::user_id.eq(types::Uuid::from(user_id)))
+        .into_boxed();
+    if unread {
+        query = query.filter(notifications::read_at.is_null());
+    }
+    let notifications: Vec<Notification> = query
+        .order(notifications::id.desc())
+        .limit(NOTIFICATIONS_LIMIT)
+        .select(Notification::as_select())
+        .load(&mut conn)
+        .await
+        .context("failed to query notifications")
+        .map_err(AppError::from)?;
+
+    let notifications = notifications
+        .into_iter()
+        .map(NotificationResponse::try_from)
+        .collect::<anyhow::Result<_>>()
+        .map_err(AppError::from)?;
+
+    Ok(Json(GetNotificationsResponse { notifications }))
+}
+
+/// Returns how many notifications of a user haven't been read yet.
+pub async fn get_unread_count(
+    State(pool): State<DbConnectionPool>,
+    Extension(authenticated_user): Extension<AuthenticatedUser>,
+    Path(NotificationsPathParams { user_id }): Path<NotificationsPathParams>,
+) -> Result<Json<GetUnreadCountResponse>> {
+    use crate::models::types;
+    use crate::schema::notifications;
+
+    if authenticated_user.subject != user_id {
+        return Err((
+            StatusCode::FORBIDDEN,
+            Json(json!({
+                "title": "PermissionDenied",
+            })),
+        ))?;
+    }
+
+    let mut conn = pool
+        .get()
+        .await
+        .context("failed to get database connection")
+        .map_err(AppError::from)?;
+
+    let unread_count: i64 = notifications::table
+        .filter(notifications::user_id.eq(types::Uuid::from(user_id)))
+        .filter(notifications::read_at.is_null())
+        .count()
+        .get_result(&mut conn)
+        .await
+        .context("failed to count notifications")
+        .map_err(AppError::from)?;
+
+    Ok(Json(GetUnreadCountResponse { unread_count }))
+}
+
+/// Marks a notification of a user as read. Notifications which were already
+/// read keep when they were first read.
+pub async fn post_notification_read(
+    State(pool): State<DbConnectionPool>,
+    Extension(authenticated_user): Extension<AuthenticatedUser>,
+    Path(NotificationPathParams {
+        user_id,
+        notification_id,
+    }): Path<NotificationPathParams>,
+) -> Result<Json<NotificationResponse>> {
+    use crate::models::types;
+    use crate::schema::notifications;
+
+    if authenticated_user.subject != user_id {
+        return Err((
+            StatusCode::FORBIDDEN,
+            Json(json!({
+                "title": "PermissionDenied",
+            })),
+        ))?;
+    }
+
+    let mut conn = pool
+        .get()
+        .await
+        .context("failed to get database connection")
+        .map_err(AppError::from)?;
+
+    diesel::update(
+        notifications::table
+            .find(types::Uuid::from(notification_id))
+            .filter(notifications::user_id.eq(types::Uuid::from(user_id)))
+            .filter(notifications::read_at.is_null()),
+    )
+    .set(notifications::read_at.eq(types::Timestamp::from(jiff::Timestamp::now())))
+    .execute(&mut conn)
+    .await
+    .context("failed to update notification")
+    .map_err(AppError::from)?;
+
+    let Some(notification): Option<Notification> = notifications::table
+        .find(types::Uuid::from(notification_id))
+        .filter(notifications::user_id.eq(types::Uuid::from(user_id)))
+        .select(Notification::as_select())
+        .first(&mut conn)
+        .await
+        .optional()
+        .context("failed to query notifications")
+        .map_err(AppError::from)?
+    else {
+        debug!(%notification_id, "could not find notification");
+
+        return Err((
+            StatusCode::NOT_FOUND,
+            Json(json!({
+                "title": "NotificationNotFound",
+            })),
+        ))?;
+    };
+
+    let notification = NotificationResponse::try_from(notification).map_err(AppError::from)?;
+
+    Ok(Json(notification))
+}
+
+/// Marks all notifications of a user as read.
+pub async fn post_notifications_read(
+    State(pool): State<DbConnectionPool>,
+    Extension(authenticated_user): Extension<AuthenticatedUser>,
+    Path(NotificationsPathParams { user_id }): Path<NotificationsPathParams>,
+) -> Result<StatusCode> {
+    use crate::models::types;
+    use crate::schema::notifications;
+
+    if authenticated_user.subject != user_id {
+        return Err((
+            StatusCode::FORBIDDEN,
+            Json(json!({
+                "title": "PermissionDenied",
+            })),
+        ))?;
+    }
+
+    let mut conn = pool
+        .get()
+        .await
+        .context("failed to get database connection")
+        .map_err(AppError::from)?;
+
+    diesel::update(
+        notifications::table
+            .filter(notifications::user_id.eq(types::Uuid::from(user_id)))
+            .filter(notifications::read_at.is_null()),
+    )
+    .set(notifications::read_at.eq(types::Timestamp::from(jiff::Timestamp::now())))
+    .execute(&mut conn)
+    .await
+    .context("failed to update notifications")
+    .map_err(AppError::from)?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Returns which kinds of notifications a user is sent.
+pub async fn get_notification_preferences(
+    State(pool): State<DbConnectionPool>,
+    Extension(authenticated_user): Extension<AuthenticatedUser>,
+    Path(NotificationsPathParams { user_id }): Path<NotificationsPathParams>,
+) -> Result<Json<NotificationPreferencesResponse>> {
+    if authenticated_user.subject != user_id {
+        return Err((
+            StatusCode::FORBIDDEN,
+            Json(json!({
+                "title": "PermissionDenied",
+            })),
+        ))?;
+    }
+
+    let mut conn = pool
+        .get()
+        .await
+        .context("failed to get database connection")
+        .map_err(AppError::from)?;
+
+    let preferences = load_preferences(&mut conn, user_id)
+        .await
+        .map_err(AppError::from)?;
+
+    Ok(Json(NotificationPreferencesResponse { preferences }))
+}
+
+/// Turns notifications of some kinds on or off for a user.
+pub async fn put_notification_preferences(
+    State(pool): State<DbConnectionPool>,
+    Extension(authenticated_user): Extension<AuthenticatedUser>,
+    Path(NotificationsPathParams { user_id }): Path<NotificationsPathParams>,
+    WithRejection(Json(payload), _): WithRejection<
+        Json<PutNotificationPreferencesPayload>,
+        JsonRejection,
+    >,
+) -> Result<Json<NotificationPreferencesResponse>> {
+    use crate::schema::notification_preferences;
+
+    if authenticated_user.subject != user_id {
+        return Err((
+            StatusCode::FORBIDDEN,
+            Json(json!({
+                "title": "PermissionDenied",
+            })),
+        ))?;
+    }
+
+    let mut conn = pool
+        .get()
+        .await
+        .context("failed to get database connection")
+        .map_err(AppError::from)?;
+
+    for (kind, enabled) in payload.preferences {
+        diesel::insert_into(notification_preferences::table)
+            .values(NotificationPreference {
+                user_id,
+                kind,
+                enabled,
+            })
+            .on_conflict((
+                notification_preferences::user_id,
+                notification_preferences::kind,
+            ))
+            .do_update()
+            .set(notification_preferences::enabled.eq(enabled))
+            .execute(&mut conn)
+            .await
+            .context("failed to upsert notification preference")
+            .map_err(AppError::from)?;
+    }
+
+    let preferences = load_preferences(&mut conn, user_id)
+        .await
+        .map_err(AppError::from)?;
+
+    Ok(Json(NotificationPreferencesResponse { preferences }))
+}
+
+/// Returns whether `user_id` is sent notifications of each kind.
+async fn load_preferences(
+    conn: &mut DbConnection,
+    user_id: Uuid,
+) -> anyhow::Result<BTreeMap<NotificationKind, bool>> {
+    use crate::models::types;
+    use crate::schema::notification_preferences;
+
+    let saved: Vec<NotificationPreference> = notification_preferences::table
+        .filter(notification_preferences::user_id.eq(types::Uuid::from(user_id)))
+        .select(NotificationPreference::as_select())
+        .load(conn)
+        .await
+        .context("failed to query notification preferences")?;
+
+    let mut preferences: BTreeMap<_, _> = NotificationKind::ALL
+        .into_iter()
+        .map(|kind| (kind, true))
+        .collect();
+    preferences.extend(
+        saved
+            .into_iter()
+            .map(|preference| (preference.kind, preference.enabled)),
+    );
+
+    Ok(preferences)
+}
@@ -0,0 +1,333 @@
+use anyhow::{Context as _, ensure};
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::response::Result;
+use axum::{Extension, Json};
+use axum_extra::extract::WithRejection;
+use bigdecimal::{BigDecimal, Zero as _};
+use biscuit::jwa::SignatureAlgorithm;
+use biscuit::{
+    ClaimPresenceOptions, ClaimsSet, JWT, Presence, RegisteredClaims, Validation,
+    ValidationOptions, jws,
+};
+use diesel::prelude::*;
+#[allow(
+    clippy::unused_trait_names,
+    reason = "error[E0034]: multiple applicable items in scope"
+)]
+use diesel_async::RunQueryDsl;
+use secrecy::ExposeSecret as _;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tracing::debug;
+use url::Url;
+use uuid::Uuid;
+
+use crate::currency::{Currency, DEFAULT_CURRENCY};
+use crate::error::{AppError, JsonRejection};
+use crate::handlers::payment_request::validate_expiry;
+use crate::handlers::transaction::validate_memo;
+use crate::ledger::TransferError;
+use crate::middleware::auth::AuthenticatedUser;
+use crate::models::User;
+use crate::state::{AccessTokenIssuer, DbConnectionPool, JwsSigningSecret, PaymentPage, PublicUrl};
+
+/// The JWS "typ" header parameter of payment links.
+///
+/// This keeps payment links from being accepted as access tokens, and the other
+/// way around.
+const PAYMENT_LINK_MEDIA_TYPE: &str = "payment+jwt";
+
+/// How long a payment link stays valid, unless specified otherwise.
+const DEFAULT_EXPIRY: jiff::SignedDuration = jiff::SignedDuration::from_hours(7 * 24);
+
+/// The longest a payment link may stay valid, in days.
+const MAX_EXPIRY_DAYS: i64 = 365;
+
+/// The private claims of a payment link. The recipient is the "sub" claim.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct PaymentLinkClaims {
+    /// A fixed amount to pay. If omitted, the sender chooses the amount.
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        with = "bigdecimal::serde::json_num_option"
+    )]
+    pub amount: Option<BigDecimal>,
+    /// The currency to pay in. Links issued before currencies were added
+    /// don't have one, and are in [`DEFAULT_CURRENCY`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub currency: Option<Currency>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub memo: Option<String>,
+}
+
+/// A verified payment link.
+#[derive(Debug)]
+pub struct PaymentLink {
+    pub recipient: Uuid,
+    pub amount: Option<BigDecimal>,
+    pub currency: Currency,
+    pub memo: Option<String>,
+    pub expires_at: jiff::Timestamp,
+}
+
+#[derive(Deserialize)]
+pub struct PostPaymentLinkPayload {
+    #[serde(default, with = "bigdecimal::serde::json_num_option")]
+    amount: Option<BigDecimal>,
+    /// Defaults to [`DEFAULT_CURRENCY`].
+    currency: Option<Currency>,
+    memo: Option<String>,
+    expires_at: Option<jiff::Timestamp>,
+}
+
+#[derive(Serialize)]
+pub struct PostPaymentLinkResponse {
+    token: String,
+    uri: Url,
+    expires_at: jiff::Timestamp,
+}
+
+#[derive(Deserialize)]
+pub struct PostPaymentLinkVerifyPayload {
+    token: String,
+}
+
+#[derive(Serialize)]
+pub struct PostPaymentLinkVerifyResponse {
+    recipient: PaymentLinkRecipientResponse,
+    #[serde(with = "bigdecimal::serde::json_num_option")]
+    amount: Option<BigDecimal>,
+    currency: Currency,
+    memo: Option<String>,
+    expires_at: jiff::Timestamp,
+}
+
+#[derive(Serialize)]
+pub struct PaymentLinkRecipientResponse {
+    id: Uuid,
+    username: String,
+}
+
+/// Issues a signed payment link for paying the authenticated user.
+pub async fn post_payment_link(
+    State(access_token_issuer): State<AccessTokenIssuer>,
+    State(jws_signing_secret): State<JwsSigningSecret>,
+    State(public_url): State<PublicUrl>,
+    Extension(authenticated_user): Extension<AuthenticatedUser>,
+    WithRejection(Json(payload), _): WithRejection<Json<PostPaymentLinkPayload>, JsonRejection>,
+) -> Result<Json<PostPaymentLinkResponse>> {
+    if payload
+        .amount
+        .as_ref()
+        .is_some_and(|amount| *amount <= BigDecimal::zero())
+    {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(json!({
+                "title": "InvalidAmount",
+            })),
+        ))?;
+    }
+    let currency = payload.currency.unwrap_or(DEFAULT_CURRENCY);
+    if payload
+        .amount
+        .as_ref()
+        .is_some_and(|amount| !currency.is_valid_amount(amount))
+    {
+        return Err(TransferError::AmountTooPrecise(currency))?;
+    }
+
+    let memo = validate_memo(payload.memo.as_deref())?;
+
+    let now = jiff::Timestamp::now();
+    let expires_at = validate_expiry(now, payload.expires_at, DEFAULT_EXPIRY, MAX_EXPIRY_DAYS)?;
+
+    let payment_link = PaymentLink {
+        recipient: authenticated_user.subject,
+        amount: payload.amount,
+        currency,
+        memo,
+        expires_at,
+    };
+    let token = encode_payment_link(&payment_link, &access_token_issuer, &jws_signing_secret)
+        .map_err(AppError::from)?;
+
+    Ok(Json(PostPaymentLinkResponse {
+        uri: public_url.payment_uri(PaymentPage::PaymentLink(&token)),
+        token,
+        expires_at,
+    }))
+}
+
+/// Verifies a payment link, so that the sender can trust its contents before
+/// paying.
+pub async fn post_payment_link_verify(
+    State(pool): State<DbConnectionPool>,
+    State(access_token_issuer): State<AccessTokenIssuer>,
+    State(jws_signing_secret): State<JwsSigningSecret>,
+    WithRejection(Json(payload), _): WithRejection<
+        Json<PostPaymentLinkVerifyPayload>,
+        JsonRejection,
+    >,
+) -> Result<Json<PostPaymentLinkVerifyResponse>> {
+    use crate::models::types;
+    use crate::schema::users;
+
+    let payment_link =
+        match decode_payment_link(&payload.token, &access_token_issuer, &jws_signing_secret) {
+            Ok(payment_link) => payment_link,
+            Err(err) => {
+                debug!(?err, "invalid payment link");
+
+                return Err((
+                    StatusCode::BAD_REQUEST,
+                    Json(json!({
+                        "title": "InvalidPaymentLink",
+                    })),
+                ))?;
+            },
+        };
+
+    let mut conn = pool
+        .get()
+        .await
+        .context("failed to get database connection")
+        .map_err(AppError::from)?;
+
+    let recipient: Option<User> = users::table
+        .find(types::Uuid::from(payment_link.recipient))
+        .select(User::as_select())
+        .first(&mut conn)
+        .await
+        .optional()
+        .context("failed to query users")
+        .map_err(AppError::from)?;
+    let Some(recipient) = recipient else {
+        debug!(%payment_link.recipient, "could not find recipient");
+
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(json!({
+                "title": "InvalidPaymentLink",
+            })),
+        ))?;
+    };
+
+    Ok(Json(PostPaymentLinkVerifyResponse {
+        recipient: PaymentLinkRecipientResponse {
+            id: recipient.id,
+            username: recipient.username,
+        },
+        amount: payment_link.amount,
+        currency: payment_link.currency,
+        memo: payment_link.memo,
+        expires_at: payment_link.expires_at,
+    }))
+}
+
+/// Encodes and signs a payment link as a compact JWS.
+pub(crate) fn encode_payment_link(
+    payment_link: &PaymentLink,
+    access_token_issuer: &AccessTokenIssuer,
+    jws_signing_secret: &JwsSigningSecret,
+) -> anyhow::Result<String> {
+    let now = chrono::Utc::now();
+    let expiry = chrono::DateTime::from_timestamp(payment_link.expires_at.as_second(), 0)
+        .context("payment link expiry out of range")?;
+
+    let token = JWT::<PaymentLinkClaims, biscuit::Empty>::new_decoded(
+        jws::RegisteredHeader {
+            algorithm: SignatureAlgorithm::HS256,
+            media_type: Some(PAYMENT_LINK_MEDIA_TYPE.to_owned()),
+            ..Default::default()
+        }
+        .into(),
+        ClaimsSet {
+            registered: RegisteredClaims {
+                issuer: Some(access_token_issuer.0.as_str().to_owned()),
+                expiry: Some(expiry.into()),
+                subject: Some(payment_link.recipient.to_string()),
+                issued_at: Some(now.into()),
+                id: Some(Uuid::new_v4().to_string()),
+                ..Default::default()
+            },
+            private: PaymentLinkClaims {
+                amount: payment_link.amount.clone(),
+                currency: Some(payment_link.currency),
+                memo: payment_link.memo.clone(),
+            },
+        },
+    );
+    let token = token
+        .encode(&jws::Secret::Bytes(
+            jws_signing_secret.0.expose_secret().to_vec(),
+        ))
+        .context("failed to encode and sign payment link")?;
+
+    Ok(token
+        .encoded()
+        .expect("`token` should be already encoded")
+        .to_string())
+}
+
+/// Decodes a payment link, and verifies its signature and expiry.
+pub(crate) fn decode_payment_link(
+    token: &str,
+    access_token_issuer: &AccessTokenIssuer,
+    jws_signing_secret: &JwsSigningSecret,
+) -> anyhow::Result<PaymentLink> {
+    let token = JWT::<PaymentLinkClaims, biscuit::Empty>::new_encoded(token)
+        .decode(
+            &jws::Secret::Bytes(jws_signing_secret.0.expose_secret().to_vec()),
+            SignatureAlgorithm::HS256,
+        )
+        .context("failed to decode payment link")?;
+
+    let header = token.header().expect("`token` should have been decoded");
+    ensure!(
+        header.registered.media_type.as_deref() == Some(PAYMENT_LINK_MEDIA_TYPE),
+        "payment link \"typ\" header parameter mismatch"
+    );
+
+    token
+        .validate(ValidationOptions {
+            claim_presence_options: ClaimPresenceOptions {
+                issuer: Presence::Required,
+                expiry: Presence::Required,
+                subject: Presence::Required,
+                ..Default::default()
+            },
+            issuer: Validation::Validate(access_token_issuer.0.as_str().to_owned()),
+            expiry: Validation::Validate(()),
+            ..Default::default()
+        })
+        .context("failed to validate payment link")?;
+
+    let claims = token.payload().expect("`token` should have been decoded");
+    let recipient = claims
+        .registered
+        .subject
+        .as_deref()
+        .expect("\"sub\" claim should be present in payment link")
+        .parse()
+        .context("payment link subject is not a valid UUID")?;
+    let expires_at = jiff::Timestamp::from_second(
+        claims
+            .registered
+            .expiry
+            .as_ref()
+            .expect("\"exp\" claim should be present in payment link")
+            .timestamp(),
+    )
+    .context("payment link expiry out of range")?;
+
+    Ok(PaymentLink {
+        recipient,
+        amount: claims.private.amount.clone(),
+        currency: claims.private.currency.unwrap_or(DEFAULT_CURRENCY),
+        memo: claims.private.memo.clone(),
+        expires_at,
+    })
+}
@@ -0,0 +1,648 @@
+use std::slice;
+
+use anyhow::Context as _;
+use axum::extract::{Path, Query, State};
+use axum::http::StatusCode;
+use axum::response::{IntoResponse as _, Response, Result};
+use axum::{Extension, Json};
+use axum_extra::extract::WithRejection;
+use bigdecimal::{BigDecimal, Zero as _};
+use diesel::prelude::*;
+#[allow(
+    clippy::unused_trait_names,
+    reason = "error[E0034]: multiple applicable items in scope"
+)]
+use diesel_async::RunQueryDsl;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tracing::debug;
+use uuid::Uuid;
+
+use crate::currency::{Currency, DEFAULT_CURRENCY};
+use crate::error::{AppError, JsonRejection, permission_denied};
+use crate::event_hub::{EventHub, publish_transactions};
+use crate::handlers::transaction::validate_memo;
+use crate::ledger::{self, NewTransfer, TransferError};
+use crate::limits::TransferLimits;
+use crate::middleware::auth::AuthenticatedUser;
+use crate::models::payment_request::{NewPaymentRequest, PaymentRequestStatus};
+use crate::models::user::UserRef;
+use crate::models::{PaymentRequest, User};
+use crate::notification;
+use crate::qr_code::{QrCodeOptions, qr_code_response};
+use crate::state::{DbConnection, DbConnectionPool, PaymentPage, PublicUrl};
+use crate::webhook;
+
+/// How long a payment request stays payable, unless specified otherwise.
+const DEFAULT_EXPIRY: jiff::SignedDuration = jiff::SignedDuration::from_hours(7 * 24);
+
+/// The longest a payment request may stay payable, in days.
+const MAX_EXPIRY_DAYS: i64 = 90;
+
+#[derive(Deserialize)]
+pub struct PaymentRequestPathParams {
+    payment_request_id: Uuid,
+}
+
+#[derive(Deserialize)]
+pub struct GetPaymentRequestsQueryParams {
+    status: Option<PaymentRequestStatus>,
+}
+
+#[derive(Deserialize)]
+pub struct PostPaymentRequestPayload {
+    #[serde(with = "bigdecimal::serde::json_num")]
+    amount: BigDecimal,
+    /// Defaults to [`DEFAULT_CURRENCY`].
+    currency: Option<Currency>,
+    /// The user asked to pay. If omitted, anyone holding the link may pay.
+    payer: Option<UserRef>,
+    memo: Option<String>,
+    expires_at: Option<jiff::Timestamp>,
+}
+
+#[derive(Serialize)]
+pub struct GetPaymentRequestsResponse {
+    payment_requests: Vec<PaymentRequestResponse>,
+}
+
+#[derive(Serialize)]
+pub struct PaymentRequestResponse {
+    id: Uuid,
+    requester: Uuid,
+    payer: Option<Uuid>,
+    #[serde(with = "bigdecimal::serde::json_num")]
+    amount: BigDecimal,
+    currency: Currency,
+    memo: Option<String>,
+    status: PaymentRequestStatus,
+    created_at: jiff::Timestamp,
+    expires_at: jiff::Timestamp,
+    transaction_id: Option<Uuid>,
+}
+
+impl PaymentRequestResponse {
+    fn new(payment_request: PaymentRequest, now: jiff::Timestamp) -> Self {
+        Self {
+            id: payment_request.id,
+            requester: payment_request.requester,
+            payer: payment_request.payer,
+            status: payment_request.status_at(now),
+            amount: payment_request.amount,
+            currency: payment_request.currency,
+            memo: payment_request.memo,
+            created_at: payment_request.created_at,
+            expires_at: payment_request.expires_at,
+            transaction_id: payment_request.transaction_id,
+        }
+    }
+}
+
+pub async fn post_payment_request(
+    State(pool): State<DbConnectionPool>,
+    Extension(authenticated_user): Extension<AuthenticatedUser>,
+    WithRejection(Json(payload), _): WithRejection<Json<PostPaymentRequestPayload>, JsonRejection>,
+) -> Result<(StatusCode, Json<PaymentRequestResponse>)> {
+    use crate::schema::payment_requests;
+
+    let mut conn = pool
+        .get()
+        .await
+        .context("failed to get database connection")
+        .map_err(AppError::from)?;
+
+    let currency = payload.currency.unwrap_or(DEFAULT_CURRENCY);
+    if payload.amount <= BigDecimal::zero() {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(json!({
+                "title": "InvalidAmount",
+            })),
+        ))?;
+    }
+    if !currency.is_valid_amount(&payload.amount) {
+        return Err(TransferError::AmountTooPrecise(currency))?;
+    }
+    if ledger::find_account(&mut conn, authenticated_user.subject, currency)
+        .await
+        .map_err(AppError::from)?
+        .is_none()
+    {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(json!({
+                "title": "CurrencyMismatch",
+                "detail": format!("requester must have a {currency} account"),
+            })),
+        ))?;
+    }
+
+    let memo = validate_memo(payload.memo.as_deref())?;
+
+    let now = jiff::Timestamp::now();
+    let expires_at = validate_expiry(now, payload.expires_at, DEFAULT_EXPIRY, MAX_EXPIRY_DAYS)?;
+
+    let payer = match &payload.payer {
+        Some(payer) => {
+            let payer: User = match payer
+                .query()
+                .select(User::as_select())
+                .first(&mut conn)
+                .await
+            {
+                Ok(payer) => payer,
+                Err(diesel::NotFound) => {
+                    debug!(?payload.payer, "could not find payer");
+
+                    return Err((
+                        StatusCode::BAD_REQUEST,
+                        Json(json!({
+                            "title": "InvalidPayer",
+                        })),
+                    ))?;
+                },
+                Err(err) => {
+                    return Err(err)
+                        .context("failed to query users")
+                        .map_err(AppError::from)?;
+                },
+            };
+            if payer.id == authenticated_user.subject {
+                return Err((
+                    StatusCode::BAD_REQUEST,
+                    Json(json!({
+                        "title": "SelfTransfer",
+                    })),
+                ))?;
+            }
+            Some(payer.id)
+        },
+        None => None,
+    };
+
+    let new_payment_request = NewPaymentRequest {
+        // Use a random UUID rather than a time-ordered one, as holding the ID is
+        // enough to pay a payment request which is open to anyone.
+        id: Uuid::new_v4(),
+        requester: authenticated_user.subject,
+        payer,
+        amount: payload.amount,
+        currency,
+        memo,
+        status: PaymentRequestStatus::Pending,
+        created_at: now,
+        expires_at,
+    };
+
+    let created_payment_request: PaymentRequest = diesel::insert_into(payment_requests::table)
+        .values(new_payment_request)
+        .returning(PaymentRequest::as_returning())
+        .get_result(&mut conn)
+        .await
+        .context("failed to insert payment request")
+        .map_err(AppError::from)?;
+
+    Ok((
+        StatusCode::CREATED,
+        Json(PaymentRequestResponse::new(created_payment_request, now)),
+    ))
+}
+
+/// Lists payment requests which the authenticated user has been asked to pay.
+///
+/// Payment requests which are open to anyone are not listed.
+pub async fn get_incoming_payment_requests(
+    State(pool): State<DbConnectionPool>,
+    Extension(authenticated_user): Extension<AuthenticatedUser>,
+    Query(GetPaymentRequestsQueryParams { status }): Query<GetPaymentRequestsQueryParams>,
+) -> Result<Json<GetPaymentRequestsResponse>> {
+    use crate::models::types;
+    use crate::schema::payment_requests;
+
+    let mut conn = pool
+        .get()
+        .await
+        .context("failed to get database connection")
+        .map_err(AppError::from)?;
+
+    let payment_requests: Vec<PaymentRequest> = payment_requests::table
+        .filter(payment_requests::payer.eq(types::Uuid::from(authenticated_user.subject)))
+        .select(PaymentRequest::as_select())
+        .order(payment_requests::created_at.desc())
+        .load(&mut conn)
+        .await
+        .context("failed to query payment requests")
+        .map_err(AppError::from)?;
+
+    Ok(Json(payment_requests_response(payment_requests, status)))
+}
+
+/// Lists payment requests created by the authenticated user.
+pub async fn get_outgoing_payment_requests(
+    State(pool): State<DbConnectionPool>,
+    Extension(authenticated_user): Extension<AuthenticatedUser>,
+    Query(GetPaymentRequestsQueryParams { status }): Query<GetPaymentRequestsQueryParams>,
+) -> Result<Json<GetPaymentRequestsResponse>> {
+    use crate::models::types;
+    use crate::schema::payment_requests;
+
+    let mut conn = pool
+        .get()
+        .await
+        .context("failed to get database connection")
+        .map_err(AppError::from)?;
+
+    let payment_requests: Vec<PaymentRequest> = payment_requests::table
+        .filter(payment_requests::requester.eq(types::Uuid::from(authenticated_user.subject)))
+        .select(PaymentRequest::as_select())
+        .order(payment_requests::created_at.desc())
+        .load(&mut conn)
+        .await
+        .context("failed to query payment requests")
+        .map_err(AppError::from)?;
+
+    Ok(Json(payment_requests_response(payment_requests, status)))
+}
+
+pub async fn get_payment_request(
+    State(pool): State<DbConnectionPool>,
+    Extension(authenticated_user): Extension<AuthenticatedUser>,
+    Path(PaymentRequestPathParams { payment_request_id }): Path<PaymentRequestPathParams>,
+) -> Result<Json<PaymentRequestResponse>> {
+    let mut conn = pool
+        .get()
+        .await
+        .context("failed to get database connection")
+        .map_err(AppError::from)?;
+
+    // # Security
+    //
+    // Respond with "not found" to anyone who may not see the payment request, so
+    // as not to reveal whether it exists or not.
+    let Some(payment_request) =
+        find_visible_payment_request(&mut conn, payment_request_id, authenticated_user.subject)
+            .await
+            .map_err(AppError::from)?
+    else {
+        debug!(%payment_request_id, "could not find payment request");
+
+        return Err(payment_request_not_found())?;
+    };
+
+    Ok(Json(PaymentRequestResponse::new(
+        payment_request,
+        jiff::Timestamp::now(),
+    )))
+}
+
+/// Renders a payment request as a QR code, which opens the frontend on the
+/// payment request.
+pub async fn get_payment_request_qr_code(
+    State(pool): State<DbConnectionPool>,
+    State(public_url): State<PublicUrl>,
+    Extension(authenticated_user): Extension<AuthenticatedUser>,
+    Path(PaymentRequestPathParams { payment_request_id }): Path<PaymentRequestPathParams>,
+    Query(options): Query<QrCodeOptions>,
+) -> Result<Response> {
+    let mut conn = pool
+        .get()
+        .await
+        .context("failed to get database connection")
+        .map_err(AppError::from)?;
+
+    let Some(payment_request) =
+        find_visible_payment_request(&mut conn, payment_request_id, authenticated_user.subject)
+            .await
+            .map_err(AppError::from)?
+    else {
+        debug!(%payment_request_id, "could not find payment request");
+
+        return Err(payment_request_not_found())?;
+    };
+
+    Ok(qr_code_response(
+        public_url
+            .payment_uri(PaymentPage::PaymentRequest(payment_request.id))
+            .as_str(),
+        options,
+    ))
+}
+
+/// Pays a payment request, by transferring the requested amount from the
+/// authenticated user to the requester.
+pub async fn post_payment_request_accept(
+    State(pool): State<DbConnectionPool>,
+    State(default_limits): State<TransferLimits>,
+    State(event_hub): State<EventHub>,
+    Extension(authenticated_user): Extension<AuthenticatedUser>,
+    Path(PaymentRequestPathParams { payment_request_id }): Path<PaymentRequestPathParams>,
+) -> Result<Json<PaymentRequestResponse>> {
+    use crate::models::types;
+    use crate::schema::payment_requests;
+
+    let mut conn = pool
+        .get()
+        .await
+        .context("failed to get database connection")
+        .map_err(AppError::from)?;
+
+    let user_id = authenticated_user.subject;
+    let default_limits = &default_limits;
+    let (payment_request, created_transaction) =
+        ledger::run_immediate_transaction(&mut conn, |conn| {
+            Box::pin(async move {
+                let mut payment_request =
+                    match find_pending_payment_request(conn, payment_request_id, user_id).await? {
+                        Ok(payment_request) => payment_request,
+                        Err(response) => return Ok(Err(response)),
+                    };
+
+                if payment_request.requester == user_id {
+                    return Ok(Err(permission_denied()));
+                }
+
+                let new_transfer = NewTransfer {
+                    amount: payment_request.amount.clone(),
+                    currency: payment_request.currency,
+                    recipient: payment_request.requester,
+                    sender: user_id,
+                    memo: payment_request.memo.clone(),
+                    reference: None,
+                    quote_id: None,
+                };
+                let created_transaction =
+                    match ledger::apply_transfer(conn, &new_transfer, default_limits).await? {
+                        Ok(created_transaction) => created_transaction,
+                        Err(err) => return Ok(Err(err.into_response())),
+                    };
+
+                payment_request.status = PaymentRequestStatus::Paid;
+                payment_request.transaction_id = Some(created_transaction.id);
+                // Fill in the payer of a payment request which was open to anyone.
+                payment_request.payer = Some(user_id);
+
+                let payment_request: PaymentRequest = diesel::update(
+                    payment_requests::table.find(types::Uuid::from(payment_request.id)),
+                )
+                .set(payment_request)
+                .returning(PaymentRequest::as_returning())
+                .get_result(conn)
+                .await
+                .context("failed to update payment request")?;
+                webhook::enqueue_payment_request_paid(conn, &payment_request).await?;
+                notification::notify_payment_request_paid(conn, &payment_request).await?;
+
+                Ok(Ok((payment_request, created_transaction)))
+            })
+        })
+        .await
+        .map_err(AppError::from)??;
+    publish_transactions(&event_hub, &mut conn, slice::from_ref(&created_transaction)).await;
+
+    Ok(Json(PaymentRequestResponse::new(
+        payment_request,
+        jiff::Timestamp::now(),
+    )))
+}
+
+/// Declines a payment request which the authenticated user has been asked to
+/// pay.
+///
+/// Payment requests which are open to anyone can't be declined, as nobody in
+/// particular was asked to pay them. They stay payable until they expire or the
+/// requester cancels them.
+pub async fn post_payment_request_decline(
+    State(pool): State<DbConnectionPool>,
+    Extension(authenticated_user): Extension<AuthenticatedUser>,
+    Path(PaymentRequestPathParams { payment_request_id }): Path<PaymentRequestPathParams>,
+) -> Result<Json<PaymentRequestResponse>> {
+    resolve_payment_request(
+        pool,
+        payment_request_id,
+        authenticated_user.subject,
+        Resolution::Decline,
+    )
+    .await
+}
+
+/// Cancels a payment request created by the authenticated user.
+pub async fn post_payment_request_cancel(
+    State(pool): State<DbConnectionPool>,
+    Extension(authenticated_user): Extension<AuthenticatedUser>,
+    Path(PaymentRequestPathParams { payment_request_id }): Path<PaymentRequestPathParams>,
+) -> Result<Json<PaymentRequestResponse>> {
+    resolve_payment_request(
+        pool,
+        payment_request_id,
+        authenticated_user.subject,
+        Resolution::Cancel,
+    )
+    .await
+}
+
+/// How a pending payment request is resolved without being paid.
+#[derive(Copy, Clone, Debug)]
+enum Resolution {
+    /// The payer declines to pay it.
+    Decline,
+    /// The requester withdraws it.
+    Cancel,
+}
+
+/// Resolves a pending payment request without paying it.
+///
+/// Only the payer may decline, and only the requester may cancel.
+async fn resolve_payment_request(
+    pool: DbConnectionPool,
+    payment_request_id: Uuid,
+    user_id: Uuid,
+    resolution: Resolution,
+) -> Result<Json<PaymentRequestResponse>> {
+    use crate::models::types;
+    use crate::schema::payment_requests;
+
+    let mut conn = pool
+        .get()
+        .await
+        .context("failed to get database connection")
+        .map_err(AppError::from)?;
+
+    let payment_request = ledger::run_immediate_transaction(&mut conn, |conn| {
+        Box::pin(async move {
+            let mut payment_request =
+                match find_pending_payment_request(conn, payment_request_id, user_id).await? {
+                    Ok(payment_request) => payment_request,
+                    Err(response) => return Ok(Err(response)),
+                };
+
+            let (allowed, status) = match resolution {
+                Resolution::Decline => (
+                    payment_request.payer == Some(user_id),
+                    PaymentRequestStatus::Declined,
+                ),
+                Resolution::Cancel => (
+                    payment_request.requester == user_id,
+                    PaymentRequestStatus::Cancelled,
+                ),
+            };
+            if !allowed {
+                return Ok(Err(permission_denied()));
+            }
+
+            payment_request.status = status;
+
+            let payment_request: PaymentRequest =
+                diesel::update(payment_requests::table.find(types::Uuid::from(payment_request.id)))
+                    .set(payment_request)
+                    .returning(PaymentRequest::as_returning())
+                    .get_result(conn)
+                    .await
+                    .context("failed to update payment request")?;
+
+            Ok(Ok(payment_request))
+        })
+    })
+    .await
+    .map_err(AppError::from)??;
+
+    Ok(Json(PaymentRequestResponse::new(
+        payment_request,
+        jiff::Timestamp::now(),
+    )))
+}
+
+/// Returns when something created at `now` expires: at `requested` if given,
+/// otherwise `default` later.
+///
+/// Returns an `InvalidExpiry` response unless that is in the next `max_days`
+/// days.
+#[allow(
+    clippy::result_large_err,
+    reason = "the error is returned from handlers, whose errors are just as large"
+)]
+pub(crate) fn validate_expiry(
+    now: jiff::Timestamp,
+    requested: Option<jiff::Timestamp>,
+    default: jiff::SignedDuration,
+    max_days: i64,
+) -> Result<jiff::Timestamp> {
+    let invalid_expiry = || {
+        (
+            StatusCode::BAD_REQUEST,
+            Json(json!({
+                "title": "InvalidExpiry",
+                "detail": format!("expiry must be in the next {max_days} days"),
+            })),
+        )
+            .into()
+    };
+
+    let max_expiry = now
+        .checked_add(jiff::SignedDuration::from_hours(
+            max_days.saturating_mul(24),
+        ))
+        .map_err(|_err| invalid_expiry())?;
+    match requested {
+        Some(expires_at) if expires_at <= now || expires_at > max_expiry => Err(invalid_expiry()),
+        Some(expires_at) => Ok(expires_at),
+        None => now.checked_add(default).map_err(|_err| invalid_expiry()),
+    }
+}
+
+/// Finds a payment request which `user_id` may see: one they created, one they
+/// have been asked to pay, or one which is open to anyone.
+async fn find_visible_payment_request(
+    conn: &mut DbConnection,
+    payment_request_id: Uuid,
+    user_id: Uuid,
+) -> anyhow::Result<Option<PaymentRequest>> {
+    use crate::models::types;
+    use crate::schema::payment_requests;
+
+    let payment_request: Option<PaymentRequest> = payment_requests::table
+        .find(types::Uuid::from(payment_request_id))
+        .select(PaymentRequest::as_select())
+        .first(conn)
+        .await
+        .optional()
+        .context("failed to query payment requests")?;
+
+    Ok(payment_request.filter(|payment_request| {
+        payment_request.requester == user_id
+            || payment_request.payer.is_none_or(|payer| payer == user_id)
+    }))
+}
+
+/// Finds a payment request which `user_id` may see and which is still pending.
+///
+/// Marks the payment request as expired if it has expired.
+async fn find_pending_payment_request(
+    conn: &mut DbConnection,
+    payment_request_id: Uuid,
+    user_id: Uuid,
+) -> anyhow::Result<Result<PaymentRequest, Response>> {
+    use crate::models::types;
+    use crate::schema::payment_requests;
+
+    let Some(payment_request) =
+        find_visible_payment_request(conn, payment_request_id, user_id).await?
+    else {
+        debug!(%payment_request_id, "could not find payment request");
+
+        return Ok(Err(payment_request_not_found()));
+    };
+
+    match payment_request.status_at(jiff::Timestamp::now()) {
+        PaymentRequestStatus::Pending => Ok(Ok(payment_request)),
+        PaymentRequestStatus::Expired => {
+            if payment_request.status != PaymentRequestStatus::Expired {
+                diesel::update(payment_requests::table.find(types::Uuid::from(payment_request.id)))
+                    .set(payment_requests::status.eq(PaymentRequestStatus::Expired))
+                    .execute(conn)
+                    .await
+                    .context("failed to update payment request")?;
+            }
+
+            Ok(Err(payment_request_not_pending(
+                PaymentRequestStatus::Expired,
+            )))
+        },
+        status => Ok(Err(payment_request_not_pending(status))),
+    }
+}
+
+fn payment_requests_response(
+    payment_requests: Vec<PaymentRequest>,
+    status: Option<PaymentRequestStatus>,
+) -> GetPaymentRequestsResponse {
+    let now = jiff::Timestamp::now();
+
+    GetPaymentRequestsResponse {
+        payment_requests: payment_requests
+            .into_iter()
+            .map(|payment_request| PaymentRequestResponse::new(payment_request, now))
+            .filter(|payment_request| status.is_none_or(|status| payment_request.status == status))
+            .collect(),
+    }
+}
+
+fn payment_request_not_found() -> Response {
+    (
+        StatusCode::NOT_FOUND,
+        Json(json!({
+            "title": "PaymentRequestNotFound",
+        })),
+    )
+        .into_response()
+}
+
+fn payment_request_not_pending(status: PaymentRequestStatus) -> Response {
+    (
+        StatusCode::CONFLICT,
+        Json(json!({
+            "title": "PaymentRequestNotPending",
+            "detail": format!("payment request is {status}"),
+        })),
+    )
+        .into_response()
+}
@@ -0,0 +1,592 @@
+use std::slice;
+
+use anyhow::Context as _;
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use axum::response::Result;
+use axum::{Extension, Json};
+use axum_extra::extract::WithRejection;
+use bigdecimal::{BigDecimal, Zero as _};
+use diesel::prelude::*;
+#[allow(
+    clippy::unused_trait_names,
+    reason = "error[E0034]: multiple applicable items in scope"
+)]
+use diesel_async::RunQueryDsl;
+use jiff::tz::TimeZone;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tracing::debug;
+use uuid::Uuid;
+
+use crate::currency::{Currency, DEFAULT_CURRENCY};
+use crate::error::{AppError, JsonRejection};
+use crate::event_hub::{EventHub, publish_transactions};
+use crate::handlers::transaction::{PostTransactionResponse, sanitize_memo};
+use crate::ledger::{self, NewPocketMove};
+use crate::middleware::auth::AuthenticatedUser;
+use crate::models::Pocket;
+use crate::models::transaction::TransactionKind;
+use crate::state::{DbConnection, DbConnectionPool};
+
+#[derive(Deserialize)]
+pub struct PocketsPathParams {
+    user_id: Uuid,
+}
+
+#[derive(Deserialize)]
+pub struct PocketPathParams {
+    user_id: Uuid,
+    pocket_id: Uuid,
+}
+
+#[derive(Deserialize)]
+pub struct PostPocketPayload {
+    name: String,
+    /// Defaults to [`DEFAULT_CURRENCY`]. The user must have an account in it.
+    currency: Option<Currency>,
+    #[serde(default, with = "bigdecimal::serde::json_num_option")]
+    target_amount: Option<BigDecimal>,
+    target_date: Option<jiff::civil::Date>,
+}
+
+/// Replaces the name and target of a pocket.
+#[derive(Deserialize)]
+pub struct PutPocketPayload {
+    name: String,
+    #[serde(default, with = "bigdecimal::serde::json_num_option")]
+    target_amount: Option<BigDecimal>,
+    target_date: Option<jiff::civil::Date>,
+}
+
+#[derive(Deserialize)]
+pub struct PostPocketMovePayload {
+    #[serde(with = "bigdecimal::serde::json_num")]
+    amount: BigDecimal,
+}
+
+#[derive(Serialize)]
+pub struct GetPocketsResponse {
+    pockets: Vec<PocketResponse>,
+}
+
+#[derive(Serialize)]
+pub struct PocketResponse {
+    id: Uuid,
+    name: String,
+    currency: Currency,
+    #[serde(with = "bigdecimal::serde::json_num")]
+    balance: BigDecimal,
+    #[serde(with = "bigdecimal::serde::json_num_option")]
+    target_amount: Option<BigDecimal>,
+    target_date: Option<jiff::civil::Date>,
+    created_at: jiff::Timestamp,
+}
+
+#[derive(Serialize)]
+pub struct PocketMoveResponse {
+    transaction: PostTransactionResponse,
+    pocket: PocketResponse,
+}
+
+/// Maximum number of pockets of a user.
+const MAX_POCKETS: i64 = 20;
+
+/// Maximum length of a pocket name, in characters, after sanitization.
+const POCKET_NAME_MAX_CHARS: usize = 64;
+
+/// Returns the pockets of the authenticated user, oldest first.
+pub async fn get_pockets(
+    State(pool): State<DbConnectionPool>,
+    Extension(authenticated_user): Extension<AuthenticatedUser>,
+    Path(PocketsPathParams { user_id }): Path<PocketsPathParams>,
+) -> Result<Json<GetPocketsResponse>> {
+    let mut conn = pool
+        .get()
+        .await
+        .context("failed to get database connection")
+        .map_err(AppError::from)?;
+
+    if authenticated_user.subject != user_id {
+        return Err((
+            StatusCode::FORBIDDEN,
+            Json(json!({
+                "title": "PermissionDenied",
+            })),
+        ))?;
+    }
+
+    let pockets = pocket_responses(&mut conn, user_id)
+        .await
+        .map_err(AppError::from)?;
+
+    Ok(Json(GetPocketsResponse { pockets }))
+}
+
+/// Creates a pocket of the authenticated user, with a balance of zero.
+pub async fn post_pocket(
+    State(pool): State<DbConnectionPool>,
+    Extension(authenticated_user): Extension<AuthenticatedUser>,
+    Path(PocketsPathParams { user_id }): Path<PocketsPathParams>,
+    WithRejection(Json(payload), _): WithRejection<Json<PostPocketPayload>, JsonRejection>,
+) -> Result<(StatusCode, Json<PocketResponse>)> {
+    use crate::models::types;
+    use crate::schema::pockets;
+
+    let mut conn = pool
+        .get()
+        .await
+        .context("failed to get database connection")
+        .map_err(AppError::from)?;
+
+    if authenticated_user.subject != user_id {
+        return Err((
+            StatusCode::FORBIDDEN,
+            Json(json!({
+                "title": "PermissionDenied",
+            })),
+        ))?;
+    }
+
+    let currency = payload.currency.unwrap_or(DEFAULT_CURRENCY);
+    let name = match sanitize_memo(&payload.name) {
+        Ok(Some(name)) if name.chars().count() <= POCKET_NAME_MAX_CHARS => name,
+        _ => {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                Json(json!({
+                    "title": "InvalidName",
+                    "detail": format!(
+                        "name must be between 1 and {POCKET_NAME_MAX_CHARS} characters"
+                    ),
+                })),
+            ))?;
+        },
+    };
+    if payload
+        .target_amount
+        .as_ref()
+        .is_some_and(|amount| *amount <= BigDecimal::zero() || !currency.is_valid_amount(amount))
+    {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(json!({
+                "title": "InvalidTargetAmount",
+            })),
+        ))?;
+    }
+    if payload
+        .target_date
+        .is_some_and(|date| date < jiff::Timestamp::now().to_zoned(TimeZone::UTC).date())
+    {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(json!({
+                "title": "InvalidTargetDate",
+                "detail": "target date must not be in the past",
+            })),
+        ))?;
+    }
+
+    if ledger::find_account(&mut conn, user_id, currency)
+        .await
+        .map_err(AppError::from)?
+        .is_none()
+    {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(json!({
+                "title": "CurrencyMismatch",
+                "detail": format!("you must have a {currency} account"),
+            })),
+        ))?;
+    }
+
+    let pocket_count: i64 = pockets::table
+        .filter(pockets::user_id.eq(types::Uuid::from(user_id)))
+        .filter(pockets::archived_at.is_null())
+        .count()
+        .get_result(&mut conn)
+        .await
+        .context("failed to count pockets")
+        .map_err(AppError::from)?;
+    if pocket_count >= MAX_POCKETS {
+        return Err((
+            StatusCode::CONFLICT,
+            Json(json!({
+                "title": "TooManyPockets",
+                "detail": format!("you may have at most {MAX_POCKETS} pockets"),
+            })),
+        ))?;
+    }
+
+    let pocket: Pocket = diesel::insert_into(pockets::table)
+        .values(Pocket {
+            id: Uuid::now_v7(),
+            user_id,
+            name,
+            currency,
+            balance: BigDecimal::zero(),
+            target_amount: payload.target_amount.map(|amount| amount.normalized()),
+            target_date: payload.target_date,
+            created_at: jiff::Timestamp::now(),
+            archived_at: None,
+        })
+        .returning(Pocket::as_returning())
+        .get_result(&mut conn)
+        .await
+        .context("failed to insert pocket")
+        .map_err(AppError::from)?;
+
+    Ok((StatusCode::CREATED, Json(pocket.into())))
+}
+
+/// Returns a pocket of the authenticated user.
+pub async fn get_pocket(
+    State(pool): State<DbConnectionPool>,
+    Extension(authenticated_user): Extension<AuthenticatedUser>,
+    Path(PocketPathParams { user_id, pocket_id }): Path<PocketPathParams>,
+) -> Result<Json<PocketResponse>> {
+    let mut conn = pool
+        .get()
+        .await
+        .context("failed to get database connection")
+        .map_err(AppError::from)?;
+
+    if authenticated_user.subject != user_id {
+        return Err((
+            StatusCode::FORBIDDEN,
+            Json(json!({
+                "title": "PermissionDenied",
+            })),
+        ))?;
+    }
+
+    let Some(pocket) = find_pocket(&mut conn, user_id, pocket_id)
+        .await
+        .map_err(AppError::from)?
+    else {
+        return Err((
+            StatusCode::NOT_FOUND,
+            Json(json!({
+                "title": "PocketNotFound",
+            })),
+        ))?;
+    };
+
+    Ok(Json(pocket.into()))
+}
+
+/// Renames a pocket of the authenticated user, or changes its target.
+pub async fn put_pocket(
+    State(pool): State<DbConnectionPool>,
+    Extension(authenticated_user): Extension<AuthenticatedUser>,
+    Path(PocketPathParams { user_id, pocket_id }): Path<PocketPathParams>,
+    WithRejection(Json(payload), _): WithRejection<Json<PutPocketPayload>, JsonRejection>,
+) -> Result<Json<PocketResponse>> {
+    use crate::models::types;
+    use crate::schema::pockets;
+
+    let mut conn = pool
+        .get()
+        .await
+        .context("failed to get database connection")
+        .map_err(AppError::from)?;
+
+    if authenticated_user.subject != user_id {
+        return Err((
+            StatusCode::FORBIDDEN,
+            Json(json!({
+                "title": "PermissionDenied",
+            })),
+        ))?;
+    }
+
+    let Some(pocket) = find_pocket(&mut conn, user_id, pocket_id)
+        .await
+        .map_err(AppError::from)?
+    else {
+        return Err((
+            StatusCode::NOT_FOUND,
+            Json(json!({
+                "title": "PocketNotFound",
+            })),
+        ))?;
+    };
+
+    let name = match sanitize_memo(&payload.name) {
+        Ok(Some(name)) if name.chars().count() <= POCKET_NAME_MAX_CHARS => name,
+        _ => {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                Json(json!({
+                    "title": "InvalidName",
+                    "detail": format!(
+                        "name must be between 1 and {POCKET_NAME_MAX_CHARS} characters"
+                    ),
+                })),
+            ))?;
+        },
+    };
+    if payload.target_amount.as_ref().is_some_and(|amount| {
+        *amount <= BigDecimal::zero() || !pocket.currency.is_valid_amount(amount)
+    }) {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(json!({
+                "title": "InvalidTargetAmount",
+            })),
+        ))?;
+    }
+    if payload
+        .target_date
+        .is_some_and(|date| date < jiff::Timestamp::now().to_zoned(TimeZone::UTC).date())
+    {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(json!({
+                "title": "InvalidTargetDate",
+                "detail": "target date must not be in the past",
+            })),
+        ))?;
+    }
+
+    // Only the name and target are updated, so that a concurrent move's
+    // balance is not overwritten.
+    let pocket: Pocket = diesel::update(pockets::table.find(types::Uuid::from(pocket.id)))
+        .set((
+            pockets::name.eq(name),
+            pockets::target_amount.eq(payload
+                .target_amount
+                .map(|amount| types::BigDecimal::from(amount.normalized()))),
+            pockets::target_date.eq(jiff_diesel::NullableDate::from(payload.target_date)),
+        ))
+        .returning(Pocket::as_returning())
+        .get_result(&mut conn)
+        .await
+        .context("failed to update pocket")
+        .map_err(AppError::from)?;
+
+    Ok(Json(pocket.into()))
+}
+
+/// Deletes a pocket of the authenticated user, which must be empty.
+///
+/// The pocket is archived rather than removed, so that moves into and out of
+/// it keep referring to it in the history.
+pub async fn delete_pocket(
+    State(pool): State<DbConnectionPool>,
+    Extension(authenticated_user): Extension<AuthenticatedUser>,
+    Path(PocketPathParams { user_id, pocket_id }): Path<PocketPathParams>,
+) -> Result<StatusCode> {
+    use crate::models::types;
+    use crate::schema::pockets;
+
+    let mut conn = pool
+        .get()
+        .await
+        .context("failed to get database connection")
+        .map_err(AppError::from)?;
+
+    if authenticated_user.subject != user_id {
+        return Err((
+            StatusCode::FORBIDDEN,
+            Json(json!({
+                "title": "PermissionDenied",
+            })),
+        ))?;
+    }
+
+    let Some(pocket) = find_pocket(&mut conn, user_id, pocket_id)
+        .await
+        .map_err(AppError::from)?
+    else {
+        return Err((
+            StatusCode::NOT_FOUND,
+            Json(json!({
+                "title": "PocketNotFound",
+            })),
+        ))?;
+    };
+    if !pocket.balance.is_zero() {
+        return Err((
+            StatusCode::CONFLICT,
+            Json(json!({
+                "title": "PocketNotEmpty",
+                "detail": "move the balance out of the pocket before deleting it",
+            })),
+        ))?;
+    }
+
+    let deleted = ledger::run_immediate_transaction(&mut conn, |conn| {
+        Box::pin(async move {
+            // The balance is checked again, in case money was moved in since.
+            let pocket = find_pocket(conn, user_id, pocket.id)
+                .await?
+                .context("could not find pocket")?;
+            if !pocket.balance.is_zero() {
+                return Ok(false);
+            }
+
+            diesel::update(pockets::table.find(types::Uuid::from(pocket.id)))
+                .set(pockets::archived_at.eq(types::Timestamp::from(jiff::Timestamp::now())))
+                .execute(conn)
+                .await
+                .context("failed to archive pocket")?;
+
+            Ok(true)
+        })
+    })
+    .await
+    .map_err(AppError::from)?;
+    if !deleted {
+        debug!(%pocket_id, "pocket was no longer empty");
+
+        return Err((
+            StatusCode::CONFLICT,
+            Json(json!({
+                "title": "PocketNotEmpty",
+                "detail": "move the balance out of the pocket before deleting it",
+            })),
+        ))?;
+    }
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Moves money from the account of the authenticated user into a pocket.
+pub async fn post_pocket_deposit(
+    State(pool): State<DbConnectionPool>,
+    State(event_hub): State<EventHub>,
+    Extension(authenticated_user): Extension<AuthenticatedUser>,
+    Path(PocketPathParams { user_id, pocket_id }): Path<PocketPathParams>,
+    WithRejection(Json(payload), _): WithRejection<Json<PostPocketMovePayload>, JsonRejection>,
+) -> Result<(StatusCode, Json<PocketMoveResponse>)> {
+    post_pocket_move(
+        pool,
+        event_hub,
+        authenticated_user,
+        user_id,
+        pocket_id,
+        payload,
+        TransactionKind::PocketDeposit,
+    )
+    .await
+}
+
+/// Moves money from a pocket back into the account of the authenticated user.
+pub async fn post_pocket_withdrawal(
+    State(pool): State<DbConnectionPool>,
+    State(event_hub): State<EventHub>,
+    Extension(authenticated_user): Extension<AuthenticatedUser>,
+    Path(PocketPathParams { user_id, pocket_id }): Path<PocketPathParams>,
+    WithRejection(Json(payload), _): WithRejection<Json<PostPocketMovePayload>, JsonRejection>,
+) -> Result<(StatusCode, Json<PocketMoveResponse>)> {
+    post_pocket_move(
+        pool,
+        event_hub,
+        authenticated_user,
+        user_id,
+        pocket_id,
+        payload,
+        TransactionKind::PocketWithdrawal,
+    )
+    .await
+}
+
+async fn post_pocket_move(
+    pool: DbConnectionPool,
+    event_hub: EventHub,
+    authenticated_user: AuthenticatedUser,
+    user_id: Uuid,
+    pocket_id: Uuid,
+    payload: PostPocketMovePayload,
+    kind: TransactionKind,
+) -> Result<(StatusCode, Json<PocketMoveResponse>)> {
+    let mut conn = pool
+        .get()
+        .await
+        .context("failed to get database connection")
+        .map_err(AppError::from)?;
+
+    if authenticated_user.subject != user_id {
+        return Err((
+            StatusCode::FORBIDDEN,
+            Json(json!({
+                "title": "PermissionDenied",
+            })),
+        ))?;
+    }
+
+    let pocket_move = NewPocketMove {
+        user_id,
+        pocket_id,
+        amount: payload.amount,
+        kind,
+    };
+    let (transaction, pocket) = ledger::move_pocket(&mut conn, &pocket_move)
+        .await
+        .map_err(AppError::from)??;
+    publish_transactions(&event_hub, &mut conn, slice::from_ref(&transaction)).await;
+
+    Ok((
+        StatusCode::CREATED,
+        Json(PocketMoveResponse {
+            transaction: transaction.into(),
+            pocket: pocket.into(),
+        }),
+    ))
+}
+
+impl From<Pocket> for PocketResponse {
+    fn from(pocket: Pocket) -> Self {
+        Self {
+            id: pocket.id,
+            name: pocket.name,
+            currency: pocket.currency,
+            balance: pocket.balance,
+            target_amount: pocket.target_amount,
+            target_date: pocket.target_date,
+            created_at: pocket.created_at,
+        }
+    }
+}
+
+/// Returns the pockets of `user_id`, oldest first.
+pub async fn pocket_responses(
+    conn: &mut DbConnection,
+    user_id: Uuid,
+) -> anyhow::Result<Vec<PocketResponse>> {
+    use crate::models::types;
+    use crate::schema::pockets;
+
+    let pockets: Vec<Pocket> = pockets::table
+        .filter(pockets::user_id.eq(types::Uuid::from(user_id)))
+        .filter(pockets::archived_at.is_null())
+        .order((pockets::created_at.asc(), pockets::id.asc()))
+        .select(Pocket::as_select())
+        .load(conn)
+        .await
+        .context("failed to query pockets")?;
+
+    Ok(pockets.into_iter().map(Into::into).collect())
+}
+
+async fn find_pocket(
+    conn: &mut DbConnection,
+    user_id: Uuid,
+    pocket_id: Uuid,
+) -> anyhow::Result<Option<Pocket>> {
+    use crate::models::types;
+    use crate::schema::pockets;
+
+    pockets::table
+        .find(types::Uuid::from(pocket_id))
+        .filter(pockets::user_id.eq(types::Uuid::from(user_id)))
+        .filter(pockets::archived_at.is_null())
+        .select(Pocket::as_select())
+        .first(conn)
+        .await
+        .optional()
+        .context("failed to query pockets")
+}
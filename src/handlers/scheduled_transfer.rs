@@ -0,0 +1,483 @@
+use anyhow::Context as _;
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use axum::response::{IntoResponse as _, Response, Result};
+use axum::{Extension, Json};
+use axum_extra::extract::WithRejection;
+use bigdecimal::{BigDecimal, Zero as _};
+use diesel::prelude::*;
+#[allow(
+    clippy::unused_trait_names,
+    reason = "error[E0034]: multiple applicable items in scope"
+)]
+use diesel_async::RunQueryDsl;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tracing::debug;
+use uuid::Uuid;
+
+use crate::currency::{Currency, DEFAULT_CURRENCY};
+use crate::error::{AppError, JsonRejection};
+use crate::handlers::transaction::{validate_memo, validate_reference};
+use crate::ledger::{self, TransferError};
+use crate::middleware::auth::AuthenticatedUser;
+use crate::models::scheduled_transfer::{
+    NewScheduledTransfer, Recurrence, ScheduledTransferAttempt, ScheduledTransferAttemptOutcome,
+    ScheduledTransferStatus,
+};
+use crate::models::user::UserRef;
+use crate::models::{ScheduledTransfer, User};
+use crate::state::{DbConnection, DbConnectionPool};
+
+#[derive(Deserialize)]
+pub struct ScheduledTransferPathParams {
+    scheduled_transfer_id: Uuid,
+}
+
+#[derive(Deserialize)]
+pub struct PostScheduledTransferPayload {
+    #[serde(with = "bigdecimal::serde::json_num")]
+    amount: BigDecimal,
+    /// Defaults to [`DEFAULT_CURRENCY`].
+    currency: Option<Currency>,
+    recipient: UserRef,
+    memo: Option<String>,
+    reference: Option<String>,
+    /// When the first, or only, transfer is due.
+    starts_at: jiff::Timestamp,
+    /// How often the transfer repeats, e.g. `FREQ=MONTHLY`. If omitted, the
+    /// transfer runs once.
+    recurrence: Option<Recurrence>,
+}
+
+#[derive(Serialize)]
+pub struct GetScheduledTransfersResponse {
+    scheduled_transfers: Vec<ScheduledTransferResponse>,
+}
+
+#[derive(Serialize)]
+pub struct ScheduledTransferResponse {
+    id: Uuid,
+    #[serde(with = "bigdecimal::serde::json_num")]
+    amount: BigDecimal,
+    currency: Currency,
+    recipient: Uuid,
+    memo: Option<String>,
+    reference: Option<String>,
+    recurrence: Option<Recurrence>,
+    starts_at: jiff::Timestamp,
+    /// When the next transfer, or the next retry of one, is due, if any.
+    next_run_at: Option<jiff::Timestamp>,
+    status: ScheduledTransferStatus,
+    created_at: jiff::Timestamp,
+}
+
+#[derive(Serialize)]
+pub struct GetScheduledTransferAttemptsResponse {
+    attempts: Vec<ScheduledTransferAttemptResponse>,
+}
+
+#[derive(Serialize)]
+pub struct ScheduledTransferAttemptResponse {
+    id: Uuid,
+    scheduled_for: jiff::Timestamp,
+    attempted_at: jiff::Timestamp,
+    outcome: ScheduledTransferAttemptOutcome,
+    error: Option<String>,
+    transaction_id: Option<Uuid>,
+}
+
+impl From<ScheduledTransfer> for ScheduledTransferResponse {
+    fn from(scheduled_transfer: ScheduledTransfer) -> Self {
+        let next_run_at = matches!(
+            scheduled_transfer.status,
+            ScheduledTransferStatus::Active | ScheduledTransferStatus::Paused
+        )
+        .then_some(scheduled_transfer.next_run_at);
+
+        Self {
+            id: scheduled_transfer.id,
+            amount: scheduled_transfer.amount,
+            currency: scheduled_transfer.currency,
+            recipient: scheduled_transfer.recipient,
+            memo: scheduled_transfer.memo,
+            reference: scheduled_transfer.reference,
+            recurrence: scheduled_transfer.recurrence,
+            starts_at: scheduled_transfer.starts_at,
+            next_run_at,
+            status: scheduled_transfer.status,
+            created_at: scheduled_transfer.created_at,
+        }
+    }
+}
+
+impl From<ScheduledTransferAttempt> for ScheduledTransferAttemptResponse {
+    fn from(attempt: ScheduledTransferAttempt) -> Self {
+        Self {
+            id: attempt.id,
+            scheduled_for: attempt.scheduled_for,
+            attempted_at: attempt.attempted_at,
+            outcome: attempt.outcome,
+            error: attempt.error,
+            transaction_id: attempt.transaction_id,
+        }
+    }
+}
+
+/// Schedules a transfer from the authenticated user, either once or repeating.
+///
+/// Scheduled transfers are run by [`crate::scheduler`], through the same
+/// ledger logic as `POST /transactions`.
+pub async fn post_scheduled_transfer(
+    State(pool): State<DbConnectionPool>,
+    Extension(authenticated_user): Extension<AuthenticatedUser>,
+    WithRejection(Json(payload), _): WithRejection<
+        Json<PostScheduledTransferPayload>,
+        JsonRejection,
+    >,
+) -> Result<(StatusCode, Json<ScheduledTransferResponse>)> {
+    use crate::schema::scheduled_transfers;
+
+    let mut conn = pool
+        .get()
+        .await
+        .context("failed to get database connection")
+        .map_err(AppError::from)?;
+
+    let currency = payload.currency.unwrap_or(DEFAULT_CURRENCY);
+    if payload.amount <= BigDecimal::zero() {
+        return Err(TransferError::InvalidAmount)?;
+    }
+    if !currency.is_valid_amount(&payload.amount) {
+        return Err(TransferError::AmountTooPrecise(currency))?;
+    }
+
+    let memo = validate_memo(payload.memo.as_deref())?;
+
+    if let Some(Err(detail)) = payload.reference.as_deref().map(validate_reference) {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(json!({
+                "title": "InvalidReference",
+                "detail": detail,
+            })),
+        ))?;
+    }
+
+    let now = jiff::Timestamp::now();
+    if payload.starts_at <= now {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(json!({
+                "title": "InvalidStartTime",
+                "detail": "start time must be in the future",
+            })),
+        ))?;
+    }
+
+    let recipient: User = match payload
+        .recipient
+        .query()
+        .select(User::as_select())
+        .first(&mut conn)
+        .await
+    {
+        Ok(recipient) => recipient,
+        Err(diesel::NotFound) => {
+            debug!(?payload.recipient, "could not find recipient");
+
+            return Err(TransferError::InvalidRecipient)?;
+        },
+        Err(err) => {
+            return Err(err)
+                .context("failed to query users")
+                .map_err(AppError::from)?;
+        },
+    };
+    if recipient.id == authenticated_user.subject {
+        return Err(TransferError::SelfTransfer)?;
+    }
+
+    let new_scheduled_transfer = NewScheduledTransfer {
+        id: Uuid::now_v7(),
+        sender: authenticated_user.subject,
+        recipient: recipient.id,
+        amount: payload.amount,
+        currency,
+        memo,
+        reference: payload.reference,
+        recurrence: payload.recurrence,
+        starts_at: payload.starts_at,
+        occurrence: 0,
+        next_run_at: payload.starts_at,
+        retries: 0,
+        status: ScheduledTransferStatus::Active,
+        created_at: now,
+    };
+
+    let created_scheduled_transfer: ScheduledTransfer =
+        diesel::insert_into(scheduled_transfers::table)
+            .values(new_scheduled_transfer)
+            .returning(ScheduledTransfer::as_returning())
+            .get_result(&mut conn)
+            .await
+            .context("failed to insert scheduled transfer")
+            .map_err(AppError::from)?;
+
+    Ok((StatusCode::CREATED, Json(created_scheduled_transfer.into())))
+}
+
+/// Lists the scheduled transfers of the authenticated user.
+pub async fn get_scheduled_transfers(
+    State(pool): State<DbConnectionPool>,
+    Extension(authenticated_user): Extension<AuthenticatedUser>,
+) -> Result<Json<GetScheduledTransfersResponse>> {
+    use crate::models::types;
+    use crate::schema::scheduled_transfers;
+
+    let mut conn = pool
+        .get()
+        .await
+        .context("failed to get database connection")
+        .map_err(AppError::from)?;
+
+    let scheduled_transfers: Vec<ScheduledTransfer> = scheduled_transfers::table
+        .filter(scheduled_transfers::sender.eq(types::Uuid::from(authenticated_user.subject)))
+        .select(ScheduledTransfer::as_select())
+        .order(scheduled_transfers::id.desc())
+        .load(&mut conn)
+        .await
+        .context("failed to query scheduled transfers")
+        .map_err(AppError::from)?;
+
+    Ok(Json(GetScheduledTransfersResponse {
+        scheduled_transfers: scheduled_transfers.into_iter().map(Into::into).collect(),
+    }))
+}
+
+pub async fn get_scheduled_transfer(
+    State(pool): State<DbConnectionPool>,
+    Extension(authenticated_user): Extension<AuthenticatedUser>,
+    Path(ScheduledTransferPathParams {
+        scheduled_transfer_id,
+    }): Path<ScheduledTransferPathParams>,
+) -> Result<Json<ScheduledTransferResponse>> {
+    let mut conn = pool
+        .get()
+        .await
+        .context("failed to get database connection")
+        .map_err(AppError::from)?;
+
+    let Some(scheduled_transfer) =
+        find_scheduled_transfer(&mut conn, scheduled_transfer_id, authenticated_user.subject)
+            .await
+            .map_err(AppError::from)?
+    else {
+        debug!(%scheduled_transfer_id, "could not find scheduled transfer");
+
+        return Err(scheduled_transfer_not_found())?;
+    };
+
+    Ok(Json(scheduled_transfer.into()))
+}
+
+/// Lists every attempt at running a scheduled transfer, most recent first,
+/// including failed ones.
+pub async fn get_scheduled_transfer_attempts(
+    State(pool): State<DbConnectionPool>,
+    Extension(authenticated_user): Extension<AuthenticatedUser>,
+    Path(ScheduledTransferPathParams {
+        scheduled_transfer_id,
+    }): Path<ScheduledTransferPathParams>,
+) -> Result<Json<GetScheduledTransferAttemptsResponse>> {
+    use crate::models::types;
+    use crate::schema::scheduled_transfer_attempts;
+
+    let mut conn = pool
+        .get()
+        .await
+        .context("failed to get database connection")
+        .map_err(AppError::from)?;
+
+    if find_scheduled_transfer(&mut conn, scheduled_transfer_id, authenticated_user.subject)
+        .await
+        .map_err(AppError::from)?
+        .is_none()
+    {
+        debug!(%scheduled_transfer_id, "could not find scheduled transfer");
+
+        return Err(scheduled_transfer_not_found())?;
+    }
+
+    let attempts: Vec<ScheduledTransferAttempt> = scheduled_transfer_attempts::table
+        .filter(
+            scheduled_transfer_attempts::scheduled_transfer_id
+                .eq(types::Uuid::from(scheduled_transfer_id)),
+        )
+        .select(ScheduledTransferAttempt::as_select())
+        .order(scheduled_transfer_attempts::id.desc())
+        .load(&mut conn)
+        .await
+        .context("failed to query scheduled transfer attempts")
+        .map_err(AppError::from)?;
+
+    Ok(Json(GetScheduledTransferAttemptsResponse {
+        attempts: attempts.into_iter().map(Into::into).collect(),
+    }))
+}
+
+/// Pauses an active scheduled transfer.
+pub async fn post_scheduled_transfer_pause(
+    State(pool): State<DbConnectionPool>,
+    Extension(authenticated_user): Extension<AuthenticatedUser>,
+    Path(ScheduledTransferPathParams {
+        scheduled_transfer_id,
+    }): Path<ScheduledTransferPathParams>,
+) -> Result<Json<ScheduledTransferResponse>> {
+    update_scheduled_transfer_status(
+        pool,
+        scheduled_transfer_id,
+        authenticated_user.subject,
+        &[ScheduledTransferStatus::Active],
+        ScheduledTransferStatus::Paused,
+    )
+    .await
+}
+
+/// Resumes a paused scheduled transfer.
+///
+/// If a transfer fell due while paused, it runs once right away. Any others
+/// that fell due are skipped.
+pub async fn post_scheduled_transfer_resume(
+    State(pool): State<DbConnectionPool>,
+    Extension(authenticated_user): Extension<AuthenticatedUser>,
+    Path(ScheduledTransferPathParams {
+        scheduled_transfer_id,
+    }): Path<ScheduledTransferPathParams>,
+) -> Result<Json<ScheduledTransferResponse>> {
+    update_scheduled_transfer_status(
+        pool,
+        scheduled_transfer_id,
+        authenticated_user.subject,
+        &[ScheduledTransferStatus::Paused],
+        ScheduledTransferStatus::Active,
+    )
+    .await
+}
+
+/// Cancels a scheduled transfer which is active or paused. This cannot be
+/// undone.
+pub async fn post_scheduled_transfer_cancel(
+    State(pool): State<DbConnectionPool>,
+    Extension(authenticated_user): Extension<AuthenticatedUser>,
+    Path(ScheduledTransferPathParams {
+        scheduled_transfer_id,
+    }): Path<ScheduledTransferPathParams>,
+) -> Result<Json<ScheduledTransferResponse>> {
+    update_scheduled_transfer_status(
+        pool,
+        scheduled_transfer_id,
+        authenticated_user.subject,
+        &[
+            ScheduledTransferStatus::Active,
+            ScheduledTransferStatus::Paused,
+        ],
+        ScheduledTransferStatus::Cancelled,
+    )
+    .await
+}
+
+/// Moves a scheduled transfer of `user_id` from one of the `from` statuses to
+/// the `to` status.
+///
+/// This runs in a `BEGIN IMMEDIATE` transaction, so that it does not overwrite
+/// changes made by the scheduler while it runs the scheduled transfer.
+async fn update_scheduled_transfer_status(
+    pool: DbConnectionPool,
+    scheduled_transfer_id: Uuid,
+    user_id: Uuid,
+    from: &[ScheduledTransferStatus],
+    to: ScheduledTransferStatus,
+) -> Result<Json<ScheduledTransferResponse>> {
+    use crate::models::types;
+    use crate::schema::scheduled_transfers;
+
+    let mut conn = pool
+        .get()
+        .await
+        .context("failed to get database connection")
+        .map_err(AppError::from)?;
+
+    let scheduled_transfer = ledger::run_immediate_transaction(&mut conn, |conn| {
+        Box::pin(async move {
+            let Some(mut scheduled_transfer) =
+                find_scheduled_transfer(conn, scheduled_transfer_id, user_id).await?
+            else {
+                debug!(%scheduled_transfer_id, "could not find scheduled transfer");
+
+                return Ok(Err(scheduled_transfer_not_found()));
+            };
+
+            if !from.contains(&scheduled_transfer.status) {
+                return Ok(Err(scheduled_transfer_conflict(scheduled_transfer.status)));
+            }
+
+            scheduled_transfer.status = to;
+
+            let scheduled_transfer: ScheduledTransfer = diesel::update(
+                scheduled_transfers::table.find(types::Uuid::from(scheduled_transfer.id)),
+            )
+            .set(scheduled_transfer)
+            .returning(ScheduledTransfer::as_returning())
+            .get_result(conn)
+            .await
+            .context("failed to update scheduled transfer")?;
+
+            Ok(Ok(scheduled_transfer))
+        })
+    })
+    .await
+    .map_err(AppError::from)??;
+
+    Ok(Json(scheduled_transfer.into()))
+}
+
+/// Finds a scheduled transfer which `user_id` is the sender of.
+async fn find_scheduled_transfer(
+    conn: &mut DbConnection,
+    scheduled_transfer_id: Uuid,
+    user_id: Uuid,
+) -> anyhow::Result<Option<ScheduledTransfer>> {
+    use crate::models::types;
+    use crate::schema::scheduled_transfers;
+
+    scheduled_transfers::table
+        .find(types::Uuid::from(scheduled_transfer_id))
+        .filter(scheduled_transfers::sender.eq(types::Uuid::from(user_id)))
+        .select(ScheduledTransfer::as_select())
+        .first(conn)
+        .await
+        .optional()
+        .context("failed to query scheduled transfers")
+}
+
+fn scheduled_transfer_not_found() -> Response {
+    (
+        StatusCode::NOT_FOUND,
+        Json(json!({
+            "title": "ScheduledTransferNotFound",
+        })),
+    )
+        .into_response()
+}
+
+fn scheduled_transfer_conflict(status: ScheduledTransferStatus) -> Response {
+    (
+        StatusCode::CONFLICT,
+        Json(json!({
+            "title": "InvalidScheduledTransferStatus",
+            "detail": format!("scheduled transfer is {status}"),
+        })),
+    )
+        .into_response()
+}
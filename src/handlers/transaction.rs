@@ -1,12 +1,13 @@
+use std::slice;
+
 use anyhow::Context as _;
-use axum::extract::State;
-use axum::http::StatusCode;
+use axum::extract::{Path, Query, State};
+use axum::http::{HeaderMap, HeaderValue, StatusCode};
 use axum::response::Result;
 use axum::{Extension, Json};
 use axum_extra::extract::WithRejection;
-use bigdecimal::BigDecimal;
+use bigdecimal::{BigDecimal, Zero as _};
 use diesel::prelude::*;
-use diesel_async::AsyncConnection as _;
 #[allow(
     clippy::unused_trait_names,
     reason = "error[E0034]: multiple applicable items in scope"
@@ -17,18 +18,103 @@ use serde_json::json;
 use tracing::debug;
 use uuid::Uuid;
 
+use crate::currency::{Currency, DEFAULT_CURRENCY};
 use crate::error::{AppError, JsonRejection};
+use crate::event_hub::{EventHub, publish_transactions};
+use crate::fees;
+use crate::handlers::user::check_username_lookup;
+use crate::ledger::{
+    self, BatchError, NewBatchTransfer, NewRefund, NewTransfer, NewTransferBatch, TransferError,
+};
+use crate::limits::TransferLimits;
 use crate::middleware::auth::AuthenticatedUser;
-use crate::models::transaction::NewTransaction;
-use crate::models::{Transaction, User};
-use crate::state::DbConnectionPool;
+use crate::models::transaction::TransactionKind;
+use crate::models::user::UserRef;
+use crate::models::{Transaction, TransferBatch, User};
+use crate::state::{DbConnectionPool, UsernameLookupRateLimiter};
 
 #[derive(Deserialize)]
-pub struct PostTranscactionPayload {
+pub struct GetTransactionPathParams {
+    transaction_id: Uuid,
+}
+
+#[derive(Serialize)]
+pub struct GetTransactionResponse {
+    id: Uuid,
     #[serde(with = "bigdecimal::serde::json_num")]
     amount: BigDecimal,
+    currency: Currency,
     recipient: Uuid,
     sender: Uuid,
+    timestamp: jiff::Timestamp,
+    memo: Option<String>,
+    reference: Option<String>,
+    kind: TransactionKind,
+    original_transaction_id: Option<Uuid>,
+    flagged: bool,
+    conversion: Option<ConversionResponse>,
+    /// The fee charged to the sender on top of the amount.
+    #[serde(with = "bigdecimal::serde::json_num_option")]
+    fee: Option<BigDecimal>,
+    /// The pocket of the user, for pocket deposits and withdrawals.
+    pocket_id: Option<Uuid>,
+    counterparty: CounterpartyResponse,
+    /// What is left to refund, for transfers only.
+    #[serde(with = "bigdecimal::serde::json_num_option")]
+    refundable_amount: Option<BigDecimal>,
+    /// Refunds and reversals of this transaction.
+    refunds: Vec<LinkedTransactionResponse>,
+}
+
+#[derive(Serialize)]
+pub struct LinkedTransactionResponse {
+    id: Uuid,
+    #[serde(with = "bigdecimal::serde::json_num")]
+    amount: BigDecimal,
+    currency: Currency,
+    timestamp: jiff::Timestamp,
+    kind: TransactionKind,
+    flagged: bool,
+}
+
+/// What the recipient of a converted transaction was credited, and at which
+/// rate.
+#[derive(Serialize)]
+pub struct ConversionResponse {
+    #[serde(with = "bigdecimal::serde::json_num")]
+    amount: BigDecimal,
+    currency: Currency,
+    #[serde(with = "bigdecimal::serde::json_num")]
+    rate: BigDecimal,
+    #[serde(with = "bigdecimal::serde::json_num")]
+    spread: BigDecimal,
+}
+
+#[derive(Serialize)]
+pub struct CounterpartyResponse {
+    id: Uuid,
+    username: String,
+}
+
+#[derive(Deserialize)]
+pub struct PostTranscactionPayload {
+    #[serde(with = "bigdecimal::serde::json_num")]
+    amount: BigDecimal,
+    /// Defaults to [`DEFAULT_CURRENCY`].
+    currency: Option<Currency>,
+    recipient: UserRef,
+    /// The sender is the authenticated user.
+    ///
+    /// # Deprecated
+    ///
+    /// Payloads which include the sender are still accepted for backwards
+    /// compatibility, as long as the sender is the authenticated user.
+    sender: Option<Uuid>,
+    memo: Option<String>,
+    reference: Option<String>,
+    /// A quote to convert the amount at, for recipients without an account in
+    /// the currency. See `POST /exchange-quotes`.
+    quote_id: Option<Uuid>,
 }
 
 #[derive(Serialize)]
@@ -36,16 +122,105 @@ pub struct PostTransactionResponse {
     id: Uuid,
     #[serde(with = "bigdecimal::serde::json_num")]
     amount: BigDecimal,
+    currency: Currency,
     recipient: Uuid,
     sender: Uuid,
     timestamp: jiff::Timestamp,
+    memo: Option<String>,
+    reference: Option<String>,
+    kind: TransactionKind,
+    original_transaction_id: Option<Uuid>,
+    flagged: bool,
+    conversion: Option<ConversionResponse>,
+    /// The fee charged to the sender on top of the amount.
+    #[serde(with = "bigdecimal::serde::json_num_option")]
+    fee: Option<BigDecimal>,
+    /// The pocket of the user, for pocket deposits and withdrawals.
+    pocket_id: Option<Uuid>,
 }
 
-pub async fn post_transaction(
+#[derive(Deserialize)]
+pub struct GetTransferBatchPathParams {
+    batch_id: Uuid,
+}
+
+#[derive(Deserialize)]
+pub struct PostTransferBatchPayload {
+    /// The currency of all transfers. Defaults to [`DEFAULT_CURRENCY`].
+    currency: Option<Currency>,
+    transfers: Vec<PostBatchTransferPayload>,
+}
+
+#[derive(Deserialize)]
+pub struct PostBatchTransferPayload {
+    #[serde(with = "bigdecimal::serde::json_num")]
+    amount: BigDecimal,
+    recipient: UserRef,
+    memo: Option<String>,
+    reference: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct TransferBatchResponse {
+    id: Uuid,
+    sender: Uuid,
+    currency: Currency,
+    created_at: jiff::Timestamp,
+    /// The transfers of the batch, in the order they were requested in.
+    transactions: Vec<PostTransactionResponse>,
+}
+
+#[derive(Deserialize)]
+pub struct GetTransactionFeeQueryParams {
+    amount: BigDecimal,
+    /// Defaults to [`DEFAULT_CURRENCY`].
+    currency: Option<Currency>,
+}
+
+#[derive(Serialize)]
+pub struct GetTransactionFeeResponse {
+    #[serde(with = "bigdecimal::serde::json_num")]
+    amount: BigDecimal,
+    currency: Currency,
+    #[serde(with = "bigdecimal::serde::json_num")]
+    fee: BigDecimal,
+    /// What the sender would be debited, i.e. the amount plus the fee.
+    #[serde(with = "bigdecimal::serde::json_num")]
+    total: BigDecimal,
+}
+
+#[derive(Deserialize)]
+pub struct PostRefundPayload {
+    /// How much to refund. If omitted, everything not yet refunded is.
+    #[serde(default, with = "bigdecimal::serde::json_num_option")]
+    amount: Option<BigDecimal>,
+    memo: Option<String>,
+}
+
+/// Maximum length of a memo, in characters, after sanitization.
+pub(crate) const MEMO_MAX_CHARS: usize = 140;
+
+/// Maximum length of a reference, in bytes.
+pub(crate) const REFERENCE_MAX_LEN: usize = 64;
+
+/// When payloads including the sender were deprecated, as an
+/// [RFC 9651](https://datatracker.ietf.org/doc/html/rfc9651#section-3.3.7) date.
+///
+/// See [RFC 9745](https://datatracker.ietf.org/doc/html/rfc9745)
+const SENDER_IN_PAYLOAD_DEPRECATION: &str = "@1792368000";
+
+/// Unicode bidirectional formatting characters, which can make text render
+/// differently from how it is stored.
+const BIDI_FORMATTING_CHARS: &[char] = &[
+    '\u{200e}', '\u{200f}', '\u{202a}', '\u{202b}', '\u{202c}', '\u{202d}', '\u{202e}', '\u{2066}',
+    '\u{2067}', '\u{2068}', '\u{2069}',
+];
+
+pub async fn get_transaction(
     State(pool): State<DbConnectionPool>,
     Extension(authenticated_user): Extension<AuthenticatedUser>,
-    WithRejection(Json(payload), _): WithRejection<Json<PostTranscactionPayload>, JsonRejection>,
-) -> Result<Json<PostTransactionResponse>> {
+    Path(GetTransactionPathParams { transaction_id }): Path<GetTransactionPathParams>,
+) -> Result<Json<GetTransactionResponse>> {
     use crate::models::types;
     use crate::schema::{transactions, users};
 
@@ -55,100 +230,551 @@ pub async fn post_transaction(
         .context("failed to get database connection")
         .map_err(AppError::from)?;
 
-    if authenticated_user.subject != payload.sender {
+    // Only the sender and the recipient may see a transaction.
+    //
+    // # Security
+    //
+    // Respond with "not found" to anyone else, so as not to reveal whether the
+    // transaction exists or not.
+    let transaction: Transaction = match transactions::table
+        .find(types::Uuid::from(transaction_id))
+        .filter(
+            transactions::recipient
+                .eq(types::Uuid::from(authenticated_user.subject))
+                .or(transactions::sender.eq(types::Uuid::from(authenticated_user.subject))),
+        )
+        .select(Transaction::as_select())
+        .first(&mut conn)
+        .await
+    {
+        Ok(transaction) => transaction,
+        Err(diesel::NotFound) => {
+            debug!(%transaction_id, "could not find transaction");
+
+            return Err((
+                StatusCode::NOT_FOUND,
+                Json(json!({
+                    "title": "TransactionNotFound",
+                })),
+            ))?;
+        },
+        Err(err) => {
+            return Err(err)
+                .context("failed to query transactions")
+                .map_err(AppError::from)?;
+        },
+    };
+
+    let counterparty_id = if transaction.sender == authenticated_user.subject {
+        transaction.recipient
+    } else {
+        transaction.sender
+    };
+    let counterparty: User = users::table
+        .find(types::Uuid::from(counterparty_id))
+        .select(User::as_select())
+        .first(&mut conn)
+        .await
+        .context("could not find user")
+        .map_err(AppError::from)?;
+
+    let refunds: Vec<Transaction> = transactions::table
+        .filter(transactions::original_transaction_id.eq(types::Uuid::from(transaction.id)))
+        .filter(transactions::kind.eq_any([TransactionKind::Refund, TransactionKind::Reversal]))
+        .select(Transaction::as_select())
+        .order(transactions::id.asc())
+        .load(&mut conn)
+        .await
+        .context("failed to query transactions")
+        .map_err(AppError::from)?;
+
+    let refundable_amount = (transaction.kind == TransactionKind::Transfer).then(|| {
+        &transaction.amount
+            - refunds
+                .iter()
+                .map(Transaction::credited_amount)
+                .sum::<BigDecimal>()
+    });
+    let conversion = ConversionResponse::new(&transaction);
+
+    Ok(Json(GetTransactionResponse {
+        id: transaction.id,
+        amount: transaction.amount,
+        currency: transaction.currency,
+        recipient: transaction.recipient,
+        sender: transaction.sender,
+        timestamp: transaction.timestamp,
+        memo: transaction.memo,
+        reference: transaction.reference,
+        kind: transaction.kind,
+        original_transaction_id: transaction.original_transaction_id,
+        flagged: transaction.flagged,
+        conversion,
+        fee: transaction.fee,
+        pocket_id: transaction.pocket_id,
+        counterparty: CounterpartyResponse {
+            id: counterparty.id,
+            username: counterparty.username,
+        },
+        refundable_amount,
+        refunds: refunds
+            .into_iter()
+            .map(|refund| LinkedTransactionResponse {
+                id: refund.id,
+                amount: refund.amount,
+                currency: refund.currency,
+                timestamp: refund.timestamp,
+                kind: refund.kind,
+                flagged: refund.flagged,
+            })
+            .collect(),
+    }))
+}
+
+/// Returns the fee which the authenticated user would be charged for a
+/// transfer, without making it.
+pub async fn get_transaction_fee(
+    State(pool): State<DbConnectionPool>,
+    Extension(authenticated_user): Extension<AuthenticatedUser>,
+    Query(query_params): Query<GetTransactionFeeQueryParams>,
+) -> Result<Json<GetTransactionFeeResponse>> {
+    let mut conn = pool
+        .get()
+        .await
+        .context("failed to get database connection")
+        .map_err(AppError::from)?;
+
+    let currency = query_params.currency.unwrap_or(DEFAULT_CURRENCY);
+    if query_params.amount <= BigDecimal::zero() {
+        return Err(TransferError::InvalidAmount)?;
+    }
+    if !currency.is_valid_amount(&query_params.amount) {
+        return Err(TransferError::AmountTooPrecise(currency))?;
+    }
+
+    let fee = fees::fee_for(
+        &mut conn,
+        authenticated_user.subject,
+        &query_params.amount,
+        currency,
+    )
+    .await
+    .map_err(AppError::from)?;
+
+    Ok(Json(GetTransactionFeeResponse {
+        total: &query_params.amount + &fee,
+        amount: query_params.amount,
+        currency,
+        fee,
+    }))
+}
+
+pub async fn post_transaction(
+    State(pool): State<DbConnectionPool>,
+    State(rate_limiter): State<UsernameLookupRateLimiter>,
+    State(default_limits): State<TransferLimits>,
+    State(event_hub): State<EventHub>,
+    Extension(authenticated_user): Extension<AuthenticatedUser>,
+    WithRejection(Json(payload), _): WithRejection<Json<PostTranscactionPayload>, JsonRejection>,
+) -> Result<(HeaderMap, Json<PostTransactionResponse>)> {
+    let mut conn = pool
+        .get()
+        .await
+        .context("failed to get database connection")
+        .map_err(AppError::from)?;
+
+    let mut response_headers = HeaderMap::new();
+    if let Some(sender) = payload.sender {
+        debug!(%sender, "deprecated payload with sender");
+
+        response_headers.insert(
+            "deprecation",
+            HeaderValue::from_static(SENDER_IN_PAYLOAD_DEPRECATION),
+        );
+
+        if authenticated_user.subject != sender {
+            return Err((
+                StatusCode::FORBIDDEN,
+                response_headers,
+                Json(json!({
+                    "title": "PermissionDenied",
+                })),
+            ))?;
+        }
+    }
+
+    let memo = validate_memo(payload.memo.as_deref())?;
+
+    if let Some(Err(detail)) = payload.reference.as_deref().map(validate_reference) {
         return Err((
-            StatusCode::FORBIDDEN,
+            StatusCode::BAD_REQUEST,
             Json(json!({
-                "title": "PermissionDenied",
+                "title": "InvalidReference",
+                "detail": detail,
             })),
         ))?;
     }
 
-    let created_transaction = conn
-        .transaction(|conn| {
-            Box::pin(async move {
-                let mut sender: User = users::table
-                    .find(types::Uuid::from(payload.sender))
-                    .select(User::as_select())
-                    .first(conn)
-                    .await
-                    .context("could not find user")?;
-
-                let mut recipient: User = match users::table
-                    .find(types::Uuid::from(payload.recipient))
-                    .select(User::as_select())
-                    .first(conn)
-                    .await
-                {
-                    Ok(recipient) => recipient,
-                    Err(diesel::NotFound) => {
-                        debug!(%payload.recipient, "could not find recipient");
-
-                        return Ok(Err((
-                            StatusCode::BAD_REQUEST,
-                            Json(json!({
-                                "title": "InvalidRecipient",
-                            })),
-                        )));
-                    },
-                    Err(err) => {
-                        return Err(err).context("failed to query users")?;
-                    },
-                };
-
-                if sender.balance < payload.amount {
-                    return Ok(Err((
-                        StatusCode::FORBIDDEN,
-                        Json(json!({
-                            "title": "InsufficientBalance",
-                        })),
-                    )));
-                }
-
-                let new_transaction = NewTransaction {
-                    id: Uuid::now_v7(),
-                    amount: payload.amount,
-                    recipient: recipient.id,
-                    sender: sender.id,
-                    timestamp: jiff::Timestamp::now(),
-                };
-
-                let created_transaction: Transaction = diesel::insert_into(transactions::table)
-                    .values(new_transaction)
-                    .returning(Transaction::as_returning())
-                    .get_result(conn)
-                    .await
-                    .context("failed to insert transaction")?;
-
-                sender.balance -= &created_transaction.amount;
-                recipient.balance += &created_transaction.amount;
-
-                let _sender: User = diesel::update(users::table.find(types::Uuid::from(sender.id)))
-                    .set(sender)
-                    .returning(User::as_returning())
-                    .get_result(conn)
-                    .await
-                    .context("failed to update user")?;
-
-                let _recipient: User =
-                    diesel::update(users::table.find(types::Uuid::from(recipient.id)))
-                        .set(recipient)
-                        .returning(User::as_returning())
-                        .get_result(conn)
-                        .await
-                        .context("failed to update user")?;
-
-                Ok::<_, anyhow::Error>(Ok(created_transaction))
-            })
-        })
+    if let UserRef::Username(_) = payload.recipient {
+        check_username_lookup(&rate_limiter, authenticated_user.subject)?;
+    }
+
+    let recipient: User = match payload
+        .recipient
+        .query()
+        .select(User::as_select())
+        .first(&mut conn)
+        .await
+    {
+        Ok(recipient) => recipient,
+        Err(diesel::NotFound) => {
+            debug!(?payload.recipient, "could not find recipient");
+
+            return Err(TransferError::InvalidRecipient)?;
+        },
+        Err(err) => {
+            return Err(err)
+                .context("failed to query users")
+                .map_err(AppError::from)?;
+        },
+    };
+
+    let new_transfer = NewTransfer {
+        amount: payload.amount,
+        currency: payload.currency.unwrap_or(DEFAULT_CURRENCY),
+        recipient: recipient.id,
+        sender: authenticated_user.subject,
+        memo,
+        reference: payload.reference,
+        quote_id: payload.quote_id,
+    };
+
+    let created_transaction = ledger::transfer(&mut conn, &new_transfer, &default_limits)
         .await
         .map_err(AppError::from)??;
+    publish_transactions(&event_hub, &mut conn, slice::from_ref(&created_transaction)).await;
+
+    Ok((response_headers, Json(created_transaction.into())))
+}
+
+/// Makes transfers from the authenticated user to many recipients at once,
+/// either all of them or none.
+///
+/// If any transfer fails, the response lists the failures by their index in
+/// the batch. Each recipient given by username counts towards the user's rate
+/// limit of username lookups.
+pub async fn post_transaction_batch(
+    State(pool): State<DbConnectionPool>,
+    State(default_limits): State<TransferLimits>,
+    State(event_hub): State<EventHub>,
+    State(rate_limiter): State<UsernameLookupRateLimiter>,
+    Extension(authenticated_user): Extension<AuthenticatedUser>,
+    WithRejection(Json(payload), _): WithRejection<Json<PostTransferBatchPayload>, JsonRejection>,
+) -> Result<(StatusCode, Json<TransferBatchResponse>)> {
+    let mut conn = pool
+        .get()
+        .await
+        .context("failed to get database connection")
+        .map_err(AppError::from)?;
+
+    if payload.transfers.len() > ledger::MAX_BATCH_TRANSFERS {
+        return Err(BatchError::TooLarge)?;
+    }
+
+    let mut transfers = Vec::with_capacity(payload.transfers.len());
+    let mut errors = Vec::new();
+    for (index, transfer) in payload.transfers.into_iter().enumerate() {
+        let memo = match transfer.memo.as_deref().map(sanitize_memo) {
+            Some(Ok(memo)) => memo,
+            Some(Err(detail)) => {
+                errors.push((index, TransferError::InvalidMemo(detail)));
+                continue;
+            },
+            None => None,
+        };
+
+        if let Some(Err(detail)) = transfer.reference.as_deref().map(validate_reference) {
+            errors.push((index, TransferError::InvalidReference(detail)));
+            continue;
+        }
+
+        if let UserRef::Username(_) = transfer.recipient {
+            check_username_lookup(&rate_limiter, authenticated_user.subject)?;
+        }
+        let recipient: Option<User> = transfer
+            .recipient
+            .query()
+            .select(User::as_select())
+            .first(&mut conn)
+            .await
+            .optional()
+            .context("failed to query users")
+            .map_err(AppError::from)?;
+        let Some(recipient) = recipient else {
+            debug!(?transfer.recipient, "could not find recipient");
+
+            errors.push((index, TransferError::InvalidRecipient));
+            continue;
+        };
+
+        transfers.push(NewBatchTransfer {
+            amount: transfer.amount,
+            recipient: recipient.id,
+            memo,
+            reference: transfer.reference,
+        });
+    }
+    if !errors.is_empty() {
+        return Err(BatchError::Rejected(errors))?;
+    }
+
+    let new_batch = NewTransferBatch {
+        sender: authenticated_user.subject,
+        currency: payload.currency.unwrap_or(DEFAULT_CURRENCY),
+        transfers,
+    };
+    let (created_batch, created_transactions) =
+        ledger::batch_transfer(&mut conn, &new_batch, &default_limits)
+            .await
+            .map_err(AppError::from)??;
+    publish_transactions(&event_hub, &mut conn, &created_transactions).await;
+
+    Ok((
+        StatusCode::CREATED,
+        Json(TransferBatchResponse {
+            id: created_batch.id,
+            sender: created_batch.sender,
+            currency: created_batch.currency,
+            created_at: created_batch.created_at,
+            transactions: created_transactions.into_iter().map(Into::into).collect(),
+        }),
+    ))
+}
+
+/// Returns a batch of the authenticated user, with its transfers.
+pub async fn get_transaction_batch(
+    State(pool): State<DbConnectionPool>,
+    Extension(authenticated_user): Extension<AuthenticatedUser>,
+    Path(GetTransferBatchPathParams { batch_id }): Path<GetTransferBatchPathParams>,
+) -> Result<Json<TransferBatchResponse>> {
+    use crate::models::types;
+    use crate::schema::{transactions, transfer_batch_items, transfer_batches};
+
+    let mut conn = pool
+        .get()
+        .await
+        .context("failed to get database connection")
+        .map_err(AppError::from)?;
+
+    let batch: Option<TransferBatch> = transfer_batches::table
+        .find(types::Uuid::from(batch_id))
+        .filter(transfer_batches::sender.eq(types::Uuid::from(authenticated_user.subject)))
+        .select(TransferBatch::as_select())
+        .first(&mut conn)
+        .await
+        .optional()
+        .context("failed to query transfer batches")
+        .map_err(AppError::from)?;
+    let Some(batch) = batch else {
+        debug!(%batch_id, "could not find transfer batch");
+
+        return Err((
+            StatusCode::NOT_FOUND,
+            Json(json!({
+                "title": "BatchNotFound",
+            })),
+        ))?;
+    };
 
-    Ok(Json(PostTransactionResponse {
-        id: created_transaction.id,
-        amount: created_transaction.amount,
-        recipient: created_transaction.recipient,
-        sender: created_transaction.sender,
-        timestamp: created_transaction.timestamp,
+    let batch_transactions: Vec<Transaction> = transfer_batch_items::table
+        .inner_join(transactions::table)
+        .filter(transfer_batch_items::batch_id.eq(types::Uuid::from(batch.id)))
+        .order(transfer_batch_items::position.asc())
+        .select(Transaction::as_select())
+        .load(&mut conn)
+        .await
+        .context("failed to query transactions")
+        .map_err(AppError::from)?;
+
+    Ok(Json(TransferBatchResponse {
+        id: batch.id,
+        sender: batch.sender,
+        currency: batch.currency,
+        created_at: batch.created_at,
+        transactions: batch_transactions.into_iter().map(Into::into).collect(),
     }))
 }
+
+/// Sends money of a transaction back to its sender, by its recipient.
+pub async fn post_transaction_refund(
+    State(pool): State<DbConnectionPool>,
+    State(event_hub): State<EventHub>,
+    Extension(authenticated_user): Extension<AuthenticatedUser>,
+    Path(GetTransactionPathParams { transaction_id }): Path<GetTransactionPathParams>,
+    WithRejection(Json(payload), _): WithRejection<Json<PostRefundPayload>, JsonRejection>,
+) -> Result<(StatusCode, Json<PostTransactionResponse>)> {
+    create_refund(
+        &pool,
+        &event_hub,
+        authenticated_user.subject,
+        transaction_id,
+        payload,
+        TransactionKind::Refund,
+    )
+    .await
+}
+
+/// Takes money of a transaction back from its recipient, by an admin.
+///
+/// Unlike a refund, a reversal goes through even if it takes the recipient's
+/// balance negative, in which case it is flagged.
+pub async fn post_transaction_reversal(
+    State(pool): State<DbConnectionPool>,
+    State(event_hub): State<EventHub>,
+    Extension(authenticated_user): Extension<AuthenticatedUser>,
+    Path(GetTransactionPathParams { transaction_id }): Path<GetTransactionPathParams>,
+    WithRejection(Json(payload), _): WithRejection<Json<PostRefundPayload>, JsonRejection>,
+) -> Result<(StatusCode, Json<PostTransactionResponse>)> {
+    create_refund(
+        &pool,
+        &event_hub,
+        authenticated_user.subject,
+        transaction_id,
+        payload,
+        TransactionKind::Reversal,
+    )
+    .await
+}
+
+async fn create_refund(
+    pool: &DbConnectionPool,
+    event_hub: &EventHub,
+    initiator: Uuid,
+    transaction_id: Uuid,
+    payload: PostRefundPayload,
+    kind: TransactionKind,
+) -> Result<(StatusCode, Json<PostTransactionResponse>)> {
+    let mut conn = pool
+        .get()
+        .await
+        .context("failed to get database connection")
+        .map_err(AppError::from)?;
+
+    let memo = validate_memo(payload.memo.as_deref())?;
+
+    let new_refund = NewRefund {
+        original_transaction_id: transaction_id,
+        amount: payload.amount,
+        initiator,
+        kind,
+        memo,
+    };
+
+    let created_transaction = ledger::refund(&mut conn, &new_refund)
+        .await
+        .map_err(AppError::from)??;
+    publish_transactions(event_hub, &mut conn, slice::from_ref(&created_transaction)).await;
+
+    Ok((StatusCode::CREATED, Json(created_transaction.into())))
+}
+
+/// Sanitizes a free-text memo.
+///
+/// Control characters and bidirectional formatting characters are dropped, and
+/// runs of whitespace are collapsed into a single space. Returns `Ok(None)` if
+/// nothing is left.
+pub(crate) fn sanitize_memo(memo: &str) -> Result<Option<String>, String> {
+    let memo = memo
+        .chars()
+        .filter(|c| !c.is_control() || c.is_whitespace())
+        .filter(|c| !BIDI_FORMATTING_CHARS.contains(c))
+        .collect::<String>();
+    let memo = memo.split_whitespace().collect::<Vec<_>>().join(" ");
+
+    if memo.is_empty() {
+        return Ok(None);
+    }
+    if memo.chars().count() > MEMO_MAX_CHARS {
+        return Err(format!("memo must be at most {MEMO_MAX_CHARS} characters"));
+    }
+
+    Ok(Some(memo))
+}
+
+/// Sanitizes an optional memo of a request, see [`sanitize_memo`].
+///
+/// Returns an `InvalidMemo` response if the memo is invalid.
+#[allow(
+    clippy::result_large_err,
+    reason = "the error is returned from handlers, whose errors are just as large"
+)]
+pub(crate) fn validate_memo(memo: Option<&str>) -> Result<Option<String>> {
+    match memo.map(sanitize_memo) {
+        Some(Ok(memo)) => Ok(memo),
+        Some(Err(detail)) => Err((
+            StatusCode::BAD_REQUEST,
+            Json(json!({
+                "title": "InvalidMemo",
+                "detail": detail,
+            })),
+        ))?,
+        None => Ok(None),
+    }
+}
+
+/// Validates an external reference ID.
+///
+/// References are opaque to us, but they are restricted to a conservative set
+/// of ASCII characters so that they can be safely echoed back and matched
+/// against other systems.
+pub(crate) fn validate_reference(reference: &str) -> Result<(), String> {
+    if reference.is_empty() || reference.len() > REFERENCE_MAX_LEN {
+        return Err(format!(
+            "reference must be between 1 and {REFERENCE_MAX_LEN} characters"
+        ));
+    }
+    if !reference
+        .bytes()
+        .all(|b| b.is_ascii_alphanumeric() || matches!(b, b'-' | b'_' | b'.' | b':' | b'/'))
+    {
+        return Err(
+            "reference may only contain ASCII letters, digits, '-', '_', '.', ':' and '/'"
+                .to_owned(),
+        );
+    }
+
+    Ok(())
+}
+
+impl From<Transaction> for PostTransactionResponse {
+    fn from(transaction: Transaction) -> Self {
+        let conversion = ConversionResponse::new(&transaction);
+        Self {
+            id: transaction.id,
+            amount: transaction.amount,
+            currency: transaction.currency,
+            recipient: transaction.recipient,
+            sender: transaction.sender,
+            timestamp: transaction.timestamp,
+            memo: transaction.memo,
+            reference: transaction.reference,
+            kind: transaction.kind,
+            original_transaction_id: transaction.original_transaction_id,
+            flagged: transaction.flagged,
+            conversion,
+            fee: transaction.fee,
+            pocket_id: transaction.pocket_id,
+        }
+    }
+}
+
+impl ConversionResponse {
+    pub fn new(transaction: &Transaction) -> Option<Self> {
+        Some(Self {
+            amount: transaction.converted_amount.clone()?,
+            currency: transaction.converted_currency?,
+            rate: transaction.exchange_rate.clone()?,
+            spread: transaction.spread.clone()?,
+        })
+    }
+}
@@ -0,0 +1,344 @@
+use anyhow::Context as _;
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use axum::response::Result;
+use axum::{Extension, Json};
+use axum_extra::extract::WithRejection;
+use bigdecimal::{BigDecimal, Zero as _};
+use diesel::prelude::*;
+#[allow(
+    clippy::unused_trait_names,
+    reason = "error[E0034]: multiple applicable items in scope"
+)]
+use diesel_async::RunQueryDsl;
+use serde::{Deserialize, Deserializer, Serialize};
+use serde_json::json;
+use tracing::debug;
+use uuid::Uuid;
+
+use crate::currency::{Currency, DEFAULT_CURRENCY};
+use crate::error::{AppError, JsonRejection};
+use crate::handlers::user::is_admin;
+use crate::limits::{Limit, TransferLimits};
+use crate::middleware::auth::AuthenticatedUser;
+use crate::models::TransferLimit;
+use crate::state::{DbConnection, DbConnectionPool};
+
+#[derive(Deserialize)]
+pub struct TransferLimitsPathParams {
+    user_id: Uuid,
+}
+
+/// Each limit falls back to the default if it is omitted, and is removed if it
+/// is `null`.
+#[derive(Deserialize)]
+pub struct PutTransferLimitsPayload {
+    #[serde(default, deserialize_with = "deserialize_amount_limit")]
+    per_transaction: Option<Option<BigDecimal>>,
+    #[serde(default, deserialize_with = "deserialize_amount_limit")]
+    daily: Option<Option<BigDecimal>>,
+    #[serde(default, deserialize_with = "deserialize_amount_limit")]
+    monthly: Option<Option<BigDecimal>>,
+    #[serde(default, deserialize_with = "deserialize_count_limit")]
+    max_transfers: Option<Option<i64>>,
+}
+
+#[derive(Serialize)]
+pub struct TransferLimitsResponse {
+    user_id: Uuid,
+    /// The currency of the amount limits. Amounts in other currencies are
+    /// converted at the current exchange rates.
+    currency: Currency,
+    /// The limits which apply to the user. Limits which are `null` don't apply.
+    #[serde(with = "bigdecimal::serde::json_num_option")]
+    per_transaction: Option<BigDecimal>,
+    #[serde(with = "bigdecimal::serde::json_num_option")]
+    daily: Option<BigDecimal>,
+    #[serde(with = "bigdecimal::serde::json_num_option")]
+    monthly: Option<BigDecimal>,
+    max_transfers: Option<u32>,
+    transfer_count_window: jiff::SignedDuration,
+    /// The limits set for the user by an admin, which take precedence over the
+    /// defaults.
+    overrides: Option<TransferLimitOverridesResponse>,
+}
+
+#[derive(Serialize)]
+pub struct TransferLimitOverridesResponse {
+    #[serde(with = "bigdecimal::serde::json_num_option")]
+    per_transaction: Option<BigDecimal>,
+    #[serde(with = "bigdecimal::serde::json_num_option")]
+    daily: Option<BigDecimal>,
+    #[serde(with = "bigdecimal::serde::json_num_option")]
+    monthly: Option<BigDecimal>,
+    max_transfers: Option<i32>,
+    /// The limits which don't apply to the user, even if there are defaults.
+    removed: Vec<Limit>,
+    updated_at: jiff::Timestamp,
+}
+
+/// Returns the transfer limits of a user.
+///
+/// Users may see their own limits, and admins those of anyone.
+pub async fn get_transfer_limits(
+    State(pool): State<DbConnectionPool>,
+    State(default_limits): State<TransferLimits>,
+    Extension(authenticated_user): Extension<AuthenticatedUser>,
+    Path(TransferLimitsPathParams { user_id }): Path<TransferLimitsPathParams>,
+) -> Result<Json<TransferLimitsResponse>> {
+    let mut conn = pool
+        .get()
+        .await
+        .context("failed to get database connection")
+        .map_err(AppError::from)?;
+
+    if authenticated_user.subject != user_id
+        && !is_admin(&mut conn, authenticated_user.subject)
+            .await
+            .map_err(AppError::from)?
+    {
+        return Err((
+            StatusCode::FORBIDDEN,
+            Json(json!({
+                "title": "PermissionDenied",
+            })),
+        ))?;
+    }
+
+    let transfer_limit = find_transfer_limit(&mut conn, user_id)
+        .await
+        .map_err(AppError::from)?;
+
+    Ok(Json(TransferLimitsResponse::new(
+        user_id,
+        &default_limits,
+        transfer_limit,
+    )))
+}
+
+/// Overrides the default transfer limits of a user. Limits which are omitted
+/// fall back to the defaults, and limits which are `null` are removed, so that
+/// they don't apply to the user even if there are defaults.
+///
+/// Only admins may set transfer limits.
+pub async fn put_transfer_limits(
+    State(pool): State<DbConnectionPool>,
+    State(default_limits): State<TransferLimits>,
+    Extension(authenticated_user): Extension<AuthenticatedUser>,
+    Path(TransferLimitsPathParams { user_id }): Path<TransferLimitsPathParams>,
+    WithRejection(Json(payload), _): WithRejection<Json<PutTransferLimitsPayload>, JsonRejection>,
+) -> Result<Json<TransferLimitsResponse>> {
+    use crate::models::types;
+    use crate::schema::{transfer_limits, users};
+
+    let mut conn = pool
+        .get()
+        .await
+        .context("failed to get database connection")
+        .map_err(AppError::from)?;
+
+    if !is_admin(&mut conn, authenticated_user.subject)
+        .await
+        .map_err(AppError::from)?
+    {
+        return Err((
+            StatusCode::FORBIDDEN,
+            Json(json!({
+                "title": "PermissionDenied",
+            })),
+        ))?;
+    }
+
+    for (name, amount) in [
+        ("per_transaction", &payload.per_transaction),
+        ("daily", &payload.daily),
+        ("monthly", &payload.monthly),
+    ] {
+        if let Some(Some(amount)) = amount
+            && *amount <= BigDecimal::zero()
+        {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                Json(json!({
+                    "title": "InvalidLimit",
+                    "detail": format!("{name} must be positive"),
+                })),
+            ))?;
+        }
+    }
+    let max_transfers = match payload.max_transfers {
+        Some(Some(max_transfers)) => match i32::try_from(max_transfers) {
+            Ok(max_transfers) if max_transfers > 0 => Some(max_transfers),
+            _ => {
+                return Err((
+                    StatusCode::BAD_REQUEST,
+                    Json(json!({
+                        "title": "InvalidLimit",
+                        "detail": format!("max_transfers must be between 1 and {}", i32::MAX),
+                    })),
+                ))?;
+            },
+        },
+        _ => None,
+    };
+
+    let user_exists = diesel::select(diesel::dsl::exists(
+        users::table.find(types::Uuid::from(user_id)),
+    ))
+    .get_result::<bool>(&mut conn)
+    .await
+    .context("failed to query users")
+    .map_err(AppError::from)?;
+    if !user_exists {
+        debug!(%user_id, "could not find user");
+
+        return Err((
+            StatusCode::NOT_FOUND,
+            Json(json!({
+                "title": "UserNotFound",
+            })),
+        ))?;
+    }
+
+    let transfer_limit = TransferLimit {
+        user_id,
+        per_transaction_removed: matches!(payload.per_transaction, Some(None)),
+        per_transaction: payload.per_transaction.flatten(),
+        daily_removed: matches!(payload.daily, Some(None)),
+        daily: payload.daily.flatten(),
+        monthly_removed: matches!(payload.monthly, Some(None)),
+        monthly: payload.monthly.flatten(),
+        max_transfers_removed: matches!(payload.max_transfers, Some(None)),
+        max_transfers,
+        updated_at: jiff::Timestamp::now(),
+    };
+    let transfer_limit: TransferLimit = diesel::insert_into(transfer_limits::table)
+        .values(transfer_limit.clone())
+        .on_conflict(transfer_limits::user_id)
+        .do_update()
+        .set(transfer_limit)
+        .returning(TransferLimit::as_returning())
+        .get_result(&mut conn)
+        .await
+        .context("failed to upsert transfer limits")
+        .map_err(AppError::from)?;
+
+    Ok(Json(TransferLimitsResponse::new(
+        user_id,
+        &default_limits,
+        Some(transfer_limit),
+    )))
+}
+
+/// Removes the transfer limits set for a user, so that the defaults apply.
+///
+/// Only admins may remove transfer limits.
+pub async fn delete_transfer_limits(
+    State(pool): State<DbConnectionPool>,
+    Extension(authenticated_user): Extension<AuthenticatedUser>,
+    Path(TransferLimitsPathParams { user_id }): Path<TransferLimitsPathParams>,
+) -> Result<StatusCode> {
+    use crate::models::types;
+    use crate::schema::transfer_limits;
+
+    let mut conn = pool
+        .get()
+        .await
+        .context("failed to get database connection")
+        .map_err(AppError::from)?;
+
+    if !is_admin(&mut conn, authenticated_user.subject)
+        .await
+        .map_err(AppError::from)?
+    {
+        return Err((
+            StatusCode::FORBIDDEN,
+            Json(json!({
+                "title": "PermissionDenied",
+            })),
+        ))?;
+    }
+
+    diesel::delete(transfer_limits::table.find(types::Uuid::from(user_id)))
+        .execute(&mut conn)
+        .await
+        .context("failed to delete transfer limits")
+        .map_err(AppError::from)?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+impl TransferLimitsResponse {
+    fn new(
+        user_id: Uuid,
+        default_limits: &TransferLimits,
+        transfer_limit: Option<TransferLimit>,
+    ) -> Self {
+        let limits = match &transfer_limit {
+            Some(transfer_limit) => default_limits.with_override(transfer_limit),
+            None => default_limits.clone(),
+        };
+
+        Self {
+            user_id,
+            currency: DEFAULT_CURRENCY,
+            per_transaction: limits.per_transaction,
+            daily: limits.daily,
+            monthly: limits.monthly,
+            max_transfers: limits.max_transfers,
+            transfer_count_window: limits.transfer_count_window,
+            overrides: transfer_limit.map(|transfer_limit| TransferLimitOverridesResponse {
+                per_transaction: transfer_limit.per_transaction,
+                daily: transfer_limit.daily,
+                monthly: transfer_limit.monthly,
+                max_transfers: transfer_limit.max_transfers,
+                removed: [
+                    (
+                        Limit::PerTransaction,
+                        transfer_limit.per_transaction_removed,
+                    ),
+                    (Limit::Daily, transfer_limit.daily_removed),
+                    (Limit::Monthly, transfer_limit.monthly_removed),
+                    (Limit::TransferCount, transfer_limit.max_transfers_removed),
+                ]
+                .into_iter()
+                .filter_map(|(limit, removed)| removed.then_some(limit))
+                .collect(),
+                updated_at: transfer_limit.updated_at,
+            }),
+        }
+    }
+}
+
+async fn find_transfer_limit(
+    conn: &mut DbConnection,
+    user_id: Uuid,
+) -> anyhow::Result<Option<TransferLimit>> {
+    use crate::models::types;
+    use crate::schema::transfer_limits;
+
+    transfer_limits::table
+        .find(types::Uuid::from(user_id))
+        .select(TransferLimit::as_select())
+        .first(conn)
+        .await
+        .optional()
+        .context("failed to query transfer limits")
+}
+
+/// Deserializes an amount limit which is present, so that `null` can be told
+/// apart from a limit which is omitted, and so `None`.
+fn deserialize_amount_limit<'de, D>(deserializer: D) -> Result<Option<Option<BigDecimal>>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    bigdecimal::serde::json_num_option::deserialize(deserializer).map(Some)
+}
+
+/// Like [`deserialize_amount_limit`], but for a number of transfers.
+fn deserialize_count_limit<'de, D>(deserializer: D) -> Result<Option<Option<i64>>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    Option::<i64>::deserialize(deserializer).map(Some)
+}
@@ -0,0 +1,224 @@
+use std::collections::BTreeMap;
+
+use anyhow::Context as _;
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::response::Result;
+use axum::{Extension, Json};
+use axum_extra::extract::WithRejection;
+use bigdecimal::{BigDecimal, Zero as _};
+use diesel::prelude::*;
+#[allow(
+    clippy::unused_trait_names,
+    reason = "error[E0034]: multiple applicable items in scope"
+)]
+use diesel_async::RunQueryDsl;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use uuid::Uuid;
+
+use crate::currency::{Currency, DEFAULT_CURRENCY};
+use crate::error::{AppError, JsonRejection};
+use crate::handlers::user::is_admin;
+use crate::ledger::{self, NewIssuance, TREASURY_USER_ID};
+use crate::middleware::auth::AuthenticatedUser;
+use crate::models::transaction::TransactionKind;
+use crate::models::{Account, Transaction};
+use crate::state::DbConnectionPool;
+
+#[derive(Deserialize)]
+pub struct PostIssuancePayload {
+    #[serde(with = "bigdecimal::serde::json_num")]
+    amount: BigDecimal,
+    /// Defaults to [`DEFAULT_CURRENCY`].
+    currency: Option<Currency>,
+    memo: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct IssuanceResponse {
+    id: Uuid,
+    #[serde(with = "bigdecimal::serde::json_num")]
+    amount: BigDecimal,
+    currency: Currency,
+    kind: TransactionKind,
+    timestamp: jiff::Timestamp,
+    memo: Option<String>,
+    initiator: Option<Uuid>,
+}
+
+#[derive(Serialize)]
+pub struct GetTreasuryResponse {
+    currencies: Vec<MoneySupplyResponse>,
+}
+
+/// The money supply in one currency.
+#[derive(Serialize)]
+pub struct MoneySupplyResponse {
+    currency: Currency,
+    #[serde(with = "bigdecimal::serde::json_num")]
+    minted: BigDecimal,
+    #[serde(with = "bigdecimal::serde::json_num")]
+    burned: BigDecimal,
+    /// What was minted less what was burned, which all accounts together
+    /// hold.
+    #[serde(with = "bigdecimal::serde::json_num")]
+    supply: BigDecimal,
+    /// What the treasury holds.
+    #[serde(with = "bigdecimal::serde::json_num")]
+    treasury_balance: BigDecimal,
+    /// What was granted out of the treasury, and is held by users.
+    #[serde(with = "bigdecimal::serde::json_num")]
+    circulating: BigDecimal,
+}
+
+impl From<Transaction> for IssuanceResponse {
+    fn from(transaction: Transaction) -> Self {
+        Self {
+            id: transaction.id,
+            amount: transaction.amount,
+            currency: transaction.currency,
+            kind: transaction.kind,
+            timestamp: transaction.timestamp,
+            memo: transaction.memo,
+            initiator: transaction.initiator,
+        }
+    }
+}
+
+/// Returns how much money was minted and burned in each currency, and where
+/// it is.
+///
+/// Only admins may see the treasury.
+pub async fn get_treasury(
+    State(pool): State<DbConnectionPool>,
+    Extension(authenticated_user): Extension<AuthenticatedUser>,
+) -> Result<Json<GetTreasuryResponse>> {
+    use crate::models::types;
+    use crate::schema::{accounts, transactions};
+
+    let mut conn = pool
+        .get()
+        .await
+        .context("failed to get database connection")
+        .map_err(AppError::from)?;
+
+    if !is_admin(&mut conn, authenticated_user.subject)
+        .await
+        .map_err(AppError::from)?
+    {
+        return Err((
+            StatusCode::FORBIDDEN,
+            Json(json!({
+                "title": "PermissionDenied",
+            })),
+        ))?;
+    }
+
+    let treasury_accounts: Vec<Account> = accounts::table
+        .filter(accounts::user_id.eq(types::Uuid::from(TREASURY_USER_ID)))
+        .select(Account::as_select())
+        .load(&mut conn)
+        .await
+        .context("failed to query accounts")
+        .map_err(AppError::from)?;
+    let issuances: Vec<Transaction> = transactions::table
+        .filter(transactions::kind.eq_any([TransactionKind::Mint, TransactionKind::Burn]))
+        .select(Transaction::as_select())
+        .load(&mut conn)
+        .await
+        .context("failed to query transactions")
+        .map_err(AppError::from)?;
+
+    // Amounts are stored as text, so they are summed here rather than in SQL.
+    let mut totals: BTreeMap<Currency, (BigDecimal, BigDecimal)> = BTreeMap::new();
+    for issuance in issuances {
+        let (minted, burned) = totals.entry(issuance.currency).or_default();
+        match issuance.kind {
+            TransactionKind::Mint => *minted += issuance.amount,
+            _ => *burned += issuance.amount,
+        }
+    }
+
+    let currencies = totals
+        .into_iter()
+        .map(|(currency, (minted, burned))| {
+            let treasury_balance = treasury_accounts
+                .iter()
+                .find(|account| account.currency == currency)
+                .map_or_else(BigDecimal::zero, |account| account.balance.clone());
+            let supply = &minted - &burned;
+            MoneySupplyResponse {
+                currency,
+                circulating: &supply - &treasury_balance,
+                minted,
+                burned,
+                supply,
+                treasury_balance,
+            }
+        })
+        .collect();
+
+    Ok(Json(GetTreasuryResponse { currencies }))
+}
+
+/// Issues new money into the treasury.
+///
+/// Only admins may mint money.
+pub async fn post_mint(
+    State(pool): State<DbConnectionPool>,
+    Extension(authenticated_user): Extension<AuthenticatedUser>,
+    WithRejection(Json(payload), _): WithRejection<Json<PostIssuancePayload>, JsonRejection>,
+) -> Result<(StatusCode, Json<IssuanceResponse>)> {
+    post_issuance(pool, authenticated_user, payload, TransactionKind::Mint).await
+}
+
+/// Removes money from the treasury. Only what the treasury holds can be
+/// burned, not what was granted to users.
+///
+/// Only admins may burn money.
+pub async fn post_burn(
+    State(pool): State<DbConnectionPool>,
+    Extension(authenticated_user): Extension<AuthenticatedUser>,
+    WithRejection(Json(payload), _): WithRejection<Json<PostIssuancePayload>, JsonRejection>,
+) -> Result<(StatusCode, Json<IssuanceResponse>)> {
+    post_issuance(pool, authenticated_user, payload, TransactionKind::Burn).await
+}
+
+async fn post_issuance(
+    pool: DbConnectionPool,
+    authenticated_user: AuthenticatedUser,
+    payload: PostIssuancePayload,
+    kind: TransactionKind,
+) -> Result<(StatusCode, Json<IssuanceResponse>)> {
+    let mut conn = pool
+        .get()
+        .await
+        .context("failed to get database connection")
+        .map_err(AppError::from)?;
+
+    if !is_admin(&mut conn, authenticated_user.subject)
+        .await
+        .map_err(AppError::from)?
+    {
+        return Err((
+            StatusCode::FORBIDDEN,
+            Json(json!({
+                "title": "PermissionDenied",
+            })),
+        ))?;
+    }
+
+    let issuance = NewIssuance {
+        amount: payload.amount,
+        currency: payload.currency.unwrap_or(DEFAULT_CURRENCY),
+        initiator: Some(authenticated_user.subject),
+        kind,
+        memo: payload.memo,
+    };
+    let transaction = ledger::issue(&mut conn, &issuance)
+        .await
+        .map_err(AppError::from)??;
+
+    Ok((StatusCode::CREATED, Json(transaction.into())))
+}
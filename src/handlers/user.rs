@@ -1,8 +1,9 @@
 use anyhow::Context as _;
-use axum::extract::{Path, State};
-use axum::http::StatusCode;
-use axum::response::Result;
+use axum::extract::{Path, Query, State};
+use axum::http::{StatusCode, header};
+use axum::response::{IntoResponse as _, Response, Result};
 use axum::{Extension, Json};
+use axum_extra::extract::WithRejection;
 use bigdecimal::BigDecimal;
 use diesel::prelude::*;
 #[allow(
@@ -12,12 +13,22 @@ use diesel::prelude::*;
 use diesel_async::RunQueryDsl;
 use serde::{Deserialize, Serialize};
 use serde_json::json;
+use tracing::debug;
 use uuid::Uuid;
 
-use crate::error::AppError;
+use crate::currency::{Currency, DEFAULT_CURRENCY};
+use crate::error::{AppError, JsonRejection};
+use crate::handlers::account::{AccountResponse, account_responses};
+use crate::handlers::pocket::{PocketResponse, pocket_responses};
+use crate::handlers::transaction::ConversionResponse;
 use crate::middleware::auth::AuthenticatedUser;
+use crate::models::transaction::TransactionKind;
+use crate::models::user::{UserKind, UserRole, UserTier};
 use crate::models::{Transaction, User};
-use crate::state::DbConnectionPool;
+use crate::qr_code::{QrCodeOptions, qr_code_response};
+use crate::state::{
+    DbConnection, DbConnectionPool, PaymentPage, PublicUrl, UsernameLookupRateLimiter,
+};
 
 #[derive(Deserialize)]
 pub struct GetUserPathParams {
@@ -28,8 +39,60 @@ pub struct GetUserPathParams {
 pub struct GetUserResponse {
     id: Uuid,
     username: String,
+    /// The balances below are those of the [`DEFAULT_CURRENCY`] account, kept
+    /// for older clients. See `accounts` for all currencies.
+    ///
+    /// The same as `ledger_balance`, kept for older clients.
     #[serde(with = "bigdecimal::serde::json_num")]
     balance: BigDecimal,
+    /// The balance which may be spent, i.e. the ledger balance minus what is
+    /// reserved by active holds.
+    #[serde(with = "bigdecimal::serde::json_num")]
+    available_balance: BigDecimal,
+    /// The balance after all settled transactions.
+    #[serde(with = "bigdecimal::serde::json_num")]
+    ledger_balance: BigDecimal,
+    role: UserRole,
+    tier: UserTier,
+    kind: UserKind,
+    accounts: Vec<AccountResponse>,
+    /// Money set aside from the accounts, which is not included in their
+    /// balances.
+    pockets: Vec<PocketResponse>,
+}
+
+#[derive(Deserialize)]
+pub struct PutUserTierPayload {
+    tier: UserTier,
+}
+
+#[derive(Serialize)]
+pub struct PutUserTierResponse {
+    id: Uuid,
+    tier: UserTier,
+}
+
+#[derive(Deserialize)]
+pub struct PutUserKindPayload {
+    kind: UserKind,
+}
+
+#[derive(Serialize)]
+pub struct PutUserKindResponse {
+    id: Uuid,
+    kind: UserKind,
+}
+
+#[derive(Deserialize)]
+pub struct GetUserLookupQueryParams {
+    username: String,
+}
+
+/// The public profile of a user, as shown to other users.
+#[derive(Serialize)]
+pub struct GetUserLookupResponse {
+    id: Uuid,
+    username: String,
 }
 
 #[derive(Deserialize)]
@@ -37,6 +100,12 @@ pub struct GetTransactionsPathParams {
     user_id: Uuid,
 }
 
+#[derive(Deserialize)]
+pub struct GetTransactionsQueryParams {
+    /// Only include transactions whose memo contains this text.
+    memo: Option<String>,
+}
+
 #[derive(Serialize)]
 pub struct GetTransactionsResponse {
     transactions: Vec<TransactionResponse>,
@@ -47,9 +116,20 @@ pub struct TransactionResponse {
     id: Uuid,
     #[serde(with = "bigdecimal::serde::json_num")]
     amount: BigDecimal,
+    currency: Currency,
     recipient: Uuid,
     sender: Uuid,
     timestamp: jiff::Timestamp,
+    memo: Option<String>,
+    reference: Option<String>,
+    kind: TransactionKind,
+    original_transaction_id: Option<Uuid>,
+    flagged: bool,
+    conversion: Option<ConversionResponse>,
+    #[serde(with = "bigdecimal::serde::json_num_option")]
+    fee: Option<BigDecimal>,
+    /// The pocket of the user, for pocket deposits and withdrawals.
+    pocket_id: Option<Uuid>,
 }
 
 pub async fn get_user(
@@ -83,10 +163,210 @@ pub async fn get_user(
         .context("could not find user")
         .map_err(AppError::from)?;
 
+    let accounts = account_responses(&mut conn, user.id, jiff::Timestamp::now())
+        .await
+        .map_err(AppError::from)?;
+    let (balance, available_balance) = accounts
+        .iter()
+        .find(|account| account.currency == DEFAULT_CURRENCY)
+        .map(|account| (account.balance.clone(), account.available_balance.clone()))
+        .unwrap_or_default();
+    let pockets = pocket_responses(&mut conn, user.id)
+        .await
+        .map_err(AppError::from)?;
+
     Ok(Json(GetUserResponse {
         id: user.id,
         username: user.username,
-        balance: user.balance,
+        available_balance,
+        ledger_balance: balance.clone(),
+        balance,
+        role: user.role,
+        tier: user.tier,
+        kind: user.kind,
+        accounts,
+        pockets,
+    }))
+}
+
+/// Sets the tier of a user, which decides the fee rules that apply to them.
+///
+/// Only admins may set tiers.
+pub async fn put_user_tier(
+    State(pool): State<DbConnectionPool>,
+    Extension(authenticated_user): Extension<AuthenticatedUser>,
+    Path(GetUserPathParams { user_id }): Path<GetUserPathParams>,
+    WithRejection(Json(payload), _): WithRejection<Json<PutUserTierPayload>, JsonRejection>,
+) -> Result<Json<PutUserTierResponse>> {
+    use crate::models::types;
+    use crate::schema::users;
+
+    let mut conn = pool
+        .get()
+        .await
+        .context("failed to get database connection")
+        .map_err(AppError::from)?;
+
+    if !is_admin(&mut conn, authenticated_user.subject)
+        .await
+        .map_err(AppError::from)?
+    {
+        return Err((
+            StatusCode::FORBIDDEN,
+            Json(json!({
+                "title": "PermissionDenied",
+            })),
+        ))?;
+    }
+
+    let updated = diesel::update(users::table.find(types::Uuid::from(user_id)))
+        .set(users::tier.eq(payload.tier))
+        .execute(&mut conn)
+        .await
+        .context("failed to update user tier")
+        .map_err(AppError::from)?;
+    if updated == 0 {
+        debug!(%user_id, "could not find user");
+
+        return Err((
+            StatusCode::NOT_FOUND,
+            Json(json!({
+                "title": "UserNotFound",
+            })),
+        ))?;
+    }
+
+    Ok(Json(PutUserTierResponse {
+        id: user_id,
+        tier: payload.tier,
+    }))
+}
+
+/// Sets the kind of a user, e.g. to let a business create checkout sessions.
+///
+/// Only admins may set kinds.
+pub async fn put_user_kind(
+    State(pool): State<DbConnectionPool>,
+    Extension(authenticated_user): Extension<AuthenticatedUser>,
+    Path(GetUserPathParams { user_id }): Path<GetUserPathParams>,
+    WithRejection(Json(payload), _): WithRejection<Json<PutUserKindPayload>, JsonRejection>,
+) -> Result<Json<PutUserKindResponse>> {
+    use crate::models::types;
+    use crate::schema::users;
+
+    let mut conn = pool
+        .get()
+        .await
+        .context("failed to get database connection")
+        .map_err(AppError::from)?;
+
+    if !is_admin(&mut conn, authenticated_user.subject)
+        .await
+        .map_err(AppError::from)?
+    {
+        return Err((
+            StatusCode::FORBIDDEN,
+            Json(json!({
+                "title": "PermissionDenied",
+            })),
+        ))?;
+    }
+
+    let updated = diesel::update(
+        users::table
+            .find(types::Uuid::from(user_id))
+            .filter(users::role.ne(UserRole::System)),
+    )
+    .set(users::kind.eq(payload.kind))
+    .execute(&mut conn)
+    .await
+    .context("failed to update user kind")
+    .map_err(AppError::from)?;
+    if updated == 0 {
+        debug!(%user_id, "could not find user");
+
+        return Err((
+            StatusCode::NOT_FOUND,
+            Json(json!({
+                "title": "UserNotFound",
+            })),
+        ))?;
+    }
+
+    Ok(Json(PutUserKindResponse {
+        id: user_id,
+        kind: payload.kind,
+    }))
+}
+
+/// Renders the receive code of the authenticated user as a QR code, which
+/// opens the frontend with the user filled in as the recipient.
+pub async fn get_user_qr_code(
+    State(public_url): State<PublicUrl>,
+    Extension(authenticated_user): Extension<AuthenticatedUser>,
+    Path(GetUserPathParams { user_id }): Path<GetUserPathParams>,
+    Query(options): Query<QrCodeOptions>,
+) -> Result<Response> {
+    if authenticated_user.subject != user_id {
+        return Err((
+            StatusCode::FORBIDDEN,
+            Json(json!({
+                "title": "PermissionDenied",
+            })),
+        ))?;
+    }
+
+    Ok(qr_code_response(
+        public_url.payment_uri(PaymentPage::User(user_id)).as_str(),
+        options,
+    ))
+}
+
+/// Resolves a username to a public profile, so that the sender can confirm who
+/// they are paying.
+pub async fn get_user_lookup(
+    State(pool): State<DbConnectionPool>,
+    State(rate_limiter): State<UsernameLookupRateLimiter>,
+    Extension(authenticated_user): Extension<AuthenticatedUser>,
+    Query(GetUserLookupQueryParams { username }): Query<GetUserLookupQueryParams>,
+) -> Result<Json<GetUserLookupResponse>> {
+    use crate::schema::users;
+
+    check_username_lookup(&rate_limiter, authenticated_user.subject)?;
+
+    let mut conn = pool
+        .get()
+        .await
+        .context("failed to get database connection")
+        .map_err(AppError::from)?;
+
+    let user: User = match users::table
+        .filter(users::username.eq(&username))
+        .select(User::as_select())
+        .first(&mut conn)
+        .await
+    {
+        Ok(user) => user,
+        Err(diesel::NotFound) => {
+            debug!(username, "could not find user");
+
+            return Err((
+                StatusCode::NOT_FOUND,
+                Json(json!({
+                    "title": "UserNotFound",
+                })),
+            ))?;
+        },
+        Err(err) => {
+            return Err(err)
+                .context("failed to query users")
+                .map_err(AppError::from)?;
+        },
+    };
+
+    Ok(Json(GetUserLookupResponse {
+        id: user.id,
+        username: user.username,
     }))
 }
 
@@ -94,6 +374,7 @@ pub async fn get_transactions(
     State(pool): State<DbConnectionPool>,
     Extension(authenticated_user): Extension<AuthenticatedUser>,
     Path(GetTransactionsPathParams { user_id }): Path<GetTransactionsPathParams>,
+    Query(GetTransactionsQueryParams { memo }): Query<GetTransactionsQueryParams>,
 ) -> Result<Json<GetTransactionsResponse>> {
     use crate::models::types;
     use crate::schema::transactions;
@@ -113,7 +394,7 @@ pub async fn get_transactions(
         ))?;
     }
 
-    let transactions: Vec<Transaction> = transactions::table
+    let mut query = transactions::table
         .filter(
             transactions::recipient
                 .eq(types::Uuid::from(user_id))
@@ -121,6 +402,17 @@ pub async fn get_transactions(
         )
         .select(Transaction::as_select())
         .order(transactions::timestamp.desc())
+        .into_boxed();
+    if let Some(memo) = memo.filter(|memo| !memo.is_empty()) {
+        // `LIKE` is case-insensitive for ASCII characters in SQLite.
+        query = query.filter(
+            transactions::memo
+                .like(format!("%{memo}%", memo = escape_like_pattern(&memo)))
+                .escape('\\'),
+        );
+    }
+
+    let transactions: Vec<Transaction> = query
         .load(&mut conn)
         .await
         .context("failed to query transactions")
@@ -129,13 +421,91 @@ pub async fn get_transactions(
     Ok(Json(GetTransactionsResponse {
         transactions: transactions
             .into_iter()
-            .map(|transaction| TransactionResponse {
-                id: transaction.id,
-                amount: transaction.amount,
-                recipient: transaction.recipient,
-                sender: transaction.sender,
-                timestamp: transaction.timestamp,
+            .map(|transaction| {
+                let conversion = ConversionResponse::new(&transaction);
+
+                TransactionResponse {
+                    id: transaction.id,
+                    amount: transaction.amount,
+                    currency: transaction.currency,
+                    recipient: transaction.recipient,
+                    sender: transaction.sender,
+                    timestamp: transaction.timestamp,
+                    memo: transaction.memo,
+                    reference: transaction.reference,
+                    kind: transaction.kind,
+                    original_transaction_id: transaction.original_transaction_id,
+                    flagged: transaction.flagged,
+                    conversion,
+                    fee: transaction.fee,
+                    pocket_id: transaction.pocket_id,
+                }
             })
             .collect(),
     }))
 }
+
+/// Whether `user_id` is an admin.
+pub async fn is_admin(conn: &mut DbConnection, user_id: Uuid) -> anyhow::Result<bool> {
+    use crate::models::types;
+    use crate::schema::users;
+
+    let user: User = users::table
+        .find(types::Uuid::from(user_id))
+        .select(User::as_select())
+        .first(conn)
+        .await
+        .context("could not find user")?;
+
+    Ok(user.role == UserRole::Admin)
+}
+
+/// Escapes the wildcard characters of a `LIKE` pattern, using `\\` as the escape
+/// character.
+fn escape_like_pattern(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        if matches!(c, '%' | '_' | '\\') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// Records that `user_id` resolves a username to a user, failing once they have
+/// done so too often.
+///
+/// # Security
+///
+/// Resolving a username reveals whether it exists, so this is rate limited
+/// wherever users refer to others by username, to slow down account
+/// enumeration.
+///
+/// See <https://owasp.org/www-project-web-security-testing-guide/stable/4-Web_Application_Security_Testing/03-Identity_Management_Testing/04-Testing_for_Account_Enumeration_and_Guessable_User_Account>
+#[allow(
+    clippy::result_large_err,
+    reason = "the error is returned from handlers, whose errors are just as large"
+)]
+pub(crate) fn check_username_lookup(
+    rate_limiter: &UsernameLookupRateLimiter,
+    user_id: Uuid,
+) -> Result<(), Response> {
+    if let Err(retry_after) = rate_limiter.0.check(user_id) {
+        debug!(%user_id, "username lookup rate limited");
+
+        return Err((
+            StatusCode::TOO_MANY_REQUESTS,
+            [(
+                header::RETRY_AFTER,
+                retry_after.as_secs().saturating_add(1).to_string(),
+            )],
+            Json(json!({
+                "title": "TooManyRequests",
+            })),
+        )
+            .into_response());
+    }
+
+    Ok(())
+}
@@ -0,0 +1,546 @@
+use std::collections::BTreeSet;
+
+use anyhow::Context as _;
+use axum::extract::{Path, Query, State};
+use axum::http::StatusCode;
+use axum::response::{IntoResponse as _, Response, Result};
+use axum::{Extension, Json};
+use axum_extra::extract::WithRejection;
+use diesel::prelude::*;
+#[allow(
+    clippy::unused_trait_names,
+    reason = "error[E0034]: multiple applicable items in scope"
+)]
+use diesel_async::RunQueryDsl;
+use secrecy::ExposeSecret as _;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tracing::debug;
+use uuid::Uuid;
+
+use crate::error::{AppError, JsonRejection};
+use crate::handlers::user::is_admin;
+use crate::http_url::{self, HostPolicy};
+use crate::ledger;
+use crate::middleware::auth::AuthenticatedUser;
+use crate::models::webhook::{WebhookDeliveryStatus, WebhookEventType, WebhookSubscription};
+use crate::models::{WebhookDelivery, WebhookEndpoint};
+use crate::state::{DbConnection, DbConnectionPool};
+use crate::webhook;
+
+/// Maximum number of webhook endpoints of a user.
+const MAX_WEBHOOK_ENDPOINTS: i64 = 10;
+
+/// The maximum length of a webhook endpoint URL, in bytes.
+const WEBHOOK_URL_MAX_LEN: usize = 2048;
+
+/// The maximum number of deliveries listed, newest first.
+const DELIVERIES_LIMIT: i64 = 100;
+
+#[derive(Deserialize)]
+pub struct WebhookEndpointPathParams {
+    webhook_endpoint_id: Uuid,
+}
+
+#[derive(Deserialize)]
+pub struct WebhookDeliveryPathParams {
+    webhook_endpoint_id: Uuid,
+    delivery_id: Uuid,
+}
+
+#[derive(Deserialize)]
+pub struct GetWebhookDeliveriesQueryParams {
+    status: Option<WebhookDeliveryStatus>,
+}
+
+#[derive(Deserialize)]
+pub struct PostWebhookEndpointPayload {
+    url: String,
+    /// The event types to send to the endpoint.
+    events: Vec<WebhookEventType>,
+    /// Whether to send the events of all users rather than only those of the
+    /// authenticated user. Only admins may set this.
+    #[serde(default)]
+    all_users: bool,
+}
+
+#[derive(Serialize)]
+pub struct GetWebhookEndpointsResponse {
+    webhook_endpoints: Vec<WebhookEndpointResponse>,
+}
+
+#[derive(Serialize)]
+pub struct WebhookEndpointResponse {
+    id: Uuid,
+    url: String,
+    events: BTreeSet<WebhookEventType>,
+    all_users: bool,
+    created_at: jiff::Timestamp,
+    /// The key which deliveries are signed with, only shown when the endpoint
+    /// is created.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    secret: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct GetWebhookDeliveriesResponse {
+    deliveries: Vec<WebhookDeliveryResponse>,
+}
+
+#[derive(Serialize)]
+pub struct WebhookDeliveryResponse {
+    id: Uuid,
+    event_id: Uuid,
+    event_type: WebhookEventType,
+    payload: serde_json::Value,
+    status: WebhookDeliveryStatus,
+    attempts: i32,
+    /// When the delivery will next be attempted, if it is pending.
+    next_attempt_at: Option<jiff::Timestamp>,
+    last_attempt_at: Option<jiff::Timestamp>,
+    last_response_status: Option<i32>,
+    last_error: Option<String>,
+    created_at: jiff::Timestamp,
+    delivered_at: Option<jiff::Timestamp>,
+}
+
+impl WebhookEndpointResponse {
+    fn new(endpoint: WebhookEndpoint, events: BTreeSet<WebhookEventType>) -> Self {
+        Self {
+            id: endpoint.id,
+            url: endpoint.url,
+            events,
+            all_users: endpoint.all_users,
+            created_at: endpoint.created_at,
+            secret: None,
+        }
+    }
+}
+
+impl TryFrom<WebhookDelivery> for WebhookDeliveryResponse {
+    type Error = anyhow::Error;
+
+    fn try_from(delivery: WebhookDelivery) -> anyhow::Result<Self> {
+        Ok(Self {
+            id: delivery.id,
+            event_id: delivery.event_id,
+            event_type: delivery.event_type,
+            payload: serde_json::from_str(&delivery.payload)
+                .context("failed to parse webhook delivery payload")?,
+            status: delivery.status,
+            attempts: delivery.attempts,
+            next_attempt_at: (delivery.status == WebhookDeliveryStatus::Pending)
+                .then_some(delivery.next_attempt_at),
+            last_attempt_at: delivery.last_attempt_at,
+            last_response_status: delivery.last_response_status,
+            last_error: delivery.last_error,
+            created_at: delivery.created_at,
+            delivered_at: delivery.delivered_at,
+        })
+    }
+}
+
+/// Registers a webhook endpoint of the authenticated user, which is sent the
+/// events it subscribes to.
+///
+/// The secret which deliveries are signed with is only returned here.
+pub async fn post_webhook_endpoint(
+    State(pool): State<DbConnectionPool>,
+    State(host_policy): State<HostPolicy>,
+    Extension(authenticated_user): Extension<AuthenticatedUser>,
+    WithRejection(Json(payload), _): WithRejection<Json<PostWebhookEndpointPayload>, JsonRejection>,
+) -> Result<(StatusCode, Json<WebhookEndpointResponse>)> {
+    use crate::models::types;
+    use crate::schema::{webhook_endpoints, webhook_subscriptions};
+
+    let mut conn = pool
+        .get()
+        .await
+        .context("failed to get database connection")
+        .map_err(AppError::from)?;
+
+    let user_id = authenticated_user.subject;
+    if payload.all_users && !is_admin(&mut conn, user_id).await.map_err(AppError::from)? {
+        return Err((
+            StatusCode::FORBIDDEN,
+            Json(json!({
+                "title": "PermissionDenied",
+                "detail": "only admins may receive the events of all users",
+            })),
+        ))?;
+    }
+
+    let url = match http_url::validate(&payload.url, WEBHOOK_URL_MAX_LEN) {
+        Ok(url) => http_url::check_host(&url, host_policy).await.map(|()| url),
+        Err(detail) => Err(detail),
+    };
+    if let Err(detail) = url {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(json!({
+                "title": "InvalidWebhookUrl",
+                "detail": detail,
+            })),
+        ))?;
+    }
+    let events: BTreeSet<WebhookEventType> = payload.events.into_iter().collect();
+    if events.is_empty() {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(json!({
+                "title": "InvalidEvents",
+                "detail": "subscribe to at least one event type",
+            })),
+        ))?;
+    }
+
+    let endpoint_count: i64 = webhook_endpoints::table
+        .filter(webhook_endpoints::user_id.eq(types::Uuid::from(user_id)))
+        .count()
+        .get_result(&mut conn)
+        .await
+        .context("failed to count webhook endpoints")
+        .map_err(AppError::from)?;
+    if endpoint_count >= MAX_WEBHOOK_ENDPOINTS {
+        return Err((
+            StatusCode::CONFLICT,
+            Json(json!({
+                "title": "TooManyWebhookEndpoints",
+                "detail": format!("a user may have at most {MAX_WEBHOOK_ENDPOINTS} webhook endpoints"),
+            })),
+        ))?;
+    }
+
+    let secret = webhook::generate_secret();
+    let now = jiff::Timestamp::now();
+    let created_endpoint = ledger::run_immediate_transaction(&mut conn, |conn| {
+        let (url, secret, events) = (payload.url.clone(), secret.clone(), events.clone());
+        Box::pin(async move {
+            let new_endpoint = WebhookEndpoint {
+                id: Uuid::now_v7(),
+                user_id,
+                url,
+                secret,
+                all_users: payload.all_users,
+                created_at: now,
+            };
+            let created_endpoint: WebhookEndpoint = diesel::insert_into(webhook_endpoints::table)
+                .values(new_endpoint)
+                .returning(WebhookEndpoint::as_returning())
+                .get_result(conn)
+                .await
+                .context("failed to insert webhook endpoint")?;
+
+            for event_type in events {
+                let subscription = WebhookSubscription {
+                    endpoint_id: created_endpoint.id,
+                    event_type,
+                };
+                diesel::insert_into(webhook_subscriptions::table)
+                    .values(subscription)
+                    .execute(conn)
+                    .await
+                    .context("failed to insert webhook subscription")?;
+            }
+
+            Ok(created_endpoint)
+        })
+    })
+    .await
+    .map_err(AppError::from)?;
+
+    let mut response = WebhookEndpointResponse::new(created_endpoint, events);
+    response.secret = Some(secret.expose_secret().to_owned());
+
+    Ok((StatusCode::CREATED, Json(response)))
+}
+
+/// Returns the webhook endpoints of the authenticated user, oldest first.
+pub async fn get_webhook_endpoints(
+    State(pool): State<DbConnectionPool>,
+    Extension(authenticated_user): Extension<AuthenticatedUser>,
+) -> Result<Json<GetWebhookEndpointsResponse>> {
+    use crate::models::types;
+    use crate::schema::webhook_endpoints;
+
+    let mut conn = pool
+        .get()
+        .await
+        .context("failed to get database connection")
+        .map_err(AppError::from)?;
+
+    let endpoints: Vec<WebhookEndpoint> = webhook_endpoints::table
+        .filter(webhook_endpoints::user_id.eq(types::Uuid::from(authenticated_user.subject)))
+        .order(webhook_endpoints::id.asc())
+        .select(WebhookEndpoint::as_select())
+        .load(&mut conn)
+        .await
+        .context("failed to query webhook endpoints")
+        .map_err(AppError::from)?;
+
+    let mut webhook_endpoints = Vec::with_capacity(endpoints.len());
+    for endpoint in endpoints {
+        let events = subscribed_events(&mut conn, endpoint.id)
+            .await
+            .map_err(AppError::from)?;
+        webhook_endpoints.push(WebhookEndpointResponse::new(endpoint, events));
+    }
+
+    Ok(Json(GetWebhookEndpointsResponse { webhook_endpoints }))
+}
+
+/// Returns a webhook endpoint of the authenticated user.
+pub async fn get_webhook_endpoint(
+    State(pool): State<DbConnectionPool>,
+    Extension(authenticated_user): Extension<AuthenticatedUser>,
+    Path(WebhookEndpointPathParams {
+        webhook_endpoint_id,
+    }): Path<WebhookEndpointPathParams>,
+) -> Result<Json<WebhookEndpointResponse>> {
+    let mut conn = pool
+        .get()
+        .await
+        .context("failed to get database connection")
+        .map_err(AppError::from)?;
+
+    let endpoint =
+        match find_webhook_endpoint(&mut conn, authenticated_user.subject, webhook_endpoint_id)
+            .await
+            .map_err(AppError::from)?
+        {
+            Ok(endpoint) => endpoint,
+            Err(response) => return Err(response)?,
+        };
+    let events = subscribed_events(&mut conn, endpoint.id)
+        .await
+        .map_err(AppError::from)?;
+
+    Ok(Json(WebhookEndpointResponse::new(endpoint, events)))
+}
+
+/// Deletes a webhook endpoint of the authenticated user, along with its
+/// deliveries, including those which are still pending.
+pub async fn delete_webhook_endpoint(
+    State(pool): State<DbConnectionPool>,
+    Extension(authenticated_user): Extension<AuthenticatedUser>,
+    Path(WebhookEndpointPathParams {
+        webhook_endpoint_id,
+    }): Path<WebhookEndpointPathParams>,
+) -> Result<StatusCode> {
+    use crate::models::types;
+    use crate::schema::{webhook_deliveries, webhook_endpoints, webhook_subscriptions};
+
+    let mut conn = pool
+        .get()
+        .await
+        .context("failed to get database connection")
+        .map_err(AppError::from)?;
+
+    let endpoint =
+        match find_webhook_endpoint(&mut conn, authenticated_user.subject, webhook_endpoint_id)
+            .await
+            .map_err(AppError::from)?
+        {
+            Ok(endpoint) => endpoint,
+            Err(response) => return Err(response)?,
+        };
+
+    ledger::run_immediate_transaction(&mut conn, |conn| {
+        Box::pin(async move {
+            let endpoint_id = types::Uuid::from(endpoint.id);
+
+            diesel::delete(
+                webhook_deliveries::table.filter(webhook_deliveries::endpoint_id.eq(endpoint_id)),
+            )
+            .execute(conn)
+            .await
+            .context("failed to delete webhook deliveries")?;
+
+            diesel::delete(
+                webhook_subscriptions::table
+                    .filter(webhook_subscriptions::endpoint_id.eq(endpoint_id)),
+            )
+            .execute(conn)
+            .await
+            .context("failed to delete webhook subscriptions")?;
+
+            diesel::delete(webhook_endpoints::table.find(endpoint_id))
+                .execute(conn)
+                .await
+                .context("failed to delete webhook endpoint")?;
+
+            Ok(())
+        })
+    })
+    .await
+    .map_err(AppError::from)?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Returns the latest deliveries to a webhook endpoint of the authenticated
+/// user, newest first.
+pub async fn get_webhook_deliveries(
+    State(pool): State<DbConnectionPool>,
+    Extension(authenticated_user): Extension<AuthenticatedUser>,
+    Path(WebhookEndpointPathParams {
+        webhook_endpoint_id,
+    }): Path<WebhookEndpointPathParams>,
+    Query(GetWebhookDeliveriesQueryParams { status }): Query<GetWebhookDeliveriesQueryParams>,
+) -> Result<Json<GetWebhookDeliveriesResponse>> {
+    use crate::models::types;
+    use crate::schema::webhook_deliveries;
+
+    let mut conn = pool
+        .get()
+        .await
+        .context("failed to get database connection")
+        .map_err(AppError::from)?;
+
+    let endpoint =
+        match find_webhook_endpoint(&mut conn, authenticated_user.subject, webhook_endpoint_id)
+            .await
+            .map_err(AppError::from)?
+        {
+            Ok(endpoint) => endpoint,
+            Err(response) => return Err(response)?,
+        };
+
+    let mut query = webhook_deliveries::table
+        .filter(webhook_deliveries::endpoint_id.eq(types::Uuid::from(endpoint.id)))
+        .into_boxed();
+    if let Some(status) = status {
+        query = query.filter(webhook_deliveries::status.eq(status));
+    }
+    let deliveries: Vec<WebhookDelivery> = query
+        .order(webhook_deliveries::id.desc())
+        .limit(DELIVERIES_LIMIT)
+        .select(WebhookDelivery::as_select())
+        .load(&mut conn)
+        .await
+        .context("failed to query webhook deliveries")
+        .map_err(AppError::from)?;
+
+    let deliveries = deliveries
+        .into_iter()
+        .map(WebhookDeliveryResponse::try_from)
+        .collect::<anyhow::Result<_>>()
+        .map_err(AppError::from)?;
+
+    Ok(Json(GetWebhookDeliveriesResponse { deliveries }))
+}
+
+/// Sends a delivery to a webhook endpoint of the authenticated user again, with
+/// a fresh set of attempts, e.g. once a dead endpoint is back up.
+///
+/// The event ID stays the same, so receivers which already processed the event
+/// can tell.
+pub async fn post_webhook_delivery_replay(
+    State(pool): State<DbConnectionPool>,
+    Extension(authenticated_user): Extension<AuthenticatedUser>,
+    Path(WebhookDeliveryPathParams {
+        webhook_endpoint_id,
+        delivery_id,
+    }): Path<WebhookDeliveryPathParams>,
+) -> Result<Json<WebhookDeliveryResponse>> {
+    use crate::models::types;
+    use crate::schema::webhook_deliveries;
+
+    let mut conn = pool
+        .get()
+        .await
+        .context("failed to get database connection")
+        .map_err(AppError::from)?;
+
+    let endpoint =
+        match find_webhook_endpoint(&mut conn, authenticated_user.subject, webhook_endpoint_id)
+            .await
+            .map_err(AppError::from)?
+        {
+            Ok(endpoint) => endpoint,
+            Err(response) => return Err(response)?,
+        };
+
+    let Some(delivery): Option<WebhookDelivery> = webhook_deliveries::table
+        .find(types::Uuid::from(delivery_id))
+        .filter(webhook_deliveries::endpoint_id.eq(types::Uuid::from(endpoint.id)))
+        .select(WebhookDelivery::as_select())
+        .first(&mut conn)
+        .await
+        .optional()
+        .context("failed to query webhook deliveries")
+        .map_err(AppError::from)?
+    else {
+        debug!(%delivery_id, "could not find webhook delivery");
+
+        return Err((
+            StatusCode::NOT_FOUND,
+            Json(json!({
+                "title": "WebhookDeliveryNotFound",
+            })),
+        ))?;
+    };
+
+    let delivery = webhook::replay(&mut conn, delivery)
+        .await
+        .map_err(AppError::from)?;
+    let delivery = WebhookDeliveryResponse::try_from(delivery).map_err(AppError::from)?;
+
+    Ok(Json(delivery))
+}
+
+/// Finds a webhook endpoint of `user_id`.
+async fn find_webhook_endpoint(
+    conn: &mut DbConnection,
+    user_id: Uuid,
+    webhook_endpoint_id: Uuid,
+) -> anyhow::Result<Result<WebhookEndpoint, Response>> {
+    use crate::models::types;
+    use crate::schema::webhook_endpoints;
+
+    let endpoint: Option<WebhookEndpoint> = webhook_endpoints::table
+        .find(types::Uuid::from(webhook_endpoint_id))
+        .select(WebhookEndpoint::as_select())
+        .first(conn)
+        .await
+        .optional()
+        .context("failed to query webhook endpoints")?;
+
+    match endpoint {
+        Some(endpoint) if endpoint.user_id == user_id => Ok(Ok(endpoint)),
+        // Don't reveal whether the endpoint exists to other users.
+        _ => {
+            debug!(%webhook_endpoint_id, "could not find webhook endpoint");
+
+            Ok(Err(webhook_endpoint_not_found()))
+        },
+    }
+}
+
+/// Returns the event types a webhook endpoint subscribes to.
+async fn subscribed_events(
+    conn: &mut DbConnection,
+    endpoint_id: Uuid,
+) -> anyhow::Result<BTreeSet<WebhookEventType>> {
+    use crate::models::types;
+    use crate::schema::webhook_subscriptions;
+
+    let events: Vec<WebhookEventType> = webhook_subscriptions::table
+        .filter(webhook_subscriptions::endpoint_id.eq(types::Uuid::from(endpoint_id)))
+        .select(webhook_subscriptions::event_type)
+        .load(conn)
+        .await
+        .context("failed to query webhook subscriptions")?;
+
+    Ok(events.into_iter().collect())
+}
+
+fn webhook_endpoint_not_found() -> Response {
+    (
+        StatusCode::NOT_FOUND,
+        Json(json!({
+            "title": "WebhookEndpointNotFound",
+        })),
+    )
+        .into_response()
+}
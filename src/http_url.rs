@@ -0,0 +1,143 @@
+//! Validation of HTTP(S) URLs which users give, for the service to send requests
+//! to or to send people to.
+//!
+//! Requests which the service sends itself, like webhook deliveries, must not be
+//! usable to reach the service's own host or private network, see
+//! [`check_host`].
+
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+
+use url::{Host, Url};
+
+/// Which hosts the service may send requests to.
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Default)]
+pub enum HostPolicy {
+    /// Only hosts whose addresses are all public.
+    #[default]
+    PublicOnly,
+    /// Any host, including loopback and private addresses. Only meant for tests
+    /// and local development.
+    Any,
+}
+
+/// Parses an absolute HTTP(S) URL of at most `max_len` characters.
+///
+/// Other schemes are rejected, so that a URL can neither run scripts when
+/// followed by a browser, nor reach anything but an HTTP server.
+pub fn validate(url: &str, max_len: usize) -> Result<Url, String> {
+    if url.len() > max_len {
+        return Err(format!("URL must be at most {max_len} characters"));
+    }
+    let url = Url::parse(url).map_err(|err| format!("invalid URL: {err}"))?;
+    if !matches!(url.scheme(), "http" | "https") {
+        return Err("URL must be http or https".to_owned());
+    }
+
+    Ok(url)
+}
+
+/// Checks that the service may send requests to the host of `url`, resolving it
+/// if it is a domain.
+///
+/// As a domain may resolve to other addresses later on, clients which send
+/// requests to such URLs should also resolve domains with [`resolve_public`].
+pub async fn check_host(url: &Url, policy: HostPolicy) -> Result<(), String> {
+    if policy == HostPolicy::Any {
+        return Ok(());
+    }
+
+    match url.host() {
+        Some(Host::Ipv4(ip)) => check_ip(IpAddr::V4(ip)),
+        Some(Host::Ipv6(ip)) => check_ip(IpAddr::V6(ip)),
+        Some(Host::Domain(domain)) => resolve_public(domain).await.map(|_addrs| ()),
+        None => Err("URL must have a host".to_owned()),
+    }
+}
+
+/// Resolves `domain`, failing unless all of its addresses are public.
+pub async fn resolve_public(domain: &str) -> Result<Vec<SocketAddr>, String> {
+    let addrs: Vec<SocketAddr> = tokio::net::lookup_host((domain, 0))
+        .await
+        .map_err(|err| format!("failed to resolve {domain}: {err}"))?
+        .collect();
+    if addrs.is_empty() {
+        return Err(format!("{domain} has no addresses"));
+    }
+    for addr in &addrs {
+        check_ip(addr.ip())?;
+    }
+
+    Ok(addrs)
+}
+
+fn check_ip(ip: IpAddr) -> Result<(), String> {
+    if is_public(ip) {
+        Ok(())
+    } else {
+        Err(format!("{ip} is not a public address"))
+    }
+}
+
+/// Returns whether `ip` is a public unicast address, rather than a loopback,
+/// private, link-local, unspecified or otherwise special one.
+pub fn is_public(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [a, b, ..] = ip.octets();
+            !(ip.is_loopback()
+                || ip.is_private()
+                || ip.is_link_local()
+                || ip.is_unspecified()
+                || ip.is_broadcast()
+                || ip.is_multicast()
+                || ip.is_documentation()
+                // "This network", 0.0.0.0/8.
+                || a == 0
+                // Shared address space of carrier-grade NAT, 100.64.0.0/10.
+                || (a == 100 && (b & 0xc0) == 64)
+                // Reserved, 240.0.0.0/4.
+                || a >= 240)
+        },
+        IpAddr::V6(ip) => match embedded_ipv4(ip) {
+            Some(ip) => is_public(IpAddr::V4(ip)),
+            None => {
+                let [a, b, ..] = ip.segments();
+                !(ip.is_loopback()
+                    || ip.is_unspecified()
+                    || ip.is_multicast()
+                    || ip.is_unique_local()
+                    || ip.is_unicast_link_local()
+                    // Documentation, 2001:db8::/32.
+                    || (a == 0x2001 && b == 0x0db8))
+            },
+        },
+    }
+}
+
+/// Returns the IPv4 address which `ip` reaches, if it embeds one.
+///
+/// Such addresses are translated to or tunnelled over IPv4, so they are only
+/// as public as the address they embed.
+fn embedded_ipv4(ip: Ipv6Addr) -> Option<Ipv4Addr> {
+    let [a, b, c, d, e, f, g, h] = ip.segments();
+    let from_segments = |high: u16, low: u16| {
+        let [b0, b1] = high.to_be_bytes();
+        let [b2, b3] = low.to_be_bytes();
+        Ipv4Addr::new(b0, b1, b2, b3)
+    };
+
+    match [a, b, c, d, e, f] {
+        // IPv4-mapped, ::ffff:0:0/96.
+        [0, 0, 0, 0, 0, 0xffff] => Some(from_segments(g, h)),
+        // IPv4-compatible, ::/96, apart from the unspecified and loopback
+        // addresses.
+        [0, 0, 0, 0, 0, 0] if !ip.is_unspecified() && !ip.is_loopback() => {
+            Some(from_segments(g, h))
+        },
+        // NAT64, 64:ff9b::/96.
+        [0x64, 0xff9b, 0, 0, 0, 0] => Some(from_segments(g, h)),
+        // 6to4, 2002::/16.
+        [0x2002, ..] => Some(from_segments(b, c)),
+        _ => None,
+    }
+}
@@ -0,0 +1,363 @@
+//! Interest on balances, which accrues daily and is paid out monthly from the
+//! treasury.
+//!
+//! Interest accrues on the balance at the time of the daily run, over a year of
+//! 365 days, and is kept at [`ACCRUAL_SCALE`] decimal places. Accruals are only
+//! ever recorded once per balance and day, so runs may be repeated safely.
+//!
+//! At the start of each month, what accrued before it is paid out per balance,
+//! rounded down to the minor unit of the currency. Accruals are marked as paid
+//! by the same database transaction which pays them, so they are never paid
+//! twice. What is left after rounding is carried over to the next payout, and
+//! balances which earned less than one minor unit are paid out in a later month,
+//! once they have earned enough.
+
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+use anyhow::Context as _;
+use bigdecimal::rounding::RoundingMode;
+use bigdecimal::{BigDecimal, Zero as _};
+use diesel::prelude::*;
+#[allow(
+    clippy::unused_trait_names,
+    reason = "error[E0034]: multiple applicable items in scope"
+)]
+use diesel_async::RunQueryDsl;
+use jiff::tz::TimeZone;
+use tracing::{error, info, warn};
+use uuid::Uuid;
+
+use crate::currency::Currency;
+use crate::event_hub::{EventHub, publish_transactions};
+use crate::ledger;
+use crate::models::interest_rate::InterestProduct;
+use crate::models::user::UserRole;
+use crate::models::{
+    Account, InterestAccrual, InterestCarryover, InterestRate, Pocket, Transaction,
+};
+use crate::state::{DbConnection, DbConnectionPool};
+
+/// How often to check whether interest is due to be accrued or paid.
+pub const POLL_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// The number of decimal places accruals are kept at, rounding down.
+pub const ACCRUAL_SCALE: i64 = 12;
+
+/// The number of days in a year, for daily interest.
+const DAYS_PER_YEAR: u32 = 365;
+
+/// Validates an annual interest rate, in percent.
+pub fn validate_rate(annual_rate: &BigDecimal) -> Result<(), String> {
+    if *annual_rate <= BigDecimal::zero() || *annual_rate > BigDecimal::from(100) {
+        return Err("annual rate must be more than 0 and at most 100 percent".to_owned());
+    }
+
+    Ok(())
+}
+
+/// Returns the interest earned by `balance` in one day at `annual_rate`, in
+/// percent, rounded down to [`ACCRUAL_SCALE`] decimal places.
+pub fn daily_interest(balance: &BigDecimal, annual_rate: &BigDecimal) -> BigDecimal {
+    let interest = balance * annual_rate / BigDecimal::from(100 * DAYS_PER_YEAR);
+
+    interest
+        .with_scale_round(ACCRUAL_SCALE, RoundingMode::Down)
+        .normalized()
+}
+
+/// Pays interest which is due and accrues interest for yesterday, forever.
+pub async fn run_interest(pool: DbConnectionPool, event_hub: EventHub) {
+    let mut interval = tokio::time::interval(POLL_INTERVAL);
+    interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+
+    loop {
+        interval.tick().await;
+
+        if let Err(err) = run_due_interest(&pool, &event_hub, jiff::Timestamp::now()).await {
+            error!(?err, "failed to run interest");
+        }
+    }
+}
+
+/// Accrues interest for the day before `now`, and pays out what accrued
+/// before the month of `now`, both in UTC. Payments are published to
+/// `event_hub`.
+///
+/// Days which were missed, e.g. because the service was down, are not accrued
+/// later, as their balances are no longer known.
+pub async fn run_due_interest(
+    pool: &DbConnectionPool,
+    event_hub: &EventHub,
+    now: jiff::Timestamp,
+) -> anyhow::Result<()> {
+    let mut conn = pool
+        .get()
+        .await
+        .context("failed to get database connection")?;
+
+    let today = now.to_zoned(TimeZone::UTC).date();
+    let yesterday = today.yesterday().context("date out of range")?;
+
+    let accrued = accrue(&mut conn, yesterday).await?;
+    if accrued > 0 {
+        info!(%yesterday, accrued, "accrued interest");
+    }
+
+    let payments = pay(&mut conn, today.first_of_month()).await?;
+    if !payments.is_empty() {
+        info!(payments = payments.len(), "paid interest");
+        publish_transactions(event_hub, &mut conn, &payments).await;
+    }
+
+    Ok(())
+}
+
+/// Returns the interest each balance with an interest rate earns on `date`,
+/// at its current balance, without recording it.
+pub async fn accruals(
+    conn: &mut DbConnection,
+    date: jiff::civil::Date,
+) -> anyhow::Result<Vec<InterestAccrual>> {
+    use crate::schema::{accounts, interest_rates, pockets, users};
+
+    let interest_rates: Vec<InterestRate> = interest_rates::table
+        .order((
+            interest_rates::product.asc(),
+            interest_rates::currency.asc(),
+        ))
+        .select(InterestRate::as_select())
+        .load(conn)
+        .await
+        .context("failed to query interest rates")?;
+
+    let now = jiff::Timestamp::now();
+    let mut accruals = Vec::new();
+    for interest_rate in interest_rates {
+        // Balances are stored as text, so they are compared here rather than
+        // in SQL.
+        let balances: Vec<(InterestProduct, Uuid, Uuid, BigDecimal)> = match interest_rate.product {
+            InterestProduct::Account => {
+                let accounts: Vec<Account> = accounts::table
+                    .inner_join(users::table)
+                    .filter(accounts::currency.eq(interest_rate.currency))
+                    .filter(users::role.ne(UserRole::System))
+                    .order(accounts::user_id.asc())
+                    .select(Account::as_select())
+                    .load(conn)
+                    .await
+                    .context("failed to query accounts")?;
+                accounts
+                    .into_iter()
+                    .map(|account| {
+                        (
+                            InterestProduct::Account,
+                            account.user_id,
+                            account.user_id,
+                            account.balance,
+                        )
+                    })
+                    .collect()
+            },
+            InterestProduct::Pocket => {
+                let pockets: Vec<Pocket> = pockets::table
+                    .filter(pockets::currency.eq(interest_rate.currency))
+                    .filter(pockets::archived_at.is_null())
+                    .order(pockets::id.asc())
+                    .select(Pocket::as_select())
+                    .load(conn)
+                    .await
+                    .context("failed to query pockets")?;
+                pockets
+                    .into_iter()
+                    .map(|pocket| {
+                        (
+                            InterestProduct::Pocket,
+                            pocket.id,
+                            pocket.user_id,
+                            pocket.balance,
+                        )
+                    })
+                    .collect()
+            },
+        };
+
+        for (product, holder_id, user_id, balance) in balances {
+            if balance <= BigDecimal::zero() {
+                continue;
+            }
+            let amount = daily_interest(&balance, &interest_rate.annual_rate);
+            if amount.is_zero() {
+                continue;
+            }
+
+            accruals.push(InterestAccrual {
+                product,
+                holder_id,
+                currency: interest_rate.currency,
+                accrued_on: date,
+                user_id,
+                balance,
+                annual_rate: interest_rate.annual_rate.clone(),
+                amount,
+                created_at: now,
+                transaction_id: None,
+            });
+        }
+    }
+
+    Ok(accruals)
+}
+
+/// Records the interest each balance earns on `date`, unless it was already
+/// recorded, and returns how many accruals were recorded.
+pub async fn accrue(conn: &mut DbConnection, date: jiff::civil::Date) -> anyhow::Result<usize> {
+    use crate::schema::interest_accruals;
+
+    // The write lock is held so that balances don't change while they are
+    // read.
+    ledger::run_immediate_transaction(conn, |conn| {
+        Box::pin(async move {
+            let mut recorded: usize = 0;
+            for accrual in accruals(conn, date).await? {
+                let inserted = diesel::insert_into(interest_accruals::table)
+                    .values(accrual)
+                    .on_conflict_do_nothing()
+                    .execute(conn)
+                    .await
+                    .context("failed to insert interest accrual")?;
+                recorded = recorded.saturating_add(inserted);
+            }
+
+            Ok(recorded)
+        })
+    })
+    .await
+}
+
+/// Pays out the interest which accrued before `before` and was not paid yet,
+/// with one transaction per balance, and returns the transactions.
+///
+/// Balances whose payment fails, e.g. because the treasury holds too little,
+/// are paid on a later run.
+pub async fn pay(
+    conn: &mut DbConnection,
+    before: jiff::civil::Date,
+) -> anyhow::Result<Vec<Transaction>> {
+    use crate::schema::interest_accruals;
+
+    let unpaid: Vec<InterestAccrual> = interest_accruals::table
+        .filter(interest_accruals::transaction_id.is_null())
+        .filter(interest_accruals::accrued_on.lt(jiff_diesel::Date::from(before)))
+        .select(InterestAccrual::as_select())
+        .load(conn)
+        .await
+        .context("failed to query interest accruals")?;
+
+    let mut holders: BTreeMap<(InterestProduct, Uuid, Currency), Uuid> = BTreeMap::new();
+    for accrual in unpaid {
+        holders.insert(
+            (accrual.product, accrual.holder_id, accrual.currency),
+            accrual.user_id,
+        );
+    }
+
+    let mut payments = Vec::new();
+    for ((product, holder_id, currency), user_id) in holders {
+        let payment = ledger::run_immediate_transaction(conn, |conn| {
+            Box::pin(async move {
+                pay_holder(conn, product, holder_id, currency, user_id, before).await
+            })
+        })
+        .await?;
+        payments.extend(payment);
+    }
+
+    Ok(payments)
+}
+
+/// Pays out the interest which one balance accrued before `before`, within an
+/// already open database transaction.
+async fn pay_holder(
+    conn: &mut DbConnection,
+    product: InterestProduct,
+    holder_id: Uuid,
+    currency: Currency,
+    user_id: Uuid,
+    before: jiff::civil::Date,
+) -> anyhow::Result<Option<Transaction>> {
+    use crate::models::types;
+    use crate::schema::{interest_accruals, interest_carryovers};
+
+    // The accruals are loaded again, so that they are not paid twice if
+    // another run paid them in the meantime.
+    let unpaid = interest_accruals::table
+        .filter(interest_accruals::product.eq(product))
+        .filter(interest_accruals::holder_id.eq(types::Uuid::from(holder_id)))
+        .filter(interest_accruals::currency.eq(currency))
+        .filter(interest_accruals::transaction_id.is_null())
+        .filter(interest_accruals::accrued_on.lt(jiff_diesel::Date::from(before)));
+
+    let accruals: Vec<InterestAccrual> = unpaid
+        .select(InterestAccrual::as_select())
+        .load(conn)
+        .await
+        .context("failed to query interest accruals")?;
+    let carryover: Option<types::BigDecimal> = interest_carryovers::table
+        .find((product, types::Uuid::from(holder_id), currency))
+        .select(interest_carryovers::amount)
+        .first(conn)
+        .await
+        .optional()
+        .context("failed to query interest carryovers")?;
+    let total = accruals
+        .iter()
+        .map(|accrual| &accrual.amount)
+        .sum::<BigDecimal>()
+        + carryover.map_or_else(BigDecimal::zero, BigDecimal::from);
+    let amount = total
+        .with_scale_round(i64::from(currency.minor_units()), RoundingMode::Down)
+        .normalized();
+    if amount.is_zero() {
+        return Ok(None);
+    }
+
+    let pocket_id = (product == InterestProduct::Pocket).then_some(holder_id);
+    let transaction =
+        match ledger::apply_interest(conn, user_id, pocket_id, &amount, currency).await? {
+            Ok(transaction) => transaction,
+            Err(err) => {
+                warn!(?err, %product, %holder_id, %currency, "could not pay interest");
+
+                return Ok(None);
+            },
+        };
+
+    diesel::update(unpaid)
+        .set(interest_accruals::transaction_id.eq(types::NullableUuid::from(Some(transaction.id))))
+        .execute(conn)
+        .await
+        .context("failed to update interest accruals")?;
+
+    let carryover = InterestCarryover {
+        product,
+        holder_id,
+        currency,
+        amount: (total - &amount).normalized(),
+        updated_at: jiff::Timestamp::now(),
+    };
+    diesel::insert_into(interest_carryovers::table)
+        .values(carryover.clone())
+        .on_conflict((
+            interest_carryovers::product,
+            interest_carryovers::holder_id,
+            interest_carryovers::currency,
+        ))
+        .do_update()
+        .set(carryover)
+        .execute(conn)
+        .await
+        .context("failed to upsert interest carryover")?;
+
+    Ok(Some(transaction))
+}
@@ -1,3 +1,18 @@
+use anyhow::Context as _;
+use biscuit::SingleOrMultiple::Single;
+use biscuit::jwa::SignatureAlgorithm;
+use biscuit::{ClaimsSet, JWT, RegisteredClaims, jws};
+use chrono::TimeDelta;
+use jiff::SpanRelativeTo;
+use secrecy::ExposeSecret as _;
+use uuid::Uuid;
+
+use crate::middleware::auth::JwtAccessTokenClaims;
+use crate::state::{
+    AccessTokenAudience, AccessTokenClientId, AccessTokenExpiration, AccessTokenIssuer,
+    JwsSigningSecret,
+};
+
 /// [RFC 7518, Section 3.2](https://datatracker.ietf.org/doc/html/rfc7518#section-3.2)
 ///
 /// > A key of the same size as the hash output (for instance, 256 bits for
@@ -9,3 +24,58 @@
 ///
 /// [NIST.800-107]: http://csrc.nist.gov/publications/nistpubs/800-107-rev1/sp800-107-rev1.pdf
 pub const HS256_SECRET_KEY_LEN: usize = 64;
+
+/// Issues a signed JWT access token for `subject`.
+///
+/// [RFC 9068, Section 2](https://datatracker.ietf.org/doc/html/rfc9068#section-2)
+pub fn encode_access_token(
+    subject: Uuid,
+    access_token_issuer: &AccessTokenIssuer,
+    access_token_expiration: AccessTokenExpiration,
+    access_token_audience: &AccessTokenAudience,
+    access_token_client_id: AccessTokenClientId,
+    jws_signing_secret: &JwsSigningSecret,
+) -> anyhow::Result<String> {
+    let now = chrono::Utc::now();
+
+    let access_token_max_age = access_token_expiration
+        .0
+        .to_duration(SpanRelativeTo::days_are_24_hours())
+        .map_err(anyhow::Error::from)
+        .and_then(|duration| std::time::Duration::try_from(duration).map_err(anyhow::Error::from))
+        .and_then(|duration| TimeDelta::from_std(duration).map_err(anyhow::Error::from))
+        .expect("converting `access_token_expiration` should not fail");
+
+    let access_token = JWT::<JwtAccessTokenClaims, biscuit::Empty>::new_decoded(
+        jws::RegisteredHeader {
+            algorithm: SignatureAlgorithm::HS256,
+            media_type: Some("at+jwt".to_owned()),
+            ..Default::default()
+        }
+        .into(),
+        ClaimsSet {
+            registered: RegisteredClaims {
+                issuer: Some(access_token_issuer.0.as_str().to_owned()),
+                expiry: Some(now.checked_add_signed(access_token_max_age).unwrap().into()),
+                audience: Some(Single(access_token_audience.0.as_str().to_owned())),
+                subject: Some(subject.to_string()),
+                issued_at: Some(now.into()),
+                id: Some(Uuid::new_v4().to_string()),
+                ..Default::default()
+            },
+            private: JwtAccessTokenClaims {
+                client_id: access_token_client_id.0.to_string(),
+            },
+        },
+    );
+    let access_token = access_token
+        .encode(&jws::Secret::Bytes(
+            jws_signing_secret.0.expose_secret().to_vec(),
+        ))
+        .context("failed to encode and sign access token")?;
+
+    Ok(access_token
+        .encoded()
+        .expect("`access_token` should be already encoded")
+        .to_string())
+}
@@ -0,0 +1,1655 @@
+use std::time::Duration;
+
+use anyhow::Context as _;
+use axum::Json;
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use bigdecimal::rounding::RoundingMode;
+use bigdecimal::{BigDecimal, Zero as _};
+use diesel::prelude::*;
+use diesel::result::DatabaseErrorKind;
+use diesel_async::AsyncConnection as _;
+#[allow(
+    clippy::unused_trait_names,
+    reason = "error[E0034]: multiple applicable items in scope"
+)]
+use diesel_async::RunQueryDsl;
+use scoped_futures::ScopedBoxFuture;
+use serde_json::json;
+use tracing::{debug, warn};
+use uuid::Uuid;
+
+use crate::currency::{Currency, DEFAULT_CURRENCY};
+use crate::fees;
+use crate::gift_code;
+use crate::limits::{self, Limit, LimitExceeded, TransferLimits};
+use crate::models::account::NewAccount;
+use crate::models::hold::HoldStatus;
+use crate::models::transaction::{NewTransaction, TransactionKind};
+use crate::models::transfer_batch::TransferBatchItem;
+use crate::models::user::UserRole;
+use crate::models::{Account, ExchangeQuote, Pocket, Transaction, TransferBatch, User};
+use crate::notification;
+use crate::state::DbConnection;
+use crate::webhook;
+
+/// The system user whose accounts hold money which was minted but not yet
+/// granted to users. All new money enters through the treasury.
+///
+/// Must match the user inserted by the migration which added the treasury.
+pub const TREASURY_USER_ID: Uuid = Uuid::from_u128(0x0000_0000_0000_7000_8000_0000_0000_0002);
+
+/// Maximum number of attempts at running a ledger transaction while the
+/// database is busy.
+const MAX_BUSY_ATTEMPTS: u32 = 5;
+
+/// Delay before retrying a ledger transaction after the first busy error. The
+/// delay doubles after every attempt.
+const BUSY_RETRY_BASE_DELAY: Duration = Duration::from_millis(20);
+
+/// A transfer of money from one user to another.
+#[derive(Clone, Debug)]
+pub struct NewTransfer {
+    pub amount: BigDecimal,
+    /// The currency of the sender's account, and of the recipient's unless
+    /// the amount is converted.
+    pub currency: Currency,
+    pub recipient: Uuid,
+    pub sender: Uuid,
+    pub memo: Option<String>,
+    pub reference: Option<String>,
+    /// A quote of the sender's to convert the amount at, into the currency of
+    /// the recipient's account. It must be for the same amount and currency.
+    pub quote_id: Option<Uuid>,
+}
+
+/// Reasons a transfer is rejected.
+#[derive(Clone, Debug)]
+pub enum TransferError {
+    InvalidAmount,
+    /// The amount is more precise than the minor unit of the currency.
+    AmountTooPrecise(Currency),
+    InvalidRecipient,
+    /// The sender or the recipient has no account in the currency.
+    CurrencyMismatch(Currency),
+    SelfTransfer,
+    InsufficientBalance,
+    LimitExceeded(LimitExceeded),
+    QuoteNotFound,
+    QuoteExpired,
+    /// The quote was already used for another transfer.
+    QuoteUsed,
+    /// The quote is for another amount or currency than the transfer.
+    QuoteMismatch,
+    /// The memo is invalid, for the given reason.
+    InvalidMemo(String),
+    /// The reference is invalid, for the given reason.
+    InvalidReference(String),
+    /// The amount can't be checked against the sender's transfer limits, which
+    /// are in [`DEFAULT_CURRENCY`], as there is no exchange rate for it.
+    RateUnavailable(Currency),
+}
+
+impl TransferError {
+    /// The title of the error response.
+    pub fn title(&self) -> &'static str {
+        match self {
+            Self::InvalidAmount | Self::AmountTooPrecise(_) => "InvalidAmount",
+            Self::InvalidRecipient => "InvalidRecipient",
+            Self::CurrencyMismatch(_) => "CurrencyMismatch",
+            Self::SelfTransfer => "SelfTransfer",
+            Self::InsufficientBalance => "InsufficientBalance",
+            Self::LimitExceeded(_) => "LimitExceeded",
+            Self::QuoteNotFound => "QuoteNotFound",
+            Self::QuoteExpired => "QuoteExpired",
+            Self::QuoteUsed => "QuoteUsed",
+            Self::QuoteMismatch => "QuoteMismatch",
+            Self::InvalidMemo(_) => "InvalidMemo",
+            Self::InvalidReference(_) => "InvalidReference",
+            Self::RateUnavailable(_) => "RateUnavailable",
+        }
+    }
+
+    /// The body of the error response.
+    pub fn body(&self) -> serde_json::Value {
+        match self {
+            Self::LimitExceeded(limit_exceeded) => {
+                let mut body = json!(limit_exceeded);
+                body["title"] = json!(self.title());
+                body["detail"] = json!(format!(
+                    "{} limit of {} exceeded",
+                    limit_exceeded.limit, limit_exceeded.max
+                ));
+                body
+            },
+            Self::AmountTooPrecise(currency) => json!({
+                "title": self.title(),
+                "detail": format!(
+                    "{currency} amounts may have at most {} decimal places",
+                    currency.minor_units()
+                ),
+            }),
+            Self::CurrencyMismatch(currency) => json!({
+                "title": self.title(),
+                "detail": format!("sender and recipient must both have a {currency} account"),
+            }),
+            Self::QuoteMismatch => json!({
+                "title": self.title(),
+                "detail": "amount and currency must match the quote",
+            }),
+            Self::InvalidMemo(detail) | Self::InvalidReference(detail) => json!({
+                "title": self.title(),
+                "detail": detail,
+            }),
+            Self::RateUnavailable(currency) => json!({
+                "title": self.title(),
+                "detail": format!(
+                    "no exchange rate from {currency} to {DEFAULT_CURRENCY}, which transfer limits are in"
+                ),
+            }),
+            _ => json!({
+                "title": self.title(),
+            }),
+        }
+    }
+}
+
+impl IntoResponse for TransferError {
+    fn into_response(self) -> Response {
+        let status = match &self {
+            Self::InvalidAmount
+            | Self::AmountTooPrecise(_)
+            | Self::InvalidRecipient
+            | Self::CurrencyMismatch(_)
+            | Self::SelfTransfer
+            | Self::QuoteMismatch
+            | Self::InvalidMemo(_)
+            | Self::InvalidReference(_) => StatusCode::BAD_REQUEST,
+            Self::QuoteNotFound => StatusCode::NOT_FOUND,
+            Self::QuoteExpired | Self::QuoteUsed => StatusCode::CONFLICT,
+            Self::InsufficientBalance => StatusCode::FORBIDDEN,
+            Self::RateUnavailable(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            Self::LimitExceeded(LimitExceeded {
+                limit: Limit::TransferCount,
+                ..
+            }) => StatusCode::TOO_MANY_REQUESTS,
+            Self::LimitExceeded(_) => StatusCode::FORBIDDEN,
+        };
+
+        (status, Json(self.body())).into_response()
+    }
+}
+
+/// Maximum number of transfers in a batch.
+pub const MAX_BATCH_TRANSFERS: usize = 100;
+
+/// Transfers from one sender to many recipients, which are made either all
+/// together or not at all.
+#[derive(Clone, Debug)]
+pub struct NewTransferBatch {
+    pub sender: Uuid,
+    /// The currency of all transfers.
+    pub currency: Currency,
+    pub transfers: Vec<NewBatchTransfer>,
+}
+
+/// A transfer of a batch.
+#[derive(Clone, Debug)]
+pub struct NewBatchTransfer {
+    pub amount: BigDecimal,
+    pub recipient: Uuid,
+    pub memo: Option<String>,
+    pub reference: Option<String>,
+}
+
+/// Reasons a batch is rejected.
+#[derive(Clone, Debug)]
+pub enum BatchError {
+    Empty,
+    TooLarge,
+    /// The transfers which failed, by their index in the batch.
+    Rejected(Vec<(usize, TransferError)>),
+}
+
+impl BatchError {
+    /// The title of the error response.
+    pub fn title(&self) -> &'static str {
+        match self {
+            Self::Empty | Self::TooLarge => "InvalidBatch",
+            Self::Rejected(_) => "BatchRejected",
+        }
+    }
+}
+
+impl IntoResponse for BatchError {
+    fn into_response(self) -> Response {
+        let status = match self {
+            Self::Empty | Self::TooLarge => StatusCode::BAD_REQUEST,
+            Self::Rejected(_) => StatusCode::UNPROCESSABLE_ENTITY,
+        };
+
+        let body = match &self {
+            Self::Empty => json!({
+                "title": self.title(),
+                "detail": "a batch must have at least one transfer",
+            }),
+            Self::TooLarge => json!({
+                "title": self.title(),
+                "detail": format!("a batch may have at most {MAX_BATCH_TRANSFERS} transfers"),
+            }),
+            Self::Rejected(errors) => json!({
+                "title": self.title(),
+                "detail": format!("{} transfers of the batch failed", errors.len()),
+                "errors": errors
+                    .iter()
+                    .map(|(index, err)| {
+                        let mut body = err.body();
+                        body["index"] = json!(index);
+                        body
+                    })
+                    .collect::<Vec<_>>(),
+            }),
+        };
+
+        (status, Json(body)).into_response()
+    }
+}
+
+/// Why the transfers of a batch are rolled back.
+enum BatchRollback {
+    Rejected(Vec<(usize, TransferError)>),
+    Failed(anyhow::Error),
+}
+
+impl From<diesel::result::Error> for BatchRollback {
+    fn from(err: diesel::result::Error) -> Self {
+        Self::Failed(err.into())
+    }
+}
+
+/// A refund or reversal of an earlier transfer.
+#[derive(Clone, Debug)]
+pub struct NewRefund {
+    pub original_transaction_id: Uuid,
+    /// The amount to send back, or everything not yet refunded if `None`.
+    pub amount: Option<BigDecimal>,
+    /// The user refunding, who must be the recipient of the original
+    /// transaction for a refund, or an admin for a reversal.
+    pub initiator: Uuid,
+    /// Either [`TransactionKind::Refund`] or [`TransactionKind::Reversal`].
+    pub kind: TransactionKind,
+    pub memo: Option<String>,
+}
+
+/// A mint of new money into the treasury, or a burn of money out of it.
+#[derive(Clone, Debug)]
+pub struct NewIssuance {
+    pub amount: BigDecimal,
+    pub currency: Currency,
+    /// The admin minting or burning, if any.
+    pub initiator: Option<Uuid>,
+    /// Either [`TransactionKind::Mint`] or [`TransactionKind::Burn`].
+    pub kind: TransactionKind,
+    pub memo: Option<String>,
+}
+
+/// Reasons a mint, burn or grant is rejected.
+#[derive(Clone, Debug)]
+pub enum IssuanceError {
+    InvalidAmount,
+    /// The amount is more precise than the minor unit of the currency.
+    AmountTooPrecise(Currency),
+    /// The treasury holds less than the amount to burn or grant.
+    InsufficientBalance,
+}
+
+impl IssuanceError {
+    /// The title of the error response.
+    pub fn title(&self) -> &'static str {
+        match self {
+            Self::InvalidAmount | Self::AmountTooPrecise(_) => "InvalidAmount",
+            Self::InsufficientBalance => "InsufficientBalance",
+        }
+    }
+}
+
+impl IntoResponse for IssuanceError {
+    fn into_response(self) -> Response {
+        let status = match self {
+            Self::InvalidAmount | Self::AmountTooPrecise(_) => StatusCode::BAD_REQUEST,
+            Self::InsufficientBalance => StatusCode::FORBIDDEN,
+        };
+
+        let body = match &self {
+            Self::AmountTooPrecise(currency) => json!({
+                "title": self.title(),
+                "detail": format!(
+                    "{currency} amounts may have at most {} decimal places",
+                    currency.minor_units()
+                ),
+            }),
+            _ => json!({
+                "title": self.title(),
+            }),
+        };
+
+        (status, Json(body)).into_response()
+    }
+}
+
+/// A move of money between the account of a user and one of their pockets.
+#[derive(Clone, Debug)]
+pub struct NewPocketMove {
+    pub user_id: Uuid,
+    pub pocket_id: Uuid,
+    pub amount: BigDecimal,
+    /// Either [`TransactionKind::PocketDeposit`] or
+    /// [`TransactionKind::PocketWithdrawal`].
+    pub kind: TransactionKind,
+}
+
+/// A move of money into or out of the escrow account which holds the funds of
+/// gift codes.
+#[derive(Clone, Debug)]
+pub struct NewGiftCodeMove {
+    /// The user the money is moved from when funding a gift code, or to
+    /// otherwise.
+    pub user_id: Uuid,
+    pub amount: BigDecimal,
+    pub currency: Currency,
+    /// One of [`TransactionKind::GiftCodeFunding`],
+    /// [`TransactionKind::GiftCodeRedemption`] or
+    /// [`TransactionKind::GiftCodeRefund`].
+    pub kind: TransactionKind,
+    pub memo: Option<String>,
+    /// The admin who funded a gift code from the treasury, if any.
+    pub initiator: Option<Uuid>,
+}
+
+/// Reasons a move into or out of a pocket is rejected.
+#[derive(Clone, Debug)]
+pub enum PocketMoveError {
+    PocketNotFound,
+    InvalidAmount,
+    /// The amount is more precise than the minor unit of the currency.
+    AmountTooPrecise(Currency),
+    /// The account has less available, or the pocket holds less, than the
+    /// amount.
+    InsufficientBalance,
+}
+
+impl PocketMoveError {
+    /// The title of the error response.
+    pub fn title(&self) -> &'static str {
+        match self {
+            Self::PocketNotFound => "PocketNotFound",
+            Self::InvalidAmount | Self::AmountTooPrecise(_) => "InvalidAmount",
+            Self::InsufficientBalance => "InsufficientBalance",
+        }
+    }
+}
+
+impl IntoResponse for PocketMoveError {
+    fn into_response(self) -> Response {
+        let status = match self {
+            Self::PocketNotFound => StatusCode::NOT_FOUND,
+            Self::InvalidAmount | Self::AmountTooPrecise(_) => StatusCode::BAD_REQUEST,
+            Self::InsufficientBalance => StatusCode::FORBIDDEN,
+        };
+
+        let body = match &self {
+            Self::AmountTooPrecise(currency) => json!({
+                "title": self.title(),
+                "detail": format!(
+                    "{currency} amounts may have at most {} decimal places",
+                    currency.minor_units()
+                ),
+            }),
+            _ => json!({
+                "title": self.title(),
+            }),
+        };
+
+        (status, Json(body)).into_response()
+    }
+}
+
+/// Reasons a refund or reversal is rejected.
+#[derive(Clone, Debug)]
+pub enum RefundError {
+    TransactionNotFound,
+    PermissionDenied,
+    /// Refunds and reversals can't be refunded themselves.
+    NotRefundable,
+    InvalidAmount,
+    ExceedsRefundableAmount {
+        refundable_amount: BigDecimal,
+    },
+    InsufficientBalance,
+}
+
+impl RefundError {
+    /// The title of the error response.
+    pub fn title(&self) -> &'static str {
+        match self {
+            Self::TransactionNotFound => "TransactionNotFound",
+            Self::PermissionDenied => "PermissionDenied",
+            Self::NotRefundable => "NotRefundable",
+            Self::InvalidAmount => "InvalidAmount",
+            Self::ExceedsRefundableAmount { .. } => "ExceedsRefundableAmount",
+            Self::InsufficientBalance => "InsufficientBalance",
+        }
+    }
+}
+
+impl IntoResponse for RefundError {
+    fn into_response(self) -> Response {
+        let status = match self {
+            Self::TransactionNotFound => StatusCode::NOT_FOUND,
+            Self::PermissionDenied | Self::InsufficientBalance => StatusCode::FORBIDDEN,
+            Self::NotRefundable => StatusCode::CONFLICT,
+            Self::InvalidAmount | Self::ExceedsRefundableAmount { .. } => StatusCode::BAD_REQUEST,
+        };
+
+        let body = match &self {
+            Self::ExceedsRefundableAmount { refundable_amount } => json!({
+                "title": self.title(),
+                "detail": format!("at most {refundable_amount} can be refunded"),
+            }),
+            _ => json!({
+                "title": self.title(),
+            }),
+        };
+
+        (status, Json(body)).into_response()
+    }
+}
+
+/// Transfers money from the sender to the recipient, in its own database
+/// transaction.
+///
+/// The transaction is started with `BEGIN IMMEDIATE`, so that the write lock is
+/// held while balances are read and compared. Otherwise, concurrent transfers
+/// from the same sender could both read the old balance, or fail to upgrade
+/// their read lock to a write lock.
+///
+/// Retries if the database stays busy after the connection's `busy_timeout`.
+pub async fn transfer(
+    conn: &mut DbConnection,
+    transfer: &NewTransfer,
+    default_limits: &TransferLimits,
+) -> anyhow::Result<Result<Transaction, TransferError>> {
+    run_immediate_transaction(conn, |conn| {
+        Box::pin(async move { apply_transfer(conn, transfer, default_limits).await })
+    })
+    .await
+}
+
+/// Runs `f` in a `BEGIN IMMEDIATE` transaction, retrying with exponential
+/// backoff if the database is busy.
+///
+/// `f` may be called more than once, so it must not have side effects outside
+/// of the database transaction.
+pub async fn run_immediate_transaction<'a, R, F>(conn: &mut DbConnection, f: F) -> anyhow::Result<R>
+where
+    F: for<'r> Fn(&'r mut DbConnection) -> ScopedBoxFuture<'a, 'r, anyhow::Result<R>>
+        + Clone
+        + Send
+        + 'a,
+    R: Send + 'a,
+{
+    let mut attempt = 1;
+    let mut delay = BUSY_RETRY_BASE_DELAY;
+    loop {
+        match conn.immediate_transaction(f.clone()).await {
+            Err(err) if attempt < MAX_BUSY_ATTEMPTS && is_database_busy(&err) => {
+                warn!(attempt, ?delay, "database busy, retrying transaction");
+
+                tokio::time::sleep(delay).await;
+                attempt = attempt.saturating_add(1);
+                delay = delay.saturating_mul(2);
+            },
+            result => return result,
+        }
+    }
+}
+
+/// Applies a transfer within an already open database transaction.
+///
+/// Only the sender's available balance may be transferred, i.e. not what is
+/// reserved by active holds, and only within the sender's transfer limits,
+/// which are `default_limits` unless overridden for the sender.
+///
+/// The fee for the transfer, if any, is charged to the sender on top of the
+/// amount, and paid to the platform's revenue account.
+///
+/// The caller is responsible for holding the write lock, see [`transfer`].
+pub async fn apply_transfer(
+    conn: &mut DbConnection,
+    transfer: &NewTransfer,
+    default_limits: &TransferLimits,
+) -> anyhow::Result<Result<Transaction, TransferError>> {
+    apply_counted_transfer(conn, transfer, default_limits, true).await
+}
+
+/// Like [`apply_transfer`], but the transfer is only checked against
+/// [`Limit::TransferCount`] if it is `counted`, which only the first transfer
+/// of a batch is, see [`limits::check`].
+async fn apply_counted_transfer(
+    conn: &mut DbConnection,
+    transfer: &NewTransfer,
+    default_limits: &TransferLimits,
+    counted: bool,
+) -> anyhow::Result<Result<Transaction, TransferError>> {
+    use crate::models::types;
+    use crate::schema::{exchange_quotes, users};
+
+    if transfer.amount <= BigDecimal::zero() {
+        return Ok(Err(TransferError::InvalidAmount));
+    }
+    if !transfer.currency.is_valid_amount(&transfer.amount) {
+        return Ok(Err(TransferError::AmountTooPrecise(transfer.currency)));
+    }
+
+    if transfer.recipient == transfer.sender {
+        return Ok(Err(TransferError::SelfTransfer));
+    }
+
+    // System users, like the one receiving fees, can't be sent money.
+    let recipient_exists = diesel::select(diesel::dsl::exists(
+        users::table
+            .find(types::Uuid::from(transfer.recipient))
+            .filter(users::role.ne(UserRole::System)),
+    ))
+    .get_result::<bool>(conn)
+    .await
+    .context("failed to query users")?;
+    if !recipient_exists {
+        debug!(%transfer.recipient, "could not find recipient");
+
+        return Ok(Err(TransferError::InvalidRecipient));
+    }
+
+    let now = jiff::Timestamp::now();
+    let quote = match transfer.quote_id {
+        Some(quote_id) => match find_usable_quote(conn, quote_id, transfer, now).await? {
+            Ok(quote) => Some(quote),
+            Err(err) => return Ok(Err(err)),
+        },
+        None => None,
+    };
+    let recipient_currency = quote
+        .as_ref()
+        .map_or(transfer.currency, |quote| quote.target_currency);
+
+    let Some(sender) = find_account(conn, transfer.sender, transfer.currency).await? else {
+        return Ok(Err(TransferError::CurrencyMismatch(transfer.currency)));
+    };
+    let Some(recipient) = find_account(conn, transfer.recipient, recipient_currency).await? else {
+        return Ok(Err(TransferError::CurrencyMismatch(recipient_currency)));
+    };
+
+    let mut limits = limits::effective_limits(conn, default_limits, transfer.sender).await?;
+    if !counted {
+        limits.max_transfers = None;
+    }
+    match limits::check(
+        conn,
+        &limits,
+        transfer.sender,
+        &transfer.amount,
+        transfer.currency,
+        now,
+    )
+    .await?
+    {
+        Ok(()) => {},
+        Err(TransferError::LimitExceeded(limit_exceeded)) => {
+            notification::notify_limit_reached(conn, transfer.sender, &limit_exceeded).await?;
+
+            return Ok(Err(TransferError::LimitExceeded(limit_exceeded)));
+        },
+        Err(err) => return Ok(Err(err)),
+    }
+
+    let fee = fees::fee_for(conn, transfer.sender, &transfer.amount, transfer.currency).await?;
+    let available_balance =
+        &sender.balance - held_amount(conn, transfer.sender, transfer.currency, now).await?;
+    if available_balance < &transfer.amount + &fee {
+        return Ok(Err(TransferError::InsufficientBalance));
+    }
+
+    let new_transaction = NewTransaction {
+        id: Uuid::now_v7(),
+        amount: transfer.amount.clone(),
+        currency: transfer.currency,
+        recipient: transfer.recipient,
+        sender: transfer.sender,
+        timestamp: now,
+        memo: transfer.memo.clone(),
+        reference: transfer.reference.clone(),
+        kind: TransactionKind::Transfer,
+        original_transaction_id: None,
+        flagged: false,
+        converted_amount: quote.as_ref().map(|quote| quote.target_amount.clone()),
+        converted_currency: quote.as_ref().map(|quote| quote.target_currency),
+        exchange_rate: quote.as_ref().map(|quote| quote.rate.clone()),
+        spread: quote.as_ref().map(|quote| quote.spread.clone()),
+        fee: (!fee.is_zero()).then(|| fee.clone()),
+        initiator: None,
+        pocket_id: None,
+    };
+
+    let created_transaction = insert_transaction(conn, new_transaction, sender, recipient).await?;
+
+    if !fee.is_zero() {
+        charge_fee(conn, &created_transaction, fee).await?;
+    }
+
+    if let Some(mut quote) = quote {
+        quote.transaction_id = Some(created_transaction.id);
+        let _quote: ExchangeQuote =
+            diesel::update(exchange_quotes::table.find(types::Uuid::from(quote.id)))
+                .set(quote)
+                .returning(ExchangeQuote::as_returning())
+                .get_result(conn)
+                .await
+                .context("failed to update exchange quote")?;
+    }
+
+    Ok(Ok(created_transaction))
+}
+
+/// Makes all transfers of a batch in one database transaction, or none if any
+/// fails.
+///
+/// See [`transfer`] for how the transaction is run.
+pub async fn batch_transfer(
+    conn: &mut DbConnection,
+    batch: &NewTransferBatch,
+    default_limits: &TransferLimits,
+) -> anyhow::Result<Result<(TransferBatch, Vec<Transaction>), BatchError>> {
+    run_immediate_transaction(conn, |conn| {
+        Box::pin(async move { apply_batch_transfer(conn, batch, default_limits).await })
+    })
+    .await
+}
+
+/// Applies a batch within an already open database transaction.
+///
+/// Each transfer is applied like [`apply_transfer`], after the ones before it,
+/// so that they count towards the sender's balance and limits. If any fails,
+/// the batch is rolled back to a savepoint, and all failures are returned. A
+/// transfer which failed doesn't count towards the ones after it.
+///
+/// The batch as a whole counts as one transfer towards
+/// [`Limit::TransferCount`], which is checked with its first transfer.
+///
+/// The caller is responsible for holding the write lock, see [`transfer`].
+pub async fn apply_batch_transfer(
+    conn: &mut DbConnection,
+    batch: &NewTransferBatch,
+    default_limits: &TransferLimits,
+) -> anyhow::Result<Result<(TransferBatch, Vec<Transaction>), BatchError>> {
+    use crate::schema::{transfer_batch_items, transfer_batches};
+
+    if batch.transfers.is_empty() {
+        return Ok(Err(BatchError::Empty));
+    }
+    if batch.transfers.len() > MAX_BATCH_TRANSFERS {
+        return Ok(Err(BatchError::TooLarge));
+    }
+
+    let result = conn
+        .transaction::<_, BatchRollback, _>(|conn| {
+            Box::pin(async move {
+                let created_batch: TransferBatch = diesel::insert_into(transfer_batches::table)
+                    .values(TransferBatch {
+                        id: Uuid::now_v7(),
+                        sender: batch.sender,
+                        currency: batch.currency,
+                        created_at: jiff::Timestamp::now(),
+                    })
+                    .returning(TransferBatch::as_returning())
+                    .get_result(conn)
+                    .await?;
+
+                let mut created_transactions = Vec::with_capacity(batch.transfers.len());
+                let mut errors = Vec::new();
+                for (index, batch_transfer) in batch.transfers.iter().enumerate() {
+                    let transfer = NewTransfer {
+                        amount: batch_transfer.amount.clone(),
+                        currency: batch.currency,
+                        recipient: batch_transfer.recipient,
+                        sender: batch.sender,
+                        memo: batch_transfer.memo.clone(),
+                        reference: batch_transfer.reference.clone(),
+                        quote_id: None,
+                    };
+                    match apply_counted_transfer(conn, &transfer, default_limits, index == 0)
+                        .await
+                        .map_err(BatchRollback::Failed)?
+                    {
+                        Ok(created_transaction) => created_transactions.push(created_transaction),
+                        Err(err) => errors.push((index, err)),
+                    }
+                }
+                if !errors.is_empty() {
+                    return Err(BatchRollback::Rejected(errors));
+                }
+
+                let items = created_transactions
+                    .iter()
+                    .enumerate()
+                    .map(|(index, created_transaction)| {
+                        Ok(TransferBatchItem {
+                            batch_id: created_batch.id,
+                            position: i32::try_from(index)?,
+                            transaction_id: created_transaction.id,
+                        })
+                    })
+                    .collect::<anyhow::Result<Vec<_>>>()
+                    .map_err(BatchRollback::Failed)?;
+                for item in items {
+                    diesel::insert_into(transfer_batch_items::table)
+                        .values(item)
+                        .execute(conn)
+                        .await?;
+                }
+
+                Ok((created_batch, created_transactions))
+            })
+        })
+        .await;
+
+    match result {
+        Ok(created) => Ok(Ok(created)),
+        Err(BatchRollback::Rejected(errors)) => Ok(Err(BatchError::Rejected(errors))),
+        Err(BatchRollback::Failed(err)) => Err(err).context("failed to apply batch"),
+    }
+}
+
+/// Moves the fee for `transaction` from its sender to the platform's revenue
+/// account, recording it as a transaction of its own.
+async fn charge_fee(
+    conn: &mut DbConnection,
+    transaction: &Transaction,
+    fee: BigDecimal,
+) -> anyhow::Result<Transaction> {
+    // The sender's account was updated by the transfer, so it is read again.
+    let sender = find_account(conn, transaction.sender, transaction.currency)
+        .await?
+        .context("could not find account")?;
+    let revenue = system_account(
+        conn,
+        fees::REVENUE_USER_ID,
+        transaction.currency,
+        transaction.timestamp,
+    )
+    .await?;
+
+    let new_transaction = NewTransaction {
+        id: Uuid::now_v7(),
+        amount: fee,
+        currency: transaction.currency,
+        recipient: fees::REVENUE_USER_ID,
+        sender: transaction.sender,
+        timestamp: transaction.timestamp,
+        memo: None,
+        reference: transaction.reference.clone(),
+        kind: TransactionKind::Fee,
+        original_transaction_id: Some(transaction.id),
+        flagged: false,
+        converted_amount: None,
+        converted_currency: None,
+        exchange_rate: None,
+        spread: None,
+        fee: None,
+        initiator: None,
+        pocket_id: None,
+    };
+
+    insert_transaction(conn, new_transaction, sender, revenue).await
+}
+
+/// Returns the quote `quote_id` of the sender, if it may be used for
+/// `transfer` at `now`.
+async fn find_usable_quote(
+    conn: &mut DbConnection,
+    quote_id: Uuid,
+    transfer: &NewTransfer,
+    now: jiff::Timestamp,
+) -> anyhow::Result<Result<ExchangeQuote, TransferError>> {
+    use crate::models::types;
+    use crate::schema::exchange_quotes;
+
+    let quote: Option<ExchangeQuote> = exchange_quotes::table
+        .find(types::Uuid::from(quote_id))
+        .filter(exchange_quotes::user_id.eq(types::Uuid::from(transfer.sender)))
+        .select(ExchangeQuote::as_select())
+        .first(conn)
+        .await
+        .optional()
+        .context("failed to query exchange quotes")?;
+    let Some(quote) = quote else {
+        debug!(%quote_id, "could not find exchange quote");
+
+        return Ok(Err(TransferError::QuoteNotFound));
+    };
+
+    if quote.transaction_id.is_some() {
+        return Ok(Err(TransferError::QuoteUsed));
+    }
+    if quote.expires_at <= now {
+        return Ok(Err(TransferError::QuoteExpired));
+    }
+    if quote.source_currency != transfer.currency || quote.source_amount != transfer.amount {
+        return Ok(Err(TransferError::QuoteMismatch));
+    }
+
+    Ok(Ok(quote))
+}
+
+/// Refunds or reverses an earlier transfer, in its own database transaction.
+///
+/// See [`transfer`] for how the transaction is run.
+pub async fn refund(
+    conn: &mut DbConnection,
+    refund: &NewRefund,
+) -> anyhow::Result<Result<Transaction, RefundError>> {
+    run_immediate_transaction(conn, |conn| {
+        Box::pin(async move { apply_refund(conn, refund).await })
+    })
+    .await
+}
+
+/// Applies a refund or reversal within an already open database transaction.
+///
+/// Money goes back from the recipient of the original transaction to its
+/// sender, up to what has not been refunded yet. A refund is limited to the
+/// recipient's available balance, whereas a reversal may take the recipient's
+/// balance negative, in which case the transaction is flagged.
+///
+/// The caller is responsible for holding the write lock, see [`transfer`].
+pub async fn apply_refund(
+    conn: &mut DbConnection,
+    refund: &NewRefund,
+) -> anyhow::Result<Result<Transaction, RefundError>> {
+    use crate::models::types;
+    use crate::schema::{transactions, users};
+
+    let original: Transaction = match transactions::table
+        .find(types::Uuid::from(refund.original_transaction_id))
+        .select(Transaction::as_select())
+        .first(conn)
+        .await
+    {
+        Ok(original) => original,
+        Err(diesel::NotFound) => {
+            debug!(%refund.original_transaction_id, "could not find transaction");
+
+            return Ok(Err(RefundError::TransactionNotFound));
+        },
+        Err(err) => {
+            return Err(err).context("failed to query transactions")?;
+        },
+    };
+
+    match refund.kind {
+        TransactionKind::Refund => {
+            if refund.initiator != original.recipient {
+                // Respond with "not found" to the sender and anyone else, so
+                // as not to reveal whether the transaction exists or not.
+                if refund.initiator != original.sender {
+                    return Ok(Err(RefundError::TransactionNotFound));
+                }
+                return Ok(Err(RefundError::PermissionDenied));
+            }
+        },
+        TransactionKind::Reversal => {
+            let initiator: User = users::table
+                .find(types::Uuid::from(refund.initiator))
+                .select(User::as_select())
+                .first(conn)
+                .await
+                .context("could not find user")?;
+            if initiator.role != UserRole::Admin {
+                return Ok(Err(RefundError::PermissionDenied));
+            }
+        },
+        TransactionKind::Transfer
+        | TransactionKind::Fee
+        | TransactionKind::Mint
+        | TransactionKind::Burn
+        | TransactionKind::Grant
+        | TransactionKind::PocketDeposit
+        | TransactionKind::PocketWithdrawal
+        | TransactionKind::Interest
+        | TransactionKind::GiftCodeFunding
+        | TransactionKind::GiftCodeRedemption
+        | TransactionKind::GiftCodeRefund => {
+            anyhow::bail!("a refund must be of kind refund or reversal");
+        },
+    }
+
+    if original.kind != TransactionKind::Transfer {
+        return Ok(Err(RefundError::NotRefundable));
+    }
+
+    let already_refunded = refunded_amount(conn, original.id).await?;
+    let refundable_amount = &original.amount - &already_refunded;
+    let amount = match &refund.amount {
+        Some(amount)
+            if *amount <= BigDecimal::zero() || !original.currency.is_valid_amount(amount) =>
+        {
+            return Ok(Err(RefundError::InvalidAmount));
+        },
+        Some(amount) => amount.clone(),
+        None => refundable_amount.clone(),
+    };
+    // A full refund of a transaction which was already fully refunded is
+    // rejected too.
+    if amount > refundable_amount || amount.is_zero() {
+        return Ok(Err(RefundError::ExceedsRefundableAmount {
+            refundable_amount,
+        }));
+    }
+
+    // The recipient of the original transaction sends the money back, in the
+    // currency they were credited. For converted transactions, that is the
+    // same share of the converted amount, so that a full refund takes back
+    // exactly what was credited.
+    let debited_amount = match &original.converted_amount {
+        Some(converted_amount) => {
+            let converted_share = |refunded: &BigDecimal| {
+                (converted_amount * refunded / &original.amount).with_scale_round(
+                    i64::from(original.credited_currency().minor_units()),
+                    RoundingMode::Down,
+                )
+            };
+            (converted_share(&(&already_refunded + &amount)) - converted_share(&already_refunded))
+                .normalized()
+        },
+        None => amount.clone(),
+    };
+    let sender = find_account(conn, original.recipient, original.credited_currency())
+        .await?
+        .context("could not find account")?;
+    let recipient = find_account(conn, original.sender, original.currency)
+        .await?
+        .context("could not find account")?;
+
+    let now = jiff::Timestamp::now();
+    let flagged = match refund.kind {
+        TransactionKind::Reversal => sender.balance < debited_amount,
+        _ => {
+            let available_balance =
+                &sender.balance - held_amount(conn, sender.user_id, sender.currency, now).await?;
+            if available_balance < debited_amount {
+                return Ok(Err(RefundError::InsufficientBalance));
+            }
+            false
+        },
+    };
+    if flagged {
+        warn!(%original.id, %sender.user_id, "reversal takes balance negative");
+    }
+
+    let is_converted = original.converted_amount.is_some();
+    let new_transaction = NewTransaction {
+        id: Uuid::now_v7(),
+        amount: debited_amount,
+        currency: original.credited_currency(),
+        recipient: original.sender,
+        sender: original.recipient,
+        timestamp: now,
+        memo: refund.memo.clone(),
+        reference: original.reference,
+        kind: refund.kind,
+        original_transaction_id: Some(original.id),
+        flagged,
+        converted_amount: is_converted.then_some(amount),
+        converted_currency: is_converted.then_some(original.currency),
+        exchange_rate: original.exchange_rate,
+        spread: original.spread,
+        fee: None,
+        initiator: (refund.kind == TransactionKind::Reversal).then_some(refund.initiator),
+        pocket_id: None,
+    };
+
+    let created_transaction = insert_transaction(conn, new_transaction, sender, recipient).await?;
+
+    Ok(Ok(created_transaction))
+}
+
+/// Mints money into, or burns money out of, the treasury, in its own database
+/// transaction.
+///
+/// See [`transfer`] for how the transaction is run.
+pub async fn issue(
+    conn: &mut DbConnection,
+    issuance: &NewIssuance,
+) -> anyhow::Result<Result<Transaction, IssuanceError>> {
+    run_immediate_transaction(conn, |conn| {
+        Box::pin(async move { apply_issuance(conn, issuance).await })
+    })
+    .await
+}
+
+/// Applies a mint or burn within an already open database transaction.
+///
+/// Both are recorded as transactions from the treasury to itself, which only
+/// change the treasury's balance. A burn is limited to the treasury's balance,
+/// so money which was granted to users can't be burnt.
+///
+/// The caller is responsible for holding the write lock, see [`transfer`].
+pub async fn apply_issuance(
+    conn: &mut DbConnection,
+    issuance: &NewIssuance,
+) -> anyhow::Result<Result<Transaction, IssuanceError>> {
+    use crate::models::types;
+    use crate::schema::{accounts, transactions};
+
+    if issuance.amount <= BigDecimal::zero() {
+        return Ok(Err(IssuanceError::InvalidAmount));
+    }
+    if !issuance.currency.is_valid_amount(&issuance.amount) {
+        return Ok(Err(IssuanceError::AmountTooPrecise(issuance.currency)));
+    }
+
+    let now = jiff::Timestamp::now();
+    let mut treasury = system_account(conn, TREASURY_USER_ID, issuance.currency, now).await?;
+    match issuance.kind {
+        TransactionKind::Mint => treasury.balance += &issuance.amount,
+        TransactionKind::Burn => {
+            if treasury.balance < issuance.amount {
+                return Ok(Err(IssuanceError::InsufficientBalance));
+            }
+            treasury.balance -= &issuance.amount;
+        },
+        _ => anyhow::bail!("an issuance must be of kind mint or burn"),
+    }
+
+    let new_transaction = NewTransaction {
+        id: Uuid::now_v7(),
+        amount: issuance.amount.clone(),
+        currency: issuance.currency,
+        recipient: TREASURY_USER_ID,
+        sender: TREASURY_USER_ID,
+        timestamp: now,
+        memo: issuance.memo.clone(),
+        reference: None,
+        kind: issuance.kind,
+        original_transaction_id: None,
+        flagged: false,
+        converted_amount: None,
+        converted_currency: None,
+        exchange_rate: None,
+        spread: None,
+        fee: None,
+        initiator: issuance.initiator,
+        pocket_id: None,
+    };
+
+    let created_transaction: Transaction = diesel::insert_into(transactions::table)
+        .values(new_transaction)
+        .returning(Transaction::as_returning())
+        .get_result(conn)
+        .await
+        .context("failed to insert transaction")?;
+    webhook::enqueue_transaction(conn, &created_transaction).await?;
+    notification::notify_transaction(conn, &created_transaction).await?;
+
+    let _treasury: Account = diesel::update(
+        accounts::table.find((types::Uuid::from(TREASURY_USER_ID), treasury.currency)),
+    )
+    .set(treasury)
+    .returning(Account::as_returning())
+    .get_result(conn)
+    .await
+    .context("failed to update account")?;
+
+    Ok(Ok(created_transaction))
+}
+
+/// Pays `amount` from the treasury to `user_id`, within an already open
+/// database transaction. The user must have an account in `currency`.
+///
+/// The caller is responsible for holding the write lock, see [`transfer`].
+pub async fn apply_grant(
+    conn: &mut DbConnection,
+    user_id: Uuid,
+    amount: &BigDecimal,
+    currency: Currency,
+) -> anyhow::Result<Result<Transaction, IssuanceError>> {
+    if *amount <= BigDecimal::zero() {
+        return Ok(Err(IssuanceError::InvalidAmount));
+    }
+    if !currency.is_valid_amount(amount) {
+        return Ok(Err(IssuanceError::AmountTooPrecise(currency)));
+    }
+
+    let now = jiff::Timestamp::now();
+    let treasury = system_account(conn, TREASURY_USER_ID, currency, now).await?;
+    if treasury.balance < *amount {
+        return Ok(Err(IssuanceError::InsufficientBalance));
+    }
+    let recipient = find_account(conn, user_id, currency)
+        .await?
+        .context("could not find account")?;
+
+    let new_transaction = NewTransaction {
+        id: Uuid::now_v7(),
+        amount: amount.clone(),
+        currency,
+        recipient: user_id,
+        sender: TREASURY_USER_ID,
+        timestamp: now,
+        memo: None,
+        reference: None,
+        kind: TransactionKind::Grant,
+        original_transaction_id: None,
+        flagged: false,
+        converted_amount: None,
+        converted_currency: None,
+        exchange_rate: None,
+        spread: None,
+        fee: None,
+        initiator: None,
+        pocket_id: None,
+    };
+
+    let created_transaction =
+        insert_transaction(conn, new_transaction, treasury, recipient).await?;
+
+    Ok(Ok(created_transaction))
+}
+
+/// Pays interest of `amount` from the treasury to `user_id`, within an already
+/// open database transaction.
+///
+/// The interest is paid into `pocket_id` if it is given and not archived, and
+/// into the user's account in `currency` otherwise.
+///
+/// The caller is responsible for holding the write lock, see [`transfer`].
+pub async fn apply_interest(
+    conn: &mut DbConnection,
+    user_id: Uuid,
+    pocket_id: Option<Uuid>,
+    amount: &BigDecimal,
+    currency: Currency,
+) -> anyhow::Result<Result<Transaction, IssuanceError>> {
+    use crate::models::types;
+    use crate::schema::{accounts, pockets, transactions};
+
+    if *amount <= BigDecimal::zero() {
+        return Ok(Err(IssuanceError::InvalidAmount));
+    }
+    if !currency.is_valid_amount(amount) {
+        return Ok(Err(IssuanceError::AmountTooPrecise(currency)));
+    }
+
+    let now = jiff::Timestamp::now();
+    let mut treasury = system_account(conn, TREASURY_USER_ID, currency, now).await?;
+    if treasury.balance < *amount {
+        return Ok(Err(IssuanceError::InsufficientBalance));
+    }
+
+    let pocket: Option<Pocket> = match pocket_id {
+        Some(pocket_id) => pockets::table
+            .find(types::Uuid::from(pocket_id))
+            .filter(pockets::user_id.eq(types::Uuid::from(user_id)))
+            .filter(pockets::archived_at.is_null())
+            .select(Pocket::as_select())
+            .first(conn)
+            .await
+            .optional()
+            .context("failed to query pockets")?,
+        None => None,
+    };
+
+    let new_transaction = NewTransaction {
+        id: Uuid::now_v7(),
+        amount: amount.clone(),
+        currency,
+        recipient: user_id,
+        sender: TREASURY_USER_ID,
+        timestamp: now,
+        memo: None,
+        reference: None,
+        kind: TransactionKind::Interest,
+        original_transaction_id: None,
+        flagged: false,
+        converted_amount: None,
+        converted_currency: None,
+        exchange_rate: None,
+        spread: None,
+        fee: None,
+        initiator: None,
+        pocket_id: pocket.as_ref().map(|pocket| pocket.id),
+    };
+
+    let Some(mut pocket) = pocket else {
+        let recipient = find_account(conn, user_id, currency)
+            .await?
+            .context("could not find account")?;
+        let created_transaction =
+            insert_transaction(conn, new_transaction, treasury, recipient).await?;
+
+        return Ok(Ok(created_transaction));
+    };
+
+    let created_transaction: Transaction = diesel::insert_into(transactions::table)
+        .values(new_transaction)
+        .returning(Transaction::as_returning())
+        .get_result(conn)
+        .await
+        .context("failed to insert transaction")?;
+    webhook::enqueue_transaction(conn, &created_transaction).await?;
+    notification::notify_transaction(conn, &created_transaction).await?;
+
+    treasury.balance -= amount;
+    let _treasury: Account = diesel::update(
+        accounts::table.find((types::Uuid::from(TREASURY_USER_ID), treasury.currency)),
+    )
+    .set(treasury)
+    .returning(Account::as_returning())
+    .get_result(conn)
+    .await
+    .context("failed to update account")?;
+
+    pocket.balance += amount;
+    let _pocket: Pocket = diesel::update(pockets::table.find(types::Uuid::from(pocket.id)))
+        .set(pocket)
+        .returning(Pocket::as_returning())
+        .get_result(conn)
+        .await
+        .context("failed to update pocket")?;
+
+    Ok(Ok(created_transaction))
+}
+
+/// Moves money between the account of a user and one of their pockets, in its
+/// own database transaction.
+///
+/// See [`transfer`] for how the transaction is run.
+pub async fn move_pocket(
+    conn: &mut DbConnection,
+    pocket_move: &NewPocketMove,
+) -> anyhow::Result<Result<(Transaction, Pocket), PocketMoveError>> {
+    run_immediate_transaction(conn, |conn| {
+        Box::pin(async move { apply_pocket_move(conn, pocket_move).await })
+    })
+    .await
+}
+
+/// Applies a move into or out of a pocket within an already open database
+/// transaction.
+///
+/// Moves are recorded as transactions from the user to themselves which refer
+/// to the pocket, so that they show up in the user's history. Only what is
+/// available, i.e. not reserved by holds, can be moved into a pocket. Transfer
+/// limits don't apply, as the money stays with the user.
+///
+/// The caller is responsible for holding the write lock, see [`transfer`].
+pub async fn apply_pocket_move(
+    conn: &mut DbConnection,
+    pocket_move: &NewPocketMove,
+) -> anyhow::Result<Result<(Transaction, Pocket), PocketMoveError>> {
+    use crate::models::types;
+    use crate::schema::{accounts, pockets, transactions};
+
+    let pocket: Option<Pocket> = pockets::table
+        .find(types::Uuid::from(pocket_move.pocket_id))
+        .filter(pockets::user_id.eq(types::Uuid::from(pocket_move.user_id)))
+        .filter(pockets::archived_at.is_null())
+        .select(Pocket::as_select())
+        .first(conn)
+        .await
+        .optional()
+        .context("failed to query pockets")?;
+    let Some(mut pocket) = pocket else {
+        return Ok(Err(PocketMoveError::PocketNotFound));
+    };
+
+    if pocket_move.amount <= BigDecimal::zero() {
+        return Ok(Err(PocketMoveError::InvalidAmount));
+    }
+    if !pocket.currency.is_valid_amount(&pocket_move.amount) {
+        return Ok(Err(PocketMoveError::AmountTooPrecise(pocket.currency)));
+    }
+
+    let now = jiff::Timestamp::now();
+    let mut account = find_account(conn, pocket.user_id, pocket.currency)
+        .await?
+        .context("could not find account of pocket")?;
+    match pocket_move.kind {
+        TransactionKind::PocketDeposit => {
+            let held_amount = held_amount(conn, account.user_id, account.currency, now).await?;
+            if &account.balance - held_amount < pocket_move.amount {
+                return Ok(Err(PocketMoveError::InsufficientBalance));
+            }
+            account.balance -= &pocket_move.amount;
+            pocket.balance += &pocket_move.amount;
+        },
+        TransactionKind::PocketWithdrawal => {
+            if pocket.balance < pocket_move.amount {
+                return Ok(Err(PocketMoveError::InsufficientBalance));
+            }
+            pocket.balance -= &pocket_move.amount;
+            account.balance += &pocket_move.amount;
+        },
+        _ => anyhow::bail!("a pocket move must be of kind pocket_deposit or pocket_withdrawal"),
+    }
+
+    let new_transaction = NewTransaction {
+        id: Uuid::now_v7(),
+        amount: pocket_move.amount.clone(),
+        currency: pocket.currency,
+        recipient: pocket.user_id,
+        sender: pocket.user_id,
+        timestamp: now,
+        memo: None,
+        reference: None,
+        kind: pocket_move.kind,
+        original_transaction_id: None,
+        flagged: false,
+        converted_amount: None,
+        converted_currency: None,
+        exchange_rate: None,
+        spread: None,
+        fee: None,
+        initiator: None,
+        pocket_id: Some(pocket.id),
+    };
+
+    let created_transaction: Transaction = diesel::insert_into(transactions::table)
+        .values(new_transaction)
+        .returning(Transaction::as_returning())
+        .get_result(conn)
+        .await
+        .context("failed to insert transaction")?;
+    webhook::enqueue_transaction(conn, &created_transaction).await?;
+    notification::notify_transaction(conn, &created_transaction).await?;
+
+    let _account: Account = diesel::update(
+        accounts::table.find((types::Uuid::from(account.user_id), account.currency)),
+    )
+    .set(account)
+    .returning(Account::as_returning())
+    .get_result(conn)
+    .await
+    .context("failed to update account")?;
+
+    let pocket: Pocket = diesel::update(pockets::table.find(types::Uuid::from(pocket.id)))
+        .set(pocket)
+        .returning(Pocket::as_returning())
+        .get_result(conn)
+        .await
+        .context("failed to update pocket")?;
+
+    Ok(Ok((created_transaction, pocket)))
+}
+
+/// Applies a move into or out of the gift code escrow account within an already
+/// open database transaction.
+///
+/// Funding a gift code is limited to the available balance of the user, and
+/// counts towards their transfer limits, which are `default_limits` unless
+/// overridden for them. Gift codes funded from the treasury are not limited.
+///
+/// The caller is responsible for holding the write lock, see [`transfer`].
+pub async fn apply_gift_code_move(
+    conn: &mut DbConnection,
+    gift_code_move: &NewGiftCodeMove,
+    default_limits: &TransferLimits,
+) -> anyhow::Result<Result<Transaction, TransferError>> {
+    if gift_code_move.amount <= BigDecimal::zero() {
+        return Ok(Err(TransferError::InvalidAmount));
+    }
+    if !gift_code_move
+        .currency
+        .is_valid_amount(&gift_code_move.amount)
+    {
+        return Ok(Err(TransferError::AmountTooPrecise(
+            gift_code_move.currency,
+        )));
+    }
+
+    let now = jiff::Timestamp::now();
+    let Some(account) = find_account(conn, gift_code_move.user_id, gift_code_move.currency).await?
+    else {
+        return Ok(Err(TransferError::CurrencyMismatch(
+            gift_code_move.currency,
+        )));
+    };
+    let escrow = system_account(
+        conn,
+        gift_code::ESCROW_USER_ID,
+        gift_code_move.currency,
+        now,
+    )
+    .await?;
+
+    let (sender, recipient) = match gift_code_move.kind {
+        TransactionKind::GiftCodeFunding => {
+            if gift_code_move.user_id != TREASURY_USER_ID {
+                let limits =
+                    limits::effective_limits(conn, default_limits, gift_code_move.user_id).await?;
+                match limits::check(
+                    conn,
+                    &limits,
+                    gift_code_move.user_id,
+                    &gift_code_move.amount,
+                    gift_code_move.currency,
+                    now,
+                )
+                .await?
+                {
+                    Ok(()) => {},
+                    Err(TransferError::LimitExceeded(limit_exceeded)) => {
+                        notification::notify_limit_reached(
+                            conn,
+                            gift_code_move.user_id,
+                            &limit_exceeded,
+                        )
+                        .await?;
+
+                        return Ok(Err(TransferError::LimitExceeded(limit_exceeded)));
+                    },
+                    Err(err) => return Ok(Err(err)),
+                }
+            }
+
+            let available_balance = &account.balance
+                - held_amount(conn, account.user_id, account.currency, now).await?;
+            if available_balance < gift_code_move.amount {
+                return Ok(Err(TransferError::InsufficientBalance));
+            }
+
+            (account, escrow)
+        },
+        TransactionKind::GiftCodeRedemption | TransactionKind::GiftCodeRefund => {
+            anyhow::ensure!(
+                escrow.balance >= gift_code_move.amount,
+                "gift code escrow holds less than the amount"
+            );
+
+            (escrow, account)
+        },
+        _ => anyhow::bail!(
+            "a gift code move must be of kind gift_code_funding, gift_code_redemption or \
+             gift_code_refund"
+        ),
+    };
+
+    let new_transaction = NewTransaction {
+        id: Uuid::now_v7(),
+        amount: gift_code_move.amount.clone(),
+        currency: gift_code_move.currency,
+        recipient: recipient.user_id,
+        sender: sender.user_id,
+        timestamp: now,
+        memo: gift_code_move.memo.clone(),
+        reference: None,
+        kind: gift_code_move.kind,
+        original_transaction_id: None,
+        flagged: false,
+        converted_amount: None,
+        converted_currency: None,
+        exchange_rate: None,
+        spread: None,
+        fee: None,
+        initiator: gift_code_move.initiator,
+        pocket_id: None,
+    };
+
+    let created_transaction = insert_transaction(conn, new_transaction, sender, recipient).await?;
+
+    Ok(Ok(created_transaction))
+}
+
+/// Returns how much of `transaction_id` has been refunded or reversed so far,
+/// in the currency of the transaction. Fees are not refunded.
+pub async fn refunded_amount(
+    conn: &mut DbConnection,
+    transaction_id: Uuid,
+) -> anyhow::Result<BigDecimal> {
+    use crate::models::types;
+    use crate::schema::transactions;
+
+    // Amounts are stored as text, so they are summed here rather than in SQL.
+    let refunds: Vec<Transaction> = transactions::table
+        .filter(transactions::original_transaction_id.eq(types::Uuid::from(transaction_id)))
+        .filter(transactions::kind.eq_any([TransactionKind::Refund, TransactionKind::Reversal]))
+        .select(Transaction::as_select())
+        .load(conn)
+        .await
+        .context("failed to query transactions")?;
+
+    Ok(refunds.iter().map(Transaction::credited_amount).sum())
+}
+
+/// Inserts `new_transaction`, and moves its amount from `sender` to
+/// `recipient`, converted if need be.
+async fn insert_transaction(
+    conn: &mut DbConnection,
+    new_transaction: NewTransaction,
+    mut sender: Account,
+    mut recipient: Account,
+) -> anyhow::Result<Transaction> {
+    use crate::models::types;
+    use crate::schema::{accounts, transactions};
+
+    let created_transaction: Transaction = diesel::insert_into(transactions::table)
+        .values(new_transaction)
+        .returning(Transaction::as_returning())
+        .get_result(conn)
+        .await
+        .context("failed to insert transaction")?;
+    webhook::enqueue_transaction(conn, &created_transaction).await?;
+    notification::notify_transaction(conn, &created_transaction).await?;
+
+    sender.balance -= &created_transaction.amount;
+    recipient.balance += created_transaction.credited_amount();
+
+    let _sender: Account =
+        diesel::update(accounts::table.find((types::Uuid::from(sender.user_id), sender.currency)))
+            .set(sender)
+            .returning(Account::as_returning())
+            .get_result(conn)
+            .await
+            .context("failed to update account")?;
+
+    let _recipient: Account = diesel::update(
+        accounts::table.find((types::Uuid::from(recipient.user_id), recipient.currency)),
+    )
+    .set(recipient)
+    .returning(Account::as_returning())
+    .get_result(conn)
+    .await
+    .context("failed to update account")?;
+
+    Ok(created_transaction)
+}
+
+/// Returns the account of `user_id` in `currency`, if the user has one.
+pub async fn find_account(
+    conn: &mut DbConnection,
+    user_id: Uuid,
+    currency: Currency,
+) -> anyhow::Result<Option<Account>> {
+    use crate::models::types;
+    use crate::schema::accounts;
+
+    accounts::table
+        .find((types::Uuid::from(user_id), currency))
+        .select(Account::as_select())
+        .first(conn)
+        .await
+        .optional()
+        .context("failed to query accounts")
+}
+
+/// Returns the account of the system user `user_id` in `currency`, which is
+/// created on first use.
+async fn system_account(
+    conn: &mut DbConnection,
+    user_id: Uuid,
+    currency: Currency,
+    created_at: jiff::Timestamp,
+) -> anyhow::Result<Account> {
+    use crate::schema::accounts;
+
+    diesel::insert_into(accounts::table)
+        .values(NewAccount {
+            user_id,
+            currency,
+            balance: BigDecimal::zero(),
+            created_at,
+        })
+        .on_conflict_do_nothing()
+        .execute(conn)
+        .await
+        .context("failed to insert account")?;
+
+    find_account(conn, user_id, currency)
+        .await?
+        .context("could not find system account")
+}
+
+/// Returns how much of the balance of `user_id` in `currency` is reserved by
+/// holds which are active at `now`.
+pub async fn held_amount(
+    conn: &mut DbConnection,
+    user_id: Uuid,
+    currency: Currency,
+    now: jiff::Timestamp,
+) -> anyhow::Result<BigDecimal> {
+    use crate::models::types;
+    use crate::schema::holds;
+
+    let amounts: Vec<types::BigDecimal> = holds::table
+        .filter(holds::sender.eq(types::Uuid::from(user_id)))
+        .filter(holds::currency.eq(currency))
+        .filter(holds::status.eq(HoldStatus::Active))
+        .filter(holds::expires_at.gt(types::Timestamp::from(now)))
+        .select(holds::amount)
+        .load(conn)
+        .await
+        .context("failed to query holds")?;
+
+    Ok(amounts.into_iter().map(BigDecimal::from).sum())
+}
+
+/// Whether `err` was caused by SQLite returning `SQLITE_BUSY`.
+fn is_database_busy(err: &anyhow::Error) -> bool {
+    err.chain().any(|err| {
+        matches!(
+            err.downcast_ref::<diesel::result::Error>(),
+            Some(diesel::result::Error::DatabaseError(DatabaseErrorKind::Unknown, info))
+                if info.message().contains("database is locked")
+                    || info.message().contains("database is busy")
+        )
+    })
+}
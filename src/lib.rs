@@ -1,8 +1,24 @@
+pub mod currency;
+pub mod db;
 mod error;
+pub mod event_hub;
+pub mod exchange;
+pub mod fees;
+pub mod gift_code;
 mod handlers;
+pub mod http_url;
+pub mod interest;
 pub mod jwt;
+pub mod ledger;
+pub mod limits;
 pub mod middleware;
 pub mod models;
+pub mod notification;
+pub mod qr_code;
+pub mod rate_limit;
 pub mod routes;
+pub mod scheduler;
 pub mod schema;
+pub mod split;
 pub mod state;
+pub mod webhook;
@@ -0,0 +1,372 @@
+//! Limits on how much and how often users may transfer money.
+
+use std::collections::{BTreeMap, HashSet};
+use std::fmt;
+
+use anyhow::Context as _;
+use bigdecimal::BigDecimal;
+use diesel::prelude::*;
+#[allow(
+    clippy::unused_trait_names,
+    reason = "error[E0034]: multiple applicable items in scope"
+)]
+use diesel_async::RunQueryDsl;
+use jiff::ToSpan as _;
+use jiff::tz::TimeZone;
+use serde::Serialize;
+use uuid::Uuid;
+
+use crate::currency::{Currency, DEFAULT_CURRENCY};
+use crate::exchange;
+use crate::ledger::TransferError;
+use crate::models::TransferLimit;
+use crate::models::transaction::TransactionKind;
+use crate::state::DbConnection;
+
+/// The window in which the number of transfers is counted, unless configured
+/// otherwise.
+pub const DEFAULT_TRANSFER_COUNT_WINDOW: jiff::SignedDuration = jiff::SignedDuration::from_mins(1);
+
+/// Limits on the transfers of a user. Limits which are `None` don't apply.
+///
+/// Amounts are in [`DEFAULT_CURRENCY`]. Daily and monthly limits are per
+/// calendar day and month in UTC, whereas the number of transfers is counted in
+/// a sliding window.
+#[derive(Clone, Debug)]
+pub struct TransferLimits {
+    /// Maximum amount of a single transfer.
+    pub per_transaction: Option<BigDecimal>,
+    /// Maximum total amount sent per day.
+    pub daily: Option<BigDecimal>,
+    /// Maximum total amount sent per month.
+    pub monthly: Option<BigDecimal>,
+    /// Maximum number of transfers per `transfer_count_window`.
+    pub max_transfers: Option<u32>,
+    pub transfer_count_window: jiff::SignedDuration,
+}
+
+/// Which of the [`TransferLimits`] was hit.
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Limit {
+    PerTransaction,
+    Daily,
+    Monthly,
+    TransferCount,
+}
+
+/// A transfer which would exceed one of the sender's [`TransferLimits`].
+#[derive(Clone, Debug, Serialize)]
+pub struct LimitExceeded {
+    pub limit: Limit,
+    /// The value of the limit, i.e. an amount, or a number of transfers for
+    /// [`Limit::TransferCount`].
+    #[serde(with = "bigdecimal::serde::json_num")]
+    pub max: BigDecimal,
+    /// When the limit resets, or `None` for [`Limit::PerTransaction`].
+    pub resets_at: Option<jiff::Timestamp>,
+}
+
+impl Default for TransferLimits {
+    fn default() -> Self {
+        Self {
+            per_transaction: None,
+            daily: None,
+            monthly: None,
+            max_transfers: None,
+            transfer_count_window: DEFAULT_TRANSFER_COUNT_WINDOW,
+        }
+    }
+}
+
+impl TransferLimits {
+    /// Returns these limits, with those set in `transfer_limit` taking
+    /// precedence, and those removed by it not applying.
+    pub fn with_override(&self, transfer_limit: &TransferLimit) -> Self {
+        let apply = |removed: bool, limit: &Option<BigDecimal>, default: &Option<BigDecimal>| {
+            if removed {
+                None
+            } else {
+                limit.clone().or_else(|| default.clone())
+            }
+        };
+
+        Self {
+            per_transaction: apply(
+                transfer_limit.per_transaction_removed,
+                &transfer_limit.per_transaction,
+                &self.per_transaction,
+            ),
+            daily: apply(
+                transfer_limit.daily_removed,
+                &transfer_limit.daily,
+                &self.daily,
+            ),
+            monthly: apply(
+                transfer_limit.monthly_removed,
+                &transfer_limit.monthly,
+                &self.monthly,
+            ),
+            max_transfers: if transfer_limit.max_transfers_removed {
+                None
+            } else {
+                // Limits which aren't positive are rejected when they are set,
+                // but would allow no transfers at all rather than any number.
+                transfer_limit
+                    .max_transfers
+                    .map(|max_transfers| u32::try_from(max_transfers).unwrap_or(0))
+                    .or(self.max_transfers)
+            },
+            transfer_count_window: self.transfer_count_window,
+        }
+    }
+}
+
+impl Limit {
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::PerTransaction => "per transaction",
+            Self::Daily => "daily",
+            Self::Monthly => "monthly",
+            Self::TransferCount => "transfer count",
+        }
+    }
+}
+
+impl fmt::Display for Limit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Returns the limits which apply to `user_id`, i.e. `defaults` with the user's
+/// overrides, if any.
+pub async fn effective_limits(
+    conn: &mut DbConnection,
+    defaults: &TransferLimits,
+    user_id: Uuid,
+) -> anyhow::Result<TransferLimits> {
+    use crate::models::types;
+    use crate::schema::transfer_limits;
+
+    let transfer_limit: Option<TransferLimit> = transfer_limits::table
+        .find(types::Uuid::from(user_id))
+        .select(TransferLimit::as_select())
+        .first(conn)
+        .await
+        .optional()
+        .context("failed to query transfer limits")?;
+
+    Ok(match transfer_limit {
+        Some(transfer_limit) => defaults.with_override(&transfer_limit),
+        None => defaults.clone(),
+    })
+}
+
+/// Checks whether `user_id` may send `amount` in `currency` at `now` within
+/// `limits`.
+///
+/// Only transfers and the funding of gift codes count towards the limits, not
+/// refunds or reversals. The number of transfers is counted across all
+/// currencies, with the transfers of a batch counting as one.
+///
+/// Amount limits are in [`DEFAULT_CURRENCY`], so amounts in other currencies
+/// are converted at the current exchange rates before they are compared, see
+/// [`exchange::find_mid_rate`]. As long as amount limits apply, transfers in
+/// currencies without an exchange rate are refused.
+pub async fn check(
+    conn: &mut DbConnection,
+    limits: &TransferLimits,
+    user_id: Uuid,
+    amount: &BigDecimal,
+    currency: Currency,
+    now: jiff::Timestamp,
+) -> anyhow::Result<Result<(), TransferError>> {
+    use crate::models::types;
+    use crate::schema::{transactions, transfer_batch_items};
+
+    let limits_amounts =
+        limits.per_transaction.is_some() || limits.daily.is_some() || limits.monthly.is_some();
+    if !limits_amounts && limits.max_transfers.is_none() {
+        return Ok(Ok(()));
+    }
+
+    let mut rates = BTreeMap::new();
+    let amount = if limits_amounts {
+        match to_default_currency(conn, &mut rates, amount, currency).await? {
+            Ok(amount) => amount,
+            Err(err) => return Ok(Err(err)),
+        }
+    } else {
+        amount.clone()
+    };
+
+    if let Some(max) = &limits.per_transaction
+        && amount > *max
+    {
+        return Ok(Err(TransferError::LimitExceeded(LimitExceeded {
+            limit: Limit::PerTransaction,
+            max: max.clone(),
+            resets_at: None,
+        })));
+    }
+
+    if limits.daily.is_none() && limits.monthly.is_none() && limits.max_transfers.is_none() {
+        return Ok(Ok(()));
+    }
+
+    let today = now
+        .to_zoned(TimeZone::UTC)
+        .start_of_day()
+        .context("failed to get start of day")?;
+    let this_month = today
+        .first_of_month()
+        .context("failed to get first of month")?;
+    let window_start = now
+        .checked_sub(limits.transfer_count_window)
+        .context("transfer count window out of range")?;
+
+    // Both windows are loaded at once, and told apart below.
+    let since = this_month.timestamp().min(window_start);
+    let recent_transfers: Vec<(
+        types::BigDecimal,
+        Currency,
+        types::Timestamp,
+        Option<types::Uuid>,
+    )> = transactions::table
+        .left_join(transfer_batch_items::table)
+        .filter(transactions::sender.eq(types::Uuid::from(user_id)))
+        .filter(
+            transactions::kind
+                .eq_any([TransactionKind::Transfer, TransactionKind::GiftCodeFunding]),
+        )
+        .filter(transactions::timestamp.ge(types::Timestamp::from(since)))
+        .order(transactions::timestamp.asc())
+        .select((
+            transactions::amount,
+            transactions::currency,
+            transactions::timestamp,
+            transfer_batch_items::batch_id.nullable(),
+        ))
+        .load(conn)
+        .await
+        .context("failed to query transactions")?;
+    let recent_transfers = recent_transfers
+        .into_iter()
+        .map(|(amount, currency, timestamp, batch_id)| {
+            (
+                BigDecimal::from(amount),
+                currency,
+                jiff::Timestamp::from(timestamp),
+                batch_id.map(Uuid::from),
+            )
+        })
+        .collect::<Vec<_>>();
+
+    if let Some(max_transfers) = limits.max_transfers {
+        // The transfers of a batch count as one, at the time of the first.
+        let mut counted_batch_ids = HashSet::new();
+        let timestamps = recent_transfers
+            .iter()
+            .filter(|(_amount, _currency, timestamp, _batch_id)| *timestamp > window_start)
+            .filter(|(_amount, _currency, _timestamp, batch_id)| {
+                batch_id.is_none_or(|batch_id| counted_batch_ids.insert(batch_id))
+            })
+            .map(|(_amount, _currency, timestamp, _batch_id)| *timestamp)
+            .collect::<Vec<_>>();
+        let max = usize::try_from(max_transfers).context("transfer count limit out of range")?;
+        if timestamps.len() >= max {
+            // The limit resets once enough of the transfers in the window have
+            // dropped out of it.
+            let resets_at = timestamps
+                .get(timestamps.len().saturating_sub(max))
+                .map(|timestamp| timestamp.checked_add(limits.transfer_count_window))
+                .transpose()
+                .context("transfer count window out of range")?;
+
+            return Ok(Err(TransferError::LimitExceeded(LimitExceeded {
+                limit: Limit::TransferCount,
+                max: BigDecimal::from(max_transfers),
+                resets_at,
+            })));
+        }
+    }
+
+    if limits.daily.is_none() && limits.monthly.is_none() {
+        return Ok(Ok(()));
+    }
+
+    // What was sent this month, in the default currency.
+    let mut sent_this_month = Vec::new();
+    for (amount, transfer_currency, timestamp, _batch_id) in &recent_transfers {
+        if *timestamp < this_month.timestamp() {
+            continue;
+        }
+        match to_default_currency(conn, &mut rates, amount, *transfer_currency).await? {
+            Ok(amount) => sent_this_month.push((amount, *timestamp)),
+            Err(err) => return Ok(Err(err)),
+        }
+    }
+
+    for (limit, max, start, end) in [
+        (
+            Limit::Daily,
+            &limits.daily,
+            &today,
+            today.checked_add(1.day()),
+        ),
+        (
+            Limit::Monthly,
+            &limits.monthly,
+            &this_month,
+            this_month.checked_add(1.month()),
+        ),
+    ] {
+        let Some(max) = max else {
+            continue;
+        };
+
+        let sent = sent_this_month
+            .iter()
+            .filter(|(_amount, timestamp)| *timestamp >= start.timestamp())
+            .map(|(amount, _timestamp)| amount)
+            .sum::<BigDecimal>();
+        if sent + &amount > *max {
+            let end = end.context("failed to get end of limit period")?;
+
+            return Ok(Err(TransferError::LimitExceeded(LimitExceeded {
+                limit,
+                max: max.clone(),
+                resets_at: Some(end.timestamp()),
+            })));
+        }
+    }
+
+    Ok(Ok(()))
+}
+
+/// Converts `amount` in `currency` into [`DEFAULT_CURRENCY`], which limits are
+/// in, looking up and caching the exchange rate in `rates` as needed.
+async fn to_default_currency(
+    conn: &mut DbConnection,
+    rates: &mut BTreeMap<Currency, Option<BigDecimal>>,
+    amount: &BigDecimal,
+    currency: Currency,
+) -> anyhow::Result<Result<BigDecimal, TransferError>> {
+    if currency == DEFAULT_CURRENCY {
+        return Ok(Ok(amount.clone()));
+    }
+
+    let rate = match rates.get(&currency) {
+        Some(rate) => rate.clone(),
+        None => {
+            let rate = exchange::find_mid_rate(conn, currency, DEFAULT_CURRENCY).await?;
+            rates.insert(currency, rate.clone());
+            rate
+        },
+    };
+
+    Ok(rate
+        .map(|rate| amount * rate)
+        .ok_or(TransferError::RateUnavailable(currency)))
+}
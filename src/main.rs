@@ -1,42 +1,49 @@
 use std::path::PathBuf;
+use std::str::FromStr;
 use std::{env, io};
 
 use anyhow::{Context as _, Result, ensure};
+use axum::BoxError;
 use axum::error_handling::HandleErrorLayer;
 use axum::http::request::Parts as RequestParts;
 use axum::http::{HeaderValue, StatusCode, header};
-use axum::routing::get;
-use axum::{BoxError, Router, middleware};
+use axum_diesel_example::currency::DEFAULT_CURRENCY;
+use axum_diesel_example::db;
+use axum_diesel_example::event_hub::EventHub;
+use axum_diesel_example::http_url::HostPolicy;
 use axum_diesel_example::jwt::HS256_SECRET_KEY_LEN;
-use axum_diesel_example::middleware::auth::authenticate_with_jwt_access_token;
-use axum_diesel_example::models::user::NewUser;
-use axum_diesel_example::routes;
+use axum_diesel_example::ledger::{self, NewIssuance};
+use axum_diesel_example::limits::{DEFAULT_TRANSFER_COUNT_WINDOW, TransferLimits};
+use axum_diesel_example::models::account::NewAccount;
+use axum_diesel_example::models::transaction::TransactionKind;
+use axum_diesel_example::models::user::{NewUser, UserKind, UserRole, UserTier};
+use axum_diesel_example::rate_limit::RateLimiter;
 use axum_diesel_example::state::{
     AccessTokenAudience, AccessTokenClientId, AccessTokenExpiration, AccessTokenIssuer, AppState,
-    AuthState, DbConnectionPool, JwsSigningSecret,
+    AuthState, DbConnectionPool, GiftCodeRedemptionRateLimiter, JwsSigningSecret, PublicUrl,
+    SignupGrant, UsernameLookupRateLimiter,
 };
-use axum_extra::vpath;
+use axum_diesel_example::webhook::{self, WebhookClient};
+use axum_diesel_example::{gift_code, interest, routes, scheduler};
 use base64ct::{Base64, Encoding as _};
-use bigdecimal::BigDecimal;
-use diesel::{ConnectionError, ConnectionResult, SqliteConnection};
-use diesel_async::pooled_connection::deadpool::Pool;
-use diesel_async::pooled_connection::{AsyncDieselConnectionManager, ManagerConfig};
-use diesel_async::sync_connection_wrapper::SyncConnectionWrapper;
-use diesel_async::{AsyncConnection as _, SimpleAsyncConnection as _};
-use futures_lite::FutureExt as _;
-use secrecy::SecretSlice;
+use bigdecimal::{BigDecimal, Zero as _};
+use secrecy::{ExposeSecret as _, SecretSlice, SecretString};
 use tokio::net::TcpListener;
 use tower::ServiceBuilder;
 use tower_http::cors::{AllowOrigin, CorsLayer};
 use tower_http::services::ServeDir;
 use tower_http::trace::TraceLayer;
-use tracing::{error, info};
+use tracing::{error, info, warn};
 use tracing_subscriber::layer::SubscriberExt as _;
 use tracing_subscriber::util::SubscriberInitExt as _;
 use uuid::Uuid;
 
 const SERVICE_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(10);
-const DB_CONNECT_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(5);
+const USERNAME_LOOKUP_RATE_LIMIT: u32 = 10;
+const USERNAME_LOOKUP_RATE_LIMIT_WINDOW: std::time::Duration = std::time::Duration::from_secs(60);
+const GIFT_CODE_REDEMPTION_RATE_LIMIT: u32 = 5;
+const GIFT_CODE_REDEMPTION_RATE_LIMIT_WINDOW: std::time::Duration =
+    std::time::Duration::from_secs(60);
 
 #[tokio::main]
 async fn main() -> Result<()> {
@@ -47,25 +54,28 @@ async fn main() -> Result<()> {
     init_tracing_subscriber();
 
     let db_url = env::var("DATABASE_URL").context("`DATABASE_URL` env var should be set")?;
-    let db_connection_pool = {
-        let mut config = ManagerConfig::default();
-        config.custom_setup = Box::new(|db_url| establish_connection(db_url).boxed());
-        let manager: AsyncDieselConnectionManager<SyncConnectionWrapper<SqliteConnection>> =
-            AsyncDieselConnectionManager::<_>::new_with_config(db_url, config);
-        Pool::builder(manager)
-            .create_timeout(Some(DB_CONNECT_TIMEOUT))
-            .runtime(deadpool::Runtime::Tokio1)
-            .build()?
-    };
+    let db_connection_pool = db::build_connection_pool(db_url)?;
 
-    create_user_fixtures(db_connection_pool.clone()).await?;
+    let signup_grant =
+        SignupGrant(parse_optional_env_var("SIGNUP_GRANT")?.unwrap_or_else(BigDecimal::zero));
 
-    let state = AppState {
-        db_connection_pool: db_connection_pool.clone(),
-    };
+    // Money is only minted for the fixtures if asked to, as it otherwise only
+    // enters through admins.
+    let fixture_treasury_supply = parse_optional_env_var("FIXTURE_TREASURY_SUPPLY")?;
+
+    create_user_fixtures(
+        db_connection_pool.clone(),
+        fixture_treasury_supply,
+        &signup_grant,
+    )
+    .await?;
+    bootstrap_admin(db_connection_pool.clone()).await?;
+
+    // Events are published by handlers and background jobs alike.
+    let event_hub = EventHub::new();
 
     let auth_state = AuthState {
-        db_connection_pool,
+        db_connection_pool: db_connection_pool.clone(),
         jws_signing_secret: JwsSigningSecret(SecretSlice::from({
             let secret = Base64::decode_vec(
                 &env::var("JWS_SIGNING_HMAC_SECRET_KEY")
@@ -102,6 +112,83 @@ async fn main() -> Result<()> {
                 .parse()
                 .context("`ACCESS_TOKEN_CLIENT_ID` env var should be a valid UUID")?,
         ),
+        signup_grant,
+        event_hub: event_hub.clone(),
+    };
+
+    // Transfers are unlimited unless configured otherwise.
+    let default_transfer_limits = TransferLimits {
+        per_transaction: parse_optional_env_var("TRANSFER_LIMIT_PER_TRANSACTION")?,
+        daily: parse_optional_env_var("TRANSFER_LIMIT_DAILY")?,
+        monthly: parse_optional_env_var("TRANSFER_LIMIT_MONTHLY")?,
+        max_transfers: parse_optional_env_var("TRANSFER_LIMIT_MAX_TRANSFERS")?,
+        transfer_count_window: parse_optional_env_var("TRANSFER_LIMIT_WINDOW")?
+            .unwrap_or(DEFAULT_TRANSFER_COUNT_WINDOW),
+    };
+    ensure!(
+        default_transfer_limits.max_transfers != Some(0),
+        "`TRANSFER_LIMIT_MAX_TRANSFERS` env var should be positive"
+    );
+    ensure!(
+        default_transfer_limits.transfer_count_window.is_positive(),
+        "`TRANSFER_LIMIT_WINDOW` env var should be positive"
+    );
+
+    // Run scheduled transfers in the background, for as long as the service runs.
+    tokio::spawn(scheduler::run_scheduled_transfers(
+        db_connection_pool.clone(),
+        default_transfer_limits.clone(),
+        event_hub.clone(),
+    ));
+
+    // Accrue and pay interest in the background too.
+    tokio::spawn(interest::run_interest(
+        db_connection_pool.clone(),
+        event_hub.clone(),
+    ));
+
+    // Webhooks may only be sent to public addresses, unless configured otherwise
+    // for local development.
+    let webhook_host_policy =
+        if parse_optional_env_var("WEBHOOK_ALLOW_PRIVATE_HOSTS")?.unwrap_or(false) {
+            HostPolicy::Any
+        } else {
+            HostPolicy::PublicOnly
+        };
+
+    // Send webhooks in the background as well.
+    tokio::spawn(webhook::run_webhook_deliveries(
+        db_connection_pool.clone(),
+        WebhookClient::new(webhook_host_policy)?,
+    ));
+
+    // Expire gift codes and refund what is left of them in the background.
+    tokio::spawn(gift_code::run_gift_code_expiry(
+        db_connection_pool.clone(),
+        event_hub.clone(),
+    ));
+
+    let state = AppState {
+        db_connection_pool,
+        username_lookup_rate_limiter: UsernameLookupRateLimiter(RateLimiter::new(
+            USERNAME_LOOKUP_RATE_LIMIT,
+            USERNAME_LOOKUP_RATE_LIMIT_WINDOW,
+        )),
+        gift_code_redemption_rate_limiter: GiftCodeRedemptionRateLimiter(RateLimiter::new(
+            GIFT_CODE_REDEMPTION_RATE_LIMIT,
+            GIFT_CODE_REDEMPTION_RATE_LIMIT_WINDOW,
+        )),
+        jws_signing_secret: auth_state.jws_signing_secret.clone(),
+        access_token_issuer: auth_state.access_token_issuer.clone(),
+        public_url: PublicUrl(
+            env::var("PUBLIC_URL")
+                .context("`PUBLIC_URL` env var should be set")?
+                .parse()
+                .context("`PUBLIC_URL` env var should be a valid URL")?,
+        ),
+        default_transfer_limits,
+        event_hub,
+        webhook_host_policy,
     };
 
     // Serve the frontend as static files. In production you'd not want to serve
@@ -111,24 +198,7 @@ async fn main() -> Result<()> {
     let static_files_service = ServeDir::new(frontend_dir).append_index_html_on_directories(true);
 
     // Create the app router.
-    let app = Router::new()
-        .nest(vpath!("/users"), routes::user::routes())
-        .nest(vpath!("/transactions"), routes::transaction::routes())
-        .with_state(state)
-        .layer(
-            // Require authentication for any routes added before this middleware.
-            //
-            // See <https://docs.rs/axum/latest/axum/middleware/index.html#ordering>
-            ServiceBuilder::new().layer(middleware::from_fn_with_state(
-                auth_state.clone(),
-                authenticate_with_jwt_access_token,
-            )),
-        )
-        .route(vpath!("/hello"), get(async || "hello world!"))
-        .nest(
-            vpath!("/auth"),
-            routes::auth::routes().with_state(auth_state),
-        )
+    let app = routes::router(state, auth_state)
         .layer(
             // Use a service builder to compose multiple middleware layers. They are run from top
             // to bottom.
@@ -211,41 +281,30 @@ fn init_tracing_subscriber() {
         .init();
 }
 
-async fn establish_connection(
-    db_url: &str,
-) -> ConnectionResult<SyncConnectionWrapper<SqliteConnection>> {
-    let mut conn = SyncConnectionWrapper::<SqliteConnection>::establish(db_url).await?;
-
-    // See <https://fractaledmind.github.io/2023/09/07/enhancing-rails-sqlite-fine-tuning/>
-
-    // Sleep if the database is busy, this corresponds to up to 2 seconds sleeping
-    // time.
-    conn.batch_execute("PRAGMA busy_timeout = 2000;")
-        .await
-        .map_err(ConnectionError::CouldntSetupConfiguration)?;
-    // Better write-concurrency.
-    conn.batch_execute("PRAGMA journal_mode = WAL;")
-        .await
-        .map_err(ConnectionError::CouldntSetupConfiguration)?;
-    // Fsync only in critical moments.
-    conn.batch_execute("PRAGMA synchronous = NORMAL;")
-        .await
-        .map_err(ConnectionError::CouldntSetupConfiguration)?;
-    // Write WAL changes back every 1000 pages, for a 1MB WAL file on average.
-    // May affect readers if number is increased.
-    conn.batch_execute("PRAGMA wal_autocheckpoint = 1000;")
-        .await
-        .map_err(ConnectionError::CouldntSetupConfiguration)?;
-    // Free some space by truncating possibly massive WAL files from the last run.
-    conn.batch_execute("PRAGMA wal_checkpoint(TRUNCATE);")
-        .await
-        .map_err(ConnectionError::CouldntSetupConfiguration)?;
-
-    Ok(conn)
+/// Parses the env var `key`, if it is set.
+fn parse_optional_env_var<T>(key: &str) -> Result<Option<T>>
+where
+    T: FromStr,
+    T::Err: std::error::Error + Send + Sync + 'static,
+{
+    match env::var(key) {
+        Ok(value) => value
+            .parse()
+            .map(Some)
+            .with_context(|| format!("`{key}` env var should be valid")),
+        Err(env::VarError::NotPresent) => Ok(None),
+        Err(err) => Err(err).with_context(|| format!("`{key}` env var should be valid Unicode")),
+    }
 }
 
-async fn create_user_fixtures(pool: DbConnectionPool) -> Result<()> {
-    use axum_diesel_example::schema::users;
+/// Creates the fixture users, first minting `treasury_supply` into the
+/// treasury if it is set and the treasury has no account yet.
+async fn create_user_fixtures(
+    pool: DbConnectionPool,
+    treasury_supply: Option<BigDecimal>,
+    signup_grant: &SignupGrant,
+) -> Result<()> {
+    use axum_diesel_example::schema::{accounts, users};
     #[allow(
         clippy::unused_trait_names,
         reason = "error[E0034]: multiple applicable items in scope"
@@ -257,30 +316,153 @@ async fn create_user_fixtures(pool: DbConnectionPool) -> Result<()> {
         .await
         .context("failed to get database connection")?;
 
+    if let Some(treasury_supply) = treasury_supply
+        && ledger::find_account(&mut conn, ledger::TREASURY_USER_ID, DEFAULT_CURRENCY)
+            .await?
+            .is_none()
+    {
+        ledger::issue(
+            &mut conn,
+            &NewIssuance {
+                amount: treasury_supply,
+                currency: DEFAULT_CURRENCY,
+                initiator: None,
+                kind: TransactionKind::Mint,
+                memo: Some("Fixture supply".to_owned()),
+            },
+        )
+        .await?
+        .map_err(|err| anyhow::anyhow!("failed to mint fixture supply: {err:?}"))?;
+    }
+
     let new_users = vec![
         NewUser {
             id: Uuid::now_v7(),
             username: "john_doe".to_owned(),
             password_hash: password_auth::generate_hash("abc123").into(),
-            balance: BigDecimal::from(12_345),
+            role: UserRole::User,
+            tier: UserTier::Standard,
+            kind: UserKind::Personal,
         },
         NewUser {
             id: Uuid::now_v7(),
             username: "mary_jane".to_owned(),
             password_hash: password_auth::generate_hash("password").into(),
-            balance: BigDecimal::from(45_678),
+            role: UserRole::User,
+            tier: UserTier::Standard,
+            kind: UserKind::Personal,
+        },
+        NewUser {
+            id: Uuid::now_v7(),
+            username: "acme_store".to_owned(),
+            password_hash: password_auth::generate_hash("merchant").into(),
+            role: UserRole::User,
+            tier: UserTier::Standard,
+            kind: UserKind::Merchant,
         },
     ];
 
-    // Insert these users if they don't already exist, otherwise do nothing.
+    // Insert these users with an account in the default currency if they
+    // don't already exist, otherwise do nothing. Like new users, they are
+    // granted money from the treasury if it holds enough.
     for new_user in new_users {
-        diesel::insert_into(users::table)
+        let new_account = NewAccount {
+            user_id: new_user.id,
+            currency: DEFAULT_CURRENCY,
+            balance: BigDecimal::zero(),
+            created_at: jiff::Timestamp::now(),
+        };
+        let user_id = new_user.id;
+        let inserted = diesel::insert_into(users::table)
             .values(new_user)
             .on_conflict(users::username)
             .do_nothing()
             .execute(&mut conn)
             .await
             .context("failed to insert user")?;
+        if inserted > 0 {
+            diesel::insert_into(accounts::table)
+                .values(new_account)
+                .execute(&mut conn)
+                .await
+                .context("failed to insert account")?;
+
+            if !signup_grant.0.is_zero()
+                && let Err(err) = ledger::run_immediate_transaction(&mut conn, |conn| {
+                    let amount = signup_grant.0.clone();
+                    Box::pin(async move {
+                        ledger::apply_grant(conn, user_id, &amount, DEFAULT_CURRENCY).await
+                    })
+                })
+                .await?
+            {
+                warn!(%user_id, ?err, "could not grant fixture user");
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Creates the admin named by the `ADMIN_USERNAME` env var with the password in
+/// `ADMIN_PASSWORD`, unless a user by that name already exists.
+///
+/// This is how the first admin is created, as there is no default one. Unset
+/// both env vars once the admin exists, and have them change their password.
+async fn bootstrap_admin(pool: DbConnectionPool) -> Result<()> {
+    use axum_diesel_example::schema::{accounts, users};
+    #[allow(
+        clippy::unused_trait_names,
+        reason = "error[E0034]: multiple applicable items in scope"
+    )]
+    use diesel_async::RunQueryDsl;
+
+    let Some(username) = parse_optional_env_var::<String>("ADMIN_USERNAME")? else {
+        return Ok(());
+    };
+    let password = SecretString::from(
+        env::var("ADMIN_PASSWORD")
+            .context("`ADMIN_PASSWORD` env var should be set along with `ADMIN_USERNAME`")?,
+    );
+    ensure!(
+        !password.expose_secret().is_empty(),
+        "`ADMIN_PASSWORD` env var should not be empty"
+    );
+
+    let mut conn = pool
+        .get()
+        .await
+        .context("failed to get database connection")?;
+
+    let new_user = NewUser {
+        id: Uuid::now_v7(),
+        username: username.clone(),
+        password_hash: password_auth::generate_hash(password.expose_secret()).into(),
+        role: UserRole::Admin,
+        tier: UserTier::Standard,
+        kind: UserKind::Personal,
+    };
+    let new_account = NewAccount {
+        user_id: new_user.id,
+        currency: DEFAULT_CURRENCY,
+        balance: BigDecimal::zero(),
+        created_at: jiff::Timestamp::now(),
+    };
+    let inserted = diesel::insert_into(users::table)
+        .values(new_user)
+        .on_conflict(users::username)
+        .do_nothing()
+        .execute(&mut conn)
+        .await
+        .context("failed to insert admin")?;
+    if inserted > 0 {
+        diesel::insert_into(accounts::table)
+            .values(new_account)
+            .execute(&mut conn)
+            .await
+            .context("failed to insert account")?;
+
+        info!(username, "created admin");
     }
 
     Ok(())
@@ -41,6 +41,10 @@ pub struct JwtAccessTokenClaims {
     pub client_id: String,
 }
 
+#[allow(
+    dead_code,
+    reason = "reserved for telling ID tokens and access tokens apart"
+)]
 #[derive(Eq, PartialEq, Debug, Deserialize, Serialize)]
 #[serde(rename_all = "lowercase")]
 enum TokenUse {
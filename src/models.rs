@@ -1,6 +1,42 @@
+pub use self::account::Account;
+pub use self::checkout_session::CheckoutSession;
+pub use self::exchange_quote::ExchangeQuote;
+pub use self::exchange_rate::ExchangeRate;
+pub use self::fee_rule::FeeRule;
+pub use self::gift_code::GiftCode;
+pub use self::group::Group;
+pub use self::hold::Hold;
+pub use self::interest_accrual::InterestAccrual;
+pub use self::interest_carryover::InterestCarryover;
+pub use self::interest_rate::InterestRate;
+pub use self::notification::Notification;
+pub use self::payment_request::PaymentRequest;
+pub use self::pocket::Pocket;
+pub use self::scheduled_transfer::ScheduledTransfer;
 pub use self::transaction::Transaction;
+pub use self::transfer_batch::TransferBatch;
+pub use self::transfer_limit::TransferLimit;
 pub use self::user::User;
+pub use self::webhook::{WebhookDelivery, WebhookEndpoint};
 
+pub mod account;
+pub mod checkout_session;
+pub mod exchange_quote;
+pub mod exchange_rate;
+pub mod fee_rule;
+pub mod gift_code;
+pub mod group;
+pub mod hold;
+pub mod interest_accrual;
+pub mod interest_carryover;
+pub mod interest_rate;
+pub mod notification;
+pub mod payment_request;
+pub mod pocket;
+pub mod scheduled_transfer;
 pub mod transaction;
+pub mod transfer_batch;
+pub mod transfer_limit;
 pub mod types;
 pub mod user;
+pub mod webhook;
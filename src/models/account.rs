@@ -0,0 +1,44 @@
+use bigdecimal::BigDecimal;
+use diesel::prelude::*;
+use diesel::sqlite::Sqlite;
+use uuid::Uuid;
+
+use super::types;
+use crate::currency::Currency;
+use crate::schema::accounts;
+
+/// The balance of a user in one currency.
+#[derive(Debug, AsChangeset, Identifiable, Queryable, Selectable)]
+#[diesel(table_name = accounts)]
+#[diesel(primary_key(user_id, currency))]
+#[diesel(check_for_backend(Sqlite))]
+pub struct Account {
+    #[diesel(
+        serialize_as = types::Uuid,
+        deserialize_as = types::Uuid,
+    )]
+    pub user_id: Uuid,
+    pub currency: Currency,
+    #[diesel(
+        serialize_as = types::BigDecimal,
+        deserialize_as = types::BigDecimal,
+    )]
+    pub balance: BigDecimal,
+    #[diesel(
+        serialize_as = types::Timestamp,
+        deserialize_as = types::Timestamp,
+    )]
+    pub created_at: jiff::Timestamp,
+}
+
+#[derive(Clone, Debug, Insertable)]
+#[diesel(table_name = accounts)]
+pub struct NewAccount {
+    #[diesel(serialize_as = types::Uuid)]
+    pub user_id: Uuid,
+    pub currency: Currency,
+    #[diesel(serialize_as = types::BigDecimal)]
+    pub balance: BigDecimal,
+    #[diesel(serialize_as = types::Timestamp)]
+    pub created_at: jiff::Timestamp,
+}
@@ -0,0 +1,158 @@
+use std::fmt;
+
+use bigdecimal::BigDecimal;
+use diesel::deserialize::{self, FromSql};
+use diesel::prelude::*;
+use diesel::serialize::{self, Output, ToSql};
+use diesel::sql_types::Text;
+use diesel::sqlite::{Sqlite, SqliteValue};
+use diesel::{AsExpression, FromSqlRow};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use super::types;
+use crate::currency::Currency;
+use crate::schema::checkout_sessions;
+
+/// A payment a merchant asks for, which a payer completes on the hosted
+/// checkout page.
+#[derive(Debug, AsChangeset, Identifiable, Queryable, Selectable)]
+#[diesel(table_name = checkout_sessions)]
+#[diesel(check_for_backend(Sqlite))]
+#[diesel(treat_none_as_null = true)]
+pub struct CheckoutSession {
+    #[diesel(
+        serialize_as = types::Uuid,
+        deserialize_as = types::Uuid,
+    )]
+    pub id: Uuid,
+    /// The merchant who created the session, and who will receive the money.
+    #[diesel(
+        serialize_as = types::Uuid,
+        deserialize_as = types::Uuid,
+    )]
+    pub merchant_id: Uuid,
+    #[diesel(
+        serialize_as = types::BigDecimal,
+        deserialize_as = types::BigDecimal,
+    )]
+    pub amount: BigDecimal,
+    pub currency: Currency,
+    /// The merchant's own reference, e.g. an order number, which is set on the
+    /// transaction.
+    pub reference: String,
+    pub memo: Option<String>,
+    /// Where the payer is sent after paying.
+    pub success_url: String,
+    /// Where the payer is sent if they don't pay.
+    pub cancel_url: String,
+    pub status: CheckoutSessionStatus,
+    #[diesel(
+        serialize_as = types::Timestamp,
+        deserialize_as = types::Timestamp,
+    )]
+    pub created_at: jiff::Timestamp,
+    #[diesel(
+        serialize_as = types::Timestamp,
+        deserialize_as = types::Timestamp,
+    )]
+    pub expires_at: jiff::Timestamp,
+    #[diesel(
+        serialize_as = types::NullableTimestamp,
+        deserialize_as = types::NullableTimestamp,
+    )]
+    pub completed_at: Option<jiff::Timestamp>,
+    #[diesel(
+        serialize_as = types::NullableUuid,
+        deserialize_as = types::NullableUuid,
+    )]
+    pub payer: Option<Uuid>,
+    #[diesel(
+        serialize_as = types::NullableUuid,
+        deserialize_as = types::NullableUuid,
+    )]
+    pub transaction_id: Option<Uuid>,
+}
+
+#[derive(Debug, Insertable)]
+#[diesel(table_name = checkout_sessions)]
+pub struct NewCheckoutSession {
+    #[diesel(serialize_as = types::Uuid)]
+    pub id: Uuid,
+    #[diesel(serialize_as = types::Uuid)]
+    pub merchant_id: Uuid,
+    #[diesel(serialize_as = types::BigDecimal)]
+    pub amount: BigDecimal,
+    pub currency: Currency,
+    pub reference: String,
+    pub memo: Option<String>,
+    pub success_url: String,
+    pub cancel_url: String,
+    pub status: CheckoutSessionStatus,
+    #[diesel(serialize_as = types::Timestamp)]
+    pub created_at: jiff::Timestamp,
+    #[diesel(serialize_as = types::Timestamp)]
+    pub expires_at: jiff::Timestamp,
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug, AsExpression, FromSqlRow, Deserialize, Serialize)]
+#[diesel(sql_type = Text)]
+#[serde(rename_all = "lowercase")]
+pub enum CheckoutSessionStatus {
+    Open,
+    Completed,
+    Cancelled,
+    Expired,
+}
+
+impl CheckoutSession {
+    /// Returns the status of this checkout session at `now`, taking expiry into
+    /// account.
+    ///
+    /// Expired checkout sessions are only marked as such in the database when
+    /// they are next updated.
+    pub fn status_at(&self, now: jiff::Timestamp) -> CheckoutSessionStatus {
+        if self.status == CheckoutSessionStatus::Open && self.expires_at <= now {
+            CheckoutSessionStatus::Expired
+        } else {
+            self.status
+        }
+    }
+}
+
+impl CheckoutSessionStatus {
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Open => "open",
+            Self::Completed => "completed",
+            Self::Cancelled => "cancelled",
+            Self::Expired => "expired",
+        }
+    }
+}
+
+impl fmt::Display for CheckoutSessionStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromSql<Text, Sqlite> for CheckoutSessionStatus {
+    fn from_sql(bytes: SqliteValue<'_, '_, '_>) -> deserialize::Result<Self> {
+        let s = <String as FromSql<Text, Sqlite>>::from_sql(bytes)?;
+
+        match &*s {
+            "open" => Ok(Self::Open),
+            "completed" => Ok(Self::Completed),
+            "cancelled" => Ok(Self::Cancelled),
+            "expired" => Ok(Self::Expired),
+            _ => Err(format!("unknown checkout session status: {s}").into()),
+        }
+    }
+}
+
+impl ToSql<Text, Sqlite> for CheckoutSessionStatus {
+    fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, Sqlite>) -> serialize::Result {
+        <str as ToSql<Text, Sqlite>>::to_sql(self.as_str(), out)
+    }
+}
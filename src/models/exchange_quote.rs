@@ -0,0 +1,88 @@
+use bigdecimal::BigDecimal;
+use diesel::prelude::*;
+use diesel::sqlite::Sqlite;
+use uuid::Uuid;
+
+use super::types;
+use crate::currency::Currency;
+use crate::schema::exchange_quotes;
+
+/// A conversion offered to a user at a locked rate, which the user may use for
+/// one transfer until it expires.
+#[derive(Debug, AsChangeset, Identifiable, Queryable, Selectable)]
+#[diesel(table_name = exchange_quotes)]
+#[diesel(check_for_backend(Sqlite))]
+#[diesel(treat_none_as_null = true)]
+pub struct ExchangeQuote {
+    #[diesel(
+        serialize_as = types::Uuid,
+        deserialize_as = types::Uuid,
+    )]
+    pub id: Uuid,
+    #[diesel(
+        serialize_as = types::Uuid,
+        deserialize_as = types::Uuid,
+    )]
+    pub user_id: Uuid,
+    pub source_currency: Currency,
+    #[diesel(
+        serialize_as = types::BigDecimal,
+        deserialize_as = types::BigDecimal,
+    )]
+    pub source_amount: BigDecimal,
+    pub target_currency: Currency,
+    #[diesel(
+        serialize_as = types::BigDecimal,
+        deserialize_as = types::BigDecimal,
+    )]
+    pub target_amount: BigDecimal,
+    #[diesel(
+        serialize_as = types::BigDecimal,
+        deserialize_as = types::BigDecimal,
+    )]
+    pub rate: BigDecimal,
+    #[diesel(
+        serialize_as = types::BigDecimal,
+        deserialize_as = types::BigDecimal,
+    )]
+    pub spread: BigDecimal,
+    #[diesel(
+        serialize_as = types::Timestamp,
+        deserialize_as = types::Timestamp,
+    )]
+    pub created_at: jiff::Timestamp,
+    #[diesel(
+        serialize_as = types::Timestamp,
+        deserialize_as = types::Timestamp,
+    )]
+    pub expires_at: jiff::Timestamp,
+    /// The transfer which used the quote, if any.
+    #[diesel(
+        serialize_as = types::NullableUuid,
+        deserialize_as = types::NullableUuid,
+    )]
+    pub transaction_id: Option<Uuid>,
+}
+
+#[derive(Debug, Insertable)]
+#[diesel(table_name = exchange_quotes)]
+pub struct NewExchangeQuote {
+    #[diesel(serialize_as = types::Uuid)]
+    pub id: Uuid,
+    #[diesel(serialize_as = types::Uuid)]
+    pub user_id: Uuid,
+    pub source_currency: Currency,
+    #[diesel(serialize_as = types::BigDecimal)]
+    pub source_amount: BigDecimal,
+    pub target_currency: Currency,
+    #[diesel(serialize_as = types::BigDecimal)]
+    pub target_amount: BigDecimal,
+    #[diesel(serialize_as = types::BigDecimal)]
+    pub rate: BigDecimal,
+    #[diesel(serialize_as = types::BigDecimal)]
+    pub spread: BigDecimal,
+    #[diesel(serialize_as = types::Timestamp)]
+    pub created_at: jiff::Timestamp,
+    #[diesel(serialize_as = types::Timestamp)]
+    pub expires_at: jiff::Timestamp,
+}
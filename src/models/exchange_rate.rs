@@ -0,0 +1,39 @@
+use bigdecimal::BigDecimal;
+use diesel::prelude::*;
+use diesel::sqlite::Sqlite;
+
+use super::types;
+use crate::currency::Currency;
+use crate::schema::exchange_rates;
+
+/// The rate at which one currency converts into another, as set by an admin.
+///
+/// Rates only apply in one direction, so converting back needs a rate of its
+/// own.
+#[derive(Clone, Debug, AsChangeset, Identifiable, Insertable, Queryable, Selectable)]
+#[diesel(table_name = exchange_rates)]
+#[diesel(primary_key(source_currency, target_currency))]
+#[diesel(check_for_backend(Sqlite))]
+pub struct ExchangeRate {
+    pub source_currency: Currency,
+    pub target_currency: Currency,
+    /// How much one unit of the source currency is worth in the target
+    /// currency, before the spread.
+    #[diesel(
+        serialize_as = types::BigDecimal,
+        deserialize_as = types::BigDecimal,
+    )]
+    pub rate: BigDecimal,
+    /// The fraction of the converted amount kept by the platform, e.g. `0.01`
+    /// for 1%.
+    #[diesel(
+        serialize_as = types::BigDecimal,
+        deserialize_as = types::BigDecimal,
+    )]
+    pub spread: BigDecimal,
+    #[diesel(
+        serialize_as = types::Timestamp,
+        deserialize_as = types::Timestamp,
+    )]
+    pub updated_at: jiff::Timestamp,
+}
@@ -0,0 +1,134 @@
+use bigdecimal::rounding::RoundingMode;
+use bigdecimal::{BigDecimal, Zero as _};
+use diesel::prelude::*;
+use diesel::sqlite::Sqlite;
+use uuid::Uuid;
+
+use super::types;
+use super::user::UserTier;
+use crate::currency::Currency;
+use crate::schema::fee_rules;
+
+/// A rule for the fee charged to the sender of a transfer, as set by an admin.
+///
+/// A rule applies to transfers matching all of its conditions, which are
+/// ignored if `None`. Of the rules which apply, the one with the highest
+/// priority is used.
+#[derive(Clone, Debug, AsChangeset, Identifiable, Insertable, Queryable, Selectable)]
+#[diesel(table_name = fee_rules)]
+#[diesel(check_for_backend(Sqlite))]
+#[diesel(treat_none_as_null = true)]
+pub struct FeeRule {
+    #[diesel(
+        serialize_as = types::Uuid,
+        deserialize_as = types::Uuid,
+    )]
+    pub id: Uuid,
+    pub currency: Option<Currency>,
+    pub tier: Option<UserTier>,
+    /// The smallest amount the rule applies to.
+    #[diesel(
+        serialize_as = types::NullableBigDecimal,
+        deserialize_as = types::NullableBigDecimal,
+    )]
+    pub min_amount: Option<BigDecimal>,
+    /// The amount from which the rule no longer applies.
+    #[diesel(
+        serialize_as = types::NullableBigDecimal,
+        deserialize_as = types::NullableBigDecimal,
+    )]
+    pub max_amount: Option<BigDecimal>,
+    /// A fixed fee, in the currency of the transfer.
+    #[diesel(
+        serialize_as = types::BigDecimal,
+        deserialize_as = types::BigDecimal,
+    )]
+    pub flat: BigDecimal,
+    /// A fee in percent of the amount, on top of the flat fee.
+    #[diesel(
+        serialize_as = types::BigDecimal,
+        deserialize_as = types::BigDecimal,
+    )]
+    pub percentage: BigDecimal,
+    #[diesel(
+        serialize_as = types::NullableBigDecimal,
+        deserialize_as = types::NullableBigDecimal,
+    )]
+    pub min_fee: Option<BigDecimal>,
+    #[diesel(
+        serialize_as = types::NullableBigDecimal,
+        deserialize_as = types::NullableBigDecimal,
+    )]
+    pub max_fee: Option<BigDecimal>,
+    pub priority: i32,
+    #[diesel(
+        serialize_as = types::Timestamp,
+        deserialize_as = types::Timestamp,
+    )]
+    pub created_at: jiff::Timestamp,
+}
+
+impl FeeRule {
+    /// Whether the rule applies to a transfer of `amount` in `currency` by a
+    /// user in `tier`.
+    pub fn applies_to(&self, amount: &BigDecimal, currency: Currency, tier: UserTier) -> bool {
+        self.currency
+            .is_none_or(|rule_currency| rule_currency == currency)
+            && self.tier.is_none_or(|rule_tier| rule_tier == tier)
+            && self
+                .min_amount
+                .as_ref()
+                .is_none_or(|min_amount| amount >= min_amount)
+            && self
+                .max_amount
+                .as_ref()
+                .is_none_or(|max_amount| amount < max_amount)
+    }
+
+    /// Returns the fee for a transfer of `amount` in `currency`, rounded to the
+    /// minor unit of the currency.
+    pub fn fee(&self, amount: &BigDecimal, currency: Currency) -> BigDecimal {
+        let mut fee = &self.flat + amount * &self.percentage / BigDecimal::from(100);
+        if let Some(min_fee) = &self.min_fee
+            && fee < *min_fee
+        {
+            fee = min_fee.clone();
+        }
+        if let Some(max_fee) = &self.max_fee
+            && fee > *max_fee
+        {
+            fee = max_fee.clone();
+        }
+
+        fee.with_scale_round(i64::from(currency.minor_units()), RoundingMode::HalfUp)
+            .normalized()
+    }
+
+    /// Checks that the rule is consistent, returning what is wrong if not.
+    pub fn validate(&self) -> Result<(), String> {
+        for (name, value) in [
+            ("min_amount", self.min_amount.as_ref()),
+            ("max_amount", self.max_amount.as_ref()),
+            ("flat", Some(&self.flat)),
+            ("percentage", Some(&self.percentage)),
+            ("min_fee", self.min_fee.as_ref()),
+            ("max_fee", self.max_fee.as_ref()),
+        ] {
+            if value.is_some_and(|value| *value < BigDecimal::zero()) {
+                return Err(format!("{name} must not be negative"));
+            }
+        }
+        if let (Some(min_amount), Some(max_amount)) = (&self.min_amount, &self.max_amount)
+            && min_amount >= max_amount
+        {
+            return Err("min_amount must be less than max_amount".to_owned());
+        }
+        if let (Some(min_fee), Some(max_fee)) = (&self.min_fee, &self.max_fee)
+            && min_fee > max_fee
+        {
+            return Err("min_fee must not be more than max_fee".to_owned());
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,170 @@
+use std::fmt;
+
+use bigdecimal::BigDecimal;
+use diesel::deserialize::{self, FromSql};
+use diesel::prelude::*;
+use diesel::serialize::{self, Output, ToSql};
+use diesel::sql_types::Text;
+use diesel::sqlite::{Sqlite, SqliteValue};
+use diesel::{AsExpression, FromSqlRow};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use super::types;
+use crate::currency::Currency;
+use crate::schema::{gift_code_redemptions, gift_codes};
+
+/// A code which pays `amount` to each user who redeems it, up to
+/// `max_redemptions` times, out of funds held in escrow since it was created.
+#[derive(Clone, Debug, AsChangeset, Identifiable, Insertable, Queryable, Selectable)]
+#[diesel(table_name = gift_codes)]
+#[diesel(check_for_backend(Sqlite))]
+#[diesel(treat_none_as_null = true)]
+pub struct GiftCode {
+    #[diesel(
+        serialize_as = types::Uuid,
+        deserialize_as = types::Uuid,
+    )]
+    pub id: Uuid,
+    #[diesel(
+        serialize_as = types::Uuid,
+        deserialize_as = types::Uuid,
+    )]
+    pub created_by: Uuid,
+    /// The user the funds came from, and are refunded to. Either the creator,
+    /// or the treasury for gift codes created by admins on its behalf.
+    #[diesel(
+        serialize_as = types::Uuid,
+        deserialize_as = types::Uuid,
+    )]
+    pub funded_by: Uuid,
+    /// The SHA-256 hash of the normalized code. The code itself isn't stored.
+    pub code_hash: Vec<u8>,
+    /// The amount paid per redemption.
+    #[diesel(
+        serialize_as = types::BigDecimal,
+        deserialize_as = types::BigDecimal,
+    )]
+    pub amount: BigDecimal,
+    pub currency: Currency,
+    pub max_redemptions: i32,
+    pub redemption_count: i32,
+    pub memo: Option<String>,
+    pub status: GiftCodeStatus,
+    #[diesel(
+        serialize_as = types::Timestamp,
+        deserialize_as = types::Timestamp,
+    )]
+    pub created_at: jiff::Timestamp,
+    #[diesel(
+        serialize_as = types::Timestamp,
+        deserialize_as = types::Timestamp,
+    )]
+    pub expires_at: jiff::Timestamp,
+    /// The transaction which moved the funds into escrow.
+    #[diesel(
+        serialize_as = types::Uuid,
+        deserialize_as = types::Uuid,
+    )]
+    pub funding_transaction_id: Uuid,
+    /// The transaction which refunded what was left once the gift code expired
+    /// or was cancelled, if anything was.
+    #[diesel(
+        serialize_as = types::NullableUuid,
+        deserialize_as = types::NullableUuid,
+    )]
+    pub refund_transaction_id: Option<Uuid>,
+}
+
+/// A user who redeemed a gift code. Each user may redeem a gift code once.
+#[derive(Debug, Insertable, Queryable, Selectable)]
+#[diesel(table_name = gift_code_redemptions)]
+#[diesel(check_for_backend(Sqlite))]
+pub struct GiftCodeRedemption {
+    #[diesel(
+        serialize_as = types::Uuid,
+        deserialize_as = types::Uuid,
+    )]
+    pub gift_code_id: Uuid,
+    #[diesel(
+        serialize_as = types::Uuid,
+        deserialize_as = types::Uuid,
+    )]
+    pub user_id: Uuid,
+    #[diesel(
+        serialize_as = types::Uuid,
+        deserialize_as = types::Uuid,
+    )]
+    pub transaction_id: Uuid,
+    #[diesel(
+        serialize_as = types::Timestamp,
+        deserialize_as = types::Timestamp,
+    )]
+    pub redeemed_at: jiff::Timestamp,
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug, AsExpression, FromSqlRow, Deserialize, Serialize)]
+#[diesel(sql_type = Text)]
+#[serde(rename_all = "lowercase")]
+pub enum GiftCodeStatus {
+    /// May still be redeemed.
+    Active,
+    /// Redeemed as many times as it may be.
+    Redeemed,
+    /// Expired before it was fully redeemed. What was left was refunded.
+    Expired,
+    /// Cancelled by its creator. What was left was refunded.
+    Cancelled,
+}
+
+impl GiftCode {
+    /// Returns the status of this gift code at `now`, taking expiry into
+    /// account.
+    ///
+    /// Expired gift codes are only marked as such in the database once what is
+    /// left of them is refunded, which happens in the background.
+    pub fn status_at(&self, now: jiff::Timestamp) -> GiftCodeStatus {
+        if self.status == GiftCodeStatus::Active && self.expires_at <= now {
+            GiftCodeStatus::Expired
+        } else {
+            self.status
+        }
+    }
+}
+
+impl GiftCodeStatus {
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Active => "active",
+            Self::Redeemed => "redeemed",
+            Self::Expired => "expired",
+            Self::Cancelled => "cancelled",
+        }
+    }
+}
+
+impl fmt::Display for GiftCodeStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromSql<Text, Sqlite> for GiftCodeStatus {
+    fn from_sql(bytes: SqliteValue<'_, '_, '_>) -> deserialize::Result<Self> {
+        let s = <String as FromSql<Text, Sqlite>>::from_sql(bytes)?;
+
+        match &*s {
+            "active" => Ok(Self::Active),
+            "redeemed" => Ok(Self::Redeemed),
+            "expired" => Ok(Self::Expired),
+            "cancelled" => Ok(Self::Cancelled),
+            _ => Err(format!("unknown gift code status: {s}").into()),
+        }
+    }
+}
+
+impl ToSql<Text, Sqlite> for GiftCodeStatus {
+    fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, Sqlite>) -> serialize::Result {
+        <str as ToSql<Text, Sqlite>>::to_sql(self.as_str(), out)
+    }
+}
@@ -0,0 +1,128 @@
+use bigdecimal::BigDecimal;
+use diesel::prelude::*;
+use diesel::sqlite::Sqlite;
+use uuid::Uuid;
+
+use super::types;
+use crate::currency::Currency;
+use crate::schema::{
+    group_expense_shares, group_expenses, group_members, group_settlements, groups,
+};
+
+/// Users who share expenses, in one currency.
+#[derive(Debug, Identifiable, Insertable, Queryable, Selectable)]
+#[diesel(table_name = groups)]
+#[diesel(check_for_backend(Sqlite))]
+pub struct Group {
+    #[diesel(
+        serialize_as = types::Uuid,
+        deserialize_as = types::Uuid,
+    )]
+    pub id: Uuid,
+    pub name: String,
+    pub currency: Currency,
+    #[diesel(
+        serialize_as = types::Uuid,
+        deserialize_as = types::Uuid,
+    )]
+    pub created_by: Uuid,
+    #[diesel(
+        serialize_as = types::Timestamp,
+        deserialize_as = types::Timestamp,
+    )]
+    pub created_at: jiff::Timestamp,
+}
+
+#[derive(Debug, Insertable, Queryable, Selectable)]
+#[diesel(table_name = group_members)]
+#[diesel(check_for_backend(Sqlite))]
+pub struct GroupMember {
+    #[diesel(
+        serialize_as = types::Uuid,
+        deserialize_as = types::Uuid,
+    )]
+    pub group_id: Uuid,
+    #[diesel(
+        serialize_as = types::Uuid,
+        deserialize_as = types::Uuid,
+    )]
+    pub user_id: Uuid,
+    #[diesel(
+        serialize_as = types::Timestamp,
+        deserialize_as = types::Timestamp,
+    )]
+    pub joined_at: jiff::Timestamp,
+}
+
+/// An expense which one member paid for, and which is split between members.
+#[derive(Debug, Identifiable, Insertable, Queryable, Selectable)]
+#[diesel(table_name = group_expenses)]
+#[diesel(check_for_backend(Sqlite))]
+pub struct GroupExpense {
+    #[diesel(
+        serialize_as = types::Uuid,
+        deserialize_as = types::Uuid,
+    )]
+    pub id: Uuid,
+    #[diesel(
+        serialize_as = types::Uuid,
+        deserialize_as = types::Uuid,
+    )]
+    pub group_id: Uuid,
+    #[diesel(
+        serialize_as = types::Uuid,
+        deserialize_as = types::Uuid,
+    )]
+    pub payer: Uuid,
+    #[diesel(
+        serialize_as = types::BigDecimal,
+        deserialize_as = types::BigDecimal,
+    )]
+    pub amount: BigDecimal,
+    pub description: String,
+    #[diesel(
+        serialize_as = types::Timestamp,
+        deserialize_as = types::Timestamp,
+    )]
+    pub created_at: jiff::Timestamp,
+}
+
+/// What a member owes for an expense. The shares of an expense add up to its
+/// amount.
+#[derive(Debug, Insertable, Queryable, Selectable)]
+#[diesel(table_name = group_expense_shares)]
+#[diesel(check_for_backend(Sqlite))]
+pub struct GroupExpenseShare {
+    #[diesel(
+        serialize_as = types::Uuid,
+        deserialize_as = types::Uuid,
+    )]
+    pub expense_id: Uuid,
+    #[diesel(
+        serialize_as = types::Uuid,
+        deserialize_as = types::Uuid,
+    )]
+    pub user_id: Uuid,
+    #[diesel(
+        serialize_as = types::BigDecimal,
+        deserialize_as = types::BigDecimal,
+    )]
+    pub amount: BigDecimal,
+}
+
+/// A transfer between members which settled what one owed the other.
+#[derive(Debug, Insertable, Queryable, Selectable)]
+#[diesel(table_name = group_settlements)]
+#[diesel(check_for_backend(Sqlite))]
+pub struct GroupSettlement {
+    #[diesel(
+        serialize_as = types::Uuid,
+        deserialize_as = types::Uuid,
+    )]
+    pub group_id: Uuid,
+    #[diesel(
+        serialize_as = types::Uuid,
+        deserialize_as = types::Uuid,
+    )]
+    pub transaction_id: Uuid,
+}
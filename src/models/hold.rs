@@ -0,0 +1,146 @@
+use std::fmt;
+
+use bigdecimal::BigDecimal;
+use diesel::deserialize::{self, FromSql};
+use diesel::prelude::*;
+use diesel::serialize::{self, Output, ToSql};
+use diesel::sql_types::Text;
+use diesel::sqlite::{Sqlite, SqliteValue};
+use diesel::{AsExpression, FromSqlRow};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use super::types;
+use crate::currency::Currency;
+use crate::schema::holds;
+
+/// Funds of the sender which are reserved for the recipient, until the
+/// recipient captures or voids them, or they expire.
+#[derive(Debug, AsChangeset, Identifiable, Queryable, Selectable)]
+#[diesel(table_name = holds)]
+#[diesel(check_for_backend(Sqlite))]
+#[diesel(treat_none_as_null = true)]
+pub struct Hold {
+    #[diesel(
+        serialize_as = types::Uuid,
+        deserialize_as = types::Uuid,
+    )]
+    pub id: Uuid,
+    #[diesel(
+        serialize_as = types::Uuid,
+        deserialize_as = types::Uuid,
+    )]
+    pub sender: Uuid,
+    #[diesel(
+        serialize_as = types::Uuid,
+        deserialize_as = types::Uuid,
+    )]
+    pub recipient: Uuid,
+    #[diesel(
+        serialize_as = types::BigDecimal,
+        deserialize_as = types::BigDecimal,
+    )]
+    pub amount: BigDecimal,
+    pub currency: Currency,
+    pub memo: Option<String>,
+    pub reference: Option<String>,
+    pub status: HoldStatus,
+    #[diesel(
+        serialize_as = types::Timestamp,
+        deserialize_as = types::Timestamp,
+    )]
+    pub created_at: jiff::Timestamp,
+    #[diesel(
+        serialize_as = types::Timestamp,
+        deserialize_as = types::Timestamp,
+    )]
+    pub expires_at: jiff::Timestamp,
+    /// The transaction which captured the hold.
+    #[diesel(
+        serialize_as = types::NullableUuid,
+        deserialize_as = types::NullableUuid,
+    )]
+    pub transaction_id: Option<Uuid>,
+}
+
+#[derive(Clone, Debug, Insertable)]
+#[diesel(table_name = holds)]
+pub struct NewHold {
+    #[diesel(serialize_as = types::Uuid)]
+    pub id: Uuid,
+    #[diesel(serialize_as = types::Uuid)]
+    pub sender: Uuid,
+    #[diesel(serialize_as = types::Uuid)]
+    pub recipient: Uuid,
+    #[diesel(serialize_as = types::BigDecimal)]
+    pub amount: BigDecimal,
+    pub currency: Currency,
+    pub memo: Option<String>,
+    pub reference: Option<String>,
+    pub status: HoldStatus,
+    #[diesel(serialize_as = types::Timestamp)]
+    pub created_at: jiff::Timestamp,
+    #[diesel(serialize_as = types::Timestamp)]
+    pub expires_at: jiff::Timestamp,
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug, AsExpression, FromSqlRow, Deserialize, Serialize)]
+#[diesel(sql_type = Text)]
+#[serde(rename_all = "lowercase")]
+pub enum HoldStatus {
+    Active,
+    Captured,
+    Voided,
+    Expired,
+}
+
+impl Hold {
+    /// Returns the status of this hold at `now`, taking expiry into account.
+    ///
+    /// Expired holds are only marked as such in the database when they are next
+    /// updated.
+    pub fn status_at(&self, now: jiff::Timestamp) -> HoldStatus {
+        if self.status == HoldStatus::Active && self.expires_at <= now {
+            HoldStatus::Expired
+        } else {
+            self.status
+        }
+    }
+}
+
+impl HoldStatus {
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Active => "active",
+            Self::Captured => "captured",
+            Self::Voided => "voided",
+            Self::Expired => "expired",
+        }
+    }
+}
+
+impl fmt::Display for HoldStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromSql<Text, Sqlite> for HoldStatus {
+    fn from_sql(bytes: SqliteValue<'_, '_, '_>) -> deserialize::Result<Self> {
+        let s = <String as FromSql<Text, Sqlite>>::from_sql(bytes)?;
+
+        match &*s {
+            "active" => Ok(Self::Active),
+            "captured" => Ok(Self::Captured),
+            "voided" => Ok(Self::Voided),
+            "expired" => Ok(Self::Expired),
+            _ => Err(format!("unknown hold status: {s}").into()),
+        }
+    }
+}
+
+impl ToSql<Text, Sqlite> for HoldStatus {
+    fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, Sqlite>) -> serialize::Result {
+        <str as ToSql<Text, Sqlite>>::to_sql(self.as_str(), out)
+    }
+}
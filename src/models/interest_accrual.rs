@@ -0,0 +1,65 @@
+use bigdecimal::BigDecimal;
+use diesel::prelude::*;
+use diesel::sqlite::Sqlite;
+use uuid::Uuid;
+
+use super::interest_rate::InterestProduct;
+use super::types;
+use crate::currency::Currency;
+use crate::schema::interest_accruals;
+
+/// The interest earned by one balance on one day, which is paid out together
+/// with the other days of the month.
+#[derive(Clone, Debug, Insertable, Queryable, Selectable)]
+#[diesel(table_name = interest_accruals)]
+#[diesel(check_for_backend(Sqlite))]
+pub struct InterestAccrual {
+    pub product: InterestProduct,
+    /// The user for accounts, or the pocket for pockets.
+    #[diesel(
+        serialize_as = types::Uuid,
+        deserialize_as = types::Uuid,
+    )]
+    pub holder_id: Uuid,
+    pub currency: Currency,
+    /// The day the interest was earned on, in UTC.
+    #[diesel(
+        serialize_as = jiff_diesel::Date,
+        deserialize_as = jiff_diesel::Date,
+    )]
+    pub accrued_on: jiff::civil::Date,
+    /// The user who is paid the interest.
+    #[diesel(
+        serialize_as = types::Uuid,
+        deserialize_as = types::Uuid,
+    )]
+    pub user_id: Uuid,
+    /// The balance the interest was earned on.
+    #[diesel(
+        serialize_as = types::BigDecimal,
+        deserialize_as = types::BigDecimal,
+    )]
+    pub balance: BigDecimal,
+    #[diesel(
+        serialize_as = types::BigDecimal,
+        deserialize_as = types::BigDecimal,
+    )]
+    pub annual_rate: BigDecimal,
+    /// The interest earned, which is more precise than the currency.
+    #[diesel(
+        serialize_as = types::BigDecimal,
+        deserialize_as = types::BigDecimal,
+    )]
+    pub amount: BigDecimal,
+    #[diesel(
+        serialize_as = types::Timestamp,
+        deserialize_as = types::Timestamp,
+    )]
+    pub created_at: jiff::Timestamp,
+    /// The transaction which paid the interest, once posted.
+    #[diesel(
+        serialize_as = types::NullableUuid,
+        deserialize_as = types::NullableUuid,
+    )]
+    pub transaction_id: Option<Uuid>,
+}
@@ -0,0 +1,36 @@
+use bigdecimal::BigDecimal;
+use diesel::prelude::*;
+use diesel::sqlite::Sqlite;
+use uuid::Uuid;
+
+use super::interest_rate::InterestProduct;
+use super::types;
+use crate::currency::Currency;
+use crate::schema::interest_carryovers;
+
+/// What was left of the interest of one balance after it was last paid out, as
+/// only whole minor units are paid. It is added to the next payout.
+#[derive(Clone, Debug, AsChangeset, Insertable, Queryable, Selectable)]
+#[diesel(table_name = interest_carryovers)]
+#[diesel(check_for_backend(Sqlite))]
+pub struct InterestCarryover {
+    pub product: InterestProduct,
+    /// The user for accounts, or the pocket for pockets.
+    #[diesel(
+        serialize_as = types::Uuid,
+        deserialize_as = types::Uuid,
+    )]
+    pub holder_id: Uuid,
+    pub currency: Currency,
+    /// Less than one minor unit of the currency.
+    #[diesel(
+        serialize_as = types::BigDecimal,
+        deserialize_as = types::BigDecimal,
+    )]
+    pub amount: BigDecimal,
+    #[diesel(
+        serialize_as = types::Timestamp,
+        deserialize_as = types::Timestamp,
+    )]
+    pub updated_at: jiff::Timestamp,
+}
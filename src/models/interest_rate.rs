@@ -0,0 +1,93 @@
+use std::fmt;
+
+use bigdecimal::BigDecimal;
+use diesel::deserialize::{self, FromSql};
+use diesel::prelude::*;
+use diesel::serialize::{self, Output, ToSql};
+use diesel::sql_types::Text;
+use diesel::sqlite::{Sqlite, SqliteValue};
+use diesel::{AsExpression, FromSqlRow};
+use serde::{Deserialize, Serialize};
+
+use super::types;
+use crate::currency::Currency;
+use crate::schema::interest_rates;
+
+/// The interest paid on balances of one product in one currency, as set by an
+/// admin.
+#[derive(Clone, Debug, AsChangeset, Identifiable, Insertable, Queryable, Selectable)]
+#[diesel(table_name = interest_rates)]
+#[diesel(primary_key(product, currency))]
+#[diesel(check_for_backend(Sqlite))]
+pub struct InterestRate {
+    pub product: InterestProduct,
+    pub currency: Currency,
+    /// The interest per year in percent of the balance, e.g. `3.5` for 3.5%.
+    #[diesel(
+        serialize_as = types::BigDecimal,
+        deserialize_as = types::BigDecimal,
+    )]
+    pub annual_rate: BigDecimal,
+    #[diesel(
+        serialize_as = types::Timestamp,
+        deserialize_as = types::Timestamp,
+    )]
+    pub updated_at: jiff::Timestamp,
+}
+
+/// The kinds of balances interest can be paid on.
+#[derive(
+    Copy,
+    Clone,
+    Eq,
+    PartialEq,
+    Hash,
+    Ord,
+    PartialOrd,
+    Debug,
+    AsExpression,
+    FromSqlRow,
+    Deserialize,
+    Serialize,
+)]
+#[diesel(sql_type = Text)]
+#[serde(rename_all = "lowercase")]
+pub enum InterestProduct {
+    /// The account of a user in a currency.
+    Account,
+    /// A pocket of a user.
+    Pocket,
+}
+
+impl InterestProduct {
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Account => "account",
+            Self::Pocket => "pocket",
+        }
+    }
+}
+
+impl fmt::Display for InterestProduct {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromSql<Text, Sqlite> for InterestProduct {
+    fn from_sql(bytes: SqliteValue<'_, '_, '_>) -> deserialize::Result<Self> {
+        let s = <String as FromSql<Text, Sqlite>>::from_sql(bytes)?;
+
+        match &*s {
+            "account" => Ok(Self::Account),
+            "pocket" => Ok(Self::Pocket),
+            _ => Err(format!("unknown interest product: {s}").into()),
+        }
+    }
+}
+
+impl ToSql<Text, Sqlite> for InterestProduct {
+    fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, Sqlite>) -> serialize::Result {
+        <str as ToSql<Text, Sqlite>>::to_sql(self.as_str(), out)
+    }
+}
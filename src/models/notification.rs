@@ -0,0 +1,153 @@
+use std::fmt;
+
+use diesel::deserialize::{self, FromSql};
+use diesel::prelude::*;
+use diesel::serialize::{self, Output, ToSql};
+use diesel::sql_types::Text;
+use diesel::sqlite::{Sqlite, SqliteValue};
+use diesel::{AsExpression, FromSqlRow};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use super::types;
+use crate::schema::{known_devices, notification_preferences, notifications};
+
+/// A message in the inbox of a user, about something which happened to them.
+#[derive(Debug, Identifiable, Insertable, Queryable, Selectable)]
+#[diesel(table_name = notifications)]
+#[diesel(check_for_backend(Sqlite))]
+pub struct Notification {
+    #[diesel(
+        serialize_as = types::Uuid,
+        deserialize_as = types::Uuid,
+    )]
+    pub id: Uuid,
+    #[diesel(
+        serialize_as = types::Uuid,
+        deserialize_as = types::Uuid,
+    )]
+    pub user_id: Uuid,
+    pub kind: NotificationKind,
+    /// The JSON details of the notification, which depend on its kind.
+    pub data: String,
+    #[diesel(
+        serialize_as = types::Timestamp,
+        deserialize_as = types::Timestamp,
+    )]
+    pub created_at: jiff::Timestamp,
+    #[diesel(
+        serialize_as = types::NullableTimestamp,
+        deserialize_as = types::NullableTimestamp,
+    )]
+    pub read_at: Option<jiff::Timestamp>,
+}
+
+/// Whether a user is sent notifications of a kind. Users are sent those of
+/// every kind without a preference.
+#[derive(Debug, AsChangeset, Insertable, Queryable, Selectable)]
+#[diesel(table_name = notification_preferences)]
+#[diesel(check_for_backend(Sqlite))]
+pub struct NotificationPreference {
+    #[diesel(
+        serialize_as = types::Uuid,
+        deserialize_as = types::Uuid,
+    )]
+    pub user_id: Uuid,
+    pub kind: NotificationKind,
+    pub enabled: bool,
+}
+
+/// A device a user has logged in from, identified by a hash of its user agent.
+#[derive(Debug, AsChangeset, Insertable, Queryable, Selectable)]
+#[diesel(table_name = known_devices)]
+#[diesel(check_for_backend(Sqlite))]
+pub struct KnownDevice {
+    #[diesel(
+        serialize_as = types::Uuid,
+        deserialize_as = types::Uuid,
+    )]
+    pub user_id: Uuid,
+    pub fingerprint: Vec<u8>,
+    #[diesel(
+        serialize_as = types::Timestamp,
+        deserialize_as = types::Timestamp,
+    )]
+    pub first_seen_at: jiff::Timestamp,
+    #[diesel(
+        serialize_as = types::Timestamp,
+        deserialize_as = types::Timestamp,
+    )]
+    pub last_seen_at: jiff::Timestamp,
+}
+
+#[derive(
+    Copy,
+    Clone,
+    Eq,
+    PartialEq,
+    Hash,
+    Ord,
+    PartialOrd,
+    Debug,
+    AsExpression,
+    FromSqlRow,
+    Deserialize,
+    Serialize,
+)]
+#[diesel(sql_type = Text)]
+#[serde(rename_all = "snake_case")]
+pub enum NotificationKind {
+    /// Money was credited to the user, e.g. by a transfer or refund.
+    MoneyReceived,
+    /// A payment request of the user was paid.
+    PaymentRequestPaid,
+    /// The user logged in from a device they hadn't logged in from before.
+    NewDeviceLogin,
+    /// A transfer of the user was rejected for exceeding one of their transfer
+    /// limits.
+    LimitReached,
+}
+
+impl NotificationKind {
+    pub const ALL: [Self; 4] = [
+        Self::MoneyReceived,
+        Self::PaymentRequestPaid,
+        Self::NewDeviceLogin,
+        Self::LimitReached,
+    ];
+
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::MoneyReceived => "money_received",
+            Self::PaymentRequestPaid => "payment_request_paid",
+            Self::NewDeviceLogin => "new_device_login",
+            Self::LimitReached => "limit_reached",
+        }
+    }
+}
+
+impl fmt::Display for NotificationKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromSql<Text, Sqlite> for NotificationKind {
+    fn from_sql(bytes: SqliteValue<'_, '_, '_>) -> deserialize::Result<Self> {
+        let s = <String as FromSql<Text, Sqlite>>::from_sql(bytes)?;
+
+        match &*s {
+            "money_received" => Ok(Self::MoneyReceived),
+            "payment_request_paid" => Ok(Self::PaymentRequestPaid),
+            "new_device_login" => Ok(Self::NewDeviceLogin),
+            "limit_reached" => Ok(Self::LimitReached),
+            _ => Err(format!("unknown notification kind: {s}").into()),
+        }
+    }
+}
+
+impl ToSql<Text, Sqlite> for NotificationKind {
+    fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, Sqlite>) -> serialize::Result {
+        <str as ToSql<Text, Sqlite>>::to_sql(self.as_str(), out)
+    }
+}
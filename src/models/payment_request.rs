@@ -0,0 +1,148 @@
+use std::fmt;
+
+use bigdecimal::BigDecimal;
+use diesel::deserialize::{self, FromSql};
+use diesel::prelude::*;
+use diesel::serialize::{self, Output, ToSql};
+use diesel::sql_types::Text;
+use diesel::sqlite::{Sqlite, SqliteValue};
+use diesel::{AsExpression, FromSqlRow};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use super::types;
+use crate::currency::Currency;
+use crate::schema::payment_requests;
+
+#[derive(Debug, AsChangeset, Identifiable, Queryable, Selectable)]
+#[diesel(table_name = payment_requests)]
+#[diesel(check_for_backend(Sqlite))]
+#[diesel(treat_none_as_null = true)]
+pub struct PaymentRequest {
+    #[diesel(
+        serialize_as = types::Uuid,
+        deserialize_as = types::Uuid,
+    )]
+    pub id: Uuid,
+    /// The user who is asking for money, and who will receive it.
+    #[diesel(
+        serialize_as = types::Uuid,
+        deserialize_as = types::Uuid,
+    )]
+    pub requester: Uuid,
+    /// The user who is asked to pay, or `None` if anyone holding the link may
+    /// pay.
+    #[diesel(
+        serialize_as = types::NullableUuid,
+        deserialize_as = types::NullableUuid,
+    )]
+    pub payer: Option<Uuid>,
+    #[diesel(
+        serialize_as = types::BigDecimal,
+        deserialize_as = types::BigDecimal,
+    )]
+    pub amount: BigDecimal,
+    pub currency: Currency,
+    pub memo: Option<String>,
+    pub status: PaymentRequestStatus,
+    #[diesel(
+        serialize_as = types::Timestamp,
+        deserialize_as = types::Timestamp,
+    )]
+    pub created_at: jiff::Timestamp,
+    #[diesel(
+        serialize_as = types::Timestamp,
+        deserialize_as = types::Timestamp,
+    )]
+    pub expires_at: jiff::Timestamp,
+    #[diesel(
+        serialize_as = types::NullableUuid,
+        deserialize_as = types::NullableUuid,
+    )]
+    pub transaction_id: Option<Uuid>,
+}
+
+#[derive(Debug, Insertable)]
+#[diesel(table_name = payment_requests)]
+pub struct NewPaymentRequest {
+    #[diesel(serialize_as = types::Uuid)]
+    pub id: Uuid,
+    #[diesel(serialize_as = types::Uuid)]
+    pub requester: Uuid,
+    #[diesel(serialize_as = types::NullableUuid)]
+    pub payer: Option<Uuid>,
+    #[diesel(serialize_as = types::BigDecimal)]
+    pub amount: BigDecimal,
+    pub currency: Currency,
+    pub memo: Option<String>,
+    pub status: PaymentRequestStatus,
+    #[diesel(serialize_as = types::Timestamp)]
+    pub created_at: jiff::Timestamp,
+    #[diesel(serialize_as = types::Timestamp)]
+    pub expires_at: jiff::Timestamp,
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug, AsExpression, FromSqlRow, Deserialize, Serialize)]
+#[diesel(sql_type = Text)]
+#[serde(rename_all = "lowercase")]
+pub enum PaymentRequestStatus {
+    Pending,
+    Paid,
+    Declined,
+    Cancelled,
+    Expired,
+}
+
+impl PaymentRequest {
+    /// Returns the status of this payment request at `now`, taking expiry into
+    /// account.
+    ///
+    /// Expired payment requests are only marked as such in the database when
+    /// they are next updated.
+    pub fn status_at(&self, now: jiff::Timestamp) -> PaymentRequestStatus {
+        if self.status == PaymentRequestStatus::Pending && self.expires_at <= now {
+            PaymentRequestStatus::Expired
+        } else {
+            self.status
+        }
+    }
+}
+
+impl PaymentRequestStatus {
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Pending => "pending",
+            Self::Paid => "paid",
+            Self::Declined => "declined",
+            Self::Cancelled => "cancelled",
+            Self::Expired => "expired",
+        }
+    }
+}
+
+impl fmt::Display for PaymentRequestStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromSql<Text, Sqlite> for PaymentRequestStatus {
+    fn from_sql(bytes: SqliteValue<'_, '_, '_>) -> deserialize::Result<Self> {
+        let s = <String as FromSql<Text, Sqlite>>::from_sql(bytes)?;
+
+        match &*s {
+            "pending" => Ok(Self::Pending),
+            "paid" => Ok(Self::Paid),
+            "declined" => Ok(Self::Declined),
+            "cancelled" => Ok(Self::Cancelled),
+            "expired" => Ok(Self::Expired),
+            _ => Err(format!("unknown payment request status: {s}").into()),
+        }
+    }
+}
+
+impl ToSql<Text, Sqlite> for PaymentRequestStatus {
+    fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, Sqlite>) -> serialize::Result {
+        <str as ToSql<Text, Sqlite>>::to_sql(self.as_str(), out)
+    }
+}
@@ -0,0 +1,58 @@
+use bigdecimal::BigDecimal;
+use diesel::prelude::*;
+use diesel::sqlite::Sqlite;
+use uuid::Uuid;
+
+use super::types;
+use crate::currency::Currency;
+use crate::schema::pockets;
+
+/// Money a user set aside from their account in the same currency, which can
+/// only be moved back to that account.
+#[derive(Debug, AsChangeset, Identifiable, Insertable, Queryable, Selectable)]
+#[diesel(table_name = pockets)]
+#[diesel(check_for_backend(Sqlite))]
+#[diesel(treat_none_as_null = true)]
+pub struct Pocket {
+    #[diesel(
+        serialize_as = types::Uuid,
+        deserialize_as = types::Uuid,
+    )]
+    pub id: Uuid,
+    #[diesel(
+        serialize_as = types::Uuid,
+        deserialize_as = types::Uuid,
+    )]
+    pub user_id: Uuid,
+    pub name: String,
+    pub currency: Currency,
+    #[diesel(
+        serialize_as = types::BigDecimal,
+        deserialize_as = types::BigDecimal,
+    )]
+    pub balance: BigDecimal,
+    /// How much the user is saving up to.
+    #[diesel(
+        serialize_as = types::NullableBigDecimal,
+        deserialize_as = types::NullableBigDecimal,
+    )]
+    pub target_amount: Option<BigDecimal>,
+    /// When the user wants to have saved up the target amount by.
+    #[diesel(
+        serialize_as = jiff_diesel::NullableDate,
+        deserialize_as = jiff_diesel::NullableDate,
+    )]
+    pub target_date: Option<jiff::civil::Date>,
+    #[diesel(
+        serialize_as = types::Timestamp,
+        deserialize_as = types::Timestamp,
+    )]
+    pub created_at: jiff::Timestamp,
+    /// When the user deleted the pocket. It is kept, as transactions still
+    /// refer to it.
+    #[diesel(
+        serialize_as = types::NullableTimestamp,
+        deserialize_as = types::NullableTimestamp,
+    )]
+    pub archived_at: Option<jiff::Timestamp>,
+}
@@ -0,0 +1,419 @@
+use std::fmt;
+use std::str::FromStr;
+
+use bigdecimal::BigDecimal;
+use diesel::deserialize::{self, FromSql};
+use diesel::prelude::*;
+use diesel::serialize::{self, Output, ToSql};
+use diesel::sql_types::Text;
+use diesel::sqlite::{Sqlite, SqliteValue};
+use diesel::{AsExpression, FromSqlRow};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use super::types;
+use crate::currency::Currency;
+use crate::schema::{scheduled_transfer_attempts, scheduled_transfers};
+
+#[derive(Debug, AsChangeset, Identifiable, Queryable, Selectable)]
+#[diesel(table_name = scheduled_transfers)]
+#[diesel(check_for_backend(Sqlite))]
+#[diesel(treat_none_as_null = true)]
+pub struct ScheduledTransfer {
+    #[diesel(
+        serialize_as = types::Uuid,
+        deserialize_as = types::Uuid,
+    )]
+    pub id: Uuid,
+    #[diesel(
+        serialize_as = types::Uuid,
+        deserialize_as = types::Uuid,
+    )]
+    pub sender: Uuid,
+    #[diesel(
+        serialize_as = types::Uuid,
+        deserialize_as = types::Uuid,
+    )]
+    pub recipient: Uuid,
+    #[diesel(
+        serialize_as = types::BigDecimal,
+        deserialize_as = types::BigDecimal,
+    )]
+    pub amount: BigDecimal,
+    pub currency: Currency,
+    pub memo: Option<String>,
+    pub reference: Option<String>,
+    /// How often the transfer repeats, or `None` if it runs once.
+    pub recurrence: Option<Recurrence>,
+    /// When the first occurrence is due.
+    #[diesel(
+        serialize_as = types::Timestamp,
+        deserialize_as = types::Timestamp,
+    )]
+    pub starts_at: jiff::Timestamp,
+    /// The index of the next occurrence to run, counting from zero.
+    pub occurrence: i32,
+    /// When the next occurrence, or the next retry of it, is due.
+    #[diesel(
+        serialize_as = types::Timestamp,
+        deserialize_as = types::Timestamp,
+    )]
+    pub next_run_at: jiff::Timestamp,
+    /// How many times the next occurrence has been retried.
+    pub retries: i32,
+    pub status: ScheduledTransferStatus,
+    #[diesel(
+        serialize_as = types::Timestamp,
+        deserialize_as = types::Timestamp,
+    )]
+    pub created_at: jiff::Timestamp,
+}
+
+#[derive(Debug, Insertable)]
+#[diesel(table_name = scheduled_transfers)]
+pub struct NewScheduledTransfer {
+    #[diesel(serialize_as = types::Uuid)]
+    pub id: Uuid,
+    #[diesel(serialize_as = types::Uuid)]
+    pub sender: Uuid,
+    #[diesel(serialize_as = types::Uuid)]
+    pub recipient: Uuid,
+    #[diesel(serialize_as = types::BigDecimal)]
+    pub amount: BigDecimal,
+    pub currency: Currency,
+    pub memo: Option<String>,
+    pub reference: Option<String>,
+    pub recurrence: Option<Recurrence>,
+    #[diesel(serialize_as = types::Timestamp)]
+    pub starts_at: jiff::Timestamp,
+    pub occurrence: i32,
+    #[diesel(serialize_as = types::Timestamp)]
+    pub next_run_at: jiff::Timestamp,
+    pub retries: i32,
+    pub status: ScheduledTransferStatus,
+    #[diesel(serialize_as = types::Timestamp)]
+    pub created_at: jiff::Timestamp,
+}
+
+#[derive(Debug, Queryable, Selectable)]
+#[diesel(table_name = scheduled_transfer_attempts)]
+#[diesel(check_for_backend(Sqlite))]
+pub struct ScheduledTransferAttempt {
+    #[diesel(deserialize_as = types::Uuid)]
+    pub id: Uuid,
+    #[diesel(deserialize_as = types::Uuid)]
+    pub scheduled_transfer_id: Uuid,
+    /// When the occurrence which was attempted was due.
+    #[diesel(deserialize_as = types::Timestamp)]
+    pub scheduled_for: jiff::Timestamp,
+    #[diesel(deserialize_as = types::Timestamp)]
+    pub attempted_at: jiff::Timestamp,
+    pub outcome: ScheduledTransferAttemptOutcome,
+    /// Why the attempt failed, as the title of the error response
+    /// `POST /transactions` would have returned.
+    pub error: Option<String>,
+    #[diesel(deserialize_as = types::NullableUuid)]
+    pub transaction_id: Option<Uuid>,
+}
+
+#[derive(Debug, Insertable)]
+#[diesel(table_name = scheduled_transfer_attempts)]
+pub struct NewScheduledTransferAttempt {
+    #[diesel(serialize_as = types::Uuid)]
+    pub id: Uuid,
+    #[diesel(serialize_as = types::Uuid)]
+    pub scheduled_transfer_id: Uuid,
+    #[diesel(serialize_as = types::Timestamp)]
+    pub scheduled_for: jiff::Timestamp,
+    #[diesel(serialize_as = types::Timestamp)]
+    pub attempted_at: jiff::Timestamp,
+    pub outcome: ScheduledTransferAttemptOutcome,
+    pub error: Option<String>,
+    #[diesel(serialize_as = types::NullableUuid)]
+    pub transaction_id: Option<Uuid>,
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug, AsExpression, FromSqlRow, Deserialize, Serialize)]
+#[diesel(sql_type = Text)]
+#[serde(rename_all = "lowercase")]
+pub enum ScheduledTransferStatus {
+    Active,
+    Paused,
+    Completed,
+    Cancelled,
+    Failed,
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug, AsExpression, FromSqlRow, Deserialize, Serialize)]
+#[diesel(sql_type = Text)]
+#[serde(rename_all = "lowercase")]
+pub enum ScheduledTransferAttemptOutcome {
+    Succeeded,
+    Failed,
+}
+
+/// A subset of iCalendar recurrence rules, e.g. `FREQ=MONTHLY;INTERVAL=1;COUNT=12`.
+///
+/// See <https://datatracker.ietf.org/doc/html/rfc5545#section-3.3.10>
+#[derive(Clone, Eq, PartialEq, Debug, AsExpression, FromSqlRow, Deserialize, Serialize)]
+#[diesel(sql_type = Text)]
+#[serde(try_from = "String", into = "String")]
+pub struct Recurrence {
+    pub frequency: Frequency,
+    /// How many `frequency` units are between occurrences.
+    pub interval: u32,
+    /// How many occurrences there are in total, or `None` to repeat until
+    /// cancelled.
+    pub count: Option<u32>,
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum Frequency {
+    Daily,
+    Weekly,
+    Monthly,
+    Yearly,
+}
+
+impl ScheduledTransfer {
+    /// Returns when the next occurrence is due, not counting retries.
+    pub fn scheduled_for(&self) -> jiff::Timestamp {
+        self.occurrence_at(self.occurrence)
+            .unwrap_or(self.next_run_at)
+    }
+
+    /// Moves on to the first occurrence due after `now`, skipping those that
+    /// were missed, or completes the scheduled transfer if there are no more.
+    pub fn advance(&mut self, now: jiff::Timestamp) {
+        self.retries = 0;
+
+        let mut occurrence = self.occurrence;
+        loop {
+            occurrence = occurrence.saturating_add(1);
+            match self.occurrence_at(occurrence) {
+                Some(next_run_at) if next_run_at <= now => {},
+                Some(next_run_at) => {
+                    self.occurrence = occurrence;
+                    self.next_run_at = next_run_at;
+                    return;
+                },
+                None => {
+                    self.status = ScheduledTransferStatus::Completed;
+                    return;
+                },
+            }
+        }
+    }
+
+    fn occurrence_at(&self, occurrence: i32) -> Option<jiff::Timestamp> {
+        let occurrence = u32::try_from(occurrence).ok()?;
+        match &self.recurrence {
+            Some(recurrence) => recurrence.occurrence(self.starts_at, occurrence),
+            None => (occurrence == 0).then_some(self.starts_at),
+        }
+    }
+}
+
+impl ScheduledTransferStatus {
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Active => "active",
+            Self::Paused => "paused",
+            Self::Completed => "completed",
+            Self::Cancelled => "cancelled",
+            Self::Failed => "failed",
+        }
+    }
+}
+
+impl fmt::Display for ScheduledTransferStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromSql<Text, Sqlite> for ScheduledTransferStatus {
+    fn from_sql(bytes: SqliteValue<'_, '_, '_>) -> deserialize::Result<Self> {
+        let s = <String as FromSql<Text, Sqlite>>::from_sql(bytes)?;
+
+        match &*s {
+            "active" => Ok(Self::Active),
+            "paused" => Ok(Self::Paused),
+            "completed" => Ok(Self::Completed),
+            "cancelled" => Ok(Self::Cancelled),
+            "failed" => Ok(Self::Failed),
+            _ => Err(format!("unknown scheduled transfer status: {s}").into()),
+        }
+    }
+}
+
+impl ToSql<Text, Sqlite> for ScheduledTransferStatus {
+    fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, Sqlite>) -> serialize::Result {
+        <str as ToSql<Text, Sqlite>>::to_sql(self.as_str(), out)
+    }
+}
+
+impl ScheduledTransferAttemptOutcome {
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Succeeded => "succeeded",
+            Self::Failed => "failed",
+        }
+    }
+}
+
+impl FromSql<Text, Sqlite> for ScheduledTransferAttemptOutcome {
+    fn from_sql(bytes: SqliteValue<'_, '_, '_>) -> deserialize::Result<Self> {
+        let s = <String as FromSql<Text, Sqlite>>::from_sql(bytes)?;
+
+        match &*s {
+            "succeeded" => Ok(Self::Succeeded),
+            "failed" => Ok(Self::Failed),
+            _ => Err(format!("unknown scheduled transfer attempt outcome: {s}").into()),
+        }
+    }
+}
+
+impl ToSql<Text, Sqlite> for ScheduledTransferAttemptOutcome {
+    fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, Sqlite>) -> serialize::Result {
+        <str as ToSql<Text, Sqlite>>::to_sql(self.as_str(), out)
+    }
+}
+
+impl Recurrence {
+    /// Returns when occurrence number `occurrence`, counting from zero, is due,
+    /// or `None` if there is no such occurrence.
+    ///
+    /// Occurrences are computed from `starts_at` rather than from each other, so
+    /// that e.g. a monthly transfer starting on the 31st is due on the last day
+    /// of shorter months, and on the 31st again after them.
+    pub fn occurrence(
+        &self,
+        starts_at: jiff::Timestamp,
+        occurrence: u32,
+    ) -> Option<jiff::Timestamp> {
+        if self.count.is_some_and(|count| occurrence >= count) {
+            return None;
+        }
+
+        let units = i64::from(self.interval).checked_mul(i64::from(occurrence))?;
+        let span = match self.frequency {
+            Frequency::Daily => jiff::Span::new().try_days(units),
+            Frequency::Weekly => jiff::Span::new().try_weeks(units),
+            Frequency::Monthly => jiff::Span::new().try_months(units),
+            Frequency::Yearly => jiff::Span::new().try_years(units),
+        }
+        .ok()?;
+
+        starts_at
+            .to_zoned(jiff::tz::TimeZone::UTC)
+            .checked_add(span)
+            .ok()
+            .map(|zoned| zoned.timestamp())
+    }
+}
+
+impl fmt::Display for Recurrence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let frequency = match self.frequency {
+            Frequency::Daily => "DAILY",
+            Frequency::Weekly => "WEEKLY",
+            Frequency::Monthly => "MONTHLY",
+            Frequency::Yearly => "YEARLY",
+        };
+        write!(
+            f,
+            "FREQ={frequency};INTERVAL={interval}",
+            interval = self.interval
+        )?;
+        if let Some(count) = self.count {
+            write!(f, ";COUNT={count}")?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Recurrence {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut frequency = None;
+        let mut interval = None;
+        let mut count = None;
+
+        for part in s.split(';') {
+            let Some((name, value)) = part.split_once('=') else {
+                return Err(format!("invalid recurrence rule part: {part}"));
+            };
+
+            match name.to_ascii_uppercase().as_str() {
+                "FREQ" if frequency.is_none() => {
+                    frequency = Some(match value.to_ascii_uppercase().as_str() {
+                        "DAILY" => Frequency::Daily,
+                        "WEEKLY" => Frequency::Weekly,
+                        "MONTHLY" => Frequency::Monthly,
+                        "YEARLY" => Frequency::Yearly,
+                        _ => return Err(format!("unsupported recurrence frequency: {value}")),
+                    });
+                },
+                "INTERVAL" if interval.is_none() => {
+                    interval = Some(
+                        value
+                            .parse()
+                            .ok()
+                            .filter(|interval| *interval > 0)
+                            .ok_or("recurrence interval must be a positive integer")?,
+                    );
+                },
+                "COUNT" if count.is_none() => {
+                    count = Some(
+                        value
+                            .parse()
+                            .ok()
+                            .filter(|count| *count > 0)
+                            .ok_or("recurrence count must be a positive integer")?,
+                    );
+                },
+                "FREQ" | "INTERVAL" | "COUNT" => {
+                    return Err(format!("duplicate recurrence rule part: {name}"));
+                },
+                _ => return Err(format!("unsupported recurrence rule part: {name}")),
+            }
+        }
+
+        Ok(Self {
+            frequency: frequency.ok_or("recurrence rule must have a FREQ part")?,
+            interval: interval.unwrap_or(1),
+            count,
+        })
+    }
+}
+
+impl TryFrom<String> for Recurrence {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl From<Recurrence> for String {
+    fn from(recurrence: Recurrence) -> Self {
+        recurrence.to_string()
+    }
+}
+
+impl FromSql<Text, Sqlite> for Recurrence {
+    fn from_sql(bytes: SqliteValue<'_, '_, '_>) -> deserialize::Result<Self> {
+        let s = <String as FromSql<Text, Sqlite>>::from_sql(bytes)?;
+
+        Ok(s.parse()?)
+    }
+}
+
+impl ToSql<Text, Sqlite> for Recurrence {
+    fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, Sqlite>) -> serialize::Result {
+        out.set_value(self.to_string());
+        Ok(serialize::IsNull::No)
+    }
+}
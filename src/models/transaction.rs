@@ -1,14 +1,23 @@
+use std::fmt;
+
 use bigdecimal::BigDecimal;
+use diesel::deserialize::{self, FromSql};
 use diesel::prelude::*;
-use diesel::sqlite::Sqlite;
+use diesel::serialize::{self, Output, ToSql};
+use diesel::sql_types::Text;
+use diesel::sqlite::{Sqlite, SqliteValue};
+use diesel::{AsExpression, FromSqlRow};
+use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
 use super::types;
+use crate::currency::Currency;
 use crate::schema::transactions;
 
-#[derive(Debug, AsChangeset, Identifiable, Queryable, Selectable)]
+#[derive(Clone, Debug, AsChangeset, Identifiable, Queryable, Selectable)]
 #[diesel(table_name = transactions)]
 #[diesel(check_for_backend(Sqlite))]
+#[diesel(treat_none_as_null = true)]
 pub struct Transaction {
     #[diesel(
         serialize_as = types::Uuid,
@@ -20,6 +29,7 @@ pub struct Transaction {
         deserialize_as = types::BigDecimal,
     )]
     pub amount: BigDecimal,
+    pub currency: Currency,
     #[diesel(
         serialize_as = types::Uuid,
         deserialize_as = types::Uuid,
@@ -31,10 +41,62 @@ pub struct Transaction {
     )]
     pub sender: Uuid,
     #[diesel(
-        serialize_as = jiff_diesel::Timestamp,
-        deserialize_as = jiff_diesel::Timestamp,
+        serialize_as = types::Timestamp,
+        deserialize_as = types::Timestamp,
     )]
     pub timestamp: jiff::Timestamp,
+    pub memo: Option<String>,
+    pub reference: Option<String>,
+    pub kind: TransactionKind,
+    /// The transaction which this refund or reversal undoes.
+    #[diesel(
+        serialize_as = types::NullableUuid,
+        deserialize_as = types::NullableUuid,
+    )]
+    pub original_transaction_id: Option<Uuid>,
+    /// Whether the sender's balance went negative, which only reversals may do.
+    pub flagged: bool,
+    /// What the recipient was credited, if the amount was converted into
+    /// another currency. The amount and currency are what the sender was
+    /// debited.
+    #[diesel(
+        serialize_as = types::NullableBigDecimal,
+        deserialize_as = types::NullableBigDecimal,
+    )]
+    pub converted_amount: Option<BigDecimal>,
+    pub converted_currency: Option<Currency>,
+    /// The mid-market rate of the conversion, before the spread.
+    #[diesel(
+        serialize_as = types::NullableBigDecimal,
+        deserialize_as = types::NullableBigDecimal,
+    )]
+    pub exchange_rate: Option<BigDecimal>,
+    /// The fraction of the converted amount kept by the platform.
+    #[diesel(
+        serialize_as = types::NullableBigDecimal,
+        deserialize_as = types::NullableBigDecimal,
+    )]
+    pub spread: Option<BigDecimal>,
+    /// The fee charged to the sender on top of the amount, for transfers.
+    #[diesel(
+        serialize_as = types::NullableBigDecimal,
+        deserialize_as = types::NullableBigDecimal,
+    )]
+    pub fee: Option<BigDecimal>,
+    /// The admin who made the transaction on behalf of its sender, for
+    /// reversals, mints and burns.
+    #[diesel(
+        serialize_as = types::NullableUuid,
+        deserialize_as = types::NullableUuid,
+    )]
+    pub initiator: Option<Uuid>,
+    /// The pocket money was moved into or out of, for pocket deposits and
+    /// withdrawals.
+    #[diesel(
+        serialize_as = types::NullableUuid,
+        deserialize_as = types::NullableUuid,
+    )]
+    pub pocket_id: Option<Uuid>,
 }
 
 #[derive(Debug, Insertable)]
@@ -44,10 +106,136 @@ pub struct NewTransaction {
     pub id: Uuid,
     #[diesel(serialize_as = types::BigDecimal)]
     pub amount: BigDecimal,
+    pub currency: Currency,
     #[diesel(serialize_as = types::Uuid)]
     pub recipient: Uuid,
     #[diesel(serialize_as = types::Uuid)]
     pub sender: Uuid,
-    #[diesel(serialize_as = jiff_diesel::Timestamp)]
+    #[diesel(serialize_as = types::Timestamp)]
     pub timestamp: jiff::Timestamp,
+    pub memo: Option<String>,
+    pub reference: Option<String>,
+    pub kind: TransactionKind,
+    #[diesel(serialize_as = types::NullableUuid)]
+    pub original_transaction_id: Option<Uuid>,
+    pub flagged: bool,
+    #[diesel(serialize_as = types::NullableBigDecimal)]
+    pub converted_amount: Option<BigDecimal>,
+    pub converted_currency: Option<Currency>,
+    #[diesel(serialize_as = types::NullableBigDecimal)]
+    pub exchange_rate: Option<BigDecimal>,
+    #[diesel(serialize_as = types::NullableBigDecimal)]
+    pub spread: Option<BigDecimal>,
+    #[diesel(serialize_as = types::NullableBigDecimal)]
+    pub fee: Option<BigDecimal>,
+    #[diesel(serialize_as = types::NullableUuid)]
+    pub initiator: Option<Uuid>,
+    #[diesel(serialize_as = types::NullableUuid)]
+    pub pocket_id: Option<Uuid>,
+}
+
+impl Transaction {
+    /// The amount credited to the recipient, which differs from the amount
+    /// debited from the sender if it was converted.
+    pub fn credited_amount(&self) -> &BigDecimal {
+        self.converted_amount.as_ref().unwrap_or(&self.amount)
+    }
+
+    /// The currency credited to the recipient.
+    pub fn credited_currency(&self) -> Currency {
+        self.converted_currency.unwrap_or(self.currency)
+    }
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug, AsExpression, FromSqlRow, Deserialize, Serialize)]
+#[diesel(sql_type = Text)]
+#[serde(rename_all = "lowercase")]
+pub enum TransactionKind {
+    Transfer,
+    /// Money sent back by the recipient of the original transaction.
+    Refund,
+    /// Money taken back from the recipient of the original transaction by an
+    /// admin.
+    Reversal,
+    /// The fee for the original transaction, paid to the platform.
+    Fee,
+    /// New money issued into the treasury by an admin.
+    Mint,
+    /// Money removed from the treasury by an admin.
+    Burn,
+    /// Money paid from the treasury to a new user.
+    Grant,
+    /// Money moved from the account of a user into one of their pockets.
+    #[serde(rename = "pocket_deposit")]
+    PocketDeposit,
+    /// Money moved from a pocket of a user back into their account.
+    #[serde(rename = "pocket_withdrawal")]
+    PocketWithdrawal,
+    /// Interest paid from the treasury into an account or pocket.
+    Interest,
+    /// Money moved into escrow to fund a gift code.
+    #[serde(rename = "gift_code_funding")]
+    GiftCodeFunding,
+    /// Money paid out of escrow to a user who redeemed a gift code.
+    #[serde(rename = "gift_code_redemption")]
+    GiftCodeRedemption,
+    /// What was left of the funds of a gift code, paid back out of escrow once
+    /// it expired or was cancelled.
+    #[serde(rename = "gift_code_refund")]
+    GiftCodeRefund,
+}
+
+impl TransactionKind {
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Transfer => "transfer",
+            Self::Refund => "refund",
+            Self::Reversal => "reversal",
+            Self::Fee => "fee",
+            Self::Mint => "mint",
+            Self::Burn => "burn",
+            Self::Grant => "grant",
+            Self::PocketDeposit => "pocket_deposit",
+            Self::PocketWithdrawal => "pocket_withdrawal",
+            Self::Interest => "interest",
+            Self::GiftCodeFunding => "gift_code_funding",
+            Self::GiftCodeRedemption => "gift_code_redemption",
+            Self::GiftCodeRefund => "gift_code_refund",
+        }
+    }
+}
+
+impl fmt::Display for TransactionKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromSql<Text, Sqlite> for TransactionKind {
+    fn from_sql(bytes: SqliteValue<'_, '_, '_>) -> deserialize::Result<Self> {
+        let s = <String as FromSql<Text, Sqlite>>::from_sql(bytes)?;
+
+        match &*s {
+            "transfer" => Ok(Self::Transfer),
+            "refund" => Ok(Self::Refund),
+            "reversal" => Ok(Self::Reversal),
+            "fee" => Ok(Self::Fee),
+            "mint" => Ok(Self::Mint),
+            "burn" => Ok(Self::Burn),
+            "grant" => Ok(Self::Grant),
+            "pocket_deposit" => Ok(Self::PocketDeposit),
+            "pocket_withdrawal" => Ok(Self::PocketWithdrawal),
+            "interest" => Ok(Self::Interest),
+            "gift_code_funding" => Ok(Self::GiftCodeFunding),
+            "gift_code_redemption" => Ok(Self::GiftCodeRedemption),
+            "gift_code_refund" => Ok(Self::GiftCodeRefund),
+            _ => Err(format!("unknown transaction kind: {s}").into()),
+        }
+    }
+}
+
+impl ToSql<Text, Sqlite> for TransactionKind {
+    fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, Sqlite>) -> serialize::Result {
+        <str as ToSql<Text, Sqlite>>::to_sql(self.as_str(), out)
+    }
 }
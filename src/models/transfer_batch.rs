@@ -0,0 +1,48 @@
+use diesel::prelude::*;
+use diesel::sqlite::Sqlite;
+use uuid::Uuid;
+
+use super::types;
+use crate::currency::Currency;
+use crate::schema::{transfer_batch_items, transfer_batches};
+
+/// Transfers from one sender to many recipients, which were made together.
+#[derive(Debug, Identifiable, Insertable, Queryable, Selectable)]
+#[diesel(table_name = transfer_batches)]
+#[diesel(check_for_backend(Sqlite))]
+pub struct TransferBatch {
+    #[diesel(
+        serialize_as = types::Uuid,
+        deserialize_as = types::Uuid,
+    )]
+    pub id: Uuid,
+    #[diesel(
+        serialize_as = types::Uuid,
+        deserialize_as = types::Uuid,
+    )]
+    pub sender: Uuid,
+    pub currency: Currency,
+    #[diesel(
+        serialize_as = types::Timestamp,
+        deserialize_as = types::Timestamp,
+    )]
+    pub created_at: jiff::Timestamp,
+}
+
+/// A transfer of a batch, in the order it was requested in.
+#[derive(Debug, Insertable, Queryable, Selectable)]
+#[diesel(table_name = transfer_batch_items)]
+#[diesel(check_for_backend(Sqlite))]
+pub struct TransferBatchItem {
+    #[diesel(
+        serialize_as = types::Uuid,
+        deserialize_as = types::Uuid,
+    )]
+    pub batch_id: Uuid,
+    pub position: i32,
+    #[diesel(
+        serialize_as = types::Uuid,
+        deserialize_as = types::Uuid,
+    )]
+    pub transaction_id: Uuid,
+}
@@ -0,0 +1,49 @@
+use bigdecimal::BigDecimal;
+use diesel::prelude::*;
+use diesel::sqlite::Sqlite;
+use uuid::Uuid;
+
+use super::types;
+use crate::schema::transfer_limits;
+
+/// Transfer limits of a user which override the defaults, as set by an admin.
+///
+/// Limits which are `None` fall back to the defaults, unless they are removed,
+/// in which case they don't apply to the user at all.
+#[derive(Clone, Debug, AsChangeset, Identifiable, Insertable, Queryable, Selectable)]
+#[diesel(table_name = transfer_limits)]
+#[diesel(primary_key(user_id))]
+#[diesel(check_for_backend(Sqlite))]
+#[diesel(treat_none_as_null = true)]
+pub struct TransferLimit {
+    #[diesel(
+        serialize_as = types::Uuid,
+        deserialize_as = types::Uuid,
+    )]
+    pub user_id: Uuid,
+    #[diesel(
+        serialize_as = types::NullableBigDecimal,
+        deserialize_as = types::NullableBigDecimal,
+    )]
+    pub per_transaction: Option<BigDecimal>,
+    #[diesel(
+        serialize_as = types::NullableBigDecimal,
+        deserialize_as = types::NullableBigDecimal,
+    )]
+    pub daily: Option<BigDecimal>,
+    #[diesel(
+        serialize_as = types::NullableBigDecimal,
+        deserialize_as = types::NullableBigDecimal,
+    )]
+    pub monthly: Option<BigDecimal>,
+    pub max_transfers: Option<i32>,
+    #[diesel(
+        serialize_as = types::Timestamp,
+        deserialize_as = types::Timestamp,
+    )]
+    pub updated_at: jiff::Timestamp,
+    pub per_transaction_removed: bool,
+    pub daily_removed: bool,
+    pub monthly_removed: bool,
+    pub max_transfers_removed: bool,
+}
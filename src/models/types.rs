@@ -1,7 +1,9 @@
-pub use self::big_decimal::BigDecimal;
+pub use self::big_decimal::{BigDecimal, NullableBigDecimal};
 pub use self::secret_string::SecretString;
-pub use self::uuid::Uuid;
+pub use self::timestamp::{NullableTimestamp, Timestamp};
+pub use self::uuid::{NullableUuid, Uuid};
 
 mod big_decimal;
 mod secret_string;
+mod timestamp;
 mod uuid;
@@ -2,7 +2,7 @@ use std::str::FromStr as _;
 
 use diesel::deserialize::{self, FromSql};
 use diesel::serialize::{self, IsNull, Output, ToSql};
-use diesel::sql_types::Text;
+use diesel::sql_types::{Nullable, Text};
 use diesel::sqlite::{Sqlite, SqliteValue};
 use diesel::{AsExpression, FromSqlRow};
 
@@ -39,3 +39,46 @@ impl ToSql<Text, Sqlite> for BigDecimal {
         Ok(IsNull::No)
     }
 }
+
+#[derive(Clone, Eq, PartialEq, Hash, Debug, Default, AsExpression, FromSqlRow)]
+#[diesel(sql_type = Nullable<Text>)]
+pub struct NullableBigDecimal(Option<bigdecimal::BigDecimal>);
+
+impl From<Option<bigdecimal::BigDecimal>> for NullableBigDecimal {
+    fn from(value: Option<bigdecimal::BigDecimal>) -> Self {
+        Self(value)
+    }
+}
+
+impl From<NullableBigDecimal> for Option<bigdecimal::BigDecimal> {
+    fn from(value: NullableBigDecimal) -> Self {
+        value.0
+    }
+}
+
+impl FromSql<Nullable<Text>, Sqlite> for NullableBigDecimal {
+    fn from_sql(bytes: SqliteValue<'_, '_, '_>) -> deserialize::Result<Self> {
+        let value = <BigDecimal as FromSql<Text, Sqlite>>::from_sql(bytes)?;
+
+        Ok(NullableBigDecimal(Some(value.0)))
+    }
+
+    fn from_nullable_sql(bytes: Option<SqliteValue<'_, '_, '_>>) -> deserialize::Result<Self> {
+        match bytes {
+            Some(bytes) => Self::from_sql(bytes),
+            None => Ok(NullableBigDecimal(None)),
+        }
+    }
+}
+
+impl ToSql<Nullable<Text>, Sqlite> for NullableBigDecimal {
+    fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, Sqlite>) -> serialize::Result {
+        match &self.0 {
+            Some(value) => {
+                out.set_value(format!("{value}"));
+                Ok(IsNull::No)
+            },
+            None => Ok(IsNull::Yes),
+        }
+    }
+}
@@ -0,0 +1,84 @@
+use diesel::deserialize::{self, FromSql};
+use diesel::serialize::{self, IsNull, Output, ToSql};
+use diesel::sql_types::{Nullable, TimestamptzSqlite};
+use diesel::sqlite::{Sqlite, SqliteValue};
+use diesel::{AsExpression, FromSqlRow};
+
+/// A timestamp stored as text with exactly nine fractional digits, so that
+/// timestamps can be compared in SQL.
+#[derive(Clone, Copy, Eq, PartialEq, Ord, PartialOrd, Hash, Debug, AsExpression, FromSqlRow)]
+#[diesel(sql_type = TimestamptzSqlite)]
+pub struct Timestamp(jiff::Timestamp);
+
+impl From<jiff::Timestamp> for Timestamp {
+    fn from(value: jiff::Timestamp) -> Self {
+        Self(value)
+    }
+}
+
+impl From<Timestamp> for jiff::Timestamp {
+    fn from(value: Timestamp) -> Self {
+        value.0
+    }
+}
+
+impl FromSql<TimestamptzSqlite, Sqlite> for Timestamp {
+    fn from_sql(bytes: SqliteValue<'_, '_, '_>) -> deserialize::Result<Self> {
+        let value =
+            <jiff_diesel::Timestamp as FromSql<TimestamptzSqlite, Sqlite>>::from_sql(bytes)?;
+
+        Ok(Timestamp(value.to_jiff()))
+    }
+}
+
+impl ToSql<TimestamptzSqlite, Sqlite> for Timestamp {
+    fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, Sqlite>) -> serialize::Result {
+        let value = &self.0;
+
+        out.set_value(format!("{value:.9}"));
+        Ok(IsNull::No)
+    }
+}
+
+#[derive(Clone, Copy, Eq, PartialEq, Hash, Debug, Default, AsExpression, FromSqlRow)]
+#[diesel(sql_type = Nullable<TimestamptzSqlite>)]
+pub struct NullableTimestamp(Option<jiff::Timestamp>);
+
+impl From<Option<jiff::Timestamp>> for NullableTimestamp {
+    fn from(value: Option<jiff::Timestamp>) -> Self {
+        Self(value)
+    }
+}
+
+impl From<NullableTimestamp> for Option<jiff::Timestamp> {
+    fn from(value: NullableTimestamp) -> Self {
+        value.0
+    }
+}
+
+impl FromSql<Nullable<TimestamptzSqlite>, Sqlite> for NullableTimestamp {
+    fn from_sql(bytes: SqliteValue<'_, '_, '_>) -> deserialize::Result<Self> {
+        let value = <Timestamp as FromSql<TimestamptzSqlite, Sqlite>>::from_sql(bytes)?;
+
+        Ok(NullableTimestamp(Some(value.0)))
+    }
+
+    fn from_nullable_sql(bytes: Option<SqliteValue<'_, '_, '_>>) -> deserialize::Result<Self> {
+        match bytes {
+            Some(bytes) => Self::from_sql(bytes),
+            None => Ok(NullableTimestamp(None)),
+        }
+    }
+}
+
+impl ToSql<Nullable<TimestamptzSqlite>, Sqlite> for NullableTimestamp {
+    fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, Sqlite>) -> serialize::Result {
+        match &self.0 {
+            Some(value) => {
+                out.set_value(format!("{value:.9}"));
+                Ok(IsNull::No)
+            },
+            None => Ok(IsNull::Yes),
+        }
+    }
+}
@@ -1,6 +1,6 @@
 use diesel::deserialize::{self, FromSql};
-use diesel::serialize::{self, Output, ToSql};
-use diesel::sql_types::Binary;
+use diesel::serialize::{self, IsNull, Output, ToSql};
+use diesel::sql_types::{Binary, Nullable};
 use diesel::sqlite::{Sqlite, SqliteValue};
 use diesel::{AsExpression, FromSqlRow};
 
@@ -36,3 +36,43 @@ impl ToSql<Binary, Sqlite> for Uuid {
         <[u8] as ToSql<Binary, Sqlite>>::to_sql(value, out)
     }
 }
+
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug, Default, AsExpression, FromSqlRow)]
+#[diesel(sql_type = Nullable<Binary>)]
+pub struct NullableUuid(Option<uuid::Uuid>);
+
+impl From<Option<uuid::Uuid>> for NullableUuid {
+    fn from(value: Option<uuid::Uuid>) -> Self {
+        Self(value)
+    }
+}
+
+impl From<NullableUuid> for Option<uuid::Uuid> {
+    fn from(value: NullableUuid) -> Self {
+        value.0
+    }
+}
+
+impl FromSql<Nullable<Binary>, Sqlite> for NullableUuid {
+    fn from_sql(bytes: SqliteValue<'_, '_, '_>) -> deserialize::Result<Self> {
+        let value = <Uuid as FromSql<Binary, Sqlite>>::from_sql(bytes)?;
+
+        Ok(NullableUuid(Some(value.0)))
+    }
+
+    fn from_nullable_sql(bytes: Option<SqliteValue<'_, '_, '_>>) -> deserialize::Result<Self> {
+        match bytes {
+            Some(bytes) => Self::from_sql(bytes),
+            None => Ok(NullableUuid(None)),
+        }
+    }
+}
+
+impl ToSql<Nullable<Binary>, Sqlite> for NullableUuid {
+    fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, Sqlite>) -> serialize::Result {
+        match &self.0 {
+            Some(value) => <[u8] as ToSql<Binary, Sqlite>>::to_sql(&value.as_bytes()[..], out),
+            None => Ok(IsNull::Yes),
+        }
+    }
+}
@@ -1,7 +1,13 @@
-use bigdecimal::BigDecimal;
+use std::fmt;
+
+use diesel::deserialize::{self, FromSql};
 use diesel::prelude::*;
-use diesel::sqlite::Sqlite;
+use diesel::serialize::{self, Output, ToSql};
+use diesel::sql_types::Text;
+use diesel::sqlite::{Sqlite, SqliteValue};
+use diesel::{AsExpression, FromSqlRow};
 use secrecy::SecretString;
+use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
 use super::types;
@@ -22,14 +28,12 @@ pub struct User {
         deserialize_as = types::SecretString,
     )]
     pub password_hash: SecretString,
-    #[diesel(
-        serialize_as = types::BigDecimal,
-        deserialize_as = types::BigDecimal,
-    )]
-    pub balance: BigDecimal,
+    pub role: UserRole,
+    pub tier: UserTier,
+    pub kind: UserKind,
 }
 
-#[derive(Debug, Insertable)]
+#[derive(Clone, Debug, Insertable)]
 #[diesel(table_name = users)]
 pub struct NewUser {
     #[diesel(serialize_as = types::Uuid)]
@@ -37,6 +41,161 @@ pub struct NewUser {
     pub username: String,
     #[diesel(serialize_as = types::SecretString)]
     pub password_hash: SecretString,
-    #[diesel(serialize_as = types::BigDecimal)]
-    pub balance: BigDecimal,
+    pub role: UserRole,
+    pub tier: UserTier,
+    pub kind: UserKind,
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug, AsExpression, FromSqlRow, Deserialize, Serialize)]
+#[diesel(sql_type = Text)]
+#[serde(rename_all = "lowercase")]
+pub enum UserRole {
+    User,
+    /// May reverse any transaction.
+    Admin,
+    /// An account of the platform itself, e.g. for fee revenue, which can't
+    /// log in or be sent money.
+    System,
+}
+
+/// The tier of a user, which fee rules may apply to.
+#[derive(Copy, Clone, Eq, PartialEq, Debug, AsExpression, FromSqlRow, Deserialize, Serialize)]
+#[diesel(sql_type = Text)]
+#[serde(rename_all = "lowercase")]
+pub enum UserTier {
+    Standard,
+    Premium,
+}
+
+/// The kind of a user, as set by an admin.
+#[derive(Copy, Clone, Eq, PartialEq, Debug, AsExpression, FromSqlRow, Deserialize, Serialize)]
+#[diesel(sql_type = Text)]
+#[serde(rename_all = "lowercase")]
+pub enum UserKind {
+    Personal,
+    /// A business, which may create checkout sessions to be paid.
+    Merchant,
+}
+
+/// A user, given either by user ID or by username.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(untagged)]
+pub enum UserRef {
+    Id(Uuid),
+    Username(String),
+}
+
+impl UserRef {
+    /// Returns a query selecting the referenced user.
+    pub fn query(&self) -> users::BoxedQuery<'_, Sqlite> {
+        match self {
+            Self::Id(id) => users::table
+                .filter(users::id.eq(types::Uuid::from(*id)))
+                .into_boxed(),
+            Self::Username(username) => users::table
+                .filter(users::username.eq(username))
+                .into_boxed(),
+        }
+    }
+}
+
+impl UserRole {
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::User => "user",
+            Self::Admin => "admin",
+            Self::System => "system",
+        }
+    }
+}
+
+impl fmt::Display for UserRole {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromSql<Text, Sqlite> for UserRole {
+    fn from_sql(bytes: SqliteValue<'_, '_, '_>) -> deserialize::Result<Self> {
+        let s = <String as FromSql<Text, Sqlite>>::from_sql(bytes)?;
+
+        match &*s {
+            "user" => Ok(Self::User),
+            "admin" => Ok(Self::Admin),
+            "system" => Ok(Self::System),
+            _ => Err(format!("unknown user role: {s}").into()),
+        }
+    }
+}
+
+impl ToSql<Text, Sqlite> for UserRole {
+    fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, Sqlite>) -> serialize::Result {
+        <str as ToSql<Text, Sqlite>>::to_sql(self.as_str(), out)
+    }
+}
+
+impl UserTier {
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Standard => "standard",
+            Self::Premium => "premium",
+        }
+    }
+}
+
+impl fmt::Display for UserTier {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromSql<Text, Sqlite> for UserTier {
+    fn from_sql(bytes: SqliteValue<'_, '_, '_>) -> deserialize::Result<Self> {
+        let s = <String as FromSql<Text, Sqlite>>::from_sql(bytes)?;
+
+        match &*s {
+            "standard" => Ok(Self::Standard),
+            "premium" => Ok(Self::Premium),
+            _ => Err(format!("unknown user tier: {s}").into()),
+        }
+    }
+}
+
+impl ToSql<Text, Sqlite> for UserTier {
+    fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, Sqlite>) -> serialize::Result {
+        <str as ToSql<Text, Sqlite>>::to_sql(self.as_str(), out)
+    }
+}
+
+impl UserKind {
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Personal => "personal",
+            Self::Merchant => "merchant",
+        }
+    }
+}
+
+impl fmt::Display for UserKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromSql<Text, Sqlite> for UserKind {
+    fn from_sql(bytes: SqliteValue<'_, '_, '_>) -> deserialize::Result<Self> {
+        let s = <String as FromSql<Text, Sqlite>>::from_sql(bytes)?;
+
+        match &*s {
+            "personal" => Ok(Self::Personal),
+            "merchant" => Ok(Self::Merchant),
+            _ => Err(format!("unknown user kind: {s}").into()),
+        }
+    }
+}
+
+impl ToSql<Text, Sqlite> for UserKind {
+    fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, Sqlite>) -> serialize::Result {
+        <str as ToSql<Text, Sqlite>>::to_sql(self.as_str(), out)
+    }
 }
@@ -0,0 +1,223 @@
+use std::fmt;
+
+use diesel::deserialize::{self, FromSql};
+use diesel::prelude::*;
+use diesel::serialize::{self, Output, ToSql};
+use diesel::sql_types::Text;
+use diesel::sqlite::{Sqlite, SqliteValue};
+use diesel::{AsExpression, FromSqlRow};
+use secrecy::SecretString;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use super::types;
+use crate::schema::{webhook_deliveries, webhook_endpoints, webhook_subscriptions};
+
+/// A URL which is sent the events of a user it subscribes to.
+#[derive(Debug, Identifiable, Insertable, Queryable, Selectable)]
+#[diesel(table_name = webhook_endpoints)]
+#[diesel(check_for_backend(Sqlite))]
+pub struct WebhookEndpoint {
+    #[diesel(
+        serialize_as = types::Uuid,
+        deserialize_as = types::Uuid,
+    )]
+    pub id: Uuid,
+    #[diesel(
+        serialize_as = types::Uuid,
+        deserialize_as = types::Uuid,
+    )]
+    pub user_id: Uuid,
+    pub url: String,
+    /// The key which deliveries are signed with.
+    #[diesel(
+        serialize_as = types::SecretString,
+        deserialize_as = types::SecretString,
+    )]
+    pub secret: SecretString,
+    /// Whether the endpoint is sent the events of all users rather than only
+    /// those of its user, e.g. for the back office. Only admins may create
+    /// such endpoints.
+    pub all_users: bool,
+    #[diesel(
+        serialize_as = types::Timestamp,
+        deserialize_as = types::Timestamp,
+    )]
+    pub created_at: jiff::Timestamp,
+}
+
+#[derive(Debug, Insertable, Queryable, Selectable)]
+#[diesel(table_name = webhook_subscriptions)]
+#[diesel(check_for_backend(Sqlite))]
+pub struct WebhookSubscription {
+    #[diesel(
+        serialize_as = types::Uuid,
+        deserialize_as = types::Uuid,
+    )]
+    pub endpoint_id: Uuid,
+    pub event_type: WebhookEventType,
+}
+
+/// One event to be sent to one endpoint, kept until it has been delivered or
+/// given up on.
+#[derive(Debug, AsChangeset, Identifiable, Insertable, Queryable, Selectable)]
+#[diesel(table_name = webhook_deliveries)]
+#[diesel(check_for_backend(Sqlite))]
+#[diesel(treat_none_as_null = true)]
+pub struct WebhookDelivery {
+    #[diesel(
+        serialize_as = types::Uuid,
+        deserialize_as = types::Uuid,
+    )]
+    pub id: Uuid,
+    #[diesel(
+        serialize_as = types::Uuid,
+        deserialize_as = types::Uuid,
+    )]
+    pub endpoint_id: Uuid,
+    /// The same for the deliveries of one event to different endpoints, so that
+    /// receivers can tell duplicates apart.
+    #[diesel(
+        serialize_as = types::Uuid,
+        deserialize_as = types::Uuid,
+    )]
+    pub event_id: Uuid,
+    pub event_type: WebhookEventType,
+    /// The JSON request body.
+    pub payload: String,
+    pub status: WebhookDeliveryStatus,
+    pub attempts: i32,
+    #[diesel(
+        serialize_as = types::Timestamp,
+        deserialize_as = types::Timestamp,
+    )]
+    pub next_attempt_at: jiff::Timestamp,
+    #[diesel(
+        serialize_as = types::NullableTimestamp,
+        deserialize_as = types::NullableTimestamp,
+    )]
+    pub last_attempt_at: Option<jiff::Timestamp>,
+    pub last_response_status: Option<i32>,
+    pub last_error: Option<String>,
+    #[diesel(
+        serialize_as = types::Timestamp,
+        deserialize_as = types::Timestamp,
+    )]
+    pub created_at: jiff::Timestamp,
+    #[diesel(
+        serialize_as = types::NullableTimestamp,
+        deserialize_as = types::NullableTimestamp,
+    )]
+    pub delivered_at: Option<jiff::Timestamp>,
+}
+
+#[derive(
+    Copy,
+    Clone,
+    Eq,
+    PartialEq,
+    Hash,
+    Ord,
+    PartialOrd,
+    Debug,
+    AsExpression,
+    FromSqlRow,
+    Deserialize,
+    Serialize,
+)]
+#[diesel(sql_type = Text)]
+pub enum WebhookEventType {
+    /// Any transaction, including refunds.
+    #[serde(rename = "transaction.created")]
+    TransactionCreated,
+    #[serde(rename = "payment_request.paid")]
+    PaymentRequestPaid,
+    /// A refund or reversal.
+    #[serde(rename = "refund.created")]
+    RefundCreated,
+    #[serde(rename = "checkout_session.completed")]
+    CheckoutSessionCompleted,
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug, AsExpression, FromSqlRow, Deserialize, Serialize)]
+#[diesel(sql_type = Text)]
+#[serde(rename_all = "lowercase")]
+pub enum WebhookDeliveryStatus {
+    /// Not delivered yet, and still being attempted.
+    Pending,
+    Succeeded,
+    /// Given up on after too many failed attempts, until it is replayed.
+    Dead,
+}
+
+impl WebhookEventType {
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::TransactionCreated => "transaction.created",
+            Self::PaymentRequestPaid => "payment_request.paid",
+            Self::RefundCreated => "refund.created",
+            Self::CheckoutSessionCompleted => "checkout_session.completed",
+        }
+    }
+}
+
+impl fmt::Display for WebhookEventType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromSql<Text, Sqlite> for WebhookEventType {
+    fn from_sql(bytes: SqliteValue<'_, '_, '_>) -> deserialize::Result<Self> {
+        let s = <String as FromSql<Text, Sqlite>>::from_sql(bytes)?;
+
+        match &*s {
+            "transaction.created" => Ok(Self::TransactionCreated),
+            "payment_request.paid" => Ok(Self::PaymentRequestPaid),
+            "refund.created" => Ok(Self::RefundCreated),
+            "checkout_session.completed" => Ok(Self::CheckoutSessionCompleted),
+            _ => Err(format!("unknown webhook event type: {s}").into()),
+        }
+    }
+}
+
+impl ToSql<Text, Sqlite> for WebhookEventType {
+    fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, Sqlite>) -> serialize::Result {
+        <str as ToSql<Text, Sqlite>>::to_sql(self.as_str(), out)
+    }
+}
+
+impl WebhookDeliveryStatus {
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Pending => "pending",
+            Self::Succeeded => "succeeded",
+            Self::Dead => "dead",
+        }
+    }
+}
+
+impl fmt::Display for WebhookDeliveryStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromSql<Text, Sqlite> for WebhookDeliveryStatus {
+    fn from_sql(bytes: SqliteValue<'_, '_, '_>) -> deserialize::Result<Self> {
+        let s = <String as FromSql<Text, Sqlite>>::from_sql(bytes)?;
+
+        match &*s {
+            "pending" => Ok(Self::Pending),
+            "succeeded" => Ok(Self::Succeeded),
+            "dead" => Ok(Self::Dead),
+            _ => Err(format!("unknown webhook delivery status: {s}").into()),
+        }
+    }
+}
+
+impl ToSql<Text, Sqlite> for WebhookDeliveryStatus {
+    fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, Sqlite>) -> serialize::Result {
+        <str as ToSql<Text, Sqlite>>::to_sql(self.as_str(), out)
+    }
+}
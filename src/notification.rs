@@ -0,0 +1,276 @@
+//! The in-app inbox, which tells users about things which happened to them,
+//! e.g. that they received money.
+//!
+//! Like webhooks, notifications are written by the same database transaction
+//! as the change they describe, and only if the user hasn't turned off
+//! notifications of that kind.
+
+use anyhow::Context as _;
+use bigdecimal::BigDecimal;
+use diesel::prelude::*;
+#[allow(
+    clippy::unused_trait_names,
+    reason = "error[E0034]: multiple applicable items in scope"
+)]
+use diesel_async::RunQueryDsl;
+use ring::digest;
+use serde::Serialize;
+use uuid::Uuid;
+
+use crate::currency::{Currency, DEFAULT_CURRENCY};
+use crate::limits::LimitExceeded;
+use crate::models::notification::{KnownDevice, NotificationKind};
+use crate::models::transaction::TransactionKind;
+use crate::models::{Notification, PaymentRequest, Transaction};
+use crate::state::DbConnection;
+
+/// The maximum length of the user agent kept in a notification, in characters.
+const USER_AGENT_MAX_LEN: usize = 256;
+
+#[derive(Serialize)]
+struct MoneyReceivedData {
+    transaction_id: Uuid,
+    sender: Uuid,
+    /// The amount credited to the user, i.e. converted if need be.
+    #[serde(with = "bigdecimal::serde::json_num")]
+    amount: BigDecimal,
+    currency: Currency,
+    kind: TransactionKind,
+    memo: Option<String>,
+}
+
+#[derive(Serialize)]
+struct PaymentRequestPaidData {
+    payment_request_id: Uuid,
+    payer: Option<Uuid>,
+    #[serde(with = "bigdecimal::serde::json_num")]
+    amount: BigDecimal,
+    currency: Currency,
+    transaction_id: Option<Uuid>,
+}
+
+#[derive(Serialize)]
+struct NewDeviceLoginData {
+    user_agent: Option<String>,
+}
+
+#[derive(Serialize)]
+struct LimitReachedData<'a> {
+    #[serde(flatten)]
+    limit_exceeded: &'a LimitExceeded,
+    /// The currency of the limit, which is always [`DEFAULT_CURRENCY`].
+    currency: Currency,
+}
+
+/// Adds a notification to the inbox of `user_id`, unless they turned off
+/// notifications of `kind`.
+///
+/// This should be called in the database transaction which makes the change
+/// the notification is about.
+pub async fn notify<T: Serialize>(
+    conn: &mut DbConnection,
+    user_id: Uuid,
+    kind: NotificationKind,
+    data: &T,
+) -> anyhow::Result<()> {
+    use crate::schema::notifications;
+
+    if !is_enabled(conn, user_id, kind).await? {
+        return Ok(());
+    }
+
+    let notification = Notification {
+        id: Uuid::now_v7(),
+        user_id,
+        kind,
+        data: serde_json::to_string(data).context("failed to serialize notification")?,
+        created_at: jiff::Timestamp::now(),
+        read_at: None,
+    };
+    diesel::insert_into(notifications::table)
+        .values(notification)
+        .execute(conn)
+        .await
+        .context("failed to insert notification")?;
+
+    Ok(())
+}
+
+/// Whether `user_id` is sent notifications of `kind`, which they are unless
+/// they turned them off.
+pub async fn is_enabled(
+    conn: &mut DbConnection,
+    user_id: Uuid,
+    kind: NotificationKind,
+) -> anyhow::Result<bool> {
+    use crate::models::types;
+    use crate::schema::notification_preferences;
+
+    let enabled: Option<bool> = notification_preferences::table
+        .find((types::Uuid::from(user_id), kind))
+        .select(notification_preferences::enabled)
+        .first(conn)
+        .await
+        .optional()
+        .context("failed to query notification preferences")?;
+
+    Ok(enabled.unwrap_or(true))
+}
+
+/// Tells the recipient of a transaction which was just made that they received
+/// money, unless the money stayed with them or went to the platform.
+pub async fn notify_transaction(
+    conn: &mut DbConnection,
+    transaction: &Transaction,
+) -> anyhow::Result<()> {
+    if !matches!(
+        transaction.kind,
+        TransactionKind::Transfer
+            | TransactionKind::Refund
+            | TransactionKind::Reversal
+            | TransactionKind::Grant
+            | TransactionKind::Interest
+            | TransactionKind::GiftCodeRedemption
+            | TransactionKind::GiftCodeRefund
+    ) {
+        return Ok(());
+    }
+
+    let data = MoneyReceivedData {
+        transaction_id: transaction.id,
+        sender: transaction.sender,
+        amount: transaction.credited_amount().clone(),
+        currency: transaction.credited_currency(),
+        kind: transaction.kind,
+        memo: transaction.memo.clone(),
+    };
+
+    notify(
+        conn,
+        transaction.recipient,
+        NotificationKind::MoneyReceived,
+        &data,
+    )
+    .await
+}
+
+/// Tells the requester of a payment request which was just paid.
+pub async fn notify_payment_request_paid(
+    conn: &mut DbConnection,
+    payment_request: &PaymentRequest,
+) -> anyhow::Result<()> {
+    let data = PaymentRequestPaidData {
+        payment_request_id: payment_request.id,
+        payer: payment_request.payer,
+        amount: payment_request.amount.clone(),
+        currency: payment_request.currency,
+        transaction_id: payment_request.transaction_id,
+    };
+
+    notify(
+        conn,
+        payment_request.requester,
+        NotificationKind::PaymentRequestPaid,
+        &data,
+    )
+    .await
+}
+
+/// Tells `user_id` that a transfer of theirs was rejected for exceeding one of
+/// their limits.
+///
+/// Users who haven't read the last such notification yet aren't told again, so
+/// that retrying a transfer doesn't flood their inbox.
+pub async fn notify_limit_reached(
+    conn: &mut DbConnection,
+    user_id: Uuid,
+    limit_exceeded: &LimitExceeded,
+) -> anyhow::Result<()> {
+    use crate::models::types;
+    use crate::schema::notifications;
+
+    let unread = diesel::select(diesel::dsl::exists(
+        notifications::table
+            .filter(notifications::user_id.eq(types::Uuid::from(user_id)))
+            .filter(notifications::kind.eq(NotificationKind::LimitReached))
+            .filter(notifications::read_at.is_null()),
+    ))
+    .get_result::<bool>(conn)
+    .await
+    .context("failed to query notifications")?;
+    if unread {
+        return Ok(());
+    }
+
+    let data = LimitReachedData {
+        limit_exceeded,
+        currency: DEFAULT_CURRENCY,
+    };
+
+    notify(conn, user_id, NotificationKind::LimitReached, &data).await
+}
+
+/// Records that `user_id` logged in from the device with `user_agent`, and
+/// tells them if they hadn't logged in from it before.
+///
+/// Nothing is sent for the first device a user logs in from.
+///
+/// # Security
+///
+/// A device is only told apart by the SHA-256 hash of its `User-Agent` header,
+/// which clients choose freely and which changes with every browser update.
+/// So an attacker can avoid the notification by sending the user's usual
+/// `User-Agent`, and users are notified again after updating their browser.
+/// The notification is a courtesy, not a defense against account takeover.
+pub async fn record_login(
+    conn: &mut DbConnection,
+    user_id: Uuid,
+    user_agent: Option<&str>,
+) -> anyhow::Result<()> {
+    use crate::models::types;
+    use crate::schema::known_devices;
+
+    let now = jiff::Timestamp::now();
+    let fingerprint = digest::digest(&digest::SHA256, user_agent.unwrap_or_default().as_bytes())
+        .as_ref()
+        .to_vec();
+
+    let updated = diesel::update(
+        known_devices::table.find((types::Uuid::from(user_id), fingerprint.clone())),
+    )
+    .set(known_devices::last_seen_at.eq(types::Timestamp::from(now)))
+    .execute(conn)
+    .await
+    .context("failed to update known device")?;
+    if updated > 0 {
+        return Ok(());
+    }
+
+    let has_known_devices = diesel::select(diesel::dsl::exists(
+        known_devices::table.filter(known_devices::user_id.eq(types::Uuid::from(user_id))),
+    ))
+    .get_result::<bool>(conn)
+    .await
+    .context("failed to query known devices")?;
+
+    diesel::insert_into(known_devices::table)
+        .values(KnownDevice {
+            user_id,
+            fingerprint,
+            first_seen_at: now,
+            last_seen_at: now,
+        })
+        .execute(conn)
+        .await
+        .context("failed to insert known device")?;
+
+    if has_known_devices {
+        let data = NewDeviceLoginData {
+            user_agent: user_agent
+                .map(|user_agent| user_agent.chars().take(USER_AGENT_MAX_LEN).collect()),
+        };
+        notify(conn, user_id, NotificationKind::NewDeviceLogin, &data).await?;
+    }
+
+    Ok(())
+}
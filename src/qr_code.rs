@@ -0,0 +1,198 @@
+//! Server-side QR code rendering, so that every client encodes the same URIs in
+//! the same way.
+
+use anyhow::Context as _;
+use axum::Json;
+use axum::http::{StatusCode, header};
+use axum::response::{IntoResponse as _, Response};
+use qrcode::render::{Canvas, Pixel, svg};
+use qrcode::{EcLevel, QrCode};
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::error::AppError;
+
+/// The default width and height of a rendered QR code, in pixels.
+pub const DEFAULT_SIZE: u32 = 256;
+
+/// The smallest width and height of a rendered QR code, in pixels.
+pub const MIN_SIZE: u32 = 64;
+
+/// The largest width and height of a rendered QR code, in pixels.
+pub const MAX_SIZE: u32 = 2048;
+
+/// The image format of a rendered QR code.
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum QrCodeFormat {
+    #[default]
+    Svg,
+    Png,
+}
+
+/// The error correction level of a QR code. Higher levels survive more damage,
+/// at the cost of a denser code.
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum QrCodeErrorCorrection {
+    /// Recovers about 7% of the data.
+    L,
+    /// Recovers about 15% of the data.
+    #[default]
+    M,
+    /// Recovers about 25% of the data.
+    Q,
+    /// Recovers about 30% of the data.
+    H,
+}
+
+/// How to render a QR code.
+#[derive(Copy, Clone, Debug, Default, Deserialize)]
+pub struct QrCodeOptions {
+    #[serde(default)]
+    pub format: QrCodeFormat,
+    /// The largest width and height of the image, in pixels. The image may be
+    /// slightly smaller, as every module is the same whole number of pixels.
+    pub size: Option<u32>,
+    #[serde(default, rename = "ecc")]
+    pub error_correction: QrCodeErrorCorrection,
+}
+
+/// A rendered QR code image.
+pub struct QrCodeImage {
+    pub content_type: &'static str,
+    pub bytes: Vec<u8>,
+}
+
+impl From<QrCodeErrorCorrection> for EcLevel {
+    fn from(error_correction: QrCodeErrorCorrection) -> Self {
+        match error_correction {
+            QrCodeErrorCorrection::L => Self::L,
+            QrCodeErrorCorrection::M => Self::M,
+            QrCodeErrorCorrection::Q => Self::Q,
+            QrCodeErrorCorrection::H => Self::H,
+        }
+    }
+}
+
+/// Renders `data` as a QR code image response.
+pub(crate) fn qr_code_response(data: &str, options: QrCodeOptions) -> Response {
+    let size = options.size.unwrap_or(DEFAULT_SIZE);
+    if !(MIN_SIZE..=MAX_SIZE).contains(&size) {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(json!({
+                "title": "InvalidSize",
+                "detail": format!("size must be between {MIN_SIZE} and {MAX_SIZE} pixels"),
+            })),
+        )
+            .into_response();
+    }
+
+    match render(data, options.format, size, options.error_correction) {
+        Ok(image) => ([(header::CONTENT_TYPE, image.content_type)], image.bytes).into_response(),
+        Err(err) => AppError::from(err).into_response(),
+    }
+}
+
+/// Renders `data` as a QR code image of at most `size` pixels square.
+pub fn render(
+    data: &str,
+    format: QrCodeFormat,
+    size: u32,
+    error_correction: QrCodeErrorCorrection,
+) -> anyhow::Result<QrCodeImage> {
+    let code = QrCode::with_error_correction_level(data, error_correction.into())
+        .context("failed to encode QR code")?;
+
+    match format {
+        QrCodeFormat::Svg => Ok(QrCodeImage {
+            content_type: "image/svg+xml",
+            bytes: code
+                .render::<svg::Color<'_>>()
+                .max_dimensions(size, size)
+                .build()
+                .into_bytes(),
+        }),
+        QrCodeFormat::Png => {
+            let pixels = code.render::<Luma>().max_dimensions(size, size).build();
+
+            let mut bytes = Vec::new();
+            let mut encoder = png::Encoder::new(&mut bytes, pixels.width, pixels.width);
+            encoder.set_color(png::ColorType::Grayscale);
+            encoder.set_depth(png::BitDepth::Eight);
+            let mut writer = encoder
+                .write_header()
+                .context("failed to write PNG header")?;
+            writer
+                .write_image_data(&pixels.data)
+                .context("failed to write PNG image data")?;
+            writer.finish().context("failed to finish PNG")?;
+
+            Ok(QrCodeImage {
+                content_type: "image/png",
+                bytes,
+            })
+        },
+    }
+}
+
+/// An 8-bit grayscale pixel.
+#[derive(Copy, Clone)]
+struct Luma(u8);
+
+/// A square 8-bit grayscale image, one byte per pixel, row by row.
+struct LumaImage {
+    width: u32,
+    data: Vec<u8>,
+}
+
+struct LumaCanvas {
+    width: u32,
+    dark: u8,
+    data: Vec<u8>,
+}
+
+impl Pixel for Luma {
+    type Canvas = LumaCanvas;
+    type Image = LumaImage;
+
+    fn default_color(color: qrcode::Color) -> Self {
+        Self(color.select(0x00, 0xff))
+    }
+}
+
+impl Canvas for LumaCanvas {
+    type Image = LumaImage;
+    type Pixel = Luma;
+
+    fn new(width: u32, height: u32, dark_pixel: Luma, light_pixel: Luma) -> Self {
+        let len = usize::try_from(u64::from(width).saturating_mul(u64::from(height)))
+            .expect("QR code image size should fit in memory");
+
+        Self {
+            width,
+            dark: dark_pixel.0,
+            data: vec![light_pixel.0; len],
+        }
+    }
+
+    fn draw_dark_pixel(&mut self, x: u32, y: u32) {
+        let index = u64::from(y)
+            .saturating_mul(u64::from(self.width))
+            .saturating_add(u64::from(x));
+        if let Some(pixel) = usize::try_from(index)
+            .ok()
+            .and_then(|index| self.data.get_mut(index))
+        {
+            *pixel = self.dark;
+        }
+    }
+
+    fn into_image(self) -> LumaImage {
+        LumaImage {
+            width: self.width,
+            data: self.data,
+        }
+    }
+}
@@ -0,0 +1,72 @@
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// An in-memory fixed-window rate limiter, keyed by e.g. user ID.
+///
+/// Each key may be used at most `max_requests` times per `window`. The state is
+/// local to this process, so limits are not shared between instances of the
+/// service.
+#[derive(Clone, Debug)]
+pub struct RateLimiter<K> {
+    windows: Arc<Mutex<HashMap<K, Window>>>,
+    max_requests: u32,
+    window: Duration,
+}
+
+#[derive(Copy, Clone, Debug)]
+struct Window {
+    started_at: Instant,
+    count: u32,
+}
+
+/// Number of tracked keys above which expired windows are pruned.
+const PRUNE_THRESHOLD: usize = 1024;
+
+impl<K> RateLimiter<K>
+where
+    K: Eq + Hash,
+{
+    pub fn new(max_requests: u32, window: Duration) -> Self {
+        Self {
+            windows: Arc::new(Mutex::new(HashMap::new())),
+            max_requests,
+            window,
+        }
+    }
+
+    /// Records a request for `key`.
+    ///
+    /// Returns `Err` with the time until the current window resets if `key` has
+    /// exceeded its limit.
+    pub fn check(&self, key: K) -> Result<(), Duration> {
+        let now = Instant::now();
+        let mut windows = self.windows.lock().expect("rate limiter mutex poisoned");
+
+        if windows.len() > PRUNE_THRESHOLD {
+            windows.retain(|_key, window| now.duration_since(window.started_at) < self.window);
+        }
+
+        let window = windows.entry(key).or_insert(Window {
+            started_at: now,
+            count: 0,
+        });
+        let elapsed = now.duration_since(window.started_at);
+        if elapsed >= self.window {
+            *window = Window {
+                started_at: now,
+                count: 0,
+            };
+        }
+
+        if window.count >= self.max_requests {
+            return Err(self
+                .window
+                .saturating_sub(now.duration_since(window.started_at)));
+        }
+        window.count = window.count.saturating_add(1);
+
+        Ok(())
+    }
+}
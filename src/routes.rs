@@ -1,3 +1,61 @@
 pub mod auth;
+pub mod checkout_session;
+pub mod event;
+pub mod exchange_quote;
+pub mod exchange_rate;
+pub mod fee_rule;
+pub mod gift_code;
+pub mod group;
+pub mod hold;
+pub mod interest_accrual;
+pub mod interest_rate;
+pub mod payment_link;
+pub mod payment_request;
+pub mod scheduled_transfer;
 pub mod transaction;
+pub mod treasury;
 pub mod user;
+pub mod webhook_endpoint;
+
+use axum::routing::get;
+use axum::{Router, middleware};
+use axum_extra::vpath;
+use tower::ServiceBuilder;
+
+use crate::middleware::auth::authenticate_with_jwt_access_token;
+use crate::state::{AppState, AuthState};
+
+/// Returns the routes of the service, with authentication required for all
+/// but logging in and signing up.
+pub fn router(state: AppState, auth_state: AuthState) -> Router {
+    Router::new()
+        .nest(vpath!("/users"), user::routes())
+        .nest(vpath!("/transactions"), transaction::routes())
+        .nest(vpath!("/events"), event::routes())
+        .nest(vpath!("/payment-requests"), payment_request::routes())
+        .nest(vpath!("/payment-links"), payment_link::routes())
+        .nest(vpath!("/checkout-sessions"), checkout_session::routes())
+        .nest(vpath!("/scheduled-transfers"), scheduled_transfer::routes())
+        .nest(vpath!("/holds"), hold::routes())
+        .nest(vpath!("/gift-codes"), gift_code::routes())
+        .nest(vpath!("/exchange-rates"), exchange_rate::routes())
+        .nest(vpath!("/exchange-quotes"), exchange_quote::routes())
+        .nest(vpath!("/fee-rules"), fee_rule::routes())
+        .nest(vpath!("/treasury"), treasury::routes())
+        .nest(vpath!("/groups"), group::routes())
+        .nest(vpath!("/interest-rates"), interest_rate::routes())
+        .nest(vpath!("/interest-accruals"), interest_accrual::routes())
+        .nest(vpath!("/webhook-endpoints"), webhook_endpoint::routes())
+        .with_state(state)
+        .layer(
+            // Require authentication for any routes added before this middleware.
+            //
+            // See <https://docs.rs/axum/latest/axum/middleware/index.html#ordering>
+            ServiceBuilder::new().layer(middleware::from_fn_with_state(
+                auth_state.clone(),
+                authenticate_with_jwt_access_token,
+            )),
+        )
+        .route(vpath!("/hello"), get(async || "hello world!"))
+        .nest(vpath!("/auth"), auth::routes().with_state(auth_state))
+}
@@ -0,0 +1,26 @@
+use axum::Router;
+use axum::routing::{get, post};
+use axum_extra::vpath;
+
+use crate::handlers::checkout_session::{
+    get_checkout_session, get_checkout_sessions, post_checkout_session,
+    post_checkout_session_cancel, post_checkout_session_pay,
+};
+use crate::state::AppState;
+
+pub fn routes() -> Router<AppState> {
+    Router::new()
+        .route(
+            vpath!("/"),
+            get(get_checkout_sessions).post(post_checkout_session),
+        )
+        .route(vpath!("/{checkout_session_id}"), get(get_checkout_session))
+        .route(
+            vpath!("/{checkout_session_id}/pay"),
+            post(post_checkout_session_pay),
+        )
+        .route(
+            vpath!("/{checkout_session_id}/cancel"),
+            post(post_checkout_session_cancel),
+        )
+}
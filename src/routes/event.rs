@@ -0,0 +1,10 @@
+use axum::Router;
+use axum::routing::get;
+use axum_extra::vpath;
+
+use crate::handlers::event::get_events;
+use crate::state::AppState;
+
+pub fn routes() -> Router<AppState> {
+    Router::new().route(vpath!("/"), get(get_events))
+}
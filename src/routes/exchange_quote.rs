@@ -0,0 +1,12 @@
+use axum::Router;
+use axum::routing::{get, post};
+use axum_extra::vpath;
+
+use crate::handlers::exchange::{get_exchange_quote, post_exchange_quote};
+use crate::state::AppState;
+
+pub fn routes() -> Router<AppState> {
+    Router::new()
+        .route(vpath!("/"), post(post_exchange_quote))
+        .route(vpath!("/{quote_id}"), get(get_exchange_quote))
+}
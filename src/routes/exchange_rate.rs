@@ -0,0 +1,18 @@
+use axum::Router;
+use axum::routing::{get, post, put};
+use axum_extra::vpath;
+
+use crate::handlers::exchange::{
+    delete_exchange_rate, get_exchange_rates, post_exchange_rates_import, put_exchange_rate,
+};
+use crate::state::AppState;
+
+pub fn routes() -> Router<AppState> {
+    Router::new()
+        .route(vpath!("/"), get(get_exchange_rates))
+        .route(vpath!("/import"), post(post_exchange_rates_import))
+        .route(
+            vpath!("/{source_currency}/{target_currency}"),
+            put(put_exchange_rate).delete(delete_exchange_rate),
+        )
+}
@@ -0,0 +1,15 @@
+use axum::Router;
+use axum::routing::{get, put};
+use axum_extra::vpath;
+
+use crate::handlers::fee_rule::{delete_fee_rule, get_fee_rules, post_fee_rule, put_fee_rule};
+use crate::state::AppState;
+
+pub fn routes() -> Router<AppState> {
+    Router::new()
+        .route(vpath!("/"), get(get_fee_rules).post(post_fee_rule))
+        .route(
+            vpath!("/{fee_rule_id}"),
+            put(put_fee_rule).delete(delete_fee_rule),
+        )
+}
@@ -0,0 +1,19 @@
+use axum::Router;
+use axum::routing::{get, post};
+use axum_extra::vpath;
+
+use crate::handlers::gift_code::{
+    get_gift_code, get_gift_codes, post_gift_code, post_gift_code_cancel, post_gift_code_redemption,
+};
+use crate::state::AppState;
+
+pub fn routes() -> Router<AppState> {
+    Router::new()
+        .route(vpath!("/"), get(get_gift_codes).post(post_gift_code))
+        .route(vpath!("/redeem"), post(post_gift_code_redemption))
+        .route(vpath!("/{gift_code_id}"), get(get_gift_code))
+        .route(
+            vpath!("/{gift_code_id}/cancel"),
+            post(post_gift_code_cancel),
+        )
+}
@@ -0,0 +1,22 @@
+use axum::Router;
+use axum::routing::{get, post};
+use axum_extra::vpath;
+
+use crate::handlers::group::{
+    get_group, get_group_balances, get_group_expenses, get_groups, post_group, post_group_expense,
+    post_group_member, post_group_settle_up,
+};
+use crate::state::AppState;
+
+pub fn routes() -> Router<AppState> {
+    Router::new()
+        .route(vpath!("/"), get(get_groups).post(post_group))
+        .route(vpath!("/{group_id}"), get(get_group))
+        .route(vpath!("/{group_id}/balances"), get(get_group_balances))
+        .route(
+            vpath!("/{group_id}/expenses"),
+            get(get_group_expenses).post(post_group_expense),
+        )
+        .route(vpath!("/{group_id}/members"), post(post_group_member))
+        .route(vpath!("/{group_id}/settle-up"), post(post_group_settle_up))
+}
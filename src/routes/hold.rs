@@ -0,0 +1,14 @@
+use axum::Router;
+use axum::routing::{get, post};
+use axum_extra::vpath;
+
+use crate::handlers::hold::{get_hold, get_holds, post_hold, post_hold_capture, post_hold_void};
+use crate::state::AppState;
+
+pub fn routes() -> Router<AppState> {
+    Router::new()
+        .route(vpath!("/"), get(get_holds).post(post_hold))
+        .route(vpath!("/{hold_id}"), get(get_hold))
+        .route(vpath!("/{hold_id}/capture"), post(post_hold_capture))
+        .route(vpath!("/{hold_id}/void"), post(post_hold_void))
+}
@@ -0,0 +1,10 @@
+use axum::Router;
+use axum::routing::get;
+use axum_extra::vpath;
+
+use crate::handlers::interest::get_interest_accrual_preview;
+use crate::state::AppState;
+
+pub fn routes() -> Router<AppState> {
+    Router::new().route(vpath!("/preview"), get(get_interest_accrual_preview))
+}
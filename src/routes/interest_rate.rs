@@ -0,0 +1,15 @@
+use axum::Router;
+use axum::routing::{get, put};
+use axum_extra::vpath;
+
+use crate::handlers::interest::{delete_interest_rate, get_interest_rates, put_interest_rate};
+use crate::state::AppState;
+
+pub fn routes() -> Router<AppState> {
+    Router::new()
+        .route(vpath!("/"), get(get_interest_rates))
+        .route(
+            vpath!("/{product}/{currency}"),
+            put(put_interest_rate).delete(delete_interest_rate),
+        )
+}
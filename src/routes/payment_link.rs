@@ -0,0 +1,12 @@
+use axum::Router;
+use axum::routing::post;
+use axum_extra::vpath;
+
+use crate::handlers::payment_link::{post_payment_link, post_payment_link_verify};
+use crate::state::AppState;
+
+pub fn routes() -> Router<AppState> {
+    Router::new()
+        .route(vpath!("/"), post(post_payment_link))
+        .route(vpath!("/verify"), post(post_payment_link_verify))
+}
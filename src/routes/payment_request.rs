@@ -0,0 +1,34 @@
+use axum::Router;
+use axum::routing::{get, post};
+use axum_extra::vpath;
+
+use crate::handlers::payment_request::{
+    get_incoming_payment_requests, get_outgoing_payment_requests, get_payment_request,
+    get_payment_request_qr_code, post_payment_request, post_payment_request_accept,
+    post_payment_request_cancel, post_payment_request_decline,
+};
+use crate::state::AppState;
+
+pub fn routes() -> Router<AppState> {
+    Router::new()
+        .route(vpath!("/"), post(post_payment_request))
+        .route(vpath!("/incoming"), get(get_incoming_payment_requests))
+        .route(vpath!("/outgoing"), get(get_outgoing_payment_requests))
+        .route(vpath!("/{payment_request_id}"), get(get_payment_request))
+        .route(
+            vpath!("/{payment_request_id}/qr-code"),
+            get(get_payment_request_qr_code),
+        )
+        .route(
+            vpath!("/{payment_request_id}/accept"),
+            post(post_payment_request_accept),
+        )
+        .route(
+            vpath!("/{payment_request_id}/decline"),
+            post(post_payment_request_decline),
+        )
+        .route(
+            vpath!("/{payment_request_id}/cancel"),
+            post(post_payment_request_cancel),
+        )
+}
@@ -0,0 +1,38 @@
+use axum::Router;
+use axum::routing::{get, post};
+use axum_extra::vpath;
+
+use crate::handlers::scheduled_transfer::{
+    get_scheduled_transfer, get_scheduled_transfer_attempts, get_scheduled_transfers,
+    post_scheduled_transfer, post_scheduled_transfer_cancel, post_scheduled_transfer_pause,
+    post_scheduled_transfer_resume,
+};
+use crate::state::AppState;
+
+pub fn routes() -> Router<AppState> {
+    Router::new()
+        .route(
+            vpath!("/"),
+            get(get_scheduled_transfers).post(post_scheduled_transfer),
+        )
+        .route(
+            vpath!("/{scheduled_transfer_id}"),
+            get(get_scheduled_transfer),
+        )
+        .route(
+            vpath!("/{scheduled_transfer_id}/attempts"),
+            get(get_scheduled_transfer_attempts),
+        )
+        .route(
+            vpath!("/{scheduled_transfer_id}/pause"),
+            post(post_scheduled_transfer_pause),
+        )
+        .route(
+            vpath!("/{scheduled_transfer_id}/resume"),
+            post(post_scheduled_transfer_resume),
+        )
+        .route(
+            vpath!("/{scheduled_transfer_id}/cancel"),
+            post(post_scheduled_transfer_cancel),
+        )
+}
@@ -1,10 +1,26 @@
 use axum::Router;
-use axum::routing::post;
+use axum::routing::{get, post};
 use axum_extra::vpath;
 
-use crate::handlers::transaction::post_transaction;
+use crate::handlers::transaction::{
+    get_transaction, get_transaction_batch, get_transaction_fee, post_transaction,
+    post_transaction_batch, post_transaction_refund, post_transaction_reversal,
+};
 use crate::state::AppState;
 
 pub fn routes() -> Router<AppState> {
-    Router::new().route(vpath!("/"), post(post_transaction))
+    Router::new()
+        .route(vpath!("/"), post(post_transaction))
+        .route(vpath!("/batch"), post(post_transaction_batch))
+        .route(vpath!("/batch/{batch_id}"), get(get_transaction_batch))
+        .route(vpath!("/fee"), get(get_transaction_fee))
+        .route(vpath!("/{transaction_id}"), get(get_transaction))
+        .route(
+            vpath!("/{transaction_id}/refund"),
+            post(post_transaction_refund),
+        )
+        .route(
+            vpath!("/{transaction_id}/reversal"),
+            post(post_transaction_reversal),
+        )
 }
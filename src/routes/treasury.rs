@@ -0,0 +1,13 @@
+use axum::Router;
+use axum::routing::{get, post};
+use axum_extra::vpath;
+
+use crate::handlers::treasury::{get_treasury, post_burn, post_mint};
+use crate::state::AppState;
+
+pub fn routes() -> Router<AppState> {
+    Router::new()
+        .route(vpath!("/"), get(get_treasury))
+        .route(vpath!("/burn"), post(post_burn))
+        .route(vpath!("/mint"), post(post_mint))
+}
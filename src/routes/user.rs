@@ -1,12 +1,73 @@
 use axum::Router;
-use axum::routing::get;
+use axum::routing::{get, post, put};
 use axum_extra::vpath;
 
-use crate::handlers::user::{get_transactions, get_user};
+use crate::handlers::account::{get_accounts, post_account};
+use crate::handlers::notification::{
+    get_notification_preferences, get_notifications, get_unread_count, post_notification_read,
+    post_notifications_read, put_notification_preferences,
+};
+use crate::handlers::pocket::{
+    delete_pocket, get_pocket, get_pockets, post_pocket, post_pocket_deposit,
+    post_pocket_withdrawal, put_pocket,
+};
+use crate::handlers::transfer_limit::{
+    delete_transfer_limits, get_transfer_limits, put_transfer_limits,
+};
+use crate::handlers::user::{
+    get_transactions, get_user, get_user_lookup, get_user_qr_code, put_user_kind, put_user_tier,
+};
 use crate::state::AppState;
 
 pub fn routes() -> Router<AppState> {
     Router::new()
+        .route(vpath!("/lookup"), get(get_user_lookup))
         .route(vpath!("/{user_id}"), get(get_user))
+        .route(
+            vpath!("/{user_id}/accounts"),
+            get(get_accounts).post(post_account),
+        )
+        .route(vpath!("/{user_id}/kind"), put(put_user_kind))
+        .route(
+            vpath!("/{user_id}/limits"),
+            get(get_transfer_limits)
+                .put(put_transfer_limits)
+                .delete(delete_transfer_limits),
+        )
+        .route(vpath!("/{user_id}/notifications"), get(get_notifications))
+        .route(
+            vpath!("/{user_id}/notifications/preferences"),
+            get(get_notification_preferences).put(put_notification_preferences),
+        )
+        .route(
+            vpath!("/{user_id}/notifications/read"),
+            post(post_notifications_read),
+        )
+        .route(
+            vpath!("/{user_id}/notifications/unread-count"),
+            get(get_unread_count),
+        )
+        .route(
+            vpath!("/{user_id}/notifications/{notification_id}/read"),
+            post(post_notification_read),
+        )
+        .route(
+            vpath!("/{user_id}/pockets"),
+            get(get_pockets).post(post_pocket),
+        )
+        .route(
+            vpath!("/{user_id}/pockets/{pocket_id}"),
+            get(get_pocket).put(put_pocket).delete(delete_pocket),
+        )
+        .route(
+            vpath!("/{user_id}/pockets/{pocket_id}/deposit"),
+            post(post_pocket_deposit),
+        )
+        .route(
+            vpath!("/{user_id}/pockets/{pocket_id}/withdrawal"),
+            post(post_pocket_withdrawal),
+        )
+        .route(vpath!("/{user_id}/qr-code"), get(get_user_qr_code))
+        .route(vpath!("/{user_id}/tier"), put(put_user_tier))
         .route(vpath!("/{user_id}/transactions"), get(get_transactions))
 }
@@ -0,0 +1,29 @@
+use axum::Router;
+use axum::routing::{get, post};
+use axum_extra::vpath;
+
+use crate::handlers::webhook_endpoint::{
+    delete_webhook_endpoint, get_webhook_deliveries, get_webhook_endpoint, get_webhook_endpoints,
+    post_webhook_delivery_replay, post_webhook_endpoint,
+};
+use crate::state::AppState;
+
+pub fn routes() -> Router<AppState> {
+    Router::new()
+        .route(
+            vpath!("/"),
+            get(get_webhook_endpoints).post(post_webhook_endpoint),
+        )
+        .route(
+            vpath!("/{webhook_endpoint_id}"),
+            get(get_webhook_endpoint).delete(delete_webhook_endpoint),
+        )
+        .route(
+            vpath!("/{webhook_endpoint_id}/deliveries"),
+            get(get_webhook_deliveries),
+        )
+        .route(
+            vpath!("/{webhook_endpoint_id}/deliveries/{delivery_id}/replay"),
+            post(post_webhook_delivery_replay),
+        )
+}
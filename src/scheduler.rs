@@ -0,0 +1,201 @@
+//! A background worker which runs scheduled transfers when they are due.
+
+use std::slice;
+use std::time::Duration;
+
+use anyhow::Context as _;
+use diesel::prelude::*;
+#[allow(
+    clippy::unused_trait_names,
+    reason = "error[E0034]: multiple applicable items in scope"
+)]
+use diesel_async::RunQueryDsl;
+use tracing::{debug, error, info};
+use uuid::Uuid;
+
+use crate::event_hub::{EventHub, publish_transactions};
+use crate::ledger::{self, NewTransfer, TransferError};
+use crate::limits::TransferLimits;
+use crate::models::scheduled_transfer::{
+    NewScheduledTransferAttempt, ScheduledTransferAttemptOutcome, ScheduledTransferStatus,
+};
+use crate::models::{ScheduledTransfer, Transaction};
+use crate::state::{DbConnection, DbConnectionPool};
+
+/// How often to look for scheduled transfers which are due.
+pub const POLL_INTERVAL: Duration = Duration::from_secs(10);
+
+/// How long to wait before retrying an occurrence which failed because the
+/// sender's balance was insufficient, or a transfer limit was hit.
+pub const RETRY_DELAY: jiff::SignedDuration = jiff::SignedDuration::from_hours(1);
+
+/// How many times to retry an occurrence which failed because the sender's
+/// balance was insufficient, or a transfer limit was hit, before skipping it.
+pub const MAX_RETRIES: i32 = 3;
+
+/// Runs scheduled transfers when they are due, forever.
+pub async fn run_scheduled_transfers(
+    pool: DbConnectionPool,
+    default_limits: TransferLimits,
+    event_hub: EventHub,
+) {
+    let mut interval = tokio::time::interval(POLL_INTERVAL);
+    interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+
+    loop {
+        interval.tick().await;
+
+        if let Err(err) =
+            run_due_scheduled_transfers(&pool, &default_limits, &event_hub, jiff::Timestamp::now())
+                .await
+        {
+            error!(?err, "failed to run scheduled transfers");
+        }
+    }
+}
+
+/// Runs every active scheduled transfer which is due at `now`, and publishes the
+/// transactions to `event_hub`.
+pub async fn run_due_scheduled_transfers(
+    pool: &DbConnectionPool,
+    default_limits: &TransferLimits,
+    event_hub: &EventHub,
+    now: jiff::Timestamp,
+) -> anyhow::Result<()> {
+    use crate::models::types;
+    use crate::schema::scheduled_transfers;
+
+    let mut conn = pool
+        .get()
+        .await
+        .context("failed to get database connection")?;
+
+    let scheduled_transfers: Vec<ScheduledTransfer> = scheduled_transfers::table
+        .filter(scheduled_transfers::status.eq(ScheduledTransferStatus::Active))
+        .filter(scheduled_transfers::next_run_at.le(types::Timestamp::from(now)))
+        .select(ScheduledTransfer::as_select())
+        .load(&mut conn)
+        .await
+        .context("failed to query scheduled transfers")?;
+
+    for scheduled_transfer in scheduled_transfers {
+        match run_scheduled_transfer(&mut conn, scheduled_transfer.id, default_limits, now).await {
+            Ok(Some(transaction)) => {
+                publish_transactions(event_hub, &mut conn, slice::from_ref(&transaction)).await;
+            },
+            Ok(None) => {},
+            Err(err) => error!(?err, %scheduled_transfer.id, "failed to run scheduled transfer"),
+        }
+    }
+
+    Ok(())
+}
+
+/// Runs the next occurrence of a scheduled transfer, and records the attempt.
+/// Returns the transaction, if the transfer was made.
+///
+/// The scheduled transfer is loaded again within the database transaction, so
+/// that it is not run after it has been paused or cancelled in the meantime.
+async fn run_scheduled_transfer(
+    conn: &mut DbConnection,
+    scheduled_transfer_id: Uuid,
+    default_limits: &TransferLimits,
+    now: jiff::Timestamp,
+) -> anyhow::Result<Option<Transaction>> {
+    use crate::models::types;
+    use crate::schema::{scheduled_transfer_attempts, scheduled_transfers};
+
+    ledger::run_immediate_transaction(conn, |conn| {
+        Box::pin(async move {
+            let mut scheduled_transfer: ScheduledTransfer = scheduled_transfers::table
+                .find(types::Uuid::from(scheduled_transfer_id))
+                .select(ScheduledTransfer::as_select())
+                .first(conn)
+                .await
+                .context("failed to query scheduled transfers")?;
+            if scheduled_transfer.status != ScheduledTransferStatus::Active
+                || scheduled_transfer.next_run_at > now
+            {
+                return Ok(None);
+            }
+
+            let new_transfer = NewTransfer {
+                amount: scheduled_transfer.amount.clone(),
+                currency: scheduled_transfer.currency,
+                recipient: scheduled_transfer.recipient,
+                sender: scheduled_transfer.sender,
+                memo: scheduled_transfer.memo.clone(),
+                reference: scheduled_transfer.reference.clone(),
+                quote_id: None,
+            };
+            let result = ledger::apply_transfer(conn, &new_transfer, default_limits).await?;
+
+            let new_attempt = NewScheduledTransferAttempt {
+                id: Uuid::now_v7(),
+                scheduled_transfer_id,
+                scheduled_for: scheduled_transfer.scheduled_for(),
+                attempted_at: jiff::Timestamp::now(),
+                outcome: match result {
+                    Ok(_) => ScheduledTransferAttemptOutcome::Succeeded,
+                    Err(_) => ScheduledTransferAttemptOutcome::Failed,
+                },
+                error: result.as_ref().err().map(|err| err.title().to_owned()),
+                transaction_id: result.as_ref().ok().map(|transaction| transaction.id),
+            };
+            diesel::insert_into(scheduled_transfer_attempts::table)
+                .values(new_attempt)
+                .execute(conn)
+                .await
+                .context("failed to insert scheduled transfer attempt")?;
+
+            match &result {
+                Ok(transaction) => {
+                    info!(%scheduled_transfer_id, %transaction.id, "ran scheduled transfer");
+
+                    scheduled_transfer.advance(now);
+                },
+                Err(err) if is_retryable(err) && scheduled_transfer.retries < MAX_RETRIES => {
+                    debug!(%scheduled_transfer_id, ?err, "retrying scheduled transfer later");
+
+                    scheduled_transfer.retries = scheduled_transfer.retries.saturating_add(1);
+                    scheduled_transfer.next_run_at = now
+                        .checked_add(RETRY_DELAY)
+                        .context("scheduled transfer retry out of range")?;
+                },
+                Err(err) => {
+                    info!(%scheduled_transfer_id, ?err, "skipping scheduled transfer occurrence");
+
+                    // Move on to the next occurrence, but mark the scheduled
+                    // transfer as failed if it was the last one, or if trying
+                    // again would be pointless.
+                    scheduled_transfer.advance(now);
+                    if scheduled_transfer.status == ScheduledTransferStatus::Completed
+                        || !is_retryable(err)
+                    {
+                        scheduled_transfer.status = ScheduledTransferStatus::Failed;
+                    }
+                },
+            }
+
+            diesel::update(
+                scheduled_transfers::table.find(types::Uuid::from(scheduled_transfer_id)),
+            )
+            .set(scheduled_transfer)
+            .execute(conn)
+            .await
+            .context("failed to update scheduled transfer")?;
+
+            Ok(result.ok())
+        })
+    })
+    .await
+}
+
+/// Whether a transfer which failed with `err` may succeed later.
+fn is_retryable(err: &TransferError) -> bool {
+    match err {
+        TransferError::InsufficientBalance => true,
+        TransferError::LimitExceeded(limit_exceeded) => limit_exceeded.resets_at.is_some(),
+        _ => false,
+    }
+}
@@ -1,5 +1,285 @@
 // @generated automatically by Diesel CLI.
 
+diesel::table! {
+    accounts (user_id, currency) {
+        user_id -> Binary,
+        currency -> Text,
+        balance -> Text,
+        created_at -> TimestamptzSqlite,
+    }
+}
+
+diesel::table! {
+    checkout_sessions (id) {
+        id -> Binary,
+        merchant_id -> Binary,
+        amount -> Text,
+        currency -> Text,
+        reference -> Text,
+        memo -> Nullable<Text>,
+        success_url -> Text,
+        cancel_url -> Text,
+        status -> Text,
+        created_at -> TimestamptzSqlite,
+        expires_at -> TimestamptzSqlite,
+        completed_at -> Nullable<TimestamptzSqlite>,
+        payer -> Nullable<Binary>,
+        transaction_id -> Nullable<Binary>,
+    }
+}
+
+diesel::table! {
+    exchange_quotes (id) {
+        id -> Binary,
+        user_id -> Binary,
+        source_currency -> Text,
+        source_amount -> Text,
+        target_currency -> Text,
+        target_amount -> Text,
+        rate -> Text,
+        spread -> Text,
+        created_at -> TimestamptzSqlite,
+        expires_at -> TimestamptzSqlite,
+        transaction_id -> Nullable<Binary>,
+    }
+}
+
+diesel::table! {
+    exchange_rates (source_currency, target_currency) {
+        source_currency -> Text,
+        target_currency -> Text,
+        rate -> Text,
+        spread -> Text,
+        updated_at -> TimestamptzSqlite,
+    }
+}
+
+diesel::table! {
+    fee_rules (id) {
+        id -> Binary,
+        currency -> Nullable<Text>,
+        tier -> Nullable<Text>,
+        min_amount -> Nullable<Text>,
+        max_amount -> Nullable<Text>,
+        flat -> Text,
+        percentage -> Text,
+        min_fee -> Nullable<Text>,
+        max_fee -> Nullable<Text>,
+        priority -> Integer,
+        created_at -> TimestamptzSqlite,
+    }
+}
+
+diesel::table! {
+    gift_code_redemptions (gift_code_id, user_id) {
+        gift_code_id -> Binary,
+        user_id -> Binary,
+        transaction_id -> Binary,
+        redeemed_at -> TimestamptzSqlite,
+    }
+}
+
+diesel::table! {
+    gift_codes (id) {
+        id -> Binary,
+        created_by -> Binary,
+        funded_by -> Binary,
+        code_hash -> Binary,
+        amount -> Text,
+        currency -> Text,
+        max_redemptions -> Integer,
+        redemption_count -> Integer,
+        memo -> Nullable<Text>,
+        status -> Text,
+        created_at -> TimestamptzSqlite,
+        expires_at -> TimestamptzSqlite,
+        funding_transaction_id -> Binary,
+        refund_transaction_id -> Nullable<Binary>,
+    }
+}
+
+diesel::table! {
+    group_expense_shares (expense_id, user_id) {
+        expense_id -> Binary,
+        user_id -> Binary,
+        amount -> Text,
+    }
+}
+
+diesel::table! {
+    group_expenses (id) {
+        id -> Binary,
+        group_id -> Binary,
+        payer -> Binary,
+        amount -> Text,
+        description -> Text,
+        created_at -> TimestamptzSqlite,
+    }
+}
+
+diesel::table! {
+    group_members (group_id, user_id) {
+        group_id -> Binary,
+        user_id -> Binary,
+        joined_at -> TimestamptzSqlite,
+    }
+}
+
+diesel::table! {
+    group_settlements (group_id, transaction_id) {
+        group_id -> Binary,
+        transaction_id -> Binary,
+    }
+}
+
+diesel::table! {
+    groups (id) {
+        id -> Binary,
+        name -> Text,
+        currency -> Text,
+        created_by -> Binary,
+        created_at -> TimestamptzSqlite,
+    }
+}
+
+diesel::table! {
+    holds (id) {
+        id -> Binary,
+        sender -> Binary,
+        recipient -> Binary,
+        amount -> Text,
+        memo -> Nullable<Text>,
+        reference -> Nullable<Text>,
+        status -> Text,
+        created_at -> TimestamptzSqlite,
+        expires_at -> TimestamptzSqlite,
+        transaction_id -> Nullable<Binary>,
+        currency -> Text,
+    }
+}
+
+diesel::table! {
+    interest_accruals (product, holder_id, currency, accrued_on) {
+        product -> Text,
+        holder_id -> Binary,
+        currency -> Text,
+        accrued_on -> Date,
+        user_id -> Binary,
+        balance -> Text,
+        annual_rate -> Text,
+        amount -> Text,
+        created_at -> TimestamptzSqlite,
+        transaction_id -> Nullable<Binary>,
+    }
+}
+
+diesel::table! {
+    interest_carryovers (product, holder_id, currency) {
+        product -> Text,
+        holder_id -> Binary,
+        currency -> Text,
+        amount -> Text,
+        updated_at -> TimestamptzSqlite,
+    }
+}
+
+diesel::table! {
+    interest_rates (product, currency) {
+        product -> Text,
+        currency -> Text,
+        annual_rate -> Text,
+        updated_at -> TimestamptzSqlite,
+    }
+}
+
+diesel::table! {
+    known_devices (user_id, fingerprint) {
+        user_id -> Binary,
+        fingerprint -> Binary,
+        first_seen_at -> TimestamptzSqlite,
+        last_seen_at -> TimestamptzSqlite,
+    }
+}
+
+diesel::table! {
+    notification_preferences (user_id, kind) {
+        user_id -> Binary,
+        kind -> Text,
+        enabled -> Bool,
+    }
+}
+
+diesel::table! {
+    notifications (id) {
+        id -> Binary,
+        user_id -> Binary,
+        kind -> Text,
+        data -> Text,
+        created_at -> TimestamptzSqlite,
+        read_at -> Nullable<TimestamptzSqlite>,
+    }
+}
+
+diesel::table! {
+    payment_requests (id) {
+        id -> Binary,
+        requester -> Binary,
+        payer -> Nullable<Binary>,
+        amount -> Text,
+        memo -> Nullable<Text>,
+        status -> Text,
+        created_at -> TimestamptzSqlite,
+        expires_at -> TimestamptzSqlite,
+        transaction_id -> Nullable<Binary>,
+        currency -> Text,
+    }
+}
+
+diesel::table! {
+    pockets (id) {
+        id -> Binary,
+        user_id -> Binary,
+        name -> Text,
+        currency -> Text,
+        balance -> Text,
+        target_amount -> Nullable<Text>,
+        target_date -> Nullable<Date>,
+        created_at -> TimestamptzSqlite,
+        archived_at -> Nullable<TimestamptzSqlite>,
+    }
+}
+
+diesel::table! {
+    scheduled_transfer_attempts (id) {
+        id -> Binary,
+        scheduled_transfer_id -> Binary,
+        scheduled_for -> TimestamptzSqlite,
+        attempted_at -> TimestamptzSqlite,
+        outcome -> Text,
+        error -> Nullable<Text>,
+        transaction_id -> Nullable<Binary>,
+    }
+}
+
+diesel::table! {
+    scheduled_transfers (id) {
+        id -> Binary,
+        sender -> Binary,
+        recipient -> Binary,
+        amount -> Text,
+        memo -> Nullable<Text>,
+        reference -> Nullable<Text>,
+        recurrence -> Nullable<Text>,
+        starts_at -> TimestamptzSqlite,
+        occurrence -> Integer,
+        next_run_at -> TimestamptzSqlite,
+        retries -> Integer,
+        status -> Text,
+        created_at -> TimestamptzSqlite,
+        currency -> Text,
+    }
+}
+
 diesel::table! {
     transactions (id) {
         id -> Binary,
@@ -7,6 +287,51 @@ diesel::table! {
         recipient -> Binary,
         sender -> Binary,
         timestamp -> TimestamptzSqlite,
+        memo -> Nullable<Text>,
+        reference -> Nullable<Text>,
+        kind -> Text,
+        original_transaction_id -> Nullable<Binary>,
+        flagged -> Bool,
+        currency -> Text,
+        converted_amount -> Nullable<Text>,
+        converted_currency -> Nullable<Text>,
+        exchange_rate -> Nullable<Text>,
+        spread -> Nullable<Text>,
+        fee -> Nullable<Text>,
+        initiator -> Nullable<Binary>,
+        pocket_id -> Nullable<Binary>,
+    }
+}
+
+diesel::table! {
+    transfer_batch_items (batch_id, position) {
+        batch_id -> Binary,
+        position -> Integer,
+        transaction_id -> Binary,
+    }
+}
+
+diesel::table! {
+    transfer_batches (id) {
+        id -> Binary,
+        sender -> Binary,
+        currency -> Text,
+        created_at -> TimestamptzSqlite,
+    }
+}
+
+diesel::table! {
+    transfer_limits (user_id) {
+        user_id -> Binary,
+        per_transaction -> Nullable<Text>,
+        daily -> Nullable<Text>,
+        monthly -> Nullable<Text>,
+        max_transfers -> Nullable<Integer>,
+        updated_at -> TimestamptzSqlite,
+        per_transaction_removed -> Bool,
+        daily_removed -> Bool,
+        monthly_removed -> Bool,
+        max_transfers_removed -> Bool,
     }
 }
 
@@ -15,8 +340,112 @@ diesel::table! {
         id -> Binary,
         username -> Text,
         password_hash -> Text,
-        balance -> Text,
+        role -> Text,
+        tier -> Text,
+        kind -> Text,
+    }
+}
+
+diesel::table! {
+    webhook_deliveries (id) {
+        id -> Binary,
+        endpoint_id -> Binary,
+        event_id -> Binary,
+        event_type -> Text,
+        payload -> Text,
+        status -> Text,
+        attempts -> Integer,
+        next_attempt_at -> TimestamptzSqlite,
+        last_attempt_at -> Nullable<TimestamptzSqlite>,
+        last_response_status -> Nullable<Integer>,
+        last_error -> Nullable<Text>,
+        created_at -> TimestamptzSqlite,
+        delivered_at -> Nullable<TimestamptzSqlite>,
+    }
+}
+
+diesel::table! {
+    webhook_endpoints (id) {
+        id -> Binary,
+        user_id -> Binary,
+        url -> Text,
+        secret -> Text,
+        all_users -> Bool,
+        created_at -> TimestamptzSqlite,
     }
 }
 
-diesel::allow_tables_to_appear_in_same_query!(transactions, users,);
+diesel::table! {
+    webhook_subscriptions (endpoint_id, event_type) {
+        endpoint_id -> Binary,
+        event_type -> Text,
+    }
+}
+
+diesel::joinable!(accounts -> users (user_id));
+diesel::joinable!(checkout_sessions -> transactions (transaction_id));
+diesel::joinable!(exchange_quotes -> transactions (transaction_id));
+diesel::joinable!(exchange_quotes -> users (user_id));
+diesel::joinable!(gift_code_redemptions -> gift_codes (gift_code_id));
+diesel::joinable!(gift_code_redemptions -> transactions (transaction_id));
+diesel::joinable!(gift_code_redemptions -> users (user_id));
+diesel::joinable!(group_expense_shares -> group_expenses (expense_id));
+diesel::joinable!(group_expense_shares -> users (user_id));
+diesel::joinable!(group_expenses -> groups (group_id));
+diesel::joinable!(group_expenses -> users (payer));
+diesel::joinable!(group_members -> groups (group_id));
+diesel::joinable!(group_members -> users (user_id));
+diesel::joinable!(group_settlements -> groups (group_id));
+diesel::joinable!(group_settlements -> transactions (transaction_id));
+diesel::joinable!(groups -> users (created_by));
+diesel::joinable!(holds -> transactions (transaction_id));
+diesel::joinable!(interest_accruals -> transactions (transaction_id));
+diesel::joinable!(interest_accruals -> users (user_id));
+diesel::joinable!(known_devices -> users (user_id));
+diesel::joinable!(notification_preferences -> users (user_id));
+diesel::joinable!(notifications -> users (user_id));
+diesel::joinable!(payment_requests -> transactions (transaction_id));
+diesel::joinable!(scheduled_transfer_attempts -> scheduled_transfers (scheduled_transfer_id));
+diesel::joinable!(scheduled_transfer_attempts -> transactions (transaction_id));
+diesel::joinable!(transactions -> pockets (pocket_id));
+diesel::joinable!(transfer_batch_items -> transactions (transaction_id));
+diesel::joinable!(transfer_batch_items -> transfer_batches (batch_id));
+diesel::joinable!(transfer_batches -> users (sender));
+diesel::joinable!(transfer_limits -> users (user_id));
+diesel::joinable!(webhook_deliveries -> webhook_endpoints (endpoint_id));
+diesel::joinable!(webhook_endpoints -> users (user_id));
+diesel::joinable!(webhook_subscriptions -> webhook_endpoints (endpoint_id));
+
+diesel::allow_tables_to_appear_in_same_query!(
+    accounts,
+    checkout_sessions,
+    exchange_quotes,
+    exchange_rates,
+    fee_rules,
+    gift_code_redemptions,
+    gift_codes,
+    group_expense_shares,
+    group_expenses,
+    group_members,
+    group_settlements,
+    groups,
+    holds,
+    interest_accruals,
+    interest_carryovers,
+    interest_rates,
+    known_devices,
+    notification_preferences,
+    notifications,
+    payment_requests,
+    pockets,
+    scheduled_transfer_attempts,
+    scheduled_transfers,
+    transactions,
+    transfer_batch_items,
+    transfer_batches,
+    transfer_limits,
+    users,
+    webhook_deliveries,
+    webhook_endpoints,
+    webhook_subscriptions,
+);
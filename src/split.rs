@@ -0,0 +1,410 @@
+//! Splitting the expenses of groups between their members, and settling up
+//! what members owe each other.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use anyhow::Context as _;
+use axum::Json;
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use bigdecimal::num_bigint::BigInt;
+use bigdecimal::rounding::RoundingMode;
+use bigdecimal::{BigDecimal, Zero as _};
+use diesel::prelude::*;
+#[allow(
+    clippy::unused_trait_names,
+    reason = "error[E0034]: multiple applicable items in scope"
+)]
+use diesel_async::RunQueryDsl;
+use serde_json::json;
+use uuid::Uuid;
+
+use crate::currency::Currency;
+use crate::ledger::{self, BatchError, NewBatchTransfer, NewTransferBatch};
+use crate::limits::TransferLimits;
+use crate::models::group::{GroupExpense, GroupExpenseShare, GroupSettlement};
+use crate::models::{Group, Transaction};
+use crate::state::DbConnection;
+
+/// How an expense is split between members.
+#[derive(Clone, Debug)]
+pub enum Split {
+    /// Equally between these members.
+    Equal(Vec<Uuid>),
+    /// In proportion to the number of shares of each member.
+    Shares(Vec<(Uuid, u32)>),
+    /// Into exact amounts, which must add up to the expense.
+    Exact(Vec<(Uuid, BigDecimal)>),
+}
+
+/// Reasons an expense can't be split.
+#[derive(Clone, Debug)]
+pub enum SplitError {
+    InvalidAmount,
+    /// The amount is more precise than the minor unit of the currency.
+    AmountTooPrecise(Currency),
+    NoMembers,
+    DuplicateMember(Uuid),
+    NotMember(Uuid),
+    /// The shares add up to zero.
+    NoShares,
+    /// The exact amounts don't add up to the expense.
+    AmountMismatch {
+        total: BigDecimal,
+    },
+}
+
+impl SplitError {
+    /// The title of the error response.
+    pub fn title(&self) -> &'static str {
+        match self {
+            Self::InvalidAmount | Self::AmountTooPrecise(_) => "InvalidAmount",
+            Self::NoMembers
+            | Self::DuplicateMember(_)
+            | Self::NotMember(_)
+            | Self::NoShares
+            | Self::AmountMismatch { .. } => "InvalidSplit",
+        }
+    }
+}
+
+impl IntoResponse for SplitError {
+    fn into_response(self) -> Response {
+        let detail = match &self {
+            Self::InvalidAmount => None,
+            Self::AmountTooPrecise(currency) => Some(format!(
+                "{currency} amounts may have at most {} decimal places",
+                currency.minor_units()
+            )),
+            Self::NoMembers => {
+                Some("an expense must be split between at least one member".to_owned())
+            },
+            Self::DuplicateMember(user_id) => Some(format!("{user_id} is listed more than once")),
+            Self::NotMember(user_id) => Some(format!("{user_id} is not a member of the group")),
+            Self::NoShares => Some("shares must add up to more than 0".to_owned()),
+            Self::AmountMismatch { total } => Some(format!(
+                "amounts add up to {total} rather than the amount of the expense"
+            )),
+        };
+
+        let body = match detail {
+            Some(detail) => json!({
+                "title": self.title(),
+                "detail": detail,
+            }),
+            None => json!({
+                "title": self.title(),
+            }),
+        };
+
+        (StatusCode::BAD_REQUEST, Json(body)).into_response()
+    }
+}
+
+/// What one member owes another.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Debt {
+    pub from: Uuid,
+    pub to: Uuid,
+    pub amount: BigDecimal,
+}
+
+/// Reasons a member can't settle up.
+#[derive(Clone, Debug)]
+pub enum SettleUpError {
+    /// The member doesn't owe anything.
+    NothingOwed,
+    /// The transfers to settle up failed.
+    Batch(BatchError),
+}
+
+impl IntoResponse for SettleUpError {
+    fn into_response(self) -> Response {
+        match self {
+            Self::NothingOwed => (
+                StatusCode::CONFLICT,
+                Json(json!({
+                    "title": "NothingOwed",
+                })),
+            )
+                .into_response(),
+            Self::Batch(err) => err.into_response(),
+        }
+    }
+}
+
+/// Splits `amount` between `members` of a group, returning what each member
+/// of the split owes.
+///
+/// Amounts are rounded down to the minor unit of `currency`, and what is left
+/// over is spread one minor unit at a time over the members in the order they
+/// are listed in, so that the amounts add up to `amount`.
+pub fn split(
+    amount: &BigDecimal,
+    currency: Currency,
+    split: &Split,
+    members: &[Uuid],
+) -> Result<Vec<(Uuid, BigDecimal)>, SplitError> {
+    if *amount <= BigDecimal::zero() {
+        return Err(SplitError::InvalidAmount);
+    }
+    if !currency.is_valid_amount(amount) {
+        return Err(SplitError::AmountTooPrecise(currency));
+    }
+
+    let user_ids = match split {
+        Split::Equal(user_ids) => user_ids.clone(),
+        Split::Shares(shares) => shares.iter().map(|(user_id, _)| *user_id).collect(),
+        Split::Exact(amounts) => amounts.iter().map(|(user_id, _)| *user_id).collect(),
+    };
+    if user_ids.is_empty() {
+        return Err(SplitError::NoMembers);
+    }
+    let mut seen = BTreeSet::new();
+    for user_id in &user_ids {
+        if !seen.insert(*user_id) {
+            return Err(SplitError::DuplicateMember(*user_id));
+        }
+        if !members.contains(user_id) {
+            return Err(SplitError::NotMember(*user_id));
+        }
+    }
+
+    let weights = match split {
+        Split::Equal(user_ids) => user_ids.iter().map(|user_id| (*user_id, 1)).collect(),
+        Split::Shares(shares) => shares.clone(),
+        Split::Exact(amounts) => {
+            for (_, share) in amounts {
+                if *share < BigDecimal::zero() {
+                    return Err(SplitError::InvalidAmount);
+                }
+                if !currency.is_valid_amount(share) {
+                    return Err(SplitError::AmountTooPrecise(currency));
+                }
+            }
+            let total = amounts.iter().map(|(_, share)| share).sum::<BigDecimal>();
+            if total != *amount {
+                return Err(SplitError::AmountMismatch {
+                    total: total.normalized(),
+                });
+            }
+            return Ok(amounts
+                .iter()
+                .map(|(user_id, share)| (*user_id, share.normalized()))
+                .collect());
+        },
+    };
+
+    let total_weight = weights
+        .iter()
+        .map(|(_, weight)| BigDecimal::from(*weight))
+        .sum::<BigDecimal>();
+    if total_weight.is_zero() {
+        return Err(SplitError::NoShares);
+    }
+
+    let scale = i64::from(currency.minor_units());
+    let minor_unit = BigDecimal::new(BigInt::from(1), scale);
+    let mut shares = weights
+        .iter()
+        .map(|(user_id, weight)| {
+            let share = (amount * BigDecimal::from(*weight) / &total_weight)
+                .with_scale_round(scale, RoundingMode::Down);
+            (*user_id, share)
+        })
+        .collect::<Vec<_>>();
+
+    let mut left_over = amount - shares.iter().map(|(_, share)| share).sum::<BigDecimal>();
+    for ((_, share), (_, weight)) in shares.iter_mut().zip(&weights) {
+        if left_over <= BigDecimal::zero() {
+            break;
+        }
+        if *weight > 0 {
+            *share += &minor_unit;
+            left_over -= &minor_unit;
+        }
+    }
+
+    Ok(shares
+        .into_iter()
+        .map(|(user_id, share)| (user_id, share.normalized()))
+        .collect())
+}
+
+/// Returns what each member is owed, or owes if negative, given what they
+/// paid for, what their shares are, and the transfers they settled up with.
+pub fn balances(
+    members: &[Uuid],
+    expenses: &[GroupExpense],
+    shares: &[GroupExpenseShare],
+    settlements: &[Transaction],
+) -> BTreeMap<Uuid, BigDecimal> {
+    let mut balances = members
+        .iter()
+        .map(|user_id| (*user_id, BigDecimal::zero()))
+        .collect::<BTreeMap<_, _>>();
+
+    for expense in expenses {
+        *balances.entry(expense.payer).or_default() += &expense.amount;
+    }
+    for share in shares {
+        *balances.entry(share.user_id).or_default() -= &share.amount;
+    }
+    for settlement in settlements {
+        *balances.entry(settlement.sender).or_default() += &settlement.amount;
+        *balances.entry(settlement.recipient).or_default() -= &settlement.amount;
+    }
+
+    balances
+        .into_iter()
+        .map(|(user_id, balance)| (user_id, balance.normalized()))
+        .collect()
+}
+
+/// Returns debts which settle `balances` in as few transfers as is practical,
+/// by repeatedly having the member who owes the most pay the member who is
+/// owed the most.
+pub fn simplify(balances: &BTreeMap<Uuid, BigDecimal>) -> Vec<Debt> {
+    let mut creditors = balances
+        .iter()
+        .filter(|(_, balance)| **balance > BigDecimal::zero())
+        .map(|(user_id, balance)| (*user_id, balance.clone()))
+        .collect::<Vec<_>>();
+    let mut debtors = balances
+        .iter()
+        .filter(|(_, balance)| **balance < BigDecimal::zero())
+        .map(|(user_id, balance)| (*user_id, -balance))
+        .collect::<Vec<_>>();
+    // Sorting is stable, so ties stay in the order of the user IDs.
+    creditors.sort_by(|(_, a), (_, b)| b.cmp(a));
+    debtors.sort_by(|(_, a), (_, b)| b.cmp(a));
+
+    let mut debts = Vec::new();
+    let (mut creditors, mut debtors) = (creditors.into_iter(), debtors.into_iter());
+    let (mut creditor, mut debtor) = (creditors.next(), debtors.next());
+    while let (Some((to, owed)), Some((from, owes))) = (&mut creditor, &mut debtor) {
+        let amount = owed.clone().min(owes.clone());
+        *owed -= &amount;
+        *owes -= &amount;
+        debts.push(Debt {
+            from: *from,
+            to: *to,
+            amount: amount.normalized(),
+        });
+
+        if owed.is_zero() {
+            creditor = creditors.next();
+        }
+        if owes.is_zero() {
+            debtor = debtors.next();
+        }
+    }
+
+    debts
+}
+
+/// Returns the members of `group_id`, in the order they joined in.
+pub async fn members(conn: &mut DbConnection, group_id: Uuid) -> anyhow::Result<Vec<Uuid>> {
+    use crate::models::types;
+    use crate::schema::group_members;
+
+    let members: Vec<types::Uuid> = group_members::table
+        .filter(group_members::group_id.eq(types::Uuid::from(group_id)))
+        .order((group_members::joined_at.asc(), group_members::user_id.asc()))
+        .select(group_members::user_id)
+        .load(conn)
+        .await
+        .context("failed to query group members")?;
+
+    Ok(members.into_iter().map(Into::into).collect())
+}
+
+/// Returns what each member of `group_id` is owed, or owes if negative.
+pub async fn group_balances(
+    conn: &mut DbConnection,
+    group_id: Uuid,
+) -> anyhow::Result<BTreeMap<Uuid, BigDecimal>> {
+    use crate::models::types;
+    use crate::schema::{group_expense_shares, group_expenses, group_settlements, transactions};
+
+    let members = members(conn, group_id).await?;
+    let expenses: Vec<GroupExpense> = group_expenses::table
+        .filter(group_expenses::group_id.eq(types::Uuid::from(group_id)))
+        .select(GroupExpense::as_select())
+        .load(conn)
+        .await
+        .context("failed to query group expenses")?;
+    let shares: Vec<GroupExpenseShare> = group_expense_shares::table
+        .inner_join(group_expenses::table)
+        .filter(group_expenses::group_id.eq(types::Uuid::from(group_id)))
+        .select(GroupExpenseShare::as_select())
+        .load(conn)
+        .await
+        .context("failed to query group expense shares")?;
+    let settlements: Vec<Transaction> = group_settlements::table
+        .inner_join(transactions::table)
+        .filter(group_settlements::group_id.eq(types::Uuid::from(group_id)))
+        .select(Transaction::as_select())
+        .load(conn)
+        .await
+        .context("failed to query group settlements")?;
+
+    Ok(balances(&members, &expenses, &shares, &settlements))
+}
+
+/// Pays what `user_id` owes other members of `group`, according to the
+/// simplified debts, in one database transaction.
+///
+/// The transfers are made like a batch, see [`ledger::apply_batch_transfer`].
+pub async fn settle_up(
+    conn: &mut DbConnection,
+    group: &Group,
+    user_id: Uuid,
+    default_limits: &TransferLimits,
+) -> anyhow::Result<Result<Vec<Transaction>, SettleUpError>> {
+    use crate::schema::group_settlements;
+
+    ledger::run_immediate_transaction(conn, |conn| {
+        Box::pin(async move {
+            let balances = group_balances(conn, group.id).await?;
+            let transfers = simplify(&balances)
+                .into_iter()
+                .filter(|debt| debt.from == user_id)
+                .map(|debt| NewBatchTransfer {
+                    amount: debt.amount,
+                    recipient: debt.to,
+                    memo: Some(format!("Settle up: {}", group.name)),
+                    reference: None,
+                })
+                .collect::<Vec<_>>();
+            if transfers.is_empty() {
+                return Ok(Err(SettleUpError::NothingOwed));
+            }
+
+            let batch = NewTransferBatch {
+                sender: user_id,
+                currency: group.currency,
+                transfers,
+            };
+            let created_transactions =
+                match ledger::apply_batch_transfer(conn, &batch, default_limits).await? {
+                    Ok((_, created_transactions)) => created_transactions,
+                    Err(err) => return Ok(Err(SettleUpError::Batch(err))),
+                };
+
+            for created_transaction in &created_transactions {
+                diesel::insert_into(group_settlements::table)
+                    .values(GroupSettlement {
+                        group_id: group.id,
+                        transaction_id: created_transaction.id,
+                    })
+                    .execute(conn)
+                    .await
+                    .context("failed to insert group settlement")?;
+            }
+
+            Ok(Ok(created_transactions))
+        })
+    })
+    .await
+}
@@ -1,4 +1,5 @@
 use axum::extract::FromRef;
+use bigdecimal::BigDecimal;
 use diesel::SqliteConnection;
 use diesel_async::pooled_connection::deadpool::Pool;
 use diesel_async::sync_connection_wrapper::SyncConnectionWrapper;
@@ -7,9 +8,23 @@ use secrecy::SecretSlice;
 use url::Url;
 use uuid::Uuid;
 
+use crate::event_hub::EventHub;
+use crate::http_url::HostPolicy;
+use crate::limits::TransferLimits;
+use crate::rate_limit::RateLimiter;
+
 #[derive(Clone, FromRef)]
 pub struct AppState {
     pub db_connection_pool: DbConnectionPool,
+    pub username_lookup_rate_limiter: UsernameLookupRateLimiter,
+    pub gift_code_redemption_rate_limiter: GiftCodeRedemptionRateLimiter,
+    pub jws_signing_secret: JwsSigningSecret,
+    pub access_token_issuer: AccessTokenIssuer,
+    pub public_url: PublicUrl,
+    pub default_transfer_limits: TransferLimits,
+    pub event_hub: EventHub,
+    /// Which hosts webhook endpoints may be at.
+    pub webhook_host_policy: HostPolicy,
 }
 
 #[derive(Clone, FromRef)]
@@ -20,9 +35,13 @@ pub struct AuthState {
     pub access_token_expiration: AccessTokenExpiration,
     pub access_token_audience: AccessTokenAudience,
     pub access_token_client_id: AccessTokenClientId,
+    pub signup_grant: SignupGrant,
+    pub event_hub: EventHub,
 }
 
-pub type DbConnectionPool = Pool<SyncConnectionWrapper<SqliteConnection>>;
+pub type DbConnectionPool = Pool<DbConnection>;
+
+pub type DbConnection = SyncConnectionWrapper<SqliteConnection>;
 
 #[derive(Clone)]
 pub struct JwsSigningSecret(pub SecretSlice<u8>);
@@ -38,3 +57,58 @@ pub struct AccessTokenAudience(pub Url);
 
 #[derive(Copy, Clone)]
 pub struct AccessTokenClientId(pub Uuid);
+
+/// What new users are granted from the treasury, in the default currency.
+/// Nothing is granted if zero.
+#[derive(Clone)]
+pub struct SignupGrant(pub BigDecimal);
+
+/// The URL the service, including the frontend, is publicly reachable at.
+#[derive(Clone)]
+pub struct PublicUrl(pub Url);
+
+/// A frontend page which pays someone, and what it pays.
+#[derive(Copy, Clone, Debug)]
+pub enum PaymentPage<'a> {
+    /// Pays a user any amount.
+    User(Uuid),
+    /// Shows a payment request, to be accepted or declined.
+    PaymentRequest(Uuid),
+    /// Pays a payment link, given its token.
+    PaymentLink(&'a str),
+    /// The hosted page which pays a checkout session.
+    CheckoutSession(Uuid),
+}
+
+impl PublicUrl {
+    /// Returns the URI of a frontend page which pays someone.
+    pub fn payment_uri(&self, page: PaymentPage<'_>) -> Url {
+        let (path, key, value) = match page {
+            PaymentPage::User(user_id) => ("index.html", "to", user_id.to_string()),
+            PaymentPage::PaymentRequest(payment_request_id) => {
+                ("index.html", "request", payment_request_id.to_string())
+            },
+            PaymentPage::PaymentLink(token) => ("index.html", "pay", token.to_owned()),
+            PaymentPage::CheckoutSession(checkout_session_id) => {
+                ("checkout.html", "session", checkout_session_id.to_string())
+            },
+        };
+
+        let mut uri = self
+            .0
+            .join(path)
+            .expect("joining a relative path should not fail");
+        uri.query_pairs_mut().append_pair(key, &value);
+        uri
+    }
+}
+
+/// Limits how often a user may look up other users by username, to make account
+/// enumeration slower.
+#[derive(Clone)]
+pub struct UsernameLookupRateLimiter(pub RateLimiter<Uuid>);
+
+/// Limits how often a user may try to redeem gift codes, to make guessing codes
+/// slower.
+#[derive(Clone)]
+pub struct GiftCodeRedemptionRateLimiter(pub RateLimiter<Uuid>);
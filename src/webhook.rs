@@ -0,0 +1,536 @@
+//! Outbound webhooks, which tell users' endpoints about events as they happen.
+//!
+//! Events are written to the `webhook_deliveries` outbox by the same database
+//! transaction as the change they describe, one delivery per subscribed
+//! endpoint, so an event is never sent for a change which was rolled back, nor
+//! lost for one which was committed. A background worker then sends them.
+//!
+//! Each delivery is a `POST` of a JSON envelope, signed with the secret of its
+//! endpoint, see [`sign`]. Deliveries which fail are retried with exponential
+//! backoff, and are given up on as dead after [`MAX_ATTEMPTS`], until they are
+//! replayed. As they may be sent more than once, receivers should use the
+//! event ID to tell duplicates apart.
+
+use std::collections::BTreeSet;
+use std::fmt::Write as _;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Context as _;
+use bigdecimal::BigDecimal;
+use diesel::prelude::*;
+#[allow(
+    clippy::unused_trait_names,
+    reason = "error[E0034]: multiple applicable items in scope"
+)]
+use diesel_async::RunQueryDsl;
+use ring::hmac;
+use secrecy::{ExposeSecret as _, SecretString};
+use serde::Serialize;
+use tracing::{debug, error, info, warn};
+use url::Url;
+use uuid::Uuid;
+
+use crate::currency::Currency;
+use crate::http_url::{self, HostPolicy};
+use crate::models::transaction::TransactionKind;
+use crate::models::webhook::{WebhookDeliveryStatus, WebhookEventType};
+use crate::models::{
+    CheckoutSession, PaymentRequest, Transaction, WebhookDelivery, WebhookEndpoint,
+};
+use crate::state::{DbConnection, DbConnectionPool};
+
+/// How often to look for deliveries which are due.
+pub const POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+/// How many times to attempt a delivery before giving up on it as dead.
+pub const MAX_ATTEMPTS: i32 = 10;
+
+/// How long to wait before retrying a delivery which failed once, doubling
+/// with each further failure.
+pub const RETRY_BASE_DELAY: jiff::SignedDuration = jiff::SignedDuration::from_mins(1);
+
+/// The longest to wait before retrying a delivery.
+pub const RETRY_MAX_DELAY: jiff::SignedDuration = jiff::SignedDuration::from_hours(6);
+
+/// How long an endpoint has to respond to a delivery.
+pub const DELIVERY_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The maximum number of deliveries sent per run.
+const DELIVERY_BATCH_SIZE: i64 = 100;
+
+/// The header with the ID of the event, which is the same for every attempt.
+pub const EVENT_ID_HEADER: &str = "webhook-id";
+
+/// The header with the type of the event.
+pub const EVENT_TYPE_HEADER: &str = "webhook-event";
+
+/// The header with the signature of the request body, see [`sign`].
+pub const SIGNATURE_HEADER: &str = "webhook-signature";
+
+/// The prefix of endpoint secrets, so that they are recognisable.
+const SECRET_PREFIX: &str = "whsec_";
+
+/// The body of a delivery.
+#[derive(Serialize)]
+struct Envelope<'a, T> {
+    id: Uuid,
+    #[serde(rename = "type")]
+    event_type: WebhookEventType,
+    created_at: jiff::Timestamp,
+    data: &'a T,
+}
+
+#[derive(Serialize)]
+struct TransactionData {
+    id: Uuid,
+    #[serde(with = "bigdecimal::serde::json_num")]
+    amount: BigDecimal,
+    currency: Currency,
+    recipient: Uuid,
+    sender: Uuid,
+    timestamp: jiff::Timestamp,
+    memo: Option<String>,
+    reference: Option<String>,
+    kind: TransactionKind,
+    original_transaction_id: Option<Uuid>,
+    /// What the recipient was credited, if the amount was converted.
+    #[serde(with = "bigdecimal::serde::json_num_option")]
+    converted_amount: Option<BigDecimal>,
+    converted_currency: Option<Currency>,
+    #[serde(with = "bigdecimal::serde::json_num_option")]
+    fee: Option<BigDecimal>,
+}
+
+#[derive(Serialize)]
+struct PaymentRequestData {
+    id: Uuid,
+    requester: Uuid,
+    payer: Option<Uuid>,
+    #[serde(with = "bigdecimal::serde::json_num")]
+    amount: BigDecimal,
+    currency: Currency,
+    memo: Option<String>,
+    transaction_id: Option<Uuid>,
+}
+
+#[derive(Serialize)]
+struct CheckoutSessionData {
+    id: Uuid,
+    merchant_id: Uuid,
+    #[serde(with = "bigdecimal::serde::json_num")]
+    amount: BigDecimal,
+    currency: Currency,
+    reference: String,
+    memo: Option<String>,
+    completed_at: Option<jiff::Timestamp>,
+    payer: Option<Uuid>,
+    transaction_id: Option<Uuid>,
+}
+
+/// Generates a new endpoint secret.
+pub fn generate_secret() -> SecretString {
+    let bytes: [u8; 32] = rand::random();
+
+    format!("{SECRET_PREFIX}{}", hex(&bytes)).into()
+}
+
+/// Returns the value of the [`SIGNATURE_HEADER`] of a request with `body` sent
+/// at `timestamp`, in Unix seconds.
+///
+/// The value is `t={timestamp},v1={signature}`, where the signature is the
+/// hex-encoded HMAC-SHA256 of `{timestamp}.{body}` keyed with the secret of
+/// the endpoint. Receivers should check the timestamp is recent, to guard
+/// against replays.
+pub fn sign(secret: &SecretString, timestamp: i64, body: &str) -> String {
+    let key = hmac::Key::new(hmac::HMAC_SHA256, secret.expose_secret().as_bytes());
+    let tag = hmac::sign(&key, format!("{timestamp}.{body}").as_bytes());
+
+    format!("t={timestamp},v1={}", hex(tag.as_ref()))
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().fold(
+        String::with_capacity(bytes.len().saturating_mul(2)),
+        |mut s, b| {
+            let _ = write!(s, "{b:02x}");
+            s
+        },
+    )
+}
+
+/// Queues an event about `user_ids` for every endpoint which subscribes to
+/// `event_type`, and is either theirs or sent the events of all users.
+///
+/// This should be called in the database transaction which makes the change
+/// the event describes.
+pub async fn enqueue<T: Serialize>(
+    conn: &mut DbConnection,
+    event_type: WebhookEventType,
+    user_ids: &[Uuid],
+    data: &T,
+) -> anyhow::Result<()> {
+    use crate::models::types;
+    use crate::schema::{webhook_deliveries, webhook_endpoints, webhook_subscriptions};
+
+    let endpoint_ids: Vec<types::Uuid> = webhook_endpoints::table
+        .inner_join(webhook_subscriptions::table)
+        .filter(webhook_subscriptions::event_type.eq(event_type))
+        .filter(
+            webhook_endpoints::user_id
+                .eq_any(user_ids.iter().copied().map(types::Uuid::from))
+                .or(webhook_endpoints::all_users.eq(true)),
+        )
+        .select(webhook_endpoints::id)
+        .load(conn)
+        .await
+        .context("failed to query webhook endpoints")?;
+    if endpoint_ids.is_empty() {
+        return Ok(());
+    }
+
+    let now = jiff::Timestamp::now();
+    let event_id = Uuid::now_v7();
+    let payload = serde_json::to_string(&Envelope {
+        id: event_id,
+        event_type,
+        created_at: now,
+        data,
+    })
+    .context("failed to serialize webhook event")?;
+
+    for endpoint_id in endpoint_ids {
+        let new_delivery = WebhookDelivery {
+            id: Uuid::now_v7(),
+            endpoint_id: endpoint_id.into(),
+            event_id,
+            event_type,
+            payload: payload.clone(),
+            status: WebhookDeliveryStatus::Pending,
+            attempts: 0,
+            next_attempt_at: now,
+            last_attempt_at: None,
+            last_response_status: None,
+            last_error: None,
+            created_at: now,
+            delivered_at: None,
+        };
+
+        diesel::insert_into(webhook_deliveries::table)
+            .values(new_delivery)
+            .execute(conn)
+            .await
+            .context("failed to insert webhook delivery")?;
+    }
+
+    Ok(())
+}
+
+/// Queues the events of a transaction which was just made, for its sender and
+/// recipient.
+pub async fn enqueue_transaction(
+    conn: &mut DbConnection,
+    transaction: &Transaction,
+) -> anyhow::Result<()> {
+    let user_ids = [transaction.sender, transaction.recipient];
+    let data = TransactionData {
+        id: transaction.id,
+        amount: transaction.amount.clone(),
+        currency: transaction.currency,
+        recipient: transaction.recipient,
+        sender: transaction.sender,
+        timestamp: transaction.timestamp,
+        memo: transaction.memo.clone(),
+        reference: transaction.reference.clone(),
+        kind: transaction.kind,
+        original_transaction_id: transaction.original_transaction_id,
+        converted_amount: transaction.converted_amount.clone(),
+        converted_currency: transaction.converted_currency,
+        fee: transaction.fee.clone(),
+    };
+
+    enqueue(conn, WebhookEventType::TransactionCreated, &user_ids, &data).await?;
+    if matches!(
+        transaction.kind,
+        TransactionKind::Refund | TransactionKind::Reversal
+    ) {
+        enqueue(conn, WebhookEventType::RefundCreated, &user_ids, &data).await?;
+    }
+
+    Ok(())
+}
+
+/// Queues the event of a payment request which was just paid, for its
+/// requester and payer.
+pub async fn enqueue_payment_request_paid(
+    conn: &mut DbConnection,
+    payment_request: &PaymentRequest,
+) -> anyhow::Result<()> {
+    let user_ids: Vec<Uuid> = [Some(payment_request.requester), payment_request.payer]
+        .into_iter()
+        .flatten()
+        .collect();
+    let data = PaymentRequestData {
+        id: payment_request.id,
+        requester: payment_request.requester,
+        payer: payment_request.payer,
+        amount: payment_request.amount.clone(),
+        currency: payment_request.currency,
+        memo: payment_request.memo.clone(),
+        transaction_id: payment_request.transaction_id,
+    };
+
+    enqueue(conn, WebhookEventType::PaymentRequestPaid, &user_ids, &data).await
+}
+
+/// Queues the event of a checkout session which was just completed, for its
+/// merchant and payer.
+pub async fn enqueue_checkout_session_completed(
+    conn: &mut DbConnection,
+    checkout_session: &CheckoutSession,
+) -> anyhow::Result<()> {
+    let user_ids: Vec<Uuid> = [Some(checkout_session.merchant_id), checkout_session.payer]
+        .into_iter()
+        .flatten()
+        .collect();
+    let data = CheckoutSessionData {
+        id: checkout_session.id,
+        merchant_id: checkout_session.merchant_id,
+        amount: checkout_session.amount.clone(),
+        currency: checkout_session.currency,
+        reference: checkout_session.reference.clone(),
+        memo: checkout_session.memo.clone(),
+        completed_at: checkout_session.completed_at,
+        payer: checkout_session.payer,
+        transaction_id: checkout_session.transaction_id,
+    };
+
+    enqueue(
+        conn,
+        WebhookEventType::CheckoutSessionCompleted,
+        &user_ids,
+        &data,
+    )
+    .await
+}
+
+/// The HTTP client which deliveries are sent with.
+#[derive(Clone)]
+pub struct WebhookClient {
+    client: reqwest::Client,
+    host_policy: HostPolicy,
+}
+
+impl WebhookClient {
+    /// Builds a client which only sends deliveries to the hosts `host_policy`
+    /// allows.
+    pub fn new(host_policy: HostPolicy) -> anyhow::Result<Self> {
+        let mut builder = reqwest::Client::builder()
+            .timeout(DELIVERY_TIMEOUT)
+            // A redirect is a failed delivery, rather than a way to have requests
+            // sent elsewhere.
+            .redirect(reqwest::redirect::Policy::none());
+        if host_policy == HostPolicy::PublicOnly {
+            // Check the addresses which are actually connected to, as a domain
+            // may resolve to another address than when its endpoint was checked.
+            builder = builder.dns_resolver(Arc::new(PublicResolver));
+        }
+
+        Ok(Self {
+            client: builder.build().context("failed to build HTTP client")?,
+            host_policy,
+        })
+    }
+}
+
+/// Resolves domains to their addresses, failing unless they are all public.
+struct PublicResolver;
+
+impl reqwest::dns::Resolve for PublicResolver {
+    fn resolve(&self, name: reqwest::dns::Name) -> reqwest::dns::Resolving {
+        Box::pin(async move {
+            let addrs = http_url::resolve_public(name.as_str()).await?;
+            let addrs: reqwest::dns::Addrs = Box::new(addrs.into_iter());
+            Ok(addrs)
+        })
+    }
+}
+
+/// Sends deliveries which are due, forever.
+pub async fn run_webhook_deliveries(pool: DbConnectionPool, client: WebhookClient) {
+    let mut interval = tokio::time::interval(POLL_INTERVAL);
+    interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+
+    loop {
+        interval.tick().await;
+
+        if let Err(err) = deliver_due_webhooks(&pool, &client, jiff::Timestamp::now()).await {
+            error!(?err, "failed to deliver webhooks");
+        }
+    }
+}
+
+/// Sends every pending delivery which is due at `now`, oldest first.
+///
+/// Once a delivery to an endpoint fails, the endpoint's other deliveries are
+/// left until the next run, so that an endpoint which is down doesn't hold up
+/// the others.
+pub async fn deliver_due_webhooks(
+    pool: &DbConnectionPool,
+    client: &WebhookClient,
+    now: jiff::Timestamp,
+) -> anyhow::Result<()> {
+    use crate::models::types;
+    use crate::schema::{webhook_deliveries, webhook_endpoints};
+
+    let mut conn = pool
+        .get()
+        .await
+        .context("failed to get database connection")?;
+
+    let deliveries: Vec<(WebhookDelivery, WebhookEndpoint)> = webhook_deliveries::table
+        .inner_join(webhook_endpoints::table)
+        .filter(webhook_deliveries::status.eq(WebhookDeliveryStatus::Pending))
+        .filter(webhook_deliveries::next_attempt_at.le(types::Timestamp::from(now)))
+        .order(webhook_deliveries::id.asc())
+        .limit(DELIVERY_BATCH_SIZE)
+        .select((WebhookDelivery::as_select(), WebhookEndpoint::as_select()))
+        .load(&mut conn)
+        .await
+        .context("failed to query webhook deliveries")?;
+
+    let mut failed_endpoints = BTreeSet::new();
+    for (delivery, endpoint) in deliveries {
+        if failed_endpoints.contains(&endpoint.id) {
+            continue;
+        }
+
+        let delivery = attempt_delivery(&mut conn, client, delivery, &endpoint, now).await?;
+        if delivery.status != WebhookDeliveryStatus::Succeeded {
+            failed_endpoints.insert(endpoint.id);
+        }
+    }
+
+    Ok(())
+}
+
+/// Sends `delivery` to `endpoint` once at `now`, and records the outcome.
+async fn attempt_delivery(
+    conn: &mut DbConnection,
+    client: &WebhookClient,
+    mut delivery: WebhookDelivery,
+    endpoint: &WebhookEndpoint,
+    now: jiff::Timestamp,
+) -> anyhow::Result<WebhookDelivery> {
+    use crate::models::types;
+    use crate::schema::webhook_deliveries;
+
+    // The signature is timestamped with the time it is actually sent, which
+    // receivers compare with their own clocks.
+    let signature = sign(
+        &endpoint.secret,
+        jiff::Timestamp::now().as_second(),
+        &delivery.payload,
+    );
+    // Endpoints are checked when they are registered too, but their domains may
+    // have been pointed elsewhere since.
+    let result = match check_endpoint_url(&endpoint.url, client.host_policy).await {
+        Ok(url) => client
+            .client
+            .post(url)
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .header(EVENT_ID_HEADER, delivery.event_id.to_string())
+            .header(EVENT_TYPE_HEADER, delivery.event_type.to_string())
+            .header(SIGNATURE_HEADER, signature)
+            .body(delivery.payload.clone())
+            .send()
+            .await
+            .map_err(|err| format!("{:#}", anyhow::Error::from(err))),
+        Err(err) => Err(err),
+    };
+
+    delivery.attempts = delivery.attempts.saturating_add(1);
+    delivery.last_attempt_at = Some(now);
+    let error = match result {
+        Ok(response) => {
+            let status = response.status();
+            delivery.last_response_status = Some(i32::from(status.as_u16()));
+            (!status.is_success()).then(|| format!("endpoint responded with {status}"))
+        },
+        Err(error) => {
+            delivery.last_response_status = None;
+            Some(error)
+        },
+    };
+
+    match error {
+        None => {
+            info!(%delivery.id, %endpoint.id, "delivered webhook");
+
+            delivery.status = WebhookDeliveryStatus::Succeeded;
+            delivery.last_error = None;
+            delivery.delivered_at = Some(now);
+        },
+        Some(error) if delivery.attempts >= MAX_ATTEMPTS => {
+            warn!(%delivery.id, %endpoint.id, error, "giving up on webhook delivery");
+
+            delivery.status = WebhookDeliveryStatus::Dead;
+            delivery.last_error = Some(error);
+        },
+        Some(error) => {
+            debug!(%delivery.id, %endpoint.id, error, "failed to deliver webhook");
+
+            delivery.next_attempt_at = now
+                .checked_add(retry_delay(delivery.attempts))
+                .context("next attempt time overflowed")?;
+            delivery.last_error = Some(error);
+        },
+    }
+
+    diesel::update(webhook_deliveries::table.find(types::Uuid::from(delivery.id)))
+        .set(delivery)
+        .returning(WebhookDelivery::as_returning())
+        .get_result(conn)
+        .await
+        .context("failed to update webhook delivery")
+}
+
+/// Parses the URL of an endpoint, and checks that deliveries may be sent to it.
+async fn check_endpoint_url(url: &str, host_policy: HostPolicy) -> Result<Url, String> {
+    let url = Url::parse(url).map_err(|err| format!("invalid URL: {err}"))?;
+    http_url::check_host(&url, host_policy).await?;
+
+    Ok(url)
+}
+
+/// Sends `delivery` again with a fresh set of attempts, as soon as the worker
+/// next runs, whatever its status.
+pub async fn replay(
+    conn: &mut DbConnection,
+    mut delivery: WebhookDelivery,
+) -> anyhow::Result<WebhookDelivery> {
+    use crate::models::types;
+    use crate::schema::webhook_deliveries;
+
+    delivery.status = WebhookDeliveryStatus::Pending;
+    delivery.attempts = 0;
+    delivery.next_attempt_at = jiff::Timestamp::now();
+    delivery.delivered_at = None;
+
+    diesel::update(webhook_deliveries::table.find(types::Uuid::from(delivery.id)))
+        .set(delivery)
+        .returning(WebhookDelivery::as_returning())
+        .get_result(conn)
+        .await
+        .context("failed to update webhook delivery")
+}
+
+/// Returns how long to wait before retrying a delivery which has failed
+/// `attempts` times.
+pub fn retry_delay(attempts: i32) -> jiff::SignedDuration {
+    u32::try_from(attempts.saturating_sub(1))
+        .ok()
+        .and_then(|exponent| 2_i32.checked_pow(exponent))
+        .and_then(|factor| RETRY_BASE_DELAY.checked_mul(factor))
+        .map_or(RETRY_MAX_DELAY, |delay| delay.min(RETRY_MAX_DELAY))
+}
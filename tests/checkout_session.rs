@@ -0,0 +1,248 @@
+mod common;
+
+use anyhow::{Context as _, Result};
+use axum_diesel_example::currency::DEFAULT_CURRENCY;
+use axum_diesel_example::fees;
+use axum_diesel_example::models::user::UserKind;
+use axum_diesel_example::models::{FeeRule, types};
+use axum_diesel_example::schema::{checkout_sessions, fee_rules, users};
+use bigdecimal::BigDecimal;
+use diesel::prelude::*;
+#[allow(
+    clippy::unused_trait_names,
+    reason = "error[E0034]: multiple applicable items in scope"
+)]
+use diesel_async::RunQueryDsl;
+use reqwest::{Method, StatusCode};
+use serde_json::json;
+use uuid::Uuid;
+
+use self::common::{TestApp, TestDatabase, balance, create_user};
+
+async fn create_merchant(db: &TestDatabase, username: &str) -> Result<Uuid> {
+    let merchant = create_user(db, username, 0).await?;
+    let mut conn = db.pool.get().await?;
+    diesel::update(users::table.find(types::Uuid::from(merchant)))
+        .set(users::kind.eq(UserKind::Merchant))
+        .execute(&mut conn)
+        .await
+        .context("failed to update user")?;
+
+    Ok(merchant)
+}
+
+async fn create_checkout_session(app: &TestApp, merchant_token: &str) -> Result<Uuid> {
+    let (status, body) = app
+        .request(
+            Method::POST,
+            "/checkout-sessions",
+            merchant_token,
+            Some(json!({
+                "amount": 40,
+                "reference": "order-1",
+                "success_url": "https://shop.example/success",
+                "cancel_url": "https://shop.example/cancel",
+            })),
+        )
+        .await?;
+    assert_eq!(status, StatusCode::CREATED);
+    assert_eq!(body["status"], "open");
+
+    body["id"]
+        .as_str()
+        .context("checkout session should have an ID")?
+        .parse()
+        .context("checkout session ID should be a UUID")
+}
+
+#[tokio::test]
+async fn checkout_session_is_completed_by_the_payer() -> Result<()> {
+    let db = TestDatabase::new().await?;
+    let merchant = create_merchant(&db, "shop").await?;
+    let payer = create_user(&db, "payer", 100).await?;
+    let app = TestApp::start(&db).await?;
+    let (merchant_token, payer_token) = (app.access_token(merchant)?, app.access_token(payer)?);
+
+    // Only merchants may create checkout sessions.
+    let (status, _body) = app
+        .request(
+            Method::POST,
+            "/checkout-sessions",
+            &payer_token,
+            Some(json!({
+                "amount": 40,
+                "reference": "order-1",
+                "success_url": "https://shop.example/success",
+                "cancel_url": "https://shop.example/cancel",
+            })),
+        )
+        .await?;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+
+    let id = create_checkout_session(&app, &merchant_token).await?;
+
+    // The payer sees what is being paid, but not who paid it.
+    let (status, body) = app
+        .request(
+            Method::GET,
+            &format!("/checkout-sessions/{id}"),
+            &payer_token,
+            None,
+        )
+        .await?;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["merchant"]["username"], "shop");
+    assert_eq!(body["amount"], 40);
+    assert!(body["transaction_id"].is_null());
+
+    // The merchant can't pay their own checkout session.
+    let (status, _body) = app
+        .request(
+            Method::POST,
+            &format!("/checkout-sessions/{id}/pay"),
+            &merchant_token,
+            None,
+        )
+        .await?;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+
+    let (status, body) = app
+        .request(
+            Method::POST,
+            &format!("/checkout-sessions/{id}/pay"),
+            &payer_token,
+            None,
+        )
+        .await?;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["status"], "completed");
+    assert_eq!(body["payer"], payer.to_string());
+    let transaction_id = body["transaction_id"].clone();
+    assert!(transaction_id.is_string());
+    assert_eq!(balance(&db, merchant).await?, BigDecimal::from(40));
+    assert_eq!(balance(&db, payer).await?, BigDecimal::from(60));
+
+    let (status, body) = app
+        .request(
+            Method::GET,
+            "/checkout-sessions?status=completed",
+            &merchant_token,
+            None,
+        )
+        .await?;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["checkout_sessions"][0]["id"], id.to_string());
+    assert_eq!(
+        body["checkout_sessions"][0]["transaction_id"],
+        transaction_id
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn only_open_checkout_sessions_are_paid() -> Result<()> {
+    let db = TestDatabase::new().await?;
+    let merchant = create_merchant(&db, "shop").await?;
+    let payer = create_user(&db, "payer", 100).await?;
+    let app = TestApp::start(&db).await?;
+    let (merchant_token, payer_token) = (app.access_token(merchant)?, app.access_token(payer)?);
+
+    let completed = create_checkout_session(&app, &merchant_token).await?;
+    let pay = format!("/checkout-sessions/{completed}/pay");
+    let (status, _body) = app.request(Method::POST, &pay, &payer_token, None).await?;
+    assert_eq!(status, StatusCode::OK);
+
+    let (status, body) = app.request(Method::POST, &pay, &payer_token, None).await?;
+    assert_eq!(status, StatusCode::CONFLICT);
+    assert_eq!(body["title"], "CheckoutSessionNotOpen");
+    assert_eq!(body["detail"], "checkout session is completed");
+    assert_eq!(balance(&db, payer).await?, BigDecimal::from(60));
+
+    let expired = create_checkout_session(&app, &merchant_token).await?;
+    let mut conn = db.pool.get().await?;
+    diesel::update(checkout_sessions::table.find(types::Uuid::from(expired)))
+        .set(checkout_sessions::expires_at.eq(types::Timestamp::from(
+            jiff::Timestamp::now().checked_sub(jiff::SignedDuration::from_secs(1))?,
+        )))
+        .execute(&mut conn)
+        .await
+        .context("failed to update checkout session")?;
+
+    let (status, body) = app
+        .request(
+            Method::POST,
+            &format!("/checkout-sessions/{expired}/pay"),
+            &payer_token,
+            None,
+        )
+        .await?;
+    assert_eq!(status, StatusCode::CONFLICT);
+    assert_eq!(body["title"], "CheckoutSessionNotOpen");
+    assert_eq!(body["detail"], "checkout session is expired");
+    assert_eq!(balance(&db, payer).await?, BigDecimal::from(60));
+
+    let (status, body) = app
+        .request(
+            Method::GET,
+            "/checkout-sessions?status=expired",
+            &merchant_token,
+            None,
+        )
+        .await?;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["checkout_sessions"][0]["id"], expired.to_string());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn payer_is_charged_fees_on_checkout() -> Result<()> {
+    let db = TestDatabase::new().await?;
+    let merchant = create_merchant(&db, "shop").await?;
+    let payer = create_user(&db, "payer", 100).await?;
+    let app = TestApp::start(&db).await?;
+    let (merchant_token, payer_token) = (app.access_token(merchant)?, app.access_token(payer)?);
+
+    let mut conn = db.pool.get().await?;
+    diesel::insert_into(fee_rules::table)
+        .values(FeeRule {
+            id: Uuid::now_v7(),
+            currency: Some(DEFAULT_CURRENCY),
+            tier: None,
+            min_amount: None,
+            max_amount: None,
+            flat: BigDecimal::from(1),
+            percentage: BigDecimal::from(5),
+            min_fee: None,
+            max_fee: None,
+            priority: 0,
+            created_at: jiff::Timestamp::now(),
+        })
+        .execute(&mut conn)
+        .await
+        .context("failed to insert fee rule")?;
+
+    let id = create_checkout_session(&app, &merchant_token).await?;
+    let (status, body) = app
+        .request(
+            Method::POST,
+            &format!("/checkout-sessions/{id}/pay"),
+            &payer_token,
+            None,
+        )
+        .await?;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["status"], "completed");
+
+    // The merchant receives the full amount, and the payer pays 1 + 5% of 40 on
+    // top.
+    assert_eq!(balance(&db, merchant).await?, BigDecimal::from(40));
+    assert_eq!(balance(&db, payer).await?, BigDecimal::from(57));
+    assert_eq!(
+        balance(&db, fees::REVENUE_USER_ID).await?,
+        BigDecimal::from(3)
+    );
+
+    Ok(())
+}
@@ -0,0 +1,269 @@
+#![allow(dead_code, reason = "each test uses only some of these helpers")]
+
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use std::{env, fs};
+
+use anyhow::{Context as _, Result};
+use axum_diesel_example::currency::DEFAULT_CURRENCY;
+use axum_diesel_example::event_hub::EventHub;
+use axum_diesel_example::http_url::HostPolicy;
+use axum_diesel_example::limits::TransferLimits;
+use axum_diesel_example::models::account::NewAccount;
+use axum_diesel_example::models::types;
+use axum_diesel_example::models::user::{NewUser, UserKind, UserRole, UserTier};
+use axum_diesel_example::rate_limit::RateLimiter;
+use axum_diesel_example::schema::{accounts, users};
+use axum_diesel_example::state::{
+    AccessTokenAudience, AccessTokenClientId, AccessTokenExpiration, AccessTokenIssuer, AppState,
+    AuthState, DbConnectionPool, GiftCodeRedemptionRateLimiter, JwsSigningSecret, PublicUrl,
+    SignupGrant, UsernameLookupRateLimiter,
+};
+use axum_diesel_example::{db, jwt, routes};
+use bigdecimal::BigDecimal;
+use diesel::prelude::*;
+#[allow(
+    clippy::unused_trait_names,
+    reason = "error[E0034]: multiple applicable items in scope"
+)]
+use diesel_async::RunQueryDsl;
+use diesel_async::SimpleAsyncConnection as _;
+use reqwest::{Method, StatusCode};
+use uuid::Uuid;
+
+/// The rate limit of username lookups and gift code redemptions in tests, which
+/// is high enough not to get in the way unless a test means to reach it.
+pub const TEST_RATE_LIMIT: u32 = 20;
+
+/// A SQLite database in a temporary file, with all migrations applied.
+///
+/// The database files are removed when this is dropped.
+pub struct TestDatabase {
+    pub pool: DbConnectionPool,
+    path: PathBuf,
+}
+
+impl TestDatabase {
+    pub async fn new() -> Result<Self> {
+        let db = Self::empty()?;
+        db.apply_migrations(|_migration| true).await?;
+
+        Ok(db)
+    }
+
+    /// Returns a database with only the migrations before `migration` applied,
+    /// to test how it migrates existing data. The others are applied by
+    /// [`Self::apply_migrations_from`].
+    pub async fn before_migration(migration: &str) -> Result<Self> {
+        let db = Self::empty()?;
+        db.apply_migrations(|name| name < migration).await?;
+
+        Ok(db)
+    }
+
+    /// Applies `migration` and all migrations after it.
+    pub async fn apply_migrations_from(&self, migration: &str) -> Result<()> {
+        self.apply_migrations(|name| name >= migration).await
+    }
+
+    fn empty() -> Result<Self> {
+        let path = env::temp_dir().join(format!("axum-diesel-example-{}.sqlite", Uuid::now_v7()));
+        let pool = db::build_connection_pool(format!("file:{}", path.display()))?;
+
+        Ok(Self { pool, path })
+    }
+
+    /// Applies the migrations whose directory names match `filter`, in order.
+    async fn apply_migrations(&self, filter: impl Fn(&str) -> bool) -> Result<()> {
+        let mut conn = self
+            .pool
+            .get()
+            .await
+            .context("failed to get database connection")?;
+        let migrations_dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("migrations");
+        let mut migrations = fs::read_dir(&migrations_dir)
+            .context("failed to read migrations directory")?
+            .map(|entry| entry.map(|entry| entry.path()))
+            .collect::<Result<Vec<_>, _>>()?;
+        migrations.sort();
+        for migration in migrations {
+            let name = migration
+                .file_name()
+                .and_then(|name| name.to_str())
+                .context("migration directory name should be UTF-8")?;
+            if !filter(name) {
+                continue;
+            }
+            let sql = fs::read_to_string(migration.join("up.sql"))
+                .with_context(|| format!("failed to read migration {}", migration.display()))?;
+            conn.batch_execute(&sql)
+                .await
+                .with_context(|| format!("failed to run migration {}", migration.display()))?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Drop for TestDatabase {
+    fn drop(&mut self) {
+        for suffix in ["", "-wal", "-shm"] {
+            let mut path = self.path.clone().into_os_string();
+            path.push(suffix);
+            let _ = fs::remove_file(path);
+        }
+    }
+}
+
+/// Creates a user with a `balance` in the default currency, returning their ID.
+pub async fn create_user(db: &TestDatabase, username: &str, balance: u32) -> Result<Uuid> {
+    let mut conn = db.pool.get().await?;
+
+    let id = Uuid::now_v7();
+    diesel::insert_into(users::table)
+        .values(NewUser {
+            id,
+            username: username.to_owned(),
+            password_hash: String::new().into(),
+            role: UserRole::User,
+            tier: UserTier::Standard,
+            kind: UserKind::Personal,
+        })
+        .execute(&mut conn)
+        .await
+        .context("failed to insert user")?;
+    diesel::insert_into(accounts::table)
+        .values(NewAccount {
+            user_id: id,
+            currency: DEFAULT_CURRENCY,
+            balance: BigDecimal::from(balance),
+            created_at: jiff::Timestamp::now(),
+        })
+        .execute(&mut conn)
+        .await
+        .context("failed to insert account")?;
+
+    Ok(id)
+}
+
+/// Returns the balance of the user's account in the default currency.
+pub async fn balance(db: &TestDatabase, user_id: Uuid) -> Result<BigDecimal> {
+    let mut conn = db.pool.get().await?;
+
+    let balance: types::BigDecimal = accounts::table
+        .filter(accounts::user_id.eq(types::Uuid::from(user_id)))
+        .filter(accounts::currency.eq(DEFAULT_CURRENCY))
+        .select(accounts::balance)
+        .first(&mut conn)
+        .await
+        .context("failed to query accounts")?;
+
+    Ok(balance.into())
+}
+
+/// The service, served on a local port with its database being a
+/// [`TestDatabase`].
+pub struct TestApp {
+    url: String,
+    auth_state: AuthState,
+    client: reqwest::Client,
+}
+
+impl TestApp {
+    pub async fn start(db: &TestDatabase) -> Result<Self> {
+        let auth_state = AuthState {
+            db_connection_pool: db.pool.clone(),
+            jws_signing_secret: JwsSigningSecret(vec![0x42; jwt::HS256_SECRET_KEY_LEN].into()),
+            access_token_issuer: AccessTokenIssuer("https://issuer.example/".parse()?),
+            access_token_expiration: AccessTokenExpiration(jiff::Span::new().minutes(5)),
+            access_token_audience: AccessTokenAudience("http://localhost/".parse()?),
+            access_token_client_id: AccessTokenClientId(Uuid::now_v7()),
+            signup_grant: SignupGrant(BigDecimal::from(0)),
+            event_hub: EventHub::new(),
+        };
+        let state = AppState {
+            db_connection_pool: db.pool.clone(),
+            username_lookup_rate_limiter: UsernameLookupRateLimiter(RateLimiter::new(
+                TEST_RATE_LIMIT,
+                Duration::from_secs(60),
+            )),
+            gift_code_redemption_rate_limiter: GiftCodeRedemptionRateLimiter(RateLimiter::new(
+                TEST_RATE_LIMIT,
+                Duration::from_secs(60),
+            )),
+            jws_signing_secret: auth_state.jws_signing_secret.clone(),
+            access_token_issuer: auth_state.access_token_issuer.clone(),
+            public_url: PublicUrl("http://localhost/".parse()?),
+            default_transfer_limits: TransferLimits::default(),
+            event_hub: auth_state.event_hub.clone(),
+            webhook_host_policy: HostPolicy::PublicOnly,
+        };
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        let app = routes::router(state, auth_state.clone());
+        tokio::spawn(async move { axum::serve(listener, app).await });
+
+        Ok(Self {
+            url: format!("http://{addr}"),
+            auth_state,
+            client: reqwest::Client::new(),
+        })
+    }
+
+    /// Issues an access token for `user_id`, as logging in would.
+    pub fn access_token(&self, user_id: Uuid) -> Result<String> {
+        jwt::encode_access_token(
+            user_id,
+            &self.auth_state.access_token_issuer,
+            self.auth_state.access_token_expiration,
+            &self.auth_state.access_token_audience,
+            self.auth_state.access_token_client_id,
+            &self.auth_state.jws_signing_secret,
+        )
+    }
+
+    /// Sends a request with a JSON `body`, authenticated with `access_token`,
+    /// returning the status and the JSON body of the response.
+    ///
+    /// An empty response body is returned as `null`.
+    pub async fn request(
+        &self,
+        method: Method,
+        path: &str,
+        access_token: &str,
+        body: Option<serde_json::Value>,
+    ) -> Result<(StatusCode, serde_json::Value)> {
+        let response = self.send(method, path, access_token, body).await?;
+        let status = response.status();
+        let body = response.bytes().await.context("failed to read response")?;
+        let body = if body.is_empty() {
+            serde_json::Value::Null
+        } else {
+            serde_json::from_slice(&body).context("response body should be JSON")?
+        };
+
+        Ok((status, body))
+    }
+
+    /// Sends a request like [`Self::request`], returning the whole response.
+    pub async fn send(
+        &self,
+        method: Method,
+        path: &str,
+        access_token: &str,
+        body: Option<serde_json::Value>,
+    ) -> Result<reqwest::Response> {
+        let mut request = self
+            .client
+            .request(method, format!("{}{path}", self.url))
+            .bearer_auth(access_token);
+        if let Some(body) = body {
+            request = request
+                .header(reqwest::header::CONTENT_TYPE, "application/json")
+                .body(body.to_string());
+        }
+
+        request.send().await.context("failed to send request")
+    }
+}
@@ -0,0 +1,99 @@
+use anyhow::{Context as _, Result};
+use axum_diesel_example::event_hub::{Delivery, EventHub, REPLAY_CAPACITY, Subscription};
+use serde_json::json;
+use uuid::Uuid;
+
+/// Returns what `subscription` receives next, without waiting for it.
+async fn next(subscription: &mut Subscription) -> Option<Delivery> {
+    tokio::time::timeout(std::time::Duration::from_millis(50), subscription.next())
+        .await
+        .ok()
+        .flatten()
+}
+
+fn event_data(delivery: Option<Delivery>) -> Result<String> {
+    match delivery.context("expected an event")? {
+        Delivery::Event(event) => Ok(event.data.clone()),
+        Delivery::Resync => anyhow::bail!("expected an event, not a resync"),
+    }
+}
+
+#[tokio::test]
+async fn events_are_only_sent_to_their_user() -> Result<()> {
+    let hub = EventHub::new();
+    let (alice, bob) = (Uuid::now_v7(), Uuid::now_v7());
+    let mut subscription = hub.subscribe(alice, None);
+
+    hub.publish(bob, "balance", &json!({"for": "bob"}))?;
+    hub.publish(alice, "balance", &json!({"for": "alice"}))?;
+
+    assert_eq!(
+        event_data(next(&mut subscription).await)?,
+        r#"{"for":"alice"}"#
+    );
+    assert!(next(&mut subscription).await.is_none());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn subscriber_resumes_after_last_event_id() -> Result<()> {
+    let hub = EventHub::new();
+    let alice = Uuid::now_v7();
+    let mut subscription = hub.subscribe(alice, None);
+
+    hub.publish(alice, "transaction", &1)?;
+    let Some(Delivery::Event(first)) = next(&mut subscription).await else {
+        anyhow::bail!("expected an event");
+    };
+    let last_event_id = hub.event_id(&first);
+    drop(subscription);
+
+    // Published while disconnected.
+    hub.publish(alice, "transaction", &2)?;
+    hub.publish(alice, "transaction", &3)?;
+
+    let mut subscription = hub.subscribe(alice, Some(&last_event_id));
+    hub.publish(alice, "transaction", &4)?;
+
+    for expected in ["2", "3", "4"] {
+        assert_eq!(event_data(next(&mut subscription).await)?, expected);
+    }
+    assert!(next(&mut subscription).await.is_none());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn subscriber_is_told_to_resync_when_events_were_dropped() -> Result<()> {
+    let hub = EventHub::new();
+    let alice = Uuid::now_v7();
+    let mut subscription = hub.subscribe(alice, None);
+
+    hub.publish(alice, "transaction", &0)?;
+    let Some(Delivery::Event(first)) = next(&mut subscription).await else {
+        anyhow::bail!("expected an event");
+    };
+    let last_event_id = hub.event_id(&first);
+    drop(subscription);
+
+    for i in 0..=REPLAY_CAPACITY {
+        hub.publish(alice, "transaction", &i)?;
+    }
+
+    let mut subscription = hub.subscribe(alice, Some(&last_event_id));
+    assert!(matches!(
+        next(&mut subscription).await,
+        Some(Delivery::Resync)
+    ));
+    assert!(next(&mut subscription).await.is_none());
+
+    // IDs from another process, e.g. before a restart, can't be resumed from.
+    let mut subscription = hub.subscribe(alice, Some("1-1"));
+    assert!(matches!(
+        next(&mut subscription).await,
+        Some(Delivery::Resync)
+    ));
+
+    Ok(())
+}
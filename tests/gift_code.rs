@@ -0,0 +1,169 @@
+mod common;
+
+use anyhow::Result;
+use axum_diesel_example::currency::DEFAULT_CURRENCY;
+use axum_diesel_example::event_hub::EventHub;
+use axum_diesel_example::gift_code::{self, NewGiftCode, RedemptionError};
+use axum_diesel_example::ledger::TransferError;
+use axum_diesel_example::limits::TransferLimits;
+use axum_diesel_example::models::gift_code::GiftCodeStatus;
+use axum_diesel_example::models::{GiftCode, types};
+use axum_diesel_example::schema::gift_codes;
+use bigdecimal::{BigDecimal, Zero as _};
+use diesel::prelude::*;
+#[allow(
+    clippy::unused_trait_names,
+    reason = "error[E0034]: multiple applicable items in scope"
+)]
+use diesel_async::RunQueryDsl;
+use secrecy::ExposeSecret as _;
+use uuid::Uuid;
+
+use self::common::{TestDatabase, balance, create_user};
+
+async fn create_gift_code(
+    db: &TestDatabase,
+    creator: Uuid,
+    amount: u32,
+    max_redemptions: i32,
+    limits: &TransferLimits,
+) -> Result<Result<(GiftCode, String), TransferError>> {
+    let mut conn = db.pool.get().await?;
+
+    let code = gift_code::generate_code();
+    let new_gift_code = NewGiftCode {
+        created_by: creator,
+        funded_by: creator,
+        amount: BigDecimal::from(amount),
+        currency: DEFAULT_CURRENCY,
+        max_redemptions,
+        memo: Some("happy birthday".to_owned()),
+        expires_at: jiff::Timestamp::now()
+            .checked_add(jiff::SignedDuration::from_hours(1))
+            .unwrap(),
+    };
+    let result = gift_code::create(
+        &mut conn,
+        &new_gift_code,
+        &gift_code::hash_code(code.expose_secret()),
+        limits,
+    )
+    .await?;
+
+    Ok(result.map(|(gift_code, _transaction)| (gift_code, code.expose_secret().to_owned())))
+}
+
+async fn redeem(
+    db: &TestDatabase,
+    user_id: Uuid,
+    code: &str,
+) -> Result<Result<GiftCode, RedemptionError>> {
+    let mut conn = db.pool.get().await?;
+
+    let result = gift_code::redeem(
+        &mut conn,
+        user_id,
+        &gift_code::hash_code(code),
+        jiff::Timestamp::now(),
+    )
+    .await?;
+
+    Ok(result.map(|(gift_code, _transaction)| gift_code))
+}
+
+#[tokio::test]
+async fn gift_code_is_redeemed_once_per_user_up_to_max_redemptions() -> Result<()> {
+    let db = TestDatabase::new().await?;
+    let alice = create_user(&db, "alice", 100).await?;
+    let bob = create_user(&db, "bob", 0).await?;
+    let carol = create_user(&db, "carol", 0).await?;
+    let dave = create_user(&db, "dave", 0).await?;
+
+    let (_gift_code, code) = create_gift_code(&db, alice, 10, 2, &TransferLimits::default())
+        .await?
+        .map_err(|err| anyhow::anyhow!("gift code creation failed: {err:?}"))?;
+    assert_eq!(balance(&db, alice).await?, BigDecimal::from(80));
+
+    // Codes are compared regardless of case and dashes.
+    let lenient_code = code.replace('-', "").to_lowercase();
+    let gift_code = redeem(&db, bob, &lenient_code)
+        .await?
+        .map_err(|err| anyhow::anyhow!("redemption failed: {err:?}"))?;
+    assert_eq!(gift_code.redemption_count, 1);
+    assert_eq!(gift_code.status, GiftCodeStatus::Active);
+    assert_eq!(balance(&db, bob).await?, BigDecimal::from(10));
+
+    let result = redeem(&db, bob, &code).await?;
+    assert!(matches!(result, Err(RedemptionError::AlreadyRedeemed)));
+
+    let gift_code = redeem(&db, carol, &code)
+        .await?
+        .map_err(|err| anyhow::anyhow!("redemption failed: {err:?}"))?;
+    assert_eq!(gift_code.status, GiftCodeStatus::Redeemed);
+
+    let result = redeem(&db, dave, &code).await?;
+    assert!(matches!(result, Err(RedemptionError::NotRedeemable)));
+    let result = redeem(&db, dave, "0000-0000-0000-0000").await?;
+    assert!(matches!(result, Err(RedemptionError::GiftCodeNotFound)));
+    assert_eq!(balance(&db, dave).await?, BigDecimal::zero());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn expired_gift_code_refunds_what_is_left() -> Result<()> {
+    let db = TestDatabase::new().await?;
+    let alice = create_user(&db, "alice", 100).await?;
+    let bob = create_user(&db, "bob", 0).await?;
+
+    let (gift_code, code) = create_gift_code(&db, alice, 10, 3, &TransferLimits::default())
+        .await?
+        .map_err(|err| anyhow::anyhow!("gift code creation failed: {err:?}"))?;
+    redeem(&db, bob, &code)
+        .await?
+        .map_err(|err| anyhow::anyhow!("redemption failed: {err:?}"))?;
+    assert_eq!(balance(&db, alice).await?, BigDecimal::from(70));
+
+    // Not yet expired.
+    gift_code::expire_due_gift_codes(&db.pool, &EventHub::new(), jiff::Timestamp::now()).await?;
+    assert_eq!(balance(&db, alice).await?, BigDecimal::from(70));
+
+    let later = gift_code
+        .expires_at
+        .checked_add(jiff::SignedDuration::from_secs(1))?;
+    gift_code::expire_due_gift_codes(&db.pool, &EventHub::new(), later).await?;
+    gift_code::expire_due_gift_codes(&db.pool, &EventHub::new(), later).await?;
+    assert_eq!(balance(&db, alice).await?, BigDecimal::from(90));
+    assert_eq!(balance(&db, bob).await?, BigDecimal::from(10));
+
+    let mut conn = db.pool.get().await?;
+    let gift_code: GiftCode = gift_codes::table
+        .find(types::Uuid::from(gift_code.id))
+        .select(GiftCode::as_select())
+        .first(&mut conn)
+        .await?;
+    assert_eq!(gift_code.status, GiftCodeStatus::Expired);
+    assert!(gift_code.refund_transaction_id.is_some());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn gift_code_funding_counts_towards_transfer_limits() -> Result<()> {
+    let db = TestDatabase::new().await?;
+    let alice = create_user(&db, "alice", 100).await?;
+    let limits = TransferLimits {
+        per_transaction: Some(BigDecimal::from(10)),
+        ..TransferLimits::default()
+    };
+
+    // Each redemption is within the limit, but all of them together are not.
+    let result = create_gift_code(&db, alice, 6, 2, &limits).await?;
+    assert!(matches!(result, Err(TransferError::LimitExceeded(_))));
+
+    let result = create_gift_code(&db, alice, 200, 1, &TransferLimits::default()).await?;
+    assert!(matches!(result, Err(TransferError::InsufficientBalance)));
+    assert_eq!(balance(&db, alice).await?, BigDecimal::from(100));
+
+    Ok(())
+}
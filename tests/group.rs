@@ -0,0 +1,64 @@
+mod common;
+
+use anyhow::{Context as _, Result};
+use reqwest::{Method, StatusCode};
+use serde_json::json;
+
+use self::common::{TestApp, TestDatabase, create_user};
+
+#[tokio::test]
+async fn expenses_are_recorded_by_their_payer() -> Result<()> {
+    let db = TestDatabase::new().await?;
+    let alice = create_user(&db, "alice", 0).await?;
+    let bob = create_user(&db, "bob", 0).await?;
+    let app = TestApp::start(&db).await?;
+    let alice_token = app.access_token(alice)?;
+
+    let (status, body) = app
+        .request(
+            Method::POST,
+            "/groups",
+            &alice_token,
+            Some(json!({ "name": "lunch", "members": [bob] })),
+        )
+        .await?;
+    assert_eq!(status, StatusCode::CREATED);
+    let expenses_path = format!(
+        "/groups/{}/expenses",
+        body["id"].as_str().context("group should have an ID")?
+    );
+
+    let expense = |payer: serde_json::Value| {
+        json!({
+            "amount": 30,
+            "description": "lunch",
+            "payer": payer,
+            "split": { "type": "equal" },
+        })
+    };
+    let (status, body) = app
+        .request(
+            Method::POST,
+            &expenses_path,
+            &alice_token,
+            Some(expense(json!(bob))),
+        )
+        .await?;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    assert_eq!(body["title"], "PermissionDenied");
+
+    for payer in [json!(alice), serde_json::Value::Null] {
+        let (status, body) = app
+            .request(
+                Method::POST,
+                &expenses_path,
+                &alice_token,
+                Some(expense(payer)),
+            )
+            .await?;
+        assert_eq!(status, StatusCode::CREATED);
+        assert_eq!(body["payer"], alice.to_string());
+    }
+
+    Ok(())
+}
@@ -0,0 +1,87 @@
+mod common;
+
+use anyhow::Result;
+use axum_diesel_example::currency::DEFAULT_CURRENCY;
+use axum_diesel_example::interest;
+use axum_diesel_example::ledger::{self, NewIssuance};
+use axum_diesel_example::models::interest_rate::InterestProduct;
+use axum_diesel_example::models::transaction::TransactionKind;
+use axum_diesel_example::models::{InterestAccrual, InterestCarryover, InterestRate, types};
+use axum_diesel_example::schema::{interest_accruals, interest_carryovers, interest_rates};
+use bigdecimal::{BigDecimal, Zero as _};
+use diesel::prelude::*;
+#[allow(
+    clippy::unused_trait_names,
+    reason = "error[E0034]: multiple applicable items in scope"
+)]
+use diesel_async::RunQueryDsl;
+use jiff::civil::date;
+
+use self::common::{TestDatabase, balance, create_user};
+
+#[tokio::test]
+async fn interest_left_after_rounding_is_carried_over() -> Result<()> {
+    let db = TestDatabase::new().await?;
+    let alice = create_user(&db, "alice", 1000).await?;
+    let mut conn = db.pool.get().await?;
+
+    ledger::issue(
+        &mut conn,
+        &NewIssuance {
+            amount: BigDecimal::from(1000),
+            currency: DEFAULT_CURRENCY,
+            initiator: None,
+            kind: TransactionKind::Mint,
+            memo: None,
+        },
+    )
+    .await?
+    .map_err(|err| anyhow::anyhow!("mint failed: {err:?}"))?;
+    // About 0.274 a day on 1000, so every payout of a single day leaves
+    // a fraction of a cent.
+    diesel::insert_into(interest_rates::table)
+        .values(InterestRate {
+            product: InterestProduct::Account,
+            currency: DEFAULT_CURRENCY,
+            annual_rate: BigDecimal::from(10),
+            updated_at: jiff::Timestamp::now(),
+        })
+        .execute(&mut conn)
+        .await?;
+
+    let mut paid = BigDecimal::zero();
+    for month in 1..=3 {
+        interest::accrue(&mut conn, date(2026, month, 15)).await?;
+        let payments = interest::pay(&mut conn, date(2026, month + 1, 1)).await?;
+        assert_eq!(payments.len(), 1);
+        paid += &payments[0].amount;
+
+        let accrued: BigDecimal = interest_accruals::table
+            .select(InterestAccrual::as_select())
+            .load(&mut conn)
+            .await?
+            .into_iter()
+            .map(|accrual| accrual.amount)
+            .sum();
+        let carryover: InterestCarryover = interest_carryovers::table
+            .find((
+                InterestProduct::Account,
+                types::Uuid::from(alice),
+                DEFAULT_CURRENCY,
+            ))
+            .select(InterestCarryover::as_select())
+            .first(&mut conn)
+            .await?;
+        assert!(carryover.amount < BigDecimal::new(1.into(), 2));
+        assert_eq!(&paid + &carryover.amount, accrued);
+    }
+
+    // Without carrying over, each payout would have been rounded down to 0.27.
+    assert_eq!(paid, BigDecimal::new(82.into(), 2));
+    assert_eq!(
+        balance(&db, alice).await?,
+        BigDecimal::new(100_082.into(), 2)
+    );
+
+    Ok(())
+}
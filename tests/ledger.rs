@@ -0,0 +1,1371 @@
+mod common;
+
+use anyhow::{Context as _, Result};
+use axum_diesel_example::currency::{Currency, DEFAULT_CURRENCY};
+use axum_diesel_example::exchange::{self, NewQuote};
+use axum_diesel_example::fees;
+use axum_diesel_example::ledger::{
+    self, BatchError, IssuanceError, NewBatchTransfer, NewIssuance, NewPocketMove, NewRefund,
+    NewTransfer, NewTransferBatch, PocketMoveError, RefundError, TransferError,
+};
+use axum_diesel_example::limits::{Limit, LimitExceeded, TransferLimits};
+use axum_diesel_example::models::account::NewAccount;
+use axum_diesel_example::models::group::{GroupExpense, GroupExpenseShare, GroupMember};
+use axum_diesel_example::models::hold::{HoldStatus, NewHold};
+use axum_diesel_example::models::transaction::TransactionKind;
+use axum_diesel_example::models::user::{UserRole, UserTier};
+use axum_diesel_example::models::{
+    Account, ExchangeRate, FeeRule, Group, Pocket, Transaction, TransferLimit, types,
+};
+use axum_diesel_example::schema::{
+    accounts, exchange_rates, fee_rules, group_expense_shares, group_expenses, group_members,
+    groups, holds, pockets, transactions, transfer_batches, transfer_limits, users,
+};
+use axum_diesel_example::split::{self, Debt, SettleUpError, Split, SplitError};
+use bigdecimal::BigDecimal;
+use diesel::prelude::*;
+#[allow(
+    clippy::unused_trait_names,
+    reason = "error[E0034]: multiple applicable items in scope"
+)]
+use diesel_async::RunQueryDsl;
+use tokio::task::JoinSet;
+use uuid::Uuid;
+
+use self::common::{TestDatabase, create_user};
+
+const USER_COUNT: usize = 10;
+const INITIAL_BALANCE: u32 = 100;
+const TRANSFER_COUNT: usize = 500;
+
+async fn create_users(db: &TestDatabase, count: usize) -> Result<Vec<Uuid>> {
+    let mut ids = Vec::with_capacity(count);
+    for i in 0..count {
+        ids.push(create_user(db, &format!("user_{i}"), INITIAL_BALANCE).await?);
+    }
+
+    Ok(ids)
+}
+
+async fn load_balances(db: &TestDatabase) -> Result<Vec<BigDecimal>> {
+    let mut conn = db.pool.get().await?;
+
+    let accounts: Vec<Account> = accounts::table
+        .filter(accounts::currency.eq(DEFAULT_CURRENCY))
+        .order(accounts::user_id.asc())
+        .select(Account::as_select())
+        .load(&mut conn)
+        .await
+        .context("failed to query accounts")?;
+
+    Ok(accounts
+        .into_iter()
+        .map(|account| account.balance)
+        .collect())
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 8)]
+async fn concurrent_transfers_conserve_money() -> Result<()> {
+    let db = TestDatabase::new().await?;
+    let user_ids = create_users(&db, USER_COUNT).await?;
+
+    let mut transfers = JoinSet::new();
+    for _ in 0..TRANSFER_COUNT {
+        let pool = db.pool.clone();
+        let sender = user_ids[rand::random_range(..USER_COUNT)];
+        let recipient = loop {
+            let recipient = user_ids[rand::random_range(..USER_COUNT)];
+            if recipient != sender {
+                break recipient;
+            }
+        };
+        let amount = BigDecimal::from(rand::random_range(1..=30_u32));
+
+        transfers.spawn(async move {
+            let mut conn = pool.get().await?;
+            ledger::transfer(
+                &mut conn,
+                &NewTransfer {
+                    amount,
+                    currency: DEFAULT_CURRENCY,
+                    recipient,
+                    sender,
+                    memo: None,
+                    reference: None,
+                    quote_id: None,
+                },
+                &TransferLimits::default(),
+            )
+            .await
+        });
+    }
+    while let Some(result) = transfers.join_next().await {
+        match result?? {
+            Ok(_) | Err(TransferError::InsufficientBalance) => {},
+            Err(err) => panic!("unexpected transfer error: {err:?}"),
+        }
+    }
+
+    let balances = load_balances(&db).await?;
+    let zero = BigDecimal::from(0);
+    assert!(balances.iter().all(|balance| *balance >= zero));
+    assert_eq!(
+        balances.into_iter().sum::<BigDecimal>(),
+        BigDecimal::from(INITIAL_BALANCE) * BigDecimal::from(USER_COUNT as u32),
+    );
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 8)]
+async fn concurrent_transfers_cannot_overdraw_sender() -> Result<()> {
+    let db = TestDatabase::new().await?;
+    let user_ids = create_users(&db, 2).await?;
+    let (sender, recipient) = (user_ids[0], user_ids[1]);
+
+    let mut transfers = JoinSet::new();
+    for _ in 0..50 {
+        let pool = db.pool.clone();
+        transfers.spawn(async move {
+            let mut conn = pool.get().await?;
+            ledger::transfer(
+                &mut conn,
+                &NewTransfer {
+                    amount: BigDecimal::from(10),
+                    currency: DEFAULT_CURRENCY,
+                    recipient,
+                    sender,
+                    memo: None,
+                    reference: None,
+                    quote_id: None,
+                },
+                &TransferLimits::default(),
+            )
+            .await
+        });
+    }
+    let mut succeeded = 0_u32;
+    while let Some(result) = transfers.join_next().await {
+        if result??.is_ok() {
+            succeeded = succeeded.saturating_add(1);
+        }
+    }
+
+    assert_eq!(succeeded, INITIAL_BALANCE / 10);
+    let mut balances = load_balances(&db).await?;
+    balances.sort();
+    assert_eq!(
+        balances,
+        vec![BigDecimal::from(0), BigDecimal::from(INITIAL_BALANCE * 2)]
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn active_holds_reduce_available_balance() -> Result<()> {
+    let db = TestDatabase::new().await?;
+    let user_ids = create_users(&db, 2).await?;
+    let (sender, recipient) = (user_ids[0], user_ids[1]);
+    let mut conn = db.pool.get().await?;
+
+    let now = jiff::Timestamp::now();
+    for (amount, expires_at) in [
+        (60, now + jiff::SignedDuration::from_hours(1)),
+        (40, now - jiff::SignedDuration::from_hours(1)),
+    ] {
+        diesel::insert_into(holds::table)
+            .values(NewHold {
+                id: Uuid::now_v7(),
+                sender,
+                recipient,
+                amount: BigDecimal::from(amount),
+                currency: DEFAULT_CURRENCY,
+                memo: None,
+                reference: None,
+                status: HoldStatus::Active,
+                created_at: now,
+                expires_at,
+            })
+            .execute(&mut conn)
+            .await
+            .context("failed to insert hold")?;
+    }
+    // Only the hold which has not expired counts.
+    assert_eq!(
+        ledger::held_amount(&mut conn, sender, DEFAULT_CURRENCY, now).await?,
+        BigDecimal::from(60)
+    );
+
+    let transfer = |amount: u32| NewTransfer {
+        amount: BigDecimal::from(amount),
+        currency: DEFAULT_CURRENCY,
+        recipient,
+        sender,
+        memo: None,
+        reference: None,
+        quote_id: None,
+    };
+    assert!(matches!(
+        ledger::transfer(&mut conn, &transfer(41), &TransferLimits::default()).await?,
+        Err(TransferError::InsufficientBalance)
+    ));
+    assert!(
+        ledger::transfer(&mut conn, &transfer(40), &TransferLimits::default())
+            .await?
+            .is_ok()
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn refunds_are_limited_to_the_amount_not_yet_refunded() -> Result<()> {
+    let db = TestDatabase::new().await?;
+    let user_ids = create_users(&db, 3).await?;
+    let (sender, recipient, admin) = (user_ids[0], user_ids[1], user_ids[2]);
+    let mut conn = db.pool.get().await?;
+
+    diesel::update(users::table.find(types::Uuid::from(admin)))
+        .set(users::role.eq(UserRole::Admin))
+        .execute(&mut conn)
+        .await
+        .context("failed to update user")?;
+
+    let original = ledger::transfer(
+        &mut conn,
+        &NewTransfer {
+            amount: BigDecimal::from(80),
+            currency: DEFAULT_CURRENCY,
+            recipient,
+            sender,
+            memo: None,
+            reference: None,
+            quote_id: None,
+        },
+        &TransferLimits::default(),
+    )
+    .await?
+    .map_err(|err| anyhow::anyhow!("transfer failed: {err:?}"))?;
+
+    let refund = |initiator: Uuid, kind: TransactionKind, amount: Option<u32>| NewRefund {
+        original_transaction_id: original.id,
+        amount: amount.map(BigDecimal::from),
+        initiator,
+        kind,
+        memo: None,
+    };
+
+    // Only the recipient may refund, and only an admin may reverse.
+    assert!(matches!(
+        ledger::refund(&mut conn, &refund(sender, TransactionKind::Refund, None)).await?,
+        Err(RefundError::PermissionDenied)
+    ));
+    assert!(matches!(
+        ledger::refund(
+            &mut conn,
+            &refund(recipient, TransactionKind::Reversal, None)
+        )
+        .await?,
+        Err(RefundError::PermissionDenied)
+    ));
+
+    let partial_refund = ledger::refund(
+        &mut conn,
+        &refund(recipient, TransactionKind::Refund, Some(30)),
+    )
+    .await?
+    .map_err(|err| anyhow::anyhow!("refund failed: {err:?}"))?;
+    assert_eq!(partial_refund.original_transaction_id, Some(original.id));
+    assert_eq!(partial_refund.sender, recipient);
+    assert_eq!(partial_refund.recipient, sender);
+    assert!(!partial_refund.flagged);
+
+    assert!(matches!(
+        ledger::refund(&mut conn, &refund(recipient, TransactionKind::Refund, Some(51))).await?,
+        Err(RefundError::ExceedsRefundableAmount { refundable_amount })
+            if refundable_amount == BigDecimal::from(50)
+    ));
+    assert!(matches!(
+        ledger::refund(
+            &mut conn,
+            &NewRefund {
+                original_transaction_id: partial_refund.id,
+                ..refund(sender, TransactionKind::Refund, None)
+            },
+        )
+        .await?,
+        Err(RefundError::NotRefundable)
+    ));
+
+    // The recipient spends the money, so only a reversal can get it back.
+    ledger::transfer(
+        &mut conn,
+        &NewTransfer {
+            amount: BigDecimal::from(INITIAL_BALANCE + 40),
+            currency: DEFAULT_CURRENCY,
+            recipient: admin,
+            sender: recipient,
+            memo: None,
+            reference: None,
+            quote_id: None,
+        },
+        &TransferLimits::default(),
+    )
+    .await?
+    .map_err(|err| anyhow::anyhow!("transfer failed: {err:?}"))?;
+    assert!(matches!(
+        ledger::refund(&mut conn, &refund(recipient, TransactionKind::Refund, None)).await?,
+        Err(RefundError::InsufficientBalance)
+    ));
+
+    let reversal = ledger::refund(&mut conn, &refund(admin, TransactionKind::Reversal, None))
+        .await?
+        .map_err(|err| anyhow::anyhow!("reversal failed: {err:?}"))?;
+    assert_eq!(reversal.amount, BigDecimal::from(50));
+    assert!(reversal.flagged);
+
+    let balances = load_balances(&db).await?;
+    assert_eq!(balances[0], BigDecimal::from(INITIAL_BALANCE));
+    assert_eq!(balances[1], BigDecimal::from(-40));
+
+    assert!(matches!(
+        ledger::refund(&mut conn, &refund(admin, TransactionKind::Reversal, None)).await?,
+        Err(RefundError::ExceedsRefundableAmount { .. })
+    ));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn transfers_are_limited() -> Result<()> {
+    let db = TestDatabase::new().await?;
+    let user_ids = create_users(&db, 2).await?;
+    let (sender, recipient) = (user_ids[0], user_ids[1]);
+    let mut conn = db.pool.get().await?;
+
+    let default_limits = TransferLimits {
+        per_transaction: Some(BigDecimal::from(30)),
+        daily: Some(BigDecimal::from(50)),
+        monthly: None,
+        max_transfers: Some(3),
+        transfer_count_window: jiff::SignedDuration::from_mins(1),
+    };
+    let transfer = |amount: u32| NewTransfer {
+        amount: BigDecimal::from(amount),
+        currency: DEFAULT_CURRENCY,
+        recipient,
+        sender,
+        memo: None,
+        reference: None,
+        quote_id: None,
+    };
+
+    assert!(matches!(
+        ledger::transfer(&mut conn, &transfer(31), &default_limits).await?,
+        Err(TransferError::LimitExceeded(LimitExceeded {
+            limit: Limit::PerTransaction,
+            resets_at: None,
+            ..
+        }))
+    ));
+
+    let first = ledger::transfer(&mut conn, &transfer(30), &default_limits)
+        .await?
+        .map_err(|err| anyhow::anyhow!("transfer failed: {err:?}"))?;
+    assert!(
+        ledger::transfer(&mut conn, &transfer(20), &default_limits)
+            .await?
+            .is_ok()
+    );
+    assert!(matches!(
+        ledger::transfer(&mut conn, &transfer(1), &default_limits).await?,
+        Err(TransferError::LimitExceeded(LimitExceeded {
+            limit: Limit::Daily,
+            resets_at: Some(resets_at),
+            ..
+        })) if resets_at > first.timestamp
+    ));
+
+    // An admin raises the daily limit of the sender.
+    diesel::insert_into(transfer_limits::table)
+        .values(TransferLimit {
+            user_id: sender,
+            per_transaction: None,
+            daily: Some(BigDecimal::from(100)),
+            monthly: None,
+            max_transfers: None,
+            updated_at: jiff::Timestamp::now(),
+            per_transaction_removed: false,
+            daily_removed: false,
+            monthly_removed: false,
+            max_transfers_removed: false,
+        })
+        .execute(&mut conn)
+        .await
+        .context("failed to insert transfer limit")?;
+
+    assert!(
+        ledger::transfer(&mut conn, &transfer(1), &default_limits)
+            .await?
+            .is_ok()
+    );
+    assert!(matches!(
+        ledger::transfer(&mut conn, &transfer(1), &default_limits).await?,
+        Err(TransferError::LimitExceeded(LimitExceeded {
+            limit: Limit::TransferCount,
+            resets_at: Some(resets_at),
+            ..
+        })) if resets_at == first.timestamp + default_limits.transfer_count_window
+    ));
+
+    // The admin removes the transfer count limit of the sender.
+    diesel::update(transfer_limits::table.find(types::Uuid::from(sender)))
+        .set(transfer_limits::max_transfers_removed.eq(true))
+        .execute(&mut conn)
+        .await
+        .context("failed to update transfer limit")?;
+
+    assert!(
+        ledger::transfer(&mut conn, &transfer(1), &default_limits)
+            .await?
+            .is_ok()
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn transfers_require_accounts_in_the_same_currency() -> Result<()> {
+    let db = TestDatabase::new().await?;
+    let user_ids = create_users(&db, 2).await?;
+    let (sender, recipient) = (user_ids[0], user_ids[1]);
+    let mut conn = db.pool.get().await?;
+
+    let jpy: Currency = "JPY".parse().map_err(anyhow::Error::msg)?;
+    let transfer = |amount: BigDecimal, currency: Currency| NewTransfer {
+        amount,
+        currency,
+        recipient,
+        sender,
+        memo: None,
+        reference: None,
+        quote_id: None,
+    };
+
+    assert!(matches!(
+        ledger::transfer(
+            &mut conn,
+            &transfer("0.001".parse()?, DEFAULT_CURRENCY),
+            &TransferLimits::default()
+        )
+        .await?,
+        Err(TransferError::AmountTooPrecise(currency)) if currency == DEFAULT_CURRENCY
+    ));
+
+    // Only the sender has a JPY account.
+    diesel::insert_into(accounts::table)
+        .values(NewAccount {
+            user_id: sender,
+            currency: jpy,
+            balance: BigDecimal::from(1_000),
+            created_at: jiff::Timestamp::now(),
+        })
+        .execute(&mut conn)
+        .await
+        .context("failed to insert account")?;
+    assert!(matches!(
+        ledger::transfer(
+            &mut conn,
+            &transfer(BigDecimal::from(100), jpy),
+            &TransferLimits::default()
+        )
+        .await?,
+        Err(TransferError::CurrencyMismatch(currency)) if currency == jpy
+    ));
+    assert!(matches!(
+        ledger::transfer(
+            &mut conn,
+            &transfer("0.5".parse()?, jpy),
+            &TransferLimits::default()
+        )
+        .await?,
+        Err(TransferError::AmountTooPrecise(currency)) if currency == jpy
+    ));
+
+    // Transfers in the default currency are unaffected.
+    assert!(
+        ledger::transfer(
+            &mut conn,
+            &transfer("0.01".parse()?, DEFAULT_CURRENCY),
+            &TransferLimits::default()
+        )
+        .await?
+        .is_ok()
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn limits_are_in_the_default_currency() -> Result<()> {
+    let db = TestDatabase::new().await?;
+    let user_ids = create_users(&db, 2).await?;
+    let (sender, recipient) = (user_ids[0], user_ids[1]);
+    let mut conn = db.pool.get().await?;
+
+    let (sgd, usd): (Currency, Currency) = (
+        "SGD".parse().map_err(anyhow::Error::msg)?,
+        "USD".parse().map_err(anyhow::Error::msg)?,
+    );
+    for user_id in [sender, recipient] {
+        for currency in [sgd, usd] {
+            diesel::insert_into(accounts::table)
+                .values(NewAccount {
+                    user_id,
+                    currency,
+                    balance: BigDecimal::from(INITIAL_BALANCE),
+                    created_at: jiff::Timestamp::now(),
+                })
+                .execute(&mut conn)
+                .await
+                .context("failed to insert account")?;
+        }
+    }
+    // Only the rate the other way is set, and the spread doesn't count.
+    diesel::insert_into(exchange_rates::table)
+        .values(ExchangeRate {
+            source_currency: DEFAULT_CURRENCY,
+            target_currency: sgd,
+            rate: "0.25".parse()?,
+            spread: "0.01".parse()?,
+            updated_at: jiff::Timestamp::now(),
+        })
+        .execute(&mut conn)
+        .await
+        .context("failed to insert exchange rate")?;
+
+    let default_limits = TransferLimits {
+        per_transaction: Some(BigDecimal::from(30)),
+        daily: Some(BigDecimal::from(50)),
+        ..TransferLimits::default()
+    };
+    let transfer = |amount: u32, currency: Currency| NewTransfer {
+        amount: BigDecimal::from(amount),
+        currency,
+        recipient,
+        sender,
+        memo: None,
+        reference: None,
+        quote_id: None,
+    };
+
+    // 8 SGD are worth 32 MYR.
+    assert!(matches!(
+        ledger::transfer(&mut conn, &transfer(8, sgd), &default_limits).await?,
+        Err(TransferError::LimitExceeded(LimitExceeded {
+            limit: Limit::PerTransaction,
+            ..
+        }))
+    ));
+    ledger::transfer(&mut conn, &transfer(7, sgd), &default_limits)
+        .await?
+        .map_err(|err| anyhow::anyhow!("transfer failed: {err:?}"))?;
+
+    // What was sent in SGD counts towards the daily limit in MYR too.
+    assert!(matches!(
+        ledger::transfer(&mut conn, &transfer(23, DEFAULT_CURRENCY), &default_limits).await?,
+        Err(TransferError::LimitExceeded(LimitExceeded {
+            limit: Limit::Daily,
+            ..
+        }))
+    ));
+    ledger::transfer(&mut conn, &transfer(22, DEFAULT_CURRENCY), &default_limits)
+        .await?
+        .map_err(|err| anyhow::anyhow!("transfer failed: {err:?}"))?;
+
+    assert!(matches!(
+        ledger::transfer(&mut conn, &transfer(1, usd), &default_limits).await?,
+        Err(TransferError::RateUnavailable(currency)) if currency == usd
+    ));
+    assert!(
+        ledger::transfer(&mut conn, &transfer(1, usd), &TransferLimits::default())
+            .await?
+            .is_ok()
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn converted_transfers_credit_the_quoted_amount() -> Result<()> {
+    let db = TestDatabase::new().await?;
+    let user_ids = create_users(&db, 2).await?;
+    let (sender, recipient) = (user_ids[0], user_ids[1]);
+    let mut conn = db.pool.get().await?;
+
+    let usd: Currency = "USD".parse().map_err(anyhow::Error::msg)?;
+    diesel::insert_into(accounts::table)
+        .values(NewAccount {
+            user_id: recipient,
+            currency: usd,
+            balance: BigDecimal::from(0),
+            created_at: jiff::Timestamp::now(),
+        })
+        .execute(&mut conn)
+        .await
+        .context("failed to insert account")?;
+    diesel::insert_into(exchange_rates::table)
+        .values(ExchangeRate {
+            source_currency: DEFAULT_CURRENCY,
+            target_currency: usd,
+            rate: "0.21".parse()?,
+            spread: "0.01".parse()?,
+            updated_at: jiff::Timestamp::now(),
+        })
+        .execute(&mut conn)
+        .await
+        .context("failed to insert exchange rate")?;
+
+    let new_quote = NewQuote {
+        user_id: sender,
+        amount: BigDecimal::from(10),
+        source_currency: DEFAULT_CURRENCY,
+        target_currency: usd,
+    };
+    let expired_quote = exchange::create_quote(
+        &mut conn,
+        &new_quote,
+        jiff::Timestamp::now() - exchange::QUOTE_VALIDITY,
+    )
+    .await?
+    .map_err(|err| anyhow::anyhow!("quote failed: {err:?}"))?;
+    let quote = exchange::create_quote(&mut conn, &new_quote, jiff::Timestamp::now())
+        .await?
+        .map_err(|err| anyhow::anyhow!("quote failed: {err:?}"))?;
+    // 10 × 0.21 × (1 − 0.01) = 2.079, rounded down to cents.
+    assert_eq!(quote.target_amount, "2.07".parse()?);
+
+    let transfer = |amount: u32, quote_id: Uuid| NewTransfer {
+        amount: BigDecimal::from(amount),
+        currency: DEFAULT_CURRENCY,
+        recipient,
+        sender,
+        memo: None,
+        reference: None,
+        quote_id: Some(quote_id),
+    };
+
+    assert!(matches!(
+        ledger::transfer(
+            &mut conn,
+            &transfer(10, expired_quote.id),
+            &TransferLimits::default()
+        )
+        .await?,
+        Err(TransferError::QuoteExpired)
+    ));
+    assert!(matches!(
+        ledger::transfer(
+            &mut conn,
+            &transfer(11, quote.id),
+            &TransferLimits::default()
+        )
+        .await?,
+        Err(TransferError::QuoteMismatch)
+    ));
+
+    let converted = ledger::transfer(
+        &mut conn,
+        &transfer(10, quote.id),
+        &TransferLimits::default(),
+    )
+    .await?
+    .map_err(|err| anyhow::anyhow!("transfer failed: {err:?}"))?;
+    assert_eq!(converted.converted_amount, Some("2.07".parse()?));
+    assert_eq!(converted.converted_currency, Some(usd));
+    assert_eq!(converted.exchange_rate, Some("0.21".parse()?));
+
+    assert!(matches!(
+        ledger::transfer(
+            &mut conn,
+            &transfer(10, quote.id),
+            &TransferLimits::default()
+        )
+        .await?,
+        Err(TransferError::QuoteUsed)
+    ));
+
+    let load_usd_balance = async |conn: &mut _| -> Result<BigDecimal> {
+        Ok(ledger::find_account(conn, recipient, usd)
+            .await?
+            .context("could not find account")?
+            .balance)
+    };
+    assert_eq!(load_usd_balance(&mut conn).await?, "2.07".parse()?);
+
+    // Refunds are in the sender's currency, and take back the same share of
+    // what the recipient was credited.
+    let refund = |amount: Option<BigDecimal>| NewRefund {
+        original_transaction_id: converted.id,
+        amount,
+        initiator: recipient,
+        kind: TransactionKind::Refund,
+        memo: None,
+    };
+    let partial_refund = ledger::refund(&mut conn, &refund(Some(BigDecimal::from(5))))
+        .await?
+        .map_err(|err| anyhow::anyhow!("refund failed: {err:?}"))?;
+    assert_eq!(partial_refund.amount, "1.03".parse()?);
+    assert_eq!(partial_refund.currency, usd);
+    assert_eq!(partial_refund.credited_amount(), &BigDecimal::from(5));
+
+    ledger::refund(&mut conn, &refund(None))
+        .await?
+        .map_err(|err| anyhow::anyhow!("refund failed: {err:?}"))?;
+    assert_eq!(load_usd_balance(&mut conn).await?, BigDecimal::from(0));
+    assert_eq!(
+        load_balances(&db).await?,
+        vec![BigDecimal::from(INITIAL_BALANCE); 2]
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn fees_are_charged_to_the_sender_and_paid_to_revenue() -> Result<()> {
+    let db = TestDatabase::new().await?;
+    let user_ids = create_users(&db, 2).await?;
+    let (sender, recipient) = (user_ids[0], user_ids[1]);
+    let mut conn = db.pool.get().await?;
+
+    let fee_rule = |tier: Option<UserTier>, flat: &str, percentage: &str, priority: i32| FeeRule {
+        id: Uuid::now_v7(),
+        currency: Some(DEFAULT_CURRENCY),
+        tier,
+        min_amount: None,
+        max_amount: None,
+        flat: flat.parse().unwrap_or_default(),
+        percentage: percentage.parse().unwrap_or_default(),
+        min_fee: None,
+        max_fee: Some(BigDecimal::from(3)),
+        priority,
+        created_at: jiff::Timestamp::now(),
+    };
+    for fee_rule in [
+        fee_rule(None, "1", "2", 0),
+        fee_rule(Some(UserTier::Premium), "0", "0", 1),
+    ] {
+        diesel::insert_into(fee_rules::table)
+            .values(fee_rule)
+            .execute(&mut conn)
+            .await
+            .context("failed to insert fee rule")?;
+    }
+
+    // 1 + 2% of 1000 = 21, capped at 3.
+    assert_eq!(
+        fees::fee_for(&mut conn, sender, &BigDecimal::from(1000), DEFAULT_CURRENCY).await?,
+        BigDecimal::from(3)
+    );
+
+    let transfer = |amount: u32| NewTransfer {
+        amount: BigDecimal::from(amount),
+        currency: DEFAULT_CURRENCY,
+        recipient,
+        sender,
+        memo: None,
+        reference: None,
+        quote_id: None,
+    };
+
+    // 1 + 2% of 50 = 2.
+    let original = ledger::transfer(&mut conn, &transfer(50), &TransferLimits::default())
+        .await?
+        .map_err(|err| anyhow::anyhow!("transfer failed: {err:?}"))?;
+    assert_eq!(original.fee, Some(BigDecimal::from(2)));
+
+    let load_balance = async |conn: &mut _, user_id: Uuid| -> Result<BigDecimal> {
+        Ok(ledger::find_account(conn, user_id, DEFAULT_CURRENCY)
+            .await?
+            .context("could not find account")?
+            .balance)
+    };
+    assert_eq!(load_balance(&mut conn, sender).await?, BigDecimal::from(48));
+    assert_eq!(
+        load_balance(&mut conn, recipient).await?,
+        BigDecimal::from(150)
+    );
+    assert_eq!(
+        load_balance(&mut conn, fees::REVENUE_USER_ID).await?,
+        BigDecimal::from(2)
+    );
+
+    let fee_transaction: Transaction = transactions::table
+        .filter(transactions::kind.eq(TransactionKind::Fee))
+        .select(Transaction::as_select())
+        .first(&mut conn)
+        .await
+        .context("failed to query fee transaction")?;
+    assert_eq!(fee_transaction.original_transaction_id, Some(original.id));
+    assert_eq!(fee_transaction.sender, sender);
+    assert_eq!(fee_transaction.recipient, fees::REVENUE_USER_ID);
+
+    // The fee is not refundable.
+    assert_eq!(
+        ledger::refunded_amount(&mut conn, original.id).await?,
+        BigDecimal::from(0)
+    );
+
+    // 47 + 1.94 is more than the remaining 48.
+    assert!(matches!(
+        ledger::transfer(&mut conn, &transfer(47), &TransferLimits::default()).await?,
+        Err(TransferError::InsufficientBalance)
+    ));
+
+    diesel::update(users::table.find(types::Uuid::from(sender)))
+        .set(users::tier.eq(UserTier::Premium))
+        .execute(&mut conn)
+        .await
+        .context("failed to update user")?;
+    let free = ledger::transfer(&mut conn, &transfer(48), &TransferLimits::default())
+        .await?
+        .map_err(|err| anyhow::anyhow!("transfer failed: {err:?}"))?;
+    assert_eq!(free.fee, None);
+    assert_eq!(load_balance(&mut conn, sender).await?, BigDecimal::from(0));
+
+    // Money cannot be sent to the revenue account directly.
+    assert!(matches!(
+        ledger::transfer(
+            &mut conn,
+            &NewTransfer {
+                sender: recipient,
+                recipient: fees::REVENUE_USER_ID,
+                ..transfer(1)
+            },
+            &TransferLimits::default()
+        )
+        .await?,
+        Err(TransferError::InvalidRecipient)
+    ));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn money_is_issued_through_the_treasury() -> Result<()> {
+    let db = TestDatabase::new().await?;
+    let user_ids = create_users(&db, 2).await?;
+    let (user, admin) = (user_ids[0], user_ids[1]);
+    let mut conn = db.pool.get().await?;
+
+    let issuance = |kind: TransactionKind, amount: &str| -> Result<NewIssuance> {
+        Ok(NewIssuance {
+            amount: amount.parse()?,
+            currency: DEFAULT_CURRENCY,
+            initiator: Some(admin),
+            kind,
+            memo: None,
+        })
+    };
+    let load_balance = async |conn: &mut _, user_id: Uuid| -> Result<BigDecimal> {
+        Ok(ledger::find_account(conn, user_id, DEFAULT_CURRENCY)
+            .await?
+            .context("could not find account")?
+            .balance)
+    };
+
+    assert!(matches!(
+        ledger::issue(&mut conn, &issuance(TransactionKind::Mint, "0")?).await?,
+        Err(IssuanceError::InvalidAmount)
+    ));
+    assert!(matches!(
+        ledger::issue(&mut conn, &issuance(TransactionKind::Mint, "0.001")?).await?,
+        Err(IssuanceError::AmountTooPrecise(_))
+    ));
+    assert!(matches!(
+        ledger::issue(&mut conn, &issuance(TransactionKind::Burn, "1")?).await?,
+        Err(IssuanceError::InsufficientBalance)
+    ));
+
+    let mint = ledger::issue(&mut conn, &issuance(TransactionKind::Mint, "100")?)
+        .await?
+        .map_err(|err| anyhow::anyhow!("mint failed: {err:?}"))?;
+    assert_eq!(mint.initiator, Some(admin));
+    assert_eq!(
+        load_balance(&mut conn, ledger::TREASURY_USER_ID).await?,
+        BigDecimal::from(100)
+    );
+
+    let grant = ledger::run_immediate_transaction(&mut conn, |conn| {
+        Box::pin(async move {
+            ledger::apply_grant(conn, user, &BigDecimal::from(30), DEFAULT_CURRENCY).await
+        })
+    })
+    .await?
+    .map_err(|err| anyhow::anyhow!("grant failed: {err:?}"))?;
+    assert_eq!(grant.kind, TransactionKind::Grant);
+    assert_eq!(grant.sender, ledger::TREASURY_USER_ID);
+    assert_eq!(
+        load_balance(&mut conn, user).await?,
+        BigDecimal::from(INITIAL_BALANCE + 30)
+    );
+
+    // Granted money is no longer in the treasury, so it can't be burned.
+    assert!(matches!(
+        ledger::issue(&mut conn, &issuance(TransactionKind::Burn, "71")?).await?,
+        Err(IssuanceError::InsufficientBalance)
+    ));
+    ledger::issue(&mut conn, &issuance(TransactionKind::Burn, "70")?)
+        .await?
+        .map_err(|err| anyhow::anyhow!("burn failed: {err:?}"))?;
+    assert_eq!(
+        load_balance(&mut conn, ledger::TREASURY_USER_ID).await?,
+        BigDecimal::from(0)
+    );
+
+    let grant = ledger::run_immediate_transaction(&mut conn, |conn| {
+        Box::pin(async move {
+            ledger::apply_grant(conn, user, &BigDecimal::from(1), DEFAULT_CURRENCY).await
+        })
+    })
+    .await?;
+    assert!(matches!(grant, Err(IssuanceError::InsufficientBalance)));
+
+    // Money can't be sent to the treasury directly.
+    assert!(matches!(
+        ledger::transfer(
+            &mut conn,
+            &NewTransfer {
+                amount: BigDecimal::from(1),
+                currency: DEFAULT_CURRENCY,
+                recipient: ledger::TREASURY_USER_ID,
+                sender: user,
+                memo: None,
+                reference: None,
+                quote_id: None,
+            },
+            &TransferLimits::default()
+        )
+        .await?,
+        Err(TransferError::InvalidRecipient)
+    ));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn batches_are_applied_all_or_nothing() -> Result<()> {
+    let db = TestDatabase::new().await?;
+    let user_ids = create_users(&db, 3).await?;
+    let (sender, first, second) = (user_ids[0], user_ids[1], user_ids[2]);
+    let mut conn = db.pool.get().await?;
+
+    let batch = |transfers: &[(Uuid, u32)]| NewTransferBatch {
+        sender,
+        currency: DEFAULT_CURRENCY,
+        transfers: transfers
+            .iter()
+            .map(|&(recipient, amount)| NewBatchTransfer {
+                amount: BigDecimal::from(amount),
+                recipient,
+                memo: None,
+                reference: None,
+            })
+            .collect(),
+    };
+
+    let (created_batch, created_transactions) = ledger::batch_transfer(
+        &mut conn,
+        &batch(&[(first, 30), (second, 40)]),
+        &TransferLimits::default(),
+    )
+    .await?
+    .map_err(|err| anyhow::anyhow!("batch failed: {err:?}"))?;
+    assert_eq!(created_batch.sender, sender);
+    assert_eq!(created_transactions.len(), 2);
+    assert_eq!(created_transactions[1].recipient, second);
+    assert_eq!(
+        load_balances(&db).await?,
+        vec![
+            BigDecimal::from(30),
+            BigDecimal::from(INITIAL_BALANCE + 30),
+            BigDecimal::from(INITIAL_BALANCE + 40),
+        ]
+    );
+
+    assert!(matches!(
+        ledger::batch_transfer(&mut conn, &batch(&[]), &TransferLimits::default()).await?,
+        Err(BatchError::Empty)
+    ));
+
+    // Each transfer would succeed on its own, but not after the ones before
+    // it, and all failures are reported.
+    let result = ledger::batch_transfer(
+        &mut conn,
+        &batch(&[(first, 20), (sender, 1), (second, 20)]),
+        &TransferLimits::default(),
+    )
+    .await?;
+    let Err(BatchError::Rejected(errors)) = result else {
+        anyhow::bail!("batch should have been rejected: {result:?}");
+    };
+    assert!(matches!(
+        &errors[..],
+        [
+            (1, TransferError::SelfTransfer),
+            (2, TransferError::InsufficientBalance),
+        ]
+    ));
+
+    // Nothing of the rejected batch was kept.
+    assert_eq!(
+        load_balances(&db).await?,
+        vec![
+            BigDecimal::from(30),
+            BigDecimal::from(INITIAL_BALANCE + 30),
+            BigDecimal::from(INITIAL_BALANCE + 40),
+        ]
+    );
+    let batch_count: i64 = transfer_batches::table
+        .count()
+        .get_result(&mut conn)
+        .await
+        .context("failed to count transfer batches")?;
+    assert_eq!(batch_count, 1);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn batches_count_as_one_transfer() -> Result<()> {
+    let db = TestDatabase::new().await?;
+    let user_ids = create_users(&db, 4).await?;
+    let (sender, recipients) = (user_ids[0], &user_ids[1..]);
+    let mut conn = db.pool.get().await?;
+
+    let default_limits = TransferLimits {
+        max_transfers: Some(2),
+        ..TransferLimits::default()
+    };
+    let batch = NewTransferBatch {
+        sender,
+        currency: DEFAULT_CURRENCY,
+        transfers: recipients
+            .iter()
+            .map(|&recipient| NewBatchTransfer {
+                amount: BigDecimal::from(1),
+                recipient,
+                memo: None,
+                reference: None,
+            })
+            .collect(),
+    };
+
+    // Each batch has more transfers than the limit, but counts as one.
+    for _ in 0..2 {
+        ledger::batch_transfer(&mut conn, &batch, &default_limits)
+            .await?
+            .map_err(|err| anyhow::anyhow!("batch failed: {err:?}"))?;
+    }
+
+    let result = ledger::batch_transfer(&mut conn, &batch, &default_limits).await?;
+    let Err(BatchError::Rejected(errors)) = result else {
+        anyhow::bail!("batch should have been rejected: {result:?}");
+    };
+    assert!(matches!(
+        &errors[..],
+        [(
+            0,
+            TransferError::LimitExceeded(LimitExceeded {
+                limit: Limit::TransferCount,
+                ..
+            })
+        )]
+    ));
+    assert!(matches!(
+        ledger::transfer(
+            &mut conn,
+            &NewTransfer {
+                amount: BigDecimal::from(1),
+                currency: DEFAULT_CURRENCY,
+                recipient: recipients[0],
+                sender,
+                memo: None,
+                reference: None,
+                quote_id: None,
+            },
+            &default_limits
+        )
+        .await?,
+        Err(TransferError::LimitExceeded(LimitExceeded {
+            limit: Limit::TransferCount,
+            ..
+        }))
+    ));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn group_expenses_are_split_and_settled() -> Result<()> {
+    let db = TestDatabase::new().await?;
+    let user_ids = create_users(&db, 3).await?;
+    let (alice, bob, carol) = (user_ids[0], user_ids[1], user_ids[2]);
+    let mut conn = db.pool.get().await?;
+
+    let amount = |amount: &str| amount.parse::<BigDecimal>().unwrap();
+
+    // What can't be split evenly is spread over the first members.
+    assert_eq!(
+        split::split(
+            &amount("10"),
+            DEFAULT_CURRENCY,
+            &Split::Equal(user_ids.clone()),
+            &user_ids
+        )
+        .map_err(|err| anyhow::anyhow!("split failed: {err:?}"))?,
+        vec![
+            (alice, amount("3.34")),
+            (bob, amount("3.33")),
+            (carol, amount("3.33")),
+        ]
+    );
+    assert_eq!(
+        split::split(
+            &amount("10"),
+            DEFAULT_CURRENCY,
+            &Split::Shares(vec![(alice, 0), (bob, 2), (carol, 1)]),
+            &user_ids
+        )
+        .map_err(|err| anyhow::anyhow!("split failed: {err:?}"))?,
+        vec![
+            (alice, amount("0")),
+            (bob, amount("6.67")),
+            (carol, amount("3.33")),
+        ]
+    );
+    assert!(matches!(
+        split::split(
+            &amount("10"),
+            DEFAULT_CURRENCY,
+            &Split::Exact(vec![(alice, amount("4")), (bob, amount("5"))]),
+            &user_ids
+        ),
+        Err(SplitError::AmountMismatch { .. })
+    ));
+    assert!(matches!(
+        split::split(
+            &amount("10"),
+            DEFAULT_CURRENCY,
+            &Split::Equal(vec![alice, Uuid::now_v7()]),
+            &user_ids
+        ),
+        Err(SplitError::NotMember(_))
+    ));
+
+    let group: Group = diesel::insert_into(groups::table)
+        .values(Group {
+            id: Uuid::now_v7(),
+            name: "Trip".to_owned(),
+            currency: DEFAULT_CURRENCY,
+            created_by: alice,
+            created_at: jiff::Timestamp::now(),
+        })
+        .returning(Group::as_returning())
+        .get_result(&mut conn)
+        .await
+        .context("failed to insert group")?;
+    for &user_id in &user_ids {
+        diesel::insert_into(group_members::table)
+            .values(GroupMember {
+                group_id: group.id,
+                user_id,
+                joined_at: jiff::Timestamp::now(),
+            })
+            .execute(&mut conn)
+            .await
+            .context("failed to insert group member")?;
+    }
+
+    // Alice paid 30 for everyone, and Bob paid 15 for Carol.
+    for (payer, total, split) in [
+        (alice, 30, Split::Equal(user_ids.clone())),
+        (bob, 15, Split::Exact(vec![(carol, amount("15"))])),
+    ] {
+        let expense: GroupExpense = diesel::insert_into(group_expenses::table)
+            .values(GroupExpense {
+                id: Uuid::now_v7(),
+                group_id: group.id,
+                payer,
+                amount: BigDecimal::from(total),
+                description: "Expense".to_owned(),
+                created_at: jiff::Timestamp::now(),
+            })
+            .returning(GroupExpense::as_returning())
+            .get_result(&mut conn)
+            .await
+            .context("failed to insert group expense")?;
+        let shares = split::split(&expense.amount, group.currency, &split, &user_ids)
+            .map_err(|err| anyhow::anyhow!("split failed: {err:?}"))?;
+        for (user_id, amount) in shares {
+            diesel::insert_into(group_expense_shares::table)
+                .values(GroupExpenseShare {
+                    expense_id: expense.id,
+                    user_id,
+                    amount,
+                })
+                .execute(&mut conn)
+                .await
+                .context("failed to insert group expense share")?;
+        }
+    }
+
+    let balances = split::group_balances(&mut conn, group.id).await?;
+    assert_eq!(
+        balances.values().cloned().collect::<Vec<_>>(),
+        vec![amount("20"), amount("5"), amount("-25")]
+    );
+    assert_eq!(
+        split::simplify(&balances),
+        vec![
+            Debt {
+                from: carol,
+                to: alice,
+                amount: amount("20"),
+            },
+            Debt {
+                from: carol,
+                to: bob,
+                amount: amount("5"),
+            },
+        ]
+    );
+
+    assert!(matches!(
+        split::settle_up(&mut conn, &group, alice, &TransferLimits::default()).await?,
+        Err(SettleUpError::NothingOwed)
+    ));
+
+    let settlements = split::settle_up(&mut conn, &group, carol, &TransferLimits::default())
+        .await?
+        .map_err(|err| anyhow::anyhow!("settle up failed: {err:?}"))?;
+    assert_eq!(settlements.len(), 2);
+    assert!(
+        split::group_balances(&mut conn, group.id)
+            .await?
+            .values()
+            .all(|balance| *balance == BigDecimal::from(0))
+    );
+    assert_eq!(
+        load_balances(&db).await?,
+        vec![
+            BigDecimal::from(INITIAL_BALANCE + 20),
+            BigDecimal::from(INITIAL_BALANCE + 5),
+            BigDecimal::from(INITIAL_BALANCE - 25),
+        ]
+    );
+
+    assert!(matches!(
+        split::settle_up(&mut conn, &group, carol, &TransferLimits::default()).await?,
+        Err(SettleUpError::NothingOwed)
+    ));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn pocket_moves_keep_money_with_the_user() -> Result<()> {
+    let db = TestDatabase::new().await?;
+    let user_ids = create_users(&db, 2).await?;
+    let (owner, other) = (user_ids[0], user_ids[1]);
+    let mut conn = db.pool.get().await?;
+
+    let pocket: Pocket = diesel::insert_into(pockets::table)
+        .values(Pocket {
+            id: Uuid::now_v7(),
+            user_id: owner,
+            name: "Holiday".to_owned(),
+            currency: DEFAULT_CURRENCY,
+            balance: BigDecimal::from(0),
+            target_amount: Some(BigDecimal::from(500)),
+            target_date: None,
+            created_at: jiff::Timestamp::now(),
+            archived_at: None,
+        })
+        .returning(Pocket::as_returning())
+        .get_result(&mut conn)
+        .await
+        .context("failed to insert pocket")?;
+
+    let pocket_move = |user_id: Uuid, amount: u32, kind: TransactionKind| NewPocketMove {
+        user_id,
+        pocket_id: pocket.id,
+        amount: BigDecimal::from(amount),
+        kind,
+    };
+
+    let (deposit, updated_pocket) = ledger::move_pocket(
+        &mut conn,
+        &pocket_move(owner, 40, TransactionKind::PocketDeposit),
+    )
+    .await?
+    .map_err(|err| anyhow::anyhow!("deposit failed: {err:?}"))?;
+    assert_eq!(deposit.sender, owner);
+    assert_eq!(deposit.recipient, owner);
+    assert_eq!(deposit.pocket_id, Some(pocket.id));
+    assert_eq!(updated_pocket.balance, BigDecimal::from(40));
+    assert_eq!(
+        load_balances(&db).await?,
+        vec![
+            BigDecimal::from(INITIAL_BALANCE - 40),
+            BigDecimal::from(INITIAL_BALANCE),
+        ]
+    );
+
+    // Only what is in the account can be moved in, and only what is in the
+    // pocket can be moved out.
+    assert!(matches!(
+        ledger::move_pocket(
+            &mut conn,
+            &pocket_move(owner, INITIAL_BALANCE, TransactionKind::PocketDeposit),
+        )
+        .await?,
+        Err(PocketMoveError::InsufficientBalance)
+    ));
+    assert!(matches!(
+        ledger::move_pocket(
+            &mut conn,
+            &pocket_move(owner, 41, TransactionKind::PocketWithdrawal),
+        )
+        .await?,
+        Err(PocketMoveError::InsufficientBalance)
+    ));
+    // Pockets of others can't be used.
+    assert!(matches!(
+        ledger::move_pocket(
+            &mut conn,
+            &pocket_move(other, 10, TransactionKind::PocketWithdrawal),
+        )
+        .await?,
+        Err(PocketMoveError::PocketNotFound)
+    ));
+
+    let (withdrawal, updated_pocket) = ledger::move_pocket(
+        &mut conn,
+        &pocket_move(owner, 15, TransactionKind::PocketWithdrawal),
+    )
+    .await?
+    .map_err(|err| anyhow::anyhow!("withdrawal failed: {err:?}"))?;
+    assert_eq!(withdrawal.kind, TransactionKind::PocketWithdrawal);
+    assert_eq!(updated_pocket.balance, BigDecimal::from(25));
+    assert_eq!(
+        load_balances(&db).await?,
+        vec![
+            BigDecimal::from(INITIAL_BALANCE - 25),
+            BigDecimal::from(INITIAL_BALANCE),
+        ]
+    );
+
+    Ok(())
+}
@@ -0,0 +1,156 @@
+mod common;
+
+use anyhow::{Context as _, Result};
+use axum_diesel_example::currency::DEFAULT_CURRENCY;
+use axum_diesel_example::ledger::{self, NewTransfer, TransferError};
+use axum_diesel_example::limits::TransferLimits;
+use axum_diesel_example::models::notification::{NotificationKind, NotificationPreference};
+use axum_diesel_example::models::{Notification, types};
+use axum_diesel_example::notification;
+use axum_diesel_example::schema::{notification_preferences, notifications};
+use bigdecimal::BigDecimal;
+use diesel::prelude::*;
+#[allow(
+    clippy::unused_trait_names,
+    reason = "error[E0034]: multiple applicable items in scope"
+)]
+use diesel_async::RunQueryDsl;
+use uuid::Uuid;
+
+use self::common::{TestDatabase, create_user};
+
+async fn send(
+    db: &TestDatabase,
+    sender: Uuid,
+    recipient: Uuid,
+    amount: u32,
+    limits: &TransferLimits,
+) -> Result<Result<(), TransferError>> {
+    let mut conn = db.pool.get().await?;
+
+    let result = ledger::transfer(
+        &mut conn,
+        &NewTransfer {
+            amount: BigDecimal::from(amount),
+            currency: DEFAULT_CURRENCY,
+            recipient,
+            sender,
+            memo: None,
+            reference: None,
+            quote_id: None,
+        },
+        limits,
+    )
+    .await?;
+
+    Ok(result.map(|_transaction| ()))
+}
+
+async fn load_notifications(db: &TestDatabase, user_id: Uuid) -> Result<Vec<Notification>> {
+    let mut conn = db.pool.get().await?;
+
+    notifications::table
+        .filter(notifications::user_id.eq(types::Uuid::from(user_id)))
+        .select(Notification::as_select())
+        .order(notifications::id.asc())
+        .load(&mut conn)
+        .await
+        .context("failed to query notifications")
+}
+
+#[tokio::test]
+async fn recipient_is_notified_of_money_received_unless_turned_off() -> Result<()> {
+    let db = TestDatabase::new().await?;
+    let alice = create_user(&db, "alice", 100).await?;
+    let bob = create_user(&db, "bob", 0).await?;
+
+    send(&db, alice, bob, 25, &TransferLimits::default())
+        .await?
+        .map_err(|err| anyhow::anyhow!("transfer failed: {err:?}"))?;
+
+    // Only the recipient is notified.
+    assert!(load_notifications(&db, alice).await?.is_empty());
+    let notifications = load_notifications(&db, bob).await?;
+    assert_eq!(notifications.len(), 1);
+    assert_eq!(notifications[0].kind, NotificationKind::MoneyReceived);
+    assert!(notifications[0].read_at.is_none());
+    let data: serde_json::Value = serde_json::from_str(&notifications[0].data)?;
+    assert_eq!(data["sender"], alice.to_string());
+    assert_eq!(data["amount"], 25);
+
+    let mut conn = db.pool.get().await?;
+    diesel::insert_into(notification_preferences::table)
+        .values(NotificationPreference {
+            user_id: bob,
+            kind: NotificationKind::MoneyReceived,
+            enabled: false,
+        })
+        .execute(&mut conn)
+        .await?;
+
+    send(&db, alice, bob, 25, &TransferLimits::default())
+        .await?
+        .map_err(|err| anyhow::anyhow!("transfer failed: {err:?}"))?;
+    assert_eq!(load_notifications(&db, bob).await?.len(), 1);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn limit_reached_is_not_repeated_while_unread() -> Result<()> {
+    let db = TestDatabase::new().await?;
+    let alice = create_user(&db, "alice", 100).await?;
+    let bob = create_user(&db, "bob", 0).await?;
+    let limits = TransferLimits {
+        per_transaction: Some(BigDecimal::from(10)),
+        ..TransferLimits::default()
+    };
+
+    for _ in 0..2 {
+        let result = send(&db, alice, bob, 20, &limits).await?;
+        assert!(matches!(result, Err(TransferError::LimitExceeded(_))));
+    }
+
+    let notifications = load_notifications(&db, alice).await?;
+    assert_eq!(notifications.len(), 1);
+    assert_eq!(notifications[0].kind, NotificationKind::LimitReached);
+    let data: serde_json::Value = serde_json::from_str(&notifications[0].data)?;
+    assert_eq!(data["limit"], "per_transaction");
+    assert_eq!(data["max"], 10);
+
+    // Once read, the user is told again.
+    let mut conn = db.pool.get().await?;
+    diesel::update(notifications::table)
+        .set(notifications::read_at.eq(types::Timestamp::from(jiff::Timestamp::now())))
+        .execute(&mut conn)
+        .await?;
+
+    let result = send(&db, alice, bob, 20, &limits).await?;
+    assert!(matches!(result, Err(TransferError::LimitExceeded(_))));
+    assert_eq!(load_notifications(&db, alice).await?.len(), 2);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn login_from_new_device_is_notified() -> Result<()> {
+    let db = TestDatabase::new().await?;
+    let alice = create_user(&db, "alice", 0).await?;
+    let mut conn = db.pool.get().await?;
+
+    // Neither the first device nor a known one is notified.
+    notification::record_login(&mut conn, alice, Some("laptop")).await?;
+    notification::record_login(&mut conn, alice, Some("laptop")).await?;
+    assert!(load_notifications(&db, alice).await?.is_empty());
+
+    notification::record_login(&mut conn, alice, Some("phone")).await?;
+    notification::record_login(&mut conn, alice, Some("phone")).await?;
+
+    let notifications = load_notifications(&db, alice).await?;
+    assert_eq!(notifications.len(), 1);
+    assert_eq!(notifications[0].kind, NotificationKind::NewDeviceLogin);
+    let data: serde_json::Value = serde_json::from_str(&notifications[0].data)?;
+    assert_eq!(data["user_agent"], "phone");
+
+    Ok(())
+}
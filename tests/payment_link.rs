@@ -0,0 +1,122 @@
+mod common;
+
+use anyhow::{Context as _, Result};
+use reqwest::{Method, StatusCode};
+use serde_json::json;
+
+use self::common::{TestApp, TestDatabase, create_user};
+
+#[tokio::test]
+async fn payment_link_is_verified() -> Result<()> {
+    let db = TestDatabase::new().await?;
+    let alice = create_user(&db, "alice", 0).await?;
+    let bob = create_user(&db, "bob", 100).await?;
+    let app = TestApp::start(&db).await?;
+    let (alice_token, bob_token) = (app.access_token(alice)?, app.access_token(bob)?);
+
+    let (status, body) = app
+        .request(
+            Method::POST,
+            "/payment-links",
+            &alice_token,
+            Some(json!({ "amount": 25, "memo": "lunch" })),
+        )
+        .await?;
+    assert_eq!(status, StatusCode::OK);
+    let token = body["token"]
+        .as_str()
+        .context("payment link should have a token")?;
+    assert!(
+        body["uri"]
+            .as_str()
+            .is_some_and(|uri| uri.starts_with("http://localhost/index.html?pay="))
+    );
+
+    let (status, body) = app
+        .request(
+            Method::POST,
+            "/payment-links/verify",
+            &bob_token,
+            Some(json!({ "token": token })),
+        )
+        .await?;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["recipient"]["id"], alice.to_string());
+    assert_eq!(body["recipient"]["username"], "alice");
+    assert_eq!(body["amount"], 25);
+    assert_eq!(body["memo"], "lunch");
+
+    // Swapping in other claims breaks the signature.
+    let link_parts: Vec<&str> = token.split('.').collect();
+    let access_token_parts: Vec<&str> = bob_token.split('.').collect();
+    let tampered = [link_parts[0], access_token_parts[1], link_parts[2]].join(".");
+    let (status, body) = app
+        .request(
+            Method::POST,
+            "/payment-links/verify",
+            &bob_token,
+            Some(json!({ "token": tampered })),
+        )
+        .await?;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(body["title"], "InvalidPaymentLink");
+
+    let (status, body) = app
+        .request(
+            Method::POST,
+            "/payment-links",
+            &alice_token,
+            Some(json!({ "amount": -1 })),
+        )
+        .await?;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(body["title"], "InvalidAmount");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn payment_link_and_access_token_are_not_interchangeable() -> Result<()> {
+    let db = TestDatabase::new().await?;
+    let alice = create_user(&db, "alice", 0).await?;
+    let app = TestApp::start(&db).await?;
+    let access_token = app.access_token(alice)?;
+
+    let (status, body) = app
+        .request(
+            Method::POST,
+            "/payment-links",
+            &access_token,
+            Some(json!({})),
+        )
+        .await?;
+    assert_eq!(status, StatusCode::OK);
+    let payment_link = body["token"]
+        .as_str()
+        .context("payment link should have a token")?;
+
+    let (status, _body) = app
+        .request(Method::GET, &format!("/users/{alice}"), &access_token, None)
+        .await?;
+    assert_eq!(status, StatusCode::OK);
+
+    // Both are signed with the same key and name the user as their subject, so
+    // only their "typ" header parameters tell them apart.
+    let (status, _body) = app
+        .request(Method::GET, &format!("/users/{alice}"), payment_link, None)
+        .await?;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+    let (status, body) = app
+        .request(
+            Method::POST,
+            "/payment-links/verify",
+            &access_token,
+            Some(json!({ "token": access_token })),
+        )
+        .await?;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(body["title"], "InvalidPaymentLink");
+
+    Ok(())
+}
@@ -0,0 +1,266 @@
+mod common;
+
+use anyhow::{Context as _, Result};
+use axum_diesel_example::models::types;
+use axum_diesel_example::schema::payment_requests;
+use bigdecimal::BigDecimal;
+use diesel::prelude::*;
+#[allow(
+    clippy::unused_trait_names,
+    reason = "error[E0034]: multiple applicable items in scope"
+)]
+use diesel_async::RunQueryDsl;
+use reqwest::{Method, StatusCode};
+use serde_json::json;
+use uuid::Uuid;
+
+use self::common::{TestApp, TestDatabase, balance, create_user};
+
+fn payment_request_id(body: &serde_json::Value) -> Result<Uuid> {
+    body["id"]
+        .as_str()
+        .context("payment request should have an ID")?
+        .parse()
+        .context("payment request ID should be a UUID")
+}
+
+#[tokio::test]
+async fn payment_request_is_paid_by_the_payer() -> Result<()> {
+    let db = TestDatabase::new().await?;
+    let alice = create_user(&db, "alice", 0).await?;
+    let bob = create_user(&db, "bob", 100).await?;
+    let app = TestApp::start(&db).await?;
+    let (alice_token, bob_token) = (app.access_token(alice)?, app.access_token(bob)?);
+
+    let (status, body) = app
+        .request(
+            Method::POST,
+            "/payment-requests",
+            &alice_token,
+            Some(json!({ "amount": 25, "payer": "bob", "memo": "  lunch\n" })),
+        )
+        .await?;
+    assert_eq!(status, StatusCode::CREATED);
+    assert_eq!(body["status"], "pending");
+    assert_eq!(body["payer"], bob.to_string());
+    assert_eq!(body["memo"], "lunch");
+    let id = payment_request_id(&body)?;
+
+    let (status, body) = app
+        .request(Method::GET, "/payment-requests/incoming", &bob_token, None)
+        .await?;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["payment_requests"][0]["id"], id.to_string());
+
+    // The requester can't pay their own payment request.
+    let (status, _body) = app
+        .request(
+            Method::POST,
+            &format!("/payment-requests/{id}/accept"),
+            &alice_token,
+            None,
+        )
+        .await?;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+
+    let (status, body) = app
+        .request(
+            Method::POST,
+            &format!("/payment-requests/{id}/accept"),
+            &bob_token,
+            None,
+        )
+        .await?;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["status"], "paid");
+    assert!(body["transaction_id"].is_string());
+    assert_eq!(balance(&db, alice).await?, BigDecimal::from(25));
+    assert_eq!(balance(&db, bob).await?, BigDecimal::from(75));
+
+    let (status, body) = app
+        .request(
+            Method::POST,
+            &format!("/payment-requests/{id}/accept"),
+            &bob_token,
+            None,
+        )
+        .await?;
+    assert_eq!(status, StatusCode::CONFLICT);
+    assert_eq!(body["title"], "PaymentRequestNotPending");
+    assert_eq!(balance(&db, bob).await?, BigDecimal::from(75));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn payment_request_is_declined_only_by_the_payer() -> Result<()> {
+    let db = TestDatabase::new().await?;
+    let alice = create_user(&db, "alice", 0).await?;
+    let bob = create_user(&db, "bob", 100).await?;
+    let carol = create_user(&db, "carol", 100).await?;
+    let app = TestApp::start(&db).await?;
+    let (alice_token, bob_token, carol_token) = (
+        app.access_token(alice)?,
+        app.access_token(bob)?,
+        app.access_token(carol)?,
+    );
+
+    let (status, body) = app
+        .request(
+            Method::POST,
+            "/payment-requests",
+            &alice_token,
+            Some(json!({ "amount": 25, "payer": bob })),
+        )
+        .await?;
+    assert_eq!(status, StatusCode::CREATED);
+    let id = payment_request_id(&body)?;
+    let decline = format!("/payment-requests/{id}/decline");
+
+    // Others can't see the payment request at all.
+    let (status, _body) = app
+        .request(Method::POST, &decline, &carol_token, None)
+        .await?;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    let (status, _body) = app
+        .request(Method::POST, &decline, &alice_token, None)
+        .await?;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+
+    let (status, body) = app
+        .request(Method::POST, &decline, &bob_token, None)
+        .await?;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["status"], "declined");
+
+    let (status, _body) = app
+        .request(
+            Method::POST,
+            &format!("/payment-requests/{id}/accept"),
+            &bob_token,
+            None,
+        )
+        .await?;
+    assert_eq!(status, StatusCode::CONFLICT);
+
+    // A payment request which is open to anyone can't be declined, only
+    // cancelled by the requester.
+    let (status, body) = app
+        .request(
+            Method::POST,
+            "/payment-requests",
+            &alice_token,
+            Some(json!({ "amount": 25 })),
+        )
+        .await?;
+    assert_eq!(status, StatusCode::CREATED);
+    let id = payment_request_id(&body)?;
+
+    let (status, _body) = app
+        .request(
+            Method::POST,
+            &format!("/payment-requests/{id}/decline"),
+            &bob_token,
+            None,
+        )
+        .await?;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    let (status, _body) = app
+        .request(
+            Method::POST,
+            &format!("/payment-requests/{id}/cancel"),
+            &bob_token,
+            None,
+        )
+        .await?;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    let (status, body) = app
+        .request(
+            Method::POST,
+            &format!("/payment-requests/{id}/cancel"),
+            &alice_token,
+            None,
+        )
+        .await?;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["status"], "cancelled");
+
+    assert_eq!(balance(&db, alice).await?, BigDecimal::from(0));
+    assert_eq!(balance(&db, bob).await?, BigDecimal::from(100));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn expired_payment_request_cannot_be_paid() -> Result<()> {
+    let db = TestDatabase::new().await?;
+    let alice = create_user(&db, "alice", 0).await?;
+    let bob = create_user(&db, "bob", 100).await?;
+    let app = TestApp::start(&db).await?;
+    let (alice_token, bob_token) = (app.access_token(alice)?, app.access_token(bob)?);
+
+    let now = jiff::Timestamp::now();
+    for expires_at in [
+        now.checked_sub(jiff::SignedDuration::from_hours(1))?,
+        now.checked_add(jiff::SignedDuration::from_hours(24 * 365))?,
+    ] {
+        let (status, body) = app
+            .request(
+                Method::POST,
+                "/payment-requests",
+                &alice_token,
+                Some(json!({ "amount": 25, "payer": bob, "expires_at": expires_at })),
+            )
+            .await?;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body["title"], "InvalidExpiry");
+    }
+
+    let (status, body) = app
+        .request(
+            Method::POST,
+            "/payment-requests",
+            &alice_token,
+            Some(json!({
+                "amount": 25,
+                "payer": bob,
+                "expires_at": now.checked_add(jiff::SignedDuration::from_hours(1))?,
+            })),
+        )
+        .await?;
+    assert_eq!(status, StatusCode::CREATED);
+    let id = payment_request_id(&body)?;
+
+    let mut conn = db.pool.get().await?;
+    diesel::update(payment_requests::table.find(types::Uuid::from(id)))
+        .set(payment_requests::expires_at.eq(types::Timestamp::from(
+            now.checked_sub(jiff::SignedDuration::from_secs(1))?,
+        )))
+        .execute(&mut conn)
+        .await?;
+
+    let (status, body) = app
+        .request(
+            Method::GET,
+            &format!("/payment-requests/{id}"),
+            &bob_token,
+            None,
+        )
+        .await?;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["status"], "expired");
+
+    let (status, body) = app
+        .request(
+            Method::POST,
+            &format!("/payment-requests/{id}/accept"),
+            &bob_token,
+            None,
+        )
+        .await?;
+    assert_eq!(status, StatusCode::CONFLICT);
+    assert_eq!(body["detail"], "payment request is expired");
+    assert_eq!(balance(&db, bob).await?, BigDecimal::from(100));
+
+    Ok(())
+}
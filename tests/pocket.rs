@@ -0,0 +1,87 @@
+mod common;
+
+use anyhow::{Context as _, Result};
+use axum_diesel_example::models::types;
+use axum_diesel_example::schema::transactions;
+use diesel::prelude::*;
+#[allow(
+    clippy::unused_trait_names,
+    reason = "error[E0034]: multiple applicable items in scope"
+)]
+use diesel_async::RunQueryDsl;
+use reqwest::{Method, StatusCode};
+use serde_json::json;
+use uuid::Uuid;
+
+use self::common::{TestApp, TestDatabase, create_user};
+
+#[tokio::test]
+async fn deleted_pockets_stay_in_the_history() -> Result<()> {
+    let db = TestDatabase::new().await?;
+    let alice = create_user(&db, "alice", 100).await?;
+    let app = TestApp::start(&db).await?;
+    let alice_token = app.access_token(alice)?;
+    let pockets_path = format!("/users/{alice}/pockets");
+
+    let (status, body) = app
+        .request(
+            Method::POST,
+            &pockets_path,
+            &alice_token,
+            Some(json!({ "name": "Holiday" })),
+        )
+        .await?;
+    assert_eq!(status, StatusCode::CREATED);
+    let pocket_id: Uuid = body["id"]
+        .as_str()
+        .context("pocket should have an ID")?
+        .parse()?;
+    let pocket_path = format!("{pockets_path}/{pocket_id}");
+    for direction in ["deposit", "withdrawal"] {
+        let (status, _body) = app
+            .request(
+                Method::POST,
+                &format!("{pocket_path}/{direction}"),
+                &alice_token,
+                Some(json!({ "amount": 10 })),
+            )
+            .await?;
+        assert_eq!(status, StatusCode::CREATED, "{direction}");
+    }
+
+    let (status, _body) = app
+        .request(Method::DELETE, &pocket_path, &alice_token, None)
+        .await?;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+
+    let (status, body) = app
+        .request(Method::GET, &pocket_path, &alice_token, None)
+        .await?;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    assert_eq!(body["title"], "PocketNotFound");
+    let (status, body) = app
+        .request(Method::GET, &pockets_path, &alice_token, None)
+        .await?;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["pockets"], json!([]));
+    let (status, _body) = app
+        .request(
+            Method::POST,
+            &format!("{pocket_path}/deposit"),
+            &alice_token,
+            Some(json!({ "amount": 10 })),
+        )
+        .await?;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    let mut conn = db.pool.get().await?;
+    let moves: i64 = transactions::table
+        .filter(transactions::pocket_id.eq(types::Uuid::from(pocket_id)))
+        .count()
+        .get_result(&mut conn)
+        .await
+        .context("failed to count transactions")?;
+    assert_eq!(moves, 2);
+
+    Ok(())
+}
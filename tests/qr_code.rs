@@ -0,0 +1,140 @@
+mod common;
+
+use std::io::Cursor;
+
+use anyhow::{Context as _, Result, ensure};
+use axum_diesel_example::qr_code::{self, QrCodeErrorCorrection, QrCodeFormat};
+use reqwest::{Method, StatusCode};
+
+use self::common::{TestApp, TestDatabase, create_user};
+
+const DATA: &str = "http://localhost/index.html?to=0198f1ea-3b7e-7c6a-9d41-0b1e3f7c2a5d";
+
+/// A decoded grayscale PNG image.
+struct Image {
+    width: u32,
+    height: u32,
+    data: Vec<u8>,
+}
+
+impl Image {
+    fn decode(bytes: &[u8]) -> Result<Self> {
+        let mut reader = png::Decoder::new(Cursor::new(bytes))
+            .read_info()
+            .context("failed to read PNG header")?;
+        let mut data = vec![0; reader.output_buffer_size().context("PNG is too large")?];
+        let info = reader
+            .next_frame(&mut data)
+            .context("failed to decode PNG")?;
+        ensure!(info.color_type == png::ColorType::Grayscale);
+        data.truncate(info.buffer_size());
+
+        Ok(Self {
+            width: info.width,
+            height: info.height,
+            data,
+        })
+    }
+
+    fn is_dark(&self, x: u32, y: u32) -> bool {
+        let index = u64::from(y)
+            .saturating_mul(u64::from(self.width))
+            .saturating_add(u64::from(x));
+        let index = usize::try_from(index).expect("index should fit in memory");
+        self.data[index] < 0x80
+    }
+}
+
+/// Reads the error correction level from the format information next to the
+/// top-left finder pattern: the two most significant of its 15 bits, masked with
+/// `0b10`, are in the leftmost two modules of the ninth row.
+fn error_correction(image: &Image) -> Result<QrCodeErrorCorrection> {
+    // The finder pattern starts with a 7 modules wide dark row, after the quiet
+    // zone.
+    let quiet_zone = (0..image.width)
+        .find(|&i| image.is_dark(i, i))
+        .context("QR code should have a finder pattern")?;
+    let finder_width = (quiet_zone..image.width)
+        .take_while(|&x| image.is_dark(x, quiet_zone))
+        .count();
+    let module = u32::try_from(finder_width / 7)?;
+
+    // Looks at the center of a module.
+    let offset = |i: u32| {
+        quiet_zone
+            .saturating_add(i.saturating_mul(module))
+            .saturating_add(module.div_ceil(2))
+    };
+    let module_is_dark = |x: u32, y: u32| image.is_dark(offset(x), offset(y));
+    Ok(match (module_is_dark(0, 8), module_is_dark(1, 8)) {
+        (true, true) => QrCodeErrorCorrection::L,
+        (true, false) => QrCodeErrorCorrection::M,
+        (false, true) => QrCodeErrorCorrection::Q,
+        (false, false) => QrCodeErrorCorrection::H,
+    })
+}
+
+#[test]
+fn png_qr_code_fits_size_and_has_error_correction_level() -> Result<()> {
+    for error_correction_level in [
+        QrCodeErrorCorrection::L,
+        QrCodeErrorCorrection::M,
+        QrCodeErrorCorrection::Q,
+        QrCodeErrorCorrection::H,
+    ] {
+        for size in [qr_code::MIN_SIZE, 300, qr_code::MAX_SIZE] {
+            let image = qr_code::render(DATA, QrCodeFormat::Png, size, error_correction_level)?;
+            assert_eq!(image.content_type, "image/png");
+
+            let image = Image::decode(&image.bytes)?;
+            assert_eq!(image.width, image.height);
+            assert!(image.width <= size, "{} > {size}", image.width);
+            if size >= 300 {
+                assert_eq!(error_correction(&image)?, error_correction_level);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[test]
+fn svg_qr_code_fits_size() -> Result<()> {
+    let image = qr_code::render(DATA, QrCodeFormat::Svg, 300, QrCodeErrorCorrection::M)?;
+    assert_eq!(image.content_type, "image/svg+xml");
+
+    let svg = String::from_utf8(image.bytes)?;
+    let width: u32 = svg
+        .split_once("width=\"")
+        .and_then(|(_, rest)| rest.split_once('"'))
+        .context("SVG should have a width")?
+        .0
+        .parse()?;
+    assert!(width <= 300, "{width} > 300");
+    assert!(width > 200, "{width} is much smaller than 300");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn qr_code_size_is_bounded() -> Result<()> {
+    let db = TestDatabase::new().await?;
+    let alice = create_user(&db, "alice", 0).await?;
+    let app = TestApp::start(&db).await?;
+    let access_token = app.access_token(alice)?;
+
+    for size in [qr_code::MIN_SIZE - 1, qr_code::MAX_SIZE + 1] {
+        let (status, body) = app
+            .request(
+                Method::GET,
+                &format!("/users/{alice}/qr-code?size={size}"),
+                &access_token,
+                None,
+            )
+            .await?;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body["title"], "InvalidSize");
+    }
+
+    Ok(())
+}
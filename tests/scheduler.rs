@@ -0,0 +1,288 @@
+mod common;
+
+use std::time::Duration;
+
+use anyhow::{Context as _, Result};
+use axum_diesel_example::currency::DEFAULT_CURRENCY;
+use axum_diesel_example::event_hub::{Delivery, EventHub};
+use axum_diesel_example::interest;
+use axum_diesel_example::ledger::{self, NewIssuance};
+use axum_diesel_example::limits::TransferLimits;
+use axum_diesel_example::models::interest_rate::InterestProduct;
+use axum_diesel_example::models::scheduled_transfer::{
+    NewScheduledTransfer, Recurrence, ScheduledTransferAttempt, ScheduledTransferAttemptOutcome,
+    ScheduledTransferStatus,
+};
+use axum_diesel_example::models::transaction::TransactionKind;
+use axum_diesel_example::models::types;
+use axum_diesel_example::models::{InterestRate, ScheduledTransfer};
+use axum_diesel_example::scheduler::{self, MAX_RETRIES, RETRY_DELAY};
+use axum_diesel_example::schema::{
+    interest_rates, scheduled_transfer_attempts, scheduled_transfers,
+};
+use bigdecimal::BigDecimal;
+use diesel::prelude::*;
+#[allow(
+    clippy::unused_trait_names,
+    reason = "error[E0034]: multiple applicable items in scope"
+)]
+use diesel_async::RunQueryDsl;
+use uuid::Uuid;
+
+use self::common::{TestDatabase, balance, create_user};
+
+async fn create_scheduled_transfer(
+    db: &TestDatabase,
+    sender: Uuid,
+    recipient: Uuid,
+    amount: u32,
+    starts_at: jiff::Timestamp,
+    recurrence: Option<&str>,
+) -> Result<Uuid> {
+    let mut conn = db.pool.get().await?;
+
+    let id = Uuid::now_v7();
+    diesel::insert_into(scheduled_transfers::table)
+        .values(NewScheduledTransfer {
+            id,
+            sender,
+            recipient,
+            amount: BigDecimal::from(amount),
+            currency: DEFAULT_CURRENCY,
+            memo: None,
+            reference: None,
+            recurrence: recurrence
+                .map(str::parse)
+                .transpose()
+                .map_err(anyhow::Error::msg)?,
+            starts_at,
+            occurrence: 0,
+            next_run_at: starts_at,
+            retries: 0,
+            status: ScheduledTransferStatus::Active,
+            created_at: jiff::Timestamp::now(),
+        })
+        .execute(&mut conn)
+        .await
+        .context("failed to insert scheduled transfer")?;
+
+    Ok(id)
+}
+
+async fn load_scheduled_transfer(db: &TestDatabase, id: Uuid) -> Result<ScheduledTransfer> {
+    let mut conn = db.pool.get().await?;
+
+    scheduled_transfers::table
+        .find(types::Uuid::from(id))
+        .select(ScheduledTransfer::as_select())
+        .first(&mut conn)
+        .await
+        .context("failed to query scheduled transfers")
+}
+
+async fn load_attempts(db: &TestDatabase, id: Uuid) -> Result<Vec<ScheduledTransferAttempt>> {
+    let mut conn = db.pool.get().await?;
+
+    scheduled_transfer_attempts::table
+        .filter(scheduled_transfer_attempts::scheduled_transfer_id.eq(types::Uuid::from(id)))
+        .select(ScheduledTransferAttempt::as_select())
+        .order(scheduled_transfer_attempts::id.asc())
+        .load(&mut conn)
+        .await
+        .context("failed to query scheduled transfer attempts")
+}
+
+#[tokio::test]
+async fn one_off_transfer_runs_once() -> Result<()> {
+    let db = TestDatabase::new().await?;
+    let sender = create_user(&db, "sender", 100).await?;
+    let recipient = create_user(&db, "recipient", 0).await?;
+    let starts_at: jiff::Timestamp = "2030-01-01T00:00:00Z".parse()?;
+    let id = create_scheduled_transfer(&db, sender, recipient, 30, starts_at, None).await?;
+    let event_hub = EventHub::new();
+    let mut subscription = event_hub.subscribe(recipient, None);
+
+    // Not due yet.
+    scheduler::run_due_scheduled_transfers(
+        &db.pool,
+        &TransferLimits::default(),
+        &event_hub,
+        starts_at - jiff::SignedDuration::from_secs(1),
+    )
+    .await?;
+    assert!(load_attempts(&db, id).await?.is_empty());
+
+    scheduler::run_due_scheduled_transfers(
+        &db.pool,
+        &TransferLimits::default(),
+        &event_hub,
+        starts_at,
+    )
+    .await?;
+    scheduler::run_due_scheduled_transfers(
+        &db.pool,
+        &TransferLimits::default(),
+        &event_hub,
+        starts_at + jiff::SignedDuration::from_hours(1),
+    )
+    .await?;
+
+    let attempts = load_attempts(&db, id).await?;
+    assert_eq!(attempts.len(), 1);
+    assert_eq!(
+        attempts[0].outcome,
+        ScheduledTransferAttemptOutcome::Succeeded
+    );
+    assert_eq!(attempts[0].scheduled_for, starts_at);
+    assert_eq!(
+        load_scheduled_transfer(&db, id).await?.status,
+        ScheduledTransferStatus::Completed
+    );
+    assert_eq!(balance(&db, sender).await?, BigDecimal::from(70));
+
+    // The recipient is told about the transfer, like one made by the sender.
+    let mut event_names = Vec::new();
+    while let Ok(Some(Delivery::Event(event))) =
+        tokio::time::timeout(Duration::from_millis(50), subscription.next()).await
+    {
+        event_names.push(event.name);
+    }
+    assert_eq!(event_names, ["transaction", "balance"]);
+    assert_eq!(balance(&db, recipient).await?, BigDecimal::from(30));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn insufficient_balance_is_retried_then_skipped() -> Result<()> {
+    let db = TestDatabase::new().await?;
+    let sender = create_user(&db, "sender", 5).await?;
+    let recipient = create_user(&db, "recipient", 0).await?;
+    let starts_at: jiff::Timestamp = "2030-01-01T00:00:00Z".parse()?;
+    let id = create_scheduled_transfer(&db, sender, recipient, 10, starts_at, Some("FREQ=DAILY"))
+        .await?;
+
+    let mut now = starts_at;
+    for retries in 1..=MAX_RETRIES {
+        scheduler::run_due_scheduled_transfers(
+            &db.pool,
+            &TransferLimits::default(),
+            &EventHub::new(),
+            now,
+        )
+        .await?;
+
+        let scheduled_transfer = load_scheduled_transfer(&db, id).await?;
+        assert_eq!(scheduled_transfer.retries, retries);
+        assert_eq!(scheduled_transfer.occurrence, 0);
+
+        now = scheduled_transfer.next_run_at;
+        assert_eq!(now, starts_at + RETRY_DELAY * retries);
+    }
+
+    // The last retry fails too, so the occurrence is skipped.
+    scheduler::run_due_scheduled_transfers(
+        &db.pool,
+        &TransferLimits::default(),
+        &EventHub::new(),
+        now,
+    )
+    .await?;
+
+    let scheduled_transfer = load_scheduled_transfer(&db, id).await?;
+    assert_eq!(scheduled_transfer.status, ScheduledTransferStatus::Active);
+    assert_eq!(scheduled_transfer.retries, 0);
+    assert_eq!(scheduled_transfer.occurrence, 1);
+    assert_eq!(
+        scheduled_transfer.next_run_at,
+        starts_at + jiff::SignedDuration::from_hours(24)
+    );
+
+    let attempts = load_attempts(&db, id).await?;
+    assert_eq!(attempts.len(), usize::try_from(MAX_RETRIES)? + 1);
+    assert!(attempts.iter().all(|attempt| {
+        attempt.outcome == ScheduledTransferAttemptOutcome::Failed
+            && attempt.error.as_deref() == Some("InsufficientBalance")
+            && attempt.scheduled_for == starts_at
+    }));
+    assert_eq!(balance(&db, sender).await?, BigDecimal::from(5));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn interest_is_accrued_and_paid_once() -> Result<()> {
+    let db = TestDatabase::new().await?;
+    // 10% a year of 3650 is 1 a day.
+    let user = create_user(&db, "saver", 3650).await?;
+    let mut conn = db.pool.get().await?;
+
+    ledger::issue(
+        &mut conn,
+        &NewIssuance {
+            amount: BigDecimal::from(100),
+            currency: DEFAULT_CURRENCY,
+            initiator: None,
+            kind: TransactionKind::Mint,
+            memo: None,
+        },
+    )
+    .await?
+    .map_err(|err| anyhow::anyhow!("{err:?}"))?;
+    diesel::insert_into(interest_rates::table)
+        .values(InterestRate {
+            product: InterestProduct::Account,
+            currency: DEFAULT_CURRENCY,
+            annual_rate: BigDecimal::from(10),
+            updated_at: jiff::Timestamp::now(),
+        })
+        .execute(&mut conn)
+        .await
+        .context("failed to insert interest rate")?;
+
+    let january_30: jiff::civil::Date = "2030-01-30".parse()?;
+    let january_31: jiff::civil::Date = "2030-01-31".parse()?;
+    let february_1: jiff::civil::Date = "2030-02-01".parse()?;
+
+    let preview = interest::accruals(&mut conn, january_30).await?;
+    assert_eq!(preview.len(), 1);
+    assert_eq!(preview[0].amount, BigDecimal::from(1));
+
+    // Accruing a day again records nothing.
+    assert_eq!(interest::accrue(&mut conn, january_30).await?, 1);
+    assert_eq!(interest::accrue(&mut conn, january_30).await?, 0);
+    assert_eq!(interest::accrue(&mut conn, january_31).await?, 1);
+
+    // Only what accrued before the given day is paid.
+    let payments = interest::pay(&mut conn, january_31).await?;
+    assert_eq!(payments.len(), 1);
+    assert_eq!(payments[0].amount, BigDecimal::from(1));
+    assert_eq!(payments[0].kind, TransactionKind::Interest);
+    assert_eq!(payments[0].recipient, user);
+
+    // Paying again pays only what was not paid yet.
+    assert_eq!(interest::pay(&mut conn, february_1).await?.len(), 1);
+    assert!(interest::pay(&mut conn, february_1).await?.is_empty());
+    assert_eq!(balance(&db, user).await?, BigDecimal::from(3652));
+
+    Ok(())
+}
+
+#[test]
+fn monthly_recurrence_keeps_day_of_month() -> Result<()> {
+    let recurrence: Recurrence = "FREQ=MONTHLY;COUNT=3".parse().map_err(anyhow::Error::msg)?;
+    let starts_at: jiff::Timestamp = "2031-01-31T09:00:00Z".parse()?;
+
+    assert_eq!(recurrence.occurrence(starts_at, 0), Some(starts_at));
+    assert_eq!(
+        recurrence.occurrence(starts_at, 1),
+        Some("2031-02-28T09:00:00Z".parse()?)
+    );
+    assert_eq!(
+        recurrence.occurrence(starts_at, 2),
+        Some("2031-03-31T09:00:00Z".parse()?)
+    );
+    assert_eq!(recurrence.occurrence(starts_at, 3), None);
+
+    Ok(())
+}
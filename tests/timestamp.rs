@@ -0,0 +1,86 @@
+mod common;
+
+use anyhow::{Context as _, Result};
+use axum_diesel_example::models::types;
+use axum_diesel_example::schema::transactions;
+use diesel::prelude::*;
+#[allow(
+    clippy::unused_trait_names,
+    reason = "error[E0034]: multiple applicable items in scope"
+)]
+use diesel_async::RunQueryDsl;
+use diesel_async::SimpleAsyncConnection as _;
+use reqwest::{Method, StatusCode};
+use serde_json::json;
+use uuid::Uuid;
+
+use self::common::{TestApp, TestDatabase};
+
+const PAD_TIMESTAMPS_MIGRATION: &str = "2026-10-19-025000_pad_timestamps";
+
+#[tokio::test]
+async fn timestamps_compare_in_sql() -> Result<()> {
+    let db = TestDatabase::before_migration(PAD_TIMESTAMPS_MIGRATION).await?;
+    let alice = Uuid::now_v7();
+    let bob = Uuid::now_v7();
+    {
+        let mut conn = db.pool.get().await?;
+        let mut sql = format!(
+            "INSERT INTO users (id, username, password_hash, balance) VALUES
+               (X'{}', 'alice', '', '100'),
+               (X'{}', 'bob', '', '0');",
+            alice.simple(),
+            bob.simple(),
+        );
+        // As written before, with as few fractional digits as needed.
+        for timestamp in [
+            "2026-01-01T00:00:00.5Z",
+            "2026-01-01T00:00:00Z",
+            "2026-01-01T00:00:00.25Z",
+            "2026-01-01T00:00:01Z",
+        ] {
+            sql.push_str(&format!(
+                "INSERT INTO transactions (id, amount, recipient, sender, timestamp) VALUES
+                   (X'{}', '1', X'{}', X'{}', '{timestamp}');",
+                Uuid::now_v7().simple(),
+                bob.simple(),
+                alice.simple(),
+            ));
+        }
+        conn.batch_execute(&sql)
+            .await
+            .context("failed to insert transactions")?;
+    }
+    db.apply_migrations_from(PAD_TIMESTAMPS_MIGRATION).await?;
+
+    let app = TestApp::start(&db).await?;
+    let (status, _) = app
+        .request(
+            Method::POST,
+            "/transactions",
+            &app.access_token(alice)?,
+            Some(json!({ "recipient": bob, "amount": "1" })),
+        )
+        .await?;
+    assert_eq!(status, StatusCode::OK);
+
+    let mut conn = db.pool.get().await?;
+    let timestamps: Vec<jiff::Timestamp> = transactions::table
+        .filter(transactions::sender.eq(types::Uuid::from(alice)))
+        .filter(transactions::timestamp.gt(types::Timestamp::from(
+            "2026-01-01T00:00:00Z".parse::<jiff::Timestamp>()?,
+        )))
+        .order(transactions::timestamp.asc())
+        .select(transactions::timestamp)
+        .load::<types::Timestamp>(&mut conn)
+        .await
+        .context("failed to query transactions")?
+        .into_iter()
+        .map(Into::into)
+        .collect();
+    assert_eq!(timestamps.len(), 4);
+    assert!(timestamps.is_sorted());
+    assert_eq!(timestamps[0], "2026-01-01T00:00:00.25Z".parse()?);
+
+    Ok(())
+}
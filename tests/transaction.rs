@@ -0,0 +1,326 @@
+mod common;
+
+use anyhow::{Context as _, Result};
+use reqwest::{Method, StatusCode};
+use serde_json::json;
+use uuid::Uuid;
+
+use self::common::{TestApp, TestDatabase, balance, create_user};
+
+#[tokio::test]
+async fn only_parties_may_see_a_transaction() -> Result<()> {
+    let db = TestDatabase::new().await?;
+    let alice = create_user(&db, "alice", 100).await?;
+    let bob = create_user(&db, "bob", 0).await?;
+    let carol = create_user(&db, "carol", 0).await?;
+    let app = TestApp::start(&db).await?;
+
+    let (status, body) = app
+        .request(
+            Method::POST,
+            "/transactions",
+            &app.access_token(alice)?,
+            Some(json!({ "amount": 30, "recipient": bob, "sender": alice })),
+        )
+        .await?;
+    assert_eq!(status, StatusCode::OK);
+    let transaction_path = format!(
+        "/transactions/{}",
+        body["id"]
+            .as_str()
+            .context("transaction should have an ID")?
+    );
+
+    for (user, counterparty) in [(alice, "bob"), (bob, "alice")] {
+        let (status, body) = app
+            .request(
+                Method::GET,
+                &transaction_path,
+                &app.access_token(user)?,
+                None,
+            )
+            .await?;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["amount"], 30);
+        assert_eq!(body["counterparty"]["username"], counterparty);
+    }
+
+    // Anyone else is told that the transaction does not exist, just as for a
+    // transaction which really does not.
+    let carol_token = app.access_token(carol)?;
+    for path in [
+        transaction_path,
+        format!("/transactions/{}", Uuid::now_v7()),
+    ] {
+        let (status, body) = app.request(Method::GET, &path, &carol_token, None).await?;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert_eq!(body["title"], "TransactionNotFound");
+    }
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn memos_are_sanitized() -> Result<()> {
+    let db = TestDatabase::new().await?;
+    let alice = create_user(&db, "alice", 100).await?;
+    let bob = create_user(&db, "bob", 0).await?;
+    let app = TestApp::start(&db).await?;
+    let alice_token = app.access_token(alice)?;
+
+    let transfer =
+        |memo: String| json!({ "amount": 1, "recipient": bob, "sender": alice, "memo": memo });
+    let cases = [
+        // Control characters are dropped, and whitespace is collapsed.
+        (
+            "for\u{0}\u{7} lunch\t\n at  noon".to_owned(),
+            json!("for lunch at noon"),
+        ),
+        // Bidirectional formatting characters are dropped.
+        (
+            "\u{202e}rof\u{202c} tea\u{2066}".to_owned(),
+            json!("rof tea"),
+        ),
+        // Nothing is left of a memo of only whitespace.
+        (" \t\u{200f} ".to_owned(), serde_json::Value::Null),
+        ("é".repeat(140), json!("é".repeat(140))),
+        // The limit applies after sanitization.
+        (
+            format!("{}\u{202e}", "a".repeat(140)),
+            json!("a".repeat(140)),
+        ),
+    ];
+    for (memo, expected) in cases {
+        let (status, body) = app
+            .request(
+                Method::POST,
+                "/transactions",
+                &alice_token,
+                Some(transfer(memo)),
+            )
+            .await?;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["memo"], expected);
+    }
+
+    let (status, body) = app
+        .request(
+            Method::POST,
+            "/transactions",
+            &alice_token,
+            Some(transfer("a".repeat(141))),
+        )
+        .await?;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(body["title"], "InvalidMemo");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn references_are_validated() -> Result<()> {
+    let db = TestDatabase::new().await?;
+    let alice = create_user(&db, "alice", 100).await?;
+    let bob = create_user(&db, "bob", 0).await?;
+    let app = TestApp::start(&db).await?;
+    let alice_token = app.access_token(alice)?;
+
+    let transfer = |reference: String| json!({ "amount": 1, "recipient": bob, "sender": alice, "reference": reference });
+    for reference in ["INV-2026/10:a_b.c".to_owned(), "a".repeat(64)] {
+        let (status, body) = app
+            .request(
+                Method::POST,
+                "/transactions",
+                &alice_token,
+                Some(transfer(reference.clone())),
+            )
+            .await?;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["reference"], reference);
+    }
+    for reference in [
+        String::new(),
+        "a".repeat(65),
+        "facture-é".to_owned(),
+        "INV 1".to_owned(),
+    ] {
+        let (status, body) = app
+            .request(
+                Method::POST,
+                "/transactions",
+                &alice_token,
+                Some(transfer(reference.clone())),
+            )
+            .await?;
+        assert_eq!(status, StatusCode::BAD_REQUEST, "{reference:?}");
+        assert_eq!(body["title"], "InvalidReference");
+    }
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn transfers_are_sent_by_the_authenticated_user() -> Result<()> {
+    let db = TestDatabase::new().await?;
+    let alice = create_user(&db, "alice", 100).await?;
+    let bob = create_user(&db, "bob", 0).await?;
+    let app = TestApp::start(&db).await?;
+    let alice_token = app.access_token(alice)?;
+
+    let response = app
+        .send(
+            Method::POST,
+            "/transactions",
+            &alice_token,
+            Some(json!({ "amount": 10, "recipient": bob })),
+        )
+        .await?;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert!(!response.headers().contains_key("deprecation"));
+    let body: serde_json::Value = serde_json::from_slice(&response.bytes().await?)?;
+    assert_eq!(body["sender"], alice.to_string());
+
+    // Payloads with the sender are deprecated, but still accepted.
+    let response = app
+        .send(
+            Method::POST,
+            "/transactions",
+            &alice_token,
+            Some(json!({ "amount": 10, "recipient": bob, "sender": alice })),
+        )
+        .await?;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()["deprecation"], "@1792368000");
+
+    let response = app
+        .send(
+            Method::POST,
+            "/transactions",
+            &alice_token,
+            Some(json!({ "amount": 10, "recipient": alice, "sender": bob })),
+        )
+        .await?;
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+    assert_eq!(response.headers()["deprecation"], "@1792368000");
+    let body: serde_json::Value = serde_json::from_slice(&response.bytes().await?)?;
+    assert_eq!(body["title"], "PermissionDenied");
+
+    assert_eq!(balance(&db, alice).await?, 80.into());
+    assert_eq!(balance(&db, bob).await?, 20.into());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn invalid_transfers_are_rejected() -> Result<()> {
+    let db = TestDatabase::new().await?;
+    let alice = create_user(&db, "alice", 100).await?;
+    let bob = create_user(&db, "bob", 100).await?;
+    let app = TestApp::start(&db).await?;
+    let alice_token = app.access_token(alice)?;
+
+    let cases = [
+        (json!({ "amount": 10, "recipient": alice }), "SelfTransfer"),
+        (
+            json!({ "amount": 10, "recipient": "alice" }),
+            "SelfTransfer",
+        ),
+        (json!({ "amount": 0, "recipient": bob }), "InvalidAmount"),
+        (json!({ "amount": -10, "recipient": bob }), "InvalidAmount"),
+    ];
+    for (payload, title) in cases {
+        let (status, body) = app
+            .request(Method::POST, "/transactions", &alice_token, Some(payload))
+            .await?;
+        assert_eq!(status, StatusCode::BAD_REQUEST, "{title}");
+        assert_eq!(body["title"], title);
+    }
+
+    assert_eq!(balance(&db, alice).await?, 100.into());
+    assert_eq!(balance(&db, bob).await?, 100.into());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn partial_refunds_are_shown_on_the_transaction() -> Result<()> {
+    let db = TestDatabase::new().await?;
+    let alice = create_user(&db, "alice", 100).await?;
+    let bob = create_user(&db, "bob", 0).await?;
+    let app = TestApp::start(&db).await?;
+    let alice_token = app.access_token(alice)?;
+    let bob_token = app.access_token(bob)?;
+
+    let (status, body) = app
+        .request(
+            Method::POST,
+            "/transactions",
+            &alice_token,
+            Some(json!({ "amount": 30, "recipient": bob })),
+        )
+        .await?;
+    assert_eq!(status, StatusCode::OK);
+    let transaction_id = body["id"]
+        .as_str()
+        .context("transaction should have an ID")?
+        .to_owned();
+    let transaction_path = format!("/transactions/{transaction_id}");
+    let refund_path = format!("{transaction_path}/refund");
+
+    let (status, body) = app
+        .request(
+            Method::POST,
+            &refund_path,
+            &bob_token,
+            Some(json!({ "amount": 10, "memo": "too much" })),
+        )
+        .await?;
+    assert_eq!(status, StatusCode::CREATED);
+    assert_eq!(body["kind"], "refund");
+    assert_eq!(body["original_transaction_id"], transaction_id);
+    let refund_id = body["id"].clone();
+
+    for token in [&alice_token, &bob_token] {
+        let (status, body) = app
+            .request(Method::GET, &transaction_path, token, None)
+            .await?;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["amount"], 30);
+        assert_eq!(body["refundable_amount"], 20);
+        assert_eq!(body["refunds"].as_array().map(Vec::len), Some(1));
+        assert_eq!(body["refunds"][0]["id"], refund_id);
+        assert_eq!(body["refunds"][0]["amount"], 10);
+        assert_eq!(body["refunds"][0]["kind"], "refund");
+    }
+
+    // No more than what is left may be refunded.
+    let (status, body) = app
+        .request(
+            Method::POST,
+            &refund_path,
+            &bob_token,
+            Some(json!({ "amount": 25 })),
+        )
+        .await?;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(body["title"], "ExceedsRefundableAmount");
+
+    // Without an amount, the rest is refunded.
+    let (status, body) = app
+        .request(Method::POST, &refund_path, &bob_token, Some(json!({})))
+        .await?;
+    assert_eq!(status, StatusCode::CREATED);
+    assert_eq!(body["amount"], 20);
+
+    let (status, body) = app
+        .request(Method::GET, &transaction_path, &alice_token, None)
+        .await?;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["refundable_amount"], 0);
+    assert_eq!(body["refunds"].as_array().map(Vec::len), Some(2));
+
+    assert_eq!(balance(&db, alice).await?, 100.into());
+    assert_eq!(balance(&db, bob).await?, 0.into());
+
+    Ok(())
+}
@@ -0,0 +1,101 @@
+mod common;
+
+use anyhow::Result;
+use bigdecimal::BigDecimal;
+use reqwest::{Method, StatusCode};
+use serde_json::json;
+
+use self::common::{TEST_RATE_LIMIT, TestApp, TestDatabase, balance, create_user};
+
+#[tokio::test]
+async fn invalid_transfers_of_a_batch_are_all_reported() -> Result<()> {
+    let db = TestDatabase::new().await?;
+    let alice = create_user(&db, "alice", 100).await?;
+    let bob = create_user(&db, "bob", 0).await?;
+    let app = TestApp::start(&db).await?;
+    let alice_token = app.access_token(alice)?;
+
+    let (status, body) = app
+        .request(
+            Method::POST,
+            "/transactions/batch",
+            &alice_token,
+            Some(json!({
+                "transfers": [
+                    { "amount": 10, "recipient": "bob", "memo": "x".repeat(141) },
+                    { "amount": 10, "recipient": "bob", "reference": "not a reference" },
+                    { "amount": 10, "recipient": "nobody" },
+                    { "amount": 10, "recipient": bob, "memo": "fine" },
+                ],
+            })),
+        )
+        .await?;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(body["title"], "BatchRejected");
+    let errors = body["errors"]
+        .as_array()
+        .map(|errors| {
+            errors
+                .iter()
+                .map(|error| (error["index"].clone(), error["title"].clone()))
+                .collect::<Vec<_>>()
+        })
+        .unwrap_or_default();
+    assert_eq!(
+        errors,
+        [
+            (json!(0), json!("InvalidMemo")),
+            (json!(1), json!("InvalidReference")),
+            (json!(2), json!("InvalidRecipient")),
+        ]
+    );
+    assert_eq!(balance(&db, bob).await?, BigDecimal::from(0));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn each_username_of_a_batch_counts_towards_the_rate_limit() -> Result<()> {
+    let db = TestDatabase::new().await?;
+    let alice = create_user(&db, "alice", 100).await?;
+    let bob = create_user(&db, "bob", 0).await?;
+    let app = TestApp::start(&db).await?;
+    let alice_token = app.access_token(alice)?;
+
+    let transfers = |recipient: serde_json::Value, count: u32| {
+        json!({
+            "transfers": (0..count)
+                .map(|_| json!({ "amount": 1, "recipient": recipient }))
+                .collect::<Vec<_>>(),
+        })
+    };
+
+    let (status, body) = app
+        .request(
+            Method::POST,
+            "/transactions/batch",
+            &alice_token,
+            Some(transfers(json!("bob"), TEST_RATE_LIMIT.saturating_add(1))),
+        )
+        .await?;
+    assert_eq!(status, StatusCode::TOO_MANY_REQUESTS);
+    assert_eq!(body["title"], "TooManyRequests");
+    assert_eq!(balance(&db, bob).await?, BigDecimal::from(0));
+
+    // Recipients given by ID are not looked up.
+    let (status, _body) = app
+        .request(
+            Method::POST,
+            "/transactions/batch",
+            &alice_token,
+            Some(transfers(json!(bob), TEST_RATE_LIMIT.saturating_add(1))),
+        )
+        .await?;
+    assert_eq!(status, StatusCode::CREATED);
+    assert_eq!(
+        balance(&db, bob).await?,
+        BigDecimal::from(TEST_RATE_LIMIT.saturating_add(1))
+    );
+
+    Ok(())
+}
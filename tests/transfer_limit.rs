@@ -0,0 +1,93 @@
+mod common;
+
+use anyhow::{Context as _, Result};
+use axum_diesel_example::models::types;
+use axum_diesel_example::models::user::UserRole;
+use axum_diesel_example::schema::users;
+use diesel::prelude::*;
+#[allow(
+    clippy::unused_trait_names,
+    reason = "error[E0034]: multiple applicable items in scope"
+)]
+use diesel_async::RunQueryDsl;
+use reqwest::{Method, StatusCode};
+use serde_json::json;
+
+use self::common::{TestApp, TestDatabase, create_user};
+
+#[tokio::test]
+async fn overrides_fall_back_to_defaults_unless_removed() -> Result<()> {
+    let db = TestDatabase::new().await?;
+    let admin = create_user(&db, "admin", 0).await?;
+    let alice = create_user(&db, "alice", 0).await?;
+    {
+        let mut conn = db.pool.get().await?;
+        diesel::update(users::table.find(types::Uuid::from(admin)))
+            .set(users::role.eq(UserRole::Admin))
+            .execute(&mut conn)
+            .await
+            .context("failed to update user")?;
+    }
+    let app = TestApp::start(&db).await?;
+    let admin_token = app.access_token(admin)?;
+    let path = format!("/users/{alice}/limits");
+
+    for max_transfers in [json!(-1), json!(0), json!(i64::from(i32::MAX) + 1)] {
+        let (status, body) = app
+            .request(
+                Method::PUT,
+                &path,
+                &admin_token,
+                Some(json!({ "max_transfers": max_transfers })),
+            )
+            .await?;
+        assert_eq!(
+            status,
+            StatusCode::BAD_REQUEST,
+            "max_transfers {max_transfers}"
+        );
+        assert_eq!(body["title"], "InvalidLimit");
+    }
+    let (status, body) = app
+        .request(
+            Method::PUT,
+            &path,
+            &admin_token,
+            Some(json!({ "daily": -5 })),
+        )
+        .await?;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(body["title"], "InvalidLimit");
+
+    // The per transaction limit is overridden, the daily one removed, and the
+    // others fall back to the defaults, of which there are none in tests.
+    let (status, body) = app
+        .request(
+            Method::PUT,
+            &path,
+            &admin_token,
+            Some(json!({ "per_transaction": 50, "daily": null })),
+        )
+        .await?;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["per_transaction"], 50);
+    assert_eq!(body["daily"], serde_json::Value::Null);
+    assert_eq!(body["overrides"]["per_transaction"], 50);
+    assert_eq!(body["overrides"]["daily"], serde_json::Value::Null);
+    assert_eq!(body["overrides"]["removed"], json!(["daily"]));
+
+    let (status, body) = app
+        .request(
+            Method::PUT,
+            &path,
+            &admin_token,
+            Some(json!({ "max_transfers": null, "monthly": 100 })),
+        )
+        .await?;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["per_transaction"], serde_json::Value::Null);
+    assert_eq!(body["monthly"], 100);
+    assert_eq!(body["overrides"]["removed"], json!(["transfer_count"]));
+
+    Ok(())
+}
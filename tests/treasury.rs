@@ -0,0 +1,87 @@
+mod common;
+
+use std::collections::BTreeMap;
+
+use anyhow::{Context as _, Result};
+use axum_diesel_example::ledger::TREASURY_USER_ID;
+use axum_diesel_example::models::Account;
+use axum_diesel_example::schema::accounts;
+use bigdecimal::BigDecimal;
+use diesel::prelude::*;
+#[allow(
+    clippy::unused_trait_names,
+    reason = "error[E0034]: multiple applicable items in scope"
+)]
+use diesel_async::RunQueryDsl;
+use diesel_async::SimpleAsyncConnection as _;
+use reqwest::{Method, StatusCode};
+use uuid::Uuid;
+
+use self::common::{TestApp, TestDatabase};
+
+const TREASURY_MIGRATION: &str = "2026-10-19-110000_create_treasury";
+
+#[tokio::test]
+async fn balances_from_before_the_treasury_are_part_of_the_supply() -> Result<()> {
+    let db = TestDatabase::before_migration(TREASURY_MIGRATION).await?;
+    let admin = Uuid::now_v7();
+    {
+        let mut conn = db.pool.get().await?;
+        // Reversals may have left a balance negative.
+        conn.batch_execute(&format!(
+            "INSERT INTO users (id, username, password_hash, role) VALUES
+               (X'{admin}', 'admin', '', 'admin'),
+               (X'{alice}', 'alice', '', 'user'),
+               (X'{bob}', 'bob', '', 'user');
+             INSERT INTO accounts (user_id, currency, balance, created_at) VALUES
+               (X'{admin}', 'MYR', '0', '2026-01-01T00:00:00.000000000Z'),
+               (X'{alice}', 'MYR', '100.50', '2026-01-01T00:00:00.000000000Z'),
+               (X'{alice}', 'SGD', '7', '2026-01-01T00:00:00.000000000Z'),
+               (X'{bob}', 'MYR', '-20.25', '2026-01-01T00:00:00.000000000Z');",
+            admin = admin.simple(),
+            alice = Uuid::now_v7().simple(),
+            bob = Uuid::now_v7().simple(),
+        ))
+        .await
+        .context("failed to insert users")?;
+    }
+    db.apply_migrations_from(TREASURY_MIGRATION).await?;
+
+    let app = TestApp::start(&db).await?;
+    let (status, body) = app
+        .request(Method::GET, "/treasury", &app.access_token(admin)?, None)
+        .await?;
+    assert_eq!(status, StatusCode::OK);
+
+    let mut conn = db.pool.get().await?;
+    let accounts: Vec<Account> = accounts::table
+        .select(Account::as_select())
+        .load(&mut conn)
+        .await
+        .context("failed to query accounts")?;
+    let mut totals: BTreeMap<String, BigDecimal> = BTreeMap::new();
+    for account in &accounts {
+        *totals.entry(account.currency.to_string()).or_default() += &account.balance;
+    }
+    assert!(
+        accounts
+            .iter()
+            .all(|account| account.user_id != TREASURY_USER_ID)
+    );
+
+    let currencies = body["currencies"]
+        .as_array()
+        .context("treasury should list currencies")?;
+    assert_eq!(currencies.len(), totals.len());
+    for currency in currencies {
+        let code = currency["currency"]
+            .as_str()
+            .context("currency should be a string")?;
+        let supply: BigDecimal = currency["supply"].to_string().parse()?;
+        assert_eq!(Some(&supply), totals.get(code), "supply of {code}");
+        assert_eq!(currency["treasury_balance"], 0);
+    }
+    assert_eq!(totals["MYR"], "80.25".parse()?);
+
+    Ok(())
+}
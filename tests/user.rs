@@ -0,0 +1,35 @@
+mod common;
+
+use anyhow::Result;
+use reqwest::{Method, StatusCode};
+
+use self::common::{TestApp, TestDatabase, create_user};
+
+#[tokio::test]
+async fn users_may_only_see_themselves() -> Result<()> {
+    let db = TestDatabase::new().await?;
+    let alice = create_user(&db, "alice", 100).await?;
+    let bob = create_user(&db, "bob", 0).await?;
+    let app = TestApp::start(&db).await?;
+    let alice_token = app.access_token(alice)?;
+
+    let (status, body) = app
+        .request(Method::GET, &format!("/users/{alice}"), &alice_token, None)
+        .await?;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["username"], "alice");
+    assert_eq!(body["balance"], 100);
+
+    let (status, body) = app
+        .request(Method::GET, &format!("/users/{bob}"), &alice_token, None)
+        .await?;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    assert_eq!(body["title"], "PermissionDenied");
+
+    let (status, _body) = app
+        .request(Method::GET, &format!("/users/{alice}"), "not-a-token", None)
+        .await?;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+    Ok(())
+}
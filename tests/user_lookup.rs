@@ -0,0 +1,143 @@
+mod common;
+
+use anyhow::Result;
+use reqwest::{Method, StatusCode};
+use serde_json::json;
+use uuid::Uuid;
+
+use self::common::{TEST_RATE_LIMIT, TestApp, TestDatabase, create_user};
+
+#[tokio::test]
+async fn usernames_resolved_anywhere_count_towards_the_rate_limit() -> Result<()> {
+    let db = TestDatabase::new().await?;
+    let alice = create_user(&db, "alice", 100).await?;
+    let bob = create_user(&db, "bob", 0).await?;
+    let app = TestApp::start(&db).await?;
+    let alice_token = app.access_token(alice)?;
+
+    // Both existing and unknown usernames count.
+    let (status, _body) = app
+        .request(
+            Method::GET,
+            "/users/lookup?username=nobody",
+            &alice_token,
+            None,
+        )
+        .await?;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    let (status, _body) = app
+        .request(
+            Method::POST,
+            "/groups",
+            &alice_token,
+            Some(json!({ "name": "lunch", "members": ["bob"] })),
+        )
+        .await?;
+    assert_eq!(status, StatusCode::CREATED);
+    for _ in 2..TEST_RATE_LIMIT {
+        let (status, _body) = app
+            .request(
+                Method::POST,
+                "/transactions",
+                &alice_token,
+                Some(json!({ "amount": 1, "recipient": "bob", "sender": alice })),
+            )
+            .await?;
+        assert_eq!(status, StatusCode::OK);
+    }
+
+    let (status, body) = app
+        .request(
+            Method::POST,
+            "/transactions",
+            &alice_token,
+            Some(json!({ "amount": 1, "recipient": "bob", "sender": alice })),
+        )
+        .await?;
+    assert_eq!(status, StatusCode::TOO_MANY_REQUESTS);
+    assert_eq!(body["title"], "TooManyRequests");
+    let (status, _body) = app
+        .request(
+            Method::GET,
+            "/users/lookup?username=bob",
+            &alice_token,
+            None,
+        )
+        .await?;
+    assert_eq!(status, StatusCode::TOO_MANY_REQUESTS);
+    let (status, _body) = app
+        .request(
+            Method::POST,
+            "/groups",
+            &alice_token,
+            Some(json!({ "name": "dinner", "members": ["bob"] })),
+        )
+        .await?;
+    assert_eq!(status, StatusCode::TOO_MANY_REQUESTS);
+
+    // Users given by ID are not looked up.
+    let (status, _body) = app
+        .request(
+            Method::POST,
+            "/transactions",
+            &alice_token,
+            Some(json!({ "amount": 1, "recipient": bob, "sender": alice })),
+        )
+        .await?;
+    assert_eq!(status, StatusCode::OK);
+    let (status, _body) = app
+        .request(
+            Method::POST,
+            "/groups",
+            &alice_token,
+            Some(json!({ "name": "dinner", "members": [bob] })),
+        )
+        .await?;
+    assert_eq!(status, StatusCode::CREATED);
+
+    // Other users have limits of their own.
+    let bob_token = app.access_token(bob)?;
+    let (status, _body) = app
+        .request(
+            Method::GET,
+            "/users/lookup?username=alice",
+            &bob_token,
+            None,
+        )
+        .await?;
+    assert_eq!(status, StatusCode::OK);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn usernames_may_not_be_shaped_like_user_ids() -> Result<()> {
+    let db = TestDatabase::new().await?;
+    let app = TestApp::start(&db).await?;
+
+    let id = Uuid::now_v7();
+    for username in [id.to_string(), id.simple().to_string()] {
+        let (status, body) = app
+            .request(
+                Method::POST,
+                "/auth/signup",
+                "",
+                Some(json!({ "username": username, "password": "hunter2" })),
+            )
+            .await?;
+        assert_eq!(status, StatusCode::BAD_REQUEST, "{username}");
+        assert_eq!(body["title"], "InvalidUsername");
+    }
+
+    let (status, _body) = app
+        .request(
+            Method::POST,
+            "/auth/signup",
+            "",
+            Some(json!({ "username": "alice", "password": "hunter2" })),
+        )
+        .await?;
+    assert_eq!(status, StatusCode::OK);
+
+    Ok(())
+}
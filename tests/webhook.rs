@@ -0,0 +1,394 @@
+mod common;
+
+use std::sync::{Arc, Mutex};
+
+use anyhow::{Context as _, Result};
+use axum::Router;
+use axum::extract::State;
+use axum::http::{HeaderMap, StatusCode};
+use axum::routing::post;
+use axum_diesel_example::currency::DEFAULT_CURRENCY;
+use axum_diesel_example::http_url::{self, HostPolicy};
+use axum_diesel_example::ledger::{self, NewTransfer};
+use axum_diesel_example::limits::TransferLimits;
+use axum_diesel_example::models::types;
+use axum_diesel_example::models::webhook::{
+    WebhookDeliveryStatus, WebhookEventType, WebhookSubscription,
+};
+use axum_diesel_example::models::{WebhookDelivery, WebhookEndpoint};
+use axum_diesel_example::schema::{webhook_deliveries, webhook_endpoints, webhook_subscriptions};
+use axum_diesel_example::webhook::{self, MAX_ATTEMPTS, RETRY_BASE_DELAY, WebhookClient};
+use bigdecimal::BigDecimal;
+use diesel::prelude::*;
+#[allow(
+    clippy::unused_trait_names,
+    reason = "error[E0034]: multiple applicable items in scope"
+)]
+use diesel_async::RunQueryDsl;
+use reqwest::Method;
+use ring::hmac;
+use secrecy::{ExposeSecret as _, SecretString};
+use serde_json::json;
+use uuid::Uuid;
+
+use self::common::{TestApp, TestDatabase, create_user};
+
+/// A local HTTP server which records the webhooks it is sent.
+#[derive(Clone, Default)]
+struct Receiver {
+    requests: Arc<Mutex<Vec<(HeaderMap, String)>>>,
+    status: Arc<Mutex<Option<StatusCode>>>,
+}
+
+impl Receiver {
+    /// Starts the receiver, returning its URL.
+    async fn start(&self) -> Result<String> {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        let app = Router::new()
+            .route("/", post(receive))
+            .with_state(self.clone());
+        tokio::spawn(async move { axum::serve(listener, app).await });
+
+        Ok(format!("http://{addr}/"))
+    }
+
+    /// Makes the receiver respond with `status` rather than 204 No Content.
+    fn respond_with(&self, status: StatusCode) {
+        *self.status.lock().unwrap() = Some(status);
+    }
+
+    fn requests(&self) -> Vec<(HeaderMap, String)> {
+        self.requests.lock().unwrap().clone()
+    }
+}
+
+async fn receive(State(receiver): State<Receiver>, headers: HeaderMap, body: String) -> StatusCode {
+    receiver.requests.lock().unwrap().push((headers, body));
+
+    receiver
+        .status
+        .lock()
+        .unwrap()
+        .unwrap_or(StatusCode::NO_CONTENT)
+}
+
+async fn create_endpoint(
+    db: &TestDatabase,
+    user_id: Uuid,
+    url: String,
+    events: &[WebhookEventType],
+) -> Result<WebhookEndpoint> {
+    let mut conn = db.pool.get().await?;
+
+    let endpoint: WebhookEndpoint = diesel::insert_into(webhook_endpoints::table)
+        .values(WebhookEndpoint {
+            id: Uuid::now_v7(),
+            user_id,
+            url,
+            secret: webhook::generate_secret(),
+            all_users: false,
+            created_at: jiff::Timestamp::now(),
+        })
+        .returning(WebhookEndpoint::as_returning())
+        .get_result(&mut conn)
+        .await
+        .context("failed to insert webhook endpoint")?;
+    for &event_type in events {
+        diesel::insert_into(webhook_subscriptions::table)
+            .values(WebhookSubscription {
+                endpoint_id: endpoint.id,
+                event_type,
+            })
+            .execute(&mut conn)
+            .await
+            .context("failed to insert webhook subscription")?;
+    }
+
+    Ok(endpoint)
+}
+
+async fn send(db: &TestDatabase, sender: Uuid, recipient: Uuid, amount: u32) -> Result<Uuid> {
+    let mut conn = db.pool.get().await?;
+
+    let transaction = ledger::transfer(
+        &mut conn,
+        &NewTransfer {
+            amount: BigDecimal::from(amount),
+            currency: DEFAULT_CURRENCY,
+            recipient,
+            sender,
+            memo: None,
+            reference: None,
+            quote_id: None,
+        },
+        &TransferLimits::default(),
+    )
+    .await?
+    .map_err(|err| anyhow::anyhow!("transfer failed: {err:?}"))?;
+
+    Ok(transaction.id)
+}
+
+async fn load_deliveries(db: &TestDatabase, endpoint_id: Uuid) -> Result<Vec<WebhookDelivery>> {
+    let mut conn = db.pool.get().await?;
+
+    webhook_deliveries::table
+        .filter(webhook_deliveries::endpoint_id.eq(types::Uuid::from(endpoint_id)))
+        .select(WebhookDelivery::as_select())
+        .order(webhook_deliveries::id.asc())
+        .load(&mut conn)
+        .await
+        .context("failed to query webhook deliveries")
+}
+
+/// Checks the signature header of a delivery, as a receiver would.
+fn verify_signature(secret: &SecretString, headers: &HeaderMap, body: &str) -> Result<()> {
+    let signature = headers
+        .get(webhook::SIGNATURE_HEADER)
+        .context("missing signature header")?
+        .to_str()?;
+    let (timestamp, tag) = signature
+        .strip_prefix("t=")
+        .and_then(|signature| signature.split_once(",v1="))
+        .context("malformed signature header")?;
+    let tag = tag
+        .as_bytes()
+        .chunks(2)
+        .map(|digits| u8::from_str_radix(std::str::from_utf8(digits)?, 16).map_err(Into::into))
+        .collect::<Result<Vec<u8>>>()?;
+
+    let key = hmac::Key::new(hmac::HMAC_SHA256, secret.expose_secret().as_bytes());
+    hmac::verify(&key, format!("{timestamp}.{body}").as_bytes(), &tag)
+        .map_err(|_| anyhow::anyhow!("signature does not match"))
+}
+
+#[tokio::test]
+async fn transaction_is_delivered_signed_once() -> Result<()> {
+    let db = TestDatabase::new().await?;
+    let receiver = Receiver::default();
+    let url = receiver.start().await?;
+    // The receiver is on a loopback address.
+    let client = WebhookClient::new(HostPolicy::Any)?;
+
+    let alice = create_user(&db, "alice", 100).await?;
+    let bob = create_user(&db, "bob", 0).await?;
+    let endpoint = create_endpoint(
+        &db,
+        alice,
+        url,
+        &[
+            WebhookEventType::TransactionCreated,
+            WebhookEventType::RefundCreated,
+        ],
+    )
+    .await?;
+
+    let transaction_id = send(&db, alice, bob, 25).await?;
+
+    webhook::deliver_due_webhooks(&db.pool, &client, jiff::Timestamp::now()).await?;
+    // Delivered deliveries are not sent again.
+    webhook::deliver_due_webhooks(&db.pool, &client, jiff::Timestamp::now()).await?;
+
+    // Only `transaction.created` is sent, as a transfer isn't a refund.
+    let requests = receiver.requests();
+    assert_eq!(requests.len(), 1);
+    let (headers, body) = &requests[0];
+    verify_signature(&endpoint.secret, headers, body)?;
+    assert_eq!(
+        headers[webhook::EVENT_TYPE_HEADER],
+        WebhookEventType::TransactionCreated.to_string()
+    );
+
+    let event: serde_json::Value = serde_json::from_str(body)?;
+    assert_eq!(event["type"], "transaction.created");
+    assert_eq!(event["data"]["id"], transaction_id.to_string());
+    assert_eq!(event["data"]["amount"], 25);
+    assert_eq!(
+        headers[webhook::EVENT_ID_HEADER],
+        event["id"].as_str().unwrap()
+    );
+
+    let deliveries = load_deliveries(&db, endpoint.id).await?;
+    assert_eq!(deliveries.len(), 1);
+    assert_eq!(deliveries[0].status, WebhookDeliveryStatus::Succeeded);
+    assert_eq!(deliveries[0].attempts, 1);
+    assert_eq!(deliveries[0].last_response_status, Some(204));
+    assert!(deliveries[0].delivered_at.is_some());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn failed_delivery_is_retried_then_dead_until_replayed() -> Result<()> {
+    let db = TestDatabase::new().await?;
+    let receiver = Receiver::default();
+    let url = receiver.start().await?;
+    // The receiver is on a loopback address.
+    let client = WebhookClient::new(HostPolicy::Any)?;
+
+    let alice = create_user(&db, "alice", 100).await?;
+    let bob = create_user(&db, "bob", 0).await?;
+    let endpoint = create_endpoint(&db, bob, url, &[WebhookEventType::TransactionCreated]).await?;
+
+    send(&db, alice, bob, 25).await?;
+    receiver.respond_with(StatusCode::INTERNAL_SERVER_ERROR);
+
+    let now = jiff::Timestamp::now();
+    webhook::deliver_due_webhooks(&db.pool, &client, now).await?;
+
+    let delivery = load_deliveries(&db, endpoint.id).await?.remove(0);
+    assert_eq!(delivery.status, WebhookDeliveryStatus::Pending);
+    assert_eq!(delivery.attempts, 1);
+    assert_eq!(delivery.last_response_status, Some(500));
+    assert!(delivery.next_attempt_at >= now.checked_add(RETRY_BASE_DELAY)?);
+
+    // Not retried before it is due.
+    webhook::deliver_due_webhooks(&db.pool, &client, now).await?;
+    assert_eq!(receiver.requests().len(), 1);
+
+    // The delay doubles with each failure, until the delivery is given up on.
+    let mut delivery = delivery;
+    let mut previous_delay = RETRY_BASE_DELAY;
+    while delivery.status == WebhookDeliveryStatus::Pending {
+        let due_at = delivery.next_attempt_at;
+        webhook::deliver_due_webhooks(&db.pool, &client, due_at).await?;
+
+        delivery = load_deliveries(&db, endpoint.id).await?.remove(0);
+        if delivery.status == WebhookDeliveryStatus::Pending {
+            let delay = delivery.next_attempt_at.duration_since(due_at);
+            assert!(delay > previous_delay);
+            previous_delay = delay;
+        }
+    }
+    assert_eq!(delivery.status, WebhookDeliveryStatus::Dead);
+    assert_eq!(delivery.attempts, MAX_ATTEMPTS);
+    assert_eq!(receiver.requests().len(), usize::try_from(MAX_ATTEMPTS)?);
+
+    // Dead deliveries are not retried.
+    let far_future = now.checked_add(jiff::SignedDuration::from_hours(24 * 365))?;
+    webhook::deliver_due_webhooks(&db.pool, &client, far_future).await?;
+    assert_eq!(receiver.requests().len(), usize::try_from(MAX_ATTEMPTS)?);
+
+    // Once replayed, the delivery is sent again with the same event ID.
+    receiver.respond_with(StatusCode::OK);
+    let mut conn = db.pool.get().await?;
+    let delivery = webhook::replay(&mut conn, delivery).await?;
+    assert_eq!(delivery.status, WebhookDeliveryStatus::Pending);
+    assert_eq!(delivery.attempts, 0);
+
+    webhook::deliver_due_webhooks(&db.pool, &client, jiff::Timestamp::now()).await?;
+
+    let delivery = load_deliveries(&db, endpoint.id).await?.remove(0);
+    assert_eq!(delivery.status, WebhookDeliveryStatus::Succeeded);
+    assert_eq!(delivery.attempts, 1);
+    let requests = receiver.requests();
+    let event_ids: Vec<_> = requests
+        .iter()
+        .map(|(headers, _)| headers[webhook::EVENT_ID_HEADER].clone())
+        .collect();
+    assert!(event_ids.iter().all(|event_id| *event_id == event_ids[0]));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn delivery_to_private_address_is_refused() -> Result<()> {
+    let db = TestDatabase::new().await?;
+    let receiver = Receiver::default();
+    let url = receiver.start().await?;
+    let client = WebhookClient::new(HostPolicy::PublicOnly)?;
+
+    let alice = create_user(&db, "alice", 100).await?;
+    let bob = create_user(&db, "bob", 0).await?;
+    // Endpoints are checked when registered, but a domain may later resolve
+    // elsewhere, so deliveries are checked again.
+    let endpoint = create_endpoint(&db, bob, url, &[WebhookEventType::TransactionCreated]).await?;
+
+    send(&db, alice, bob, 25).await?;
+    webhook::deliver_due_webhooks(&db.pool, &client, jiff::Timestamp::now()).await?;
+
+    assert!(receiver.requests().is_empty());
+    let delivery = load_deliveries(&db, endpoint.id).await?.remove(0);
+    assert_eq!(delivery.status, WebhookDeliveryStatus::Pending);
+    assert_eq!(delivery.attempts, 1);
+    assert_eq!(delivery.last_response_status, None);
+    assert_eq!(
+        delivery.last_error.as_deref(),
+        Some("127.0.0.1 is not a public address")
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn endpoint_at_private_address_is_rejected() -> Result<()> {
+    let db = TestDatabase::new().await?;
+    let alice = create_user(&db, "alice", 0).await?;
+    let app = TestApp::start(&db).await?;
+    let access_token = app.access_token(alice)?;
+
+    for url in [
+        "http://127.0.0.1:8000/",
+        "http://localhost/",
+        "http://10.0.0.1/",
+        "http://169.254.169.254/latest/meta-data/",
+        "http://[::1]/",
+        "http://[::ffff:192.168.0.1]/",
+        "http://[64:ff9b::a9fe:a9fe]/",
+        "http://[2001:db8::1]/",
+        "http://0.0.0.0/",
+        "ftp://example.com/",
+    ] {
+        let (status, body) = app
+            .request(
+                Method::POST,
+                "/webhook-endpoints",
+                &access_token,
+                Some(json!({ "url": url, "events": ["transaction.created"] })),
+            )
+            .await?;
+        assert_eq!(status, StatusCode::BAD_REQUEST, "{url}");
+        assert_eq!(body["title"], "InvalidWebhookUrl", "{url}");
+    }
+
+    let (status, _body) = app
+        .request(
+            Method::POST,
+            "/webhook-endpoints",
+            &access_token,
+            Some(json!({ "url": "https://93.184.215.14/", "events": ["transaction.created"] })),
+        )
+        .await?;
+    assert_eq!(status, StatusCode::CREATED);
+
+    Ok(())
+}
+
+#[test]
+fn ipv6_addresses_embedding_ipv4_are_as_public_as_the_ipv4_address() -> Result<()> {
+    for (ip, public) in [
+        ("2606:4700::1111", true),
+        ("2001:db8::1", false),
+        ("::1", false),
+        ("::", false),
+        ("fe80::1", false),
+        // IPv4-mapped.
+        ("::ffff:93.184.215.14", true),
+        ("::ffff:10.0.0.1", false),
+        // IPv4-compatible.
+        ("::93.184.215.14", true),
+        ("::127.0.0.1", false),
+        // NAT64.
+        ("64:ff9b::93.184.215.14", true),
+        ("64:ff9b::169.254.169.254", false),
+        // 6to4.
+        ("2002:5db8:d70e::1", true),
+        ("2002:c0a8:0001::1", false),
+        ("2002:7f00:0001::1", false),
+    ] {
+        assert_eq!(http_url::is_public(ip.parse()?), public, "{ip}");
+    }
+
+    Ok(())
+}